@@ -1 +1,3 @@
+pub mod plic;
 pub mod registers;
+pub mod trap;
@@ -0,0 +1,172 @@
+//! Platform-Level Interrupt Controller (PLIC), which gathers device interrupts and signals them to the harts as
+//! supervisor external interrupts.
+//!
+//! Each interrupt source is routed onto the device vector of the same index (see [`vector`]), so drivers register
+//! their handlers with [`irq`] just as they would on x86_64. Sources are enabled in every hart's supervisor context,
+//! and whichever hart claims an interrupt first services it.
+//!
+//! #### Remark
+//!
+//! A hart's supervisor context is taken to be `(hart * 2) + 1`, as it is on QEMU's `virt` machine (and boards whose
+//! harts each have a machine and a supervisor context). Boards with a monitor hart lacking supervisor mode number
+//! their contexts otherwise, which only the PLIC's `interrupts-extended` describes.
+
+use crate::{
+    interrupts::{irq, InterruptCell},
+    mem::{
+        io::resource::{self, Kind, Space},
+        paging::TableEntryFlags,
+        vmap::Mapping,
+    },
+};
+use spin::Mutex;
+
+crate::error_impl! {
+    #[derive(Debug)]
+    pub enum Error {
+        /// The platform describes no PLIC.
+        NotPresent => None,
+        NoRegisters => None,
+        /// Source 0 is reserved, and sources beyond the device vectors can't be routed.
+        InvalidSource { source: u32 } => None,
+        NotInitialized => None,
+        Resource { err: resource::Error } => Some(err),
+        Vmap { err: crate::mem::vmap::Error } => Some(err)
+    }
+}
+
+/// Models of PLIC the driver binds to.
+const COMPATIBLE: [&str; 2] = ["sifive,plic-1.0.0", "riscv,plic0"];
+
+const PRIORITY_OFFSET: usize = 0x0;
+const ENABLE_OFFSET: usize = 0x2000;
+const ENABLE_STRIDE: usize = 0x80;
+const CONTEXT_OFFSET: usize = 0x20_0000;
+const CONTEXT_STRIDE: usize = 0x1000;
+const THRESHOLD_OFFSET: usize = 0x0;
+const CLAIM_OFFSET: usize = 0x4;
+
+/// Priority of every enabled source, which is above the threshold of 0 that every context is given.
+const SOURCE_PRIORITY: u32 = 1;
+
+struct Plic {
+    mapping: Mapping,
+    /// Harts whose contexts the sources are enabled in.
+    harts: alloc::vec::Vec<u32>,
+    /// Serializes the read-modify-write of enable bits.
+    enabling: InterruptCell<Mutex<()>>,
+}
+
+impl Plic {
+    /// Index of `hart`'s supervisor context, or `None` if its registers lie outside the mapping.
+    fn context(&self, hart: u32) -> Option<usize> {
+        let context = (usize::try_from(hart).ok()? * 2) + 1;
+
+        (context_regs(context) + CLAIM_OFFSET + 4 <= self.mapping.len()).then_some(context)
+    }
+
+    fn read(&self, offset: usize) -> u32 {
+        assert!(offset + 4 <= self.mapping.len());
+
+        // Safety: Offset lies within the mapping, and the PLIC's registers are naturally aligned words.
+        unsafe { self.mapping.as_ptr().as_ptr().add(offset).cast::<u32>().read_volatile() }
+    }
+
+    fn write(&self, offset: usize, value: u32) {
+        assert!(offset + 4 <= self.mapping.len());
+
+        // Safety: Offset lies within the mapping, and the PLIC's registers are naturally aligned words.
+        unsafe { self.mapping.as_ptr().as_ptr().add(offset).cast::<u32>().write_volatile(value) };
+    }
+}
+
+/// Offset of the threshold and claim registers of `context`.
+const fn context_regs(context: usize) -> usize {
+    CONTEXT_OFFSET + (context * CONTEXT_STRIDE)
+}
+
+static PLIC: spin::Once<Plic> = spin::Once::new();
+
+/// Device vector the interrupts of `source` are routed onto.
+pub fn vector(source: u32) -> Result<u64> {
+    let vector = irq::DEVICE_VECTORS.start() + u64::from(source);
+
+    (source > 0 && irq::DEVICE_VECTORS.contains(&vector)).then_some(vector).ok_or(Error::InvalidSource { source })
+}
+
+/// Maps the PLIC the platform describes, and unmasks external interrupts on the local hart.
+pub fn init() -> Result<()> {
+    let platform = crate::platform::get().ok_or(Error::NotPresent)?;
+    let device =
+        COMPATIBLE.iter().find_map(|compatible| platform.compatible(compatible).next()).ok_or(Error::NotPresent)?;
+    let regs = device.regs.first().cloned().ok_or(Error::NoRegisters)?;
+
+    resource::claim(Space::Memory, regs.start, regs.len(), "plic", Kind::Exclusive)
+        .map_err(|err| Error::Resource { err })?;
+    let mapping =
+        crate::mem::vmap::map(regs.start, regs.len(), TableEntryFlags::MMIO).map_err(|err| Error::Vmap { err })?;
+
+    let plic = PLIC.call_once(|| Plic {
+        mapping,
+        harts: platform.cpus.iter().map(|cpu| cpu.id).collect(),
+        enabling: InterruptCell::new(Mutex::new(())),
+    });
+
+    for hart in &plic.harts {
+        match plic.context(*hart) {
+            Some(context) => plic.write(context_regs(context) + THRESHOLD_OFFSET, 0),
+            None => warn!("PLIC has no supervisor context for hart {}.", hart),
+        }
+    }
+
+    // Safety: The trap handler claims external interrupts through `handle`, now that the PLIC is mapped.
+    unsafe { crate::arch::rv64::registers::sie::enable_external() };
+
+    info!("PLIC                {:#X}..{:#X}", regs.start, regs.end);
+
+    Ok(())
+}
+
+/// Enables `source` in every hart's context, returning the device vector its interrupts are dispatched to.
+pub fn enable(source: u32) -> Result<u64> {
+    let vector = vector(source)?;
+    let plic = PLIC.get().ok_or(Error::NotInitialized)?;
+    let source = usize::try_from(source).unwrap();
+
+    plic.write(PRIORITY_OFFSET + (source * 4), SOURCE_PRIORITY);
+
+    plic.enabling.with(|enabling| {
+        let _guard = enabling.lock();
+
+        for hart in &plic.harts {
+            let Some(context) = plic.context(*hart) else { continue };
+            let offset = ENABLE_OFFSET + (context * ENABLE_STRIDE) + ((source / 32) * 4);
+
+            plic.write(offset, plic.read(offset) | (1 << (source % 32)));
+        }
+    });
+
+    Ok(vector)
+}
+
+/// Claims and dispatches the interrupts pending for the local hart.
+pub fn handle() {
+    let Some(plic) = PLIC.get() else { return };
+    let Some(context) = crate::cpu::state::get_core_id().ok().and_then(|hart| plic.context(hart)) else { return };
+    let claim = context_regs(context) + CLAIM_OFFSET;
+
+    loop {
+        let source = plic.read(claim);
+        if source == 0 {
+            break;
+        }
+
+        match vector(source) {
+            Ok(vector) => irq::dispatch(vector),
+            Err(_) => warn!("PLIC source {} has no device vector.", source),
+        }
+
+        // Completing the claim lets the source interrupt again.
+        plic.write(claim, source);
+    }
+}
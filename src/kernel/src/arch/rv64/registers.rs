@@ -3,8 +3,15 @@ use core::arch::asm;
 bitflags::bitflags! {
     // Wrapper type for `sstatus` register.
     #[repr(transparent)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
     pub struct SSTATUS : u64 {
         const SIE = 1 << 1;
+        /// Value `sret` restores to `SIE`.
+        const SPIE = 1 << 5;
+        /// Whether the trap was taken from supervisor mode (and so, whether `sret` returns to it).
+        const SPP = 1 << 8;
+        /// Permits supervisor-mode accesses to user pages.
+        const SUM = 1 << 18;
     }
 }
 
@@ -62,6 +69,23 @@ pub mod sstatus {
     }
 }
 
+pub mod sie {
+    use core::arch::asm;
+
+    /// Supervisor external interrupt enable, through which the PLIC signals device interrupts.
+    const SEIE: usize = 1 << 9;
+
+    /// Unmasks supervisor external interrupts on the local hart.
+    ///
+    /// ### Safety
+    ///
+    /// The trap handler must be ready to claim external interrupts once they're unmasked.
+    #[inline]
+    pub unsafe fn enable_external() {
+        asm!("csrs sie, {}", in(reg) SEIE, options(nostack, nomem));
+    }
+}
+
 pub mod stvec {
     use core::arch::asm;
 
@@ -72,6 +96,90 @@ pub mod stvec {
 
         value
     }
+
+    /// ### Safety
+    ///
+    /// `address` must be a 4-byte aligned trap entry, which remains valid for as long as traps are taken.
+    #[inline]
+    pub unsafe fn write(address: usize) {
+        asm!("csrw stvec, {}", in(reg) address, options(nostack, nomem));
+    }
+}
+
+pub mod sscratch {
+    use core::arch::asm;
+
+    /// ### Safety
+    ///
+    /// The trap entry swaps `sp` with `sscratch`, so it must hold the top of a valid trap stack.
+    #[inline]
+    pub unsafe fn write(value: usize) {
+        asm!("csrw sscratch, {}", in(reg) value, options(nostack, nomem));
+    }
+
+    /// Reads the top of the core's trap stack (which the trap entry restores before handling a trap).
+    #[inline]
+    pub fn read() -> usize {
+        let value: usize;
+
+        // Safety: Reading `sscratch` has no side effects.
+        unsafe { asm!("csrr {}, sscratch", out(reg) value, options(nostack, nomem)) };
+
+        value
+    }
+}
+
+/// Thread pointer, which holds the address of the core-local state while in the kernel (see [`crate::cpu::state`]).
+pub mod tp {
+    use core::arch::asm;
+
+    #[inline]
+    pub fn read() -> usize {
+        let value: usize;
+
+        // Safety: Reading `tp` has no side effects.
+        unsafe { asm!("mv {}, tp", out(reg) value, options(nostack, nomem, preserves_flags)) };
+
+        value
+    }
+
+    /// ### Safety
+    ///
+    /// `tp` is taken to point to the core-local state, so `value` must be its address (or 0).
+    #[inline]
+    pub unsafe fn write(value: usize) {
+        asm!("mv tp, {}", in(reg) value, options(nostack, nomem, preserves_flags));
+    }
+}
+
+pub mod scause {
+    use core::arch::asm;
+
+    /// Reads the cause of the last trap (with the top bit set for interrupts).
+    #[inline]
+    pub fn read() -> usize {
+        let value: usize;
+
+        // Safety: Reading `scause` has no side effects.
+        unsafe { asm!("csrr {}, scause", out(reg) value, options(nostack, nomem)) };
+
+        value
+    }
+}
+
+pub mod stval {
+    use core::arch::asm;
+
+    /// Reads the trap value of the last trap (i.e. the faulting address).
+    #[inline]
+    pub fn read() -> usize {
+        let value: usize;
+
+        // Safety: Reading `stval` has no side effects.
+        unsafe { asm!("csrr {}, stval", out(reg) value, options(nostack, nomem)) };
+
+        value
+    }
 }
 
 pub mod satp {
@@ -0,0 +1,212 @@
+//! Supervisor trap entry, through which system calls (`ecall` from user mode) enter the kernel.
+//!
+//! `sscratch` holds the top of the core's trap stack. On entry it's swapped with `sp`, and the interrupted context is
+//! saved to a [`TrapFrame`] on the trap stack for [`trap_handler`]. The frame (which the handler may replace, i.e. when
+//! switching tasks) is then restored, and `sret` returns into it.
+//!
+//! The top of the trap stack holds the core's state pointer (see [`set_state_ptr`]), which is loaded into `tp` on
+//! entry, as user mode is free to overwrite `tp`. Returning to supervisor mode leaves `tp` as it is, since the frame
+//! may be of a kernel task last run on another core.
+//!
+//! #### Remark
+//!
+//! Every trap on a core shares its trap stack, so traps mustn't nest. This holds while handlers run with
+//! `sstatus.SIE` clear (as it is on entry), and don't fault.
+
+use crate::{
+    arch::rv64::registers::{scause, sscratch, stval, stvec, SSTATUS},
+    task::{Registers, State},
+};
+use core::ptr::NonNull;
+use libsys::Address;
+
+#[repr(C)]
+pub struct TrapFrame {
+    pub regs: Registers,
+    pub state: State,
+}
+
+/// Size of the frame on the trap stack, which must stay 16-byte aligned.
+const FRAME_SIZE: usize = core::mem::size_of::<TrapFrame>().next_multiple_of(16);
+/// Offsets of the fields of [`TrapFrame::state`], which follows the registers and whose fields are each a register
+/// wide.
+const IP_OFFSET: usize = core::mem::size_of::<Registers>();
+const SP_OFFSET: usize = IP_OFFSET + 8;
+const SSTATUS_OFFSET: usize = SP_OFFSET + 8;
+
+/// `scause` of an `ecall` from user mode.
+const CAUSE_USER_ECALL: usize = 8;
+/// `scause` of a supervisor external interrupt (raised by the PLIC), with the interrupt bit set.
+const CAUSE_SUPERVISOR_EXTERNAL: usize = (1 << (usize::BITS - 1)) | 9;
+/// `scause` of a load access fault, and of a load page fault.
+const CAUSE_LOAD_ACCESS_FAULT: usize = 5;
+const CAUSE_LOAD_PAGE_FAULT: usize = 13;
+/// Length of the `ecall` instruction (which has no compressed form).
+const ECALL_LEN: usize = 4;
+/// Space reserved at the top of the trap stack for the core's state pointer, keeping frames 16-byte aligned.
+const STATE_PTR_SLOT: usize = 16;
+
+core::arch::global_asm!(
+    "
+    .section .text
+    .balign 4
+    .global __trap_entry
+__trap_entry:
+    csrrw sp, sscratch, sp
+    addi sp, sp, -{frame_size}
+
+    sd x1, 0(sp)
+    sd x3, 8(sp)
+    sd x4, 16(sp)
+    sd x5, 24(sp)
+    sd x6, 32(sp)
+    sd x7, 40(sp)
+    sd x8, 48(sp)
+    sd x9, 56(sp)
+    sd x10, 64(sp)
+    sd x11, 72(sp)
+    sd x12, 80(sp)
+    sd x13, 88(sp)
+    sd x14, 96(sp)
+    sd x15, 104(sp)
+    sd x16, 112(sp)
+    sd x17, 120(sp)
+    sd x18, 128(sp)
+    sd x19, 136(sp)
+    sd x20, 144(sp)
+    sd x21, 152(sp)
+    sd x22, 160(sp)
+    sd x23, 168(sp)
+    sd x24, 176(sp)
+    sd x25, 184(sp)
+    sd x26, 192(sp)
+    sd x27, 200(sp)
+    sd x28, 208(sp)
+    sd x29, 216(sp)
+    sd x30, 224(sp)
+    sd x31, 232(sp)
+
+    ld tp, {frame_size}(sp)
+
+    csrr t0, sepc
+    sd t0, {ip}(sp)
+    csrr t0, sscratch
+    sd t0, {sp}(sp)
+    csrr t0, sstatus
+    sd t0, {sstatus}(sp)
+
+    # Reset `sscratch` to the top of the trap stack, for the next trap.
+    addi t0, sp, {frame_size}
+    csrw sscratch, t0
+
+    mv a0, sp
+    call {handler}
+
+    ld t0, {ip}(sp)
+    csrw sepc, t0
+    ld t0, {sstatus}(sp)
+    csrw sstatus, t0
+
+    ld x1, 0(sp)
+    ld x3, 8(sp)
+    andi t0, t0, {spp}
+    bnez t0, 1f
+    ld x4, 16(sp)
+1:
+    ld x5, 24(sp)
+    ld x6, 32(sp)
+    ld x7, 40(sp)
+    ld x8, 48(sp)
+    ld x9, 56(sp)
+    ld x10, 64(sp)
+    ld x11, 72(sp)
+    ld x12, 80(sp)
+    ld x13, 88(sp)
+    ld x14, 96(sp)
+    ld x15, 104(sp)
+    ld x16, 112(sp)
+    ld x17, 120(sp)
+    ld x18, 128(sp)
+    ld x19, 136(sp)
+    ld x20, 144(sp)
+    ld x21, 152(sp)
+    ld x22, 160(sp)
+    ld x23, 168(sp)
+    ld x24, 176(sp)
+    ld x25, 184(sp)
+    ld x26, 192(sp)
+    ld x27, 200(sp)
+    ld x28, 208(sp)
+    ld x29, 216(sp)
+    ld x30, 224(sp)
+    ld x31, 232(sp)
+
+    ld sp, {sp}(sp)
+    sret
+    ",
+    frame_size = const FRAME_SIZE,
+    ip = const IP_OFFSET,
+    sp = const SP_OFFSET,
+    sstatus = const SSTATUS_OFFSET,
+    spp = const SSTATUS::SPP.bits(),
+    handler = sym trap_handler,
+);
+
+extern "C" fn trap_handler(frame: &mut TrapFrame) {
+    match scause::read() {
+        CAUSE_USER_ECALL => {
+            // Return past the `ecall`, including if the task is switched out and later resumed.
+            frame.state.ip = Address::new(frame.state.ip.get() + ECALL_LEN).unwrap();
+            crate::interrupts::traps::handle_syscall(&mut frame.state, &mut frame.regs);
+        }
+
+        CAUSE_SUPERVISOR_EXTERNAL => crate::arch::rv64::plic::handle(),
+
+        // A probe of MMIO which isn't backed (see `crate::mem::io::probe`) resumes at its fixup. Probes are only made
+        // outside of traps, so the fault is taken on an otherwise unused trap stack.
+        cause @ (CAUSE_LOAD_ACCESS_FAULT | CAUSE_LOAD_PAGE_FAULT) if frame.state.sstatus.contains(SSTATUS::SPP) => {
+            match crate::interrupts::exceptions::fixup::search(frame.state.ip) {
+                Some(fixup) => frame.state.ip = fixup,
+                None => panic!(
+                    "kernel load fault: scause={:#X} stval={:#X} sepc={:X?}",
+                    cause,
+                    stval::read(),
+                    frame.state.ip
+                ),
+            }
+        }
+
+        cause => panic!("unhandled trap: scause={:#X} stval={:#X} sepc={:X?}", cause, stval::read(), frame.state.ip),
+    }
+}
+
+/// Directs the core's traps to the trap entry, with the trap stack ending at `stack_top`.
+///
+/// ### Safety
+///
+/// `stack_top` must be the top of a stack reserved for this core's traps.
+pub unsafe fn init(stack_top: NonNull<u8>) {
+    extern "C" {
+        fn __trap_entry();
+    }
+
+    let trap_top = stack_top.as_ptr().sub(STATE_PTR_SLOT);
+    // Safety: The slot lies within the trap stack, above any frame, and is aligned as the stack is.
+    trap_top.cast::<usize>().write(0);
+
+    sscratch::write(trap_top.addr());
+    // Direct mode: every trap enters at the base address.
+    stvec::write(__trap_entry as usize);
+}
+
+/// Sets the state pointer the trap entry loads into `tp`, which must be the value `tp` holds in the kernel.
+///
+/// ### Safety
+///
+/// The core's traps must have been directed to the trap entry (see [`init`]), and `state_ptr` must be the address of
+/// the core-local state (or 0).
+pub unsafe fn set_state_ptr(state_ptr: usize) {
+    // Outside of the trap entry, `sscratch` holds the address of the slot, which frames are pushed below.
+    let slot = sscratch::read() as *mut usize;
+    slot.write_volatile(state_ptr);
+}
@@ -14,6 +14,13 @@ pub mod sync {
         // Safety: `mfence` does not have instruction side effects.
         unsafe { core::arch::asm!("mfence", options(nostack, nomem, preserves_flags)) };
     }
+
+    /// Orders all prior stores, draining any write-combining buffers.
+    #[inline]
+    pub fn sfence() {
+        // Safety: `sfence` does not have instruction side effects.
+        unsafe { core::arch::asm!("sfence", options(nostack, preserves_flags)) };
+    }
 }
 
 pub mod tlb {
@@ -1,5 +1,5 @@
 use crate::{
-    interrupts::exceptions::{ex_handler, ArchException},
+    interrupts::exceptions::{ex_handler, ArchException, Resolution},
     task::{Registers, State},
 };
 use libsys::Address;
@@ -204,20 +204,36 @@ macro_rules! irq_stub {
 /// ### Safety
 ///
 /// This function should not be called from software.
-#[allow(clippy::cast_possible_truncation)]
 unsafe extern "sysv64" fn irq_handoff(irq_number: u64, isf: &mut InterruptStackFrame, regs: &mut Registers) {
+    #[cfg(feature = "irq_audit")]
+    let window = crate::interrupts::audit::Window::interrupt(irq_number);
+
+    let mut state = read_state(isf);
+    crate::interrupts::traps::handle_trap(irq_number, &mut state, regs);
+    write_state(isf, &state);
+
+    #[cfg(feature = "irq_audit")]
+    window.close();
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn read_state(isf: &InterruptStackFrame) -> State {
     use crate::arch::x86_64::registers::RFlags;
-    use ia32utils::VirtAddr;
 
-    let mut state = State {
+    State {
         ip: Address::from_ptr(isf.instruction_pointer.as_mut_ptr::<()>()),
         cs: usize::try_from(isf.code_segment).unwrap(),
         rfl: RFlags::from_bits_retain(isf.cpu_flags as usize),
         sp: Address::from_ptr(isf.stack_pointer.as_mut_ptr::<()>()),
         ss: usize::try_from(isf.stack_segment).unwrap(),
-    };
+    }
+}
 
-    crate::interrupts::traps::handle_trap(irq_number, &mut state, regs);
+/// ### Safety
+///
+/// `state` must be a valid context to return into.
+unsafe fn write_state(isf: &mut InterruptStackFrame, state: &State) {
+    use ia32utils::VirtAddr;
 
     isf.as_mut().write(InterruptStackFrameValue {
         instruction_pointer: VirtAddr::from_ptr(state.ip.as_ptr()),
@@ -228,6 +244,17 @@ unsafe extern "sysv64" fn irq_handoff(irq_number: u64, isf: &mut InterruptStackF
     });
 }
 
+/// Terminates the task which raised an exception, returning into the next task in its place.
+///
+/// ### Safety
+///
+/// `isf` and `regs` must be the context the exception returns through.
+unsafe fn kill_faulting_task(isf: &mut InterruptStackFrame, regs: &mut Registers) {
+    let mut state = read_state(isf);
+    crate::cpu::state::with_scheduler(|scheduler| scheduler.kill_task(&mut state, regs));
+    write_state(isf, &state);
+}
+
 exception_handler!(de, ());
 extern "sysv64" fn de_handler_inner(stack_frame: &InterruptStackFrame, gprs: &Registers) {
     ex_handler(&ArchException::DivideError(stack_frame, gprs));
@@ -296,8 +323,29 @@ extern "sysv64" fn gp_handler_inner(stack_frame: &InterruptStackFrame, error_cod
 }
 
 exception_handler_with_error!(pf, PageFaultErrorCode, ());
-extern "sysv64" fn pf_handler_inner(stack_frame: &InterruptStackFrame, err: PageFaultErrorCode, gprs: &Registers) {
-    ex_handler(&ArchException::PageFault(stack_frame, gprs, err, crate::arch::x86_64::registers::control::CR2::read()));
+extern "sysv64" fn pf_handler_inner(
+    stack_frame: &mut InterruptStackFrame,
+    err: PageFaultErrorCode,
+    gprs: &mut Registers,
+) {
+    let fault = crate::interrupts::exceptions::PageFault::capture(err);
+
+    match ex_handler(&ArchException::PageFault(stack_frame, gprs, fault)) {
+        Resolution::Resume => {}
+
+        Resolution::KillTask => {
+            // Safety: The stack frame and registers are those the exception returns through.
+            unsafe { kill_faulting_task(stack_frame, gprs) };
+        }
+
+        Resolution::Fixup(ip) => {
+            let mut state = read_state(stack_frame);
+            state.ip = ip;
+
+            // Safety: The fixup was designated as where the faulting instruction resumes, in the same context.
+            unsafe { write_state(stack_frame, &state) };
+        }
+    }
 }
 
 // --- reserved 15
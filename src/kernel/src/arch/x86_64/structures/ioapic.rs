@@ -1,10 +1,28 @@
 use crate::interrupts;
 use acpi::platform::interrupt::{Polarity, TriggerMode};
-// use alloc::vec::Vec;
+use alloc::vec::Vec;
 use bit_field::BitField;
 use libkernel::mem::VolatileCell;
 use spin::Mutex;
 
+crate::error_impl! {
+    #[derive(Debug)]
+    pub enum Error {
+        /// The platform's interrupt model isn't the APIC, so it has no I/O APICs.
+        NoApic => None,
+        /// No I/O APIC handles the global system interrupt.
+        Unhandled { gsi: u32 } => None,
+        /// The vector or destination APIC ID doesn't fit a redirection entry.
+        InvalidRedirection => None,
+        Vmap { err: crate::mem::vmap::Error } => Some(err)
+    }
+}
+
+/// Length of an I/O APIC's register window, through its data register.
+const IOREGS_LEN: usize = 0x14;
+/// Offset of the data register (`IOWIN`), from the register select (`IOREGSEL`).
+const IOWIN_OFFSET: usize = 0x10;
+
 #[repr(transparent)]
 pub struct RedirectionEntry(u64);
 
@@ -128,10 +146,16 @@ impl IoApic<'_> {
         self.handled_irqs.clone()
     }
 
-    pub fn get_redirection(&self, global_irq_num: u32) -> RedirectionEntry {
+    /// Index of the low register of the redirection entry of `global_irq_num`, which is relative to the interrupts
+    /// this I/O APIC handles.
+    fn redirection_index(&self, global_irq_num: u32) -> u32 {
         assert!(self.handled_irqs().contains(&global_irq_num), "I/O APIC does not handle the provided redirection");
 
-        let reg_base_index = 0x10 + (global_irq_num * 2);
+        0x10 + ((global_irq_num - self.handled_irqs.start()) * 2)
+    }
+
+    pub fn get_redirection(&self, global_irq_num: u32) -> RedirectionEntry {
+        let reg_base_index = self.redirection_index(global_irq_num);
 
         let ioregs = self.ioregs.lock();
 
@@ -144,13 +168,12 @@ impl IoApic<'_> {
     }
 
     pub fn set_redirection(&self, global_irq_num: u32, redirection: &RedirectionEntry) {
-        assert!(self.handled_irqs().contains(&global_irq_num), "I/O APIC does not handle the provided redirection");
+        let reg_base_index = self.redirection_index(global_irq_num);
 
         #[allow(clippy::cast_possible_truncation)]
         {
             let redirection_low = redirection.0 as u32;
             let redirection_high = (redirection.0 >> 32) as u32;
-            let reg_base_index = 0x10 + (global_irq_num * 2);
 
             let ioregs = self.ioregs.lock();
 
@@ -166,49 +189,95 @@ impl IoApic<'_> {
     }
 }
 
-// TODO We don't need to store this probably, find some way to init architecture-specifically.
-//      Maybe just iterate them once, processing redirections within the same context.
-// static IOAPICS: Once<Vec<IoApic>> = Once::new();
-// /// Queries the platform for I/O APICs, and returns them in a collection.
-// pub fn get_io_apics() -> &'static Vec<IoApic<'static>> {
-//     IOAPICS.call_once(|| {
-//         todo!()
-
-//          let platform_info = libsys::acpi::get_platform_info();
-
-//          if let acpi::platform::interrupt::InterruptModel::Apic(apic) = &platform_info.interrupt_model {
-//              apic.io_apics
-//                  .iter()
-//                  // TODO unsafety comment
-//                  .map(|ioapic_info| unsafe {
-
-//                      let (ioregsel, ioregwin) = {
-//                          let Ok(ioapic_regs) = libsys::memory::get().allocate_to(Address::<Frame>::new_truncate(ioapic_info.address as u64), 1)
-//                              else { panic!("failed to initialize I/O APIC") };
-
-//                          (
-//                              &*ioapic_regs.as_ptr::<VolatileCell<u32, libsys::WriteOnly>>(),
-//                              &*ioapic_regs.as_ptr::<VolatileCell<u32, libsys::ReadWrite>>().add(1)
-//                          )
-//                      };
-
-//                      let id = {
-//                          ioregsel.write(0x0);
-//                          ioregwin.read().get_bits(24..28) as u8
-//                      };
-//                      let (version, irq_count) = {
-//                          ioregsel.write(0x1);
-//                          let value = ioregwin.read();
-//                          (value.get_bits(0..8) as u8, value.get_bits(16..24) as u32)
-//                      };
-//                      let irq_base = ioapic_info.global_system_interrupt_base;
-//                      let handled_irqs = irq_base..=(irq_base + irq_count);
-
-//                      IoApic { id, version, handled_irqs, ioregs: Mutex::new((ioregsel, ioregwin)) }
-//                  })
-//                  .collect()
-//          } else {
-//              alloc::vec::Vec::new()
-//          }
-//     })
-// }
+impl IoApic<'static> {
+    /// Maps the I/O APIC whose registers are at `address`, which handles the global system interrupts from
+    /// `gsi_base`.
+    fn map(address: usize, gsi_base: u32) -> Result<Self> {
+        let mapping = crate::mem::vmap::map(address, IOREGS_LEN, crate::mem::paging::TableEntryFlags::MMIO)
+            .map_err(|err| Error::Vmap { err })?;
+        let ptr = mapping.as_ptr();
+        // The I/O APIC is used for as long as the kernel runs, so its window is never unmapped.
+        core::mem::forget(mapping);
+
+        // Safety: The window maps both registers, which are naturally aligned words, and isn't ever unmapped.
+        let (ioregsel, iowin) = unsafe {
+            (
+                &*ptr.as_ptr().cast::<VolatileCell<u32, libkernel::WriteOnly>>(),
+                &*ptr.as_ptr().add(IOWIN_OFFSET).cast::<VolatileCell<u32, libkernel::ReadWrite>>(),
+            )
+        };
+
+        ioregsel.write(0x0);
+        let id = u8::try_from(iowin.read().get_bits(24..28)).unwrap();
+        ioregsel.write(0x1);
+        let version_reg = iowin.read();
+        let version = u8::try_from(version_reg.get_bits(0..8)).unwrap();
+        // The register holds the index of the last redirection entry.
+        let last_entry = version_reg.get_bits(16..24);
+
+        Ok(Self {
+            id,
+            version,
+            handled_irqs: gsi_base..=(gsi_base + last_entry),
+            ioregs: Mutex::new((ioregsel, iowin)),
+        })
+    }
+}
+
+static IOAPICS: spin::Once<Vec<IoApic<'static>>> = spin::Once::new();
+
+/// Maps the I/O APICs the ACPI tables describe, once.
+fn io_apics() -> Result<&'static [IoApic<'static>]> {
+    use acpi::platform::interrupt::InterruptModel;
+
+    IOAPICS
+        .try_call_once(|| {
+            let platform_info = crate::acpi::PLATFORM_INFO.as_ref().ok_or(Error::NoApic)?.lock();
+            let InterruptModel::Apic(apic) = &platform_info.interrupt_model else { return Err(Error::NoApic) };
+
+            apic.io_apics
+                .iter()
+                .map(|io_apic| {
+                    IoApic::map(usize::try_from(io_apic.address).unwrap(), io_apic.global_system_interrupt_base)
+                })
+                .collect()
+        })
+        .map(Vec::as_slice)
+}
+
+/// Routes the ISA interrupt `isa_irq` to `vector` on the core with the local APIC ID `destination`, following any
+/// override of the interrupt's line, polarity, or trigger mode the ACPI tables make.
+pub fn route_isa(isa_irq: u8, vector: u64, destination: u32) -> Result<()> {
+    use acpi::platform::interrupt::InterruptModel;
+
+    let vector = u8::try_from(vector).ok().filter(|vector| *vector >= 32).ok_or(Error::InvalidRedirection)?;
+    let destination = u8::try_from(destination).map_err(|_| Error::InvalidRedirection)?;
+
+    // ISA interrupts are identity-mapped, edge-triggered, and active-high, unless overridden.
+    let (gsi, polarity, trigger_mode) = {
+        let platform_info = crate::acpi::PLATFORM_INFO.as_ref().ok_or(Error::NoApic)?.lock();
+        let InterruptModel::Apic(apic) = &platform_info.interrupt_model else { return Err(Error::NoApic) };
+
+        apic.interrupt_source_overrides.iter().find(|source_override| source_override.isa_source == isa_irq).map_or(
+            (u32::from(isa_irq), Polarity::ActiveHigh, TriggerMode::Edge),
+            |source_override| {
+                (source_override.global_system_interrupt, source_override.polarity, source_override.trigger_mode)
+            },
+        )
+    };
+
+    let io_apic =
+        io_apics()?.iter().find(|io_apic| io_apic.handled_irqs().contains(&gsi)).ok_or(Error::Unhandled { gsi })?;
+
+    let mut redirection = RedirectionEntry(0);
+    redirection.set_vector(vector);
+    redirection.set_delivery_mode(interrupts::DeliveryMode::Fixed);
+    redirection.set_destination_mode(interrupts::DestinationMode::Physical);
+    redirection.set_pin_polarity(polarity);
+    redirection.set_trigger_mode(trigger_mode);
+    redirection.set_masked(false);
+    redirection.set_destination_id(destination);
+    io_apic.set_redirection(gsi, &redirection);
+
+    Ok(())
+}
@@ -0,0 +1,62 @@
+//! GUID partition table parsing, enough to locate partitions of a given type on a disk.
+
+use super::{Disk, Result};
+use alloc::vec;
+use uuid::Uuid;
+
+/// Type GUID of the EFI system partition.
+pub const EFI_SYSTEM: Uuid = Uuid::from_u128(0xC12A7328_F81F_11D2_BA4B_00A0C93EC93B);
+
+const SIGNATURE: &[u8; 8] = b"EFI PART";
+
+/// A partition, as an inclusive range of the disk's blocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Partition {
+    pub first_lba: u64,
+    pub last_lba: u64,
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..(offset + 4)].try_into().unwrap())
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..(offset + 8)].try_into().unwrap())
+}
+
+/// Finds the first partition of type `kind` on `disk`, or `None` if it has no (valid) partition table.
+pub fn find(disk: &Disk, kind: Uuid) -> Result<Option<Partition>> {
+    let block_size = disk.device().block_size();
+    if block_size < 512 {
+        return Ok(None);
+    }
+
+    let mut block = vec![0u8; block_size];
+    disk.read(1, &mut block)?;
+    if &block[..8] != SIGNATURE {
+        return Ok(None);
+    }
+
+    let entries_lba = read_u64(&block, 72);
+    let entry_count = read_u32(&block, 80) as usize;
+    let entry_size = read_u32(&block, 84) as usize;
+    if entry_size < 128 || entry_size > block_size || (block_size % entry_size) > 0 {
+        return Ok(None);
+    }
+
+    let per_block = block_size / entry_size;
+    for index in 0..entry_count {
+        if (index % per_block) == 0 {
+            disk.read(entries_lba + u64::try_from(index / per_block).unwrap(), &mut block)?;
+        }
+
+        let entry = &block[((index % per_block) * entry_size)..][..entry_size];
+        // GUIDs are stored with their first three fields little-endian.
+        let type_guid = Uuid::from_bytes_le(entry[..16].try_into().unwrap());
+        if type_guid == kind {
+            return Ok(Some(Partition { first_lba: read_u64(entry, 32), last_lba: read_u64(entry, 40) }));
+        }
+    }
+
+    Ok(None)
+}
@@ -0,0 +1,229 @@
+//! Block layer: registry of block devices, with request accounting. Requests are dispatched through the device's
+//! hardware queues (see [`mq`]).
+
+pub mod gpt;
+pub mod mq;
+#[cfg(feature = "nvme")]
+pub mod nvme;
+#[cfg(feature = "virtio")]
+pub mod virtio;
+
+use crate::task::registry::IoDirection;
+use alloc::{boxed::Box, collections::BTreeMap, format, string::String, sync::Arc, vec::Vec};
+use core::sync::atomic::{AtomicU64, Ordering};
+use libsys::syscall::block::Stats;
+use spin::RwLock;
+
+crate::error_impl! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Error {
+        NoSuchDevice => None,
+        AlreadyRegistered => None,
+        /// The buffer length is not a multiple of the device's block size.
+        Unaligned => None,
+        /// The request extends beyond the end of the device.
+        OutOfRange => None,
+        /// The device failed to complete the request.
+        Device => None
+    }
+}
+
+impl From<Error> for libsys::syscall::Error {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::NoSuchDevice => Self::NotFound,
+            Error::AlreadyRegistered => Self::AlreadyExists,
+            Error::Unaligned | Error::OutOfRange => Self::InvalidArgument,
+            Error::Device => Self::Io,
+        }
+    }
+}
+
+/// A device addressed in fixed-size blocks, implemented by storage drivers.
+pub trait BlockDevice: Send + Sync {
+    fn block_size(&self) -> usize;
+    fn block_count(&self) -> u64;
+
+    /// Reads `buf.len() / block_size` blocks, starting at block `lba`.
+    fn read_blocks(&self, lba: u64, buf: &mut [u8]) -> Result<()>;
+    /// Writes `buf.len() / block_size` blocks, starting at block `lba`.
+    fn write_blocks(&self, lba: u64, buf: &[u8]) -> Result<()>;
+    /// Writes back any data held in the device's volatile cache.
+    fn flush(&self) -> Result<()>;
+
+    /// Number of hardware queues requests can be issued through concurrently.
+    fn queue_count(&self) -> usize {
+        1
+    }
+
+    /// As [`Self::read_blocks`], issued through hardware queue `queue` (below [`Self::queue_count`]).
+    fn read_blocks_on(&self, queue: usize, lba: u64, buf: &mut [u8]) -> Result<()> {
+        let _ = queue;
+        self.read_blocks(lba, buf)
+    }
+
+    /// As [`Self::write_blocks`], issued through hardware queue `queue`.
+    fn write_blocks_on(&self, queue: usize, lba: u64, buf: &[u8]) -> Result<()> {
+        let _ = queue;
+        self.write_blocks(lba, buf)
+    }
+
+    /// Steers the completion interrupts of hardware queue `queue` to the core with the APIC ID `apic_id` (i.e. by
+    /// retargeting its MSI-X entry). Devices which complete requests synchronously needn't.
+    fn steer_completions(&self, queue: usize, apic_id: u32) {
+        let _ = (queue, apic_id);
+    }
+}
+
+#[derive(Default)]
+struct Counters {
+    reads: AtomicU64,
+    writes: AtomicU64,
+    flushes: AtomicU64,
+    errors: AtomicU64,
+    /// Sum of the latencies of every completed request, in microseconds.
+    latency_us: AtomicU64,
+}
+
+/// A registered block device.
+pub struct Disk {
+    name: String,
+    device: Box<dyn BlockDevice>,
+    queues: mq::Queues,
+    counters: Counters,
+}
+
+impl Disk {
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[inline]
+    pub fn device(&self) -> &dyn BlockDevice {
+        &*self.device
+    }
+
+    fn check_bounds(&self, lba: u64, len: usize) -> Result<()> {
+        let block_size = self.device.block_size();
+        if (len % block_size) > 0 {
+            return Err(Error::Unaligned);
+        }
+
+        let blocks = u64::try_from(len / block_size).unwrap();
+        match lba.checked_add(blocks) {
+            Some(end) if end <= self.device.block_count() => Ok(()),
+            _ => Err(Error::OutOfRange),
+        }
+    }
+
+    /// Issues `request` to the device through the local core's hardware queue (at the I/O class of the task issuing
+    /// it), accounting it against `counter`.
+    fn request(&self, counter: &AtomicU64, request: impl FnOnce(usize) -> Result<()>) -> Result<()> {
+        use crate::time::ClockSource;
+
+        let clock = &*crate::time::SYSTEM_CLOCK;
+        let class = mq::current_class();
+
+        let start = clock.get_timestamp();
+        let result =
+            self.queues.dispatch(class, |queue| self.device.steer_completions(queue, crate::cpu::read_id()), request);
+        let elapsed = clock.elapsed(start);
+
+        counter.fetch_add(1, Ordering::Relaxed);
+        // Widened, as a long request at a fast clock overflows the product.
+        let latency_us = (u128::from(elapsed) * 1_000_000) / u128::from(clock.frequency());
+        self.counters.latency_us.fetch_add(u64::try_from(latency_us).unwrap_or(u64::MAX), Ordering::Relaxed);
+        if result.is_err() {
+            self.counters.errors.fetch_add(1, Ordering::Relaxed);
+        }
+
+        result
+    }
+
+    pub fn read(&self, lba: u64, buf: &mut [u8]) -> Result<()> {
+        self.check_bounds(lba, buf.len())?;
+        self.request(&self.counters.reads, |queue| self.device.read_blocks_on(queue, lba, buf))
+            .inspect(|()| mq::account(IoDirection::Read, buf.len()))
+    }
+
+    pub fn write(&self, lba: u64, buf: &[u8]) -> Result<()> {
+        self.check_bounds(lba, buf.len())?;
+        self.request(&self.counters.writes, |queue| self.device.write_blocks_on(queue, lba, buf))
+            .inspect(|()| mq::account(IoDirection::Write, buf.len()))
+    }
+
+    pub fn flush(&self) -> Result<()> {
+        self.request(&self.counters.flushes, |_| self.device.flush())
+    }
+
+    pub fn stats(&self) -> Stats {
+        let reads = self.counters.reads.load(Ordering::Relaxed);
+        let writes = self.counters.writes.load(Ordering::Relaxed);
+        let flushes = self.counters.flushes.load(Ordering::Relaxed);
+        let completed = reads + writes + flushes;
+
+        Stats {
+            reads,
+            writes,
+            flushes,
+            errors: self.counters.errors.load(Ordering::Relaxed),
+            average_latency_us: self.counters.latency_us.load(Ordering::Relaxed).checked_div(completed).unwrap_or(0),
+        }
+    }
+}
+
+impl crate::power::Hooks for Disk {
+    /// Flushes the device's volatile cache, so no written data is lost with power.
+    fn suspend(&self) -> core::result::Result<(), ()> {
+        self.flush().map_err(|err| warn!("Failed to flush {} for suspend: {:?}", self.name, err))
+    }
+
+    fn resume(&self) {}
+}
+
+static DISKS: RwLock<BTreeMap<String, Arc<Disk>>> = RwLock::new(BTreeMap::new());
+
+/// Registers `device` under `name`, and publishes its attributes under `block/<name>`.
+pub fn register(name: &str, device: Box<dyn BlockDevice>) -> Result<Arc<Disk>> {
+    let queues = mq::Queues::new(device.queue_count());
+    let disk = Arc::new(Disk { name: String::from(name), device, queues, counters: Counters::default() });
+    DISKS.write().try_insert(String::from(name), disk.clone()).map_err(|_| Error::AlreadyRegistered)?;
+    crate::power::register(name, disk.clone());
+
+    let path = format!("block/{name}");
+    crate::sysfs::set(&path, "block_size", format!("{}", disk.device.block_size()));
+    crate::sysfs::set(&path, "blocks", format!("{}", disk.device.block_count()));
+    crate::sysfs::set(&path, "stat", {
+        let disk = Arc::downgrade(&disk);
+
+        crate::sysfs::Value::Dynamic(Box::new(move || {
+            disk.upgrade().map(|disk| format!("{:?}", disk.stats())).unwrap_or_default()
+        }))
+    });
+    crate::sysfs::set(&path, "mq", {
+        let disk = Arc::downgrade(&disk);
+
+        crate::sysfs::Value::Dynamic(Box::new(move || {
+            disk.upgrade().map(|disk| disk.queues.render()).unwrap_or_default()
+        }))
+    });
+
+    info!(
+        "Registered block device {}: {} blocks of {} bytes",
+        name,
+        disk.device.block_count(),
+        disk.device.block_size()
+    );
+
+    Ok(disk)
+}
+
+pub fn get(name: &str) -> Result<Arc<Disk>> {
+    DISKS.read().get(name).cloned().ok_or(Error::NoSuchDevice)
+}
+
+/// Returns every registered block device, ordered by name.
+pub fn disks() -> Vec<Arc<Disk>> {
+    DISKS.read().values().cloned().collect()
+}
@@ -0,0 +1,268 @@
+//! Multi-queue dispatch of a disk's requests.
+//!
+//! Each core stages its requests on a software queue of its own, mapped onto one of the device's hardware queues (by
+//! the core's index, modulo the number of hardware queues). Cores mapped onto different hardware queues issue requests
+//! to the device in parallel, and only those sharing one take turns.
+//!
+//! Turns on a hardware queue go to the waiting request of the highest [`IoClass`], and within a class are handed
+//! round-robin between the tasks waiting, so a task streaming requests can't starve the others sharing its queue. An
+//! idle-class request which has been passed over for [`IDLE_GRACE_TURNS`] turns competes as a normal-class request,
+//! so write-back yields to interactive reads without stalling behind them forever. The completions of each hardware
+//! queue are steered to the first core to submit on it (see [`super::BlockDevice::steer_completions`]).
+
+use crate::{
+    interrupts::{ipi::MAX_CORES, InterruptCell},
+    task::{
+        registry::{IoDirection, IoKind},
+        Tid,
+    },
+};
+use alloc::{collections::BTreeMap, string::String, vec::Vec};
+use core::{
+    fmt::Write,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+};
+use libsys::syscall::task::IoClass;
+use spin::Mutex;
+
+/// Ticket of no request, granted while a hardware queue is idle.
+const NO_TICKET: u64 = 0;
+
+/// Turns an idle-class request waits before it competes as a normal-class request.
+pub const IDLE_GRACE_TURNS: u64 = 64;
+
+const CLASS_COUNT: usize = IoClass::Idle as usize + 1;
+
+struct Waiter {
+    ticket: u64,
+    /// Task which submitted the request, or `None` for the kernel.
+    owner: Option<Tid>,
+    class: IoClass,
+    /// Turn the hardware queue was on as the request began waiting.
+    queued_turn: u64,
+}
+
+#[derive(Default)]
+struct Turns {
+    busy: bool,
+    waiters: Vec<Waiter>,
+    /// Turn each task last took, for round-robin between them.
+    last_turn: BTreeMap<Option<Tid>, u64>,
+    turn: u64,
+}
+
+impl Turns {
+    /// Takes the turn for `owner`, once it's been granted.
+    fn take(&mut self, owner: Option<Tid>) {
+        self.busy = true;
+        self.turn += 1;
+        self.last_turn.insert(owner, self.turn);
+    }
+
+    /// Class `waiter` competes for the next turn as, after any promotion for the turns it's waited.
+    fn effective_class(&self, waiter: &Waiter) -> IoClass {
+        if waiter.class == IoClass::Idle && (self.turn - waiter.queued_turn) >= IDLE_GRACE_TURNS {
+            IoClass::Normal
+        } else {
+            waiter.class
+        }
+    }
+
+    /// Removes the waiter of the highest class whose task has waited longest since its last turn, along with whether
+    /// it was promoted out of the idle class to get it.
+    fn next_waiter(&mut self) -> Option<(Waiter, bool)> {
+        let index = self
+            .waiters
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, waiter)| {
+                (self.effective_class(waiter), self.last_turn.get(&waiter.owner).copied().unwrap_or(0), waiter.ticket)
+            })
+            .map(|(index, _)| index)?;
+
+        let promoted = self.effective_class(&self.waiters[index]) != self.waiters[index].class;
+        Some((self.waiters.swap_remove(index), promoted))
+    }
+}
+
+struct HardwareQueue {
+    turns: InterruptCell<Mutex<Turns>>,
+    /// Ticket of the waiter granted the next turn.
+    granted: AtomicU64,
+    /// Whether completions have been steered to a core submitting on the queue.
+    steered: AtomicBool,
+    /// Requests dispatched of each [`IoClass`].
+    dispatched: [AtomicU64; CLASS_COUNT],
+    /// Idle-class requests dispatched only once they'd waited out [`IDLE_GRACE_TURNS`].
+    promoted: AtomicU64,
+}
+
+/// Requests staged by a core.
+struct SoftwareQueue {
+    submitted: AtomicU64,
+    /// Requests which had to wait for their hardware queue.
+    waited: AtomicU64,
+}
+
+pub struct Queues {
+    hardware: Vec<HardwareQueue>,
+    software: [SoftwareQueue; MAX_CORES],
+    next_ticket: AtomicU64,
+}
+
+/// Index of the local core's software queue.
+fn local_index() -> usize {
+    crate::cpu::state::get_core_index().unwrap_or(0)
+}
+
+/// Task the local core is running, if any.
+fn current_owner() -> Option<Tid> {
+    crate::cpu::state::get_core_index().ok()?;
+    crate::cpu::state::with_scheduler(|scheduler| scheduler.process().map(crate::task::Task::id))
+}
+
+/// [`IoClass`] of the task the local core is running, which the kernel's own requests share with
+/// [`IoClass::Normal`].
+pub fn current_class() -> IoClass {
+    if crate::cpu::state::get_core_index().is_err() {
+        return IoClass::Normal;
+    }
+
+    crate::cpu::state::with_scheduler(|scheduler| scheduler.process().map(|task| task.info().io_class()))
+        .unwrap_or(IoClass::Normal)
+}
+
+/// Accounts block I/O of `direction`, which transferred `bytes`, to the task the local core is running (if any).
+pub fn account(direction: IoDirection, bytes: usize) {
+    if crate::cpu::state::get_core_index().is_err() {
+        return;
+    }
+
+    crate::cpu::state::with_scheduler(|scheduler| {
+        if let Some(task) = scheduler.process() {
+            task.info().account(IoKind::Block, direction, bytes);
+        }
+    });
+}
+
+impl Queues {
+    pub fn new(hardware_queue_count: usize) -> Self {
+        Self {
+            hardware: (0..hardware_queue_count.max(1))
+                .map(|_| HardwareQueue {
+                    turns: InterruptCell::new(Mutex::new(Turns::default())),
+                    granted: AtomicU64::new(NO_TICKET),
+                    steered: AtomicBool::new(false),
+                    dispatched: [const { AtomicU64::new(0) }; CLASS_COUNT],
+                    promoted: AtomicU64::new(0),
+                })
+                .collect(),
+            software: [const { SoftwareQueue { submitted: AtomicU64::new(0), waited: AtomicU64::new(0) } }; MAX_CORES],
+            next_ticket: AtomicU64::new(NO_TICKET + 1),
+        }
+    }
+
+    /// Hardware queue the core at `index` is mapped onto.
+    #[inline]
+    pub fn hardware_queue_of(&self, index: usize) -> usize {
+        index % self.hardware.len()
+    }
+
+    /// Stages a request of `class` on the local core's software queue, calling `request` with its hardware queue once
+    /// it's that queue's turn to issue it. `steer` is called to steer the hardware queue's completions to the local
+    /// core, the first time it's submitted on.
+    pub fn dispatch<T>(&self, class: IoClass, steer: impl FnOnce(usize), request: impl FnOnce(usize) -> T) -> T {
+        let core_index = local_index();
+        let queue_index = self.hardware_queue_of(core_index);
+        let queue = &self.hardware[queue_index];
+
+        self.software[core_index].submitted.fetch_add(1, Ordering::Relaxed);
+        if !queue.steered.swap(true, Ordering::AcqRel) {
+            steer(queue_index);
+        }
+
+        let owner = current_owner();
+        let ticket = queue.turns.with(|turns| {
+            let mut turns = turns.lock();
+
+            if turns.busy {
+                let ticket = self.next_ticket.fetch_add(1, Ordering::Relaxed);
+                let queued_turn = turns.turn;
+                turns.waiters.push(Waiter { ticket, owner, class, queued_turn });
+                Some(ticket)
+            } else {
+                turns.take(owner);
+                None
+            }
+        });
+
+        if let Some(ticket) = ticket {
+            self.software[core_index].waited.fetch_add(1, Ordering::Relaxed);
+
+            while queue.granted.load(Ordering::Acquire) != ticket {
+                core::hint::spin_loop();
+            }
+        }
+
+        let result = request(queue_index);
+        queue.dispatched[class as usize].fetch_add(1, Ordering::Relaxed);
+
+        // Hands the turn to the next waiter, or idles the queue.
+        queue.turns.with(|turns| {
+            let mut turns = turns.lock();
+
+            if let Some((waiter, promoted)) = turns.next_waiter() {
+                if promoted {
+                    queue.promoted.fetch_add(1, Ordering::Relaxed);
+                }
+
+                turns.take(waiter.owner);
+                queue.granted.store(waiter.ticket, Ordering::Release);
+            } else {
+                // Without contention, there's nothing to be fair between.
+                turns.busy = false;
+                turns.last_turn.clear();
+                queue.granted.store(NO_TICKET, Ordering::Release);
+            }
+        });
+
+        result
+    }
+
+    /// Renders the requests dispatched on each hardware queue (by class), and submitted from each core.
+    pub fn render(&self) -> String {
+        let mut string = String::new();
+
+        for (index, queue) in self.hardware.iter().enumerate() {
+            let [realtime, normal, idle] = queue.dispatched.each_ref().map(|count| count.load(Ordering::Relaxed));
+
+            writeln!(
+                string,
+                "hwq {:<3} dispatched={} rt={} normal={} idle={} idle_promoted={}",
+                index,
+                realtime + normal + idle,
+                realtime,
+                normal,
+                idle,
+                queue.promoted.load(Ordering::Relaxed)
+            )
+            .unwrap();
+        }
+
+        for index in crate::interrupts::ipi::cores() {
+            let queue = &self.software[index];
+
+            writeln!(
+                string,
+                "cpu {:<3} hwq={} submitted={} waited={}",
+                index,
+                self.hardware_queue_of(index),
+                queue.submitted.load(Ordering::Relaxed),
+                queue.waited.load(Ordering::Relaxed)
+            )
+            .unwrap();
+        }
+
+        string
+    }
+}
@@ -0,0 +1,141 @@
+//! Submission and completion queue entries, and the commands the driver issues.
+
+use bit_field::BitField;
+
+/// Admin command set opcodes.
+pub mod admin {
+    pub const CREATE_IO_SQ: u8 = 0x01;
+    pub const CREATE_IO_CQ: u8 = 0x05;
+    pub const IDENTIFY: u8 = 0x06;
+    pub const SET_FEATURES: u8 = 0x09;
+}
+
+/// NVM command set opcodes.
+pub mod io {
+    pub const FLUSH: u8 = 0x00;
+    pub const WRITE: u8 = 0x01;
+    pub const READ: u8 = 0x02;
+}
+
+/// Controller or namespace structure returned by [`admin::IDENTIFY`], in `cdw10`.
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cns {
+    Namespace = 0x00,
+    Controller = 0x01,
+    ActiveNamespaces = 0x02,
+}
+
+/// Feature set by [`admin::SET_FEATURES`]: the number of I/O queues requested.
+pub const FEATURE_QUEUE_COUNT: u32 = 0x07;
+
+/// A submission queue entry.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Command {
+    pub opcode: u8,
+    /// Fused operation and PRP/SGL selection, which are always left as a normal command with PRPs.
+    pub flags: u8,
+    /// Set as the command is submitted, to the slot tracking it.
+    pub command_id: u16,
+    pub nsid: u32,
+    pub cdw2: u32,
+    pub cdw3: u32,
+    pub metadata: u64,
+    pub prp1: u64,
+    pub prp2: u64,
+    pub cdw10: u32,
+    pub cdw11: u32,
+    pub cdw12: u32,
+    pub cdw13: u32,
+    pub cdw14: u32,
+    pub cdw15: u32,
+}
+
+const _: () = assert!(core::mem::size_of::<Command>() == 64);
+
+impl Command {
+    pub fn identify(cns: Cns, nsid: u32) -> Self {
+        Self { opcode: admin::IDENTIFY, nsid, cdw10: cns as u32, ..Default::default() }
+    }
+
+    /// Requests `count` submission and completion queues (beyond the admin pair).
+    pub fn set_queue_count(count: u16) -> Self {
+        let count = u32::from(count - 1);
+        Self {
+            opcode: admin::SET_FEATURES,
+            cdw10: FEATURE_QUEUE_COUNT,
+            cdw11: (count << 16) | count,
+            ..Default::default()
+        }
+    }
+
+    /// Creates the completion queue `id` of `depth` entries at `address`, interrupting on MSI-X entry `vector`.
+    pub fn create_io_cq(id: u16, depth: u16, address: usize, vector: u16) -> Self {
+        Self {
+            opcode: admin::CREATE_IO_CQ,
+            prp1: u64::try_from(address).unwrap(),
+            cdw10: (u32::from(depth - 1) << 16) | u32::from(id),
+            // Physically contiguous, with interrupts enabled.
+            cdw11: (u32::from(vector) << 16) | 0b11,
+            ..Default::default()
+        }
+    }
+
+    /// Creates the submission queue `id` of `depth` entries at `address`, completing onto completion queue `cq_id`.
+    pub fn create_io_sq(id: u16, depth: u16, address: usize, cq_id: u16) -> Self {
+        Self {
+            opcode: admin::CREATE_IO_SQ,
+            prp1: u64::try_from(address).unwrap(),
+            cdw10: (u32::from(depth - 1) << 16) | u32::from(id),
+            // Physically contiguous.
+            cdw11: (u32::from(cq_id) << 16) | 0b1,
+            ..Default::default()
+        }
+    }
+
+    /// Reads or writes (by `opcode`) `blocks` blocks of namespace `nsid`, starting at `lba`.
+    pub fn transfer(opcode: u8, nsid: u32, lba: u64, blocks: u16) -> Self {
+        Self {
+            opcode,
+            nsid,
+            cdw10: u32::try_from(lba.get_bits(0..32)).unwrap(),
+            cdw11: u32::try_from(lba.get_bits(32..64)).unwrap(),
+            cdw12: u32::from(blocks - 1),
+            ..Default::default()
+        }
+    }
+
+    pub fn flush(nsid: u32) -> Self {
+        Self { opcode: io::FLUSH, nsid, ..Default::default() }
+    }
+}
+
+/// A completion queue entry.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Completion {
+    /// Command-specific result (i.e. the queues allocated by [`Command::set_queue_count`]).
+    pub result: u32,
+    pub reserved: u32,
+    pub sq_head: u16,
+    pub sq_id: u16,
+    pub command_id: u16,
+    /// Phase tag in bit 0, and the command's status above it.
+    pub status: u16,
+}
+
+const _: () = assert!(core::mem::size_of::<Completion>() == 16);
+
+impl Completion {
+    #[inline]
+    pub const fn phase(&self) -> bool {
+        (self.status & 0b1) == 1
+    }
+
+    /// Status field, without the phase tag (zero on success).
+    #[inline]
+    pub const fn status_field(&self) -> u16 {
+        self.status >> 1
+    }
+}
@@ -0,0 +1,558 @@
+//! NVMe controllers, bound by the driver framework (see [`crate::driver`]) to PCI devices of class 01h/08h.
+//!
+//! Each controller is reset and enabled with an admin queue pair, then given an I/O queue pair for each MSI-X entry
+//! beyond the admin queue's (up to [`MAX_IO_QUEUES`], or as many as the controller grants). Each active namespace is
+//! registered as a block device (`nvme<controller>n<namespace>`), whose hardware queues are the I/O queue pairs.
+//!
+//! Transfers go through a bounce buffer of DMA memory. [`Namespace::submit_read`] and [`Namespace::submit_write`]
+//! issue a transfer without waiting for it, returning a [`Pending`] request to poll or wait on.
+
+mod command;
+mod queue;
+
+use crate::{
+    driver::{self, Driver, Match},
+    mem::{
+        alloc::pmm,
+        io::{
+            dma::DmaBuffer,
+            pci::{self, Device, Standard},
+            poll,
+        },
+        vmap::Mapping,
+    },
+};
+use alloc::{boxed::Box, collections::BTreeMap, format, string::String, sync::Arc, vec::Vec};
+use bit_field::BitField;
+use command::{Cns, Command};
+use core::{
+    num::NonZeroUsize,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use libsys::{page_shift, page_size};
+use queue::{PRP_LIST_LEN, QueuePair};
+use spin::Mutex;
+
+crate::error_impl! {
+    #[derive(Debug)]
+    pub enum Error {
+        Pci { err: pci::device::Error } => Some(err),
+        Allocation { err: pmm::Error } => None,
+        /// The controller doesn't support the NVM command set, or the kernel's page size.
+        Unsupported => None,
+        /// The controller reported a fatal status.
+        Fatal => None,
+        /// The controller didn't become ready, or a command didn't complete, in time.
+        Timeout => None,
+        /// A command completed with the (non-zero) status field `status`.
+        Status { status: u16 } => None,
+        /// The controller granted no I/O queues.
+        NoIoQueues => None,
+        /// A transfer is larger than a single command can carry (see [`Namespace::max_transfer`]).
+        TooLarge { len: usize } => None,
+        /// A transfer is empty, or isn't a whole number of blocks.
+        Unaligned { len: usize } => None
+    }
+}
+
+impl From<Error> for crate::block::Error {
+    fn from(err: Error) -> Self {
+        warn!("NVMe request failed: {:?}", err);
+
+        match err {
+            Error::TooLarge { .. } | Error::Unaligned { .. } => Self::OutOfRange,
+            _ => Self::Device,
+        }
+    }
+}
+
+/// Most I/O queue pairs requested of a controller. Not every core is started as controllers are probed, so this
+/// bounds the queues to as many as the cores which may share them.
+pub const MAX_IO_QUEUES: u16 = 16;
+/// Entries of the admin queues.
+const ADMIN_DEPTH: u16 = 32;
+/// Most entries of each I/O queue, which the controller may bound further.
+const IO_DEPTH: u16 = 64;
+/// Most bytes transferred by a single command, which the controller may bound further.
+const MAX_TRANSFER: usize = 128 * 1024;
+
+/// Bytes of each identify structure.
+const IDENTIFY_LEN: usize = 4096;
+
+/// Offsets of the controller registers.
+const CAP: usize = 0x00;
+const CC: usize = 0x14;
+const CSTS: usize = 0x1C;
+const AQA: usize = 0x24;
+const ASQ: usize = 0x28;
+const ACQ: usize = 0x30;
+/// Offset of the first doorbell register.
+const DOORBELLS: usize = 0x1000;
+
+/// Bits of the controller configuration and status registers.
+const CC_ENABLE: usize = 0;
+const CSTS_READY: usize = 0;
+const CSTS_FATAL: usize = 1;
+
+/// The controller's register block (BAR 0).
+pub struct Registers {
+    mapping: Mapping,
+    /// Bytes between each doorbell register.
+    doorbell_stride: usize,
+}
+
+impl Registers {
+    fn read32(&self, offset: usize) -> u32 {
+        // Safety: Offset lies within the register block, which is mapped for as long as `self`.
+        unsafe { self.mapping.as_ptr().as_ptr().add(offset).cast::<u32>().read_volatile() }
+    }
+
+    fn write32(&self, offset: usize, value: u32) {
+        // Safety: Offset lies within the register block, which is mapped for as long as `self`.
+        unsafe { self.mapping.as_ptr().as_ptr().add(offset).cast::<u32>().write_volatile(value) };
+    }
+
+    fn read64(&self, offset: usize) -> u64 {
+        // Safety: Offset lies within the register block, which is mapped for as long as `self`.
+        unsafe { self.mapping.as_ptr().as_ptr().add(offset).cast::<u64>().read_volatile() }
+    }
+
+    fn write64(&self, offset: usize, value: u64) {
+        // Safety: Offset lies within the register block, which is mapped for as long as `self`.
+        unsafe { self.mapping.as_ptr().as_ptr().add(offset).cast::<u64>().write_volatile(value) };
+    }
+
+    /// Writes `value` to doorbell `index`: the submission queue tail of queue `id` is doorbell `2 * id`, and its
+    /// completion queue head is the next.
+    pub fn ring_doorbell(&self, index: u16, value: u16) {
+        self.write32(DOORBELLS + (usize::from(index) * self.doorbell_stride), u32::from(value));
+    }
+
+    /// Sets whether the controller is enabled, waiting up to `timeout_us` for it to become (or stop being) ready.
+    fn set_enabled(&self, enabled: bool, timeout_us: u32) -> Result<()> {
+        let mut config = self.read32(CC);
+        config.set_bit(CC_ENABLE, enabled);
+        self.write32(CC, config);
+
+        poll::poll_until_yield(timeout_us, || {
+            let status = self.read32(CSTS);
+            status.get_bit(CSTS_FATAL) || status.get_bit(CSTS_READY) == enabled
+        })
+        .map_err(|_| Error::Timeout)?;
+
+        if self.read32(CSTS).get_bit(CSTS_FATAL) { Err(Error::Fatal) } else { Ok(()) }
+    }
+}
+
+pub struct Controller {
+    name: String,
+    registers: Arc<Registers>,
+    admin: Arc<QueuePair>,
+    io: Vec<Arc<QueuePair>>,
+    /// MSI-X of the device: entry 0 interrupts for the admin queue, and each entry after for the I/O queue of its
+    /// index.
+    msix: Mutex<pci::Msix>,
+    /// Most bytes transferred by a single command.
+    max_transfer: usize,
+    /// Time the controller may take to become (or stop being) ready.
+    timeout_us: u32,
+}
+
+impl Controller {
+    /// Issues an admin command, waiting for it to complete.
+    fn admin_command(&self, command: Command, data: Option<(&DmaBuffer, usize)>) -> Result<u32> {
+        let slot = self.admin.submit(command, data)?;
+        self.admin.wait(slot)
+    }
+
+    /// Reads the identify structure `cns` (of namespace `nsid`) into a page of DMA memory.
+    fn identify(&self, cns: Cns, nsid: u32) -> Result<DmaBuffer> {
+        let buffer = DmaBuffer::new(IDENTIFY_LEN).map_err(|err| Error::Allocation { err })?;
+        self.admin_command(Command::identify(cns, nsid), Some((&buffer, IDENTIFY_LEN)))?;
+
+        Ok(buffer)
+    }
+}
+
+impl Drop for Controller {
+    fn drop(&mut self) {
+        // The controller must stop using its queues before they're freed.
+        if let Err(err) = self.registers.set_enabled(false, self.timeout_us) {
+            error!("Failed to disable {}, so its queues are leaked: {:?}", self.name, err);
+            core::mem::forget(self.admin.clone());
+            self.io.iter().for_each(|queue| core::mem::forget(queue.clone()));
+        }
+    }
+}
+
+/// An active namespace of a controller.
+#[derive(Clone)]
+pub struct Namespace {
+    controller: Arc<Controller>,
+    nsid: u32,
+    block_size: usize,
+    block_count: u64,
+}
+
+/// Direction of a transfer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Transfer {
+    Read,
+    Write,
+}
+
+/// A transfer which has been submitted, holding its bounce buffer until it completes.
+pub struct Pending {
+    queue: Arc<QueuePair>,
+    slot: u16,
+    /// Taken once the transfer completes, so it's dropped only once the device is done with it.
+    buffer: Option<DmaBuffer>,
+}
+
+impl Pending {
+    /// Whether the transfer has completed (successfully or not), without waiting for it.
+    pub fn is_complete(&self) -> bool {
+        self.queue.reap();
+        // The completion is left in its slot for `wait` to take.
+        self.queue.is_done(self.slot)
+    }
+
+    /// Waits for the transfer to complete, taking back its buffer once the device is done with it.
+    fn finish(&mut self) -> Result<DmaBuffer> {
+        let buffer = self.buffer.take().unwrap();
+
+        match self.queue.wait(self.slot) {
+            Ok(_) => Ok(buffer),
+
+            Err(Error::Timeout) => {
+                // The device may yet write to the buffer, so it can never be freed.
+                warn!("Leaking the buffer of an NVMe transfer which never completed.");
+                core::mem::forget(buffer);
+
+                Err(Error::Timeout)
+            }
+
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Waits for the transfer to complete, returning its buffer (which, for a read, holds the data read).
+    pub fn wait(mut self) -> Result<DmaBuffer> {
+        self.finish()
+    }
+}
+
+impl Drop for Pending {
+    fn drop(&mut self) {
+        if self.buffer.is_some() {
+            self.finish().ok();
+        }
+    }
+}
+
+impl Namespace {
+    /// Most bytes a single [`Pending`] transfer can carry.
+    #[inline]
+    pub fn max_transfer(&self) -> usize {
+        self.controller.max_transfer
+    }
+
+    fn submit(&self, queue: usize, transfer: Transfer, lba: u64, buffer: DmaBuffer, len: usize) -> Result<Pending> {
+        if len > self.max_transfer() || len > buffer.len() {
+            return Err(Error::TooLarge { len });
+        }
+        if len == 0 || (len % self.block_size) > 0 {
+            return Err(Error::Unaligned { len });
+        }
+
+        let blocks = u16::try_from(len / self.block_size).map_err(|_| Error::TooLarge { len })?;
+        let opcode = match transfer {
+            Transfer::Read => command::io::READ,
+            Transfer::Write => command::io::WRITE,
+        };
+
+        let queue = self.controller.io[queue % self.controller.io.len()].clone();
+        let slot = queue.submit(Command::transfer(opcode, self.nsid, lba, blocks), Some((&buffer, len)))?;
+
+        Ok(Pending { queue, slot, buffer: Some(buffer) })
+    }
+
+    /// Submits a read of the `len` bytes (a whole number of blocks) from `lba` into `buffer`, through I/O queue
+    /// `queue`, without waiting for it to complete.
+    pub fn submit_read(&self, queue: usize, lba: u64, buffer: DmaBuffer, len: usize) -> Result<Pending> {
+        self.submit(queue, Transfer::Read, lba, buffer, len)
+    }
+
+    /// Submits a write of the first `len` bytes (a whole number of blocks) of `buffer` to the blocks from `lba`,
+    /// through I/O queue `queue`, without waiting for it to complete.
+    pub fn submit_write(&self, queue: usize, lba: u64, buffer: DmaBuffer, len: usize) -> Result<Pending> {
+        self.submit(queue, Transfer::Write, lba, buffer, len)
+    }
+
+    /// Bytes of each chunk a transfer is split into: as many whole blocks as a single command can carry.
+    fn chunk_len(&self) -> usize {
+        self.max_transfer() - (self.max_transfer() % self.block_size)
+    }
+
+    /// First block of chunk `index` of a transfer from `lba`.
+    fn chunk_lba(&self, lba: u64, index: usize) -> u64 {
+        lba + u64::try_from((index * self.chunk_len()) / self.block_size).unwrap()
+    }
+
+    /// Allocates a bounce buffer large enough for any chunk of a transfer of `len` bytes.
+    fn bounce_buffer(&self, len: usize) -> Result<DmaBuffer> {
+        DmaBuffer::new(self.chunk_len().min(len)).map_err(|err| Error::Allocation { err })
+    }
+
+    /// Reads into `buf` in chunks (see [`Self::chunk_len`]), each through a bounce buffer.
+    fn read_chunks(&self, queue: usize, lba: u64, buf: &mut [u8]) -> Result<()> {
+        if buf.is_empty() {
+            return Ok(());
+        }
+
+        let mut bounce = self.bounce_buffer(buf.len())?;
+        for (index, chunk) in buf.chunks_mut(self.chunk_len()).enumerate() {
+            bounce = self.submit_read(queue, self.chunk_lba(lba, index), bounce, chunk.len())?.wait()?;
+            chunk.copy_from_slice(&bounce.as_slice()[..chunk.len()]);
+        }
+
+        Ok(())
+    }
+
+    /// Writes `buf` in chunks (see [`Self::chunk_len`]), each through a bounce buffer.
+    fn write_chunks(&self, queue: usize, lba: u64, buf: &[u8]) -> Result<()> {
+        if buf.is_empty() {
+            return Ok(());
+        }
+
+        let mut bounce = self.bounce_buffer(buf.len())?;
+        for (index, chunk) in buf.chunks(self.chunk_len()).enumerate() {
+            bounce.as_mut_slice()[..chunk.len()].copy_from_slice(chunk);
+            bounce = self.submit_write(queue, self.chunk_lba(lba, index), bounce, chunk.len())?.wait()?;
+        }
+
+        Ok(())
+    }
+}
+
+impl crate::block::BlockDevice for Namespace {
+    fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    fn block_count(&self) -> u64 {
+        self.block_count
+    }
+
+    fn read_blocks(&self, lba: u64, buf: &mut [u8]) -> crate::block::Result<()> {
+        self.read_blocks_on(0, lba, buf)
+    }
+
+    fn write_blocks(&self, lba: u64, buf: &[u8]) -> crate::block::Result<()> {
+        self.write_blocks_on(0, lba, buf)
+    }
+
+    fn flush(&self) -> crate::block::Result<()> {
+        let queue = &self.controller.io[0];
+        let slot = queue.submit(Command::flush(self.nsid), None)?;
+        queue.wait(slot)?;
+
+        Ok(())
+    }
+
+    fn queue_count(&self) -> usize {
+        self.controller.io.len()
+    }
+
+    fn read_blocks_on(&self, queue: usize, lba: u64, buf: &mut [u8]) -> crate::block::Result<()> {
+        Ok(self.read_chunks(queue, lba, buf)?)
+    }
+
+    fn write_blocks_on(&self, queue: usize, lba: u64, buf: &[u8]) -> crate::block::Result<()> {
+        Ok(self.write_chunks(queue, lba, buf)?)
+    }
+
+    fn steer_completions(&self, queue: usize, apic_id: u32) {
+        if let Err(err) = self.controller.msix.lock().set_target(queue + 1, apic_id) {
+            warn!("Failed to steer the completions of {} queue {}: {:?}", self.controller.name, queue + 1, err);
+        }
+    }
+}
+
+static NAMESPACES: Mutex<BTreeMap<String, Namespace>> = Mutex::new(BTreeMap::new());
+static NEXT_CONTROLLER: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns the namespace registered as the block device `name`, for issuing [`Pending`] transfers to.
+pub fn get(name: &str) -> Option<Namespace> {
+    NAMESPACES.lock().get(name).cloned()
+}
+
+/// Enables MSI-X with `vector_count` entries (or as many as the device has, if it has fewer), each reaping the
+/// completions of the queue of its index in `queues`.
+fn enable_interrupts(
+    device: &mut Device<Standard>,
+    name: &str,
+    vector_count: usize,
+    queues: &Arc<[spin::Once<Arc<QueuePair>>]>,
+) -> Result<pci::Msix> {
+    let handler_for = |index: usize| -> crate::interrupts::irq::Handler {
+        let queues = queues.clone();
+        Box::new(move || queues[index].get().is_some_and(|queue| queue.reap()))
+    };
+
+    match device.enable_msix(NonZeroUsize::new(vector_count).unwrap(), name, handler_for) {
+        Err(pci::device::Error::TooManyVectors { available, .. }) => {
+            device.enable_msix(NonZeroUsize::new(available).unwrap(), name, handler_for)
+        }
+
+        result => result,
+    }
+    .map_err(|err| Error::Pci { err })
+}
+
+/// Resets and enables the controller of `device`, then creates its I/O queues.
+fn init_controller(device: &mut Device<Standard>, name: String) -> Result<Controller> {
+    device.set_memory_decode(true);
+    let mapping = device.map_bar(0).map_err(|err| Error::Pci { err })?;
+
+    let mut registers = Registers { mapping, doorbell_stride: 0 };
+    let capabilities = registers.read64(CAP);
+    registers.doorbell_stride = 4 << capabilities.get_bits(32..36);
+    let registers = Arc::new(registers);
+
+    let max_depth = u16::try_from(capabilities.get_bits(0..16) + 1).unwrap_or(u16::MAX);
+    // Timeout is given in units of 500ms.
+    let timeout_us = u32::try_from(capabilities.get_bits(24..32) * 500_000).unwrap().max(500_000);
+    let page_bits = u64::from(page_shift().get() - 12);
+    if !capabilities.get_bit(37)
+        || !(capabilities.get_bits(48..52)..=capabilities.get_bits(52..56)).contains(&page_bits)
+    {
+        return Err(Error::Unsupported);
+    }
+
+    registers.set_enabled(false, timeout_us)?;
+
+    let admin_depth = ADMIN_DEPTH.min(max_depth);
+    let admin = Arc::new(QueuePair::new(0, admin_depth, registers.clone())?);
+    registers.write32(AQA, (u32::from(admin_depth - 1) << 16) | u32::from(admin_depth - 1));
+    registers.write64(ASQ, u64::try_from(admin.sq_address()).unwrap());
+    registers.write64(ACQ, u64::try_from(admin.cq_address()).unwrap());
+
+    let queues: Arc<[spin::Once<Arc<QueuePair>>]> =
+        (0..=MAX_IO_QUEUES).map(|_| spin::Once::new()).collect::<Vec<_>>().into();
+    queues[0].call_once(|| admin.clone());
+    let msix = enable_interrupts(device, &name, usize::from(MAX_IO_QUEUES) + 1, &queues)?;
+    let vector_count = msix.vectors().count();
+
+    // 64-byte submission entries, 16-byte completion entries, round-robin arbitration, and the NVM command set.
+    registers.write32(CC, (4 << 20) | (6 << 16) | (u32::try_from(page_bits).unwrap() << 7));
+
+    // Controller is disabled again as it's dropped, so any failure from here leaves it disabled.
+    let mut controller = Controller {
+        name,
+        registers,
+        admin,
+        io: Vec::new(),
+        msix: Mutex::new(msix),
+        max_transfer: MAX_TRANSFER.min(PRP_LIST_LEN * page_size()),
+        timeout_us,
+    };
+    controller.registers.set_enabled(true, timeout_us)?;
+
+    let identify = controller.identify(Cns::Controller, 0)?;
+    // Maximum data transfer size, as a power of two of the minimum page size (zero if unbounded).
+    let mdts = identify.as_slice()[77];
+    if mdts > 0
+        && let Some(limit) =
+            1usize.checked_shl(u32::from(mdts) + 12 + u32::try_from(capabilities.get_bits(48..52)).unwrap())
+    {
+        controller.max_transfer = controller.max_transfer.min(limit);
+    }
+    let model = core::str::from_utf8(&identify.as_slice()[24..64]).unwrap_or("").trim_end();
+    info!("{}: {} (max transfer {} KiB)", controller.name, model, controller.max_transfer / 1024);
+
+    // Allocated queue counts are zero-based, with submission queues in the low word.
+    let requested = u16::try_from(vector_count - 1).unwrap().min(MAX_IO_QUEUES);
+    if requested == 0 {
+        return Err(Error::NoIoQueues);
+    }
+    let allocated = controller.admin_command(Command::set_queue_count(requested), None)?;
+    let granted = requested
+        .min(u16::try_from(allocated.get_bits(0..16)).unwrap() + 1)
+        .min(u16::try_from(allocated.get_bits(16..32)).unwrap() + 1);
+
+    let io_depth = IO_DEPTH.min(max_depth);
+    for id in 1..=granted {
+        let queue = Arc::new(QueuePair::new(id, io_depth, controller.registers.clone())?);
+        queues[usize::from(id)].call_once(|| queue.clone());
+
+        controller.admin_command(Command::create_io_cq(id, io_depth, queue.cq_address(), id), None)?;
+        controller.admin_command(Command::create_io_sq(id, io_depth, queue.sq_address(), id), None)?;
+        controller.io.push(queue);
+    }
+
+    Ok(controller)
+}
+
+/// Registers each active namespace of `controller` as a block device.
+fn register_namespaces(controller: &Arc<Controller>) -> Result<()> {
+    let list = controller.identify(Cns::ActiveNamespaces, 0)?;
+    let nsids = list
+        .as_slice()
+        .chunks_exact(4)
+        .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+        .take_while(|nsid| *nsid != 0);
+
+    for nsid in nsids {
+        let identify = controller.identify(Cns::Namespace, nsid)?;
+        let identify = identify.as_slice();
+
+        let block_count = u64::from_le_bytes(identify[0..8].try_into().unwrap());
+        // Formatted LBA size indexes the LBA formats, whose data size is a power of two.
+        let format = usize::from(identify[26].get_bits(0..4));
+        let format_offset = 128 + (format * 4);
+        let data_bits =
+            u32::from_le_bytes(identify[format_offset..(format_offset + 4)].try_into().unwrap()).get_bits(16..24);
+
+        // Each command must carry at least a block, as transfers are split into commands of whole blocks.
+        if block_count == 0 || !(9..=16).contains(&data_bits) || (1 << data_bits) > controller.max_transfer {
+            warn!("{} namespace {} has an unsupported format.", controller.name, nsid);
+            continue;
+        }
+
+        let namespace = Namespace { controller: controller.clone(), nsid, block_size: 1 << data_bits, block_count };
+        let name = format!("{}n{}", controller.name, nsid);
+        match crate::block::register(&name, Box::new(namespace.clone())) {
+            Ok(_) => {
+                NAMESPACES.lock().insert(name, namespace);
+            }
+
+            Err(err) => warn!("Failed to register {}: {:?}", name, err),
+        }
+    }
+
+    Ok(())
+}
+
+fn probe(device: &mut Device<Standard>) -> driver::Result<driver::Instance> {
+    let name = format!("nvme{}", NEXT_CONTROLLER.fetch_add(1, Ordering::Relaxed));
+
+    let controller = init_controller(device, name.clone()).map(Arc::new).map_err(|err| {
+        warn!("Failed to initialize {} ({}): {:?}", name, device.bdf(), err);
+        driver::Error::Device
+    })?;
+
+    if let Err(err) = register_namespaces(&controller) {
+        warn!("Failed to enumerate the namespaces of {}: {:?}", name, err);
+    }
+
+    Ok(Box::new(controller))
+}
+
+static DRIVER: Driver =
+    Driver { name: "nvme", matches: &[Match::Class { class: 0x01, subclass: 0x08, prog_if: Some(0x02) }], probe };
+
+/// Registers the NVMe driver, to be offered the PCI devices of the NVM Express class.
+pub fn register() {
+    driver::register(&DRIVER);
+}
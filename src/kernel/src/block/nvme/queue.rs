@@ -0,0 +1,233 @@
+//! A submission queue, paired with the completion queue its commands complete onto.
+//!
+//! Each command in flight holds a slot, whose index is its command ID. Completions are reaped into their slots by the
+//! queue's interrupt handler, or by a waiter polling the completion queue itself (i.e. before interrupts are enabled).
+
+use super::{
+    command::{Command, Completion},
+    Error, Registers, Result,
+};
+use crate::{
+    interrupts::InterruptCell,
+    mem::io::{dma::DmaBuffer, poll},
+};
+use alloc::{boxed::Box, sync::Arc};
+use core::sync::atomic::{fence, AtomicU32, Ordering};
+use libsys::page_size;
+use spin::Mutex;
+
+/// Slot holds no command.
+const SLOT_FREE: u32 = 0;
+/// Slot's command has been submitted, and is yet to complete.
+const SLOT_PENDING: u32 = 1;
+/// Set on a slot whose command has completed, with its status field in the low bits.
+const SLOT_DONE: u32 = 1 << 31;
+
+/// Time a command may take to complete before it's given up on.
+pub const COMMAND_TIMEOUT_US: u32 = 5_000_000;
+
+/// PRP entries which fit in a page of a PRP list, which bounds the pages of a single transfer.
+pub const PRP_LIST_LEN: usize = 512;
+
+struct Rings {
+    sq_tail: u16,
+    cq_head: u16,
+    /// Phase tag of the completions yet to be reaped, which flips each time the completion queue wraps.
+    phase: bool,
+}
+
+pub struct QueuePair {
+    id: u16,
+    depth: u16,
+    registers: Arc<Registers>,
+    sq: DmaBuffer,
+    cq: DmaBuffer,
+    /// A page of PRP list for each slot.
+    prp_lists: DmaBuffer,
+    rings: InterruptCell<Mutex<Rings>>,
+    /// State of each slot (see [`SLOT_FREE`]).
+    slots: Box<[AtomicU32]>,
+    /// Command-specific result of each slot's completion.
+    results: Box<[AtomicU32]>,
+}
+
+impl QueuePair {
+    /// Allocates the rings of queue pair `id`, with `depth` entries each.
+    pub fn new(id: u16, depth: u16, registers: Arc<Registers>) -> Result<Self> {
+        let allocate = |len| DmaBuffer::new(len).map_err(|err| Error::Allocation { err });
+
+        // One entry is always left empty, so a full submission queue can be told apart from an empty one.
+        let slot_count = usize::from(depth) - 1;
+
+        Ok(Self {
+            id,
+            depth,
+            registers,
+            sq: allocate(usize::from(depth) * core::mem::size_of::<Command>())?,
+            cq: allocate(usize::from(depth) * core::mem::size_of::<Completion>())?,
+            prp_lists: allocate(slot_count * page_size())?,
+            rings: InterruptCell::new(Mutex::new(Rings { sq_tail: 0, cq_head: 0, phase: true })),
+            slots: (0..slot_count).map(|_| AtomicU32::new(SLOT_FREE)).collect(),
+            results: (0..slot_count).map(|_| AtomicU32::new(0)).collect(),
+        })
+    }
+
+    #[inline]
+    pub const fn id(&self) -> u16 {
+        self.id
+    }
+
+    #[inline]
+    pub const fn depth(&self) -> u16 {
+        self.depth
+    }
+
+    #[inline]
+    pub fn sq_address(&self) -> usize {
+        self.sq.physical()
+    }
+
+    #[inline]
+    pub fn cq_address(&self) -> usize {
+        self.cq.physical()
+    }
+
+    fn try_acquire(&self) -> Option<u16> {
+        self.slots
+            .iter()
+            .position(|slot| {
+                slot.compare_exchange(SLOT_FREE, SLOT_PENDING, Ordering::Acquire, Ordering::Relaxed).is_ok()
+            })
+            .map(|index| u16::try_from(index).unwrap())
+    }
+
+    /// Submits `command`, transferring the first `len` bytes of `data` (if any), and returns the slot it holds.
+    pub fn submit(&self, mut command: Command, data: Option<(&DmaBuffer, usize)>) -> Result<u16> {
+        // Waits for a command to complete if every slot is taken.
+        let mut slot = None;
+        poll::poll_until_yield(COMMAND_TIMEOUT_US, || {
+            self.reap();
+            slot = self.try_acquire();
+            slot.is_some()
+        })
+        .map_err(|_| Error::Timeout)?;
+        let slot = slot.unwrap();
+
+        command.command_id = slot;
+        if let Some((buffer, len)) = data {
+            let pages = len.div_ceil(page_size());
+            debug_assert!(len <= buffer.len() && pages <= PRP_LIST_LEN);
+
+            command.prp1 = u64::try_from(buffer.physical()).unwrap();
+            command.prp2 = match pages {
+                0 | 1 => 0,
+                2 => u64::try_from(buffer.physical() + page_size()).unwrap(),
+                pages => {
+                    let list_offset = usize::from(slot) * page_size();
+                    let list = self.prp_lists.as_ptr().as_ptr().wrapping_add(list_offset).cast::<u64>();
+
+                    for index in 1..pages {
+                        let address = u64::try_from(buffer.physical() + (index * page_size())).unwrap();
+                        // Safety: Each entry lies within the slot's page of the PRP lists, which no other command uses.
+                        unsafe { list.add(index - 1).write_volatile(address) };
+                    }
+
+                    u64::try_from(self.prp_lists.physical() + list_offset).unwrap()
+                }
+            };
+        }
+
+        self.rings.with(|rings| {
+            let mut rings = rings.lock();
+
+            let entry = self.sq.as_ptr().as_ptr().cast::<Command>().wrapping_add(usize::from(rings.sq_tail));
+            // Safety: The tail entry lies within the ring, and (as a slot was free) the device is done with it.
+            unsafe { entry.write_volatile(command) };
+            rings.sq_tail = (rings.sq_tail + 1) % self.depth;
+
+            // The entry must be visible to the device before it's told of it.
+            fence(Ordering::SeqCst);
+            self.registers.ring_doorbell(2 * self.id, rings.sq_tail);
+        });
+
+        Ok(slot)
+    }
+
+    /// Reaps any new completions into their slots, returning whether there were any.
+    pub fn reap(&self) -> bool {
+        self.rings.with(|rings| {
+            let mut rings = rings.lock();
+
+            let mut reaped = false;
+            loop {
+                let entry = self.cq.as_ptr().as_ptr().cast::<Completion>().wrapping_add(usize::from(rings.cq_head));
+                // Safety: The head entry lies within the ring.
+                let completion = unsafe { entry.read_volatile() };
+                if completion.phase() != rings.phase {
+                    break;
+                }
+                fence(Ordering::Acquire);
+
+                match self.slots.get(usize::from(completion.command_id)) {
+                    Some(slot) => {
+                        self.results[usize::from(completion.command_id)].store(completion.result, Ordering::Relaxed);
+                        slot.store(SLOT_DONE | u32::from(completion.status_field()), Ordering::Release);
+                    }
+
+                    None => warn!("NVMe queue {} completed unknown command ID {}.", self.id, completion.command_id),
+                }
+
+                rings.cq_head += 1;
+                if rings.cq_head == self.depth {
+                    rings.cq_head = 0;
+                    rings.phase = !rings.phase;
+                }
+                reaped = true;
+            }
+
+            if reaped {
+                self.registers.ring_doorbell((2 * self.id) + 1, rings.cq_head);
+            }
+
+            reaped
+        })
+    }
+
+    /// Whether the command in `slot` has completed, leaving its completion to be taken.
+    pub fn is_done(&self, slot: u16) -> bool {
+        (self.slots[usize::from(slot)].load(Ordering::Acquire) & SLOT_DONE) > 0
+    }
+
+    /// Takes the completion of the command in `slot` (freeing the slot), if it has completed.
+    pub fn take(&self, slot: u16) -> Option<Result<u32>> {
+        let state = self.slots[usize::from(slot)].load(Ordering::Acquire);
+        if (state & SLOT_DONE) == 0 {
+            return None;
+        }
+
+        let result = self.results[usize::from(slot)].load(Ordering::Relaxed);
+        self.slots[usize::from(slot)].store(SLOT_FREE, Ordering::Release);
+
+        match u16::try_from(state & !SLOT_DONE).unwrap() {
+            0 => Some(Ok(result)),
+            status => Some(Err(Error::Status { status })),
+        }
+    }
+
+    /// Waits for the command in `slot` to complete, returning its command-specific result.
+    ///
+    /// #### Remark
+    ///
+    /// A command which times out keeps its slot, as the device may yet complete it.
+    pub fn wait(&self, slot: u16) -> Result<u32> {
+        let mut completion = None;
+        poll::poll_until_yield(COMMAND_TIMEOUT_US, || {
+            self.reap();
+            completion = self.take(slot);
+            completion.is_some()
+        })
+        .map_err(|_| Error::Timeout)?;
+
+        completion.unwrap()
+    }
+}
@@ -0,0 +1,224 @@
+//! virtio-blk devices, bound by the driver framework (see [`crate::driver`]) to virtio PCI functions of the block
+//! device type.
+//!
+//! Each device is registered as a block device (`vd<index>`) of 512-byte sectors, with a single request queue. Each
+//! request goes through a bounce buffer of DMA memory: its header and status byte share the buffer's first page, and its
+//! data follows.
+
+use crate::{
+    driver::{self, Driver, Match},
+    mem::io::{
+        dma::DmaBuffer,
+        pci::{Device, Standard},
+    },
+    virtio::{self, Segment, Transport, Virtqueue},
+};
+use alloc::{boxed::Box, format, string::String};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use libsys::page_size;
+
+/// The device is read-only.
+const F_RO: u64 = 1 << 5;
+/// The device supports the flush request.
+const F_FLUSH: u64 = 1 << 9;
+
+/// Request types.
+const T_IN: u32 = 0;
+const T_OUT: u32 = 1;
+const T_FLUSH: u32 = 4;
+
+/// Status the device writes for a request which succeeded.
+const S_OK: u8 = 0;
+
+/// Bytes of each sector, in which the device is addressed regardless of its physical block size.
+const SECTOR_SIZE: usize = 512;
+/// Offset of the capacity (in sectors) within the device configuration.
+const CONFIG_CAPACITY: usize = 0x00;
+
+/// Most entries of the request queue.
+const QUEUE_SIZE: u16 = 128;
+/// Most bytes transferred by a single request.
+const MAX_TRANSFER: usize = 128 * 1024;
+
+/// Bytes of a request's header: its type, a reserved dword, and its first sector.
+const HEADER_LEN: usize = 16;
+/// Offset of the status byte within a request's buffer, following its header.
+const STATUS_OFFSET: usize = HEADER_LEN;
+
+pub struct VirtioBlock {
+    name: String,
+    /// Declared before the queue, so the device is reset before the queue's rings are freed.
+    transport: Transport,
+    queue: Virtqueue,
+    sector_count: u64,
+    read_only: bool,
+    flush: bool,
+}
+
+impl VirtioBlock {
+    /// Issues a request of `kind` for `len` bytes from `sector`, whose data is the buffer following the first page of
+    /// `buffer`, and waits for it to complete, handing back the buffer.
+    fn request(&self, kind: u32, sector: u64, mut buffer: DmaBuffer, len: usize) -> crate::block::Result<DmaBuffer> {
+        let bytes = buffer.as_mut_slice();
+        bytes[0..4].copy_from_slice(&kind.to_le_bytes());
+        bytes[4..8].fill(0);
+        bytes[8..16].copy_from_slice(&sector.to_le_bytes());
+        bytes[STATUS_OFFSET] = 0xFF;
+
+        let header = Segment { address: buffer.physical(), len: u32::try_from(HEADER_LEN).unwrap(), writable: false };
+        let status = Segment { address: buffer.physical() + STATUS_OFFSET, len: 1, writable: true };
+        let result = if len > 0 {
+            let data = Segment {
+                address: buffer.physical() + page_size(),
+                len: u32::try_from(len).unwrap(),
+                writable: kind == T_IN,
+            };
+
+            self.queue.submit(&[header, data, status])
+        } else {
+            self.queue.submit(&[header, status])
+        }
+        .and_then(|head| self.queue.wait(head));
+
+        match result {
+            Ok(_) if buffer.as_slice()[STATUS_OFFSET] == S_OK => Ok(buffer),
+
+            Ok(_) => {
+                warn!("{}: request {} failed with status {}.", self.name, kind, buffer.as_slice()[STATUS_OFFSET]);
+                Err(crate::block::Error::Device)
+            }
+
+            Err(virtio::Error::Timeout) => {
+                // The device may yet write to the buffer, so it can never be freed.
+                warn!("{}: request {} timed out, leaking its buffer.", self.name, kind);
+                core::mem::forget(buffer);
+                Err(crate::block::Error::Device)
+            }
+
+            Err(err) => {
+                warn!("{}: request {} failed: {:?}", self.name, kind, err);
+                Err(crate::block::Error::Device)
+            }
+        }
+    }
+
+    /// Allocates a request buffer with room for `len` bytes of data.
+    fn buffer(len: usize) -> crate::block::Result<DmaBuffer> {
+        DmaBuffer::new(page_size() + len).map_err(|_| crate::block::Error::Device)
+    }
+}
+
+impl crate::block::BlockDevice for VirtioBlock {
+    fn block_size(&self) -> usize {
+        SECTOR_SIZE
+    }
+
+    fn block_count(&self) -> u64 {
+        self.sector_count
+    }
+
+    fn read_blocks(&self, lba: u64, buf: &mut [u8]) -> crate::block::Result<()> {
+        if buf.is_empty() {
+            return Ok(());
+        }
+
+        let mut buffer = Self::buffer(MAX_TRANSFER.min(buf.len()))?;
+        for (index, chunk) in buf.chunks_mut(MAX_TRANSFER).enumerate() {
+            let sector = lba + u64::try_from((index * MAX_TRANSFER) / SECTOR_SIZE).unwrap();
+            buffer = self.request(T_IN, sector, buffer, chunk.len())?;
+            chunk.copy_from_slice(&buffer.as_slice()[page_size()..(page_size() + chunk.len())]);
+        }
+
+        Ok(())
+    }
+
+    fn write_blocks(&self, lba: u64, buf: &[u8]) -> crate::block::Result<()> {
+        if self.read_only {
+            warn!("{}: write to a read-only device.", self.name);
+            return Err(crate::block::Error::Device);
+        }
+
+        if buf.is_empty() {
+            return Ok(());
+        }
+
+        let mut buffer = Self::buffer(MAX_TRANSFER.min(buf.len()))?;
+        for (index, chunk) in buf.chunks(MAX_TRANSFER).enumerate() {
+            let sector = lba + u64::try_from((index * MAX_TRANSFER) / SECTOR_SIZE).unwrap();
+            buffer.as_mut_slice()[page_size()..(page_size() + chunk.len())].copy_from_slice(chunk);
+            buffer = self.request(T_OUT, sector, buffer, chunk.len())?;
+        }
+
+        Ok(())
+    }
+
+    fn flush(&self) -> crate::block::Result<()> {
+        if !self.flush {
+            // Without the feature, the device doesn't cache writes.
+            return Ok(());
+        }
+
+        self.request(T_FLUSH, 0, Self::buffer(0)?, 0).map(|_| ())
+    }
+}
+
+static NEXT_DEVICE: AtomicUsize = AtomicUsize::new(0);
+
+fn init_device(device: &mut Device<Standard>, name: String) -> virtio::Result<VirtioBlock> {
+    let transport = Transport::new(device)?;
+    let features = transport.negotiate(0, F_RO | F_FLUSH)?;
+    let queue = transport.queue(0, QUEUE_SIZE)?;
+    transport.finish();
+
+    let sector_count = transport.read_config::<u64>(CONFIG_CAPACITY);
+
+    Ok(VirtioBlock {
+        name,
+        transport,
+        queue,
+        sector_count,
+        read_only: (features & F_RO) > 0,
+        flush: (features & F_FLUSH) > 0,
+    })
+}
+
+fn probe(device: &mut Device<Standard>) -> driver::Result<driver::Instance> {
+    let name = format!("vd{}", NEXT_DEVICE.fetch_add(1, Ordering::Relaxed));
+
+    let block = init_device(device, name.clone()).map_err(|err| {
+        warn!("Failed to initialize {} ({}): {:?}", name, device.bdf(), err);
+        match err {
+            virtio::Error::MissingCapability { .. } => driver::Error::Unsupported,
+            _ => driver::Error::Device,
+        }
+    })?;
+
+    info!(
+        "{}: {} MiB{}",
+        name,
+        (block.sector_count * u64::try_from(SECTOR_SIZE).unwrap()) / (1024 * 1024),
+        if block.read_only { " (read-only)" } else { "" }
+    );
+
+    let disk = crate::block::register(&name, Box::new(block)).map_err(|err| {
+        warn!("Failed to register {}: {:?}", name, err);
+        driver::Error::Device
+    })?;
+
+    Ok(Box::new(disk))
+}
+
+static DRIVER: Driver = Driver {
+    name: "virtio-blk",
+    matches: &[
+        // Transitional device, which also exposes the modern transport.
+        Match::Id { vendor: virtio::VENDOR_ID, device: 0x1001 },
+        Match::Id { vendor: virtio::VENDOR_ID, device: 0x1042 },
+    ],
+    probe,
+};
+
+/// Registers the virtio-blk driver, to be offered the virtio block devices.
+pub fn register() {
+    driver::register(&DRIVER);
+}
@@ -1,6 +1,10 @@
-use crate::{interrupts::exceptions::Exception, interrupts::InterruptCell, task::Scheduler};
+use crate::{interrupts::InterruptCell, task::Scheduler};
 use alloc::boxed::Box;
-use core::{cell::UnsafeCell, num::NonZeroU64, ptr::NonNull, sync::atomic::AtomicBool};
+use core::{
+    num::NonZeroU64,
+    ptr::NonNull,
+    sync::atomic::{compiler_fence, AtomicBool, Ordering},
+};
 
 pub(self) const US_PER_SEC: u32 = 1000000;
 pub(self) const US_WAIT: u32 = 10000;
@@ -14,10 +18,14 @@ crate::error_impl! {
 }
 
 pub const STACK_SIZE: usize = 0x10000;
+/// Size of the stack the panic path runs on, so it works even if the panicking stack is exhausted (or smashed).
+pub const EMERGENCY_STACK_SIZE: usize = 0x8000;
 
 #[repr(C)]
 struct State {
     core_id: u32,
+    /// Index of the core in the IPI registry (see [`crate::interrupts::ipi`]).
+    core_index: usize,
     scheduler: InterruptCell<Scheduler>,
 
     #[cfg(target_arch = "x86_64")]
@@ -28,18 +36,42 @@ struct State {
     #[cfg(target_arch = "x86_64")]
     apic: apic::Apic,
 
+    /// Timer counts per scheduler tick.
     timer_interval: Option<NonZeroU64>,
+    /// Scheduler ticks per second.
+    timer_frequency: u16,
+    /// Context of the allocations made on the core (see [`crate::mem::alloc::context`]).
+    alloc_context: crate::mem::alloc::context::Context,
+    /// Depth of the interrupt handlers running on the core (see [`enter_interrupt`]).
+    interrupt_depth: u32,
+    /// APIC timer counts elapsed in completed preemption waits (in one-shot mode).
+    #[cfg(target_arch = "x86_64")]
+    timer_counts: u64,
+    /// Initial count of the current preemption wait (in one-shot mode).
+    #[cfg(target_arch = "x86_64")]
+    timer_initial_count: u32,
+    #[cfg(target_arch = "x86_64")]
+    drift_checkpoint: Option<DriftCheckpoint>,
 
-    catch_exception: AtomicBool,
-    exception: UnsafeCell<Option<Exception>>,
+    emergency_stack_top: NonNull<u8>,
+    emergency_stack_taken: AtomicBool,
 }
 
 pub const SYSCALL_STACK_SIZE: usize = 0x40000;
 
-pub enum ExceptionCatcher {
-    Caught(Exception),
-    Await,
-    Idle,
+/// Greatest correction of the timer's calibration accepted from a drift measurement, in parts-per-million.
+///
+/// Spread-spectrum clocking modulates by well under a percent, so a larger discrepancy means the measurement window
+/// was disturbed (i.e. the timer was masked, or the system clock wrapped more than once).
+const MAX_DRIFT_PPM: u64 = 20_000;
+
+/// Start of a timer drift measurement.
+#[derive(Debug, Clone, Copy)]
+struct DriftCheckpoint {
+    /// Timestamp of the system clock.
+    reference: u64,
+    /// Timer counts elapsed on the core (see [`timer_counts`]).
+    counts: u64,
 }
 
 /// Initializes the core-local state structure.
@@ -68,29 +100,41 @@ pub unsafe fn init(timer_frequency: u16) {
         use core::num::NonZeroUsize;
         use ia32utils::VirtAddr;
 
-        fn allocate_tss_stack() -> VirtAddr {
+        fn allocate_tss_stack(name: &'static str) -> VirtAddr {
             use crate::mem::Stack;
 
             const TSS_STACK_SIZE: NonZeroUsize = NonZeroUsize::new(0x16000).unwrap();
 
-            VirtAddr::from_ptr(Box::leak(Box::new(Stack::<{ TSS_STACK_SIZE.get() }>::new())).as_ptr_range().end)
+            let stack = Box::leak(Box::new(Stack::<{ TSS_STACK_SIZE.get() }>::new()));
+            VirtAddr::from_ptr(crate::interrupts::stacks::track(name, &mut stack[..]).as_ptr())
         }
 
         let mut tss = Box::new(tss::TaskStateSegment::new());
         // TODO guard pages for these stacks
-        tss.privilege_stack_table[0] = allocate_tss_stack();
-        tss.interrupt_stack_table[StackTableIndex::Debug as usize] = allocate_tss_stack();
-        tss.interrupt_stack_table[StackTableIndex::NonMaskable as usize] = allocate_tss_stack();
-        tss.interrupt_stack_table[StackTableIndex::DoubleFault as usize] = allocate_tss_stack();
-        tss.interrupt_stack_table[StackTableIndex::MachineCheck as usize] = allocate_tss_stack();
+        tss.privilege_stack_table[0] = allocate_tss_stack("privilege");
+        tss.interrupt_stack_table[StackTableIndex::Debug as usize] = allocate_tss_stack("debug");
+        tss.interrupt_stack_table[StackTableIndex::NonMaskable as usize] = allocate_tss_stack("non-maskable");
+        tss.interrupt_stack_table[StackTableIndex::DoubleFault as usize] = allocate_tss_stack("double-fault");
+        tss.interrupt_stack_table[StackTableIndex::MachineCheck as usize] = allocate_tss_stack("machine-check");
 
         tss::load_local(tss::ptr_as_descriptor(NonNull::new(&mut *tss).unwrap()));
 
         tss
     };
 
+    #[cfg(target_arch = "riscv64")]
+    {
+        const TRAP_STACK_SIZE: usize = 0x16000;
+
+        let trap_stack = Box::leak(Box::new(crate::mem::Stack::<TRAP_STACK_SIZE>::new()));
+        // Safety: The stack is leaked, so it's reserved for this core's traps for as long as they're taken.
+        crate::arch::rv64::trap::init(crate::interrupts::stacks::track("trap", &mut trap_stack[..]));
+    }
+
+    let core_id = crate::cpu::read_id();
     let mut state = Box::new(State {
-        core_id: crate::cpu::read_id(),
+        core_id,
+        core_index: crate::interrupts::ipi::register(core_id).unwrap(),
         scheduler: InterruptCell::new(Scheduler::new(false)),
 
         #[cfg(target_arch = "x86_64")]
@@ -102,14 +146,24 @@ pub unsafe fn init(timer_frequency: u16) {
         apic: apic::Apic::new(Some(|address: usize| crate::mem::HHDM.ptr().add(address))).unwrap(),
 
         timer_interval: None,
+        timer_frequency,
+        alloc_context: crate::mem::alloc::context::Context::default(),
+        interrupt_depth: 0,
+        #[cfg(target_arch = "x86_64")]
+        timer_counts: 0,
+        #[cfg(target_arch = "x86_64")]
+        timer_initial_count: 0,
+        #[cfg(target_arch = "x86_64")]
+        drift_checkpoint: None,
 
-        catch_exception: AtomicBool::new(false),
-        exception: UnsafeCell::new(None),
+        // Allocated up-front, as the panic path may be entered from the allocator itself.
+        emergency_stack_top: Box::leak(Box::new(crate::mem::Stack::<EMERGENCY_STACK_SIZE>::new())).top(),
+        emergency_stack_taken: AtomicBool::new(false),
     });
 
     /* init APIC */
     {
-        use crate::{arch::x86_64, interrupts::Vector};
+        use crate::{arch::x86_64, interrupts::Vector, time::ClockSource};
 
         let apic = &mut state.apic;
 
@@ -169,15 +223,37 @@ pub unsafe fn init(timer_frequency: u16) {
         state.timer_interval = NonZeroU64::new(timer_interval);
     }
 
-    let state_address = Box::into_raw(state).addr();
+    // The pointer is cast to an address (which exposes its provenance), as it's later taken back from the register.
+    let state_address = Box::into_raw(state) as usize;
+    // The register is written with `nomem` assembly, which the state's initialization mustn't be moved past.
+    compiler_fence(Ordering::Release);
 
     #[cfg(target_arch = "x86_64")]
     crate::arch::x86_64::registers::msr::IA32_KERNEL_GS_BASE::write(state_address as u64);
+
+    #[cfg(target_arch = "riscv64")]
+    {
+        crate::arch::rv64::trap::set_state_ptr(state_address);
+        crate::arch::rv64::registers::tp::write(state_address);
+    }
 }
 
+/// Pointer to the local core's state, which is published in a core-local register (`IA32_KERNEL_GS_BASE` on x86_64,
+/// and `tp` on RISC-V).
+///
+/// #### Remark
+///
+/// Only the local core reads or writes its state pointer, so it needs no ordering with other cores; the compiler
+/// fences only keep the state's initialization ahead of the pointer being published, and its reads behind it.
 fn get_state_ptr() -> Result<NonNull<State>> {
-    let kernel_gs_usize = usize::try_from(crate::arch::x86_64::registers::msr::IA32_KERNEL_GS_BASE::read()).unwrap();
-    NonNull::new(kernel_gs_usize as *mut State).ok_or(Error::NotInitialized)
+    #[cfg(target_arch = "x86_64")]
+    let state_address = usize::try_from(crate::arch::x86_64::registers::msr::IA32_KERNEL_GS_BASE::read()).unwrap();
+    #[cfg(target_arch = "riscv64")]
+    let state_address = crate::arch::rv64::registers::tp::read();
+
+    compiler_fence(Ordering::Acquire);
+
+    NonNull::new(state_address as *mut State).ok_or(Error::NotInitialized)
 }
 
 fn get_state() -> Result<&'static State> {
@@ -190,11 +266,75 @@ fn get_state_mut() -> Result<&'static mut State> {
     unsafe { get_state_ptr().map(|mut ptr| ptr.as_mut()) }
 }
 
+/// Takes the local core's emergency stack, returning its top.
+///
+/// Returns `None` if the stack has already been taken, i.e. the core panicked within the panic path. Until the
+/// core-local state is initialized, cores share a single boot emergency stack.
+pub fn take_emergency_stack() -> Option<NonNull<u8>> {
+    static mut BOOT_EMERGENCY_STACK: crate::mem::Stack<EMERGENCY_STACK_SIZE> = crate::mem::Stack::new();
+    static BOOT_EMERGENCY_STACK_TAKEN: AtomicBool = AtomicBool::new(false);
+
+    match get_state() {
+        Ok(state) => (!state.emergency_stack_taken.swap(true, Ordering::AcqRel)).then_some(state.emergency_stack_top),
+
+        Err(_) if !BOOT_EMERGENCY_STACK_TAKEN.swap(true, Ordering::AcqRel) => {
+            // Safety: The stack is only ever used once, by whichever core takes it first, and no reference to it is
+            //         formed.
+            let stack_ptr = unsafe { core::ptr::addr_of_mut!(BOOT_EMERGENCY_STACK) }.cast::<u8>();
+
+            // Safety: The offset is the end of the stack's allocation.
+            NonNull::new(unsafe { stack_ptr.add(EMERGENCY_STACK_SIZE) })
+        }
+
+        Err(_) => None,
+    }
+}
+
 /// Returns the generated ID for the local core.
 pub fn get_core_id() -> Result<u32> {
     get_state().map(|state| state.core_id)
 }
 
+/// Returns the index of the local core in the IPI registry.
+pub fn get_core_index() -> Result<usize> {
+    get_state().map(|state| state.core_index)
+}
+
+/// Returns the local core's allocation context.
+pub fn get_alloc_context() -> Result<crate::mem::alloc::context::Context> {
+    get_state().map(|state| state.alloc_context)
+}
+
+/// Replaces the local core's allocation context with `context`, returning the previous.
+pub fn replace_alloc_context(
+    context: crate::mem::alloc::context::Context,
+) -> Result<crate::mem::alloc::context::Context> {
+    get_state_mut().map(|state| core::mem::replace(&mut state.alloc_context, context))
+}
+
+/// Marks the local core as running an interrupt handler, until the matching [`exit_interrupt`].
+///
+/// #### Remark
+///
+/// Only device interrupts and IPIs are marked. The timer and system call vectors run the scheduler and system calls,
+/// which act on behalf of the interrupted task (and so may allocate).
+pub fn enter_interrupt() {
+    if let Ok(state) = get_state_mut() {
+        state.interrupt_depth += 1;
+    }
+}
+
+pub fn exit_interrupt() {
+    if let Ok(state) = get_state_mut() {
+        state.interrupt_depth = state.interrupt_depth.checked_sub(1).expect("interrupt exit without an entry");
+    }
+}
+
+/// Indicates whether the local core is running an interrupt handler.
+pub fn in_interrupt() -> bool {
+    get_state().is_ok_and(|state| state.interrupt_depth > 0)
+}
+
 pub unsafe fn begin_scheduling() -> Result<()> {
     // Enable scheduler ...
     with_scheduler(|scheduler| {
@@ -233,11 +373,92 @@ pub unsafe fn end_of_interrupt() -> Result<()> {
     Ok(())
 }
 
+/// Sends the interrupt `vector` to the core with the APIC ID `apic_id`.
+///
+/// ### Safety
+///
+/// The target core must expect the interrupt.
+pub unsafe fn send_ipi(apic_id: u32, vector: crate::interrupts::Vector) -> Result<()> {
+    #[cfg(target_arch = "x86_64")]
+    {
+        let state = get_state()?;
+        let command = apic::InterruptCommand::new(
+            u8::try_from(vector as u64).unwrap(),
+            apic_id,
+            apic::DeliveryMode::Fixed,
+            false,
+            true,
+        );
+
+        // Safety: Caller is required to ensure the target core expects the interrupt. Interrupts are disabled so the
+        //         command isn't interleaved with another sent from this core.
+        crate::interrupts::without(|| unsafe { state.apic.send_int_cmd(command) });
+    }
+
+    Ok(())
+}
+
+/// Timer counts elapsed on the core: TSC ticks in TSC-deadline mode, or APIC timer counts summed across preemption
+/// waits in one-shot mode.
+#[cfg(target_arch = "x86_64")]
+fn timer_counts(state: &State) -> u64 {
+    match state.apic.get_timer().get_mode() {
+        // Safety: Reading the TSC has no side effects.
+        apic::TimerMode::TscDeadline => unsafe { core::arch::x86_64::_rdtsc() },
+        _ => {
+            state.timer_counts
+                + u64::from(state.timer_initial_count.saturating_sub(state.apic.get_timer_current_count()))
+        }
+    }
+}
+
+/// Measures the timer against the system clock over windows of about a second, correcting its calibration for any
+/// drift (i.e. from spread-spectrum clocking) so that scheduler ticks stay true to time.
+#[cfg(target_arch = "x86_64")]
+fn correct_timer_drift(state: &mut State) {
+    use crate::time::{ClockSource, SYSTEM_CLOCK};
+
+    let checkpoint = DriftCheckpoint { reference: SYSTEM_CLOCK.get_timestamp(), counts: timer_counts(state) };
+    let Some(last) = state.drift_checkpoint else {
+        state.drift_checkpoint = Some(checkpoint);
+        return;
+    };
+
+    // Windows are kept within half of the clock's wrap, so that a wrap is accounted for.
+    let window = SYSTEM_CLOCK.frequency().min(SYSTEM_CLOCK.max_timestamp() / 2);
+    let reference_elapsed = checkpoint.reference.wrapping_sub(last.reference) & SYSTEM_CLOCK.max_timestamp();
+    if reference_elapsed < window {
+        return;
+    }
+
+    state.drift_checkpoint = Some(checkpoint);
+
+    let counts_elapsed = u128::from(checkpoint.counts.wrapping_sub(last.counts));
+    let Ok(measured) = u64::try_from(
+        (counts_elapsed * u128::from(SYSTEM_CLOCK.frequency()))
+            / (u128::from(reference_elapsed) * u128::from(state.timer_frequency)),
+    ) else {
+        return;
+    };
+
+    let current = state.timer_interval.map_or(1, NonZeroU64::get);
+    let drift_ppm = measured.abs_diff(current).saturating_mul(1_000_000) / current;
+    if drift_ppm > MAX_DRIFT_PPM {
+        trace!("Discarding timer drift measurement of {} ppm.", drift_ppm);
+    } else if let Some(interval) = NonZeroU64::new(measured) {
+        state.timer_interval = Some(interval);
+    }
+}
+
 /// ### Safety
 ///
 /// Caller must ensure that setting a new preemption wait will not cause undefined behaviour.
 pub unsafe fn set_preemption_wait(interval_wait: core::num::NonZeroU16) -> Result<()> {
     let state = get_state_mut()?;
+
+    #[cfg(target_arch = "x86_64")]
+    correct_timer_drift(state);
+
     let timer_interval = state.timer_interval.unwrap();
 
     #[cfg(target_arch = "x86_64")]
@@ -247,8 +468,15 @@ pub unsafe fn set_preemption_wait(interval_wait: core::num::NonZeroU16) -> Resul
         match apic.get_timer().get_mode() {
             // Safety: Control flow expects timer initial count to be set.
             apic::TimerMode::OneShot => unsafe {
-                let final_count = timer_interval.get() * u64::from(interval_wait.get());
-                apic.set_timer_initial_count(final_count.try_into().unwrap_or(u32::MAX));
+                let final_count =
+                    u32::try_from(timer_interval.get() * u64::from(interval_wait.get())).unwrap_or(u32::MAX);
+
+                // Count whatever elapsed of the previous wait, which may have been cut short.
+                state.timer_counts +=
+                    u64::from(state.timer_initial_count.saturating_sub(apic.get_timer_current_count()));
+                state.timer_initial_count = final_count;
+
+                apic.set_timer_initial_count(final_count);
             },
 
             // Safety: Control flow expects the TSC deadline to be set.
@@ -264,40 +492,3 @@ pub unsafe fn set_preemption_wait(interval_wait: core::num::NonZeroU16) -> Resul
 
     Ok(())
 }
-
-// pub fn provide_exception<T: Into<Exception>>(exception: T) -> core::result::Result<(), T> {
-//     let state = get_state_mut();
-//     if state.catch_exception.load(Ordering::Relaxed) {
-//         let exception_cell = state.exception.get_mut();
-
-//         debug_assert!(exception_cell.is_none());
-//         *exception_cell = Some(exception.into());
-//         Ok(())
-//     } else {
-//         Err(exception)
-//     }
-// }
-
-// /// ### Safety
-// ///
-// /// Caller must ensure `do_func` is effectively stackless, since no stack cleanup will occur on an exception.
-// pub unsafe fn do_catch<T>(do_func: impl FnOnce() -> T) -> core::result::Result<T, Exception> {
-//     let state = get_state_mut();
-
-//     debug_assert!(state.exception.get_mut().is_none());
-
-//     state
-//         .catch_exception
-//         .compare_exchange(false, true, Ordering::Relaxed, Ordering::Relaxed)
-//         .expect("nested exception catching is not supported");
-
-//     let do_func_result = do_func();
-//     let result = state.exception.get_mut().take().map_or(Ok(do_func_result), Err);
-
-//     state
-//         .catch_exception
-//         .compare_exchange(true, false, Ordering::Relaxed, Ordering::Relaxed)
-//         .expect("inconsistent local catch state");
-
-//     result
-// }
@@ -0,0 +1,174 @@
+//! Kernel drivers, and their binding to the PCI devices they drive.
+//!
+//! A driver [`register`]s the devices it [`Match`]es, and a probe function. Once PCI devices are enumerated, [`probe`]
+//! offers each unowned device to the drivers which match it, in the order they registered, and the first whose probe
+//! succeeds is bound to it. A driver registered later is offered the devices still unowned as it registers.
+//!
+//! Binding hands the driver the device's resources: each of its BARs is claimed exclusively for the driver (within the
+//! window the PCI bus claimed for it), and the driver's probe is free to map them and enable the device's interrupts.
+
+use crate::mem::io::{
+    pci::{self, Bar, Device, Standard},
+    resource::{self, Kind, ResourceId, Space},
+};
+use alloc::{boxed::Box, string::String, vec::Vec};
+use core::{any::Any, fmt::Write};
+use spin::Mutex;
+
+crate::error_impl! {
+    #[derive(Debug)]
+    pub enum Error {
+        /// The driver matched the device, but can't drive it (i.e. an unsupported revision).
+        Unsupported => None,
+        /// The device failed to initialize.
+        Device => None,
+        Pci { err: pci::device::Error } => Some(err),
+        Resource { err: resource::Error } => Some(err)
+    }
+}
+
+/// Devices a driver is offered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Match {
+    /// Devices of the class and subclass, with the programming interface `prog_if` (or any, if it's `None`).
+    Class { class: u8, subclass: u8, prog_if: Option<u8> },
+    /// Devices with the vendor and device IDs.
+    Id { vendor: u16, device: u16 },
+}
+
+impl Match {
+    pub fn matches(self, device: &Device<Standard>) -> bool {
+        match self {
+            Self::Class { class, subclass, prog_if } => {
+                let codes = device.get_class_codes();
+                codes.0 == class && codes.1 == subclass && prog_if.is_none_or(|prog_if| codes.2 == prog_if)
+            }
+
+            Self::Id { vendor, device: device_id } => {
+                device.get_vendor_id() == vendor && device.get_device_id() == device_id
+            }
+        }
+    }
+}
+
+/// State a driver keeps for each device it's bound to (i.e. the mappings of its BARs, and its [`pci::Msix`]), which is
+/// kept alive for as long as the binding.
+pub type Instance = Box<dyn Any + Send>;
+
+pub struct Driver {
+    pub name: &'static str,
+    pub matches: &'static [Match],
+    /// Readies the device to be driven, failing if the driver can't drive it after all.
+    pub probe: fn(&mut Device<Standard>) -> Result<Instance>,
+}
+
+struct Binding {
+    driver: &'static Driver,
+    device: Device<Standard>,
+    /// Exclusive claims of the device's BARs.
+    claims: Vec<ResourceId>,
+    _instance: Instance,
+}
+
+static DRIVERS: Mutex<Vec<&'static Driver>> = Mutex::new(Vec::new());
+static BINDINGS: Mutex<Vec<Binding>> = Mutex::new(Vec::new());
+/// Whether devices have been enumerated, so drivers are offered them as they register.
+static PROBED: spin::Once<()> = spin::Once::new();
+
+/// Claims each of the device's BARs exclusively for `owner`.
+fn claim_bars(device: &Device<Standard>, owner: &str) -> Result<Vec<ResourceId>> {
+    let mut claims = Vec::new();
+
+    for (_, bar) in device.bars().into_iter().filter(|(_, bar)| !bar.is_unused() && bar.is_implemented()) {
+        let space = match bar {
+            Bar::MemorySpace32 { .. } | Bar::MemorySpace64 { .. } => Space::Memory,
+            Bar::IOSpace { .. } => Space::Port,
+        };
+
+        match resource::claim(space, bar.get_address().get(), bar.get_size(), owner, Kind::Exclusive) {
+            Ok(id) => claims.push(id),
+
+            Err(err) => {
+                release(claims);
+                return Err(Error::Resource { err });
+            }
+        }
+    }
+
+    Ok(claims)
+}
+
+fn release(claims: Vec<ResourceId>) {
+    for id in claims {
+        resource::release(id).ok();
+    }
+}
+
+/// Binds `device` to the first of `drivers` which matches and successfully probes it, or hands it back if none do.
+fn bind(drivers: &[&'static Driver], mut device: Device<Standard>) -> Option<Device<Standard>> {
+    for &driver in drivers {
+        if !driver.matches.iter().any(|matcher| matcher.matches(&device)) {
+            continue;
+        }
+
+        let claims = match claim_bars(&device, driver.name) {
+            Ok(claims) => claims,
+            Err(err) => {
+                warn!("Driver `{}` can't claim the resources of {}: {:?}", driver.name, device.bdf(), err);
+                continue;
+            }
+        };
+
+        match (driver.probe)(&mut device) {
+            Ok(instance) => {
+                info!("Bound {} to driver `{}`.", device.bdf(), driver.name);
+                BINDINGS.lock().push(Binding { driver, device, claims, _instance: instance });
+
+                return None;
+            }
+
+            Err(err) => {
+                debug!("Driver `{}` declined {}: {:?}", driver.name, device.bdf(), err);
+                release(claims);
+            }
+        }
+    }
+
+    Some(device)
+}
+
+/// Registers `driver`, offering it any unowned devices it matches if they've already been enumerated.
+pub fn register(driver: &'static Driver) {
+    DRIVERS.lock().push(driver);
+
+    crate::sysfs::set(
+        "drivers",
+        driver.name,
+        crate::sysfs::Value::Dynamic(Box::new(move || {
+            BINDINGS.lock().iter().filter(|binding| core::ptr::eq(binding.driver, driver)).fold(
+                String::new(),
+                |mut string, binding| {
+                    writeln!(string, "{}", binding.device.bdf()).unwrap();
+                    string
+                },
+            )
+        })),
+    );
+
+    if PROBED.is_completed() {
+        pci::bind_devices(|device| bind(&[driver], device));
+    }
+}
+
+/// Offers each enumerated device to the registered drivers.
+pub fn probe() {
+    PROBED.call_once(|| {
+        let drivers = DRIVERS.lock().clone();
+        pci::bind_devices(|device| bind(&drivers, device));
+    });
+}
+
+/// Name of the driver bound to the device `bdf`, if any.
+pub fn bound_to(bdf: pci::Bdf) -> Option<&'static str> {
+    BINDINGS.lock().iter().find(|binding| binding.device.bdf() == bdf).map(|binding| binding.driver.name)
+}
@@ -2,5 +2,4 @@
 
 // pub mod ahci;
 // pub mod graphics;
-// pub mod nvme;
 // pub mod sata;
@@ -0,0 +1,337 @@
+//! UEFI runtime services, for firmware variables and the firmware-backed wall clock.
+//!
+//! Runtime service regions are mapped into a dedicated window of the kernel address
+//! space, and firmware is informed of the new mappings via `SetVirtualAddressMap`.
+//! Afterwards, all calls into firmware are serialized and made with interrupts disabled.
+
+use crate::mem::{
+    layout::Region,
+    paging::{TableDepth, TableEntryFlags},
+};
+use alloc::vec::Vec;
+use core::ffi::c_void;
+use libsys::{page_size, Address};
+use spin::Mutex;
+
+crate::error_impl! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Error {
+        Boot { err: crate::init::boot::Error } => Some(err),
+        Paging { err: crate::mem::paging::Error } => Some(err),
+        Layout { err: crate::mem::layout::Error } => Some(err),
+        InvalidSystemTable => None,
+        NotInitialized => None,
+        /// Firmware returned a non-success status code.
+        Status { status: usize } => None
+    }
+}
+
+const SYSTEM_TABLE_SIGNATURE: u64 = 0x5453_5953_2049_4249;
+const RUNTIME_SERVICES_SIGNATURE: u64 = 0x5652_4553_544E_5552;
+
+const MEMORY_RUNTIME: u64 = 1 << 63;
+const MEMORY_TYPE_RUNTIME_CODE: u32 = 5;
+const MEMORY_TYPE_RUNTIME_DATA: u32 = 6;
+const MEMORY_TYPE_MMIO: u32 = 11;
+const MEMORY_TYPE_MMIO_PORT_SPACE: u32 = 12;
+
+const STATUS_ERROR_BIT: usize = 1 << (usize::BITS - 1);
+pub const STATUS_BUFFER_TOO_SMALL: usize = STATUS_ERROR_BIT | 5;
+pub const STATUS_NOT_FOUND: usize = STATUS_ERROR_BIT | 14;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Guid {
+    pub data1: u32,
+    pub data2: u16,
+    pub data3: u16,
+    pub data4: [u8; 8],
+}
+
+impl Guid {
+    /// Vendor GUID for architecturally defined variables (e.g. `BootOrder`, `Boot####`).
+    pub const GLOBAL_VARIABLE: Self =
+        Self { data1: 0x8BE4_DF61, data2: 0x93CA, data3: 0x11D2, data4: [0xAA, 0x0D, 0x00, 0xE0, 0x98, 0x03, 0x2B, 0x8C] };
+}
+
+bitflags::bitflags! {
+    #[repr(transparent)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct VariableAttributes : u32 {
+        const NON_VOLATILE = 1 << 0;
+        const BOOTSERVICE_ACCESS = 1 << 1;
+        const RUNTIME_ACCESS = 1 << 2;
+        const HARDWARE_ERROR_RECORD = 1 << 3;
+        const TIME_BASED_AUTHENTICATED_WRITE_ACCESS = 1 << 5;
+        const APPEND_WRITE = 1 << 6;
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Time {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    _pad1: u8,
+    pub nanosecond: u32,
+    pub time_zone: i16,
+    pub daylight: u8,
+    _pad2: u8,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+struct TimeCapabilities {
+    resolution: u32,
+    accuracy: u32,
+    sets_to_zero: bool,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct TableHeader {
+    signature: u64,
+    revision: u32,
+    header_size: u32,
+    crc32: u32,
+    reserved: u32,
+}
+
+#[repr(C)]
+struct SystemTable {
+    header: TableHeader,
+    firmware_vendor: *const u16,
+    firmware_revision: u32,
+    console_in_handle: *const c_void,
+    con_in: *const c_void,
+    console_out_handle: *const c_void,
+    con_out: *const c_void,
+    standard_error_handle: *const c_void,
+    std_err: *const c_void,
+    runtime_services: *const RuntimeServices,
+    boot_services: *const c_void,
+    number_of_table_entries: usize,
+    configuration_table: *const c_void,
+}
+
+#[repr(C)]
+struct MemoryDescriptor {
+    ty: u32,
+    physical_start: u64,
+    virtual_start: u64,
+    number_of_pages: u64,
+    attribute: u64,
+}
+
+type Status = usize;
+
+#[repr(C)]
+struct RuntimeServices {
+    header: TableHeader,
+    get_time: extern "efiapi" fn(time: *mut Time, capabilities: *mut TimeCapabilities) -> Status,
+    set_time: extern "efiapi" fn(time: *const Time) -> Status,
+    get_wakeup_time: extern "efiapi" fn(enabled: *mut bool, pending: *mut bool, time: *mut Time) -> Status,
+    set_wakeup_time: extern "efiapi" fn(enable: bool, time: *const Time) -> Status,
+    set_virtual_address_map: extern "efiapi" fn(
+        map_size: usize,
+        desc_size: usize,
+        desc_version: u32,
+        virtual_map: *mut MemoryDescriptor,
+    ) -> Status,
+    convert_pointer: extern "efiapi" fn(debug_disposition: usize, address: *mut *const c_void) -> Status,
+    get_variable: extern "efiapi" fn(
+        name: *const u16,
+        vendor: *const Guid,
+        attributes: *mut u32,
+        data_size: *mut usize,
+        data: *mut u8,
+    ) -> Status,
+    get_next_variable_name: extern "efiapi" fn(name_size: *mut usize, name: *mut u16, vendor: *mut Guid) -> Status,
+    set_variable: extern "efiapi" fn(
+        name: *const u16,
+        vendor: *const Guid,
+        attributes: u32,
+        data_size: usize,
+        data: *const u8,
+    ) -> Status,
+}
+
+struct Runtime(&'static RuntimeServices);
+
+// Safety: Runtime services are globally mapped, and all calls are serialized behind a mutex.
+unsafe impl Send for Runtime {}
+
+static RUNTIME: spin::Once<Mutex<Runtime>> = spin::Once::new();
+
+const fn check_status(status: Status) -> Result<()> {
+    if status == 0 {
+        Ok(())
+    } else {
+        Err(Error::Status { status })
+    }
+}
+
+/// Maps the firmware runtime services, and switches them to their virtual mappings.
+///
+/// #### Remark
+///
+/// This function must be called after the kernel page tables are active, but before bootloader memory is reclaimed.
+pub fn init() -> Result<()> {
+    RUNTIME.try_call_once(|| {
+        let system_table_address =
+            crate::init::boot::get_efi_system_table_address().map_err(|err| Error::Boot { err })?.get();
+        let memory_map = crate::init::boot::get_efi_memory_map().map_err(|err| Error::Boot { err })?;
+
+        // Safety: Bootloader guarantees the system table address is valid.
+        let system_table = unsafe { &*crate::mem::HHDM.ptr().add(system_table_address).cast::<SystemTable>() };
+        if system_table.header.signature != SYSTEM_TABLE_SIGNATURE {
+            return Err(Error::InvalidSystemTable);
+        }
+
+        let runtime_services_address = system_table.runtime_services.addr();
+
+        let map_ptr = memory_map.map.as_mut_ptr();
+        let map_len = memory_map.map.len();
+        let desc_size = memory_map.desc_size;
+        let runtime_descriptors = || {
+            (0..(map_len / desc_size))
+                .map(move |index| map_ptr.wrapping_add(index * desc_size).cast::<MemoryDescriptor>())
+                .filter(|descriptor| {
+                    // Safety: Descriptors are `desc_size` apart, which is at least `size_of::<MemoryDescriptor>()`.
+                    (unsafe { descriptor.read_unaligned() }.attribute & MEMORY_RUNTIME) > 0
+                })
+        };
+
+        let runtime_range = |descriptor: &MemoryDescriptor| {
+            let start = usize::try_from(descriptor.physical_start).unwrap();
+            let len = usize::try_from(descriptor.number_of_pages).unwrap() * page_size();
+            start..(start + len)
+        };
+
+        // Runtime service regions are mapped into their window at `base + physical address`.
+        let window_base = Region::EfiRuntime.start();
+
+        crate::mem::with_kmapper(|kmapper| {
+            for descriptor_ptr in runtime_descriptors() {
+                // Safety: See above.
+                let mut descriptor = unsafe { descriptor_ptr.read_unaligned() };
+
+                let range = runtime_range(&descriptor);
+                Region::EfiRuntime
+                    .check((window_base + range.start)..(window_base + range.end))
+                    .map_err(|err| Error::Layout { err })?;
+
+                let flags = match descriptor.ty {
+                    MEMORY_TYPE_RUNTIME_CODE => TableEntryFlags::RX,
+                    MEMORY_TYPE_MMIO | MEMORY_TYPE_MMIO_PORT_SPACE => TableEntryFlags::MMIO,
+                    _ => TableEntryFlags::RW,
+                };
+
+                for address in runtime_range(&descriptor).step_by(page_size()) {
+                    let frame = Address::new(address).unwrap();
+                    let window_page = Address::new(window_base + address).unwrap();
+                    let identity_page = Address::new(address).unwrap();
+
+                    kmapper
+                        .map(window_page, TableDepth::min(), frame, false, flags)
+                        .map_err(|err| Error::Paging { err })?;
+                    // Firmware code runs in physical mode until `SetVirtualAddressMap` returns.
+                    kmapper
+                        .map(identity_page, TableDepth::min(), frame, false, flags)
+                        .map_err(|err| Error::Paging { err })?;
+                }
+
+                descriptor.virtual_start = u64::try_from(window_base).unwrap() + descriptor.physical_start;
+                // Safety: See above.
+                unsafe { descriptor_ptr.write_unaligned(descriptor) };
+            }
+
+            Ok::<_, Error>(())
+        })?;
+
+        // Safety: Runtime services table is identity-mapped, and the signature is verified before use.
+        let physical_runtime = unsafe { &*(runtime_services_address as *const RuntimeServices) };
+        if physical_runtime.header.signature != RUNTIME_SERVICES_SIGNATURE {
+            return Err(Error::InvalidSystemTable);
+        }
+
+        let status = crate::interrupts::without(|| {
+            (physical_runtime.set_virtual_address_map)(map_len, desc_size, memory_map.desc_version, map_ptr.cast())
+        });
+
+        // The identity mappings are no longer required, regardless of whether the call succeeded.
+        crate::mem::with_kmapper(|kmapper| {
+            for descriptor_ptr in runtime_descriptors() {
+                // Safety: See above.
+                let descriptor = unsafe { descriptor_ptr.read_unaligned() };
+
+                for address in runtime_range(&descriptor).step_by(page_size()) {
+                    // Safety: Identity mappings were created above, and nothing else refers to them.
+                    unsafe { kmapper.unmap(Address::new(address).unwrap(), None, false) }
+                        .map_err(|err| Error::Paging { err })?;
+                }
+            }
+
+            Ok::<_, Error>(())
+        })?;
+
+        check_status(status)?;
+
+        // Safety: Firmware has relocated the runtime services to the virtual window.
+        let runtime = unsafe { &*((window_base + runtime_services_address) as *const RuntimeServices) };
+
+        Ok(Mutex::new(Runtime(runtime)))
+    })?;
+
+    debug!("Initialized EFI runtime services.");
+
+    Ok(())
+}
+
+fn with_runtime<T>(func: impl FnOnce(&RuntimeServices) -> T) -> Result<T> {
+    let runtime = RUNTIME.get().ok_or(Error::NotInitialized)?;
+
+    Ok(crate::interrupts::without(|| func(runtime.lock().0)))
+}
+
+fn encode_name(name: &str) -> Vec<u16> {
+    name.encode_utf16().chain(core::iter::once(0)).collect()
+}
+
+/// Reads the current firmware wall clock time.
+pub fn get_time() -> Result<Time> {
+    let mut time = Time::default();
+    let mut capabilities = TimeCapabilities::default();
+
+    with_runtime(|runtime| (runtime.get_time)(&mut time, &mut capabilities)).and_then(check_status)?;
+
+    Ok(time)
+}
+
+/// Reads the variable `name` into `buffer`, returning its size and attributes.
+///
+/// If `buffer` is too small, [`STATUS_BUFFER_TOO_SMALL`] is returned as the error status.
+pub fn get_variable(name: &str, vendor: &Guid, buffer: &mut [u8]) -> Result<(usize, VariableAttributes)> {
+    let name = encode_name(name);
+    let mut attributes = 0u32;
+    let mut data_size = buffer.len();
+
+    with_runtime(|runtime| {
+        (runtime.get_variable)(name.as_ptr(), vendor, &mut attributes, &mut data_size, buffer.as_mut_ptr())
+    })
+    .and_then(check_status)?;
+
+    Ok((data_size, VariableAttributes::from_bits_truncate(attributes)))
+}
+
+/// Writes (or, with empty `data`, deletes) the variable `name`.
+pub fn set_variable(name: &str, vendor: &Guid, attributes: VariableAttributes, data: &[u8]) -> Result<()> {
+    let name = encode_name(name);
+
+    with_runtime(|runtime| (runtime.set_variable)(name.as_ptr(), vendor, attributes.bits(), data.len(), data.as_ptr()))
+        .and_then(check_status)
+}
@@ -0,0 +1,395 @@
+//! Boot framebuffer, drawn through a shadow buffer in normal memory.
+//!
+//! All drawing targets the shadow buffer, and the regions it touches are accumulated as damage. [`Framebuffer::flush`]
+//! then copies only the damaged scanline spans to device memory (which is mapped write-combining), so drawing never
+//! reads from the device, and writes to it in long sequential bursts.
+
+use crate::{
+    init::boot::FramebufferInfo,
+    interrupts::InterruptCell,
+    mem::{alloc::pmm, HHDM},
+};
+use core::{num::NonZeroUsize, ptr::NonNull};
+use libsys::{syscall::fb, Address, Frame};
+use spin::Mutex;
+
+crate::error_impl! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Error {
+        Boot { err: crate::init::boot::Error } => Some(err),
+        /// Only 32 bits-per-pixel framebuffers are supported.
+        UnsupportedFormat { bpp: u16 } => None,
+        Allocation { err: pmm::Error } => None,
+        Device { err: crate::vfs::Error } => Some(err),
+        Resource { err: crate::mem::io::resource::Error } => Some(err)
+    }
+}
+
+const BYTES_PER_PIXEL: usize = 4;
+
+/// Number of disjoint damage rectangles tracked before they are collapsed into their bounding rectangle.
+const MAX_DAMAGE: usize = 8;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Rect {
+    #[inline]
+    pub const fn new(x: usize, y: usize, width: usize, height: usize) -> Self {
+        Self { x, y, width, height }
+    }
+
+    #[inline]
+    pub const fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    #[inline]
+    const fn right(&self) -> usize {
+        self.x.saturating_add(self.width)
+    }
+
+    #[inline]
+    const fn bottom(&self) -> usize {
+        self.y.saturating_add(self.height)
+    }
+
+    /// Returns the smallest rectangle containing both `self` and `other`.
+    pub fn union(self, other: Self) -> Self {
+        if self.is_empty() {
+            other
+        } else if other.is_empty() {
+            self
+        } else {
+            let x = self.x.min(other.x);
+            let y = self.y.min(other.y);
+
+            Self { x, y, width: self.right().max(other.right()) - x, height: self.bottom().max(other.bottom()) - y }
+        }
+    }
+
+    /// Returns the region covered by both `self` and `other`, which may be empty.
+    pub fn intersection(self, other: Self) -> Self {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+
+        Self {
+            x,
+            y,
+            width: self.right().min(other.right()).saturating_sub(x),
+            height: self.bottom().min(other.bottom()).saturating_sub(y),
+        }
+    }
+
+    #[inline]
+    pub fn intersects(self, other: Self) -> bool {
+        !self.intersection(other).is_empty()
+    }
+}
+
+pub struct Framebuffer {
+    info: FramebufferInfo,
+    device: NonNull<u8>,
+    shadow: NonNull<u8>,
+    shadow_frame: Address<Frame>,
+    shadow_pages: usize,
+    damage: [Rect; MAX_DAMAGE],
+    damage_len: usize,
+}
+
+// Safety: Both buffers are owned exclusively by the framebuffer, and accessed only through `&mut self`.
+unsafe impl Send for Framebuffer {}
+
+impl Framebuffer {
+    fn new(info: FramebufferInfo) -> Result<Self> {
+        if info.bpp != u16::try_from(BYTES_PER_PIXEL * 8).unwrap() || (info.pitch % BYTES_PER_PIXEL) > 0 {
+            return Err(Error::UnsupportedFormat { bpp: info.bpp });
+        }
+
+        let len = info.pitch * info.height;
+        let shadow_pages = libsys::align_up_div(len, libsys::page_shift());
+        let shadow_frame = pmm::get()
+            .next_frames(NonZeroUsize::new(shadow_pages).unwrap(), None, pmm::Owner::Kernel)
+            .map_err(|err| Error::Allocation { err })?;
+
+        let device = NonNull::new(HHDM.offset(info.address).unwrap().as_ptr()).unwrap();
+        let shadow = NonNull::new(HHDM.offset(shadow_frame).unwrap().as_ptr()).unwrap();
+
+        // Safety: Both regions are at least `len` bytes, and are distinct (one is device memory, one was just allocated).
+        //         Reading device memory once here preserves whatever the bootloader had already drawn.
+        unsafe { core::ptr::copy_nonoverlapping(device.as_ptr(), shadow.as_ptr(), len) };
+
+        Ok(Self {
+            info,
+            device,
+            shadow,
+            shadow_frame,
+            shadow_pages,
+            damage: [Rect::default(); MAX_DAMAGE],
+            damage_len: 0,
+        })
+    }
+
+    #[inline]
+    pub const fn width(&self) -> usize {
+        self.info.width
+    }
+
+    #[inline]
+    pub const fn height(&self) -> usize {
+        self.info.height
+    }
+
+    /// Length of a scanline in the shadow buffer, in pixels.
+    #[inline]
+    pub const fn stride(&self) -> usize {
+        self.info.pitch / BYTES_PER_PIXEL
+    }
+
+    #[inline]
+    pub const fn bounds(&self) -> Rect {
+        Rect::new(0, 0, self.info.width, self.info.height)
+    }
+
+    /// Encodes the given color channels into the framebuffer's native pixel format.
+    #[inline]
+    pub fn encode(&self, red: u8, green: u8, blue: u8) -> u32 {
+        (u32::from(red) << self.info.red_shift)
+            | (u32::from(green) << self.info.green_shift)
+            | (u32::from(blue) << self.info.blue_shift)
+    }
+
+    /// Returns the entire shadow buffer, as raw bytes.
+    fn shadow_bytes_mut(&mut self) -> &mut [u8] {
+        // Safety: The shadow buffer is `pitch * height` bytes, and exclusively borrowed via `self`.
+        unsafe { core::slice::from_raw_parts_mut(self.shadow.as_ptr(), self.info.pitch * self.info.height) }
+    }
+
+    /// Returns the pixels of scanline `y` in the shadow buffer.
+    ///
+    /// #### Remark
+    ///
+    /// Writes made through this slice are not tracked; the caller must [`Self::damage`] what it draws.
+    pub fn row_mut(&mut self, y: usize) -> &mut [u32] {
+        assert!(y < self.info.height);
+
+        // Safety: The shadow buffer is `pitch * height` bytes, pixel-aligned, and exclusively borrowed via `self`.
+        unsafe {
+            core::slice::from_raw_parts_mut(
+                self.shadow.as_ptr().add(y * self.info.pitch).cast::<u32>(),
+                self.info.width,
+            )
+        }
+    }
+
+    /// Marks `rect` as needing to be copied to the device on the next flush.
+    pub fn damage(&mut self, rect: Rect) {
+        let mut rect = rect.intersection(self.bounds());
+        if rect.is_empty() {
+            return;
+        }
+
+        // Absorb any overlapping damage, so no region is copied more than once.
+        let mut index = 0;
+        while index < self.damage_len {
+            if self.damage[index].intersects(rect) {
+                rect = rect.union(self.damage[index]);
+                self.damage_len -= 1;
+                self.damage.swap(index, self.damage_len);
+            } else {
+                index += 1;
+            }
+        }
+
+        if self.damage_len == MAX_DAMAGE {
+            rect = self.damage.iter().fold(rect, |bounding, damage| bounding.union(*damage));
+            self.damage_len = 0;
+        }
+
+        self.damage[self.damage_len] = rect;
+        self.damage_len += 1;
+    }
+
+    /// Fills `rect` in the shadow buffer with `pixel`.
+    pub fn fill(&mut self, rect: Rect, pixel: u32) {
+        let rect = rect.intersection(self.bounds());
+
+        for y in rect.y..rect.bottom() {
+            self.row_mut(y)[rect.x..rect.right()].fill(pixel);
+        }
+
+        self.damage(rect);
+    }
+
+    /// Copies all damaged regions of the shadow buffer to the device.
+    ///
+    /// #### Remark
+    ///
+    /// This does not wait for vertical blanking, so a flush may still be scanned out across two refreshes.
+    pub fn flush(&mut self) {
+        for rect in &self.damage[..self.damage_len] {
+            let span = rect.width * BYTES_PER_PIXEL;
+
+            for y in rect.y..rect.bottom() {
+                let offset = (y * self.info.pitch) + (rect.x * BYTES_PER_PIXEL);
+
+                // Safety: Damage is clipped to the framebuffer bounds, so both spans lie within their buffers.
+                unsafe {
+                    core::ptr::copy_nonoverlapping(
+                        self.shadow.as_ptr().add(offset),
+                        self.device.as_ptr().add(offset),
+                        span,
+                    );
+                }
+            }
+        }
+
+        self.damage_len = 0;
+
+        // Drain the write-combining buffers, so the flushed contents are visible to the device.
+        #[cfg(target_arch = "x86_64")]
+        crate::arch::x86_64::instructions::sync::sfence();
+    }
+
+    /// Iterates the (physically contiguous) frames backing the shadow buffer, i.e. for mapping it into a compositor.
+    pub fn shadow_frames(&self) -> impl Iterator<Item = Address<Frame>> {
+        let base = self.shadow_frame.index();
+
+        (base..(base + self.shadow_pages)).map(|index| Address::from_index(index).unwrap())
+    }
+}
+
+static FRAMEBUFFER: spin::Once<InterruptCell<Mutex<Framebuffer>>> = spin::Once::new();
+
+/// Takes over the bootloader's framebuffer, allocating its shadow buffer.
+///
+/// #### Remark
+///
+/// This function must be called before bootloader memory is reclaimed.
+pub fn init() -> Result<()> {
+    FRAMEBUFFER.try_call_once(|| {
+        use crate::mem::io::resource::{self, Kind, Space};
+
+        let info = crate::init::boot::get_framebuffer().map_err(|err| Error::Boot { err })?;
+        let framebuffer = Framebuffer::new(info)?;
+        resource::claim(Space::Memory, info.address.get().get(), info.pitch * info.height, "fb0", Kind::Exclusive)
+            .map_err(|err| Error::Resource { err })?;
+
+        crate::sysfs::set("graphics/fb0", "width", alloc::format!("{}", info.width));
+        crate::sysfs::set("graphics/fb0", "height", alloc::format!("{}", info.height));
+        crate::sysfs::set("graphics/fb0", "pitch", alloc::format!("{}", info.pitch));
+        crate::sysfs::set("graphics/fb0", "bpp", alloc::format!("{}", info.bpp));
+
+        crate::vfs::devfs::register("fb0", alloc::sync::Arc::new(Device)).map_err(|err| Error::Device { err })?;
+
+        info!("Framebuffer         {}x{} @ {:X?}", info.width, info.height, info.address);
+
+        Ok(InterruptCell::new(Mutex::new(framebuffer)))
+    })?;
+
+    Ok(())
+}
+
+/// Runs `func` with exclusive access to the framebuffer, or returns `None` if there is none.
+pub fn with<T>(func: impl FnOnce(&mut Framebuffer) -> T) -> Option<T> {
+    FRAMEBUFFER.get().map(|framebuffer| framebuffer.with(|framebuffer| func(&mut framebuffer.lock())))
+}
+
+/// The framebuffer's device node, `/dev/fb0`.
+///
+/// Reads and writes address the shadow buffer as bytes (damaging the scanlines they touch), and the shadow buffer
+/// can be mapped directly; either way, drawing is only made visible by a [`fb::FLUSH`] request.
+struct Device;
+
+impl crate::vfs::Node for Device {
+    fn metadata(&self) -> crate::vfs::Metadata {
+        crate::vfs::Metadata {
+            kind: crate::vfs::NodeKind::Device,
+            size: with(|framebuffer| framebuffer.shadow_bytes_mut().len()).unwrap_or(0),
+            mode: libsys::syscall::fs::Mode::from_bits_retain(0o660),
+            owner: crate::vfs::Owner::ROOT,
+            accessed: 0,
+            modified: 0,
+            created: 0,
+        }
+    }
+
+    fn read(&self, offset: usize, buf: &mut [u8]) -> crate::vfs::Result<usize> {
+        with(|framebuffer| {
+            let Some(shadow) = framebuffer.shadow_bytes_mut().get(offset..) else { return 0 };
+
+            let len = shadow.len().min(buf.len());
+            buf[..len].copy_from_slice(&shadow[..len]);
+            len
+        })
+        .ok_or(crate::vfs::Error::NotFound)
+    }
+
+    fn write(&self, offset: usize, buf: &[u8]) -> crate::vfs::Result<usize> {
+        with(|framebuffer| {
+            let pitch = framebuffer.info.pitch;
+            let Some(shadow) = framebuffer.shadow_bytes_mut().get_mut(offset..) else { return 0 };
+
+            let len = shadow.len().min(buf.len());
+            shadow[..len].copy_from_slice(&buf[..len]);
+
+            let rows = (offset / pitch)..(offset + len).div_ceil(pitch);
+            framebuffer.damage(Rect::new(0, rows.start, framebuffer.width(), rows.len()));
+            len
+        })
+        .ok_or(crate::vfs::Error::NotFound)
+    }
+
+    fn control(&self, request: u32, arg: &mut [u8]) -> crate::vfs::Result<usize> {
+        use core::mem::size_of;
+
+        with(|framebuffer| match request {
+            fb::GET_MODE_INFO => {
+                let info = fb::ModeInfo {
+                    width: u32::try_from(framebuffer.info.width).unwrap(),
+                    height: u32::try_from(framebuffer.info.height).unwrap(),
+                    pitch: u32::try_from(framebuffer.info.pitch).unwrap(),
+                    bpp: framebuffer.info.bpp,
+                    red_shift: framebuffer.info.red_shift,
+                    green_shift: framebuffer.info.green_shift,
+                    blue_shift: framebuffer.info.blue_shift,
+                    reserved: [0; 3],
+                };
+
+                let arg = arg.get_mut(..size_of::<fb::ModeInfo>()).ok_or(crate::vfs::Error::BufferTooSmall)?;
+                // Safety: `arg` is exactly the size of `ModeInfo`, and unaligned writes are used.
+                unsafe { arg.as_mut_ptr().cast::<fb::ModeInfo>().write_unaligned(info) };
+
+                Ok(arg.len())
+            }
+
+            fb::FLUSH => {
+                let arg = arg.get(..size_of::<fb::Damage>()).ok_or(crate::vfs::Error::BufferTooSmall)?;
+                // Safety: `arg` is exactly the size of `Damage`, which is valid for any bytes.
+                let damage = unsafe { arg.as_ptr().cast::<fb::Damage>().read_unaligned() };
+
+                framebuffer.damage(Rect::new(
+                    damage.x as usize,
+                    damage.y as usize,
+                    damage.width as usize,
+                    damage.height as usize,
+                ));
+                framebuffer.flush();
+
+                Ok(0)
+            }
+
+            _ => Err(crate::vfs::Error::InvalidArgument),
+        })
+        .ok_or(crate::vfs::Error::NotFound)?
+    }
+
+    fn map_frame(&self, index: usize) -> crate::vfs::Result<Address<Frame>> {
+        with(|framebuffer| framebuffer.shadow_frames().nth(index)).flatten().ok_or(crate::vfs::Error::InvalidArgument)
+    }
+}
@@ -0,0 +1,324 @@
+//! Flattened devicetree (FDT) parsing, for discovering the hardware of platforms which describe it with a devicetree
+//! rather than ACPI (i.e. RISC-V boards).
+//!
+//! The blob is copied into a tree of [`Node`]s, as it lives in bootloader memory which is later reclaimed, and the tree
+//! is then flattened into the [`Platform`] description the ACPI path also fills.
+
+use crate::{
+    mem::HHDM,
+    platform::{Cpu, Device, Platform},
+};
+use alloc::{boxed::Box, string::String, vec::Vec};
+use core::ops::Range;
+
+crate::error_impl! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Error {
+        Boot { err: crate::init::boot::Error } => Some(err),
+        InvalidMagic => None,
+        /// The blob's layout isn't compatible with version 17, which the parser reads.
+        UnsupportedVersion { version: u32 } => None,
+        /// A block, structure, or string extends past the end of the blob.
+        Truncated => None,
+        /// A node or property name isn't valid UTF-8.
+        InvalidString => None,
+        UnexpectedToken { token: u32 } => None
+    }
+}
+
+const MAGIC: u32 = 0xD00D_FEED;
+const VERSION: u32 = 17;
+const HEADER_LEN: usize = 0x28;
+
+const TOKEN_BEGIN_NODE: u32 = 0x1;
+const TOKEN_END_NODE: u32 = 0x2;
+const TOKEN_PROP: u32 = 0x3;
+const TOKEN_NOP: u32 = 0x4;
+const TOKEN_END: u32 = 0x9;
+
+/// `#address-cells` and `#size-cells` of a node's children, if the node doesn't specify them.
+const DEFAULT_ADDRESS_CELLS: u32 = 2;
+const DEFAULT_SIZE_CELLS: u32 = 1;
+
+#[derive(Debug, Clone)]
+pub struct Property {
+    pub name: String,
+    pub value: Box<[u8]>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Node {
+    /// Name of the node, including its unit address (i.e. `serial@10000000`). The root node's name is empty.
+    pub name: String,
+    pub properties: Vec<Property>,
+    pub children: Vec<Node>,
+}
+
+impl Node {
+    pub fn property(&self, name: &str) -> Option<&[u8]> {
+        self.properties.iter().find(|property| property.name == name).map(|property| &*property.value)
+    }
+
+    /// Reads a single-cell property, i.e. `#address-cells`.
+    pub fn u32(&self, name: &str) -> Option<u32> {
+        self.property(name).and_then(|value| read_u32(value, 0))
+    }
+
+    /// Reads a string list property, i.e. `compatible`.
+    pub fn strings(&self, name: &str) -> impl Iterator<Item = &str> {
+        self.property(name)
+            .unwrap_or_default()
+            .split(|byte| *byte == 0)
+            .filter(|bytes| !bytes.is_empty())
+            .filter_map(|bytes| core::str::from_utf8(bytes).ok())
+    }
+
+    /// Indicates whether the node is enabled, as it is unless its `status` says otherwise.
+    pub fn is_enabled(&self) -> bool {
+        self.strings("status").next().is_none_or(|status| status == "okay" || status == "ok")
+    }
+}
+
+pub struct Tree {
+    pub root: Node,
+    /// ID of the CPU (hart) the system booted on.
+    pub boot_cpu: u32,
+}
+
+/// Parses the devicetree the bootloader was handed.
+///
+/// #### Remark
+///
+/// This function must be called before bootloader memory is reclaimed.
+pub fn load() -> Result<Tree> {
+    let address = crate::init::boot::get_dtb_address().map_err(|err| Error::Boot { err })?.get();
+
+    // Safety: Bootloader guarantees the provided devicetree address is valid, and a blob begins with its header.
+    let header = unsafe { core::slice::from_raw_parts(HHDM.ptr().add(address), HEADER_LEN) };
+    if read_u32(header, 0) != Some(MAGIC) {
+        return Err(Error::InvalidMagic);
+    }
+
+    let len = usize::try_from(read_u32(header, 0x4).ok_or(Error::Truncated)?).unwrap();
+    // Safety: The header's magic was checked, so its total size is as reliable as the bootloader makes it.
+    let blob = unsafe { core::slice::from_raw_parts(HHDM.ptr().add(address), len) };
+
+    parse(blob)
+}
+
+/// Parses the devicetree `blob` into a tree of its nodes.
+pub fn parse(blob: &[u8]) -> Result<Tree> {
+    let header = |index: usize| read_u32(blob, index * 4).ok_or(Error::Truncated);
+
+    if header(0)? != MAGIC {
+        return Err(Error::InvalidMagic);
+    }
+    let version = header(5)?;
+    if version < VERSION || header(6)? > VERSION {
+        return Err(Error::UnsupportedVersion { version });
+    }
+
+    let structs = block(blob, header(2)?, header(9)?)?;
+    let strings = block(blob, header(3)?, header(8)?)?;
+    let boot_cpu = header(7)?;
+
+    // Nodes which have begun, but not yet ended, from the root down.
+    let mut open: Vec<Node> = Vec::new();
+    let mut root = None;
+    let mut offset = 0;
+
+    loop {
+        let token = read_u32(structs, offset).ok_or(Error::Truncated)?;
+        offset += 4;
+
+        match token {
+            TOKEN_BEGIN_NODE if root.is_none() => {
+                let name = read_str(structs, offset)?;
+                offset = (offset + name.len() + 1).next_multiple_of(4);
+
+                open.push(Node { name: String::from(name), ..Node::default() });
+            }
+
+            TOKEN_END_NODE => {
+                let node = open.pop().ok_or(Error::UnexpectedToken { token })?;
+                match open.last_mut() {
+                    Some(parent) => parent.children.push(node),
+                    None => root = Some(node),
+                }
+            }
+
+            TOKEN_PROP => {
+                let len = usize::try_from(read_u32(structs, offset).ok_or(Error::Truncated)?).unwrap();
+                let name_offset = usize::try_from(read_u32(structs, offset + 4).ok_or(Error::Truncated)?).unwrap();
+                let value = structs.get((offset + 8)..(offset + 8 + len)).ok_or(Error::Truncated)?;
+                offset = (offset + 8 + len).next_multiple_of(4);
+
+                let name = read_str(strings, name_offset)?;
+                let node = open.last_mut().ok_or(Error::UnexpectedToken { token })?;
+                node.properties.push(Property { name: String::from(name), value: value.into() });
+            }
+
+            TOKEN_NOP => {}
+            TOKEN_END if open.is_empty() => break,
+            token => return Err(Error::UnexpectedToken { token }),
+        }
+    }
+
+    Ok(Tree { root: root.ok_or(Error::Truncated)?, boot_cpu })
+}
+
+/// Flattens `tree` into a description of the platform.
+pub fn platform(tree: &Tree) -> Platform {
+    let mut platform = Platform::default();
+
+    let bus =
+        Bus { address_cells: DEFAULT_ADDRESS_CELLS, size_cells: DEFAULT_SIZE_CELLS, windows: None }.child(&tree.root);
+    for node in &tree.root.children {
+        visit(node, &bus, tree.boot_cpu, &mut platform);
+    }
+
+    platform
+}
+
+fn visit(node: &Node, bus: &Bus, boot_cpu: u32, platform: &mut Platform) {
+    if !node.is_enabled() {
+        return;
+    }
+
+    match node.strings("device_type").next() {
+        Some("memory") => platform.memory.extend(bus.regs(node)),
+
+        // A CPU's `reg` holds its hart ID, rather than an address.
+        Some("cpu") => {
+            let id = node.property("reg").and_then(|reg| read_cells(reg, bus.address_cells));
+            if let Some(id) = id.and_then(|(id, _)| u32::try_from(id).ok()) {
+                platform.cpus.push(Cpu { id, is_boot: id == boot_cpu });
+            }
+        }
+
+        _ if node.property("compatible").is_some() => platform.devices.push(Device {
+            name: node.name.clone(),
+            compatible: node.strings("compatible").map(String::from).collect(),
+            regs: bus.regs(node),
+            interrupts: node
+                .property("interrupts")
+                .unwrap_or_default()
+                .chunks_exact(4)
+                .filter_map(|cell| read_u32(cell, 0))
+                .collect(),
+            properties: node.properties.clone(),
+        }),
+
+        _ => {}
+    }
+
+    let bus = bus.child(node);
+    for child in &node.children {
+        visit(child, &bus, boot_cpu, platform);
+    }
+}
+
+/// Addressing of the children of a node.
+struct Bus {
+    address_cells: u32,
+    size_cells: u32,
+    /// Ranges of the bus's addresses, and the physical addresses they're translated to, or `None` if the addresses
+    /// are physical.
+    windows: Option<Vec<(Range<usize>, usize)>>,
+}
+
+impl Bus {
+    /// Addressing of the children of `node`, a child of this bus.
+    fn child(&self, node: &Node) -> Self {
+        let address_cells = node.u32("#address-cells").unwrap_or(DEFAULT_ADDRESS_CELLS);
+        let size_cells = node.u32("#size-cells").unwrap_or(DEFAULT_SIZE_CELLS);
+
+        let windows = match node.property("ranges") {
+            // An empty `ranges` maps the child address space directly onto the parent's. Strictly, a node without one
+            // can't be translated through, but it's taken the same way so that devices below it are still found.
+            None | Some([]) => self.windows.clone(),
+
+            Some(ranges) => {
+                let entry_len = usize::try_from(address_cells + self.address_cells + size_cells).unwrap() * 4;
+                let windows = ranges.chunks_exact(entry_len.max(4)).filter_map(|entry| {
+                    let (child, entry) = read_cells(entry, address_cells)?;
+                    let (parent, entry) = read_cells(entry, self.address_cells)?;
+                    let (size, _) = read_cells(entry, size_cells)?;
+
+                    Some((child..child.checked_add(size)?, self.translate(parent)?))
+                });
+
+                Some(windows.collect())
+            }
+        };
+
+        Self { address_cells, size_cells, windows }
+    }
+
+    fn translate(&self, address: usize) -> Option<usize> {
+        match &self.windows {
+            None => Some(address),
+            Some(windows) => windows
+                .iter()
+                .find(|(range, _)| range.contains(&address))
+                .map(|(range, base)| base + (address - range.start)),
+        }
+    }
+
+    /// Physical memory ranges described by the `reg` of `node`, a child of this bus.
+    fn regs(&self, node: &Node) -> Vec<Range<usize>> {
+        let entry_len = usize::try_from(self.address_cells + self.size_cells).unwrap() * 4;
+        if entry_len == 0 {
+            return Vec::new();
+        }
+
+        node.property("reg")
+            .unwrap_or_default()
+            .chunks_exact(entry_len)
+            .filter_map(|entry| {
+                let (address, entry) = read_cells(entry, self.address_cells)?;
+                let (size, _) = read_cells(entry, self.size_cells)?;
+                let address = self.translate(address)?;
+
+                Some(address..address.checked_add(size)?)
+            })
+            .collect()
+    }
+}
+
+fn block(blob: &[u8], offset: u32, len: u32) -> Result<&[u8]> {
+    let offset = usize::try_from(offset).unwrap();
+    let len = usize::try_from(len).unwrap();
+
+    blob.get(offset..(offset + len)).ok_or(Error::Truncated)
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    bytes.get(offset..(offset + 4)).map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap()))
+}
+
+/// Reads the NUL-terminated string at `offset`.
+fn read_str(bytes: &[u8], offset: usize) -> Result<&str> {
+    let bytes = bytes.get(offset..).ok_or(Error::Truncated)?;
+    let len = bytes.iter().position(|byte| *byte == 0).ok_or(Error::Truncated)?;
+
+    core::str::from_utf8(&bytes[..len]).map_err(|_| Error::InvalidString)
+}
+
+/// Reads a value of `cells` big-endian cells from the front of `bytes`, returning it with the bytes which follow.
+///
+/// #### Remark
+///
+/// Values of more than two cells (i.e. PCI addresses) aren't supported.
+fn read_cells(bytes: &[u8], cells: u32) -> Option<(usize, &[u8])> {
+    if cells > 2 {
+        return None;
+    }
+
+    let (value, remaining) = bytes.split_at_checked(usize::try_from(cells).unwrap() * 4)?;
+    let value = value
+        .chunks_exact(4)
+        .fold(0u64, |value, cell| (value << 32) | u64::from(u32::from_be_bytes(cell.try_into().unwrap())));
+
+    Some((usize::try_from(value).ok()?, remaining))
+}
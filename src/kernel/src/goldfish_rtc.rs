@@ -0,0 +1,55 @@
+//! Goldfish real-time clock, which QEMU's RISC-V `virt` machine provides in place of a firmware wall clock.
+//!
+//! The clock counts nanoseconds since the Unix epoch. It's read once, to anchor the wall clock to uptime (see
+//! [`crate::time::set_wall_clock`]), after which wall-clock time follows the system clock.
+
+use crate::mem::{
+    io::resource::{self, Kind, Space},
+    paging::TableEntryFlags,
+};
+use core::time::Duration;
+
+crate::error_impl! {
+    #[derive(Debug)]
+    pub enum Error {
+        /// The platform describes no Goldfish RTC.
+        NotPresent => None,
+        NoRegisters => None,
+        Resource { err: resource::Error } => Some(err),
+        Vmap { err: crate::mem::vmap::Error } => Some(err)
+    }
+}
+
+const COMPATIBLE: &str = "google,goldfish-rtc";
+
+/// Reading `TIME_LOW` latches the high half of the time into `TIME_HIGH`, so they must be read in that order.
+const TIME_LOW_OFFSET: usize = 0x00;
+const TIME_HIGH_OFFSET: usize = 0x04;
+
+/// Reads the RTC the platform describes, and anchors the wall clock to it.
+pub fn init() -> Result<()> {
+    let device =
+        crate::platform::get().and_then(|platform| platform.compatible(COMPATIBLE).next()).ok_or(Error::NotPresent)?;
+    let regs = device.regs.first().cloned().ok_or(Error::NoRegisters)?;
+
+    resource::claim(Space::Memory, regs.start, regs.len(), "goldfish-rtc", Kind::Exclusive)
+        .map_err(|err| Error::Resource { err })?;
+    let mapping =
+        crate::mem::vmap::map(regs.start, regs.len(), TableEntryFlags::MMIO).map_err(|err| Error::Vmap { err })?;
+    assert!(TIME_HIGH_OFFSET + 4 <= mapping.len());
+
+    let read = |offset: usize| {
+        // Safety: Offset lies within the mapping, and the RTC's registers are naturally aligned words.
+        unsafe { mapping.as_ptr().as_ptr().add(offset).cast::<u32>().read_volatile() }
+    };
+
+    let uptime = crate::time::uptime();
+    let low = read(TIME_LOW_OFFSET);
+    let high = read(TIME_HIGH_OFFSET);
+    let now = Duration::from_nanos((u64::from(high) << 32) | u64::from(low));
+
+    info!("Goldfish RTC        {:#X}, reads {}", regs.start, crate::time::DateTime(now));
+    crate::time::set_wall_clock(now, uptime);
+
+    Ok(())
+}
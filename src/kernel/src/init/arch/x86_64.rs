@@ -59,6 +59,15 @@ pub fn cpu_setup() {
         });
     }
 
+    // Reprogram PAT entries 1 and 5 (selected by `PWT`, without and with `PAT`) from write-through to write-combining,
+    // leaving the other entries at their power-on defaults. This must be identical across cores, as it alters how
+    // shared mappings are cached.
+    if cpuid::FEATURE_INFO.has_pat() {
+        // Safety: Nothing is mapped with `PWT` set at this point (besides, possibly, the bootloader's framebuffer
+        //         mapping, which is meant to be write-combining), so no other mapping changes its caching type.
+        unsafe { msr::IA32_PAT::write(0x0007_0106_0007_0106) };
+    }
+
     // Load the static processor tables for this core.
     crate::arch::x86_64::structures::load_static_tables();
 
@@ -23,7 +23,12 @@ crate::error_impl! {
     pub enum Error {
         BootExpired => None,
         NoRsdpAddress => None,
-        NoMemoryMap => None
+        NoSmbiosAddress => None,
+        NoEfiSystemTable => None,
+        NoDtbAddress => None,
+        NoEfiMemoryMap => None,
+        NoMemoryMap => None,
+        NoFramebuffer => None
     }
 }
 
@@ -70,6 +75,139 @@ pub fn get_rsdp_address() -> Result<Address<Virtual>> {
     .flatten()
 }
 
+/// Returns the physical address of the SMBIOS entry point, preferring the 64-bit (SMBIOS 3.0) entry point.
+pub fn get_smbios_address() -> Result<Address<Virtual>> {
+    boot_only!({
+        #[limine::limine_tag]
+        static LIMINE_SMBIOS: limine::SmbiosRequest = limine::SmbiosRequest::new(LIMINE_REV);
+
+        LIMINE_SMBIOS
+            .get_response()
+            .and_then(|response| response.entry_64().or_else(|| response.entry_32()))
+            .and_then(|ptr| {
+                Address::new(
+                    // Properly handle the bootloader's mapping of SMBIOS addresses in lower-half or higher-half memory space.
+                    core::cmp::min(ptr.addr().get(), ptr.addr().get().wrapping_sub(crate::mem::HHDM.address().get())),
+                )
+            })
+            .ok_or(Error::NoSmbiosAddress)
+    })
+    .flatten()
+}
+
+/// Returns the physical address of the EFI system table, if the kernel was booted via UEFI.
+pub fn get_efi_system_table_address() -> Result<Address<Virtual>> {
+    boot_only!({
+        #[limine::limine_tag]
+        static LIMINE_EFI_SYSTEM_TABLE: limine::EfiSystemTableRequest = limine::EfiSystemTableRequest::new(LIMINE_REV);
+
+        LIMINE_EFI_SYSTEM_TABLE
+            .get_response()
+            .and_then(limine::EfiSystemTableResponse::address)
+            .and_then(|ptr| {
+                Address::new(core::cmp::min(
+                    ptr.addr().get(),
+                    ptr.addr().get().wrapping_sub(crate::mem::HHDM.address().get()),
+                ))
+            })
+            .ok_or(Error::NoEfiSystemTable)
+    })
+    .flatten()
+}
+
+/// Returns the physical address of the flattened devicetree, if the bootloader was handed one (i.e. on RISC-V).
+pub fn get_dtb_address() -> Result<Address<Virtual>> {
+    boot_only!({
+        #[limine::limine_tag]
+        static LIMINE_DTB: limine::DtbRequest = limine::DtbRequest::new(LIMINE_REV);
+
+        LIMINE_DTB
+            .get_response()
+            .and_then(limine::DtbResponse::address)
+            .and_then(|ptr| {
+                Address::new(core::cmp::min(
+                    ptr.addr().get(),
+                    ptr.addr().get().wrapping_sub(crate::mem::HHDM.address().get()),
+                ))
+            })
+            .ok_or(Error::NoDtbAddress)
+    })
+    .flatten()
+}
+
+/// The UEFI memory map, as it was provided by firmware to the bootloader.
+pub struct EfiMemoryMap {
+    pub map: &'static mut [u8],
+    pub desc_size: usize,
+    pub desc_version: u32,
+}
+
+pub fn get_efi_memory_map() -> Result<EfiMemoryMap> {
+    boot_only!({
+        #[limine::limine_tag]
+        static LIMINE_EFI_MMAP: limine::EfiMemoryMapRequest = limine::EfiMemoryMapRequest::new(LIMINE_REV);
+
+        LIMINE_EFI_MMAP
+            .get_response()
+            .and_then(|response| {
+                let map_ptr = response.memmap()?;
+                let map_size = usize::try_from(response.memmap_size()).unwrap();
+
+                Some(EfiMemoryMap {
+                    // Safety: Bootloader guarantees the memory map pointer is valid for `memmap_size` bytes.
+                    map: unsafe { core::slice::from_raw_parts_mut(map_ptr.as_ptr().cast::<u8>(), map_size) },
+                    desc_size: usize::try_from(response.desc_size()).unwrap(),
+                    desc_version: u32::try_from(response.desc_version()).unwrap(),
+                })
+            })
+            .ok_or(Error::NoEfiMemoryMap)
+    })
+    .flatten()
+}
+
+/// Layout of the framebuffer the bootloader set up.
+#[derive(Debug, Clone, Copy)]
+pub struct FramebufferInfo {
+    pub address: Address<libsys::Frame>,
+    pub width: usize,
+    pub height: usize,
+    /// Length of a scanline, in bytes.
+    pub pitch: usize,
+    pub bpp: u16,
+    pub red_shift: u8,
+    pub green_shift: u8,
+    pub blue_shift: u8,
+}
+
+/// Returns the layout of the first framebuffer provided by the bootloader.
+pub fn get_framebuffer() -> Result<FramebufferInfo> {
+    boot_only!({
+        #[limine::limine_tag]
+        static LIMINE_FRAMEBUFFER: limine::FramebufferRequest = limine::FramebufferRequest::new(LIMINE_REV);
+
+        LIMINE_FRAMEBUFFER
+            .get_response()
+            .and_then(|response| response.framebuffers().first())
+            .and_then(|framebuffer| {
+                let ptr = framebuffer.address()?;
+
+                Some(FramebufferInfo {
+                    // The framebuffer is provided within the higher-half direct map.
+                    address: Address::new(ptr.addr().get().wrapping_sub(crate::mem::HHDM.address().get()))?,
+                    width: usize::try_from(framebuffer.width()).unwrap(),
+                    height: usize::try_from(framebuffer.height()).unwrap(),
+                    pitch: usize::try_from(framebuffer.pitch()).unwrap(),
+                    bpp: framebuffer.bpp(),
+                    red_shift: framebuffer.red_mask_shift(),
+                    green_shift: framebuffer.green_mask_shift(),
+                    blue_shift: framebuffer.blue_mask_shift(),
+                })
+            })
+            .ok_or(Error::NoFramebuffer)
+    })
+    .flatten()
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct ReclaimMemoryError;
 
@@ -0,0 +1,62 @@
+//! Registry of the optional drivers & subsystems compiled into the kernel.
+//!
+//! Each entry corresponds to a cargo feature of the same name, so a minimal kernel
+//! can be built with `--no-default-features --features <...>`. Userspace drivers
+//! named after a driver feature are only loaded if it's compiled in.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Driver,
+    Subsystem,
+    Instrumentation,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Feature {
+    pub name: &'static str,
+    pub kind: Kind,
+    pub enabled: bool,
+}
+
+macro_rules! features {
+    ($($name:literal => $kind:ident),* $(,)?) => {
+        &[$(Feature { name: $name, kind: Kind::$kind, enabled: cfg!(feature = $name) }),*]
+    };
+}
+
+pub static FEATURES: &[Feature] = features! {
+    "nvme" => Driver,
+    "virtio" => Driver,
+    "e1000" => Driver,
+    "xhci" => Driver,
+    "netstack" => Subsystem,
+    "fat32" => Subsystem,
+    "irq_audit" => Instrumentation,
+    "alloc_audit" => Instrumentation,
+};
+
+/// Indicates whether the feature with the given name was compiled in.
+pub fn is_enabled(name: &str) -> bool {
+    FEATURES.iter().any(|feature| feature.enabled && feature.name == name)
+}
+
+/// Indicates whether `name` is a driver feature.
+pub fn is_driver(name: &str) -> bool {
+    FEATURES.iter().any(|feature| feature.kind == Kind::Driver && feature.name == name)
+}
+
+/// Logs which features were compiled into the kernel.
+pub fn log() {
+    for feature in FEATURES {
+        info!(
+            "Feature             {:<12}{:<12}{}",
+            feature.name,
+            match feature.kind {
+                Kind::Driver => "driver",
+                Kind::Subsystem => "subsystem",
+                Kind::Instrumentation => "instrumentation",
+            },
+            if feature.enabled { "enabled" } else { "disabled" }
+        );
+    }
+}
@@ -5,43 +5,19 @@ use libsys::{page_size, Address};
 crate::error_impl! {
     #[derive(Debug)]
     pub enum Error {
-        KernelAddress => None,
-        KernelElf { err: elf::ParseError } => Some(err),
+        /// The kernel's image hasn't been parsed (see [`crate::kimage::init`]).
+        KernelImage => None,
         Paging { err: paging::Error } => Some(err),
+        Layout { err: crate::mem::layout::Error } => Some(err),
         Boot { err: crate::init::boot::Error } => Some(err)
     }
 }
 
-struct KernelAddresses {
-    phys: usize,
-    virt: usize,
-}
-
-fn get_kernel_addresses() -> Result<KernelAddresses> {
-    #[limine::limine_tag]
-    static LIMINE_KERNEL_ADDR: limine::KernelAddressRequest =
-        limine::KernelAddressRequest::new(crate::init::boot::LIMINE_REV);
-
-    LIMINE_KERNEL_ADDR
-        .get_response()
-        .map(|response| KernelAddresses {
-            phys: usize::try_from(response.physical_base()).unwrap(),
-            virt: usize::try_from(response.virtual_base()).unwrap(),
-        })
-        .ok_or(Error::KernelAddress)
-}
-
-#[allow(clippy::too_many_lines)]
-pub fn setup(kernel_file: &limine::File) -> Result<()> {
-    // Extract kernel address information.
-    let kernel_addresses = get_kernel_addresses()?;
+pub fn setup() -> Result<()> {
+    let image = crate::kimage::get().ok_or(Error::KernelImage)?;
 
     debug!("Preparing kernel memory system.");
 
-    // Safety: Bootloader guarantees the provided information to be correct.
-    let kernel_elf = elf::ElfBytes::<elf::endian::AnyEndian>::minimal_parse(kernel_file.data())
-        .map_err(|err| Error::KernelElf { err })?;
-
     /* load and map segments */
 
     debug!("Mapping the higher-half direct map.");
@@ -74,8 +50,9 @@ pub fn setup(kernel_file: &limine::File) -> Result<()> {
 
                     MemoryMapEntryType::AcpiNvs
                     | MemoryMapEntryType::AcpiReclaimable
-                    | MemoryMapEntryType::BootloaderReclaimable
-                    | MemoryMapEntryType::Framebuffer => Some((TableEntryFlags::RW, true)),
+                    | MemoryMapEntryType::BootloaderReclaimable => Some((TableEntryFlags::RW, true)),
+
+                    MemoryMapEntryType::Framebuffer => Some((TableEntryFlags::WC, true)),
 
                     MemoryMapEntryType::Reserved | MemoryMapEntryType::KernelAndModules => {
                         Some((TableEntryFlags::RO, true))
@@ -93,36 +70,27 @@ pub fn setup(kernel_file: &limine::File) -> Result<()> {
         }
 
         /* load kernel segments */
-        kernel_elf
-            .segments()
-            .expect("kernel file has no segments")
-            .into_iter()
-            .filter(|ph| ph.p_type == elf::abi::PT_LOAD)
-            .try_for_each(|phdr| {
-                extern "C" {
-                    static KERNEL_BASE: libkernel::LinkerSymbol;
-                }
-
-                debug!("{:X?}", phdr);
-
-                // Safety: `KERNEL_BASE` is a linker symbol to an in-executable memory location, so it is guaranteed to be valid (and is never written to).
-                let base_offset = usize::try_from(phdr.p_vaddr).unwrap() - unsafe { KERNEL_BASE.as_usize() };
-                let base_offset_end = base_offset + usize::try_from(phdr.p_memsz).unwrap();
-                let flags = TableEntryFlags::from(crate::task::segment_to_mmap_permissions(phdr.p_flags));
-
-                (base_offset..base_offset_end)
-                    .step_by(page_size())
-                    // Attempt to map the page to the frame.
-                    .try_for_each(|offset| {
-                        let phys_addr = Address::new(kernel_addresses.phys + offset).unwrap();
-                        let virt_addr = Address::new(kernel_addresses.virt + offset).unwrap();
-
-                        trace!("Map  {:X?} -> {:X?}   {:?}", virt_addr, phys_addr, flags);
-                        kmapper
-                            .map(virt_addr, TableDepth::min(), phys_addr, true, flags)
-                            .map_err(|err| Error::Paging { err })
-                    })
-            })?;
+        image.segments.iter().try_for_each(|segment| {
+            debug!("{:X?}", segment);
+
+            let base_offset = segment.range.start - image.virtual_base;
+            let base_offset_end = segment.range.end - image.virtual_base;
+            let flags = TableEntryFlags::from(segment.permissions);
+
+            (base_offset..base_offset_end)
+                .step_by(page_size())
+                // Attempt to map the page to the frame.
+                .try_for_each(|offset| {
+                    let phys_addr = Address::new(image.physical_base + offset).unwrap();
+                    let virt_addr = Address::new(image.virtual_base + offset).unwrap();
+
+                    trace!("Map  {:X?} -> {:X?}   {:?}", virt_addr, phys_addr, flags);
+                    // The image's frames were already locked, as kernel memory, by the higher-half direct map.
+                    kmapper
+                        .map(virt_addr, TableDepth::min(), phys_addr, false, flags)
+                        .map_err(|err| Error::Paging { err })
+                })
+        })?;
 
         debug!("Switching to kernel page tables...");
         // Safety: Kernel mappings should be identical to the bootloader mappings.
@@ -130,7 +98,16 @@ pub fn setup(kernel_file: &limine::File) -> Result<()> {
         debug!("Kernel has finalized control of page tables.");
 
         Ok(())
-    })
+    })?;
+
+    let max_physical = crate::init::boot::get_memory_map()
+        .map_err(|err| Error::Boot { err })?
+        .iter()
+        .map(|entry| usize::try_from(entry.range().end).unwrap())
+        .max()
+        .unwrap_or(0);
+    crate::mem::layout::validate(max_physical, image.virtual_base..image.range().end)
+        .map_err(|err| Error::Layout { err })
 }
 
 fn map_hhdm_range(
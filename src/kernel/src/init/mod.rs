@@ -4,9 +4,14 @@ mod memory;
 mod params;
 pub use params::*;
 
+pub mod features;
+
 pub mod boot;
+pub mod modules;
+pub mod progress;
 
-use libsys::Address;
+mod subsystem;
+use subsystem::Subsystem;
 
 crate::error_impl! {
     #[derive(Debug)]
@@ -17,13 +22,325 @@ crate::error_impl! {
 
 pub static KERNEL_HANDLE: spin::Lazy<uuid::Uuid> = spin::Lazy::new(uuid::Uuid::new_v4);
 
-#[allow(clippy::too_many_lines)]
-pub unsafe extern "C" fn init() -> ! {
-    use core::sync::atomic::{AtomicBool, Ordering};
-
+fn kernel_file() -> &'static limine::File {
     #[limine::limine_tag]
     static LIMINE_KERNEL_FILE: limine::KernelFileRequest = limine::KernelFileRequest::new(boot::LIMINE_REV);
 
+    LIMINE_KERNEL_FILE
+        .get_response()
+        .map(limine::KernelFileResponse::file)
+        .expect("bootloader did not respond to kernel file request")
+}
+
+/// Subsystems initialized at boot, in the order they're run when the order of their requirements allows either.
+const SUBSYSTEMS: &[Subsystem] = {
+    use progress::Stage;
+
+    &[
+        Subsystem {
+            name: "pmm",
+            provides: &["allocator"],
+            requires: &[],
+            stage: Stage::Memory,
+            init: || crate::mem::alloc::pmm::init(boot::get_memory_map().unwrap()).unwrap(),
+        },
+        Subsystem {
+            name: "kimage",
+            provides: &[],
+            requires: &["allocator"],
+            stage: Stage::Memory,
+            init: || crate::kimage::init(kernel_file()).unwrap(),
+        },
+        Subsystem {
+            name: "symbols",
+            provides: &[],
+            requires: &["allocator"],
+            stage: Stage::Memory,
+            init: || crate::panic::symbols::parse(kernel_file()).unwrap(),
+        },
+        Subsystem {
+            name: "paging",
+            provides: &[],
+            requires: &["kimage"],
+            stage: Stage::Memory,
+            init: || memory::setup().unwrap(),
+        },
+        Subsystem {
+            name: "vmap",
+            provides: &[],
+            requires: &["paging"],
+            stage: Stage::Memory,
+            init: || crate::mem::vmap::init().unwrap(),
+        },
+        Subsystem {
+            name: "acpi",
+            provides: &[],
+            requires: &["vmap"],
+            stage: Stage::Platform,
+            init: || crate::acpi::init_interface().unwrap(),
+        },
+        Subsystem {
+            name: "platform",
+            provides: &[],
+            #[cfg(target_arch = "x86_64")]
+            requires: &["acpi"],
+            #[cfg(not(target_arch = "x86_64"))]
+            requires: &["vmap"],
+            stage: Stage::Platform,
+            init: crate::platform::init,
+        },
+        Subsystem {
+            name: "smbios",
+            provides: &[],
+            requires: &["vmap"],
+            stage: Stage::Platform,
+            init: || {
+                if let Err(err) = crate::smbios::init() {
+                    warn!("Failed to parse SMBIOS tables: {:?}", err);
+                }
+            },
+        },
+        Subsystem {
+            name: "efi",
+            provides: &[],
+            requires: &["vmap"],
+            stage: Stage::Platform,
+            init: || {
+                if let Err(err) = crate::efi::init() {
+                    debug!("EFI runtime services are unavailable: {:?}", err);
+                }
+            },
+        },
+        Subsystem {
+            name: "time",
+            provides: &["clock"],
+            requires: &["acpi"],
+            stage: Stage::Platform,
+            init: crate::time::init,
+        },
+        #[cfg(target_arch = "riscv64")]
+        Subsystem {
+            name: "goldfish_rtc",
+            provides: &[],
+            requires: &["clock", "platform", "resource", "vmap"],
+            stage: Stage::Platform,
+            init: || {
+                if let Err(err) = crate::goldfish_rtc::init() {
+                    debug!("No Goldfish RTC is available: {:?}", err);
+                }
+            },
+        },
+        Subsystem {
+            name: "resource",
+            provides: &[],
+            requires: &["acpi"],
+            stage: Stage::Platform,
+            init: crate::mem::io::resource::init,
+        },
+        Subsystem {
+            name: "fb",
+            provides: &[],
+            requires: &["vmap"],
+            stage: Stage::Platform,
+            init: || {
+                if let Err(err) = crate::fb::init() {
+                    debug!("No framebuffer is available: {:?}", err);
+                }
+            },
+        },
+        Subsystem {
+            name: "modules",
+            provides: &[],
+            requires: &["paging"],
+            stage: Stage::Platform,
+            init: modules::init,
+        },
+        Subsystem {
+            name: "vfs",
+            provides: &[],
+            requires: &["modules"],
+            stage: Stage::Platform,
+            init: || crate::vfs::init().unwrap(),
+        },
+        Subsystem {
+            name: "input",
+            provides: &[],
+            requires: &["vfs"],
+            stage: Stage::Platform,
+            init: || crate::input::init().unwrap(),
+        },
+        Subsystem {
+            name: "power",
+            provides: &[],
+            requires: &["acpi", "vfs"],
+            stage: Stage::Platform,
+            init: || crate::power::init().unwrap(),
+        },
+        Subsystem {
+            name: "kmsg",
+            provides: &[],
+            requires: &["vfs"],
+            stage: Stage::Platform,
+            init: || crate::logging::init_kmsg().unwrap(),
+        },
+        Subsystem {
+            name: "trace",
+            provides: &[],
+            requires: &["vfs"],
+            stage: Stage::Platform,
+            init: || crate::task::trace::init().unwrap(),
+        },
+        Subsystem {
+            name: "zero",
+            provides: &[],
+            requires: &["allocator"],
+            stage: Stage::Platform,
+            init: crate::mem::alloc::zero::init,
+        },
+        Subsystem {
+            name: "workqueue",
+            provides: &[],
+            requires: &["clock"],
+            stage: Stage::Platform,
+            init: crate::task::workqueue::init,
+        },
+        Subsystem {
+            name: "publish",
+            provides: &[],
+            requires: &["kimage", "clock"],
+            stage: Stage::Platform,
+            init: || {
+                crate::task::group::publish();
+                crate::task::registry::publish();
+                crate::mem::alloc::pmm::publish();
+                crate::kimage::publish();
+                crate::interrupts::stacks::publish();
+                progress::publish();
+            },
+        },
+        #[cfg(target_arch = "x86_64")]
+        Subsystem {
+            name: "i8042",
+            provides: &[],
+            requires: &["input"],
+            stage: Stage::Platform,
+            init: || {
+                if let Err(err) = crate::input::i8042::init() {
+                    warn!("Failed to initialize PS/2 controller: {:?}", err);
+                }
+            },
+        },
+        #[cfg(target_arch = "riscv64")]
+        Subsystem {
+            name: "plic",
+            provides: &[],
+            requires: &["platform", "resource", "vmap"],
+            stage: Stage::Platform,
+            init: || {
+                if let Err(err) = crate::arch::rv64::plic::init() {
+                    warn!("Failed to initialize PLIC: {:?}", err);
+                }
+            },
+        },
+        Subsystem {
+            name: "serial",
+            provides: &[],
+            #[cfg(target_arch = "x86_64")]
+            requires: &["acpi", "input", "resource", "vmap"],
+            #[cfg(target_arch = "riscv64")]
+            requires: &["input", "plic"],
+            stage: Stage::Platform,
+            init: crate::input::serial::init,
+        },
+        Subsystem {
+            name: "tty",
+            provides: &[],
+            requires: &["fb", "modules", "vfs"],
+            stage: Stage::Platform,
+            init: || crate::tty::init().unwrap(),
+        },
+        Subsystem {
+            name: "pci",
+            provides: &[],
+            requires: &["acpi", "resource", "vmap"],
+            stage: Stage::Pci,
+            init: || crate::mem::io::pci::init_devices().unwrap(),
+        },
+        #[cfg(feature = "nvme")]
+        Subsystem {
+            name: "nvme",
+            provides: &["pci-drivers"],
+            requires: &["pci"],
+            stage: Stage::Drivers,
+            init: crate::block::nvme::register,
+        },
+        #[cfg(feature = "virtio")]
+        Subsystem {
+            name: "virtio",
+            provides: &["pci-drivers"],
+            requires: &["pci"],
+            stage: Stage::Drivers,
+            init: crate::block::virtio::register,
+        },
+        #[cfg(feature = "virtio")]
+        Subsystem {
+            name: "virtio-input",
+            provides: &["pci-drivers"],
+            requires: &["input", "pci"],
+            stage: Stage::Drivers,
+            init: crate::input::virtio::register,
+        },
+        Subsystem {
+            name: "probe",
+            provides: &["pci-drivers"],
+            requires: &["pci"],
+            stage: Stage::Drivers,
+            init: crate::driver::probe,
+        },
+        #[cfg(feature = "fat32")]
+        Subsystem {
+            name: "fat32",
+            provides: &[],
+            requires: &["pci-drivers", "vfs"],
+            stage: Stage::Drivers,
+            init: crate::vfs::fat32::mount_esp,
+        },
+        Subsystem {
+            name: "drivers",
+            provides: &[],
+            requires: &["modules", "pci-drivers"],
+            stage: Stage::Drivers,
+            init: load_drivers,
+        },
+        Subsystem {
+            name: "init",
+            provides: &[],
+            requires: &["vfs", "drivers"],
+            stage: Stage::Drivers,
+            init: spawn_init,
+        },
+        #[cfg(feature = "irq_audit")]
+        Subsystem {
+            name: "irq_audit",
+            provides: &[],
+            requires: &["pci-drivers"],
+            stage: Stage::Drivers,
+            init: crate::interrupts::audit::publish,
+        },
+        Subsystem { name: "smp", provides: &[], requires: &["init"], stage: Stage::Smp, init: setup_smp },
+        Subsystem {
+            name: "reclaim",
+            provides: &[],
+            requires: &["smp", "modules", "kimage"],
+            stage: Stage::Init,
+            init: || crate::init::boot::reclaim_memory().unwrap(),
+        },
+    ]
+};
+
+pub unsafe extern "C" fn init() -> ! {
+    use core::sync::atomic::{AtomicBool, Ordering};
+
     static INIT: AtomicBool = AtomicBool::new(false);
     assert!(!INIT.load(Ordering::Acquire), "`init()` has already been called!");
     INIT.store(true, Ordering::Release);
@@ -32,25 +349,9 @@ pub unsafe extern "C" fn init() -> ! {
     arch::cpu_setup();
     print_boot_info();
 
-    let kernel_file = LIMINE_KERNEL_FILE
-        .get_response()
-        .map(limine::KernelFileResponse::file)
-        .expect("bootloader did not respond to kernel file request");
-
-    params::parse(kernel_file.cmdline());
-    crate::mem::alloc::pmm::init(boot::get_memory_map().unwrap()).unwrap();
-    crate::panic::symbols::parse(kernel_file).unwrap();
-    memory::setup(kernel_file).unwrap();
-
-    crate::acpi::init_interface().unwrap();
-
-    crate::mem::io::pci::init_devices().unwrap();
-
-    load_drivers();
-
-    setup_smp();
-
-    crate::init::boot::reclaim_memory().unwrap();
+    params::parse(kernel_file().cmdline());
+    subsystem::run(SUBSYSTEMS);
+    progress::finish();
 
     kernel_core_setup()
 }
@@ -59,7 +360,7 @@ pub unsafe extern "C" fn init() -> ! {
 ///
 /// This function should only ever be called once per core.
 pub(self) unsafe fn kernel_core_setup() -> ! {
-    crate::cpu::state::init(1000);
+    crate::cpu::state::init(crate::time::TIMER_FREQUENCY);
 
     // Ensure we enable interrupts prior to enabling the scheduler.
     crate::interrupts::enable();
@@ -96,100 +397,56 @@ fn print_boot_info() {
     } else {
         info!("Vendor              Unknown");
     }
+
+    features::log();
 }
 
 fn load_drivers() {
-    use crate::task::{AddressSpace, Priority, Task};
-    use elf::endian::AnyEndian;
-
-    #[limine::limine_tag]
-    static LIMINE_MODULES: limine::ModuleRequest = limine::ModuleRequest::new(crate::init::boot::LIMINE_REV);
-
     debug!("Unpacking kernel drivers...");
 
-    let Some(modules) = LIMINE_MODULES.get_response()
-    else {
-        warn!("Bootloader provided no modules; skipping driver loading.");
-        return
-    };
-
-    let modules = modules.modules();
-    trace!("Found modules: {:X?}", modules);
-
-    let Some(drivers_module) = modules.iter().find(|module| module.path().ends_with("drivers"))
-    else {
-        panic!("no drivers module found")
+    let Some(drivers_module) = modules::get("drivers") else {
+        warn!("No drivers module found; skipping driver loading.");
+        return;
     };
 
     let archive = tar_no_std::TarArchiveRef::new(drivers_module.data());
-    archive
-        .entries()
-        .filter_map(|entry| {
-            debug!("Attempting to parse driver blob: {}", entry.filename());
-
-            match elf::ElfBytes::<AnyEndian>::minimal_parse(entry.data()) {
-                Ok(elf) => Some((entry, elf)),
-                Err(err) => {
-                    error!("Failed to parse driver blob into ELF: {:?}", err);
-                    None
-                }
+    for entry in archive.entries() {
+        let filename = entry.filename();
+        let name = filename.as_str().rsplit('/').next().unwrap_or_default().split('.').next().unwrap_or_default();
+        if features::is_driver(name) && !features::is_enabled(name) {
+            debug!("Skipping driver blob {}, as its feature is disabled.", filename);
+            continue;
+        }
+
+        debug!("Attempting to parse driver blob: {}", entry.filename());
+
+        let data = crate::task::ElfData::Memory(alloc::boxed::Box::from(entry.data()));
+        match crate::task::loader::load_bytes(entry.data(), data, &[entry.filename().as_str()], &[]) {
+            Ok(task) => {
+                task.set_name(entry.filename().as_str());
+                crate::task::spawn(task);
             }
-        })
-        .for_each(|(entry, elf)| {
-            // Get and copy the ELF segments into a small box.
-            let Some(segments_copy) = elf.segments().map(|segments| segments.into_iter().collect())
-            else {
-                error!("ELF has no segments.");
-                return
-            };
-
-            // Safety: In-place transmutation of initialized bytes for the purpose of copying safely.
-            // let (_, archive_data, _) = unsafe { entry.data().align_to::<MaybeUninit<u8>>() };
-            trace!("Allocating ELF data into memory...");
-            let elf_data = alloc::boxed::Box::from(entry.data());
-            trace!("ELF data allocated into memory.");
-
-            let Ok((Some(shdrs), Some(_))) = elf.section_headers_with_strtab()
-            else {
-                panic!("Error retrieving ELF relocation metadata.")
-            };
-
-            let load_offset = crate::task::MIN_LOAD_OFFSET;
-
-            trace!("Processing relocations localized to fault page.");
-            let mut relas = alloc::vec::Vec::with_capacity(shdrs.len());
-
-            shdrs
-                .iter()
-                .filter(|shdr| shdr.sh_type == elf::abi::SHT_RELA)
-                .flat_map(|shdr| elf.section_data_as_relas(&shdr).unwrap())
-                .for_each(|rela| {
-                    use crate::task::ElfRela;
-
-                    match rela.r_type {
-                        elf::abi::R_X86_64_RELATIVE => relas.push(ElfRela {
-                            address: Address::new(usize::try_from(rela.r_offset).unwrap()).unwrap(),
-                            value: load_offset + usize::try_from(rela.r_addend).unwrap(),
-                        }),
-
-                        _ => unimplemented!(),
-                    }
-                });
-
-            trace!("Finished processing relocations, pushing task.");
-
-            let task = Task::new(
-                Priority::Normal,
-                AddressSpace::new_userspace(),
-                load_offset,
-                elf.ehdr,
-                segments_copy,
-                relas,
-                crate::task::ElfData::Memory(elf_data),
-            );
-
-            crate::task::PROCESSES.lock().push_back(task);
-        });
+
+            Err(err) => error!("Failed to load driver {}: {:?}", entry.filename(), err),
+        }
+    }
+}
+
+/// Path of the first userspace task, loaded from the root filesystem (i.e. the initramfs) if it's present.
+const INIT_PATH: &str = "/sbin/init";
+
+fn spawn_init() {
+    use crate::task::loader::Error;
+
+    match crate::task::loader::load_file(INIT_PATH, &[INIT_PATH], &[]) {
+        Ok(task) => {
+            task.set_name("init");
+            crate::task::spawn(task);
+        }
+
+        Err(Error::Vfs { err: crate::vfs::Error::NotFound }) => debug!("No {} found; skipping init.", INIT_PATH),
+        Err(err) => error!("Failed to load {}: {:?}", INIT_PATH, err),
+    }
 }
 
 fn setup_smp() {
@@ -0,0 +1,75 @@
+//! Modules loaded alongside the kernel by the bootloader (i.e. the driver archive, an initrd, or config files).
+//!
+//! Module data lies in memory the bootloader hands to the kernel, which is never reclaimed, so modules outlive boot
+//! and can be mapped read-only into privileged tasks rather than copied. The bootloader's descriptions of them are
+//! reclaimed however, so they're recorded by [`init`] beforehand.
+
+use alloc::{string::String, vec::Vec};
+use libsys::{page_size, Address, Frame};
+
+#[derive(Debug)]
+pub struct Module {
+    /// File name of the module (the last component of its path).
+    name: String,
+    data: &'static [u8],
+}
+
+impl Module {
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[inline]
+    pub const fn data(&self) -> &'static [u8] {
+        self.data
+    }
+
+    /// Frames holding the module's data, or `None` if it isn't page-aligned (so can't be mapped in place).
+    ///
+    /// #### Remark
+    ///
+    /// The last frame is mapped whole, so the bytes following the module's data within it are exposed too.
+    pub fn frames(&self) -> Option<Vec<Address<Frame>>> {
+        let start = self.data.as_ptr().addr().checked_sub(crate::mem::HHDM.address().get())?;
+        let end = start + self.data.len();
+
+        (start..end).step_by(page_size()).map(Address::new).collect()
+    }
+}
+
+static MODULES: spin::Once<Vec<Module>> = spin::Once::new();
+
+/// Records the modules provided by the bootloader.
+///
+/// #### Remark
+///
+/// This must be called before bootloader memory is reclaimed.
+pub fn init() {
+    #[limine::limine_tag]
+    static LIMINE_MODULES: limine::ModuleRequest = limine::ModuleRequest::new(crate::init::boot::LIMINE_REV);
+
+    MODULES.call_once(|| {
+        let Some(response) = LIMINE_MODULES.get_response() else {
+            warn!("Bootloader provided no modules.");
+            return Vec::new();
+        };
+
+        response
+            .modules()
+            .iter()
+            .map(|module| {
+                let path = module.path();
+                let name = path.rsplit('/').next().unwrap_or(path);
+                trace!("Found boot module {:?}: {:#X} bytes", name, module.data().len());
+
+                Module { name: String::from(name), data: module.data() }
+            })
+            .collect()
+    });
+}
+
+/// Returns the module named `name`, if the bootloader provided it.
+pub fn get(name: &str) -> Option<&'static Module> {
+    MODULES.get()?.iter().find(|module| module.name() == name)
+}
@@ -1,8 +1,54 @@
+/// Flow control on the serial console, by which the kernel pauses the sender while its receive buffer is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlowControl {
+    None,
+    /// RTS/CTS, which requires the lines to be wired through.
+    Hardware,
+    /// XON/XOFF, sent in-band.
+    Software,
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct Parameters {
     pub smp: bool,
     pub symbolinfo: bool,
     pub low_memory: bool,
+    pub crashdump: bool,
+    /// Whether boot progress is drawn on the framebuffer (see [`crate::init::progress`]).
+    pub splash: bool,
+    /// Number of pre-zeroed frames to keep pooled for userspace allocations.
+    pub zero_pool: usize,
+    pub zero_on_free: bool,
+    pub serial_flow: FlowControl,
+    /// Open descriptors each task may hold (see [`crate::task::quota`]).
+    pub max_descriptors: usize,
+    /// Address space regions each task may hold (see [`crate::task::quota`]).
+    pub max_regions: usize,
+    /// Threads each process may hold (see [`crate::task::quota`]).
+    pub max_threads: usize,
+    /// Threads of each process which may block on futexes (see [`crate::task::quota`]).
+    pub max_futex_waiters: usize,
+    /// Threads of each process which may sleep on timers (see [`crate::task::quota`]).
+    pub max_sleep_timers: usize,
+    /// Highest priority a task may select in the fair class.
+    pub fair_ceiling: crate::task::Priority,
+    /// Highest priority a task may select in the real-time classes.
+    pub realtime_ceiling: crate::task::Priority,
+    /// Kernel tasks which run queued work (see [`crate::task::workqueue`]).
+    pub workers: usize,
+}
+
+fn parse_priority(name: &str) -> Option<crate::task::Priority> {
+    use crate::task::Priority;
+
+    match name {
+        "idle" => Some(Priority::Idle),
+        "low" => Some(Priority::Low),
+        "normal" => Some(Priority::Normal),
+        "high" => Some(Priority::High),
+        "critical" => Some(Priority::Critical),
+        _ => None,
+    }
 }
 
 impl Parameters {
@@ -19,6 +65,56 @@ impl Parameters {
                 "--nosmp" => me.smp = false,
                 "--symbolinfo" => me.symbolinfo = true,
                 "--lomem" => me.low_memory = true,
+                "--crashdump" => me.crashdump = true,
+                "--splash" => me.splash = true,
+                "--zero-on-free" => me.zero_on_free = true,
+                other if let Some(count) = other.strip_prefix("--zeropool=") => match count.parse() {
+                    Ok(count) => me.zero_pool = count,
+                    Err(_) => warn!("Invalid zero pool size: {:?}", count),
+                },
+                other if let Some(count) = other.strip_prefix("--maxfds=") => match count.parse() {
+                    Ok(count) => me.max_descriptors = count,
+                    Err(_) => warn!("Invalid descriptor quota: {:?}", count),
+                },
+                other if let Some(count) = other.strip_prefix("--maxregions=") => match count.parse() {
+                    Ok(count) => me.max_regions = count,
+                    Err(_) => warn!("Invalid region quota: {:?}", count),
+                },
+                other if let Some(count) = other.strip_prefix("--maxthreads=") => match count.parse() {
+                    Ok(count) => me.max_threads = count,
+                    Err(_) => warn!("Invalid thread quota: {:?}", count),
+                },
+                other if let Some(count) = other.strip_prefix("--maxfutexwaiters=") => match count.parse() {
+                    Ok(count) => me.max_futex_waiters = count,
+                    Err(_) => warn!("Invalid futex waiter quota: {:?}", count),
+                },
+                other if let Some(count) = other.strip_prefix("--maxtimers=") => match count.parse() {
+                    Ok(count) => me.max_sleep_timers = count,
+                    Err(_) => warn!("Invalid sleep timer quota: {:?}", count),
+                },
+                other if let Some(count) = other.strip_prefix("--workers=") => match count.parse() {
+                    Ok(count) if count > 0 => me.workers = count,
+                    _ => warn!("Invalid worker count: {:?}", count),
+                },
+                other if let Some(name) = other.strip_prefix("--fairceiling=") => match parse_priority(name) {
+                    Some(priority) => me.fair_ceiling = priority,
+                    None => warn!("Invalid fair priority ceiling: {:?}", name),
+                },
+                other if let Some(name) = other.strip_prefix("--rtceiling=") => match parse_priority(name) {
+                    Some(priority) => me.realtime_ceiling = priority,
+                    None => warn!("Invalid real-time priority ceiling: {:?}", name),
+                },
+                other if let Some(directives) = other.strip_prefix("--log=") => {
+                    if let Err(err) = crate::logging::filter::set(directives) {
+                        warn!("Invalid log directives {:?}: {:?}", directives, err);
+                    }
+                }
+                other if let Some(flow) = other.strip_prefix("--serialflow=") => match flow {
+                    "none" => me.serial_flow = FlowControl::None,
+                    "rtscts" => me.serial_flow = FlowControl::Hardware,
+                    "xonxoff" => me.serial_flow = FlowControl::Software,
+                    flow => warn!("Invalid serial flow control: {:?}", flow),
+                },
 
                 // ignore
                 "" => {}
@@ -33,7 +129,24 @@ impl Parameters {
 
 impl Default for Parameters {
     fn default() -> Self {
-        Self { smp: true, symbolinfo: false, low_memory: false }
+        Self {
+            smp: true,
+            symbolinfo: false,
+            low_memory: false,
+            crashdump: false,
+            splash: false,
+            zero_pool: 64,
+            zero_on_free: false,
+            serial_flow: FlowControl::Hardware,
+            max_descriptors: 256,
+            max_regions: 1024,
+            max_threads: 64,
+            max_futex_waiters: 64,
+            max_sleep_timers: 64,
+            fair_ceiling: crate::task::Priority::High,
+            realtime_ceiling: crate::task::Priority::High,
+            workers: 2,
+        }
     }
 }
 
@@ -46,3 +159,12 @@ pub fn parse(cmdline: &str) {
 pub fn get() -> &'static Parameters {
     PARAMETERS.get().expect("parameters have not been parsed")
 }
+
+/// Returns the parsed parameters, if they have been parsed yet.
+///
+/// #### Remark
+///
+/// Prefer [`get`] outside of contexts which may run before parameter parsing (e.g. the panic handler).
+pub fn try_get() -> Option<&'static Parameters> {
+    PARAMETERS.get()
+}
@@ -0,0 +1,195 @@
+//! Boot progress: each stage of boot is stamped with the uptime it's reached at, which is logged, retained as a coarse
+//! boot profile in sysfs (`kernel/boot/stages`), and (with `--splash`) drawn as a progress bar on the framebuffer.
+//!
+//! The bar gains a segment as each stage is reached. Once boot finishes, it's redrawn with each segment's width in
+//! proportion to the time its stage took.
+//!
+//! #### Remark
+//!
+//! The system clock is only started once ACPI is up, so stages reached before then are stamped at zero, and their
+//! time is counted against the first stage reached after it.
+
+use crate::{fb::Rect, interrupts::InterruptCell};
+use alloc::string::String;
+use core::{fmt::Write, time::Duration};
+use spin::Mutex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// The physical memory manager, kernel image, and kernel page tables.
+    Memory,
+    /// ACPI, the system clock, and the early devices (framebuffer, input, and serial).
+    Platform,
+    Pci,
+    Drivers,
+    /// Additional cores are started.
+    Smp,
+    /// Bootloader memory is reclaimed, ahead of entering the scheduler to run the first tasks.
+    Init,
+}
+
+const STAGE_COUNT: usize = Stage::Init as usize + 1;
+
+impl Stage {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Memory => "memory",
+            Self::Platform => "platform",
+            Self::Pci => "pci",
+            Self::Drivers => "drivers",
+            Self::Smp => "smp",
+            Self::Init => "init",
+        }
+    }
+
+    /// Color of the stage's segment of the progress bar.
+    const fn color(self) -> (u8, u8, u8) {
+        match self {
+            Self::Memory => (0x3B, 0x82, 0xF6),
+            Self::Platform => (0x8B, 0x5C, 0xF6),
+            Self::Pci => (0xEC, 0x48, 0x99),
+            Self::Drivers => (0xF5, 0x9E, 0x0B),
+            Self::Smp => (0x10, 0xB9, 0x81),
+            Self::Init => (0xE5, 0xE7, 0xEB),
+        }
+    }
+
+    const fn from_index(index: usize) -> Self {
+        match index {
+            0 => Self::Memory,
+            1 => Self::Platform,
+            2 => Self::Pci,
+            3 => Self::Drivers,
+            4 => Self::Smp,
+            _ => Self::Init,
+        }
+    }
+}
+
+struct Progress {
+    /// Uptime each stage was reached at.
+    reached: [Option<Duration>; STAGE_COUNT],
+    finished: Option<Duration>,
+}
+
+static PROGRESS: InterruptCell<Mutex<Progress>> =
+    InterruptCell::new(Mutex::new(Progress { reached: [None; STAGE_COUNT], finished: None }));
+
+impl Progress {
+    /// Time each reached stage took, up to the next stage reached (or the end of boot), if it's known yet.
+    fn durations(&self) -> impl Iterator<Item = (Stage, Duration, Option<Duration>)> + '_ {
+        self.reached.iter().enumerate().filter_map(|(index, reached)| {
+            let start = (*reached)?;
+            let end = self.reached[(index + 1)..].iter().find_map(|reached| *reached).or(self.finished);
+
+            Some((Stage::from_index(index), start, end.map(|end| end.saturating_sub(start))))
+        })
+    }
+}
+
+/// Height of the progress bar, and its distance from the bottom of the framebuffer, in pixels.
+const BAR_HEIGHT: usize = 6;
+const BAR_MARGIN: usize = 48;
+
+/// Draws the progress bar: a segment per reached stage, of equal widths while booting, or in proportion to each stage's
+/// time once it has finished.
+fn draw(progress: &Progress) {
+    crate::fb::with(|framebuffer| {
+        let width = framebuffer.width() / 2;
+        let Some(y) = framebuffer.height().checked_sub(BAR_MARGIN + BAR_HEIGHT) else { return };
+        let track = Rect::new((framebuffer.width() - width) / 2, y, width, BAR_HEIGHT);
+
+        framebuffer.fill(track, framebuffer.encode(0x1F, 0x29, 0x37));
+
+        let started = progress.reached.iter().find_map(|reached| *reached).unwrap_or_default();
+        let total = progress.finished.map(|finished| finished.saturating_sub(started));
+        let mut x = track.x;
+        for (stage, _, duration) in progress.durations() {
+            let segment_width = match (total, duration) {
+                (Some(total), Some(duration)) if !total.is_zero() => {
+                    usize::try_from((duration.as_nanos() * u128::try_from(width).unwrap()) / total.as_nanos())
+                        .unwrap_or(width)
+                }
+
+                _ => width / STAGE_COUNT,
+            };
+            let segment_width = segment_width.min(track.right() - x);
+
+            let (red, green, blue) = stage.color();
+            framebuffer.fill(Rect::new(x, track.y, segment_width, BAR_HEIGHT), framebuffer.encode(red, green, blue));
+            x += segment_width;
+        }
+
+        framebuffer.flush();
+    });
+}
+
+/// Stamps `stage` as reached now.
+pub fn reach(stage: Stage) {
+    let now = crate::time::uptime_nowait();
+    debug!("Boot stage `{}` reached at {:?}.", stage.as_str(), now);
+
+    PROGRESS.with(|progress| {
+        let mut progress = progress.lock();
+        progress.reached[stage as usize].get_or_insert(now);
+
+        if crate::init::get().splash {
+            draw(&progress);
+        }
+    });
+}
+
+/// Stamps the end of boot, logging the time each stage took.
+pub fn finish() {
+    let now = crate::time::uptime_nowait();
+
+    PROGRESS.with(|progress| {
+        let mut progress = progress.lock();
+        if progress.finished.is_some() {
+            return;
+        }
+        progress.finished = Some(now);
+
+        for (stage, _, duration) in progress.durations() {
+            info!("Boot stage {:<10} took {:?}", stage.as_str(), duration.unwrap_or_default());
+        }
+        info!("Boot finished in {:?}.", now);
+
+        if crate::init::get().splash {
+            draw(&progress);
+        }
+    });
+}
+
+/// Renders the boot profile: when each stage was reached, and the time it took.
+fn render() -> String {
+    PROGRESS.with(|progress| {
+        let progress = progress.lock();
+
+        let mut string = String::new();
+        for (stage, start, duration) in progress.durations() {
+            write!(
+                string,
+                "{:<10} start={:>6}.{:03}ms",
+                stage.as_str(),
+                start.as_millis(),
+                start.subsec_micros() % 1000
+            )
+            .unwrap();
+            match duration {
+                Some(duration) => {
+                    writeln!(string, " took={:>6}.{:03}ms", duration.as_millis(), duration.subsec_micros() % 1000)
+                        .unwrap()
+                }
+                None => writeln!(string, " took=?").unwrap(),
+            }
+        }
+
+        string
+    })
+}
+
+/// Publishes the boot profile to sysfs (`kernel/boot/stages`), rendered as it's read.
+pub fn publish() {
+    crate::sysfs::set("kernel/boot", "stages", crate::sysfs::Value::Dynamic(alloc::boxed::Box::new(render)));
+}
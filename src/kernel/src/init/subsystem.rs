@@ -0,0 +1,68 @@
+//! Subsystem initialization: each subsystem declares what it requires, and boot runs them in a deterministic
+//! topological order (see [`libkernel::deps`]), logging the time each one took.
+//!
+//! A requirement names either a subsystem, or a facility some subsystem provides (so a requirement like `clock` is
+//! met by whichever subsystems provide it, without naming them). Subsystems which are ready at the same time run in
+//! the order they were declared.
+
+use super::progress::{self, Stage};
+use libkernel::deps::{self, Dependent, MAX_DEPENDENTS};
+
+pub struct Subsystem {
+    pub name: &'static str,
+    /// Facilities the subsystem provides, besides its name.
+    pub provides: &'static [&'static str],
+    /// Subsystems (or facilities) which must be initialized first.
+    pub requires: &'static [&'static str],
+    /// Boot stage the subsystem belongs to, reached as its first subsystem runs.
+    pub stage: Stage,
+    pub init: fn(),
+}
+
+impl Dependent for Subsystem {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn provides(&self) -> &'static [&'static str] {
+        self.provides
+    }
+
+    fn requires(&self) -> &'static [&'static str] {
+        self.requires
+    }
+}
+
+/// Initializes `subsystems` in dependency order.
+///
+/// ### Panics
+///
+/// If a requirement can't be met, or subsystems require one another, as there's no order to boot them in.
+pub fn run(subsystems: &[Subsystem]) {
+    let order = match deps::sort(subsystems) {
+        Ok(order) => order,
+        Err(deps::Error::TooManyDependents { count }) => {
+            panic!("{count} subsystems were declared, but at most {MAX_DEPENDENTS} can be sorted")
+        }
+        Err(deps::Error::MissingProvider { dependent: subsystem, requirement }) => {
+            panic!("subsystem `{subsystem}` requires `{requirement}`, but no subsystem provides it")
+        }
+        Err(deps::Error::Cycle { dependent: subsystem, requirement }) => {
+            panic!("subsystem `{subsystem}` requires `{requirement}`, which can only be initialized after it")
+        }
+    };
+
+    let mut stage = None;
+    for subsystem in order[..subsystems.len()].iter().map(|&index| &subsystems[index]) {
+        if stage != Some(subsystem.stage) {
+            progress::reach(subsystem.stage);
+            stage = Some(subsystem.stage);
+        }
+
+        let start = crate::time::uptime_nowait();
+        (subsystem.init)();
+        let took = crate::time::uptime_nowait().saturating_sub(start);
+
+        debug!("Initialized subsystem `{}` in {:?}.", subsystem.name, took);
+    }
+}
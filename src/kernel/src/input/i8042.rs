@@ -0,0 +1,277 @@
+//! PS/2 controller (i8042), with a keyboard on the first port and a mouse on the auxiliary port.
+//!
+//! #### Remark
+//!
+//! Device interrupts aren't yet routed through the I/O APIC, so the controller is polled by a kernel task, which
+//! halts between polls (and so samples at the scheduler's tick rate).
+
+use super::{push, push_key, KeyCode, KeyEvent};
+use libsys::syscall::input::{EventKind, AXIS_X, AXIS_Y};
+use port::{PortAddress, ReadWritePort};
+
+crate::error_impl! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Error {
+        /// The controller didn't become ready within the polling limit.
+        Timeout => None,
+        /// A device didn't acknowledge a command.
+        NoAck { response: u8 } => None,
+        /// The controller's ports are claimed by another driver.
+        Resource { err: crate::mem::io::resource::Error } => Some(err)
+    }
+}
+
+const DATA_PORT: PortAddress = 0x60;
+const COMMAND_PORT: PortAddress = 0x64;
+
+const STATUS_OUTPUT_FULL: u8 = 1 << 0;
+const STATUS_INPUT_FULL: u8 = 1 << 1;
+/// The byte in the output buffer came from the auxiliary device.
+const STATUS_AUX_DATA: u8 = 1 << 5;
+
+const CMD_READ_CONFIG: u8 = 0x20;
+const CMD_WRITE_CONFIG: u8 = 0x60;
+const CMD_DISABLE_AUX: u8 = 0xA7;
+const CMD_ENABLE_AUX: u8 = 0xA8;
+const CMD_DISABLE_KEYBOARD: u8 = 0xAD;
+const CMD_ENABLE_KEYBOARD: u8 = 0xAE;
+const CMD_WRITE_AUX: u8 = 0xD4;
+
+const CONFIG_KEYBOARD_IRQ: u8 = 1 << 0;
+const CONFIG_AUX_IRQ: u8 = 1 << 1;
+const CONFIG_AUX_CLOCK_DISABLED: u8 = 1 << 5;
+
+const MOUSE_SET_DEFAULTS: u8 = 0xF6;
+const MOUSE_ENABLE_REPORTING: u8 = 0xF4;
+const ACK: u8 = 0xFA;
+
+/// Number of status polls before a controller operation is abandoned.
+const POLL_LIMIT: usize = 100_000;
+
+const EXTENDED_PREFIX: u8 = 0xE0;
+const RELEASE_BIT: u8 = 1 << 7;
+
+struct Controller {
+    data: ReadWritePort<u8>,
+    command: ReadWritePort<u8>,
+}
+
+impl Controller {
+    /// ### Safety
+    ///
+    /// Only one `Controller` may issue commands at a time.
+    const unsafe fn new() -> Self {
+        Self { data: ReadWritePort::new(DATA_PORT), command: ReadWritePort::new(COMMAND_PORT) }
+    }
+
+    fn status(&self) -> u8 {
+        self.command.read()
+    }
+
+    fn wait_writable(&self) -> Result<()> {
+        (0..POLL_LIMIT).find(|_| (self.status() & STATUS_INPUT_FULL) == 0).map(|_| ()).ok_or(Error::Timeout)
+    }
+
+    fn wait_readable(&self) -> Result<()> {
+        (0..POLL_LIMIT).find(|_| (self.status() & STATUS_OUTPUT_FULL) > 0).map(|_| ()).ok_or(Error::Timeout)
+    }
+
+    fn command(&mut self, command: u8) -> Result<()> {
+        self.wait_writable()?;
+        self.command.write(command);
+
+        Ok(())
+    }
+
+    fn write_data(&mut self, value: u8) -> Result<()> {
+        self.wait_writable()?;
+        self.data.write(value);
+
+        Ok(())
+    }
+
+    fn read_data(&mut self) -> Result<u8> {
+        self.wait_readable()?;
+
+        Ok(self.data.read())
+    }
+
+    /// Sends `command` to the auxiliary device, and waits for its acknowledgement.
+    fn aux_command(&mut self, command: u8) -> Result<()> {
+        self.command(CMD_WRITE_AUX)?;
+        self.write_data(command)?;
+
+        match self.read_data()? {
+            ACK => Ok(()),
+            response => Err(Error::NoAck { response }),
+        }
+    }
+
+    /// Configures the controller for polling, returning whether an auxiliary device is present.
+    fn configure(&mut self) -> Result<bool> {
+        self.command(CMD_DISABLE_KEYBOARD)?;
+        self.command(CMD_DISABLE_AUX)?;
+
+        // Discard anything left in the output buffer.
+        while (self.status() & STATUS_OUTPUT_FULL) > 0 {
+            self.data.read();
+        }
+
+        self.command(CMD_READ_CONFIG)?;
+        let config = self.read_data()? & !(CONFIG_KEYBOARD_IRQ | CONFIG_AUX_IRQ);
+        self.command(CMD_WRITE_CONFIG)?;
+        self.write_data(config)?;
+
+        self.command(CMD_ENABLE_KEYBOARD)?;
+        self.command(CMD_ENABLE_AUX)?;
+
+        // The auxiliary clock remains disabled if there is no auxiliary port.
+        self.command(CMD_READ_CONFIG)?;
+        let has_aux = (self.read_data()? & CONFIG_AUX_CLOCK_DISABLED) == 0;
+        if has_aux {
+            self.aux_command(MOUSE_SET_DEFAULTS)?;
+            self.aux_command(MOUSE_ENABLE_REPORTING)?;
+        }
+
+        Ok(has_aux)
+    }
+}
+
+/// Decodes scancode set 1 (as translated by the controller) into key events.
+#[derive(Default)]
+struct KeyboardDecoder {
+    extended: bool,
+}
+
+impl KeyboardDecoder {
+    fn feed(&mut self, byte: u8) -> Option<KeyEvent> {
+        if byte == EXTENDED_PREFIX {
+            self.extended = true;
+            return None;
+        }
+
+        let pressed = (byte & RELEASE_BIT) == 0;
+        let code = byte & !RELEASE_BIT;
+
+        let code = if core::mem::take(&mut self.extended) {
+            match code {
+                0x1D => KeyCode::RIGHT_CTRL,
+                0x38 => KeyCode::RIGHT_ALT,
+                0x48 => KeyCode::UP,
+                0x49 => KeyCode::PAGE_UP,
+                0x4B => KeyCode::LEFT,
+                0x4D => KeyCode::RIGHT,
+                0x50 => KeyCode::DOWN,
+                0x51 => KeyCode::PAGE_DOWN,
+                0x1C => KeyCode::ENTER,
+                _ => return None,
+            }
+        } else {
+            KeyCode(code)
+        };
+
+        Some(KeyEvent { code, pressed })
+    }
+}
+
+/// Decodes the standard 3-byte PS/2 mouse packet.
+#[derive(Default)]
+struct MouseDecoder {
+    packet: [u8; 3],
+    len: usize,
+    buttons: u8,
+}
+
+impl MouseDecoder {
+    const ALWAYS_SET: u8 = 1 << 3;
+    const X_SIGN: u8 = 1 << 4;
+    const Y_SIGN: u8 = 1 << 5;
+    const OVERFLOW: u8 = 0b11 << 6;
+    const BUTTONS: u8 = 0b111;
+
+    fn feed(&mut self, byte: u8) {
+        // Resynchronize on a byte which can't begin a packet.
+        if self.len == 0 && (byte & Self::ALWAYS_SET) == 0 {
+            return;
+        }
+
+        self.packet[self.len] = byte;
+        self.len += 1;
+        if self.len < self.packet.len() {
+            return;
+        }
+        self.len = 0;
+
+        let [flags, x, y] = self.packet;
+        if (flags & Self::OVERFLOW) > 0 {
+            return;
+        }
+
+        let dx = i32::from(x) - if (flags & Self::X_SIGN) > 0 { 0x100 } else { 0 };
+        let dy = i32::from(y) - if (flags & Self::Y_SIGN) > 0 { 0x100 } else { 0 };
+        if dx != 0 {
+            push(EventKind::Motion, AXIS_X, dx);
+        }
+        if dy != 0 {
+            // PS/2 reports upward motion as positive.
+            push(EventKind::Motion, AXIS_Y, -dy);
+        }
+
+        let buttons = flags & Self::BUTTONS;
+        let changed = buttons ^ self.buttons;
+        for button in (0..3u16).filter(|button| (changed & (1 << button)) > 0) {
+            push(EventKind::Button, button, i32::from((buttons & (1 << button)) > 0));
+        }
+        self.buttons = buttons;
+    }
+}
+
+/// Configures the controller, and spawns the task which polls it.
+pub fn init() -> Result<()> {
+    use crate::{
+        mem::io::resource::{self, Kind, Space},
+        task::{Priority, Task},
+    };
+
+    for port in [DATA_PORT, COMMAND_PORT] {
+        resource::claim(Space::Port, usize::from(port), 1, "i8042", Kind::Exclusive)
+            .map_err(|err| Error::Resource { err })?;
+    }
+
+    // Safety: The polling task isn't spawned until configuration is complete.
+    let mut controller = unsafe { Controller::new() };
+
+    let has_aux = controller.configure()?;
+    info!("PS/2 controller configured (auxiliary device: {})", has_aux);
+
+    extern "C" fn poll_task() -> ! {
+        // Safety: Once configured, the controller is only accessed by this task, which only reads from it.
+        let controller = unsafe { Controller::new() };
+        let mut keyboard = KeyboardDecoder::default();
+        let mut mouse = MouseDecoder::default();
+
+        loop {
+            loop {
+                let status = controller.status();
+                if (status & STATUS_OUTPUT_FULL) == 0 {
+                    break;
+                }
+
+                let byte = controller.data.read();
+                if (status & STATUS_AUX_DATA) > 0 {
+                    mouse.feed(byte);
+                } else if let Some(event) = keyboard.feed(byte) {
+                    push_key(event);
+                }
+            }
+
+            crate::interrupts::wait();
+        }
+    }
+
+    let task = Task::kernel(poll_task, core::num::NonZeroUsize::new(0x4000).unwrap(), Priority::Normal);
+    task.set_name("i8042");
+    crate::task::spawn(task);
+
+    Ok(())
+}
@@ -0,0 +1,345 @@
+//! Keymaps, which assign characters to key codes, and the translation of key events through them.
+//!
+//! ### Keymap files
+//!
+//! Alternate keymaps are loaded from text files, one directive per line (`#` begins a comment):
+//!
+//! ```text
+//! key <code> <plain> [<shift> [<altgr> [<altgr+shift>]]]
+//! compose <accent> <base> <result>
+//! ```
+//!
+//! Each action is a single character, `U+XXXX` (i.e. `U+0023` for a literal `#`), `dead:<accent>` for a dead key,
+//! or `-` to leave the level unassigned (so it falls back to the level beneath). Keys which a file doesn't mention
+//! keep their assignment from the US keymap.
+
+use super::{KeyCode, KeyEvent};
+use alloc::{collections::BTreeMap, string::String, sync::Arc};
+use spin::RwLock;
+
+crate::error_impl! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Error {
+        Vfs { err: crate::vfs::Error } => Some(err),
+        /// The keymap file isn't valid UTF-8.
+        InvalidUtf8 => None,
+        /// A directive of the keymap file is malformed.
+        Syntax { line: usize } => None,
+        NoSuchKeymap => None
+    }
+}
+
+/// Number of distinct key codes a keymap can assign.
+const KEY_COUNT: usize = 128;
+
+/// Name of the built-in keymap.
+pub const DEFAULT_KEYMAP: &str = "us";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Char(char),
+    /// Combines with the following character, per the keymap's compose table.
+    Dead(char),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(usize)]
+enum Level {
+    Plain,
+    Shift,
+    AltGr,
+    AltGrShift,
+}
+
+pub struct Keymap {
+    levels: [[Option<Action>; KEY_COUNT]; 4],
+    /// Compositions of dead key accents with base characters, keyed by `(accent, base)`.
+    compose: BTreeMap<(char, char), char>,
+}
+
+impl Keymap {
+    /// Returns the US (QWERTY) keymap.
+    pub fn us() -> Self {
+        let mut keymap = Self { levels: [[None; KEY_COUNT]; 4], compose: BTreeMap::new() };
+
+        keymap.set_row(KeyCode(2), "1234567890-=", "!@#$%^&*()_+");
+        keymap.set_row(KeyCode(16), "qwertyuiop[]", "QWERTYUIOP{}");
+        keymap.set_row(KeyCode(30), "asdfghjkl;'`", "ASDFGHJKL:\"~");
+        keymap.set_row(KeyCode(43), "\\zxcvbnm,./", "|ZXCVBNM<>?");
+
+        for (code, char) in [
+            (KeyCode::ESCAPE, '\x1B'),
+            (KeyCode::BACKSPACE, '\x08'),
+            (KeyCode::TAB, '\t'),
+            (KeyCode::ENTER, '\n'),
+            (KeyCode::SPACE, ' '),
+        ] {
+            keymap.set(code, Level::Plain, Some(Action::Char(char)));
+            keymap.set(code, Level::Shift, Some(Action::Char(char)));
+        }
+
+        keymap
+    }
+
+    fn set(&mut self, code: KeyCode, level: Level, action: Option<Action>) {
+        self.levels[level as usize][usize::from(code.0)] = action;
+    }
+
+    fn set_row(&mut self, first: KeyCode, plain: &str, shift: &str) {
+        for (offset, (plain, shift)) in plain.chars().zip(shift.chars()).enumerate() {
+            let code = KeyCode(first.0 + u8::try_from(offset).unwrap());
+
+            self.set(code, Level::Plain, Some(Action::Char(plain)));
+            self.set(code, Level::Shift, Some(Action::Char(shift)));
+        }
+    }
+
+    /// Parses a keymap file (see the [module documentation](self)), on top of the US keymap.
+    pub fn parse(source: &str) -> Result<Self> {
+        fn parse_action(token: &str) -> Option<Option<Action>> {
+            let parse_char = |token: &str| {
+                let mut chars = token.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(char), None) => Some(char),
+                    _ => token.strip_prefix("U+").and_then(|hex| char::from_u32(u32::from_str_radix(hex, 16).ok()?)),
+                }
+            };
+
+            if token == "-" {
+                Some(None)
+            } else if let Some(accent) = token.strip_prefix("dead:") {
+                Some(Some(Action::Dead(parse_char(accent)?)))
+            } else {
+                Some(Some(Action::Char(parse_char(token)?)))
+            }
+        }
+
+        let mut keymap = Self::us();
+
+        for (index, line) in source.lines().enumerate() {
+            let syntax = Error::Syntax { line: index + 1 };
+            let mut tokens = line.split('#').next().unwrap().split_whitespace();
+
+            match tokens.next() {
+                None => {}
+
+                Some("key") => {
+                    let code = tokens.next().and_then(|code| code.parse::<u8>().ok()).ok_or(syntax)?;
+                    if usize::from(code) >= KEY_COUNT {
+                        return Err(syntax);
+                    }
+
+                    let levels = [Level::Plain, Level::Shift, Level::AltGr, Level::AltGrShift];
+                    for (level, token) in levels.into_iter().zip(tokens.by_ref()) {
+                        keymap.set(KeyCode(code), level, parse_action(token).ok_or(syntax)?);
+                    }
+                }
+
+                Some("compose") => {
+                    let mut chars = tokens.by_ref().map(|token| match parse_action(token) {
+                        Some(Some(Action::Char(char))) => Some(char),
+                        _ => None,
+                    });
+                    let (Some(Some(accent)), Some(Some(base)), Some(Some(result))) =
+                        (chars.next(), chars.next(), chars.next())
+                    else {
+                        return Err(syntax);
+                    };
+
+                    keymap.compose.insert((accent, base), result);
+                }
+
+                Some(_) => return Err(syntax),
+            }
+
+            if tokens.next().is_some() {
+                return Err(syntax);
+            }
+        }
+
+        Ok(keymap)
+    }
+
+    /// Reads and parses the keymap file at `path`.
+    pub fn load(path: &str) -> Result<Self> {
+        let node = crate::vfs::lookup(crate::vfs::ROOT, path).map_err(|err| Error::Vfs { err })?;
+
+        let mut source = alloc::vec![0u8; node.metadata().size];
+        let len = node.read(0, &mut source).map_err(|err| Error::Vfs { err })?;
+        source.truncate(len);
+
+        Self::parse(core::str::from_utf8(&source).map_err(|_| Error::InvalidUtf8)?)
+    }
+
+    fn action(&self, code: KeyCode, level: Level) -> Option<Action> {
+        let actions = &self.levels[level as usize];
+
+        // Keys without an assignment on a shifted level fall back to the level beneath it.
+        actions.get(usize::from(code.0)).copied().flatten().or_else(|| match level {
+            Level::Shift | Level::AltGr => self.action(code, Level::Plain),
+            Level::AltGrShift => self.action(code, Level::AltGr),
+            Level::Plain => None,
+        })
+    }
+}
+
+static KEYMAPS: RwLock<BTreeMap<String, Arc<Keymap>>> = RwLock::new(BTreeMap::new());
+
+/// Returns the keymap registered as `name`; the US keymap is always available as [`DEFAULT_KEYMAP`].
+pub fn get(name: &str) -> Result<Arc<Keymap>> {
+    if let Some(keymap) = KEYMAPS.read().get(name) {
+        return Ok(keymap.clone());
+    }
+
+    if name == DEFAULT_KEYMAP {
+        Ok(KEYMAPS.write().entry(String::from(name)).or_insert_with(|| Arc::new(Keymap::us())).clone())
+    } else {
+        Err(Error::NoSuchKeymap)
+    }
+}
+
+/// Loads the keymap file at `path`, registering it as `name` (replacing any keymap of that name).
+pub fn load(name: &str, path: &str) -> Result<Arc<Keymap>> {
+    let keymap = Arc::new(Keymap::load(path)?);
+    KEYMAPS.write().insert(String::from(name), keymap.clone());
+
+    Ok(keymap)
+}
+
+bitflags::bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Modifiers: u8 {
+        const SHIFT = 1 << 0;
+        const CTRL = 1 << 1;
+        const ALT = 1 << 2;
+        const ALT_GR = 1 << 3;
+        const CAPS_LOCK = 1 << 4;
+    }
+}
+
+/// Whether a consumer (i.e. a TTY) receives key events as they are, or translated into characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Raw,
+    Translated,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Output {
+    Nothing,
+    Key(KeyEvent),
+    Char(char),
+    /// A dead key followed by a character it doesn't compose with, which are both emitted.
+    Chars(char, char),
+}
+
+/// Per-consumer translation state: held modifiers, and any pending dead key.
+pub struct Translator {
+    keymap: Arc<Keymap>,
+    mode: Mode,
+    modifiers: Modifiers,
+    dead: Option<char>,
+}
+
+impl Translator {
+    pub fn new(keymap: Arc<Keymap>, mode: Mode) -> Self {
+        Self { keymap, mode, modifiers: Modifiers::empty(), dead: None }
+    }
+
+    #[inline]
+    pub const fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+        self.dead = None;
+    }
+
+    pub fn set_keymap(&mut self, keymap: Arc<Keymap>) {
+        self.keymap = keymap;
+        self.dead = None;
+    }
+
+    #[inline]
+    pub const fn modifiers(&self) -> Modifiers {
+        self.modifiers
+    }
+
+    /// Feeds a key event through the translator.
+    ///
+    /// #### Remark
+    ///
+    /// Modifier state is tracked in both modes, so switching modes mid-chord behaves as expected.
+    pub fn feed(&mut self, event: KeyEvent) -> Output {
+        let modifier = match event.code {
+            KeyCode::LEFT_SHIFT | KeyCode::RIGHT_SHIFT => Some(Modifiers::SHIFT),
+            KeyCode::LEFT_CTRL | KeyCode::RIGHT_CTRL => Some(Modifiers::CTRL),
+            KeyCode::LEFT_ALT => Some(Modifiers::ALT),
+            KeyCode::RIGHT_ALT => Some(Modifiers::ALT_GR),
+            _ => None,
+        };
+
+        if let Some(modifier) = modifier {
+            self.modifiers.set(modifier, event.pressed);
+        } else if event.code == KeyCode::CAPS_LOCK && event.pressed {
+            self.modifiers.toggle(Modifiers::CAPS_LOCK);
+        }
+
+        if self.mode == Mode::Raw {
+            return Output::Key(event);
+        } else if modifier.is_some() || !event.pressed {
+            return Output::Nothing;
+        }
+
+        let level = match (self.modifiers.contains(Modifiers::ALT_GR), self.modifiers.contains(Modifiers::SHIFT)) {
+            (false, false) => Level::Plain,
+            (false, true) => Level::Shift,
+            (true, false) => Level::AltGr,
+            (true, true) => Level::AltGrShift,
+        };
+
+        match self.keymap.action(event.code, level) {
+            None => Output::Nothing,
+
+            Some(Action::Dead(accent)) => match self.dead.replace(accent) {
+                // Pressing a dead key twice produces its accent.
+                Some(pending) if pending == accent => {
+                    self.dead = None;
+                    Output::Char(accent)
+                }
+                Some(pending) => Output::Char(pending),
+                None => Output::Nothing,
+            },
+
+            Some(Action::Char(char)) => {
+                let char = self.apply_modifiers(char);
+
+                match self.dead.take() {
+                    Some(accent) => match self.keymap.compose.get(&(accent, char)) {
+                        Some(composed) => Output::Char(*composed),
+                        None => Output::Chars(accent, char),
+                    },
+                    None => Output::Char(char),
+                }
+            }
+        }
+    }
+
+    fn apply_modifiers(&self, char: char) -> char {
+        let char = if self.modifiers.contains(Modifiers::CAPS_LOCK) && char.is_alphabetic() {
+            // Caps Lock inverts the case chosen by Shift.
+            let swapped = if char.is_lowercase() { char.to_uppercase().next() } else { char.to_lowercase().next() };
+            swapped.unwrap_or(char)
+        } else {
+            char
+        };
+
+        if self.modifiers.contains(Modifiers::CTRL) && char.is_ascii_alphabetic() {
+            // Ctrl + letter produces the corresponding C0 control character.
+            char::from(u8::try_from(char).unwrap() & 0x1F)
+        } else {
+            char
+        }
+    }
+}
@@ -0,0 +1,124 @@
+//! Input devices, and the translation of their key events into characters.
+//!
+//! Drivers push their events into a single queue, which userspace reads from `/dev/input`.
+
+#[cfg(target_arch = "x86_64")]
+pub mod i8042;
+pub mod keymap;
+pub mod serial;
+#[cfg(feature = "virtio")]
+pub mod virtio;
+
+use crate::interrupts::InterruptCell;
+use alloc::collections::VecDeque;
+use libsys::syscall::input::{Event, EventKind};
+use spin::Mutex;
+
+/// Position of a key, independent of layout.
+///
+/// Codes follow the PS/2 scancode set 1 make codes (so `1` is Escape, `2` the `1` key, and so on), which
+/// drivers for other protocols translate into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct KeyCode(pub u8);
+
+impl KeyCode {
+    pub const ESCAPE: Self = Self(1);
+    pub const BACKSPACE: Self = Self(14);
+    pub const TAB: Self = Self(15);
+    pub const ENTER: Self = Self(28);
+    pub const LEFT_CTRL: Self = Self(29);
+    pub const LEFT_SHIFT: Self = Self(42);
+    pub const RIGHT_SHIFT: Self = Self(54);
+    pub const LEFT_ALT: Self = Self(56);
+    pub const SPACE: Self = Self(57);
+    pub const CAPS_LOCK: Self = Self(58);
+    /// F1 through F10 are consecutive, from here.
+    pub const F1: Self = Self(59);
+    pub const F11: Self = Self(87);
+    pub const F12: Self = Self(88);
+    pub const RIGHT_CTRL: Self = Self(97);
+    /// Right Alt, which acts as AltGr on layouts with a third level.
+    pub const RIGHT_ALT: Self = Self(100);
+    pub const UP: Self = Self(103);
+    pub const PAGE_UP: Self = Self(104);
+    pub const LEFT: Self = Self(105);
+    pub const RIGHT: Self = Self(106);
+    pub const DOWN: Self = Self(108);
+    pub const PAGE_DOWN: Self = Self(109);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub code: KeyCode,
+    pub pressed: bool,
+}
+
+/// Number of events held before the oldest are discarded.
+const QUEUE_CAPACITY: usize = 256;
+
+static QUEUE: InterruptCell<Mutex<VecDeque<Event>>> = InterruptCell::new(Mutex::new(VecDeque::new()));
+
+/// Queues an event for userspace, discarding the oldest event if the queue is full.
+pub fn push(kind: EventKind, code: u16, value: i32) {
+    let event = Event { timestamp_ms: crate::time::coarse_ms(), kind: kind as u16, code, value };
+
+    QUEUE.with(|queue| {
+        let mut queue = queue.lock();
+        if queue.len() == QUEUE_CAPACITY {
+            queue.pop_front();
+        }
+
+        queue.push_back(event);
+    });
+}
+
+/// Queues a key event for userspace, and passes it to the active terminal.
+pub fn push_key(event: KeyEvent) {
+    push(EventKind::Key, u16::from(event.code.0), i32::from(event.pressed));
+    crate::tty::key(event);
+}
+
+/// Registers the input device node, `/dev/input`.
+pub fn init() -> crate::vfs::Result<()> {
+    crate::vfs::devfs::register("input", alloc::sync::Arc::new(Device))
+}
+
+struct Device;
+
+impl crate::vfs::Node for Device {
+    fn metadata(&self) -> crate::vfs::Metadata {
+        crate::vfs::Metadata {
+            kind: crate::vfs::NodeKind::Device,
+            size: 0,
+            mode: libsys::syscall::fs::Mode::from_bits_retain(0o440),
+            owner: crate::vfs::Owner::ROOT,
+            accessed: 0,
+            modified: 0,
+            created: 0,
+        }
+    }
+
+    /// Dequeues as many whole events as fit in `buf`.
+    fn read(&self, _offset: usize, buf: &mut [u8]) -> crate::vfs::Result<usize> {
+        const EVENT_LEN: usize = core::mem::size_of::<Event>();
+
+        if buf.len() < EVENT_LEN {
+            return Err(crate::vfs::Error::BufferTooSmall);
+        }
+
+        QUEUE.with(|queue| {
+            let mut queue = queue.lock();
+
+            let mut written = 0;
+            for record in buf.chunks_exact_mut(EVENT_LEN) {
+                let Some(event) = queue.pop_front() else { break };
+
+                // Safety: `record` is exactly the size of `Event`, and unaligned writes are used.
+                unsafe { record.as_mut_ptr().cast::<Event>().write_unaligned(event) };
+                written += EVENT_LEN;
+            }
+
+            Ok(written)
+        })
+    }
+}
@@ -0,0 +1,139 @@
+//! COM1, the 16550 UART of PC-compatibles, which is also the logging console (see [`crate::logging`]).
+//!
+//! Bytes are received on the UART's ISA interrupt, routed through the I/O APIC. Where it can't be routed, the
+//! receiver is polled instead, which at 1000Hz the 16550's receive FIFO covers for rates up to 115200 baud.
+
+use crate::{
+    arch::x86_64::structures::ioapic,
+    interrupts::{irq, InterruptCell},
+    mem::io::resource::{self, Kind, Space},
+};
+use port::{PortAddress, ReadWritePort};
+use spin::Mutex;
+
+crate::error_impl! {
+    #[derive(Debug)]
+    pub enum Error {
+        Resource { err: resource::Error } => Some(err),
+        Vfs { err: crate::vfs::Error } => Some(err),
+        Irq { err: irq::Error } => Some(err),
+        IoApic { err: ioapic::Error } => Some(err),
+        /// The local core's APIC ID isn't yet known.
+        NoCore => None
+    }
+}
+
+const COM1: PortAddress = 0x3F8;
+/// ISA interrupt line of COM1.
+const COM1_IRQ: u8 = 4;
+const DATA_OFFSET: PortAddress = 0;
+const INTERRUPT_ENABLE_OFFSET: PortAddress = 1;
+const MODEM_CONTROL_OFFSET: PortAddress = 4;
+const LINE_STATUS_OFFSET: PortAddress = 5;
+/// Number of ports the UART decodes, from [`COM1`].
+const PORT_COUNT: usize = 8;
+
+const INTERRUPT_ENABLE_RX: u8 = 1 << 0;
+
+const LINE_STATUS_DATA_READY: u8 = 1 << 0;
+
+const MODEM_CONTROL_DTR: u8 = 1 << 0;
+const MODEM_CONTROL_RTS: u8 = 1 << 1;
+/// Gates the UART's interrupt line on PC-compatibles.
+const MODEM_CONTROL_OUT2: u8 = 1 << 3;
+
+struct Receiver {
+    data: ReadWritePort<u8>,
+    interrupt_enable: ReadWritePort<u8>,
+    modem_control: ReadWritePort<u8>,
+    line_status: ReadWritePort<u8>,
+}
+
+impl Receiver {
+    /// ### Safety
+    ///
+    /// Only one `Receiver` may exist at a time, and the UART must not be reconfigured while it does.
+    const unsafe fn new() -> Self {
+        Self {
+            data: ReadWritePort::new(COM1 + DATA_OFFSET),
+            interrupt_enable: ReadWritePort::new(COM1 + INTERRUPT_ENABLE_OFFSET),
+            modem_control: ReadWritePort::new(COM1 + MODEM_CONTROL_OFFSET),
+            line_status: ReadWritePort::new(COM1 + LINE_STATUS_OFFSET),
+        }
+    }
+
+    fn set_rx_interrupt(&mut self, enabled: bool) {
+        self.interrupt_enable.write(if enabled { INTERRUPT_ENABLE_RX } else { 0 });
+    }
+}
+
+impl super::Port for Receiver {
+    fn try_read(&mut self) -> Option<u8> {
+        ((self.line_status.read() & LINE_STATUS_DATA_READY) > 0).then(|| self.data.read())
+    }
+
+    fn set_rts(&mut self, asserted: bool) {
+        let rts = if asserted { MODEM_CONTROL_RTS } else { 0 };
+        self.modem_control.write(MODEM_CONTROL_DTR | MODEM_CONTROL_OUT2 | rts);
+    }
+
+    /// Sends `byte` through the logging console, so it isn't interleaved within a record.
+    fn send(&mut self, byte: u8) {
+        crate::logging::write_serial(&[byte]).ok();
+    }
+}
+
+// Safety: This is the only `Receiver`, and the UART isn't reconfigured elsewhere (the logging console only transmits).
+static RECEIVER: InterruptCell<Mutex<Receiver>> = InterruptCell::new(Mutex::new(unsafe { Receiver::new() }));
+
+fn transmit(bytes: &[u8]) -> bool {
+    crate::logging::write_serial(bytes).is_ok()
+}
+
+fn receive() -> bool {
+    RECEIVER.with(|receiver| super::receive(&mut *receiver.lock()))
+}
+
+/// Routes COM1's interrupt to a device vector on the local core, and enables the UART's receive interrupt.
+fn route_interrupt() -> Result<u64> {
+    let destination = crate::cpu::state::get_core_id().map_err(|_| Error::NoCore)?;
+    let (vector, id) =
+        irq::register_any("serial:ttyS0", alloc::boxed::Box::new(receive)).map_err(|err| Error::Irq { err })?;
+
+    RECEIVER.with(|receiver| receiver.lock().set_rx_interrupt(true));
+
+    if let Err(err) = ioapic::route_isa(COM1_IRQ, vector, destination) {
+        RECEIVER.with(|receiver| receiver.lock().set_rx_interrupt(false));
+        irq::unregister(vector, id).ok();
+
+        return Err(Error::IoApic { err });
+    }
+
+    Ok(vector)
+}
+
+/// Asserts the modem control lines, registers the serial TTY, and enables the UART's receive interrupt (or spawns the
+/// task which polls the receiver, if it can't be routed).
+pub fn init() -> Result<()> {
+    use super::Port;
+
+    resource::claim(Space::Port, usize::from(COM1), PORT_COUNT, "serial:ttyS0", Kind::Exclusive)
+        .map_err(|err| Error::Resource { err })?;
+
+    RECEIVER.with(|receiver| receiver.lock().set_rts(true));
+
+    super::register(transmit).map_err(|err| Error::Vfs { err })?;
+
+    match route_interrupt() {
+        Ok(vector) => debug!("Serial interrupt is routed to vector {:#X}.", vector),
+
+        Err(err) => {
+            debug!("Failed to route serial interrupt, so the receiver will be polled: {:?}", err);
+            super::spawn_poll(|| {
+                receive();
+            });
+        }
+    }
+
+    Ok(())
+}
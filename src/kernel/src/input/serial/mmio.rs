@@ -0,0 +1,252 @@
+//! Memory-mapped UARTs of RISC-V boards, found in the devicetree: the ns16550 (as on QEMU's `virt` machine), and the
+//! SiFive UART. Bytes are received on the UART's interrupt, which is routed through the PLIC.
+//!
+//! The UART is left at the baud rate and framing the firmware configured it with.
+
+use crate::{
+    arch::rv64::plic,
+    interrupts::{irq, InterruptCell},
+    mem::{
+        io::resource::{self, Kind, Space},
+        paging::TableEntryFlags,
+        vmap::Mapping,
+    },
+    platform::Device,
+};
+use spin::Mutex;
+
+crate::error_impl! {
+    #[derive(Debug)]
+    pub enum Error {
+        /// The platform describes no supported UART.
+        NotPresent => None,
+        NoRegisters => None,
+        NoInterrupt => None,
+        /// The UART's registers are accessed with a width other than 1 or 4 bytes.
+        UnsupportedWidth { width: u32 } => None,
+        Resource { err: resource::Error } => Some(err),
+        Vmap { err: crate::mem::vmap::Error } => Some(err),
+        Plic { err: plic::Error } => Some(err),
+        Irq { err: irq::Error } => Some(err),
+        Vfs { err: crate::vfs::Error } => Some(err)
+    }
+}
+
+const NS16550_COMPATIBLE: [&str; 2] = ["ns16550a", "ns16550"];
+const SIFIVE_COMPATIBLE: &str = "sifive,uart0";
+
+/// Register indices of the ns16550, which are spaced `1 << reg-shift` bytes apart.
+const NS16550_DATA: usize = 0;
+const NS16550_INTERRUPT_ENABLE: usize = 1;
+const NS16550_MODEM_CONTROL: usize = 4;
+const NS16550_LINE_STATUS: usize = 5;
+
+const NS16550_INTERRUPT_ENABLE_RX: u32 = 1 << 0;
+const NS16550_LINE_STATUS_DATA_READY: u32 = 1 << 0;
+const NS16550_LINE_STATUS_THR_EMPTY: u32 = 1 << 5;
+const NS16550_MODEM_CONTROL_DTR: u32 = 1 << 0;
+const NS16550_MODEM_CONTROL_RTS: u32 = 1 << 1;
+/// Gates the UART's interrupt output on some boards (as on PC-compatibles).
+const NS16550_MODEM_CONTROL_OUT2: u32 = 1 << 3;
+
+/// Register offsets of the SiFive UART.
+const SIFIVE_TXDATA: usize = 0x00;
+const SIFIVE_RXDATA: usize = 0x04;
+const SIFIVE_TXCTRL: usize = 0x08;
+const SIFIVE_RXCTRL: usize = 0x0C;
+const SIFIVE_INTERRUPT_ENABLE: usize = 0x10;
+
+/// Set in `txdata` while the transmit FIFO is full, and in `rxdata` while the receive FIFO is empty.
+const SIFIVE_FIFO_FLAG: u32 = 1 << 31;
+/// Enables the transmitter or receiver, in `txctrl` or `rxctrl`. The receive watermark (bits 16..19 of `rxctrl`) is
+/// left at 0, so its interrupt is raised whenever a byte is waiting.
+const SIFIVE_CTRL_ENABLE: u32 = 1 << 0;
+const SIFIVE_INTERRUPT_ENABLE_RXWM: u32 = 1 << 1;
+
+#[derive(Debug, Clone, Copy)]
+enum Model {
+    /// Registers are `1 << shift` bytes apart, and accessed `width` bytes at a time.
+    Ns16550 {
+        shift: u32,
+        width: u32,
+    },
+    Sifive,
+}
+
+impl Model {
+    /// Model of `device`, if it's a supported UART.
+    fn of(device: &Device) -> Option<Result<Self>> {
+        if NS16550_COMPATIBLE.iter().any(|compatible| device.is_compatible(compatible)) {
+            let shift = device.u32("reg-shift").unwrap_or(0);
+            let width = device.u32("reg-io-width").unwrap_or(1);
+
+            Some(match width {
+                1 | 4 => Ok(Self::Ns16550 { shift, width }),
+                width => Err(Error::UnsupportedWidth { width }),
+            })
+        } else if device.is_compatible(SIFIVE_COMPATIBLE) {
+            Some(Ok(Self::Sifive))
+        } else {
+            None
+        }
+    }
+}
+
+struct Uart {
+    mapping: Mapping,
+    model: Model,
+}
+
+impl Uart {
+    /// Offset and width of register `reg` (an index on the ns16550, or an offset on the SiFive UART).
+    fn locate(&self, reg: usize) -> (usize, u32) {
+        match self.model {
+            Model::Ns16550 { shift, width } => (reg << shift, width),
+            Model::Sifive => (reg, 4),
+        }
+    }
+
+    fn read(&self, reg: usize) -> u32 {
+        let (offset, width) = self.locate(reg);
+        assert!(offset + usize::try_from(width).unwrap() <= self.mapping.len());
+
+        let ptr = self.mapping.as_ptr().as_ptr();
+        // Safety: Offset lies within the mapping, and the UART's registers are aligned to their access width.
+        unsafe {
+            match width {
+                1 => u32::from(ptr.add(offset).read_volatile()),
+                _ => ptr.add(offset).cast::<u32>().read_volatile(),
+            }
+        }
+    }
+
+    fn write(&self, reg: usize, value: u32) {
+        let (offset, width) = self.locate(reg);
+        assert!(offset + usize::try_from(width).unwrap() <= self.mapping.len());
+
+        let ptr = self.mapping.as_ptr().as_ptr();
+        // Safety: Offset lies within the mapping, and the UART's registers are aligned to their access width.
+        unsafe {
+            match width {
+                1 => ptr.add(offset).write_volatile(value.to_le_bytes()[0]),
+                _ => ptr.add(offset).cast::<u32>().write_volatile(value),
+            }
+        }
+    }
+
+    /// Enables the UART's receive interrupt (and, on the SiFive UART, its transmitter and receiver).
+    fn enable_rx(&mut self) {
+        match self.model {
+            Model::Ns16550 { .. } => self.write(NS16550_INTERRUPT_ENABLE, NS16550_INTERRUPT_ENABLE_RX),
+
+            Model::Sifive => {
+                self.write(SIFIVE_TXCTRL, self.read(SIFIVE_TXCTRL) | SIFIVE_CTRL_ENABLE);
+                self.write(SIFIVE_RXCTRL, SIFIVE_CTRL_ENABLE);
+                self.write(SIFIVE_INTERRUPT_ENABLE, SIFIVE_INTERRUPT_ENABLE_RXWM);
+            }
+        }
+    }
+
+    /// Waits for room in the transmitter, then sends `byte`.
+    fn transmit(&mut self, byte: u8) {
+        match self.model {
+            Model::Ns16550 { .. } => {
+                while (self.read(NS16550_LINE_STATUS) & NS16550_LINE_STATUS_THR_EMPTY) == 0 {
+                    core::hint::spin_loop();
+                }
+
+                self.write(NS16550_DATA, u32::from(byte));
+            }
+
+            Model::Sifive => {
+                while (self.read(SIFIVE_TXDATA) & SIFIVE_FIFO_FLAG) > 0 {
+                    core::hint::spin_loop();
+                }
+
+                self.write(SIFIVE_TXDATA, u32::from(byte));
+            }
+        }
+    }
+}
+
+impl super::Port for Uart {
+    fn try_read(&mut self) -> Option<u8> {
+        match self.model {
+            Model::Ns16550 { .. } => ((self.read(NS16550_LINE_STATUS) & NS16550_LINE_STATUS_DATA_READY) > 0)
+                .then(|| self.read(NS16550_DATA).to_le_bytes()[0]),
+
+            // Reading `rxdata` dequeues the byte it holds, so the flag and data must be read together.
+            Model::Sifive => {
+                let rxdata = self.read(SIFIVE_RXDATA);
+                ((rxdata & SIFIVE_FIFO_FLAG) == 0).then_some(rxdata.to_le_bytes()[0])
+            }
+        }
+    }
+
+    /// The SiFive UART has no modem control lines, so only the ns16550's RTS is driven.
+    fn set_rts(&mut self, asserted: bool) {
+        if let Model::Ns16550 { .. } = self.model {
+            let rts = if asserted { NS16550_MODEM_CONTROL_RTS } else { 0 };
+            self.write(NS16550_MODEM_CONTROL, NS16550_MODEM_CONTROL_DTR | NS16550_MODEM_CONTROL_OUT2 | rts);
+        }
+    }
+
+    fn send(&mut self, byte: u8) {
+        self.transmit(byte);
+    }
+}
+
+static UART: spin::Once<InterruptCell<Mutex<Uart>>> = spin::Once::new();
+
+fn transmit(bytes: &[u8]) -> bool {
+    UART.get()
+        .map(|uart| {
+            uart.with(|uart| {
+                let mut uart = uart.lock();
+                for byte in bytes {
+                    uart.transmit(*byte);
+                }
+            });
+        })
+        .is_some()
+}
+
+/// Maps the first UART the platform describes, registers the serial TTY, and enables its receive interrupt.
+pub fn init() -> Result<()> {
+    use super::Port;
+
+    let platform = crate::platform::get().ok_or(Error::NotPresent)?;
+    let (device, model) =
+        platform.devices.iter().find_map(|device| Some((device, Model::of(device)?))).ok_or(Error::NotPresent)?;
+    let model = model?;
+
+    let regs = device.regs.first().cloned().ok_or(Error::NoRegisters)?;
+    // The PLIC takes a single cell, naming the source.
+    let source = device.interrupts.first().copied().ok_or(Error::NoInterrupt)?;
+
+    resource::claim(Space::Memory, regs.start, regs.len(), "serial:ttyS0", Kind::Exclusive)
+        .map_err(|err| Error::Resource { err })?;
+    let mapping =
+        crate::mem::vmap::map(regs.start, regs.len(), TableEntryFlags::MMIO).map_err(|err| Error::Vmap { err })?;
+
+    let uart = UART.call_once(|| InterruptCell::new(Mutex::new(Uart { mapping, model })));
+    uart.with(|uart| uart.lock().set_rts(true));
+
+    super::register(transmit).map_err(|err| Error::Vfs { err })?;
+
+    let vector = plic::vector(source).map_err(|err| Error::Plic { err })?;
+    irq::register(
+        vector,
+        "serial:ttyS0",
+        irq::Sharing::Exclusive,
+        alloc::boxed::Box::new(|| UART.get().is_some_and(|uart| uart.with(|uart| super::receive(&mut *uart.lock())))),
+    )
+    .map_err(|err| Error::Irq { err })?;
+    plic::enable(source).map_err(|err| Error::Plic { err })?;
+
+    uart.with(|uart| uart.lock().enable_rx());
+
+    info!("Serial              {} ({:?}) at {:#X}, PLIC source {}", device.name, model, regs.start, source);
+
+    Ok(())
+}
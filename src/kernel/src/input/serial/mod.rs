@@ -0,0 +1,295 @@
+//! Serial console input, received from a UART and read from the serial TTY (`/dev/ttyS0`).
+//!
+//! Received bytes are buffered until read, and the sender is paused (with RTS/CTS or XON/XOFF, see
+//! [`FlowControl`]) while the buffer is nearly full, so bytes aren't dropped at low baud rates. The buffer is made of
+//! chunks taken from a [`Pool`], so the interrupt handler receives into it without allocating.
+//!
+//! The UART is COM1 on x86_64 (see [`com`]). On RISC-V, it's the devicetree's ns16550 or SiFive UART (see [`mmio`]),
+//! or else the SBI firmware's console (see [`sbi`]). Bytes are received on the UART's interrupt where it can be
+//! routed, and are otherwise polled for by a kernel task.
+
+#[cfg(target_arch = "x86_64")]
+pub mod com;
+#[cfg(target_arch = "riscv64")]
+pub mod mmio;
+#[cfg(target_arch = "riscv64")]
+pub mod sbi;
+
+use crate::{
+    init::FlowControl,
+    interrupts::InterruptCell,
+    mem::alloc::mempool::{Pool, Pooled},
+};
+use core::{
+    num::NonZeroUsize,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+};
+use spin::Mutex;
+
+const XON: u8 = 0x11;
+const XOFF: u8 = 0x13;
+
+/// Number of bytes each chunk of the buffer holds.
+const CHUNK_LEN: usize = 64;
+/// Number of chunks buffered before further bytes are dropped.
+const CHUNK_COUNT: usize = 64;
+/// Number of bytes buffered before further bytes are dropped.
+const CAPACITY: usize = CHUNK_LEN * CHUNK_COUNT;
+/// Fill level at which the sender is paused, leaving room for bytes already in flight.
+const HIGH_WATERMARK: usize = CAPACITY * 3 / 4;
+/// Fill level at which a paused sender is resumed.
+const LOW_WATERMARK: usize = CAPACITY / 4;
+
+/// Bytes received into a chunk of the buffer.
+struct Chunk {
+    len: usize,
+    bytes: [u8; CHUNK_LEN],
+}
+
+/// Chunks of received bytes, oldest first.
+struct Received {
+    chunks: [Option<Pooled<Chunk>>; CHUNK_COUNT],
+    /// Index of the oldest chunk.
+    head: usize,
+    count: usize,
+    /// Bytes of the oldest chunk which have been read.
+    read: usize,
+    /// Bytes yet to be read.
+    len: usize,
+}
+
+impl Received {
+    const fn new() -> Self {
+        Self { chunks: [const { None }; CHUNK_COUNT], head: 0, count: 0, read: 0, len: 0 }
+    }
+
+    /// Appends `byte`, taking a chunk from `pool` if the newest is full. Returns whether there was room for it.
+    fn push(&mut self, pool: &'static Pool<Chunk>, byte: u8) -> bool {
+        let tail = (self.head + self.count + CHUNK_COUNT - 1) % CHUNK_COUNT;
+        let has_room = self.count > 0 && self.chunks[tail].as_ref().is_some_and(|chunk| chunk.len < CHUNK_LEN);
+
+        let tail = if has_room {
+            tail
+        } else {
+            if self.count == CHUNK_COUNT {
+                return false;
+            }
+            let Some(mut chunk) = pool.take() else { return false };
+            chunk.len = 0;
+
+            let tail = (self.head + self.count) % CHUNK_COUNT;
+            self.chunks[tail] = Some(chunk);
+            self.count += 1;
+
+            tail
+        };
+
+        let chunk = self.chunks[tail].as_mut().unwrap();
+        chunk.bytes[chunk.len] = byte;
+        chunk.len += 1;
+        self.len += 1;
+
+        true
+    }
+
+    /// Moves as many bytes as fit into `buf`, returning each chunk to its pool once it's read.
+    fn pop_into(&mut self, buf: &mut [u8]) -> usize {
+        let mut written = 0;
+
+        while written < buf.len() && self.count > 0 {
+            let chunk = self.chunks[self.head].as_ref().unwrap();
+            let len = (chunk.len - self.read).min(buf.len() - written);
+            buf[written..(written + len)].copy_from_slice(&chunk.bytes[self.read..(self.read + len)]);
+            written += len;
+            self.read += len;
+            self.len -= len;
+
+            if self.read == chunk.len {
+                self.chunks[self.head] = None;
+                self.head = (self.head + 1) % CHUNK_COUNT;
+                self.count -= 1;
+                self.read = 0;
+            }
+        }
+
+        written
+    }
+}
+
+static RECEIVED: InterruptCell<Mutex<Received>> = InterruptCell::new(Mutex::new(Received::new()));
+/// Chunks the buffer is made of, created before the UART's interrupt is routed.
+static CHUNKS: spin::Once<&'static Pool<Chunk>> = spin::Once::new();
+/// Bytes dropped as the buffer was full, which are reported as it's read (as the interrupt handler can't log).
+static DROPPED: AtomicU64 = AtomicU64::new(0);
+/// Whether the sender has been paused.
+static PAUSED: AtomicBool = AtomicBool::new(false);
+
+/// A UART the console is received from.
+trait Port {
+    fn try_read(&mut self) -> Option<u8>;
+
+    /// Asserts or deasserts RTS, if the UART has modem control lines.
+    fn set_rts(&mut self, asserted: bool);
+
+    /// Sends a flow control byte to the sender.
+    fn send(&mut self, byte: u8);
+
+    /// Pauses or resumes the sender, according to `flow`.
+    fn set_paused(&mut self, flow: FlowControl, paused: bool) {
+        match flow {
+            FlowControl::None => {}
+            FlowControl::Hardware => self.set_rts(!paused),
+            FlowControl::Software => self.send(if paused { XOFF } else { XON }),
+        }
+
+        PAUSED.store(paused, Ordering::Relaxed);
+    }
+}
+
+/// Writes bytes to the serial console, returning whether it's present.
+type Transmit = fn(&[u8]) -> bool;
+
+/// Drains `port` into the buffer, pausing or resuming the sender as the buffer fills and empties. Returns whether any
+/// bytes were received.
+fn receive(port: &mut impl Port) -> bool {
+    let Some(&pool) = CHUNKS.get() else { return false };
+
+    let (received, fill) = RECEIVED.with(|buffer| {
+        let mut buffer = buffer.lock();
+
+        let mut received = 0;
+        while let Some(byte) = port.try_read() {
+            received += 1;
+
+            if !buffer.push(pool, byte) {
+                DROPPED.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        (received, buffer.len)
+    });
+
+    let flow = crate::init::get().serial_flow;
+    let paused = PAUSED.load(Ordering::Relaxed);
+    if !paused && fill >= HIGH_WATERMARK {
+        port.set_paused(flow, true);
+    } else if paused && fill <= LOW_WATERMARK {
+        port.set_paused(flow, false);
+    }
+
+    received > 0
+}
+
+/// Drains the UART [`spawn_poll`] polls into the buffer.
+static POLL: spin::Once<fn()> = spin::Once::new();
+
+/// Spawns the task which polls the UART with `poll`, for UARTs whose interrupt can't be routed.
+fn spawn_poll(poll: fn()) {
+    use crate::task::{Priority, Task};
+
+    extern "C" fn poll_task() -> ! {
+        let poll = *POLL.get().unwrap();
+
+        loop {
+            poll();
+            crate::interrupts::wait();
+        }
+    }
+
+    POLL.call_once(|| poll);
+
+    let task = Task::kernel(poll_task, core::num::NonZeroUsize::new(0x4000).unwrap(), Priority::Normal);
+    task.set_name("serial");
+    crate::task::spawn(task);
+}
+
+/// Dequeues as many received bytes as fit in `buf`, returning how many were, without waiting.
+pub fn try_read(buf: &mut [u8]) -> usize {
+    let dropped = DROPPED.swap(0, Ordering::Relaxed);
+    if dropped > 0 {
+        warn!("Serial receive buffer was full; dropped {} bytes.", dropped);
+    }
+
+    RECEIVED.with(|received| received.lock().pop_into(buf))
+}
+
+/// Dequeues as many received bytes as fit in `buf`, waiting until at least one has been received.
+///
+/// #### Remark
+///
+/// The core halts between checks of the buffer, to be woken by the UART's interrupt (or the scheduler's tick, while
+/// the UART is polled). So, interrupts must be enabled.
+pub fn read(buf: &mut [u8]) -> usize {
+    loop {
+        let len = try_read(buf);
+        if len > 0 || buf.is_empty() {
+            return len;
+        }
+
+        crate::interrupts::wait();
+    }
+}
+
+/// Registers the serial TTY on the platform's UART.
+pub fn init() {
+    #[cfg(target_arch = "x86_64")]
+    if let Err(err) = com::init() {
+        warn!("Failed to initialize serial console: {:?}", err);
+    }
+
+    #[cfg(target_arch = "riscv64")]
+    match mmio::init() {
+        Ok(()) => {}
+        Err(mmio::Error::NotPresent) => {
+            if let Err(err) = sbi::init() {
+                warn!("Failed to initialize SBI serial console: {:?}", err);
+            }
+        }
+        Err(err) => warn!("Failed to initialize serial console: {:?}", err),
+    }
+}
+
+/// Registers the serial TTY, whose writes are sent with `transmit`.
+fn register(transmit: Transmit) -> crate::vfs::Result<()> {
+    CHUNKS.call_once(|| {
+        // Starts with a quarter of the buffer, growing as input arrives faster than it's read.
+        Pool::new("serial", NonZeroUsize::new(CHUNK_COUNT / 4).unwrap(), CHUNK_COUNT / 16, || Chunk {
+            len: 0,
+            bytes: [0; CHUNK_LEN],
+        })
+    });
+
+    crate::vfs::devfs::register("ttyS0", alloc::sync::Arc::new(Device { transmit }))
+}
+
+struct Device {
+    transmit: Transmit,
+}
+
+impl crate::vfs::Node for Device {
+    fn metadata(&self) -> crate::vfs::Metadata {
+        crate::vfs::Metadata {
+            kind: crate::vfs::NodeKind::Device,
+            size: 0,
+            mode: libsys::syscall::fs::Mode::from_bits_retain(0o620),
+            owner: crate::vfs::Owner::ROOT,
+            accessed: 0,
+            modified: 0,
+            created: 0,
+        }
+    }
+
+    /// Dequeues as many received bytes as fit in `buf`, without blocking.
+    fn read(&self, _offset: usize, buf: &mut [u8]) -> crate::vfs::Result<usize> {
+        Ok(try_read(buf))
+    }
+
+    /// Writes `buf` to the serial console.
+    fn write(&self, _offset: usize, buf: &[u8]) -> crate::vfs::Result<usize> {
+        if !(self.transmit)(buf) {
+            return Err(crate::vfs::Error::Unsupported);
+        }
+
+        Ok(buf.len())
+    }
+}
@@ -0,0 +1,63 @@
+//! The SBI firmware's console, for RISC-V boards whose UART isn't described (or is taken by the firmware).
+//!
+//! The legacy console extension is used, as it's the one implemented by every SBI firmware. It has no interrupt, so
+//! the console is polled.
+
+/// Extension IDs of the legacy console calls, which take their argument and return their result in `a0` (and may
+/// clobber `a1`).
+const CONSOLE_PUTCHAR: usize = 0x01;
+const CONSOLE_GETCHAR: usize = 0x02;
+
+fn legacy_call(extension: usize, arg: usize) -> isize {
+    let result: isize;
+
+    // Safety: The legacy console calls only move a byte through the firmware's console, and clobber no registers
+    //         other than those given to `asm!`.
+    unsafe {
+        core::arch::asm!(
+            "ecall",
+            inlateout("a0") arg => result,
+            lateout("a1") _,
+            in("a7") extension,
+            options(nostack)
+        );
+    }
+
+    result
+}
+
+struct Console;
+
+impl super::Port for Console {
+    /// `console_getchar` returns -1 while no byte is waiting.
+    fn try_read(&mut self) -> Option<u8> {
+        u8::try_from(legacy_call(CONSOLE_GETCHAR, 0)).ok()
+    }
+
+    /// The firmware's console has no modem control lines.
+    fn set_rts(&mut self, _: bool) {}
+
+    fn send(&mut self, byte: u8) {
+        legacy_call(CONSOLE_PUTCHAR, usize::from(byte));
+    }
+}
+
+fn transmit(bytes: &[u8]) -> bool {
+    use super::Port;
+
+    for byte in bytes {
+        Console.send(*byte);
+    }
+
+    true
+}
+
+/// Registers the serial TTY on the firmware's console, and spawns the task which polls it.
+pub fn init() -> crate::vfs::Result<()> {
+    super::register(transmit)?;
+    super::spawn_poll(|| {
+        super::receive(&mut Console);
+    });
+
+    Ok(())
+}
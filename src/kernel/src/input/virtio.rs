@@ -0,0 +1,179 @@
+//! virtio-input devices (i.e. QEMU's `virtio-keyboard-pci` and `virtio-mouse-pci`), bound by the driver framework (see
+//! [`crate::driver`]) to virtio PCI functions of the input device type.
+//!
+//! Devices report Linux evdev events, each into a buffer the driver offers on the event queue. Key codes below 128 are
+//! the same as [`KeyCode`]s, and the mouse buttons, relative axes, and wheel map onto [`EventKind`]s directly; other
+//! events (i.e. absolute axes of tablets) are discarded.
+//!
+//! #### Remark
+//!
+//! The event queue is set up without an interrupt, so it's polled by a kernel task, which halts between polls (as the
+//! i8042 driver does).
+
+use super::{push, push_key, KeyCode, KeyEvent};
+use crate::{
+    driver::{self, Driver, Match},
+    mem::io::{
+        dma::DmaBuffer,
+        pci::{Device, Standard},
+    },
+    virtio::{self, Segment, Transport, Virtqueue},
+};
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use libsys::syscall::input::EventKind;
+use spin::Mutex;
+
+/// Index of the event queue (the status queue, for LEDs, is left unused).
+const EVENT_QUEUE: u16 = 0;
+/// Most entries of the event queue, each holding a buffer for one event.
+const QUEUE_SIZE: u16 = 64;
+
+/// Bytes of each event: its type, code, and value.
+const EVENT_LEN: usize = 8;
+
+/// Event types.
+const EV_KEY: u16 = 0x01;
+const EV_REL: u16 = 0x02;
+
+/// Relative axes.
+const REL_X: u16 = 0x00;
+const REL_Y: u16 = 0x01;
+const REL_WHEEL: u16 = 0x08;
+
+/// Code of the left mouse button, which the right and middle buttons follow (in the order of [`EventKind::Button`]'s
+/// codes).
+const BTN_LEFT: u16 = 0x110;
+
+/// Devices being polled.
+static DEVICES: Mutex<Vec<Arc<VirtioInput>>> = Mutex::new(Vec::new());
+
+struct VirtioInput {
+    /// Declared before the queue, so the device is reset before the queue's rings are freed.
+    _transport: Transport,
+    queue: Virtqueue,
+    /// A buffer for each entry of the queue.
+    events: DmaBuffer,
+    /// Head descriptor of the chain offering each buffer, and so only touched by the polling task once it's spawned.
+    heads: Mutex<Box<[u16]>>,
+}
+
+impl VirtioInput {
+    fn segment(&self, slot: usize) -> Segment {
+        Segment {
+            address: self.events.physical() + (slot * EVENT_LEN),
+            len: u32::try_from(EVENT_LEN).unwrap(),
+            writable: true,
+        }
+    }
+
+    /// Pushes the events the device wrote into each used buffer, and offers it again.
+    fn poll(&self) {
+        if !self.queue.reap() {
+            return;
+        }
+
+        let mut heads = self.heads.lock();
+        for (slot, head) in heads.iter_mut().enumerate() {
+            let Some(len) = self.queue.take(*head) else { continue };
+
+            if usize::try_from(len).unwrap() >= EVENT_LEN {
+                let event = &self.events.as_slice()[(slot * EVENT_LEN)..((slot + 1) * EVENT_LEN)];
+                decode(
+                    u16::from_le_bytes([event[0], event[1]]),
+                    u16::from_le_bytes([event[2], event[3]]),
+                    i32::from_le_bytes([event[4], event[5], event[6], event[7]]),
+                );
+            }
+
+            match self.queue.submit(&[self.segment(slot)]) {
+                Ok(next) => *head = next,
+                Err(err) => warn!("Failed to offer virtio-input event buffer {}: {:?}", slot, err),
+            }
+        }
+    }
+}
+
+/// Pushes the evdev event of `kind`, `code`, and `value`, if it has an equivalent.
+fn decode(kind: u16, code: u16, value: i32) {
+    match (kind, code) {
+        (EV_KEY, code) if code < 128 => {
+            // Autorepeats (a value of 2) are reported as further presses, as the PS/2 keyboard's typematic makes are.
+            push_key(KeyEvent { code: KeyCode(u8::try_from(code).unwrap()), pressed: value != 0 });
+        }
+
+        (EV_KEY, code) if (BTN_LEFT..(BTN_LEFT + 3)).contains(&code) => {
+            push(EventKind::Button, code - BTN_LEFT, i32::from(value != 0));
+        }
+
+        // Both evdev and `/dev/input` take downward motion, and scrolling away from the user, as positive.
+        (EV_REL, REL_X | REL_Y) => push(EventKind::Motion, code, value),
+        (EV_REL, REL_WHEEL) => push(EventKind::Scroll, 0, value),
+
+        _ => {}
+    }
+}
+
+fn init_device(device: &mut Device<Standard>) -> virtio::Result<VirtioInput> {
+    let transport = Transport::new(device)?;
+    transport.negotiate(0, 0)?;
+    let queue = transport.queue(EVENT_QUEUE, QUEUE_SIZE)?;
+    let events =
+        DmaBuffer::new(usize::from(queue.size()) * EVENT_LEN).map_err(|err| virtio::Error::Allocation { err })?;
+
+    let mut input = VirtioInput { _transport: transport, queue, events, heads: Mutex::new(Box::new([])) };
+
+    let heads = (0..usize::from(input.queue.size()))
+        .map(|slot| input.queue.submit(&[input.segment(slot)]))
+        .collect::<virtio::Result<Box<[u16]>>>()?;
+    input.heads = Mutex::new(heads);
+
+    input._transport.finish();
+
+    Ok(input)
+}
+
+fn probe(device: &mut Device<Standard>) -> driver::Result<driver::Instance> {
+    let input = init_device(device).map_err(|err| {
+        warn!("Failed to initialize virtio-input device ({}): {:?}", device.bdf(), err);
+        match err {
+            virtio::Error::MissingCapability { .. } => driver::Error::Unsupported,
+            _ => driver::Error::Device,
+        }
+    })?;
+
+    info!("virtio-input device ({}): {} event buffers", device.bdf(), input.heads.lock().len());
+
+    let input = Arc::new(input);
+    DEVICES.lock().push(input.clone());
+
+    static POLL_TASK: spin::Once = spin::Once::new();
+    POLL_TASK.call_once(spawn_poll_task);
+
+    Ok(Box::new(input))
+}
+
+fn spawn_poll_task() {
+    use crate::task::{Priority, Task};
+
+    extern "C" fn poll_task() -> ! {
+        loop {
+            for input in DEVICES.lock().iter() {
+                input.poll();
+            }
+
+            crate::interrupts::wait();
+        }
+    }
+
+    let task = Task::kernel(poll_task, core::num::NonZeroUsize::new(0x4000).unwrap(), Priority::Normal);
+    task.set_name("virtio-input");
+    crate::task::spawn(task);
+}
+
+static DRIVER: Driver =
+    Driver { name: "virtio-input", matches: &[Match::Id { vendor: virtio::VENDOR_ID, device: 0x1052 }], probe };
+
+/// Registers the virtio-input driver, to be offered the virtio input devices.
+pub fn register() {
+    driver::register(&DRIVER);
+}
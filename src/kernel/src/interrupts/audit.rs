@@ -0,0 +1,143 @@
+//! Instrumentation which records the longest windows for which interrupts were disabled.
+//!
+//! Windows are measured in timestamp counter cycles, and attributed to the site which opened them: either the
+//! caller of [`super::without`] (and so also [`super::InterruptCell`] borrows), or the entry of an interrupt.
+
+use alloc::vec::Vec;
+use core::{
+    panic::Location,
+    sync::atomic::{AtomicU64, Ordering},
+};
+use spin::Mutex;
+
+/// Number of distinct sites for which worst-case windows are kept.
+const MAX_RECORDS: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Site {
+    Caller(&'static Location<'static>),
+    Interrupt { vector: u64 },
+}
+
+impl core::fmt::Display for Site {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Site::Caller(location) => write!(f, "{location}"),
+            Site::Interrupt { vector } => write!(f, "interrupt {vector:#X}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Record {
+    pub site: Site,
+    pub cycles: u64,
+}
+
+static RECORDS: Mutex<[Option<Record>; MAX_RECORDS]> = Mutex::new([None; MAX_RECORDS]);
+/// Shortest window held in the (full) record table, so shorter windows can be discarded without locking.
+static THRESHOLD: AtomicU64 = AtomicU64::new(0);
+
+#[inline]
+fn timestamp() -> u64 {
+    #[cfg(target_arch = "x86_64")]
+    {
+        core::arch::x86_64::_rdtsc()
+    }
+
+    #[cfg(target_arch = "riscv64")]
+    {
+        let time: u64;
+        // Safety: Reading the `time` CSR has no side effects.
+        unsafe { core::arch::asm!("rdtime {}", out(reg) time, options(nostack, nomem)) };
+        time
+    }
+}
+
+/// An open interrupts-off window.
+#[must_use]
+pub struct Window {
+    site: Site,
+    start: u64,
+}
+
+impl Window {
+    /// Opens a window attributed to the caller.
+    #[inline]
+    #[track_caller]
+    pub fn caller() -> Self {
+        Self { site: Site::Caller(Location::caller()), start: timestamp() }
+    }
+
+    /// Opens a window attributed to the entry of interrupt `vector`.
+    #[inline]
+    pub fn interrupt(vector: u64) -> Self {
+        Self { site: Site::Interrupt { vector }, start: timestamp() }
+    }
+
+    /// Closes the window, recording its length if it is among the worst seen.
+    #[inline]
+    pub fn close(self) {
+        record(self.site, timestamp().wrapping_sub(self.start));
+    }
+}
+
+fn record(site: Site, cycles: u64) {
+    if cycles <= THRESHOLD.load(Ordering::Relaxed) {
+        return;
+    }
+
+    // The table may already be held by whatever this window interrupted, so never wait on it.
+    let Some(mut records) = RECORDS.try_lock() else { return };
+
+    if let Some(record) = records.iter_mut().flatten().find(|record| record.site == site) {
+        record.cycles = record.cycles.max(cycles);
+    } else if let Some(slot) = records.iter_mut().min_by_key(|slot| slot.map_or(0, |record| record.cycles))
+        && slot.map_or(true, |record| record.cycles < cycles)
+    {
+        *slot = Some(Record { site, cycles });
+    }
+
+    let threshold = records.iter().try_fold(u64::MAX, |min, slot| slot.map(|record| min.min(record.cycles)));
+    THRESHOLD.store(threshold.unwrap_or(0), Ordering::Relaxed);
+}
+
+/// Returns the recorded worst-case windows, longest first.
+pub fn worst() -> Vec<Record> {
+    let mut records = crate::interrupts::without(|| RECORDS.lock().iter().flatten().copied().collect::<Vec<_>>());
+    records.sort_unstable_by(|a, b| b.cycles.cmp(&a.cycles));
+
+    records
+}
+
+/// Clears the recorded windows, i.e. to measure a specific workload.
+pub fn reset() {
+    crate::interrupts::without(|| {
+        *RECORDS.lock() = [None; MAX_RECORDS];
+        THRESHOLD.store(0, Ordering::Relaxed);
+    });
+}
+
+/// Publishes the recorded windows to the attribute tree.
+pub fn publish() {
+    use alloc::string::String;
+    use core::fmt::Write;
+
+    crate::sysfs::set(
+        "kernel/irq_audit",
+        "worst",
+        crate::sysfs::Value::Dynamic(alloc::boxed::Box::new(|| {
+            worst().into_iter().fold(String::new(), |mut string, record| {
+                writeln!(string, "{:>12} {}", record.cycles, record.site).unwrap();
+                string
+            })
+        })),
+    );
+}
+
+/// Logs the recorded windows.
+pub fn log() {
+    for record in worst() {
+        info!("IRQs Off            {:>12} cycles @ {}", record.cycles, record.site);
+    }
+}
@@ -1,6 +1,8 @@
-use crate::{interrupts::exceptions::Exception, task::Registers};
+use crate::{
+    interrupts::exceptions::{Access, Exception, PageFault},
+    task::Registers,
+};
 use ia32utils::structures::idt::{InterruptStackFrame, PageFaultErrorCode, SelectorErrorCode};
-use libsys::{Address, Virtual};
 
 /// x86_64 exception wrapper type.
 #[repr(C)]
@@ -63,7 +65,7 @@ pub enum ArchException<'a> {
     ///     - Attempting to load the instruction TLB with a translation for a non-executable page.
     ///     - A protection cehck (privilege, r/w) failed.
     ///     - A reserved bit in the page directory table or entries is set to 1.
-    PageFault(&'a InterruptStackFrame, &'a Registers, PageFaultErrorCode, Address<Virtual>),
+    PageFault(&'a InterruptStackFrame, &'a Registers, PageFault),
 
     /// Occurs when the `fwait` or `wait` instruction (or any floating point instruction) is executed, and the
     /// following conditions are true:
@@ -107,14 +109,10 @@ impl From<ArchException<'_>> for Exception {
         use core::ptr::NonNull;
 
         match value {
-            ArchException::PageFault(isf, _, err, address) => Exception::new(
+            ArchException::PageFault(isf, _, fault) => Exception::new(
                 ExceptionKind::PageFault {
-                    ptr: NonNull::new(address.as_ptr()).unwrap(),
-                    reason: if err.contains(PageFaultErrorCode::PROTECTION_VIOLATION) {
-                        PageFaultReason::BadPermissions
-                    } else {
-                        PageFaultReason::NotMapped
-                    },
+                    ptr: NonNull::new(fault.address.as_ptr()).unwrap(),
+                    reason: if fault.present { PageFaultReason::BadPermissions } else { PageFaultReason::NotMapped },
                 },
                 NonNull::new(isf.instruction_pointer.as_mut_ptr::<u8>()).unwrap(),
                 NonNull::new(isf.stack_pointer.as_mut_ptr::<u8>()).unwrap(),
@@ -124,3 +122,29 @@ impl From<ArchException<'_>> for Exception {
         }
     }
 }
+
+impl PageFault {
+    /// Decodes the error code pushed for a page fault, alongside the faulting address held in `CR2`.
+    ///
+    /// #### Remark
+    ///
+    /// `CR2` is overwritten by any later page fault, so this must be called before anything else can fault.
+    pub fn capture(err: PageFaultErrorCode) -> Self {
+        let access = if err.contains(PageFaultErrorCode::INSTRUCTION_FETCH) {
+            Access::Execute
+        } else if err.contains(PageFaultErrorCode::CAUSED_BY_WRITE) {
+            Access::Write
+        } else {
+            Access::Read
+        };
+
+        Self {
+            address: crate::arch::x86_64::registers::control::CR2::read(),
+            access,
+            present: err.contains(PageFaultErrorCode::PROTECTION_VIOLATION),
+            user: err.contains(PageFaultErrorCode::USER_MODE),
+            malformed_table: err.contains(PageFaultErrorCode::MALFORMED_TABLE),
+            protection_key: err.contains(PageFaultErrorCode::PROTECTION_KEY),
+        }
+    }
+}
@@ -0,0 +1,76 @@
+//! The exception table: instructions designated as able to fault, each with the address execution resumes at when
+//! one does.
+//!
+//! Entries are emitted alongside their instructions (see [`fixup_entry`]) into the `.ex_table` section, which each
+//! linker script bounds with `__ex_table_start` and `__ex_table_end`. Each address is stored relative to its own field,
+//! so the table holds wherever the kernel is loaded.
+
+use libkernel::LinkerSymbol;
+use libsys::{Address, Virtual};
+
+/// Expands to assembly recording that, should the instruction at local label `$fault` fault, execution resumes at
+/// local label `$fixup` (i.e. `fixup_entry!("2b", "3f")`).
+///
+/// #### Remark
+///
+/// The fixup is reached with registers as they were when the instruction faulted, so the accessor must tell a fault
+/// from success by what the instruction leaves behind (i.e. `rcx` of a `rep movsb`).
+macro_rules! fixup_entry {
+    ($fault:literal, $fixup:literal) => {
+        concat!(
+            "
+            .pushsection .ex_table, \"a\"
+            .balign 4
+            .long ",
+            $fault,
+            " - .
+            .long ",
+            $fixup,
+            " - .
+            .popsection
+            "
+        )
+    };
+}
+
+pub(crate) use fixup_entry;
+
+#[repr(C)]
+struct Entry {
+    fault: i32,
+    fixup: i32,
+}
+
+impl Entry {
+    fn fault(&self) -> usize {
+        Self::resolve(&self.fault)
+    }
+
+    fn fixup(&self) -> usize {
+        Self::resolve(&self.fixup)
+    }
+
+    fn resolve(field: &i32) -> usize {
+        core::ptr::from_ref(field).addr().wrapping_add_signed(isize::try_from(*field).unwrap())
+    }
+}
+
+fn entries() -> &'static [Entry] {
+    extern "C" {
+        static __ex_table_start: LinkerSymbol;
+        static __ex_table_end: LinkerSymbol;
+    }
+
+    // Safety: The linker script bounds the table with these symbols, and the table is never written.
+    unsafe {
+        let start = __ex_table_start.as_ptr::<Entry>();
+        let end = __ex_table_end.as_ptr::<Entry>();
+
+        core::slice::from_raw_parts(start, end.sub_ptr(start))
+    }
+}
+
+/// Returns the address to resume at should the instruction at `ip` fault, if it's designated as able to.
+pub fn search(ip: Address<Virtual>) -> Option<Address<Virtual>> {
+    entries().iter().find(|entry| entry.fault() == ip.get()).map(|entry| Address::new_truncate(entry.fixup()))
+}
@@ -1,23 +1,74 @@
 mod arch;
 pub use arch::*;
 
+pub mod fixup;
+mod oops;
 mod page_fault;
+pub use page_fault::{Access, PageFault};
+
+use libsys::{Address, Virtual};
+
+/// How the context which raised an exception continues.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    /// The context resumes where it left off.
+    Resume,
+    /// The current task is terminated, and the next scheduled in its place.
+    KillTask,
+    /// The context resumes at the given address, the fixup of the faulting instruction (see [`fixup`]).
+    Fixup(Address<Virtual>),
+}
 
 #[doc(hidden)]
 #[inline(never)]
-pub fn ex_handler(exception: &ArchException) {
+pub fn ex_handler(exception: &ArchException) -> Resolution {
     trace!("Exception: {:#X?}", exception);
 
     match exception {
-        // Safety: Function is called once per this page fault exception.
-        ArchException::PageFault(_, _, _, address) => unsafe {
-            if let Err(err) = page_fault::handler(*address) {
-                panic!("error handling page fault: {}", err)
+        ArchException::PageFault(stack_frame, _, fault) => {
+            let address = fault.address;
+            let from_user = fault.user;
+            let fixup = || {
+                fixup::search(Address::from_ptr(stack_frame.instruction_pointer.as_mut_ptr::<()>()))
+                    .map(Resolution::Fixup)
+            };
+
+            // The kernel half is never demand-mapped, and the scheduler's lock may be held by the faulting code.
+            if !from_user && !crate::mem::layout::Region::Userspace.addresses().contains(&address.get()) {
+                return fixup()
+                    .unwrap_or_else(|| oops::oops(exception, format_args!("kernel page fault at {:X?}", address)));
+            }
+
+            // Safety: Function is called once per this page fault exception.
+            match unsafe { page_fault::handler(fault) } {
+                Ok(()) => Resolution::Resume,
+
+                // Only userspace is terminated, as kernel code may hold locks (or be mid-way through a change).
+                Err(page_fault::Error::StackOverflow { addr }) if from_user => {
+                    warn!("Task overflowed its stack (accessing {:X?}); terminating it.", addr);
+                    Resolution::KillTask
+                }
+
+                Err(page_fault::Error::AccessViolation { addr, access }) if from_user => {
+                    warn!(
+                        "Task made a {:?} access to {:X?} which its mapping doesn't permit; terminating it.",
+                        access, addr
+                    );
+                    Resolution::KillTask
+                }
+
+                Err(err) if !from_user => {
+                    fixup().unwrap_or_else(|| oops::oops(exception, format_args!("error handling page fault: {}", err)))
+                }
+
+                Err(err) => panic!("error handling page fault: {}", err),
             }
-        },
+        }
+
+        ArchException::DoubleFault(..) => oops::oops(exception, format_args!("double fault")),
 
         _ => panic!("could not handle exception!"),
-    };
+    }
 }
 
 use core::ptr::NonNull;
@@ -0,0 +1,117 @@
+//! Reports of faults the kernel can't recover from, written straight to the serial port before panicking.
+//!
+//! An oops touches no locks and never allocates, so it can be raised from any context. A fault raised while one is
+//! being reported (i.e. by walking a corrupted page table) halts the core rather than reporting it again.
+
+use super::ArchException;
+use crate::mem::{
+    paging::{PageTableEntry, TableDepth},
+    HHDM,
+};
+use core::{
+    fmt::{Arguments, Write},
+    sync::atomic::{AtomicBool, Ordering},
+};
+use libsys::{Address, Frame, Virtual};
+
+static OOPSING: AtomicBool = AtomicBool::new(false);
+
+/// Reports `exception` (raised for `cause`) with the state of the faulting context, then panics.
+pub fn oops(exception: &ArchException, cause: Arguments) -> ! {
+    if OOPSING.swap(true, Ordering::AcqRel) {
+        if let Some(mut w) = crate::panic::serial_writer() {
+            let _ = w.write_str("KERNEL OOPS: raised while another oops is being reported\n");
+        }
+
+        // Safety: Reporting another oops would likely fault again.
+        unsafe { crate::interrupts::halt_and_catch_fire() }
+    }
+
+    if let Some(mut w) = crate::panic::serial_writer() {
+        // Errors are ignored, as the panic which follows reports what it can regardless.
+        let _ = writeln!(w, "KERNEL OOPS: {}", cause);
+        let _ = report(&mut w, exception);
+    }
+
+    panic!("kernel oops: {}", cause)
+}
+
+#[cfg(target_arch = "x86_64")]
+fn report(w: &mut impl Write, exception: &ArchException) -> core::fmt::Result {
+    use crate::arch::x86_64::registers::control::{CR2, CR3};
+    use crate::interrupts::exceptions::Access;
+
+    let (stack_frame, gprs) = match exception {
+        ArchException::PageFault(stack_frame, gprs, fault) => {
+            let access = match fault.access {
+                Access::Read => "read",
+                Access::Write => "write",
+                Access::Execute => "fetch",
+            };
+
+            writeln!(
+                w,
+                "{} of {:#018X} in {} mode: {}{}{}",
+                access,
+                fault.address.get(),
+                if fault.user { "user" } else { "kernel" },
+                if fault.present { "protection violation" } else { "not present" },
+                if fault.malformed_table { " (malformed table)" } else { "" },
+                if fault.protection_key { " (protection key)" } else { "" },
+            )?;
+
+            (stack_frame, gprs)
+        }
+
+        ArchException::DoubleFault(stack_frame, gprs) => (stack_frame, gprs),
+
+        exception => return writeln!(w, "{:#X?}", exception),
+    };
+
+    let cr2 = CR2::read();
+    let (cr3, _) = CR3::read();
+    writeln!(
+        w,
+        "RIP {:#018X}  RSP {:#018X}  CS {:#06X}  SS {:#06X}  RFLAGS {:#010X}",
+        stack_frame.instruction_pointer.as_u64(),
+        stack_frame.stack_pointer.as_u64(),
+        stack_frame.code_segment,
+        stack_frame.stack_segment,
+        stack_frame.cpu_flags
+    )?;
+    writeln!(w, "CR2 {:#018X}  CR3 {:#018X}", cr2.get(), cr3.get().get())?;
+    writeln!(w, "RAX {:#018X}  RBX {:#018X}  RCX {:#018X}  RDX {:#018X}", gprs.rax, gprs.rbx, gprs.rcx, gprs.rdx)?;
+    writeln!(w, "RSI {:#018X}  RDI {:#018X}  RBP {:#018X}  R8  {:#018X}", gprs.rsi, gprs.rdi, gprs.rbp, gprs.r8)?;
+    writeln!(w, "R9  {:#018X}  R10 {:#018X}  R11 {:#018X}  R12 {:#018X}", gprs.r9, gprs.r10, gprs.r11, gprs.r12)?;
+    writeln!(w, "R13 {:#018X}  R14 {:#018X}  R15 {:#018X}", gprs.r13, gprs.r14, gprs.r15)?;
+
+    // A double fault reports no address, but CR2 still holds that of any page fault which escalated to it.
+    write_mapping(w, cr3, cr2)
+}
+
+/// Writes each entry translating `address` under the root table `root`, reading the tables through the direct map
+/// without taking the address space's lock (as its holder may be what faulted).
+fn write_mapping(w: &mut impl Write, root: Address<Frame>, address: Address<Virtual>) -> core::fmt::Result {
+    writeln!(w, "---------MAPPING-STATE--------")?;
+
+    let mut depth = TableDepth::max();
+    let mut table = root;
+    while let Some(index) = depth.index_of(address) {
+        let Some(table_page) = HHDM.offset(table) else {
+            return writeln!(w, "table {:X?} lies outside the direct map", table);
+        };
+
+        // Safety: Table is a page table frame, mapped by the direct map, and the entry lies within it.
+        let entry = unsafe { table_page.as_ptr().cast::<PageTableEntry>().add(index).read_volatile() };
+        writeln!(w, "L{} [{:>3}] {:X?} {:?}", depth.get(), index, entry.get_frame(), entry.get_attributes())?;
+
+        if !entry.is_present() || entry.is_huge() {
+            break;
+        }
+
+        table = entry.get_frame();
+        depth = depth.next();
+    }
+
+    Ok(())
+}
@@ -1,3 +1,4 @@
+use crate::task::Backing;
 use libsys::{Address, Virtual};
 
 crate::error_impl! {
@@ -6,19 +7,85 @@ crate::error_impl! {
     pub enum Error {
         CoreState => None,
         NoTask => None,
+        AddressSpace { err: crate::task::AddressSpaceError } => Some(err),
+        /// The task touched the guard page below its stack.
+        StackOverflow { addr: Address<Virtual> } => None,
+        /// The page is mapped, but doesn't permit the access.
+        AccessViolation { addr: Address<Virtual>, access: Access } => None,
+        /// A paging structure translating the address has a reserved bit set.
+        MalformedTable { addr: Address<Virtual> } => None,
         Task { err: crate::task::Error } => Some(err),
     }
 }
 
+/// Kind of access which raised a page fault.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    Execute,
+}
+
+/// Details of a page fault, decoded from what the CPU reports about it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageFault {
+    /// Address whose access faulted.
+    pub address: Address<Virtual>,
+    pub access: Access,
+    /// Whether the page was present, i.e. the fault is a protection violation rather than a missing translation.
+    pub present: bool,
+    /// Whether the access was made from user mode.
+    pub user: bool,
+    /// Whether a paging structure translating the address has a reserved bit set.
+    pub malformed_table: bool,
+    /// Whether the access was denied by the page's protection key.
+    pub protection_key: bool,
+}
+
 /// ### Safety
 ///
 /// This function should only be called in the case of passing context to handle a page fault.
 /// Calling this function more than once and/or outside the context of a page fault is undefined behaviour.
 #[doc(hidden)]
 #[inline(never)]
-pub unsafe fn handler(fault_address: Address<Virtual>) -> Result<()> {
+pub unsafe fn handler(fault: &PageFault) -> Result<()> {
+    let fault_address = fault.address;
+
+    if fault.malformed_table {
+        return Err(Error::MalformedTable { addr: fault_address });
+    }
+
     crate::cpu::state::with_scheduler(|scheduler| {
-        scheduler.task_mut().ok_or(Error::NoTask)?.demand_map(fault_address).map_err(|err| Error::Task { err })
+        let process = scheduler.task_mut().ok_or(Error::NoTask)?.process();
+
+        let fault_page = Address::new_truncate(fault_address.get());
+        if process.address_space().region(fault_page).is_some_and(|region| region.backing == Backing::Guard) {
+            return Err(Error::StackOverflow { addr: fault_address });
+        }
+
+        if !fault.present {
+            return match process.demand_map(fault_address) {
+                // A sibling thread (on another core) mapped the page between the fault and now.
+                Ok(()) | Err(crate::task::Error::AlreadyMapped) => Ok(()),
+                Err(err) => Err(Error::Task { err }),
+            };
+        }
+
+        // Copy-on-write pages are mapped read-only, so the first write to one faults. Any other fault on a present
+        // page is an access the mapping doesn't permit.
+        let mut address_space = process.address_space();
+        if fault.access != Access::Write || fault.protection_key {
+            return Err(Error::AccessViolation { addr: fault_address, access: fault.access });
+        }
+        if address_space.copy_on_write(fault_page).map_err(|err| Error::AddressSpace { err })? {
+            return Ok(());
+        }
+
+        // A sibling thread may have unshared the page between the fault and now.
+        match address_space.get_flags(fault_page) {
+            Ok(flags) if flags.contains(crate::mem::paging::TableEntryFlags::WRITABLE) => Ok(()),
+            _ => Err(Error::AccessViolation { addr: fault_address, access: fault.access }),
+        }
     })?;
 
     Ok(())
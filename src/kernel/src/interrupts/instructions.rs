@@ -44,9 +44,13 @@ pub fn are_enabled() -> bool {
 
 /// Disables interrupts, executes the given [`FnOnce`], and re-enables interrupts if they were prior.
 #[inline]
+#[track_caller]
 pub fn without<R>(func: impl FnOnce() -> R) -> R {
     let interrupts_enabled = are_enabled();
 
+    #[cfg(feature = "irq_audit")]
+    let window = if interrupts_enabled { Some(super::audit::Window::caller()) } else { None };
+
     if interrupts_enabled {
         // Safety: Interrupts are expected to be disabled, and are later re-enabled.
         unsafe {
@@ -63,6 +67,11 @@ pub fn without<R>(func: impl FnOnce() -> R) -> R {
         }
     }
 
+    #[cfg(feature = "irq_audit")]
+    if let Some(window) = window {
+        window.close();
+    }
+
     return_value
 }
 
@@ -0,0 +1,61 @@
+//! Inter-processor interrupts, and the registry of the cores they're sent between.
+//!
+//! Each core registers its APIC ID as its local state is initialized, taking the next index of the registry. Other
+//! subsystems keep per-core data in tables of [`MAX_CORES`] entries, indexed the same way (i.e. [`crate::mem::tlb`]).
+
+use super::Vector;
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+crate::error_impl! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Error {
+        /// More cores were started than the registry has room for.
+        TooManyCores => None,
+        /// No core is registered at the index.
+        NoSuchCore { index: usize } => None,
+        State { err: crate::cpu::state::Error } => Some(err)
+    }
+}
+
+/// Greatest number of cores the registry holds.
+pub const MAX_CORES: usize = 64;
+
+/// Value of [`APIC_IDS`] for indices which have been taken, but not yet registered.
+const UNREGISTERED: u32 = u32::MAX;
+
+static APIC_IDS: [AtomicU32; MAX_CORES] = [const { AtomicU32::new(UNREGISTERED) }; MAX_CORES];
+static NEXT_INDEX: AtomicUsize = AtomicUsize::new(0);
+
+/// Registers the core with the APIC ID `apic_id`, returning its index.
+pub fn register(apic_id: u32) -> Result<usize> {
+    let index = NEXT_INDEX
+        .fetch_update(Ordering::AcqRel, Ordering::Acquire, |index| (index < MAX_CORES).then_some(index + 1))
+        .map_err(|_| Error::TooManyCores)?;
+    APIC_IDS[index].store(apic_id, Ordering::Release);
+
+    trace!("Registered core {} with APIC ID {}.", index, apic_id);
+
+    Ok(index)
+}
+
+/// Indices of the registered cores.
+pub fn cores() -> impl Iterator<Item = usize> {
+    (0..NEXT_INDEX.load(Ordering::Acquire).min(MAX_CORES))
+        .filter(|&index| APIC_IDS[index].load(Ordering::Acquire) != UNREGISTERED)
+}
+
+/// Sends the interrupt `vector` to the core at `index`.
+///
+/// ### Safety
+///
+/// The core must expect the interrupt, i.e. `vector` must be handled by [`super::traps::handle_trap`].
+pub unsafe fn send(index: usize, vector: Vector) -> Result<()> {
+    let apic_id = APIC_IDS
+        .get(index)
+        .map(|apic_id| apic_id.load(Ordering::Acquire))
+        .filter(|&apic_id| apic_id != UNREGISTERED)
+        .ok_or(Error::NoSuchCore { index })?;
+
+    // Safety: Caller is required to ensure the core expects the interrupt.
+    unsafe { crate::cpu::state::send_ipi(apic_id, vector) }.map_err(|err| Error::State { err })
+}
@@ -0,0 +1,210 @@
+//! Device interrupt handlers, registered against the vectors their lines are routed to.
+//!
+//! Legacy INTx lines may be shared by several devices, so each vector holds a chain of handlers, called in the order
+//! they're registered until one claims the interrupt. Interrupts no handler claims are counted as spurious, and a
+//! vector whose interrupts are almost all spurious is reported, as its line is likely misrouted (or a device's
+//! handler is missing).
+
+use super::InterruptCell;
+use alloc::{boxed::Box, string::String, vec::Vec};
+use core::{
+    ops::RangeInclusive,
+    sync::atomic::{AtomicU64, Ordering},
+};
+use spin::Mutex;
+
+crate::error_impl! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Error {
+        /// The vector isn't one of [`DEVICE_VECTORS`].
+        InvalidVector { vector: u64 } => None,
+        /// The vector already has a handler, and either it or the new handler can't share it.
+        Busy { vector: u64 } => None,
+        /// No handler with the given ID is registered to the vector.
+        NotRegistered => None,
+        /// Every device vector already has a handler.
+        Exhausted => None
+    }
+}
+
+/// Vectors which device interrupts may be routed to.
+pub const DEVICE_VECTORS: RangeInclusive<u64> = 0x40..=0x7F;
+/// Number of [`DEVICE_VECTORS`].
+const LINE_COUNT: usize = 0x40;
+
+/// Number of interrupts over which a vector's spurious interrupts are counted, before it's checked for misrouting.
+const MISROUTE_WINDOW: u64 = 1000;
+/// Spurious interrupts (of [`MISROUTE_WINDOW`]) above which a vector is reported as misrouted.
+const MISROUTE_THRESHOLD: u64 = (MISROUTE_WINDOW * 99) / 100;
+
+/// Services an interrupt, returning whether its device raised it.
+///
+/// Handlers mustn't allocate (see [`crate::mem::alloc::mempool`] for objects they need).
+pub type Handler = Box<dyn Fn() -> bool + Send + Sync>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct HandlerId(u64);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sharing {
+    /// The handler must be the only one on its vector (i.e. an edge-triggered ISA line).
+    Exclusive,
+    /// The handler may be chained with others (i.e. a level-triggered PCI INTx line).
+    Shared,
+}
+
+struct Entry {
+    id: HandlerId,
+    name: String,
+    sharing: Sharing,
+    handler: Handler,
+}
+
+struct Line {
+    handlers: InterruptCell<Mutex<Vec<Entry>>>,
+    /// Interrupts claimed by a handler.
+    handled: AtomicU64,
+    /// Interrupts no handler claimed.
+    spurious: AtomicU64,
+    /// Spurious interrupts within the current misrouting window.
+    window_spurious: AtomicU64,
+}
+
+impl Line {
+    const fn new() -> Self {
+        Self {
+            handlers: InterruptCell::new(Mutex::new(Vec::new())),
+            handled: AtomicU64::new(0),
+            spurious: AtomicU64::new(0),
+            window_spurious: AtomicU64::new(0),
+        }
+    }
+}
+
+static LINES: [Line; LINE_COUNT] = [const { Line::new() }; LINE_COUNT];
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+fn line(vector: u64) -> Result<&'static Line> {
+    vector
+        .checked_sub(*DEVICE_VECTORS.start())
+        .and_then(|index| LINES.get(usize::try_from(index).ok()?))
+        .ok_or(Error::InvalidVector { vector })
+}
+
+/// Chains `handler` onto `line`, returning its ID and whether it's the line's first handler, or handing the handler
+/// back if the line can't take it.
+fn push(
+    line: &Line,
+    name: &str,
+    sharing: Sharing,
+    handler: Handler,
+) -> core::result::Result<(HandlerId, bool), Handler> {
+    line.handlers.with(|handlers| {
+        let mut handlers = handlers.lock();
+
+        let shareable = sharing == Sharing::Shared && handlers.iter().all(|entry| entry.sharing == Sharing::Shared);
+        if !handlers.is_empty() && !shareable {
+            return Err(handler);
+        }
+
+        let id = HandlerId(NEXT_ID.fetch_add(1, Ordering::Relaxed));
+        handlers.push(Entry { id, name: String::from(name), sharing, handler });
+
+        Ok((id, handlers.len() == 1))
+    })
+}
+
+/// Chains `handler` onto `vector`, returning its ID (for [`unregister`]).
+pub fn register(vector: u64, name: &str, sharing: Sharing, handler: Handler) -> Result<HandlerId> {
+    let line = line(vector)?;
+    let (id, is_first) = push(line, name, sharing, handler).map_err(|_| Error::Busy { vector })?;
+
+    if is_first {
+        publish_stat(vector, line);
+    }
+
+    Ok(id)
+}
+
+/// Registers `handler` exclusively on a device vector which has no handlers, returning the vector and the handler's
+/// ID (i.e. for a message-signalled interrupt, which needn't be routed to any particular vector).
+pub fn register_any(name: &str, handler: Handler) -> Result<(u64, HandlerId)> {
+    let mut handler = handler;
+
+    // Taken from the top, away from the vectors legacy lines tend to be routed to.
+    for vector in DEVICE_VECTORS.rev() {
+        let line = line(vector)?;
+
+        match push(line, name, Sharing::Exclusive, handler) {
+            Ok((id, is_first)) => {
+                if is_first {
+                    publish_stat(vector, line);
+                }
+
+                return Ok((vector, id));
+            }
+
+            Err(returned) => handler = returned,
+        }
+    }
+
+    Err(Error::Exhausted)
+}
+
+fn publish_stat(vector: u64, line: &'static Line) {
+    crate::sysfs::set(
+        &alloc::format!("irq/{vector:#x}"),
+        "stat",
+        crate::sysfs::Value::Dynamic(Box::new(move || {
+            let names = line
+                .handlers
+                .with(|handlers| handlers.lock().iter().map(|entry| entry.name.clone()).collect::<Vec<_>>());
+
+            alloc::format!(
+                "handlers={} handled={} spurious={}",
+                names.join(","),
+                line.handled.load(Ordering::Relaxed),
+                line.spurious.load(Ordering::Relaxed)
+            )
+        })),
+    );
+}
+
+/// Removes the handler `id` from `vector`'s chain.
+pub fn unregister(vector: u64, id: HandlerId) -> Result<()> {
+    line(vector)?.handlers.with(|handlers| {
+        let mut handlers = handlers.lock();
+        let index = handlers.iter().position(|entry| entry.id == id).ok_or(Error::NotRegistered)?;
+        handlers.remove(index);
+
+        Ok(())
+    })
+}
+
+/// Calls `vector`'s handlers until one claims the interrupt.
+pub(crate) fn dispatch(vector: u64) {
+    let Ok(line) = line(vector) else { return };
+
+    crate::cpu::state::enter_interrupt();
+    let handled = line.handlers.with(|handlers| handlers.lock().iter().any(|entry| (entry.handler)()));
+    crate::cpu::state::exit_interrupt();
+
+    if handled {
+        line.handled.fetch_add(1, Ordering::Relaxed);
+    } else {
+        line.spurious.fetch_add(1, Ordering::Relaxed);
+        line.window_spurious.fetch_add(1, Ordering::Relaxed);
+    }
+
+    let total = line.handled.load(Ordering::Relaxed) + line.spurious.load(Ordering::Relaxed);
+    if (total % MISROUTE_WINDOW) == 0 {
+        let window_spurious = line.window_spurious.swap(0, Ordering::Relaxed);
+
+        if window_spurious > MISROUTE_THRESHOLD {
+            warn!(
+                "Vector {:#X}: {} of the last {} interrupts went unclaimed; its line is likely misrouted.",
+                vector, window_spurious, MISROUTE_WINDOW
+            );
+        }
+    }
+}
@@ -1,6 +1,12 @@
 pub mod exceptions;
+pub mod ipi;
+pub mod irq;
+pub mod stacks;
 pub mod traps;
 
+#[cfg(feature = "irq_audit")]
+pub mod audit;
+
 mod instructions;
 pub use instructions::*;
 
@@ -49,7 +55,10 @@ pub enum Vector {
     Timer = 0x30,
     Thermal = 0x32,
     Performance = 0x33,
-    /* 0x34..=0x3B free for use */
+    TlbShootdown = 0x34,
+    /// Halts the core for another core's panic (see [`crate::panic`]).
+    PanicHalt = 0x35,
+    /* 0x36..=0x3B free for use */
     Error = 0x3C,
     LINT0 = 0x3D,
     LINT1 = 0x3E,
@@ -68,11 +77,13 @@ impl<T> InterruptCell<T> {
     }
 
     #[inline]
+    #[track_caller]
     pub fn with<U>(&self, func: impl FnOnce(&T) -> U) -> U {
         without(|| func(&self.0))
     }
 
     #[inline]
+    #[track_caller]
     pub fn with_mut<U>(&mut self, func: impl FnOnce(&mut T) -> U) -> U {
         without(|| func(&mut self.0))
     }
@@ -0,0 +1,165 @@
+//! High-water marks of the stacks interrupts run on (the privilege and IST stacks, or the trap stack).
+//!
+//! Each tracked stack is painted with a canary pattern before it's first used. The deepest the stack has ever grown is
+//! then wherever the pattern first breaks, scanning up from its bottom. Scans are made on demand (see [`publish`]),
+//! and a stack found to have used more than [`WARN_PERCENT`] of itself is warned of once.
+//!
+//! The stack each core's current task runs on (if it isn't a tracked stack) is also recorded as it's switched in, so
+//! the panic path can find the [`bounds`] of whichever stack it panicked on.
+
+use crate::interrupts::{ipi::MAX_CORES, InterruptCell};
+use alloc::{string::String, vec::Vec};
+use core::{
+    ops::Range,
+    ptr::NonNull,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+use spin::Mutex;
+
+/// Pattern painted over each tracked stack, repeated every 8 bytes (by address, so alignment doesn't matter).
+const CANARY: u64 = 0xC0DE_57AC_CA4A_12E5;
+
+/// Percentage of a stack's size past which its usage is warned of.
+pub const WARN_PERCENT: usize = 75;
+
+struct Tracked {
+    core_id: u32,
+    name: &'static str,
+    /// Lowest address of the stack.
+    bottom: NonNull<u8>,
+    len: usize,
+    warned: AtomicBool,
+}
+
+// Safety: Tracked stacks are leaked kernel memory, only ever read through the pointer.
+unsafe impl Send for Tracked {}
+
+static STACKS: InterruptCell<Mutex<Vec<Tracked>>> = InterruptCell::new(Mutex::new(Vec::new()));
+
+/// Bottom and top of the stack each core's current task runs on, by core index (or empty, if it runs on a tracked
+/// stack, as userspace tasks do in the kernel).
+static RUNNING: [(AtomicUsize, AtomicUsize); MAX_CORES] =
+    [const { (AtomicUsize::new(0), AtomicUsize::new(0)) }; MAX_CORES];
+
+const fn canary_byte(address: usize) -> u8 {
+    CANARY.to_ne_bytes()[address % core::mem::size_of::<u64>()]
+}
+
+/// Paints `stack` with the canary pattern and tracks it as the local core's `name` stack, returning its top.
+pub fn track(name: &'static str, stack: &'static mut [u8]) -> NonNull<u8> {
+    let bottom = stack.as_ptr().addr();
+    for (index, byte) in stack.iter_mut().enumerate() {
+        *byte = canary_byte(bottom + index);
+    }
+
+    let range = stack.as_mut_ptr_range();
+    STACKS.with(|stacks| {
+        stacks.lock().push(Tracked {
+            core_id: crate::cpu::read_id(),
+            name,
+            bottom: NonNull::new(range.start).unwrap(),
+            len: stack.len(),
+            warned: AtomicBool::new(false),
+        });
+    });
+
+    NonNull::new(range.end).unwrap()
+}
+
+impl Tracked {
+    /// Bytes of the stack which have ever been used.
+    fn high_water(&self) -> usize {
+        let untouched = (0..self.len)
+            .take_while(|&index| {
+                // Safety: The stack is leaked, so remains valid. It may be in use by its core, so is read volatile.
+                let byte = unsafe { self.bottom.as_ptr().add(index).read_volatile() };
+                byte == canary_byte(self.bottom.as_ptr().addr() + index)
+            })
+            .count();
+
+        self.len - untouched
+    }
+}
+
+/// Records `stack` as the one the local core's current task runs on, or `None` if it runs on a tracked stack.
+pub fn set_running(stack: Option<Range<usize>>) {
+    let Ok(index) = crate::cpu::state::get_core_index() else { return };
+    let stack = stack.unwrap_or(0..0);
+
+    RUNNING[index].0.store(stack.start, Ordering::Relaxed);
+    RUNNING[index].1.store(stack.end, Ordering::Relaxed);
+}
+
+/// Bounds of the stack holding `address`, if it's the stack of the local core's current task, or a tracked stack.
+///
+/// #### Remark
+///
+/// This is meant for the panic path, so it doesn't wait on the tracked stacks if they're locked.
+pub fn bounds(address: usize) -> Option<Range<usize>> {
+    if let Ok(index) = crate::cpu::state::get_core_index() {
+        let running = RUNNING[index].0.load(Ordering::Relaxed)..RUNNING[index].1.load(Ordering::Relaxed);
+        if running.contains(&address) {
+            return Some(running);
+        }
+    }
+
+    STACKS.with(|stacks| {
+        stacks
+            .try_lock()?
+            .iter()
+            .map(|stack| stack.bottom.as_ptr().addr()..(stack.bottom.as_ptr().addr() + stack.len))
+            .find(|stack| stack.contains(&address))
+    })
+}
+
+/// High-water mark of a tracked stack.
+#[derive(Debug, Clone, Copy)]
+pub struct Usage {
+    pub core_id: u32,
+    pub name: &'static str,
+    /// Deepest the stack has grown, in bytes.
+    pub high_water: usize,
+    pub len: usize,
+}
+
+/// Scans every tracked stack for its high-water mark, warning of those past [`WARN_PERCENT`] of their size.
+pub fn scan() -> Vec<Usage> {
+    STACKS.with(|stacks| {
+        stacks
+            .lock()
+            .iter()
+            .map(|stack| {
+                let high_water = stack.high_water();
+                if (high_water * 100) > (stack.len * WARN_PERCENT) && !stack.warned.swap(true, Ordering::Relaxed) {
+                    warn!(
+                        "Core {} {} stack has used {:#X} of its {:#X} bytes",
+                        stack.core_id, stack.name, high_water, stack.len
+                    );
+                }
+
+                Usage { core_id: stack.core_id, name: stack.name, high_water, len: stack.len }
+            })
+            .collect()
+    })
+}
+
+/// Publishes the high-water marks of the tracked stacks to sysfs (`kernel/stacks`), scanned as they're read.
+pub fn publish() {
+    use core::fmt::Write;
+
+    crate::sysfs::set(
+        "kernel/stacks",
+        "high_water",
+        crate::sysfs::Value::Dynamic(alloc::boxed::Box::new(|| {
+            scan().into_iter().fold(String::new(), |mut string, usage| {
+                writeln!(
+                    string,
+                    "core {:<4} {:<14} {:#8X} / {:#8X}",
+                    usage.core_id, usage.name, usage.high_water, usage.len
+                )
+                .unwrap();
+                string
+            })
+        })),
+    );
+}
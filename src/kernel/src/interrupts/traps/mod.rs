@@ -17,6 +17,18 @@ pub unsafe fn handle_trap(irq_vector: u64, state: &mut State, regs: &mut Registe
 
         Ok(Vector::Syscall) => handle_syscall(state, regs),
 
+        Ok(Vector::TlbShootdown) => {
+            crate::cpu::state::enter_interrupt();
+            crate::mem::tlb::service();
+            crate::cpu::state::exit_interrupt();
+        }
+
+        Ok(Vector::PanicHalt) => crate::panic::halt_for_panic(state, regs),
+
+        Err(_) if crate::interrupts::irq::DEVICE_VECTORS.contains(&irq_vector) => {
+            crate::interrupts::irq::dispatch(irq_vector);
+        }
+
         Err(err) => panic!("Invalid interrupt vector: {:X?}", err),
         vector_result => unimplemented!("Unhandled interrupt: {:?}", vector_result),
     }
@@ -24,18 +36,33 @@ pub unsafe fn handle_trap(irq_vector: u64, state: &mut State, regs: &mut Registe
     crate::cpu::state::end_of_interrupt().unwrap();
 }
 
+/// Dispatches the system call in `regs` (laid out per the ABI in [`libsys::syscall`]), and writes its result back.
 #[allow(clippy::similar_names)]
-fn handle_syscall(state: &mut State, regs: &mut Registers) {
-    let vector = regs.rax;
-    let arg0 = regs.rdi;
-    let arg1 = regs.rsi;
-    let arg2 = regs.rdx;
-    let arg3 = regs.rcx;
-    let arg4 = regs.r8;
-    let arg5 = regs.r9;
-
-    let result = syscall::process(vector, arg0, arg1, arg2, arg3, arg4, arg5, state, regs);
-    let (rdi, rsi) = <libsys::syscall::Result as libsys::syscall::ResultConverter>::into_registers(result);
-    regs.rdi = rdi;
-    regs.rsi = rsi;
+pub fn handle_syscall(state: &mut State, regs: &mut Registers) {
+    #[cfg(target_arch = "x86_64")]
+    let (vector, [arg0, arg1, arg2, arg3, arg4, arg5]) =
+        (regs.rax, [regs.rdi, regs.rsi, regs.rdx, regs.rcx, regs.r8, regs.r9]);
+    #[cfg(target_arch = "riscv64")]
+    let (vector, [arg0, arg1, arg2, arg3, arg4, arg5]) =
+        (regs.a7, [regs.a0, regs.a1, regs.a2, regs.a3, regs.a4, regs.a5]);
+
+    if let Some(result) = syscall::process(vector, arg0, arg1, arg2, arg3, arg4, arg5, state, regs) {
+        write_result(regs, result);
+    }
+}
+
+/// Writes the result of a system call into the result registers of `regs`.
+pub fn write_result(regs: &mut Registers, result: libsys::syscall::Result) {
+    let (discriminant, value) = <libsys::syscall::Result as libsys::syscall::ResultConverter>::into_registers(result);
+
+    #[cfg(target_arch = "x86_64")]
+    {
+        regs.rdi = discriminant;
+        regs.rsi = value;
+    }
+    #[cfg(target_arch = "riscv64")]
+    {
+        regs.a0 = discriminant;
+        regs.a1 = value;
+    }
 }
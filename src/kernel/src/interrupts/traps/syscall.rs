@@ -1,6 +1,26 @@
-use crate::task::{Registers, State};
-use libsys::syscall::{Error, Result, Success, Vector};
+use crate::task::{
+    registry::{IoDirection, IoKind},
+    Registers, State,
+};
+use alloc::{string::String, vec::Vec};
+use libsys::syscall::{
+    block::StatsArgs,
+    cred::{Capabilities, Credentials, GetArgs, SetArgs},
+    dl::{HeadersArgs, RelocateArgs, SegmentArgs},
+    fs::{
+        ControlArgs, CreateArgs, FdArgs, FileKind, IoArgs, MapArgs, Mode, OpenArgs, OpenFlags, PathPairArgs, Stat,
+        StatArgs,
+    },
+    futex,
+    group::{AttachArgs, CreateArgs as GroupCreateArgs, GroupArgs, SharesArgs},
+    mem::{MapFlags, MemMapArgs, ModuleArgs, ProtectArgs, Protection, UnmapArgs},
+    task::{DeadlineArgs, ExitArgs, InfoArgs, IoClassArgs, SchedPolicyArgs, SleepArgs, SpawnThreadArgs, WaitArgs},
+    time::ClockArgs,
+    Arguments, BufferArgs, Error, KlogArgs, Result, Success, Vector,
+};
 
+/// Processes the system call `vector`, returning its result, or `None` if the calling task was switched out (in which
+/// case its result, if it's to resume, has been written into its saved registers).
 #[allow(clippy::too_many_arguments)]
 pub(super) fn process(
     vector: usize,
@@ -12,7 +32,7 @@ pub(super) fn process(
     arg5: usize,
     state: &mut State,
     regs: &mut Registers,
-) -> Result {
+) -> Option<Result> {
     trace!(
         "Syscall Args: Vector:{:X?}   0:{:X?}  1:{:X?}  2:{:X?}  3:{:X?}  4:{:X?}  5:{:X?}",
         vector,
@@ -24,48 +44,140 @@ pub(super) fn process(
         arg5
     );
 
+    let args = [arg0, arg1, arg2, arg3, arg4, arg5];
     let result = match Vector::try_from(vector) {
         Err(err) => {
             warn!("Unhandled system call vector: {:X?}", err);
             Err(Error::InvalidVector)
         }
 
-        Ok(Vector::KlogInfo) => process_klog(log::Level::Info, arg0, arg1),
-        Ok(Vector::KlogError) => process_klog(log::Level::Error, arg0, arg1),
-        Ok(Vector::KlogDebug) => process_klog(log::Level::Debug, arg0, arg1),
-        Ok(Vector::KlogTrace) => process_klog(log::Level::Trace, arg0, arg1),
+        Ok(Vector::KlogInfo) => process_klog(log::Level::Info, KlogArgs::from_args(args)),
+        Ok(Vector::KlogError) => process_klog(log::Level::Error, KlogArgs::from_args(args)),
+        Ok(Vector::KlogDebug) => process_klog(log::Level::Debug, KlogArgs::from_args(args)),
+        Ok(Vector::KlogTrace) => process_klog(log::Level::Trace, KlogArgs::from_args(args)),
 
         Ok(Vector::TaskExit) => {
-            crate::cpu::state::with_scheduler(|scheduler| scheduler.kill_task(state, regs));
+            let ExitArgs { code } = ExitArgs::from_args(args);
+            crate::cpu::state::with_scheduler(|scheduler| scheduler.exit_task(state, regs, code));
 
-            Ok(Success::Ok)
+            return None;
         }
         Ok(Vector::TaskYield) => {
+            super::write_result(regs, Ok(Success::Ok));
             crate::cpu::state::with_scheduler(|scheduler| scheduler.yield_task(state, regs));
 
-            Ok(Success::Ok)
+            return None;
+        }
+        Ok(Vector::TaskSetName) => process_task_set_name(BufferArgs::from_args(args)),
+        Ok(Vector::TaskInfo) => process_task_info(InfoArgs::from_args(args)),
+        Ok(Vector::TaskList) => process_task_list(BufferArgs::from_args(args)),
+        Ok(Vector::TaskId) => with_task(|task| Ok(Success::Value(task.id().get() as usize))),
+        Ok(Vector::TaskSleep) => match check_held_quota(crate::task::quota::Resource::SleepTimers) {
+            Ok(()) => {
+                let SleepArgs { ns } = SleepArgs::from_args(args);
+                // Scheduler ticks are milliseconds, and a sleep never ends early.
+                let ticks = ns.div_ceil(1_000_000);
+                super::write_result(regs, Ok(Success::Ok));
+                crate::cpu::state::with_scheduler(|scheduler| scheduler.sleep_task(state, regs, ticks));
+
+                return None;
+            }
+
+            Err(err) => Err(err),
+        },
+        Ok(Vector::TaskSetSchedPolicy) => process_task_set_sched_policy(SchedPolicyArgs::from_args(args)),
+        Ok(Vector::TaskSetIoClass) => process_task_set_io_class(IoClassArgs::from_args(args)),
+        Ok(Vector::TaskSpawnThread) => process_task_spawn_thread(SpawnThreadArgs::from_args(args)),
+        Ok(Vector::TaskSetDeadline) => process_task_set_deadline(DeadlineArgs::from_args(args)),
+        Ok(Vector::TaskWait) => {
+            let WaitArgs { alias } = WaitArgs::from_args(args);
+
+            // The task blocks unless its target has already exited (or doesn't exist).
+            match crate::task::registry::resolve(uuid::Uuid::from_u128(alias)) {
+                Some(target) => {
+                    crate::cpu::state::with_scheduler(|scheduler| scheduler.wait_task(state, regs, target))?
+                }
+                None => Err(Error::NoSuchTask),
+            }
+        }
+
+        Ok(Vector::FsChdir) => process_chdir(BufferArgs::from_args(args)),
+        Ok(Vector::FsGetcwd) => process_getcwd(BufferArgs::from_args(args)),
+        Ok(Vector::FsOpen) => process_open(OpenArgs::from_args(args)),
+        Ok(Vector::FsClose) => process_close(FdArgs::from_args(args)),
+        Ok(Vector::FsRead) => process_read(IoArgs::from_args(args)),
+        Ok(Vector::FsWrite) => process_write(IoArgs::from_args(args)),
+        Ok(Vector::FsStat) => process_stat(StatArgs::from_args(args)),
+        Ok(Vector::FsReadDir) => process_read_dir(IoArgs::from_args(args)),
+        Ok(Vector::FsCreate) => process_create(CreateArgs::from_args(args)),
+        Ok(Vector::FsUnlink) => process_unlink(BufferArgs::from_args(args)),
+        Ok(Vector::FsRename) => process_rename(PathPairArgs::from_args(args)),
+        Ok(Vector::FsLink) => process_link(PathPairArgs::from_args(args)),
+        Ok(Vector::FsControl) => process_control(ControlArgs::from_args(args)),
+        Ok(Vector::FsMap) => process_map(MapArgs::from_args(args)),
+
+        Ok(Vector::BlockStats) => process_block_stats(StatsArgs::from_args(args)),
+        Ok(Vector::BlockFlush) => process_block_flush(BufferArgs::from_args(args)),
+
+        Ok(Vector::MemMap) => process_mem_map(MemMapArgs::from_args(args)),
+        Ok(Vector::MemProtect) => process_mem_protect(ProtectArgs::from_args(args)),
+        Ok(Vector::MemUnmap) => process_mem_unmap(UnmapArgs::from_args(args)),
+        Ok(Vector::MemMapModule) => process_mem_map_module(ModuleArgs::from_args(args)),
+
+        Ok(Vector::DlProgramHeaders) => process_dl_program_headers(HeadersArgs::from_args(args)),
+        Ok(Vector::DlMapSegment) => process_dl_map_segment(SegmentArgs::from_args(args)),
+        Ok(Vector::DlRelocate) => process_dl_relocate(RelocateArgs::from_args(args)),
+
+        Ok(Vector::TimeGet) => process_time_get(ClockArgs::from_args(args)),
+
+        Ok(Vector::GroupCreate) => process_group_create(GroupCreateArgs::from_args(args)),
+        Ok(Vector::GroupDestroy) => process_group_destroy(GroupArgs::from_args(args)),
+        Ok(Vector::GroupSetShares) => process_group_set_shares(SharesArgs::from_args(args)),
+        Ok(Vector::GroupAttach) => process_group_attach(AttachArgs::from_args(args)),
+        Ok(Vector::GroupInfo) => process_group_info(libsys::syscall::group::InfoArgs::from_args(args)),
+
+        Ok(Vector::CredGet) => process_cred_get(GetArgs::from_args(args)),
+        Ok(Vector::CredSet) => process_cred_set(SetArgs::from_args(args)),
+
+        Ok(Vector::FutexWait) => {
+            let futex::WaitArgs { addr, expected } = futex::WaitArgs::from_args(args);
+
+            // Demand mapped up front, as the word is read through the page tables once the futexes are locked.
+            let address = futex_address(addr).and_then(|address| {
+                check_held_quota(crate::task::quota::Resource::FutexWaiters)?;
+                map_user_memory(addr, core::mem::size_of::<u32>())?;
+                Ok(address)
+            });
+
+            match address {
+                Ok(address) => crate::cpu::state::with_scheduler(|scheduler| {
+                    scheduler.futex_wait_task(state, regs, address, expected)
+                })?,
+                Err(err) => Err(err),
+            }
         }
+        Ok(Vector::FutexWake) => process_futex_wake(futex::WakeArgs::from_args(args)),
     };
 
     trace!("Syscall: {:X?}", result);
 
-    result
+    Some(result)
 }
 
-fn process_klog(level: log::Level, str_ptr_arg: usize, str_len: usize) -> Result {
-    let str_ptr = str_ptr_arg as *mut u8;
+/// Ensures the active task's memory at `ptr..(ptr + len)` is mapped.
+fn map_user_memory(ptr: usize, len: usize) -> Result<()> {
+    use crate::task::Error as TaskError;
+    use libsys::{page_size, Address};
 
-    // TODO abstract this into a function
-    crate::cpu::state::with_scheduler(|scheduler| {
-        use crate::task::Error as TaskError;
-        use libsys::{page_size, Address};
+    // Kernel memory is always mapped, so it'd otherwise pass as the task's.
+    crate::mem::user::check(ptr, len)?;
 
-        let str_start = str_ptr.addr();
-        let str_end = str_start + str_len;
+    let end = ptr + len;
+    let start = ptr & !libsys::page_mask();
 
-        let task = scheduler.task_mut().ok_or(Error::NoActiveTask)?;
-        for address in (str_start..str_end).step_by(page_size() / 2).map(Address::new_truncate) {
-            match task.demand_map(address) {
+    with_task(|task| {
+        for address in (start..end).step_by(page_size()).map(Address::new_truncate) {
+            match task.process().demand_map(address) {
                 Ok(()) | Err(TaskError::AlreadyMapped) => {}
 
                 err => {
@@ -75,14 +187,660 @@ fn process_klog(level: log::Level, str_ptr_arg: usize, str_len: usize) -> Result
             }
         }
 
+        Ok(())
+    })
+}
+
+/// Copies the active task's memory at `ptr..(ptr + len)`.
+fn read_user(BufferArgs { ptr, len }: BufferArgs) -> Result<Vec<u8>> {
+    // Safety: Any byte is a valid `u8`.
+    unsafe { crate::mem::user::read::<u8>(ptr, len) }.map_err(Error::from)
+}
+
+/// Allocates a zeroed buffer of `len` bytes, to be copied to the active task's memory.
+fn kernel_buffer(len: usize) -> Result<Vec<u8>> {
+    // Length is the task's to choose, so failing to allocate for it is an error rather than an abort.
+    let mut buf = Vec::new();
+    buf.try_reserve_exact(len).map_err(|_| Error::OutOfMemory)?;
+    buf.resize(len, 0);
+
+    Ok(buf)
+}
+
+/// Copies `values` to the active task's memory at `ptr`, which must be aligned for them.
+fn write_user<T: Copy>(ptr: usize, values: &[T]) -> Result<()> {
+    if (ptr % core::mem::align_of::<T>()) > 0 {
+        return Err(Error::InvalidPtr);
+    }
+
+    crate::mem::user::write(ptr, values).map_err(Error::from)
+}
+
+/// Fails with [`Error::QuotaExceeded`] if the task holds its quota of `resource`.
+fn check_quota(resource: crate::task::quota::Resource, used: usize) -> Result<()> {
+    crate::task::quota::permits(resource, used).then_some(()).ok_or(Error::QuotaExceeded)
+}
+
+/// Fails with [`Error::QuotaExceeded`] if the active task's process holds its quota of `resource`, as counted by its
+/// [`crate::task::quota::Usage`].
+fn check_held_quota(resource: crate::task::quota::Resource) -> Result<()> {
+    with_task(|task| check_quota(resource, task.process().usage().held(resource)))
+}
+
+/// Fails with [`Error::QuotaExceeded`] if the task holds its quota of address space regions.
+fn check_region_quota(task: &crate::task::Task) -> Result<()> {
+    check_quota(crate::task::quota::Resource::Regions, task.process().address_space().regions().count())
+}
+
+/// Fails with [`Error::PermissionDenied`] unless the task's process holds `capabilities`.
+fn check_capabilities(task: &crate::task::Task, capabilities: Capabilities) -> Result<()> {
+    task.process().credentials().capabilities.contains(capabilities).then_some(()).ok_or(Error::PermissionDenied)
+}
+
+/// Fails with [`Error::PermissionDenied`] unless the task `target` runs as the active task's user, or the active task's
+/// process holds [`Capabilities::TASK_CONTROL`].
+fn check_controls(target: &crate::task::registry::Info) -> Result<()> {
+    let credentials = with_task(|task| Ok(task.process().credentials()))?;
+    let owned = target.credentials().is_some_and(|target| target.uid == credentials.uid);
+
+    (owned || credentials.capabilities.contains(Capabilities::TASK_CONTROL))
+        .then_some(())
+        .ok_or(Error::PermissionDenied)
+}
+
+fn with_task<T>(func: impl FnOnce(&mut crate::task::Task) -> Result<T>) -> Result<T> {
+    crate::cpu::state::with_scheduler(|scheduler| func(scheduler.task_mut().ok_or(Error::NoActiveTask)?))
+}
+
+fn process_klog(level: log::Level, args: KlogArgs) -> Result {
+    let str = user_str(args)?;
+
+    log!(level, "[KLOG]: {}", str);
+
+    Ok(Success::Ok)
+}
+
+fn process_task_set_name(name: BufferArgs) -> Result {
+    let name = user_str(name)?;
+
+    with_task(|task| {
+        task.set_name(&name);
+
         Ok(Success::Ok)
+    })
+}
+
+fn process_task_info(InfoArgs { tid, info_ptr }: InfoArgs) -> Result {
+    use libsys::syscall::task::CURRENT;
+
+    let info = if tid == CURRENT {
+        with_task(|task| {
+            // The current task's usage is otherwise only published as it's switched out.
+            task.update_info();
+
+            Ok(task.info().snapshot())
+        })?
+    } else {
+        crate::task::registry::get(tid).ok_or(Error::NoSuchTask)?.snapshot()
+    };
+
+    write_user(info_ptr, &[info])?;
+
+    Ok(Success::Ok)
+}
+
+fn process_task_set_sched_policy(SchedPolicyArgs { tid, policy, priority }: SchedPolicyArgs) -> Result {
+    use libsys::syscall::task::{Policy, Priority, CURRENT};
+
+    let policy = Policy::try_from(policy).map_err(|_| Error::InvalidArgument)?;
+    let priority = crate::task::Priority::from(Priority::try_from(priority).map_err(|_| Error::InvalidArgument)?);
+    if priority > crate::task::class(policy).ceiling() {
+        return Err(Error::PermissionDenied);
+    }
+
+    let tid = if tid == CURRENT { with_task(|task| Ok(task.id().get()))? } else { tid };
+    let info = crate::task::registry::get(tid).ok_or(Error::NoSuchTask)?;
+    check_controls(&info)?;
+    info.set_sched_policy(policy, priority);
+
+    // A queued task is moved into its new queue now, while any other task moves as it's next queued.
+    crate::task::PROCESSES.lock().requeue(info.tid());
+
+    Ok(Success::Ok)
+}
+
+fn process_task_set_deadline(DeadlineArgs { period, budget }: DeadlineArgs) -> Result {
+    use crate::task::deadline;
+
+    let (Some(period), Some(budget)) = (core::num::NonZeroU16::new(period), core::num::NonZeroU16::new(budget)) else {
+        return Err(Error::InvalidArgument);
+    };
+    let params = deadline::Parameters::new(period, budget).map_err(|_| Error::InvalidArgument)?;
+
+    match crate::cpu::state::with_scheduler(|scheduler| scheduler.admit_deadline(params)) {
+        Ok(()) => Ok(Success::Ok),
+        Err(deadline::Error::OverSubscribed) => Err(Error::OverSubscribed),
+        Err(deadline::Error::InvalidParameters) => Err(Error::InvalidArgument),
+    }
+}
+
+fn process_task_spawn_thread(SpawnThreadArgs { entry, stack, arg }: SpawnThreadArgs) -> Result {
+    // The thread enters userspace at `entry`, and pushes to the memory below `stack`.
+    let userspace = crate::mem::layout::Region::Userspace.addresses();
+    if !userspace.contains(&entry) || stack.checked_sub(1).is_none_or(|last| !userspace.contains(&last)) {
+        return Err(Error::InvalidPtr);
+    }
+    let entry = libsys::Address::new(entry).ok_or(Error::InvalidPtr)?;
+    let stack = libsys::Address::new(stack).ok_or(Error::InvalidPtr)?;
+
+    let thread = with_task(|task| {
+        if task.is_kernel() {
+            return Err(Error::Unsupported);
+        }
+        check_quota(crate::task::quota::Resource::Threads, task.thread_count())?;
+
+        Ok(task.spawn_thread(entry, stack, arg))
     })?;
 
-    // Safety: TODO
-    let str_slice = unsafe { core::slice::from_raw_parts(str_ptr, str_len) };
-    let str = core::str::from_utf8(str_slice).map_err(Error::from)?;
+    let tid = thread.id().get();
+    crate::task::spawn(thread);
 
-    log!(level, "[KLOG]: {}", str);
+    Ok(Success::Value(tid as usize))
+}
+
+fn process_task_set_io_class(IoClassArgs { tid, class }: IoClassArgs) -> Result {
+    use libsys::syscall::task::{IoClass, CURRENT};
+
+    let class = IoClass::try_from(class).map_err(|_| Error::InvalidArgument)?;
+
+    let tid = if tid == CURRENT { with_task(|task| Ok(task.id().get()))? } else { tid };
+    let info = crate::task::registry::get(tid).ok_or(Error::NoSuchTask)?;
+    check_controls(&info)?;
+    // Real-time I/O is reserved for tasks already trusted with real-time scheduling, which its ceiling gates.
+    if class == IoClass::Realtime && !crate::task::class(info.policy()).is_realtime() {
+        return Err(Error::PermissionDenied);
+    }
+    info.set_io_class(class);
+
+    Ok(Success::Ok)
+}
+
+fn process_task_list(BufferArgs { ptr, len }: BufferArgs) -> Result {
+    let tids = crate::task::registry::tids();
+
+    let slots: alloc::vec::Vec<u32> = tids.iter().take(len).map(|tid| tid.get()).collect();
+    write_user(ptr, &slots)?;
+
+    Ok(Success::Value(tids.len()))
+}
+
+fn process_chdir(args: BufferArgs) -> Result {
+    let path = user_str(args)?;
+
+    with_task(|task| {
+        let cwd = crate::vfs::resolve(&task.process().cwd(), &path)?;
+        if crate::vfs::lookup(crate::vfs::ROOT, &cwd)?.kind() != crate::vfs::NodeKind::Directory {
+            return Err(Error::NotDirectory);
+        }
+
+        task.process().set_cwd(cwd);
+
+        Ok(Success::Ok)
+    })
+}
+
+fn process_getcwd(args: BufferArgs) -> Result {
+    let cwd = with_task(|task| Ok(task.process().cwd().clone()))?;
+    if cwd.len() > args.len {
+        return Err(Error::BufferTooSmall);
+    }
+
+    write_user(args.ptr, cwd.as_bytes())?;
+
+    Ok(Success::Value(cwd.len()))
+}
+
+/// Copies the active task's string at `args`.
+fn user_str(args: BufferArgs) -> Result<String> {
+    String::from_utf8(read_user(args)?).map_err(|err| Error::from(err.utf8_error()))
+}
+
+fn process_open(OpenArgs { path, flags }: OpenArgs) -> Result {
+    let path = user_str(path)?;
+    let flags = OpenFlags::from_bits(flags).ok_or(Error::InvalidArgument)?;
+
+    with_task(|task| {
+        check_quota(crate::task::quota::Resource::Descriptors, task.process().files().count())?;
+        let file = crate::vfs::open(&task.process().cwd(), &path, flags, task.process().owner())?;
+        check_capabilities(task, file.node().required_capabilities())?;
+
+        Ok(Success::Value(task.process().files().insert(file)))
+    })
+}
+
+fn process_close(FdArgs { fd }: FdArgs) -> Result {
+    with_task(|task| {
+        task.process().files().remove(fd)?;
+
+        Ok(Success::Ok)
+    })
+}
+
+fn process_read(IoArgs { fd, buf }: IoArgs) -> Result {
+    let mut data = kernel_buffer(buf.len)?;
+
+    let len = with_task(|task| {
+        let len = task.process().files().get_mut(fd)?.read(&mut data)?;
+        task.info().account(IoKind::File, IoDirection::Read, len);
+
+        Ok(len)
+    })?;
+
+    write_user(buf.ptr, &data[..len])?;
+
+    Ok(Success::Value(len))
+}
+
+fn process_write(IoArgs { fd, buf }: IoArgs) -> Result {
+    let data = read_user(buf)?;
+
+    with_task(|task| {
+        let len = task.process().files().get_mut(fd)?.write(&data)?;
+        task.info().account(IoKind::File, IoDirection::Write, len);
+
+        Ok(Success::Value(len))
+    })
+}
+
+fn process_read_dir(IoArgs { fd, buf }: IoArgs) -> Result {
+    let mut data = kernel_buffer(buf.len)?;
+
+    let len = with_task(|task| Ok(task.process().files().get_mut(fd)?.read_dir(&mut data)?))?;
+    write_user(buf.ptr, &data[..len])?;
+
+    Ok(Success::Value(len))
+}
+
+fn process_stat(StatArgs { path, stat_ptr }: StatArgs) -> Result {
+    let path = user_str(path)?;
+    let metadata = with_task(|task| Ok(crate::vfs::lookup(&task.process().cwd(), &path)?.metadata()))?;
+
+    write_user(stat_ptr, &[Stat::from(metadata)])?;
 
     Ok(Success::Ok)
 }
+
+fn process_create(CreateArgs { path, kind, mode }: CreateArgs) -> Result {
+    let path = user_str(path)?;
+    let kind = match FileKind::try_from(kind).map_err(|_| Error::InvalidArgument)? {
+        FileKind::File => crate::vfs::NodeKind::File,
+        FileKind::Directory => crate::vfs::NodeKind::Directory,
+        // Device nodes are only created by drivers.
+        FileKind::Device => return Err(Error::InvalidArgument),
+    };
+    let mode = Mode::from_bits(mode).ok_or(Error::InvalidArgument)?;
+
+    with_task(|task| {
+        crate::vfs::create(&task.process().cwd(), &path, kind, mode, task.process().owner())?;
+
+        Ok(Success::Ok)
+    })
+}
+
+fn process_unlink(path: BufferArgs) -> Result {
+    let path = user_str(path)?;
+
+    with_task(|task| {
+        crate::vfs::unlink(&task.process().cwd(), &path, task.process().owner())?;
+
+        Ok(Success::Ok)
+    })
+}
+
+fn process_rename(PathPairArgs { from, to }: PathPairArgs) -> Result {
+    let from = user_str(from)?;
+    let to = user_str(to)?;
+
+    with_task(|task| {
+        crate::vfs::rename(&task.process().cwd(), &from, &to, task.process().owner())?;
+
+        Ok(Success::Ok)
+    })
+}
+
+fn process_link(PathPairArgs { from, to }: PathPairArgs) -> Result {
+    let existing = user_str(from)?;
+    let new = user_str(to)?;
+
+    with_task(|task| {
+        crate::vfs::link(&task.process().cwd(), &existing, &new, task.process().owner())?;
+
+        Ok(Success::Ok)
+    })
+}
+
+fn process_control(ControlArgs { fd, request, arg }: ControlArgs) -> Result {
+    // The argument is both read and written by the request, so it's copied back whole.
+    let mut data = read_user(arg)?;
+
+    let value = with_task(|task| Ok(task.process().files().get_mut(fd)?.control(request, &mut data)?))?;
+    write_user(arg.ptr, &data)?;
+
+    Ok(Success::Value(value))
+}
+
+fn process_map(MapArgs { fd, len }: MapArgs) -> Result {
+    use crate::task::MmapPermissions;
+
+    let page_count = libsys::align_up_div(len, libsys::page_shift());
+
+    with_task(|task| {
+        check_capabilities(task, Capabilities::RAW_DEVICE)?;
+        check_region_quota(task)?;
+        let mut files = task.process().files();
+        let file = files.get_mut(fd)?;
+        let permissions = if file.flags().contains(OpenFlags::WRITE) {
+            MmapPermissions::ReadWrite
+        } else {
+            MmapPermissions::ReadOnly
+        };
+        let frames = file.map_frames(page_count)?;
+
+        let mapping =
+            task.process().address_space().map_frames(&frames, permissions, alloc::format!("mmio:fd{fd}")).map_err(
+                |err| {
+                    warn!("Failed to map device frames: {:?}", err);
+                    Error::InvalidArgument
+                },
+            )?;
+
+        Ok(Success::NonNullPtr(mapping.cast()))
+    })
+}
+
+fn process_block_stats(StatsArgs { name, stats_ptr }: StatsArgs) -> Result {
+    let stats = crate::block::get(&user_str(name)?)?.stats();
+
+    write_user(stats_ptr, &[stats])?;
+
+    Ok(Success::Ok)
+}
+
+fn process_block_flush(name: BufferArgs) -> Result {
+    crate::block::get(&user_str(name)?)?.flush()?;
+
+    Ok(Success::Ok)
+}
+
+fn mmap_permissions(protection: u32) -> Result<crate::task::MmapPermissions> {
+    use crate::task::MmapPermissions;
+
+    match Protection::try_from(protection).map_err(|_| Error::InvalidArgument)? {
+        Protection::ReadOnly => Ok(MmapPermissions::ReadOnly),
+        Protection::ReadWrite => Ok(MmapPermissions::ReadWrite),
+        Protection::ReadExecute => Ok(MmapPermissions::ReadExecute),
+    }
+}
+
+fn process_mem_map(MemMapArgs { page_count, protection, flags }: MemMapArgs) -> Result {
+    let page_count = core::num::NonZeroUsize::new(page_count).ok_or(Error::InvalidArgument)?;
+    let permissions = mmap_permissions(protection)?;
+    let flags = MapFlags::from_bits(flags).ok_or(Error::InvalidArgument)?;
+
+    with_task(|task| {
+        check_region_quota(task)?;
+        let mut address_space = task.process().address_space();
+        let mapping = address_space.mmap(None, page_count, permissions)?;
+
+        let address = libsys::Address::new_truncate(mapping.as_non_null_ptr().addr().get());
+        address_space.name_region(address, "anon")?;
+        if flags.contains(MapFlags::ALLOW_WX_TRANSITION) {
+            address_space.allow_wx_transition(address)?;
+        }
+
+        Ok(Success::NonNullPtr(mapping.cast()))
+    })
+}
+
+fn process_mem_protect(ProtectArgs { address, page_count, protection }: ProtectArgs) -> Result {
+    let page_count = core::num::NonZeroUsize::new(page_count).ok_or(Error::InvalidArgument)?;
+    let permissions = mmap_permissions(protection)?;
+    let address = libsys::Address::new(address).ok_or(Error::InvalidPtr)?;
+
+    with_task(|task| {
+        // Changing the protection of part of a region splits it.
+        check_region_quota(task)?;
+        task.process().address_space().protect(address, page_count, permissions)?;
+
+        Ok(Success::Ok)
+    })
+}
+
+fn process_mem_unmap(UnmapArgs { address, page_count }: UnmapArgs) -> Result {
+    let page_count = core::num::NonZeroUsize::new(page_count).ok_or(Error::InvalidArgument)?;
+    let address = libsys::Address::new(address).ok_or(Error::InvalidPtr)?;
+
+    with_task(|task| {
+        task.process().address_space().munmap(address, page_count)?;
+
+        Ok(Success::Ok)
+    })
+}
+
+fn process_mem_map_module(ModuleArgs { name, len_ptr }: ModuleArgs) -> Result {
+    use crate::task::MmapPermissions;
+
+    let name = user_str(name)?;
+    if (len_ptr % core::mem::align_of::<usize>()) > 0 {
+        return Err(Error::InvalidPtr);
+    }
+
+    let module = crate::init::modules::get(&name).ok_or(Error::NotFound)?;
+    let frames = module.frames().ok_or(Error::Unsupported)?;
+
+    let mapping = with_task(|task| {
+        check_capabilities(task, Capabilities::RAW_DEVICE)?;
+        check_region_quota(task)?;
+
+        let mut address_space = task.process().address_space();
+        let mapping = address_space
+            .map_frames(&frames, MmapPermissions::ReadOnly, alloc::format!("module:{name}"))
+            .map_err(|err| {
+                warn!("Failed to map boot module: {:?}", err);
+                Error::InvalidArgument
+            })?;
+        // The module's frames are shared by every task which maps it, so it's never made writable.
+        address_space.seal(libsys::Address::new_truncate(mapping.cast::<u8>().addr().get())).unwrap();
+
+        Ok(mapping)
+    })?;
+
+    write_user(len_ptr, &[module.data().len()])?;
+
+    Ok(Success::NonNullPtr(mapping.cast()))
+}
+
+/// Reads the object open as `fd`, returning its contents and program headers.
+fn read_object(fd: usize) -> Result<(alloc::vec::Vec<u8>, alloc::vec::Vec<elf::segment::ProgramHeader>)> {
+    let data = with_task(|task| Ok(task.process().files().get_mut(fd)?.read_to_end()?))?;
+    let segments = elf::ElfBytes::<elf::endian::AnyEndian>::minimal_parse(&data)
+        .map_err(|_| Error::InvalidArgument)?
+        .segments()
+        .map(|segments| segments.into_iter().collect())
+        .unwrap_or_default();
+
+    Ok((data, segments))
+}
+
+fn process_dl_program_headers(HeadersArgs { fd, headers_ptr, count }: HeadersArgs) -> Result {
+    use libsys::syscall::dl::ProgramHeader;
+
+    let (_, segments) = read_object(fd)?;
+
+    let headers: alloc::vec::Vec<ProgramHeader> = segments
+        .iter()
+        .take(count)
+        .map(|segment| ProgramHeader {
+            p_type: segment.p_type,
+            p_flags: segment.p_flags,
+            p_offset: segment.p_offset,
+            p_vaddr: segment.p_vaddr,
+            p_paddr: segment.p_paddr,
+            p_filesz: segment.p_filesz,
+            p_memsz: segment.p_memsz,
+            p_align: segment.p_align,
+        })
+        .collect();
+    write_user(headers_ptr, &headers)?;
+
+    Ok(Success::Value(segments.len()))
+}
+
+fn process_dl_map_segment(SegmentArgs { fd, index, base }: SegmentArgs) -> Result {
+    use core::num::NonZeroUsize;
+    use libsys::{page_size, Address};
+
+    let to_usize = |value: u64| usize::try_from(value).map_err(|_| Error::InvalidArgument);
+
+    let (data, segments) = read_object(fd)?;
+    let segment = segments.get(index).ok_or(Error::InvalidArgument)?;
+    if segment.p_type != elf::abi::PT_LOAD || segment.p_filesz > segment.p_memsz {
+        return Err(Error::InvalidArgument);
+    } else if (segment.p_flags & elf::abi::PF_W) > 0 && (segment.p_flags & elf::abi::PF_X) > 0 {
+        return Err(Error::WxViolation);
+    }
+
+    let start = base.checked_add(to_usize(segment.p_vaddr)?).ok_or(Error::InvalidArgument)?;
+    let end = start.checked_add(to_usize(segment.p_memsz)?).ok_or(Error::InvalidArgument)?;
+    let file_start = to_usize(segment.p_offset)?;
+    let file_end = file_start.checked_add(to_usize(segment.p_filesz)?).ok_or(Error::InvalidArgument)?;
+    let file_data = data.get(file_start..file_end).ok_or(Error::InvalidArgument)?;
+    let permissions = crate::task::segment_to_mmap_permissions(segment.p_flags);
+
+    with_task(|task| {
+        check_region_quota(task)?;
+        let mut address_space = task.process().address_space();
+
+        // A page shared with a previously mapped segment is covered by (and written through) its region.
+        let mut start_index = start / page_size();
+        if let Some(region) = address_space.region(Address::from_index(start_index).ok_or(Error::InvalidPtr)?) {
+            start_index = region.start.index() + region.page_count.get();
+        }
+        if let Some(page_count) = end.div_ceil(page_size()).checked_sub(start_index).and_then(NonZeroUsize::new) {
+            let address = Address::from_index(start_index).ok_or(Error::InvalidPtr)?;
+            address_space.map_loaded(address, page_count, permissions, alloc::format!("dl:fd{fd}"))?;
+        }
+
+        address_space.write_loaded(Address::new(start).ok_or(Error::InvalidPtr)?, file_data)?;
+
+        Ok(Success::NonNullPtr(core::ptr::NonNull::new(start as *mut core::ffi::c_void).ok_or(Error::InvalidPtr)?))
+    })
+}
+
+fn process_dl_relocate(RelocateArgs { relocations_ptr, count }: RelocateArgs) -> Result {
+    use libsys::syscall::dl::Relocation;
+
+    if (relocations_ptr % core::mem::align_of::<Relocation>()) > 0 {
+        return Err(Error::InvalidPtr);
+    }
+
+    // Copied out, as the relocations may themselves lie within memory they relocate.
+    // Safety: Relocations are plain integers, so any bytes are valid.
+    let relocations = unsafe { crate::mem::user::read::<Relocation>(relocations_ptr, count)? };
+
+    with_task(|task| {
+        for Relocation { address, value } in relocations {
+            let address = usize::try_from(address)
+                .ok()
+                .and_then(libsys::Address::<libsys::Virtual>::new)
+                .ok_or(Error::InvalidPtr)?;
+            task.process().address_space().relocate_loaded(address, &value.to_ne_bytes())?;
+        }
+
+        Ok(Success::Ok)
+    })
+}
+
+fn process_time_get(ClockArgs { clock }: ClockArgs) -> Result {
+    use libsys::syscall::time::Clock;
+
+    let time = match Clock::try_from(clock).map_err(|_| Error::InvalidArgument)? {
+        Clock::Monotonic => crate::time::uptime(),
+        Clock::Realtime => crate::time::now().ok_or(Error::Unsupported)?,
+    };
+
+    Ok(Success::Value(usize::try_from(time.as_nanos()).unwrap_or(usize::MAX)))
+}
+
+fn process_group_create(GroupCreateArgs { shares }: GroupCreateArgs) -> Result {
+    let group = crate::task::group::create(shares)?;
+
+    Ok(Success::Value(group as usize))
+}
+
+fn process_group_destroy(GroupArgs { group }: GroupArgs) -> Result {
+    crate::task::group::destroy(group)?;
+
+    Ok(Success::Ok)
+}
+
+fn process_group_set_shares(SharesArgs { group, shares }: SharesArgs) -> Result {
+    crate::task::group::set_shares(group, shares)?;
+
+    Ok(Success::Ok)
+}
+
+fn process_group_attach(AttachArgs { tid, group }: AttachArgs) -> Result {
+    use libsys::syscall::task::CURRENT;
+
+    let tid = if tid == CURRENT { with_task(|task| Ok(task.id().get()))? } else { tid };
+    let info = crate::task::registry::get(tid).ok_or(Error::NoSuchTask)?;
+    check_controls(&info)?;
+    crate::task::group::attach(&info, group)?;
+
+    Ok(Success::Ok)
+}
+
+fn process_group_info(
+    libsys::syscall::group::InfoArgs { group, info_ptr }: libsys::syscall::group::InfoArgs,
+) -> Result {
+    let info = crate::task::group::snapshot(group)?;
+
+    write_user(info_ptr, &[info])?;
+
+    Ok(Success::Ok)
+}
+
+fn process_cred_get(GetArgs { credentials_ptr }: GetArgs) -> Result {
+    let credentials = with_task(|task| Ok(task.process().credentials()))?;
+
+    write_user(credentials_ptr, &[credentials])?;
+
+    Ok(Success::Ok)
+}
+
+fn process_cred_set(SetArgs { uid, gid, capabilities }: SetArgs) -> Result {
+    let capabilities = Capabilities::from_bits(capabilities).ok_or(Error::InvalidArgument)?;
+
+    with_task(|task| {
+        task.process().set_credentials(Credentials { uid, gid, capabilities }).map_err(|()| Error::PermissionDenied)?;
+
+        Ok(Success::Ok)
+    })
+}
+
+/// Validates the address of a futex word, which must be aligned (so that it can't straddle pages).
+fn futex_address(addr: usize) -> Result<libsys::Address<libsys::Virtual>> {
+    if (addr % core::mem::align_of::<u32>()) > 0 || !crate::mem::layout::Region::Userspace.addresses().contains(&addr) {
+        return Err(Error::InvalidPtr);
+    }
+
+    libsys::Address::new(addr).ok_or(Error::InvalidPtr)
+}
+
+fn process_futex_wake(futex::WakeArgs { addr, count }: futex::WakeArgs) -> Result {
+    let address = futex_address(addr)?;
+    let woken = crate::cpu::state::with_scheduler(|scheduler| scheduler.futex_wake(address, count))?;
+
+    Ok(Success::Value(woken))
+}
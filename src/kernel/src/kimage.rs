@@ -0,0 +1,220 @@
+//! Description of the kernel's own image, retained from its ELF after boot: where each loaded segment lies (and with
+//! which permissions), and which ranges hold its text, read-only data, data, and BSS.
+//!
+//! Addresses are those the image runs at, rather than those it was linked at, so they can be compared against
+//! instruction pointers and mappings directly.
+
+use crate::task::MmapPermissions;
+use alloc::{string::String, vec::Vec};
+use core::{fmt, ops::Range};
+use elf::{abi, endian::AnyEndian};
+
+crate::error_impl! {
+    #[derive(Debug)]
+    pub enum Error {
+        /// The bootloader didn't report where it loaded the kernel.
+        KernelAddress => None,
+        Elf { err: elf::ParseError } => Some(err),
+        /// The kernel's ELF has no loadable segments, or no section headers.
+        NoLayout => None
+    }
+}
+
+/// A loadable segment of the image.
+#[derive(Debug, Clone)]
+pub struct Segment {
+    /// Virtual range the segment occupies (which needn't be page-aligned).
+    pub range: Range<usize>,
+    /// Physical address of the start of the segment.
+    pub physical: usize,
+    pub permissions: MmapPermissions,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SectionKind {
+    Text,
+    ReadOnly,
+    Data,
+    Bss,
+}
+
+impl SectionKind {
+    fn from_header(header: &elf::section::SectionHeader) -> Option<Self> {
+        let has_flag = |flag: u32| (header.sh_flags & u64::from(flag)) > 0;
+
+        // Thread-local BSS is only a template for each thread's copy, so takes no space in the image itself.
+        if !has_flag(abi::SHF_ALLOC) || (has_flag(abi::SHF_TLS) && header.sh_type == abi::SHT_NOBITS) {
+            None
+        } else if has_flag(abi::SHF_EXECINSTR) {
+            Some(Self::Text)
+        } else if !has_flag(abi::SHF_WRITE) {
+            Some(Self::ReadOnly)
+        } else if header.sh_type == abi::SHT_NOBITS {
+            Some(Self::Bss)
+        } else {
+            Some(Self::Data)
+        }
+    }
+
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Text => "text",
+            Self::ReadOnly => "rodata",
+            Self::Data => "data",
+            Self::Bss => "bss",
+        }
+    }
+}
+
+/// An allocated section of the image.
+#[derive(Debug, Clone)]
+pub struct Section {
+    pub name: String,
+    pub kind: SectionKind,
+    /// Virtual range the section occupies.
+    pub range: Range<usize>,
+}
+
+#[derive(Debug)]
+pub struct Image {
+    /// Physical and virtual base the image was loaded at.
+    pub physical_base: usize,
+    pub virtual_base: usize,
+    pub segments: Vec<Segment>,
+    /// Allocated sections, ordered by address.
+    pub sections: Vec<Section>,
+}
+
+impl Image {
+    /// Virtual range spanned by the image's segments.
+    pub fn range(&self) -> Range<usize> {
+        let start = self.segments.iter().map(|segment| segment.range.start).min().unwrap_or(self.virtual_base);
+        let end = self.segments.iter().map(|segment| segment.range.end).max().unwrap_or(self.virtual_base);
+
+        start..end
+    }
+
+    /// Returns the section containing `address`, if any.
+    pub fn section_of(&self, address: usize) -> Option<&Section> {
+        self.sections.iter().find(|section| section.range.contains(&address))
+    }
+
+    /// Returns the ranges of every section of `kind`.
+    pub fn ranges_of(&self, kind: SectionKind) -> impl Iterator<Item = Range<usize>> + '_ {
+        self.sections.iter().filter(move |section| section.kind == kind).map(|section| section.range.clone())
+    }
+}
+
+impl fmt::Display for Image {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for segment in &self.segments {
+            writeln!(
+                f,
+                "segment {:#018X}..{:#018X} @ {:#X} {:?}",
+                segment.range.start, segment.range.end, segment.physical, segment.permissions
+            )?;
+        }
+
+        for section in &self.sections {
+            writeln!(
+                f,
+                "section {:#018X}..{:#018X} {:<6} {}",
+                section.range.start,
+                section.range.end,
+                section.kind.as_str(),
+                section.name
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+static IMAGE: spin::Once<Image> = spin::Once::new();
+
+fn get_kernel_addresses() -> Result<(usize, usize)> {
+    #[limine::limine_tag]
+    static LIMINE_KERNEL_ADDR: limine::KernelAddressRequest =
+        limine::KernelAddressRequest::new(crate::init::boot::LIMINE_REV);
+
+    LIMINE_KERNEL_ADDR
+        .get_response()
+        .map(|response| {
+            (usize::try_from(response.physical_base()).unwrap(), usize::try_from(response.virtual_base()).unwrap())
+        })
+        .ok_or(Error::KernelAddress)
+}
+
+/// Parses and retains the layout of the kernel's image from its ELF, `kernel_file`.
+pub fn init(kernel_file: &limine::File) -> Result<()> {
+    extern "C" {
+        static KERNEL_BASE: libkernel::LinkerSymbol;
+    }
+
+    IMAGE.try_call_once(|| {
+        let (physical_base, virtual_base) = get_kernel_addresses()?;
+        // Safety: `KERNEL_BASE` is a linker symbol to an in-executable memory location, so it is guaranteed to be
+        //         valid (and is never written to).
+        let link_base = unsafe { KERNEL_BASE.as_usize() };
+        let relocate = |link_address: u64| usize::try_from(link_address).unwrap() - link_base + virtual_base;
+
+        let kernel_elf =
+            elf::ElfBytes::<AnyEndian>::minimal_parse(kernel_file.data()).map_err(|err| Error::Elf { err })?;
+
+        let segments: Vec<Segment> = kernel_elf
+            .segments()
+            .ok_or(Error::NoLayout)?
+            .into_iter()
+            .filter(|phdr| phdr.p_type == abi::PT_LOAD)
+            .map(|phdr| {
+                let start = relocate(phdr.p_vaddr);
+
+                Segment {
+                    range: start..(start + usize::try_from(phdr.p_memsz).unwrap()),
+                    physical: physical_base + (start - virtual_base),
+                    permissions: crate::task::segment_to_mmap_permissions(phdr.p_flags),
+                }
+            })
+            .collect();
+        if segments.is_empty() {
+            return Err(Error::NoLayout);
+        }
+
+        let (Some(headers), Some(strtab)) =
+            kernel_elf.section_headers_with_strtab().map_err(|err| Error::Elf { err })?
+        else {
+            return Err(Error::NoLayout);
+        };
+        let mut sections: Vec<Section> = headers
+            .iter()
+            .filter(|header| header.sh_size > 0)
+            .filter_map(|header| {
+                let kind = SectionKind::from_header(&header)?;
+                let start = relocate(header.sh_addr);
+
+                Some(Section {
+                    name: String::from(strtab.get(header.sh_name as usize).unwrap_or("?")),
+                    kind,
+                    range: start..(start + usize::try_from(header.sh_size).unwrap()),
+                })
+            })
+            .collect();
+        sections.sort_unstable_by_key(|section| section.range.start);
+
+        Ok(Image { physical_base, virtual_base, segments, sections })
+    })?;
+
+    Ok(())
+}
+
+/// Publishes the image's layout to sysfs (`kernel/image`).
+pub fn publish() {
+    if let Some(image) = IMAGE.get() {
+        crate::sysfs::set("kernel", "image", alloc::format!("{image}"));
+    }
+}
+
+/// Returns the layout of the kernel's image, once [`init`] has parsed it.
+pub fn get() -> Option<&'static Image> {
+    IMAGE.get()
+}
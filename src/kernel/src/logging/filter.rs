@@ -0,0 +1,122 @@
+//! Runtime filtering of log records by severity and module, configured with directives such as `info,mem::alloc=trace`:
+//! a bare level sets the default, and `<module>=<level>` overrides it for a module (and the modules within it).
+//!
+//! Directives are held in fixed tables, as they may be set (i.e. from the command line) before the allocator is up.
+
+use crate::interrupts::InterruptCell;
+use log::LevelFilter;
+use spin::Mutex;
+
+/// Most module overrides a filter holds.
+const MAX_DIRECTIVES: usize = 16;
+/// Longest module path an override may name.
+const MAX_MODULE_LEN: usize = 48;
+
+crate::error_impl! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Error {
+        InvalidLevel => None,
+        TooManyDirectives => None,
+        ModuleTooLong => None
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Directive {
+    module: [u8; MAX_MODULE_LEN],
+    module_len: usize,
+    level: LevelFilter,
+}
+
+impl Directive {
+    fn module(&self) -> &str {
+        // Copied from a `str`, so it's valid UTF-8.
+        core::str::from_utf8(&self.module[..self.module_len]).unwrap()
+    }
+
+    fn matches(&self, module: &str) -> bool {
+        module.strip_prefix(self.module()).is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Filter {
+    default: LevelFilter,
+    directives: [Option<Directive>; MAX_DIRECTIVES],
+}
+
+impl Filter {
+    const fn new(default: LevelFilter) -> Self {
+        Self { default, directives: [None; MAX_DIRECTIVES] }
+    }
+
+    fn parse(directives: &str) -> Result<Self> {
+        let mut filter = Self::new(DEFAULT_LEVEL);
+        let mut slots = filter.directives.iter_mut();
+
+        for directive in directives.split(',').map(str::trim).filter(|directive| !directive.is_empty()) {
+            match directive.split_once('=') {
+                None => filter.default = parse_level(directive)?,
+
+                Some((module, level)) => {
+                    let mut buf = [0u8; MAX_MODULE_LEN];
+                    buf.get_mut(..module.len()).ok_or(Error::ModuleTooLong)?.copy_from_slice(module.as_bytes());
+
+                    let slot = slots.next().ok_or(Error::TooManyDirectives)?;
+                    *slot = Some(Directive { module: buf, module_len: module.len(), level: parse_level(level)? });
+                }
+            }
+        }
+
+        Ok(filter)
+    }
+
+    /// Level of the records let through from `module`, as set by the most specific directive naming it (or a module
+    /// it's within), if any.
+    fn level(&self, module: &str) -> LevelFilter {
+        self.directives
+            .iter()
+            .flatten()
+            .filter(|directive| directive.matches(module))
+            .max_by_key(|directive| directive.module_len)
+            .map_or(self.default, |directive| directive.level)
+    }
+
+    /// Most verbose level let through from any module.
+    fn max_level(&self) -> LevelFilter {
+        self.directives.iter().flatten().map(|directive| directive.level).fold(self.default, Ord::max)
+    }
+}
+
+const DEFAULT_LEVEL: LevelFilter = LevelFilter::Trace;
+
+static FILTER: InterruptCell<Mutex<Filter>> = InterruptCell::new(Mutex::new(Filter::new(DEFAULT_LEVEL)));
+
+fn parse_level(level: &str) -> Result<LevelFilter> {
+    level.parse().map_err(|_| Error::InvalidLevel)
+}
+
+/// Strips the crate name from `target`, so that directives name modules as they're pathed within the kernel.
+fn module_of(target: &str) -> &str {
+    target.split_once("::").map_or("", |(_, module)| module)
+}
+
+/// Replaces the filter with one configured by `directives`.
+pub fn set(directives: &str) -> Result<()> {
+    let filter = Filter::parse(directives)?;
+    FILTER.with(|current| *current.lock() = filter);
+    log::set_max_level(filter.max_level());
+
+    Ok(())
+}
+
+/// Most verbose level the filter lets through from any module.
+pub fn max_level() -> LevelFilter {
+    FILTER.with(|filter| filter.lock().max_level())
+}
+
+pub fn enabled(metadata: &log::Metadata) -> bool {
+    let module = module_of(metadata.target());
+
+    FILTER.with(|filter| metadata.level() <= filter.lock().level(module))
+}
@@ -0,0 +1,186 @@
+//! Kernel logging: records which pass the [`filter`] are kept in the [`ring`]s of recent records (drained through
+//! `/dev/kmsg`), and written to the serial console.
+
+pub mod filter;
+pub mod ring;
+
+use crate::interrupts::InterruptCell;
+use core::time::Duration;
+use spin::Mutex;
+use uart::{Data, Uart, UartWriter};
+
+pub struct Serial(InterruptCell<Mutex<UartWriter>>);
+
+// Safety: Interior address is not thread-specific.
+unsafe impl Send for Serial {}
+// Safety: This isn't actually safe. It relies entirely on only
+//         one `Serial` being created and used at a time.
+//         So basically, TODO.
+unsafe impl Sync for Serial {}
+
+impl Serial {
+    fn write_record(&self, uptime: Duration, record: &log::Record) {
+        let uptime = crate::time::Seconds(uptime);
+        self.0.with(|uart| {
+            use core::fmt::Write;
+
+            let mut uart = uart.lock();
+
+            uart.write_fmt(format_args!("[{uptime}][{level}] {args}\n", level = record.level(), args = record.args()))
+                .unwrap();
+        });
+    }
+}
+
+struct Logger;
+
+impl log::Log for Logger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        filter::enabled(metadata)
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            let uptime = crate::time::uptime_nowait();
+
+            ring::push(record, uptime);
+            if let Some(serial) = SERIAL_UART.as_ref() {
+                serial.write_record(uptime, record);
+            }
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: Logger = Logger;
+
+crate::error_impl! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Error {
+        SetLogger => None,
+        NoLogger => None
+    }
+}
+
+static SERIAL_UART: spin::Lazy<Option<Serial>> = spin::Lazy::new(|| {
+    crate::interrupts::without(|| {
+        UartWriter::new(
+            #[cfg(target_arch = "x86_64")]
+            // Safety: Constructor is called only once, with a hopefully-valid address.
+            unsafe {
+                Uart::<Data>::new(uart::COM1)
+            },
+        )
+        .map(Mutex::new)
+        .map(InterruptCell::new)
+        .map(Serial)
+    })
+});
+
+/// Writes `bytes` to the serial console, between (rather than within) log records.
+///
+/// #### Remark
+///
+/// Only the valid UTF-8 portions of `bytes` are written.
+pub fn write_serial(bytes: &[u8]) -> Result<()> {
+    use core::fmt::Write;
+
+    let serial = SERIAL_UART.as_ref().ok_or(Error::NoLogger)?;
+    serial.0.with(|uart| {
+        let mut uart = uart.lock();
+        for chunk in bytes.utf8_chunks() {
+            uart.write_str(chunk.valid()).unwrap();
+        }
+    });
+
+    Ok(())
+}
+
+pub fn init() -> Result<()> {
+    log::set_max_level(filter::max_level());
+    log::set_logger(&LOGGER).map_err(|_| Error::SetLogger)?;
+
+    Ok(())
+}
+
+static KMSG_READER: InterruptCell<Mutex<ring::Reader>> = InterruptCell::new(Mutex::new(ring::Reader::new()));
+
+/// Registers the kernel log device node, `/dev/kmsg`.
+pub fn init_kmsg() -> crate::vfs::Result<()> {
+    crate::vfs::devfs::register("kmsg", alloc::sync::Arc::new(Kmsg))
+}
+
+struct Kmsg;
+
+impl crate::vfs::Node for Kmsg {
+    fn metadata(&self) -> crate::vfs::Metadata {
+        crate::vfs::Metadata {
+            kind: crate::vfs::NodeKind::Device,
+            size: 0,
+            mode: libsys::syscall::fs::Mode::from_bits_retain(0o600),
+            owner: crate::vfs::Owner::ROOT,
+            accessed: 0,
+            modified: 0,
+            created: 0,
+        }
+    }
+
+    /// Drains as many whole records as fit in `buf`, one per line.
+    fn read(&self, _offset: usize, buf: &mut [u8]) -> crate::vfs::Result<usize> {
+        use core::fmt::Write;
+
+        KMSG_READER.with(|reader| {
+            let mut reader = reader.lock();
+
+            let mut cursor = Cursor { buf, len: 0 };
+            while let Some(record) = reader.read() {
+                let start = cursor.len;
+                let line = format_args!(
+                    "[{}][{}][{}] {}: {}\n",
+                    crate::time::Seconds(record.uptime),
+                    record.core,
+                    record.level,
+                    record.module(),
+                    record.message()
+                );
+
+                if cursor.write_fmt(line).is_err() {
+                    reader.unread(record);
+                    if start == 0 {
+                        return Err(crate::vfs::Error::BufferTooSmall);
+                    }
+
+                    cursor.len = start;
+                    break;
+                }
+            }
+
+            Ok(cursor.len)
+        })
+    }
+
+    /// Replaces the log filter with the directives in `buf` (see [`filter`]).
+    fn write(&self, _offset: usize, buf: &[u8]) -> crate::vfs::Result<usize> {
+        let directives = core::str::from_utf8(buf).map_err(|_| crate::vfs::Error::InvalidArgument)?;
+        filter::set(directives.trim_end_matches('\n')).map_err(|_| crate::vfs::Error::InvalidArgument)?;
+
+        Ok(buf.len())
+    }
+}
+
+/// Writes into a buffer, failing (rather than truncating) on what doesn't fit.
+struct Cursor<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl core::fmt::Write for Cursor<'_> {
+    fn write_str(&mut self, str: &str) -> core::fmt::Result {
+        let end = self.len + str.len();
+        self.buf.get_mut(self.len..end).ok_or(core::fmt::Error)?.copy_from_slice(str.as_bytes());
+        self.len = end;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,221 @@
+//! Rings of recent log records, one per core (shared beyond [`RINGS`] cores), which a [`Reader`] drains.
+//!
+//! A ring is written without locks: a writer reserves the next sequence number, and stamps its slot with it once the
+//! record is written. Readers check the stamp again after copying a slot out, so a record overwritten mid-copy (as the
+//! ring wraps) is skipped rather than torn. Writers may also race on a ring (i.e. an interrupt handler logging over
+//! the task it interrupted), each within the slot it reserved.
+
+use core::{
+    cell::UnsafeCell,
+    fmt::Write,
+    mem::MaybeUninit,
+    sync::atomic::{fence, AtomicU64, Ordering},
+    time::Duration,
+};
+
+/// Rings the cores' records are spread between.
+const RINGS: usize = 8;
+/// Records each ring holds before its oldest are overwritten.
+const SLOTS: usize = 128;
+/// Bytes kept of each record's module path and message, beyond which they're truncated.
+const MODULE_LEN: usize = 48;
+const MESSAGE_LEN: usize = 128;
+
+#[derive(Clone, Copy)]
+pub struct Record {
+    /// Uptime at which the record was logged.
+    pub uptime: Duration,
+    pub level: log::Level,
+    /// Index of the core which logged the record.
+    pub core: u16,
+    module: Text<MODULE_LEN>,
+    message: Text<MESSAGE_LEN>,
+}
+
+impl Record {
+    pub fn module(&self) -> &str {
+        self.module.as_str()
+    }
+
+    pub fn message(&self) -> &str {
+        self.message.as_str()
+    }
+}
+
+/// Text truncated to fit `N` bytes.
+#[derive(Clone, Copy)]
+struct Text<const N: usize> {
+    bytes: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> Text<N> {
+    const fn new() -> Self {
+        Self { bytes: [0; N], len: 0 }
+    }
+
+    fn as_str(&self) -> &str {
+        // Only whole characters are written.
+        core::str::from_utf8(&self.bytes[..self.len]).unwrap()
+    }
+}
+
+impl<const N: usize> Write for Text<N> {
+    fn write_str(&mut self, str: &str) -> core::fmt::Result {
+        let mut len = str.len().min(N - self.len);
+        while !str.is_char_boundary(len) {
+            len -= 1;
+        }
+
+        self.bytes[self.len..(self.len + len)].copy_from_slice(&str.as_bytes()[..len]);
+        self.len += len;
+
+        Ok(())
+    }
+}
+
+struct Slot {
+    /// `(sequence * 2) + 2` once the record of `sequence` is written, and odd while a record is being written.
+    stamp: AtomicU64,
+    record: UnsafeCell<MaybeUninit<Record>>,
+}
+
+struct Ring {
+    /// Sequence number of the next record to be written.
+    next: AtomicU64,
+    slots: [Slot; SLOTS],
+}
+
+// Safety: A slot is only written by the writer which reserved it, and its record is only read out if its stamp shows
+//         it wasn't written meanwhile.
+unsafe impl Sync for Ring {}
+
+impl Ring {
+    const fn new() -> Self {
+        Self {
+            next: AtomicU64::new(0),
+            slots: [const { Slot { stamp: AtomicU64::new(0), record: UnsafeCell::new(MaybeUninit::uninit()) } }; SLOTS],
+        }
+    }
+
+    fn slot(&self, sequence: u64) -> &Slot {
+        &self.slots[usize::try_from(sequence % (SLOTS as u64)).unwrap()]
+    }
+
+    fn push(&self, record: &Record) {
+        let sequence = self.next.fetch_add(1, Ordering::Relaxed);
+        let slot = self.slot(sequence);
+
+        slot.stamp.store((sequence * 2) + 1, Ordering::Relaxed);
+        fence(Ordering::Release);
+        // Safety: The slot was reserved for this record, and readers discard whatever they copy out of it meanwhile.
+        unsafe { slot.record.get().write_volatile(MaybeUninit::new(*record)) };
+        slot.stamp.store((sequence * 2) + 2, Ordering::Release);
+    }
+
+    /// Copies out the record of `sequence`, or returns `None` if it isn't (or is no longer) held whole by its slot.
+    fn read(&self, sequence: u64) -> Option<Record> {
+        let slot = self.slot(sequence);
+        let stamp = (sequence * 2) + 2;
+        if slot.stamp.load(Ordering::Acquire) != stamp {
+            return None;
+        }
+
+        // Safety: The slot's stamp shows it holds a whole record, and the copy is only kept if it still does after.
+        let record = unsafe { slot.record.get().read_volatile() };
+        fence(Ordering::Acquire);
+
+        // Safety: The stamp is unchanged, so the record was written in full before the copy, and not since.
+        (slot.stamp.load(Ordering::Relaxed) == stamp).then(|| unsafe { record.assume_init() })
+    }
+}
+
+static RECORDS: [Ring; RINGS] = [const { Ring::new() }; RINGS];
+
+/// Records `record`, logged at `uptime`, into the ring of the local core.
+pub fn push(record: &log::Record, uptime: Duration) {
+    let core = crate::cpu::state::get_core_index().unwrap_or(0);
+
+    let mut entry = Record {
+        uptime,
+        level: record.level(),
+        core: u16::try_from(core).unwrap_or(u16::MAX),
+        module: Text::new(),
+        message: Text::new(),
+    };
+    // Neither write fails, as they truncate instead.
+    entry.module.write_str(record.target()).ok();
+    entry.message.write_fmt(*record.args()).ok();
+
+    RECORDS[core % RINGS].push(&entry);
+}
+
+/// Cursor over the records of every ring, which yields them in the order they were logged.
+pub struct Reader {
+    next: [u64; RINGS],
+    /// A record taken, but given back by [`Reader::unread`].
+    pending: Option<Record>,
+    /// Records overwritten before they could be read.
+    pub dropped: u64,
+}
+
+impl Default for Reader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Reader {
+    pub const fn new() -> Self {
+        Self { next: [0; RINGS], pending: None, dropped: 0 }
+    }
+
+    /// Takes the oldest record not yet read, or returns `None` if every record has been.
+    pub fn read(&mut self) -> Option<Record> {
+        if let Some(record) = self.pending.take() {
+            return Some(record);
+        }
+
+        let mut oldest: Option<(usize, Record)> = None;
+        for (index, ring) in RECORDS.iter().enumerate() {
+            let Some(record) = self.peek(index, ring) else { continue };
+            if oldest.is_none_or(|(_, oldest)| record.uptime < oldest.uptime) {
+                oldest = Some((index, record));
+            }
+        }
+
+        let (index, record) = oldest?;
+        self.next[index] += 1;
+
+        Some(record)
+    }
+
+    /// Gives `record` back, to be taken again by the next [`Reader::read`] (i.e. when it didn't fit a buffer).
+    pub fn unread(&mut self, record: Record) {
+        self.pending = Some(record);
+    }
+
+    /// Reads the next record of the ring at `index`, skipping those overwritten, or returns `None` if the ring has none
+    /// (or its next is still being written).
+    fn peek(&mut self, index: usize, ring: &Ring) -> Option<Record> {
+        loop {
+            let written = ring.next.load(Ordering::Acquire);
+            let held_from = written.saturating_sub(SLOTS as u64);
+
+            if self.next[index] < held_from {
+                self.dropped += held_from - self.next[index];
+                self.next[index] = held_from;
+            }
+            if self.next[index] >= written {
+                return None;
+            }
+
+            match ring.read(self.next[index]) {
+                Some(record) => return Some(record),
+                // Still being written, as it hasn't since been overwritten.
+                None if ring.next.load(Ordering::Acquire) < (self.next[index] + (SLOTS as u64)) => return None,
+                None => {}
+            }
+        }
+    }
+}
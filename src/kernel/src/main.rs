@@ -69,16 +69,33 @@ extern crate log;
 
 mod acpi;
 mod arch;
+mod block;
 mod cpu;
+mod driver;
+mod efi;
 mod error;
+mod fb;
+mod fdt;
+#[cfg(target_arch = "riscv64")]
+mod goldfish_rtc;
 mod init;
+mod input;
 mod interrupts;
+mod kimage;
 mod logging;
 mod mem;
 mod panic;
+mod platform;
+mod power;
 mod rand;
+mod smbios;
+mod sysfs;
 mod task;
 mod time;
+mod tty;
+mod vfs;
+#[cfg(feature = "virtio")]
+mod virtio;
 
 /// ### Safety
 ///
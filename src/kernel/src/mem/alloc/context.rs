@@ -0,0 +1,76 @@
+//! Allocation contexts, through which a subsystem states where the frames it allocates (including those backing the
+//! kernel heap) should come from, i.e. a driver allocating the queues of a device which only addresses 32 bits.
+//!
+//! A context applies to the allocations made on the core which pushed it, until it's popped. It's pushed with
+//! interrupts disabled, so no other task's allocations are made under it.
+
+use core::num::NonZeroU32;
+use libsys::{page_shift, Address, Frame};
+use spin::Mutex;
+
+/// Range of physical memory which frames must lie within.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Zone {
+    #[default]
+    Any,
+    /// Frames below 4 GiB, for devices which only address 32 bits.
+    Dma32,
+}
+
+impl Zone {
+    /// Index of the first frame beyond the zone.
+    pub fn end_index(self) -> usize {
+        match self {
+            Self::Any => usize::MAX,
+            Self::Dma32 => (1 << 32) >> page_shift().get(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Context {
+    /// NUMA node the frames should be local to, if possible.
+    ///
+    /// #### Remark
+    ///
+    /// The platform's affinity tables aren't parsed, so all memory is treated as local to every node, and the
+    /// preference is always met.
+    pub node: Option<u32>,
+    /// Zone the frames must lie within.
+    pub zone: Zone,
+    /// Least alignment of the frames, as a power of two (in bytes).
+    pub align_bits: Option<NonZeroU32>,
+}
+
+impl Context {
+    /// Whether `frame` lies within the context's zone, at its alignment.
+    pub fn admits(&self, frame: Address<Frame>) -> bool {
+        frame.index() < self.zone.end_index()
+            && self.align_bits.map_or(true, |align_bits| frame.get().get().trailing_zeros() >= align_bits.get())
+    }
+}
+
+/// Context of the boot core until its local state is initialized (before which no other core runs).
+static BOOT_CONTEXT: Mutex<Context> = Mutex::new(Context { node: None, zone: Zone::Any, align_bits: None });
+
+/// Replaces the local core's context with `context`, returning the previous.
+fn replace(context: Context) -> Context {
+    crate::cpu::state::replace_alloc_context(context)
+        .unwrap_or_else(|_| core::mem::replace(&mut *BOOT_CONTEXT.lock(), context))
+}
+
+/// Runs `func` with `context` pushed on the local core, popping it once `func` returns.
+pub fn with<T>(context: Context, func: impl FnOnce() -> T) -> T {
+    crate::interrupts::without(|| {
+        let prev = replace(context);
+        let result = func();
+        replace(prev);
+
+        result
+    })
+}
+
+/// Context of the allocations made on the local core.
+pub fn current() -> Context {
+    crate::cpu::state::get_alloc_context().unwrap_or_else(|_| *BOOT_CONTEXT.lock())
+}
@@ -0,0 +1,321 @@
+//! Pools of objects allocated up-front, for interrupt handlers (and the paths they feed, i.e. packet reception), which
+//! mustn't allocate (with the `alloc_audit` feature, the allocator panics if they do).
+//!
+//! Objects are taken from, and returned to, a lock-free freelist, so an interrupt handler never waits on a core it
+//! interrupted. Taking from an exhausted pool fails, rather than allocating more, so a handler must be able to drop
+//! its work when it does.
+//!
+//! A take which leaves fewer objects than a pool's low watermark free flags the pool, and kicks a single work item
+//! shared by every pool (see [`crate::task::workqueue::StaticWork`]), which grows each flagged pool by another segment,
+//! as many objects as it started with. Segments are never freed, so an object's slot stays valid for as long as the
+//! pool does; and pools are leaked on creation, so that's forever.
+//!
+//! #### Remark
+//!
+//! The freelist's head packs a generation into its upper half, incremented by every change, so a taker that read a
+//! since-recycled head fails its exchange rather than corrupting the list (the ABA problem).
+
+use crate::task::workqueue::StaticWork;
+use alloc::{boxed::Box, vec::Vec};
+use core::{
+    cell::UnsafeCell,
+    num::NonZeroUsize,
+    ops::{Deref, DerefMut},
+    ptr::NonNull,
+    sync::atomic::{AtomicBool, AtomicPtr, AtomicU32, AtomicU64, AtomicUsize, Ordering},
+};
+use spin::Mutex;
+
+/// Most segments a pool can grow to.
+const MAX_SEGMENTS: usize = 8;
+/// Index of no slot, terminating the freelist.
+const NIL: u32 = u32::MAX;
+
+/// Grows the flagged pools, kicked by takes which leave a pool below its low watermark.
+static REFILL: StaticWork = StaticWork::new(refill);
+/// Every pool, for [`REFILL`] to find the flagged ones among.
+static POOLS: Mutex<Vec<&'static dyn Refill>> = Mutex::new(Vec::new());
+
+trait Refill: Sync {
+    /// Grows the pool if it's flagged as needing it.
+    fn refill(&self);
+
+    fn stats(&self) -> Stats;
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Stats {
+    pub name: &'static str,
+    pub capacity: usize,
+    pub available: usize,
+    /// Takes which found the pool exhausted.
+    pub exhausted: u64,
+}
+
+/// Calls `func` with the statistics of every pool, unless the list of pools is locked (in which case, returns `false`
+/// without waiting on it, for the panic path).
+pub fn try_for_each_stats(mut func: impl FnMut(Stats)) -> bool {
+    let Some(pools) = POOLS.try_lock() else { return false };
+    for pool in pools.iter() {
+        func(pool.stats());
+    }
+
+    true
+}
+
+fn refill() {
+    for pool in POOLS.lock().iter() {
+        pool.refill();
+    }
+}
+
+struct Slot<T> {
+    /// Next free slot, while this one is free.
+    next: AtomicU32,
+    object: UnsafeCell<T>,
+}
+
+pub struct Pool<T: 'static> {
+    name: &'static str,
+    /// Objects in each segment.
+    segment_len: NonZeroUsize,
+    low_watermark: usize,
+    init: fn() -> T,
+
+    segments: [AtomicPtr<Slot<T>>; MAX_SEGMENTS],
+    segment_count: AtomicUsize,
+    /// Generation (upper half) and index (lower half) of the first free slot.
+    head: AtomicU64,
+    available: AtomicUsize,
+    /// Takes which found the pool exhausted.
+    exhausted: AtomicU64,
+    /// Whether the pool fell below its low watermark, and has yet to be grown.
+    needs_refill: AtomicBool,
+    /// Whether the pool fell below its low watermark with no room to grow, so refilling has stopped.
+    full: AtomicBool,
+}
+
+// Safety: A slot's object is only accessed by whoever took it from the freelist, so it moves between cores as `T` does.
+unsafe impl<T: Send> Sync for Pool<T> {}
+// Safety: As above.
+unsafe impl<T: Send> Send for Pool<T> {}
+
+fn pack(generation: u32, index: u32) -> u64 {
+    (u64::from(generation) << 32) | u64::from(index)
+}
+
+fn unpack(head: u64) -> (u32, u32) {
+    (u32::try_from(head >> 32).unwrap(), u32::try_from(head & u64::from(u32::MAX)).unwrap())
+}
+
+impl<T: Send + 'static> Pool<T> {
+    /// Allocates a pool of `segment_len` objects (each created with `init`), which grows by as many again while fewer
+    /// than `low_watermark` are free.
+    ///
+    /// #### Remark
+    ///
+    /// The pool is leaked, as interrupt handlers hold onto it for as long as they're registered.
+    pub fn new(name: &'static str, segment_len: NonZeroUsize, low_watermark: usize, init: fn() -> T) -> &'static Self {
+        assert!(segment_len.get().saturating_mul(MAX_SEGMENTS) < usize::try_from(NIL).unwrap());
+
+        let pool: &'static Self = Box::leak(Box::new(Self {
+            name,
+            segment_len,
+            low_watermark,
+            init,
+            segments: [const { AtomicPtr::new(core::ptr::null_mut()) }; MAX_SEGMENTS],
+            segment_count: AtomicUsize::new(0),
+            head: AtomicU64::new(pack(0, NIL)),
+            available: AtomicUsize::new(0),
+            exhausted: AtomicU64::new(0),
+            needs_refill: AtomicBool::new(false),
+            full: AtomicBool::new(false),
+        }));
+
+        pool.grow();
+
+        static REGISTER: spin::Once = spin::Once::new();
+        REGISTER.call_once(|| crate::task::workqueue::system().register(&REFILL));
+        POOLS.lock().push(pool);
+
+        pool
+    }
+
+    #[inline]
+    pub const fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Number of objects the pool holds, taken or not.
+    pub fn capacity(&self) -> usize {
+        self.segment_count.load(Ordering::Relaxed) * self.segment_len.get()
+    }
+
+    /// Number of objects which can be taken.
+    pub fn available(&self) -> usize {
+        self.available.load(Ordering::Relaxed)
+    }
+
+    /// Number of takes which found the pool exhausted.
+    pub fn exhausted(&self) -> u64 {
+        self.exhausted.load(Ordering::Relaxed)
+    }
+
+    fn slot(&self, index: u32) -> &Slot<T> {
+        let index = usize::try_from(index).unwrap();
+        let segment = self.segments[index / self.segment_len.get()].load(Ordering::Acquire);
+        debug_assert!(!segment.is_null());
+
+        // Safety: The index was published in the freelist (or taken from it) after its segment, which is never freed.
+        unsafe { &*segment.add(index % self.segment_len.get()) }
+    }
+
+    /// Takes an object from the pool, or `None` if every object is taken.
+    ///
+    /// #### Remark
+    ///
+    /// Objects are returned to the pool as they were left, so the taker must reinitialize whatever it uses.
+    pub fn take(&'static self) -> Option<Pooled<T>> {
+        // Acquiring the head orders the slot's link (and the object's last use) before they're read.
+        let mut head = self.head.load(Ordering::Acquire);
+
+        loop {
+            let (generation, index) = unpack(head);
+            if index == NIL {
+                self.exhausted.fetch_add(1, Ordering::Relaxed);
+                self.flag_refill();
+                return None;
+            }
+
+            // The slot may be taken (and its link changed) meanwhile, in which case the exchange fails.
+            let next = self.slot(index).next.load(Ordering::Relaxed);
+
+            match self.head.compare_exchange_weak(
+                head,
+                pack(generation.wrapping_add(1), next),
+                Ordering::Acquire,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => {
+                    if self.available.fetch_sub(1, Ordering::Relaxed) <= self.low_watermark {
+                        self.flag_refill();
+                    }
+
+                    return Some(Pooled { pool: self, index });
+                }
+
+                Err(current) => head = current,
+            }
+        }
+    }
+
+    /// Returns the slot `index` to the freelist.
+    fn give(&self, index: u32) {
+        let slot = self.slot(index);
+        // Counted before it's pushed, so a take of it never finds the count short.
+        self.available.fetch_add(1, Ordering::Relaxed);
+
+        let mut head = self.head.load(Ordering::Relaxed);
+
+        loop {
+            let (generation, next) = unpack(head);
+            slot.next.store(next, Ordering::Relaxed);
+
+            // Releasing the head publishes the link, and the returner's last use of the object, to the next taker.
+            match self.head.compare_exchange_weak(
+                head,
+                pack(generation.wrapping_add(1), index),
+                Ordering::Release,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(current) => head = current,
+            }
+        }
+    }
+
+    /// Allocates another segment and frees its objects into the pool, returning whether there was room for it.
+    ///
+    /// Only the pool's creation, or the refill work (under the lock of the list of pools), grows the pool, so segments
+    /// are never added concurrently.
+    fn grow(&self) -> bool {
+        let segment_index = self.segment_count.load(Ordering::Relaxed);
+        if segment_index >= MAX_SEGMENTS {
+            return false;
+        }
+
+        let segment = (0..self.segment_len.get())
+            .map(|_| Slot { next: AtomicU32::new(NIL), object: UnsafeCell::new((self.init)()) })
+            .collect::<Box<[Slot<T>]>>();
+        let segment = NonNull::new(Box::into_raw(segment).cast::<Slot<T>>()).unwrap();
+
+        // The segment is published before any of its slots, so a slot's segment is always found by `Self::slot`.
+        self.segments[segment_index].store(segment.as_ptr(), Ordering::Release);
+        self.segment_count.store(segment_index + 1, Ordering::Relaxed);
+
+        let first = segment_index * self.segment_len.get();
+        for index in first..(first + self.segment_len.get()) {
+            self.give(u32::try_from(index).unwrap());
+        }
+
+        true
+    }
+
+    /// Flags the pool as needing to grow, kicking the refill work unless it's already flagged (or can't grow).
+    fn flag_refill(&self) {
+        if !self.full.load(Ordering::Relaxed) && !self.needs_refill.swap(true, Ordering::Relaxed) {
+            REFILL.kick();
+        }
+    }
+}
+
+impl<T: Send + 'static> Refill for Pool<T> {
+    fn refill(&self) {
+        // Cleared first, so a take which falls below the low watermark meanwhile flags the pool again.
+        if !self.needs_refill.swap(false, Ordering::Relaxed) {
+            return;
+        }
+
+        while self.available() < self.low_watermark {
+            if self.grow() {
+                debug!("Pool '{}' grew to {} objects.", self.name, self.capacity());
+            } else {
+                self.full.store(true, Ordering::Relaxed);
+                warn!("Pool '{}' is below its low watermark, with no room to grow.", self.name);
+                break;
+            }
+        }
+    }
+
+    fn stats(&self) -> Stats {
+        Stats { name: self.name, capacity: self.capacity(), available: self.available(), exhausted: self.exhausted() }
+    }
+}
+
+/// An object taken from a [`Pool`], which returns it on drop.
+pub struct Pooled<T: Send + 'static> {
+    pool: &'static Pool<T>,
+    index: u32,
+}
+
+impl<T: Send + 'static> Deref for Pooled<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        // Safety: The slot was taken from the freelist, so its object is only accessed through `self`.
+        unsafe { &*self.pool.slot(self.index).object.get() }
+    }
+}
+
+impl<T: Send + 'static> DerefMut for Pooled<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        // Safety: The slot was taken from the freelist, so its object is only accessed through `self`.
+        unsafe { &mut *self.pool.slot(self.index).object.get() }
+    }
+}
+
+impl<T: Send + 'static> Drop for Pooled<T> {
+    fn drop(&mut self) {
+        self.pool.give(self.index);
+    }
+}
@@ -1,4 +1,7 @@
+pub mod context;
+pub mod mempool;
 pub mod pmm;
+pub mod zero;
 
 use alloc::alloc::Global;
 use core::{
@@ -21,8 +24,25 @@ mod global_allocator_impl {
 
     struct GlobalAllocator;
 
+    /// Panics if the local core is running an interrupt handler, where allocating may deadlock on the allocator's
+    /// locks (or stall the handler on frame allocation).
+    #[cfg(feature = "alloc_audit")]
+    fn audit(layout: Layout) {
+        use core::sync::atomic::{AtomicBool, Ordering};
+
+        /// Set once the audit has tripped, so the panic path is free to allocate.
+        static TRIPPED: AtomicBool = AtomicBool::new(false);
+
+        if crate::cpu::state::in_interrupt() && !TRIPPED.swap(true, Ordering::Relaxed) {
+            panic!("allocator called in interrupt context: {:?}", layout);
+        }
+    }
+
     unsafe impl GlobalAlloc for GlobalAllocator {
         unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            #[cfg(feature = "alloc_audit")]
+            audit(layout);
+
             KMALLOC.allocate(layout).map_or(core::ptr::null_mut(), |ptr| {
                 trace!("Allocation {:?} -> @{:X?}   0x{:X?}", layout, ptr, ptr.as_ref().len());
 
@@ -31,6 +51,9 @@ mod global_allocator_impl {
         }
 
         unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            #[cfg(feature = "alloc_audit")]
+            audit(layout);
+
             trace!("Deallocation @{:?}   {:?}", ptr, layout);
             KMALLOC.deallocate(NonNull::new(ptr).unwrap(), layout);
         }
@@ -38,10 +61,16 @@ mod global_allocator_impl {
 
     unsafe impl Allocator for GlobalAllocator {
         fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, core::alloc::AllocError> {
+            #[cfg(feature = "alloc_audit")]
+            audit(layout);
+
             KMALLOC.allocate(layout)
         }
 
         unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+            #[cfg(feature = "alloc_audit")]
+            audit(layout);
+
             KMALLOC.deallocate(ptr, layout);
         }
     }
@@ -1,12 +1,11 @@
 use crate::{interrupts::InterruptCell, mem::HHDM};
-use bitvec::slice::BitSlice;
 use core::{
     alloc::{AllocError, Allocator, Layout},
     num::{NonZeroU32, NonZeroUsize},
     ops::Range,
     ptr::NonNull,
-    sync::atomic::AtomicUsize,
 };
+use libkernel::mem::SummaryBitmap;
 use libsys::{page_mask, page_shift, page_size};
 use libsys::{Address, Frame};
 use spin::RwLock;
@@ -44,6 +43,11 @@ pub fn get() -> PhysicalAllocator {
     PMM.get().expect("physical memory manager has not been initialized")
 }
 
+/// Returns the physical memory manager, if it has been initialized yet.
+pub fn try_get() -> Option<PhysicalAllocator> {
+    PMM.get()
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Error {
     /// There are not enough free frames to satisfy the request.
@@ -56,6 +60,10 @@ pub enum Error {
     NotFree,
     /// Attempted to free a frame that wasn't locked.
     NotLocked,
+    /// Attempted to free a frame which others still hold references to.
+    Shared,
+    /// The frame already holds [`MAX_REFERENCES`].
+    ReferenceOverflow,
 
     TypeMismatch,
 
@@ -96,6 +104,113 @@ impl FrameType {
     }
 }
 
+/// Consumer of an allocated frame, so physical memory usage (and leaks) can be attributed to a subsystem.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Owner {
+    Free = 0,
+    /// The frame allocator's own ledger.
+    Pmm = 1,
+    /// Backing for the kernel heap.
+    Heap = 2,
+    PageTable = 3,
+    PageCache = 4,
+    /// Anonymous memory handed to userspace (including pre-zeroed frames pooled for it).
+    UserAnon = 5,
+    /// Buffers shared with devices.
+    Dma = 6,
+    /// Device memory, which is never handed out (only tracked, once locked).
+    Mmio = 7,
+    /// Memory in use as the kernel took over from the bootloader (i.e. the kernel image, or firmware tables).
+    Reserved = 8,
+    /// Other kernel allocations (i.e. the framebuffer's shadow buffer).
+    Kernel = 9,
+}
+
+impl Owner {
+    pub const COUNT: usize = 10;
+    pub const ALL: [Self; Self::COUNT] = [
+        Self::Free,
+        Self::Pmm,
+        Self::Heap,
+        Self::PageTable,
+        Self::PageCache,
+        Self::UserAnon,
+        Self::Dma,
+        Self::Mmio,
+        Self::Reserved,
+        Self::Kernel,
+    ];
+
+    #[inline]
+    fn from_u8(value: u8) -> Self {
+        Self::ALL[usize::from(value)]
+    }
+
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Free => "free",
+            Self::Pmm => "pmm",
+            Self::Heap => "heap",
+            Self::PageTable => "page_table",
+            Self::PageCache => "page_cache",
+            Self::UserAnon => "user_anon",
+            Self::Dma => "dma",
+            Self::Mmio => "mmio",
+            Self::Reserved => "reserved",
+            Self::Kernel => "kernel",
+        }
+    }
+}
+
+/// Number of frames held by each [`Owner`], indexed by its discriminant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Usage([usize; Owner::COUNT]);
+
+impl Usage {
+    #[inline]
+    pub const fn frames(&self, owner: Owner) -> usize {
+        self.0[owner as usize]
+    }
+
+    /// Frames held by each owner, excluding those which hold none.
+    pub fn iter(&self) -> impl Iterator<Item = (Owner, usize)> + '_ {
+        Owner::ALL.into_iter().map(|owner| (owner, self.frames(owner))).filter(|(_, frames)| *frames > 0)
+    }
+}
+
+impl core::fmt::Display for Usage {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        for (index, (owner, frames)) in self.iter().enumerate() {
+            if index > 0 {
+                f.write_str(" ")?;
+            }
+
+            write!(f, "{}={}", owner.as_str(), frames)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Bits of a frame's table entry holding its [`Owner`], below which lie its reference count.
+const OWNER_MASK: u32 = 0xFF;
+const REFERENCES_SHIFT: u32 = 8;
+/// Most references a single frame can hold.
+pub const MAX_REFERENCES: u32 = u32::MAX >> REFERENCES_SHIFT;
+
+const fn entry(owner: Owner, references: u32) -> u32 {
+    (references << REFERENCES_SHIFT) | (owner as u32)
+}
+
+fn entry_owner(entry: u32) -> Owner {
+    Owner::from_u8(u8::try_from(entry & OWNER_MASK).unwrap())
+}
+
+const fn entry_references(entry: u32) -> u32 {
+    entry >> REFERENCES_SHIFT
+}
+
 struct RegionDescriptor {
     ty: FrameType,
     region: Range<usize>,
@@ -122,15 +237,14 @@ unsafe impl Allocator for &PhysicalMemoryManager<'_> {
         let frame_count = libsys::align_up_div(layout.size(), page_shift());
         let frame = match frame_count.cmp(&1usize) {
             core::cmp::Ordering::Greater => {
-                self.next_frames(NonZeroUsize::new(frame_count).unwrap(), Some(page_shift()))
+                self.next_frames(NonZeroUsize::new(frame_count).unwrap(), Some(page_shift()), Owner::Heap)
             }
-            core::cmp::Ordering::Equal => self.next_frame(),
+            core::cmp::Ordering::Equal => self.next_frame(Owner::Heap),
             core::cmp::Ordering::Less => unreachable!(),
         }
         .map_err(|_| AllocError)?;
-        let address = HHDM.offset(frame).ok_or(AllocError)?;
 
-        Ok(NonNull::slice_from_raw_parts(NonNull::new(address.as_ptr()).unwrap(), frame_count * page_size()))
+        HHDM.frames(frame, NonZeroUsize::new(frame_count).unwrap()).ok_or(AllocError)
     }
 
     unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
@@ -150,21 +264,103 @@ unsafe impl Allocator for &PhysicalMemoryManager<'_> {
     }
 }
 
+/// Frame table with one bit per frame (set if the frame is locked), summarized per word so free frames are found by
+/// word scans (see [`SummaryBitmap`]).
+///
+/// Each frame also has an entry holding its [`Owner`] tag (with a running count of frames per owner), and the number of
+/// references held to it, i.e. by each mapping of a frame shared between address spaces.
+struct Table<'a> {
+    frames: SummaryBitmap<'a>,
+    entries: &'a mut [u32],
+    usage: [usize; Owner::COUNT],
+}
+
+impl<'a> Table<'a> {
+    /// Number of table words required to track `len` frames, including the summary words.
+    const fn words_for(len: usize) -> usize {
+        SummaryBitmap::words_for(len)
+    }
+
+    /// Creates a table with every frame free, from memory of (at least) [`Self::words_for`] words, and `len` frame
+    /// entries.
+    fn new(memory: &'a mut [usize], entries: &'a mut [u32], len: usize) -> Self {
+        let entries = &mut entries[..len];
+        entries.fill(entry(Owner::Free, 0));
+
+        let mut usage = [0; Owner::COUNT];
+        usage[Owner::Free as usize] = len;
+
+        Self { frames: SummaryBitmap::new(memory, len), entries, usage }
+    }
+
+    #[inline]
+    const fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    fn set_range(&mut self, range: Range<usize>, locked: bool) {
+        self.frames.set_range(range, locked);
+    }
+
+    /// Tags the frames of `range` (which must be within the table) as belonging to `owner`, with `references` each.
+    fn set_owner(&mut self, range: Range<usize>, owner: Owner, references: u32) {
+        for frame_entry in &mut self.entries[range] {
+            self.usage[entry_owner(*frame_entry) as usize] -= 1;
+            self.usage[owner as usize] += 1;
+            *frame_entry = entry(owner, references);
+        }
+    }
+
+    /// Locks the frames of `range`, tagging them as belonging to `owner` (which holds the only reference to each).
+    fn lock_range(&mut self, range: Range<usize>, owner: Owner) {
+        self.set_range(range.clone(), true);
+        self.set_owner(range, owner, 1);
+    }
+
+    /// Frees the frame at `index`.
+    fn free(&mut self, index: usize) {
+        self.set_range(index..(index + 1), false);
+        self.set_owner(index..(index + 1), Owner::Free, 0);
+    }
+
+    /// Returns the index of the first locked frame in `range`, if any.
+    fn first_locked(&self, range: Range<usize>) -> Option<usize> {
+        self.frames.first_set(range)
+    }
+
+    /// Returns the index of the first free frame at or after `start`, if any.
+    fn first_free(&self, start: usize) -> Option<usize> {
+        self.frames.first_clear(start)
+    }
+}
+
+/// Allocator of physical frames, from the [`Table`].
+///
+/// #### Remark
+///
+/// Every change to a frame's state (locking, freeing, and its references) is made under the table's write lock, and
+/// never with atomics of its own. Releasing the lock after freeing a frame, and acquiring it to hand the frame out
+/// again, orders the previous owner's accesses to the frame before the next owner's, even on weakly-ordered harts
+/// (where a relaxed flag wouldn't). So, a frame must only be freed once its previous owner is done with it, i.e. once
+/// any mappings of it have been shot down from every core's TLB (see [`crate::mem::tlb`]).
 pub struct FrameAllocator<'a> {
-    table: InterruptCell<RwLock<&'a mut BitSlice<AtomicUsize>>>,
+    table: InterruptCell<RwLock<Table<'a>>>,
 }
 
-// Safety: Type uses entirely atomic operations.
+// Safety: Table is only accessed through its lock.
 unsafe impl Send for FrameAllocator<'_> {}
-// Safety: Type uses entirely atomic operations.
+// Safety: Table is only accessed through its lock.
 unsafe impl Sync for FrameAllocator<'_> {}
 
 impl FrameAllocator<'_> {
     pub fn new(free_regions: impl Iterator<Item = Range<usize>>, total_memory: usize) -> Option<Self> {
         let total_frames = total_memory / page_size();
-        let table_slice_len =
-            libsys::align_up_div(total_frames, NonZeroU32::new(usize::BITS.trailing_zeros()).unwrap());
-        let table_size_in_frames = libsys::align_up_div(table_slice_len * core::mem::size_of::<usize>(), page_shift());
+        let table_words = Table::words_for(total_frames);
+        // Frame entries follow the table words.
+        let table_size_in_frames = libsys::align_up_div(
+            (table_words * core::mem::size_of::<usize>()) + (total_frames * core::mem::size_of::<u32>()),
+            page_shift(),
+        );
         let table_size_in_bytes = table_size_in_frames * page_size();
 
         let select_region = free_regions
@@ -177,23 +373,26 @@ impl FrameAllocator<'_> {
 
         trace!("Selecting PMM ledger region: {:X?}", select_region);
 
-        // Safety: Memory map describes HHDM, so this pointer into it will be valid if the bootloader memory map is.s
+        // Safety: Memory map describes HHDM, so this pointer into it will be valid if the bootloader memory map is.
         let ledger_start_ptr = unsafe { HHDM.ptr().add(select_region.start) };
-        // Safety: Unless the memory map lied to us, this memory is valid for a `&[AtomicUsize; total_frames]`.
-        let ledger = BitSlice::from_slice_mut(unsafe {
-            core::slice::from_raw_parts_mut(ledger_start_ptr.cast::<AtomicUsize>(), table_slice_len)
-        });
-        ledger.fill(false);
-
-        // Fill the extant bits, as the physical memory bitslice may not be exactly divisible by `usize::BITS`.
-        ledger[total_frames..(table_slice_len * (usize::BITS as usize))].fill(true);
+        // Safety: Unless the memory map lied to us, this memory is valid for a `&mut [usize; table_words]`.
+        let ledger = unsafe { core::slice::from_raw_parts_mut(ledger_start_ptr.cast::<usize>(), table_words) };
+        // Safety: The frame entries directly follow the table words (so are aligned for a `u32`), within the same
+        //         selected region.
+        let entries = unsafe {
+            core::slice::from_raw_parts_mut(
+                ledger_start_ptr.add(table_words * core::mem::size_of::<usize>()).cast::<u32>(),
+                total_frames,
+            )
+        };
+        let mut table = Table::new(ledger, entries, total_frames);
 
         // Ensure the table pages are reserved.
         let ledger_start_index = select_region.start / page_size();
         let ledger_end_index = select_region.end / page_size();
-        ledger[ledger_start_index..ledger_end_index].fill(true);
+        table.lock_range(ledger_start_index..ledger_end_index, Owner::Pmm);
 
-        Some(Self { table: InterruptCell::new(spin::RwLock::new(ledger)) })
+        Some(Self { table: InterruptCell::new(spin::RwLock::new(table)) })
     }
 
     #[inline]
@@ -204,61 +403,222 @@ impl FrameAllocator<'_> {
         })
     }
 
-    pub fn next_frame(&self) -> Result<Address<Frame>> {
+    /// Allocates a frame for `owner`, within the local core's allocation context (see [`super::context`]).
+    pub fn next_frame(&self, owner: Owner) -> Result<Address<Frame>> {
+        let context = super::context::current();
+        if context.align_bits.is_some_and(|align_bits| align_bits.get() > page_shift().get()) {
+            return self.next_frames(NonZeroUsize::MIN, None, owner);
+        }
+
         self.table.with(|table| {
             let mut table = table.write();
-            let index = table.first_zero().ok_or(Error::NoneFree)?;
-            table.set(index, true);
+            let index = table.first_free(0).filter(|&index| index < context.zone.end_index()).ok_or(Error::NoneFree)?;
+            table.lock_range(index..(index + 1), owner);
 
             Ok(Address::new(index << page_shift().get()).unwrap())
         })
     }
 
-    pub fn next_frames(&self, count: NonZeroUsize, align_bits: Option<NonZeroU32>) -> Result<Address<Frame>> {
-        let align_bits = align_bits.unwrap_or(NonZeroU32::MIN).get();
-        let align_index_skip = u32::max(1, align_bits >> page_shift().get());
+    /// Allocates `count` contiguous frames for `owner`, aligned to `1 << align_bits` bytes, within the local core's
+    /// allocation context (see [`super::context`]). The stricter of the two alignments is kept.
+    pub fn next_frames(
+        &self,
+        count: NonZeroUsize,
+        align_bits: Option<NonZeroU32>,
+        owner: Owner,
+    ) -> Result<Address<Frame>> {
+        let context = super::context::current();
+        let align_bits = align_bits.max(context.align_bits).map_or(0, NonZeroU32::get);
+        let align_index_skip =
+            1usize.checked_shl(align_bits.saturating_sub(page_shift().get())).ok_or(Error::InvalidAlignment)?;
         self.table.with(|table| {
             let mut table = table.write();
-            let index = table
-                .windows(count.get())
-                .enumerate()
-                .step_by(align_index_skip.try_into().unwrap())
-                .find_map(|(index, window)| window.not_any().then_some(index))
-                .ok_or(Error::NoneFree)?;
-            let window = table.get_mut(index..(index + count.get())).unwrap();
-            window.fill(true);
+            let end_limit = table.len().min(context.zone.end_index());
+
+            let mut search_index = 0;
+            let index = loop {
+                let free_index = table.first_free(search_index).ok_or(Error::NoneFree)?;
+                let index = free_index.next_multiple_of(align_index_skip);
+                let end_index =
+                    index.checked_add(count.get()).filter(|end| *end <= end_limit).ok_or(Error::NoneFree)?;
+
+                match table.first_locked(index..end_index) {
+                    // Resume the search past the locked frame, as no window containing it can be free.
+                    Some(locked_index) => search_index = locked_index + 1,
+                    None => break index,
+                }
+            };
+            table.lock_range(index..(index + count.get()), owner);
 
             Ok(Address::new(index << page_shift().get()).unwrap())
         })
     }
 
-    pub fn lock_frame(&self, address: Address<Frame>) -> Result<()> {
+    /// Locks the free frame at `address` for `owner`, failing with [`Error::NotFree`] if it's already locked.
+    pub fn lock_frame(&self, address: Address<Frame>, owner: Owner) -> Result<()> {
+        self.lock_frames(address, NonZeroUsize::MIN, owner)
+    }
+
+    /// Locks the `count` free frames from `address` for `owner`, failing with [`Error::NotFree`] (and locking none) if
+    /// any is already locked.
+    pub fn lock_frames(&self, address: Address<Frame>, count: NonZeroUsize, owner: Owner) -> Result<()> {
         self.table.with(|table| {
-            let table = table.read();
+            let mut table = table.write();
             let index = address.index();
+            let end_index =
+                index.checked_add(count.get()).filter(|end| *end <= table.len()).ok_or(Error::OutOfBounds)?;
 
-            if index >= table.len() {
-                Err(Error::OutOfBounds)
+            if table.first_locked(index..end_index).is_some() {
+                Err(Error::NotFree)
             } else {
-                table.set_aliased(index, true);
+                table.lock_range(index..end_index, owner);
 
                 Ok(())
             }
         })
     }
 
+    /// Changes the owner of the locked frame at `address` to `owner`, keeping its references.
+    pub fn retag_frame(&self, address: Address<Frame>, owner: Owner) -> Result<()> {
+        self.table.with(|table| {
+            let mut table = table.write();
+            let index = address.index();
+
+            match table.entries.get(index).copied().map(entry_references) {
+                None => Err(Error::OutOfBounds),
+                Some(0) => Err(Error::NotLocked),
+                Some(references) => {
+                    table.set_owner(index..(index + 1), owner, references);
+
+                    Ok(())
+                }
+            }
+        })
+    }
+
+    /// Frees the frame at `address`, which must hold no references other than the caller's.
     pub fn free_frame(&self, address: Address<Frame>) -> Result<()> {
         self.table.with(|table| {
-            let table = table.read();
+            let mut table = table.write();
             let index = address.index();
 
-            if index >= table.len() {
-                Err(Error::OutOfBounds)
-            } else {
-                table.set_aliased(index, false);
+            match table.entries.get(index).copied().map(entry_references) {
+                None => Err(Error::OutOfBounds),
+                Some(0) => Err(Error::NotLocked),
+                Some(references) if references > 1 => Err(Error::Shared),
+                Some(_) => {
+                    table.free(index);
 
-                Ok(())
+                    Ok(())
+                }
+            }
+        })
+    }
+
+    /// Takes another reference to the locked frame at `address`.
+    pub fn retain(&self, address: Address<Frame>) -> Result<()> {
+        self.table.with(|table| {
+            let mut table = table.write();
+            let frame_entry = table.entries.get_mut(address.index()).ok_or(Error::OutOfBounds)?;
+
+            match entry_references(*frame_entry) {
+                0 => Err(Error::NotLocked),
+                MAX_REFERENCES => Err(Error::ReferenceOverflow),
+                references => {
+                    *frame_entry = entry(entry_owner(*frame_entry), references + 1);
+
+                    Ok(())
+                }
             }
         })
     }
+
+    /// Drops a reference to the frame at `address`, freeing it if it was the last.
+    pub fn release(&self, address: Address<Frame>) -> Result<()> {
+        if !self.release_shared(address)? {
+            self.free_frame(address)?;
+        }
+
+        Ok(())
+    }
+
+    /// Drops a reference to the frame at `address` if others remain, returning whether it did.
+    ///
+    /// #### Remark
+    ///
+    /// The last reference is left for its holder to free, so the frame can be recycled as it sees fit (i.e. zeroed
+    /// into a pool).
+    pub fn release_shared(&self, address: Address<Frame>) -> Result<bool> {
+        self.table.with(|table| {
+            let mut table = table.write();
+            let frame_entry = table.entries.get_mut(address.index()).ok_or(Error::OutOfBounds)?;
+
+            match entry_references(*frame_entry) {
+                0 => Err(Error::NotLocked),
+                1 => Ok(false),
+                references => {
+                    *frame_entry = entry(entry_owner(*frame_entry), references - 1);
+
+                    Ok(true)
+                }
+            }
+        })
+    }
+
+    /// Number of references held to the frame at `address` (none, if it's free).
+    pub fn references(&self, address: Address<Frame>) -> Result<u32> {
+        self.table.with(|table| {
+            let table = table.read();
+            table.entries.get(address.index()).copied().map(entry_references).ok_or(Error::OutOfBounds)
+        })
+    }
+
+    /// Returns the [`Owner`] of the frame at `address`.
+    pub fn owner(&self, address: Address<Frame>) -> Result<Owner> {
+        self.table.with(|table| {
+            let table = table.read();
+            table.entries.get(address.index()).copied().map(entry_owner).ok_or(Error::OutOfBounds)
+        })
+    }
+
+    /// Whether `predicate` holds for the owner of each of the `count` frames from `address`, which is given `None` for
+    /// frames beyond the end of the table.
+    pub fn all_owners(&self, address: Address<Frame>, count: usize, predicate: impl Fn(Option<Owner>) -> bool) -> bool {
+        self.table.with(|table| {
+            let table = table.read();
+            (address.index()..address.index().saturating_add(count))
+                .all(|index| predicate(table.entries.get(index).copied().map(entry_owner)))
+        })
+    }
+
+    /// Number of frames held by each [`Owner`].
+    pub fn usage(&self) -> Usage {
+        self.table.with(|table| Usage(table.read().usage))
+    }
+
+    /// Number of frames held by each [`Owner`], or `None` if the frame table is locked for writing.
+    pub fn try_usage(&self) -> Option<Usage> {
+        self.table.with(|table| table.try_read().map(|table| Usage(table.usage)))
+    }
+}
+
+/// Takes another reference to the frame `frame` (see [`FrameAllocator::retain`]).
+#[inline]
+pub fn retain(frame: Address<Frame>) -> Result<()> {
+    get().retain(frame)
+}
+
+/// Drops a reference to the frame `frame`, freeing it if it was the last (see [`FrameAllocator::release`]).
+#[inline]
+pub fn release(frame: Address<Frame>) -> Result<()> {
+    get().release(frame)
+}
+
+/// Publishes physical memory usage by owner to sysfs (`mem/frames/usage`).
+pub fn publish() {
+    crate::sysfs::set(
+        "mem/frames",
+        "usage",
+        crate::sysfs::Value::Dynamic(alloc::boxed::Box::new(|| alloc::format!("{}", get().usage()))),
+    );
 }
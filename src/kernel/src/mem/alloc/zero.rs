@@ -0,0 +1,196 @@
+//! Zeroed frames, for memory handed to userspace.
+//!
+//! A background task keeps a pool of pre-zeroed frames topped up, so that allocations usually don't pay for zeroing
+//! inline. With zero-on-free, freed frames are scrubbed immediately (and recycled into the pool while it's below its
+//! target), so their contents don't linger in free memory.
+
+use super::pmm;
+use crate::{interrupts::InterruptCell, mem::HHDM};
+use alloc::vec::Vec;
+use core::{
+    num::NonZeroUsize,
+    sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+};
+use libsys::{Address, Frame};
+use spin::Mutex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Policy {
+    /// Number of pre-zeroed frames the background task keeps pooled.
+    pub pool_target: usize,
+    /// Whether every freed frame is zeroed, rather than only those freed with [`free_frame_sensitive`].
+    pub zero_on_free: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    /// Allocations satisfied from the pool.
+    pub hits: u64,
+    /// Allocations which had to zero a frame inline.
+    pub misses: u64,
+    /// Frames zeroed as they were freed.
+    pub zeroed_on_free: u64,
+    /// Frames currently pooled.
+    pub pooled: usize,
+}
+
+static POOL_TARGET: AtomicUsize = AtomicUsize::new(0);
+static ZERO_ON_FREE: AtomicBool = AtomicBool::new(false);
+
+static HITS: AtomicU64 = AtomicU64::new(0);
+static MISSES: AtomicU64 = AtomicU64::new(0);
+static ZEROED_ON_FREE: AtomicU64 = AtomicU64::new(0);
+
+static POOL: InterruptCell<Mutex<Vec<Address<Frame>>>> = InterruptCell::new(Mutex::new(Vec::new()));
+
+fn zero(frame: Address<Frame>) {
+    // Safety: The frame is owned by the caller.
+    unsafe { HHDM.slice_mut(frame, NonZeroUsize::MIN) }.expect("zeroed frame isn't allocated").fill(0);
+}
+
+/// Pools `frame` if the pool is below its target, returning it otherwise.
+fn try_pool(frame: Address<Frame>) -> Option<Address<Frame>> {
+    POOL.with(|pool| {
+        let mut pool = pool.lock();
+
+        if pool.len() < POOL_TARGET.load(Ordering::Relaxed) {
+            pool.push(frame);
+            None
+        } else {
+            Some(frame)
+        }
+    })
+}
+
+/// Allocates a zeroed frame, from the pool if possible.
+pub fn next_frame() -> pmm::Result<Address<Frame>> {
+    // Pooled frames are allocated outside of any context, so may not suit the caller's.
+    let context = super::context::current();
+    let pooled = POOL.with(|pool| {
+        let mut pool = pool.lock();
+        pool.last().is_some_and(|frame| context.admits(*frame)).then(|| pool.pop()).flatten()
+    });
+    if let Some(frame) = pooled {
+        HITS.fetch_add(1, Ordering::Relaxed);
+        return Ok(frame);
+    }
+
+    MISSES.fetch_add(1, Ordering::Relaxed);
+    let frame = pmm::get().next_frame(pmm::Owner::UserAnon)?;
+    zero(frame);
+
+    Ok(frame)
+}
+
+/// Frees `frame`, zeroing it first if the policy requires it.
+pub fn free_frame(frame: Address<Frame>) -> pmm::Result<()> {
+    if ZERO_ON_FREE.load(Ordering::Relaxed) {
+        free_frame_sensitive(frame)
+    } else {
+        pmm::get().free_frame(frame)
+    }
+}
+
+/// Drops a reference to `frame`, freeing it according to the policy if it was the last.
+pub fn release_frame(frame: Address<Frame>) -> pmm::Result<()> {
+    // Frames still referenced elsewhere mustn't be zeroed.
+    if pmm::get().release_shared(frame)? {
+        Ok(())
+    } else {
+        free_frame(frame)
+    }
+}
+
+/// Zeroes and frees `frame`, regardless of the policy (i.e. for frames which held key material).
+pub fn free_frame_sensitive(frame: Address<Frame>) -> pmm::Result<()> {
+    zero(frame);
+    ZEROED_ON_FREE.fetch_add(1, Ordering::Relaxed);
+
+    try_pool(frame).map_or(Ok(()), |frame| pmm::get().free_frame(frame))
+}
+
+pub fn policy() -> Policy {
+    Policy { pool_target: POOL_TARGET.load(Ordering::Relaxed), zero_on_free: ZERO_ON_FREE.load(Ordering::Relaxed) }
+}
+
+/// Applies `policy`, returning any frames pooled beyond the new target to the frame allocator.
+pub fn set_policy(policy: Policy) {
+    POOL_TARGET.store(policy.pool_target, Ordering::Relaxed);
+    ZERO_ON_FREE.store(policy.zero_on_free, Ordering::Relaxed);
+
+    let excess = POOL.with(|pool| {
+        let mut pool = pool.lock();
+        let excess = pool.split_off(pool.len().min(policy.pool_target));
+        // Reserve up-front, so pooling freed frames doesn't allocate.
+        pool.reserve(policy.pool_target.saturating_sub(pool.len()));
+
+        excess
+    });
+
+    for frame in excess {
+        pmm::get().free_frame(frame).ok();
+    }
+}
+
+pub fn stats() -> Stats {
+    Stats {
+        hits: HITS.load(Ordering::Relaxed),
+        misses: MISSES.load(Ordering::Relaxed),
+        zeroed_on_free: ZEROED_ON_FREE.load(Ordering::Relaxed),
+        pooled: POOL.with(|pool| pool.lock().len()),
+    }
+}
+
+/// Zeroes frames into the pool until it reaches its target.
+fn refill() {
+    loop {
+        // Checked before allocating, to avoid zeroing a frame only to free it again.
+        if POOL.with(|pool| pool.lock().len()) >= POOL_TARGET.load(Ordering::Relaxed) {
+            break;
+        }
+
+        let Ok(frame) = pmm::get().next_frame(pmm::Owner::UserAnon) else { break };
+        zero(frame);
+
+        if let Some(frame) = try_pool(frame) {
+            pmm::get().free_frame(frame).ok();
+            break;
+        }
+    }
+}
+
+/// Applies the policy from the kernel parameters, publishes the pool statistics, and spawns the zeroing task.
+pub fn init() {
+    use crate::task::{Priority, Task};
+
+    let params = crate::init::get();
+    set_policy(Policy {
+        pool_target: if params.low_memory { 0 } else { params.zero_pool },
+        zero_on_free: params.zero_on_free,
+    });
+
+    crate::sysfs::set(
+        "mem/zero",
+        "stat",
+        crate::sysfs::Value::Dynamic(alloc::boxed::Box::new(|| {
+            let Policy { pool_target, zero_on_free } = policy();
+            let Stats { hits, misses, zeroed_on_free, pooled } = stats();
+
+            alloc::format!(
+                "pooled={pooled} target={pool_target} hits={hits} misses={misses} zero_on_free={zero_on_free} \
+                 zeroed_on_free={zeroed_on_free}"
+            )
+        })),
+    );
+
+    extern "C" fn zero_task() -> ! {
+        loop {
+            refill();
+            crate::interrupts::wait();
+        }
+    }
+
+    let task = Task::kernel(zero_task, core::num::NonZeroUsize::new(0x4000).unwrap(), Priority::Idle);
+    task.set_name("zero");
+    crate::task::spawn(task);
+}
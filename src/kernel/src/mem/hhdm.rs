@@ -1,4 +1,6 @@
-use libsys::{Address, Frame, Page, Virtual};
+use crate::mem::alloc::pmm;
+use core::{num::NonZeroUsize, ptr::NonNull};
+use libsys::{page_size, Address, Frame, Page, Virtual};
 
 pub static HHDM: spin::Lazy<Hhdm> = spin::Lazy::new(|| {
     #[limine::limine_tag]
@@ -40,4 +42,44 @@ impl Hhdm {
     pub fn offset(self, frame: Address<Frame>) -> Option<Address<Page>> {
         self.address().get().checked_add(frame.get().get()).and_then(Address::new)
     }
+
+    /// Returns the mapping of the `count` frames from `frame`, if the frame table holds each as allocated RAM (rather
+    /// than free, or beyond the end of physical memory).
+    ///
+    /// #### Remark
+    ///
+    /// Frames can't be checked before the frame table is initialized, so this always fails until it is.
+    pub fn frames(self, frame: Address<Frame>, count: NonZeroUsize) -> Option<NonNull<[u8]>> {
+        let allocated = pmm::try_get()?
+            .all_owners(frame, count.get(), |owner| owner.is_some_and(|owner| owner != pmm::Owner::Free));
+        if !allocated {
+            return None;
+        }
+
+        let ptr = NonNull::new(self.offset(frame)?.as_ptr())?;
+        Some(NonNull::slice_from_raw_parts(ptr, count.get().checked_mul(page_size())?))
+    }
+
+    /// Borrows the `count` frames from `frame` as a slice, with the checks of [`Self::frames`].
+    ///
+    /// ### Safety
+    ///
+    /// Caller must have exclusive access to the frames for `'a` (i.e. by having just allocated them).
+    pub unsafe fn slice_mut<'a>(self, frame: Address<Frame>, count: NonZeroUsize) -> Option<&'a mut [u8]> {
+        // Safety: Frames are allocated RAM within the HHDM, and the caller guarantees exclusive access.
+        self.frames(frame, count).map(|mut frames| unsafe { frames.as_mut() })
+    }
+
+    /// Returns the mapping of the `len` bytes of device memory at `address`, if none of it is RAM the frame allocator
+    /// could hand out (i.e. it's beyond the end of physical memory, or reserved).
+    pub fn mmio(self, address: Address<Frame>, len: usize) -> Option<NonNull<u8>> {
+        let device_memory = pmm::try_get()?.all_owners(address, len.div_ceil(page_size()), |owner| {
+            owner.is_none_or(|owner| matches!(owner, pmm::Owner::Reserved | pmm::Owner::Mmio))
+        });
+        if !device_memory {
+            return None;
+        }
+
+        NonNull::new(self.offset(address)?.as_ptr())
+    }
 }
@@ -0,0 +1,74 @@
+//! Physically contiguous buffers for devices to access directly (i.e. queues, and the bounce buffers of transfers).
+
+use crate::mem::{alloc::pmm, HHDM};
+use core::{num::NonZeroUsize, ptr::NonNull};
+use libsys::{page_shift, page_size, Address, Frame};
+
+/// Zeroed, physically contiguous frames, accessed by the kernel through the HHDM. The frames are freed on drop, so
+/// the device must be done with them first.
+pub struct DmaBuffer {
+    frame: Address<Frame>,
+    pages: NonZeroUsize,
+    ptr: NonNull<u8>,
+}
+
+// Safety: Type refers only to its own frames, which are accessed through the global HHDM.
+unsafe impl Send for DmaBuffer {}
+// Safety: See above.
+unsafe impl Sync for DmaBuffer {}
+
+impl DmaBuffer {
+    /// Allocates a buffer of at least `len` bytes (rounded up to whole pages), aligned to a page.
+    pub fn new(len: usize) -> pmm::Result<Self> {
+        let pages = NonZeroUsize::new(libsys::align_up_div(len, page_shift())).ok_or(pmm::Error::InvalidAlignment)?;
+        let frame = pmm::get().next_frames(pages, None, pmm::Owner::Dma)?;
+        let ptr = HHDM.frames(frame, pages).unwrap().cast::<u8>();
+
+        // Safety: The frames were just allocated, and span `pages` pages.
+        unsafe { core::ptr::write_bytes(ptr.as_ptr(), 0, pages.get() * page_size()) };
+
+        Ok(Self { frame, pages, ptr })
+    }
+
+    /// Physical address of the start of the buffer, as the device addresses it.
+    #[inline]
+    pub fn physical(&self) -> usize {
+        self.frame.get().get()
+    }
+
+    #[inline]
+    pub const fn len(&self) -> usize {
+        self.pages.get() * page_size()
+    }
+
+    #[inline]
+    pub const fn as_ptr(&self) -> NonNull<u8> {
+        self.ptr
+    }
+
+    /// #### Remark
+    ///
+    /// The device may write the buffer at any time it's been handed it, so it should only be read once the device is
+    /// done with it (i.e. once a request's completion has been observed).
+    pub fn as_slice(&self) -> &[u8] {
+        // Safety: The buffer spans `len` bytes of frames owned by it.
+        unsafe { core::slice::from_raw_parts(self.ptr.as_ptr(), self.len()) }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        // Safety: The buffer spans `len` bytes of frames owned by it, and is borrowed mutably.
+        unsafe { core::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len()) }
+    }
+}
+
+impl Drop for DmaBuffer {
+    fn drop(&mut self) {
+        for index in 0..self.pages.get() {
+            let frame = Address::new(self.physical() + (index * page_size())).unwrap();
+
+            if let Err(err) = pmm::get().free_frame(frame) {
+                warn!("Failed to free DMA frame {:X?}: {:?}", frame, err);
+            }
+        }
+    }
+}
@@ -1 +1,5 @@
+pub mod dma;
 pub mod pci;
+pub mod poll;
+pub mod probe;
+pub mod resource;
@@ -1,6 +1,7 @@
 mod class;
 pub use class::*;
 
+pub mod pci2pci;
 pub mod standard;
 
 use bit_field::BitField;
@@ -14,7 +15,27 @@ crate::error_impl! {
         InvalidKind { raw: u8 } => None,
         UnsupportedKind { raw: u8 } => None,
         InvalidBarSpace { value: u8 } => None,
-        BarIndexOverflow { index: usize } => None
+        BarIndexOverflow { index: usize } => None,
+        /// The BAR decodes an address beyond the physical address space.
+        InvalidBarAddress { address: u64 } => None,
+        /// No BAR starts at register `index` (it's the upper half of a 64-bit BAR, or failed to decode).
+        NoBar { index: usize } => None,
+        /// The BAR decodes I/O space, so can't be mapped.
+        NotMemoryBar { index: usize } => None,
+        /// The BAR hasn't been given an address to decode.
+        UnassignedBar { index: usize } => None,
+        /// The device implements no expansion ROM.
+        NoExpansionRom => None,
+        /// The expansion ROM hasn't been given an address to decode.
+        UnassignedExpansionRom => None,
+        Vmap { err: crate::mem::vmap::Error } => Some(err),
+        /// The device has no (usable) MSI-X capability.
+        NoMsix => None,
+        /// More interrupts were requested than the device's MSI-X table has entries for.
+        TooManyVectors { requested: usize, available: usize } => None,
+        /// The target core's APIC ID can't be encoded in a message address.
+        MsixUnaddressable { apic_id: u32 } => None,
+        Irq { err: crate::interrupts::irq::Error } => Some(err)
     }
 }
 
@@ -22,6 +43,12 @@ crate::error_impl! {
 #[derive(Debug, Clone, Copy)]
 pub struct Command(u16);
 
+/// Bits of the command register enabling the device's decoding of its I/O and memory BARs.
+const COMMAND_IO_SPACE: usize = 0;
+const COMMAND_MEMORY_SPACE: usize = 1;
+/// Bit of the command register allowing the device to initiate memory accesses (i.e. DMA).
+const COMMAND_BUS_MASTER: usize = 2;
+
 // TODO impl command bits
 // impl CommandRegister {
 //     volatile_bitfield_getter_ro!(reg, io_space, 0);
@@ -123,7 +150,26 @@ pub enum Devices {
     PCI2PCI(Device<PCI2PCI>),
 }
 
-pub struct Device<T: Kind>(NonNull<u8>, PhantomData<T>);
+/// Segment, bus, device, and function numbers identifying a PCI device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Bdf {
+    pub segment: u16,
+    pub bus: u8,
+    pub device: u8,
+    pub function: u8,
+}
+
+impl fmt::Display for Bdf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04x}:{:02x}:{:02x}.{:x}", self.segment, self.bus, self.device, self.function)
+    }
+}
+
+/// Most BARs any supported kind of header has.
+const MAX_BARS: usize = 6;
+
+/// A PCI function's configuration space, along with its BARs as they were sized when it was enumerated.
+pub struct Device<T: Kind>(NonNull<u8>, Bdf, PhantomData<T>, [Option<Bar>; MAX_BARS]);
 
 // Safety: PCI MMIO (and so, the pointers used for it) utilize the global HHDM, and so can be sent between threads.
 unsafe impl<T: Kind> Send for Device<T> {}
@@ -131,12 +177,24 @@ unsafe impl<T: Kind> Send for Device<T> {}
 /// Safety
 ///
 /// Caller must ensure that the provided base pointer is a valid (and mapped) PCI MMIO header base.
-pub unsafe fn new(ptr: NonNull<u8>) -> Result<Devices> {
+pub unsafe fn new(ptr: NonNull<u8>, bdf: Bdf) -> Result<Devices> {
     let header_ty = unsafe { ptr.as_ptr().cast::<LittleEndianU8>().add(14).read_volatile() };
 
     match header_ty.get().get_bits(0..7) {
-        0x0 => Ok(Devices::Standard(Device::<Standard>(ptr, PhantomData))),
-        0x1 => Ok(Devices::PCI2PCI(Device(ptr, PhantomData))),
+        0x0 => {
+            let mut device = Device::<Standard>(ptr, bdf, PhantomData, [None; MAX_BARS]);
+            device.probe_bars();
+
+            Ok(Devices::Standard(device))
+        }
+
+        0x1 => {
+            let mut bridge = Device::<PCI2PCI>(ptr, bdf, PhantomData, [None; MAX_BARS]);
+            bridge.probe_bars();
+
+            Ok(Devices::PCI2PCI(bridge))
+        }
+
         0x2 => Err(Error::UnsupportedKind { raw: 0x2 }),
         raw => Err(Error::InvalidKind { raw }),
     }
@@ -153,6 +211,11 @@ impl<T: Kind> Device<T> {
         self.0.as_ptr().add(offset).cast::<U>().write_volatile(U::from(value));
     }
 
+    #[inline]
+    pub const fn bdf(&self) -> Bdf {
+        self.1
+    }
+
     pub fn get_vendor_id(&self) -> u16 {
         unsafe { self.read_offset::<LittleEndianU16>(0) }
     }
@@ -177,7 +240,8 @@ impl<T: Kind> Device<T> {
         unsafe { self.read_offset::<LittleEndianU8>(2 * Self::ROW_SIZE) }
     }
 
-    pub fn get_class(&self) -> Class {
+    /// Raw class, subclass, and programming interface codes.
+    pub fn get_class_codes(&self) -> (u8, u8, u8) {
         // Match format is:
         //  0x  00      | 00        | 00
         //      Class   | Subclass  | Program interface
@@ -187,6 +251,12 @@ impl<T: Kind> Device<T> {
         let subclass = unsafe { self.read_offset::<LittleEndianU8>(row_offset + 2) };
         let prog_if = unsafe { self.read_offset::<LittleEndianU8>(row_offset + 1) };
 
+        (class, subclass, prog_if)
+    }
+
+    pub fn get_class(&self) -> Class {
+        let (class, subclass, prog_if) = self.get_class_codes();
+
         Class::parse(class, subclass, prog_if)
     }
 
@@ -206,7 +276,9 @@ impl<T: Kind> Device<T> {
         unsafe { self.read_offset::<LittleEndianU8>((3 * Self::ROW_SIZE) + 2) }.get_bit(7)
     }
 
-    pub fn get_bar(&mut self, index: usize) -> Result<Bar> {
+    /// Sizes BAR `index` by writing all-ones to it and reading back which address bits are fixed, restoring its value
+    /// afterwards.
+    fn size_bar(&mut self, index: usize) -> Result<Bar> {
         if index >= T::REGISTER_COUNT {
             return Err(Error::BarIndexOverflow { index });
         }
@@ -215,26 +287,39 @@ impl<T: Kind> Device<T> {
         let bar = unsafe { self.read_offset::<LittleEndianU32>(bar_offset) };
 
         if bar.get_bit(0) {
-            Ok(Bar::IOSpace { address: bar & !0b11, size: 0 })
+            // Safety: See above about PCI spec.
+            let size = unsafe {
+                self.write_offset::<LittleEndianU32>(bar_offset, u32::MAX);
+                // Devices may only decode the lower 16 bits of I/O space, leaving the upper bits zeroed.
+                let size = (!(self.read_offset::<LittleEndianU32>(bar_offset) & !0b11)).wrapping_add(1) & 0xFFFF;
+                self.write_offset::<LittleEndianU32>(bar_offset, bar);
+                size
+            };
+
+            Ok(Bar::IOSpace { address: bar & !0b11, size })
         } else {
             match bar.get_bits(1..3) {
                 0b00 => {
                     // Safety: See above about PCI spec.
                     let size = unsafe {
                         self.write_offset::<LittleEndianU32>(bar_offset, u32::MAX);
-                        let size = !(self.read_offset::<LittleEndianU32>(bar_offset) & !0xF) + 1;
+                        let size = (!(self.read_offset::<LittleEndianU32>(bar_offset) & !0xF)).wrapping_add(1);
                         self.write_offset::<LittleEndianU32>(bar_offset, bar);
                         size
                     };
 
                     Ok(Bar::MemorySpace32 {
-                        address: Address::new(usize::try_from(bar).unwrap()).unwrap(),
+                        address: Address::new(usize::try_from(bar & !0xF).unwrap()).unwrap(),
                         size,
                         prefetch: bar.get_bit(3),
                     })
                 }
 
                 0b10 => {
+                    if (index + 1) >= T::REGISTER_COUNT {
+                        return Err(Error::BarIndexOverflow { index: index + 1 });
+                    }
+
                     let high_bar_offset = bar_offset + Self::ROW_SIZE;
                     let high_bar = unsafe { self.read_offset::<LittleEndianU32>(high_bar_offset) };
 
@@ -245,7 +330,7 @@ impl<T: Kind> Device<T> {
 
                         let size_low = u64::from(self.read_offset::<LittleEndianU32>(bar_offset) & !0xF);
                         let size_high = u64::from(self.read_offset::<LittleEndianU32>(high_bar_offset));
-                        let size = ((size_high << 32) | size_low) + 1;
+                        let size = (!((size_high << 32) | size_low)).wrapping_add(1);
 
                         self.write_offset::<LittleEndianU32>(bar_offset, bar);
                         self.write_offset::<LittleEndianU32>(high_bar_offset, high_bar);
@@ -256,9 +341,12 @@ impl<T: Kind> Device<T> {
                     let address = (u64::from(high_bar) << 32) | (u64::from(bar) & !0xF);
 
                     Ok(Bar::MemorySpace64 {
-                        address: Address::new(usize::try_from(address).unwrap()).unwrap(),
+                        address: usize::try_from(address)
+                            .ok()
+                            .and_then(Address::new)
+                            .ok_or(Error::InvalidBarAddress { address })?,
                         size,
-                        prefetch: address.get_bit(3),
+                        prefetch: bar.get_bit(3),
                     })
                 }
 
@@ -267,6 +355,136 @@ impl<T: Kind> Device<T> {
         }
     }
 
+    /// Sizes each of the device's BARs, recording them for [`Self::get_bar`].
+    ///
+    /// #### Remark
+    ///
+    /// Memory and I/O decoding are disabled while the BARs are sized, so the device never claims the all-ones
+    /// addresses sizing briefly programs into them.
+    fn probe_bars(&mut self) {
+        // Safety: The command register lies within the header.
+        let command = unsafe { self.read_offset::<LittleEndianU16>(Self::ROW_SIZE) };
+        // Safety: Only the decode bits of the command register are changed, and they're restored afterwards.
+        unsafe {
+            self.write_offset::<LittleEndianU16>(
+                Self::ROW_SIZE,
+                command & !((1 << COMMAND_IO_SPACE) | (1 << COMMAND_MEMORY_SPACE)),
+            );
+        }
+
+        self.3 = [None; MAX_BARS];
+
+        let mut index = 0;
+        while index < T::REGISTER_COUNT.min(MAX_BARS) {
+            match self.size_bar(index) {
+                Ok(bar) => {
+                    self.3[index] = Some(bar);
+                    index += bar.register_count();
+                }
+
+                Err(err) => {
+                    trace!("Failed to decode BAR{} of {}: {:?}", index, self.bdf(), err);
+                    index += 1;
+                }
+            }
+        }
+
+        // Safety: See above.
+        unsafe { self.write_offset::<LittleEndianU16>(Self::ROW_SIZE, command) };
+    }
+
+    /// Returns BAR `index`, as it was sized when the device was enumerated.
+    pub fn get_bar(&self, index: usize) -> Result<Bar> {
+        if index >= T::REGISTER_COUNT {
+            return Err(Error::BarIndexOverflow { index });
+        }
+
+        self.3.get(index).copied().flatten().ok_or(Error::NoBar { index })
+    }
+
+    /// Each of the device's BARs, paired with its register index (the upper register of a 64-bit BAR is skipped,
+    /// rather than decoded as a BAR of its own).
+    pub fn bars(&self) -> alloc::vec::Vec<(usize, Bar)> {
+        self.3.iter().take(T::REGISTER_COUNT).enumerate().filter_map(|(index, bar)| Some((index, (*bar)?))).collect()
+    }
+
+    /// Programs memory BAR `index` to decode from `address`, which must be aligned to the BAR's size.
+    pub fn set_bar_address(&mut self, index: usize, address: Address<Physical>) -> Result<()> {
+        let bar = self.get_bar(index)?;
+        let bar_offset = (4 + index) * Self::ROW_SIZE;
+        let address_bits = address.get();
+        debug_assert_eq!(address_bits % bar.get_size().max(1), 0);
+
+        // Safety: Only the address bits of the BAR's registers are written; its type bits are read-only.
+        let bar = unsafe {
+            let type_bits = self.read_offset::<LittleEndianU32>(bar_offset) & 0xF;
+
+            match bar {
+                Bar::MemorySpace32 { size, prefetch, .. } => {
+                    let low = u32::try_from(address_bits)
+                        .map_err(|_| Error::InvalidBarAddress { address: u64::try_from(address_bits).unwrap() })?;
+                    self.write_offset::<LittleEndianU32>(bar_offset, low | type_bits);
+
+                    Bar::MemorySpace32 { address, size, prefetch }
+                }
+
+                Bar::MemorySpace64 { size, prefetch, .. } => {
+                    let address_bits = u64::try_from(address_bits).unwrap();
+                    self.write_offset::<LittleEndianU32>(
+                        bar_offset,
+                        u32::try_from(address_bits & 0xFFFF_FFF0).unwrap() | type_bits,
+                    );
+                    self.write_offset::<LittleEndianU32>(
+                        bar_offset + Self::ROW_SIZE,
+                        u32::try_from(address_bits >> 32).unwrap(),
+                    );
+
+                    Bar::MemorySpace64 { address, size, prefetch }
+                }
+
+                Bar::IOSpace { .. } => return Err(Error::NotMemoryBar { index }),
+            }
+        };
+
+        self.3[index] = Some(bar);
+
+        Ok(())
+    }
+
+    /// Enables (or disables) the device's decoding of its memory BARs.
+    pub fn set_memory_decode(&mut self, enabled: bool) {
+        // Safety: Only the memory space bit of the command register is changed.
+        unsafe {
+            let mut command = self.read_offset::<LittleEndianU16>(Self::ROW_SIZE);
+            command.set_bit(COMMAND_MEMORY_SPACE, enabled);
+            self.write_offset::<LittleEndianU16>(Self::ROW_SIZE, command);
+        }
+    }
+
+    /// Allows (or disallows) the device to master the bus, which it must to access memory itself.
+    pub fn set_bus_master(&mut self, enabled: bool) {
+        // Safety: Only the bus master bit of the command register is changed.
+        unsafe {
+            let mut command = self.read_offset::<LittleEndianU16>(Self::ROW_SIZE);
+            command.set_bit(COMMAND_BUS_MASTER, enabled);
+            self.write_offset::<LittleEndianU16>(Self::ROW_SIZE, command);
+        }
+    }
+
+    /// Maps exactly the bytes memory BAR `index` decodes into the kernel's mapping window, wherever it lies in the
+    /// physical address space.
+    pub fn map_bar(&self, index: usize) -> Result<crate::mem::vmap::Mapping> {
+        let bar = self.get_bar(index)?;
+        if matches!(bar, Bar::IOSpace { .. }) {
+            return Err(Error::NotMemoryBar { index });
+        } else if bar.is_unused() || bar.get_size() == 0 {
+            return Err(Error::UnassignedBar { index });
+        }
+
+        crate::mem::vmap::map(bar.get_address().get(), bar.get_size(), crate::mem::paging::TableEntryFlags::MMIO)
+            .map_err(|err| Error::Vmap { err })
+    }
+
     pub fn generic_debug_fmt(&self, debug_struct: &mut fmt::DebugStruct) {
         debug_struct
             .field("ID", &format_args!("{:4X}:{:4X}", self.get_vendor_id(), self.get_device_id()))
@@ -297,6 +515,11 @@ impl Bar {
         }
     }
 
+    /// Whether the BAR decodes any addresses at all (an unimplemented register sizes to zero).
+    pub fn is_implemented(&self) -> bool {
+        self.get_size() > 0
+    }
+
     pub fn get_size(&self) -> usize {
         match self {
             Bar::MemorySpace32 { address: _, size, prefetch: _ } => usize::try_from(*size).unwrap(),
@@ -305,6 +528,14 @@ impl Bar {
         }
     }
 
+    /// Number of registers the BAR occupies.
+    pub const fn register_count(&self) -> usize {
+        match self {
+            Bar::MemorySpace64 { .. } => 2,
+            Bar::MemorySpace32 { .. } | Bar::IOSpace { .. } => 1,
+        }
+    }
+
     pub fn get_address(&self) -> Address<Physical> {
         match self {
             Bar::MemorySpace32 { address, size: _, prefetch: _ } => *address,
@@ -313,9 +544,3 @@ impl Bar {
         }
     }
 }
-
-impl core::fmt::Debug for Device<PCI2PCI> {
-    fn fmt(&self, formatter: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        formatter.debug_tuple("Not Implemented").finish()
-    }
-}
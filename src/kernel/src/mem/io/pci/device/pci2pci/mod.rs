@@ -0,0 +1,48 @@
+use crate::mem::io::pci::{Device, PCI2PCI};
+use libkernel::LittleEndianU8;
+
+impl Device<PCI2PCI> {
+    /// Offset of the bridge's primary, secondary, and subordinate bus numbers (in that order).
+    const BUS_NUMBERS_OFFSET: usize = Self::ROW_SIZE * 0x6;
+
+    /// Bus the bridge sits on.
+    pub fn primary_bus(&self) -> u8 {
+        // Safety: Offset is within the bridge's header.
+        unsafe { self.read_offset::<LittleEndianU8>(Self::BUS_NUMBERS_OFFSET) }
+    }
+
+    /// Bus directly behind the bridge.
+    pub fn secondary_bus(&self) -> u8 {
+        // Safety: Offset is within the bridge's header.
+        unsafe { self.read_offset::<LittleEndianU8>(Self::BUS_NUMBERS_OFFSET + 1) }
+    }
+
+    /// Highest-numbered bus behind the bridge (through any further bridges).
+    pub fn subordinate_bus(&self) -> u8 {
+        // Safety: Offset is within the bridge's header.
+        unsafe { self.read_offset::<LittleEndianU8>(Self::BUS_NUMBERS_OFFSET + 2) }
+    }
+
+    /// Numbers the buses the bridge forwards configuration cycles between.
+    pub fn set_bus_numbers(&mut self, primary: u8, secondary: u8, subordinate: u8) {
+        // Safety: Offsets are within the bridge's header, and the bus numbers are read-write.
+        unsafe {
+            self.write_offset::<LittleEndianU8>(Self::BUS_NUMBERS_OFFSET, primary);
+            self.write_offset::<LittleEndianU8>(Self::BUS_NUMBERS_OFFSET + 1, secondary);
+            self.write_offset::<LittleEndianU8>(Self::BUS_NUMBERS_OFFSET + 2, subordinate);
+        }
+    }
+}
+
+impl core::fmt::Debug for Device<PCI2PCI> {
+    fn fmt(&self, formatter: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let debug_struct = &mut formatter.debug_struct("PCIe Device (PCI-to-PCI Bridge)");
+
+        self.generic_debug_fmt(debug_struct);
+        debug_struct
+            .field("Primary Bus", &self.primary_bus())
+            .field("Secondary Bus", &self.secondary_bus())
+            .field("Subordinate Bus", &self.subordinate_bus())
+            .finish()
+    }
+}
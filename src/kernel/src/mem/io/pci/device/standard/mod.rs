@@ -1,8 +1,25 @@
 // mod capabilities;
 // pub use capabilities::*;
 
-use crate::mem::io::pci::{Device, Standard};
+mod msix;
+pub use msix::*;
+
+use crate::mem::{
+    io::pci::{
+        device::{Error, Result},
+        Device, Standard, Status,
+    },
+    paging::TableEntryFlags,
+    vmap::Mapping,
+};
+use alloc::vec::Vec;
+use bit_field::BitField;
 use libkernel::{LittleEndianU16, LittleEndianU32, LittleEndianU8};
+use libsys::{Address, Physical};
+
+/// Address bits of the expansion ROM register; the rest are reserved, besides the enable bit.
+const ROM_ADDRESS_MASK: u32 = 0xFFFF_F800;
+const ROM_ENABLE: usize = 0;
 
 impl Device<Standard> {
     pub fn cardbus_cis_ptr(&self) -> Option<usize> {
@@ -27,6 +44,133 @@ impl Device<Standard> {
         }
     }
 
+    /// Address the expansion ROM decodes from, if it's been given one.
+    pub fn expansion_rom_address(&self) -> Option<Address<Physical>> {
+        self.expansion_rom_base_addr()
+            .map(|value| value & usize::try_from(ROM_ADDRESS_MASK).unwrap())
+            .filter(|address| *address > 0)
+            .and_then(Address::new)
+    }
+
+    /// Sizes the expansion ROM as BARs are sized, returning 0 if the device implements none.
+    pub fn expansion_rom_size(&mut self) -> usize {
+        let offset = Self::ROW_SIZE * 0xC;
+
+        // Safety: Memory decoding is disabled while the all-ones address is programmed, and the command and ROM
+        //         registers are restored afterwards.
+        let fixed = unsafe {
+            let command = self.read_offset::<LittleEndianU16>(Self::ROW_SIZE);
+            self.set_memory_decode(false);
+
+            let rom = self.read_offset::<LittleEndianU32>(offset);
+            self.write_offset::<LittleEndianU32>(offset, ROM_ADDRESS_MASK | (rom & !ROM_ADDRESS_MASK));
+            let fixed = self.read_offset::<LittleEndianU32>(offset) & ROM_ADDRESS_MASK;
+            self.write_offset::<LittleEndianU32>(offset, rom);
+
+            self.write_offset::<LittleEndianU16>(Self::ROW_SIZE, command);
+            fixed
+        };
+
+        match fixed {
+            0 => 0,
+            fixed => usize::try_from((!fixed).wrapping_add(1)).unwrap(),
+        }
+    }
+
+    /// Enables (or disables) the device's decoding of its expansion ROM.
+    ///
+    /// #### Remark
+    ///
+    /// A device may share one address decoder between its ROM and its BARs, so the ROM should only be enabled while
+    /// it's being read.
+    pub fn set_expansion_rom_enabled(&mut self, enabled: bool) {
+        let offset = Self::ROW_SIZE * 0xC;
+
+        // Safety: Only the enable bit of the ROM register is changed.
+        unsafe {
+            let mut rom = self.read_offset::<LittleEndianU32>(offset);
+            rom.set_bit(ROM_ENABLE, enabled);
+            self.write_offset::<LittleEndianU32>(offset, rom);
+        }
+    }
+
+    /// Enables the expansion ROM (and memory decoding), and maps it into the kernel's mapping window.
+    pub fn map_expansion_rom(&mut self) -> Result<Mapping> {
+        let size = self.expansion_rom_size();
+        if size == 0 {
+            return Err(Error::NoExpansionRom);
+        }
+
+        let address = self.expansion_rom_address().ok_or(Error::UnassignedExpansionRom)?;
+        self.set_expansion_rom_enabled(true);
+        self.set_memory_decode(true);
+
+        crate::mem::vmap::map(address.get(), size, TableEntryFlags::MMIO).map_err(|err| Error::Vmap { err })
+    }
+
+    /// Copies out the whole expansion ROM, disabling it again afterwards.
+    pub fn read_expansion_rom(&mut self) -> Result<Vec<u8>> {
+        let rom = self.map_expansion_rom().map(|mapping| {
+            let mut rom = alloc::vec![0u8; mapping.len()];
+            // ROMs are read a dword at a time, as some devices don't support narrower reads of them.
+            for (index, dword) in rom.chunks_exact_mut(core::mem::size_of::<u32>()).enumerate() {
+                // Safety: ROMs are aligned to (and sized in multiples of) 2 KiB, so every dword lies within the mapping.
+                let value = unsafe { mapping.as_ptr().cast::<u32>().add(index).read_volatile() };
+                dword.copy_from_slice(&value.to_ne_bytes());
+            }
+
+            rom
+        });
+
+        self.set_expansion_rom_enabled(false);
+
+        rom
+    }
+
+    /// Offsets of the device's capabilities within its configuration space, paired with their IDs.
+    pub fn capabilities(&self) -> impl Iterator<Item = (u8, usize)> + '_ {
+        /// Capabilities lie beyond the standard header.
+        const MIN_OFFSET: u8 = 0x40;
+        /// Most capabilities which fit in the rest of the configuration space, past which a list must loop.
+        const MAX_CAPABILITIES: usize = 48;
+
+        let first = if self.get_status().contains(Status::CAPABILITIES) {
+            // Safety: The capabilities pointer is valid when the status register reports it is.
+            unsafe { self.read_offset::<LittleEndianU8>(Self::ROW_SIZE * 0xD) & !0b11 }
+        } else {
+            0
+        };
+
+        core::iter::successors(Some(first).filter(|offset| *offset >= MIN_OFFSET), move |offset| {
+            // Safety: Each capability's next pointer directly follows its ID.
+            let next = unsafe { self.read_offset::<LittleEndianU8>(usize::from(*offset) + 1) } & !0b11;
+            (next >= MIN_OFFSET).then_some(next)
+        })
+        .take(MAX_CAPABILITIES)
+        .map(|offset| {
+            // Safety: Each capability begins with its ID.
+            (unsafe { self.read_offset::<LittleEndianU8>(usize::from(offset)) }, usize::from(offset))
+        })
+    }
+
+    /// Returns the offset of the device's first capability with the ID `id`.
+    pub fn find_capability(&self, id: u8) -> Option<usize> {
+        self.capabilities().find(|(capability_id, _)| *capability_id == id).map(|(_, offset)| offset)
+    }
+
+    /// Reads dword `index` of the capability at `offset` (as yielded by [`Self::capabilities`]), for capabilities whose
+    /// layout is left to the driver (i.e. vendor-specific ones).
+    pub fn read_capability_dword(&self, offset: usize, index: usize) -> u32 {
+        /// Bytes of extended configuration space, within which every capability lies.
+        const CONFIG_SPACE_LEN: usize = 0x1000;
+
+        let dword_offset = offset + (index * Self::ROW_SIZE);
+        assert!(dword_offset + Self::ROW_SIZE <= CONFIG_SPACE_LEN);
+
+        // Safety: Offset lies within the function's configuration space.
+        unsafe { self.read_offset::<LittleEndianU32>(dword_offset) }
+    }
+
     // pub(self) fn capabilities(&self) -> CapablitiesIterator {
     //     CapablitiesIterator::new(&self.mmio, unsafe { (self.mmio.read::<u8>(0x34).assume_init() & !0b11) as usize })
     // }
@@ -0,0 +1,206 @@
+//! MSI-X, through which a device signals each of its interrupts by writing a message to a core's local APIC, rather
+//! than asserting a (possibly shared) INTx line.
+//!
+//! Each interrupt is given a device vector of its own (see [`irq::register_any`]), so its handler is never chained
+//! with another device's.
+
+use crate::{
+    interrupts::irq,
+    mem::{
+        io::pci::{
+            device::{Error, Result},
+            Device, Standard,
+        },
+        vmap,
+    },
+};
+use alloc::vec::Vec;
+use bit_field::BitField;
+use core::{marker::PhantomData, num::NonZeroUsize};
+use libkernel::{LittleEndian, LittleEndianU16, LittleEndianU32};
+
+/// Capability ID of MSI-X.
+const CAPABILITY_ID: u8 = 0x11;
+/// Size of each entry of the MSI-X table, in bytes.
+const ENTRY_SIZE: usize = 16;
+/// Offset of the vector control word within a table entry, whose lowest bit masks the entry.
+const ENTRY_VECTOR_CONTROL: usize = 12;
+
+/// Bits of the capability's message control word.
+const CONTROL_FUNCTION_MASK: usize = 14;
+const CONTROL_ENABLE: usize = 15;
+
+/// Bits of the device's command register.
+const COMMAND_MEMORY_SPACE: usize = 1;
+const COMMAND_BUS_MASTER: usize = 2;
+const COMMAND_INTX_DISABLE: usize = 10;
+
+/// Returns the message address and data which interrupt the core with the APIC ID `apic_id` on `vector`.
+#[cfg(target_arch = "x86_64")]
+fn message(apic_id: u32, vector: u64) -> Result<(u64, u32)> {
+    /// Base of the range of addresses which interrupt the local APIC with the ID in bits 12..20.
+    const ADDRESS_BASE: u64 = 0xFEE0_0000;
+
+    // Wider IDs can only be targeted through interrupt remapping.
+    let apic_id = u8::try_from(apic_id).map_err(|_| Error::MsixUnaddressable { apic_id })?;
+
+    // Fixed delivery, edge-triggered.
+    Ok((ADDRESS_BASE | (u64::from(apic_id) << 12), u32::try_from(vector).unwrap()))
+}
+
+/// MSI-X enabled on a device, with a handler registered for each of its first table entries.
+///
+/// #### Remark
+///
+/// Dropping it masks each entry and disables MSI-X before unregistering the handlers, so the device is left unable to
+/// signal interrupts (its INTx line stays disabled).
+pub struct Msix {
+    /// Handle to the device's configuration space, aliasing the device's own.
+    device: Device<Standard>,
+    /// Offset of the capability within the configuration space.
+    capability: usize,
+    table: vmap::Mapping,
+    table_offset: usize,
+    vectors: Vec<(u64, irq::HandlerId)>,
+}
+
+impl Msix {
+    /// Vectors allocated to each table entry, in order.
+    pub fn vectors(&self) -> impl Iterator<Item = u64> + '_ {
+        self.vectors.iter().map(|(vector, _)| *vector)
+    }
+
+    fn entry_word(&self, index: usize, offset: usize) -> *mut LittleEndianU32 {
+        assert!(index < self.vectors.len());
+
+        self.table.as_ptr().as_ptr().wrapping_add(self.table_offset + (index * ENTRY_SIZE) + offset).cast()
+    }
+
+    /// Masks (or unmasks) table entry `index`, so the device holds its interrupts pending rather than signalling them.
+    pub fn set_masked(&self, index: usize, masked: bool) {
+        let vector_control = self.entry_word(index, ENTRY_VECTOR_CONTROL);
+
+        // Safety: The entry lies within the mapped table, and only its mask bit is changed.
+        unsafe {
+            let mut value = vector_control.read_volatile().get();
+            value.set_bit(0, masked);
+            vector_control.write_volatile(LittleEndianU32::from(value));
+        }
+    }
+
+    /// Retargets table entry `index` at the core with the APIC ID `apic_id` (i.e. to steer a queue's completions to
+    /// the core which submits on it).
+    pub fn set_target(&self, index: usize, apic_id: u32) -> Result<()> {
+        let (address, data) = message(apic_id, self.vectors[index].0)?;
+
+        self.set_masked(index, true);
+        self.write_message(index, address, data);
+        self.set_masked(index, false);
+
+        Ok(())
+    }
+
+    fn write_message(&self, index: usize, address: u64, data: u32) {
+        // Safety: Each word lies within the entry, which lies within the mapped table.
+        unsafe {
+            self.entry_word(index, 0)
+                .write_volatile(LittleEndianU32::from(u32::try_from(address & 0xFFFF_FFFF).unwrap()));
+            self.entry_word(index, 4).write_volatile(LittleEndianU32::from(u32::try_from(address >> 32).unwrap()));
+            self.entry_word(index, 8).write_volatile(LittleEndianU32::from(data));
+        }
+    }
+
+    fn set_control(&mut self, bit: usize, value: bool) {
+        let offset = self.capability + 2;
+
+        // Safety: The message control word lies within the capability.
+        unsafe {
+            let mut control = self.device.read_offset::<LittleEndianU16>(offset);
+            control.set_bit(bit, value);
+            self.device.write_offset::<LittleEndianU16>(offset, control);
+        }
+    }
+}
+
+impl Drop for Msix {
+    fn drop(&mut self) {
+        for index in 0..self.vectors.len() {
+            self.set_masked(index, true);
+        }
+        self.set_control(CONTROL_ENABLE, false);
+
+        for (vector, id) in self.vectors.drain(..) {
+            if let Err(err) = irq::unregister(vector, id) {
+                warn!("Failed to unregister MSI-X handler from vector {:#X}: {:?}", vector, err);
+            }
+        }
+    }
+}
+
+impl Device<Standard> {
+    /// Enables MSI-X on the device with `vector_count` interrupts, allocating each a device vector and registering the
+    /// handler `handler_for` returns for its table entry index. Interrupts are targeted at the local core.
+    pub fn enable_msix(
+        &mut self,
+        vector_count: NonZeroUsize,
+        name: &str,
+        mut handler_for: impl FnMut(usize) -> irq::Handler,
+    ) -> Result<Msix> {
+        let capability = self.find_capability(CAPABILITY_ID).ok_or(Error::NoMsix)?;
+
+        // Safety: The capability's control, table, and pending bit array words lie within it.
+        let (control, table_info) = unsafe {
+            (self.read_offset::<LittleEndianU16>(capability + 2), self.read_offset::<LittleEndianU32>(capability + 4))
+        };
+
+        let available = usize::from(control.get_bits(0..11)) + 1;
+        if vector_count.get() > available {
+            return Err(Error::TooManyVectors { requested: vector_count.get(), available });
+        }
+
+        let table_offset = usize::try_from(table_info & !0b111).unwrap();
+        let table = self.map_bar(usize::try_from(table_info.get_bits(0..3)).unwrap())?;
+        if (table_offset + (vector_count.get() * ENTRY_SIZE)) > table.len() {
+            return Err(Error::NoMsix);
+        }
+
+        let mut msix = Msix {
+            device: Device(self.0, self.1, PhantomData, self.3),
+            capability,
+            table,
+            table_offset,
+            vectors: Vec::with_capacity(vector_count.get()),
+        };
+
+        // Entries are only written while the whole function is masked.
+        msix.set_control(CONTROL_FUNCTION_MASK, true);
+        msix.set_control(CONTROL_ENABLE, true);
+
+        let apic_id = crate::cpu::read_id();
+        for index in 0..vector_count.get() {
+            let handler_name = alloc::format!("{name}:msix{index}");
+            let (vector, id) =
+                irq::register_any(&handler_name, handler_for(index)).map_err(|err| Error::Irq { err })?;
+            msix.vectors.push((vector, id));
+
+            let (address, data) = message(apic_id, vector)?;
+
+            msix.set_masked(index, true);
+            msix.write_message(index, address, data);
+            msix.set_masked(index, false);
+        }
+
+        // Safety: Only the memory space, bus mastering, and INTx disable bits are changed, which MSI-X requires.
+        unsafe {
+            let mut command = self.read_offset::<LittleEndianU16>(Self::ROW_SIZE);
+            command.set_bit(COMMAND_MEMORY_SPACE, true);
+            command.set_bit(COMMAND_BUS_MASTER, true);
+            command.set_bit(COMMAND_INTX_DISABLE, true);
+            self.write_offset::<LittleEndianU16>(Self::ROW_SIZE, command);
+        }
+
+        msix.set_control(CONTROL_FUNCTION_MASK, false);
+
+        Ok(msix)
+    }
+}
@@ -1,13 +1,17 @@
-mod device;
+pub mod device;
 pub use device::*;
 
+pub mod rom;
+
 use crate::mem::{alloc::pmm, paging, HHDM};
-use alloc::{collections::BTreeMap, vec::Vec};
-use core::ptr::NonNull;
-use libkernel::{LittleEndian, LittleEndianU16};
-use libsys::{Address, Frame};
+use alloc::vec::Vec;
+use bit_field::BitField;
+use core::{
+    ops::{Range, RangeInclusive},
+    ptr::NonNull,
+};
+use libsys::{page_size, Address, Frame};
 use spin::Mutex;
-use uuid::Uuid;
 
 crate::error_impl! {
     #[derive(Debug)]
@@ -18,14 +22,138 @@ crate::error_impl! {
     }
 }
 
+/// Enumerated devices which no driver has been bound to.
 static PCI_DEVICES: Mutex<Vec<Device<Standard>>> = Mutex::new(Vec::new());
-static OWNED_DEVICES: Mutex<BTreeMap<Uuid, Device<Standard>>> = Mutex::new(BTreeMap::new());
 
-pub fn get_device_base_address(base: usize, bus_index: u8, device_index: u8) -> Address<Frame> {
+pub fn get_device_base_address(base: usize, bus_index: u8, device_index: u8, function_index: u8) -> Address<Frame> {
     let bus_index = usize::from(bus_index);
     let device_index = usize::from(device_index);
+    let function_index = usize::from(function_index);
+
+    Address::new(base | (bus_index << 20) | (device_index << 15) | (function_index << 12)).unwrap()
+}
 
-    Address::new(base | (bus_index << 20) | (device_index << 15)).unwrap()
+/// Enumeration of the buses of a PCI segment, walking down through PCI-to-PCI bridges.
+///
+/// Bridges the firmware left unnumbered are given the next free bus numbers, with their subordinate bus provisionally
+/// the last of the segment while the buses behind them are walked.
+struct Enumeration<'a> {
+    base: usize,
+    segment: u16,
+    buses: RangeInclusive<u8>,
+    /// Next bus number to give an unnumbered bridge.
+    next_bus: u16,
+    visited: [bool; 256],
+    devices: &'a mut Vec<Device<Standard>>,
+}
+
+impl Enumeration<'_> {
+    /// Returns a pointer to the configuration space of the function, if one is present.
+    fn probe(&self, bus_index: u8, device_index: u8, function_index: u8) -> Option<NonNull<u8>> {
+        let device_frame = get_device_base_address(self.base, bus_index, device_index, function_index);
+        let Some(device_ptr) = HHDM.mmio(device_frame, page_size()) else {
+            warn!("PCI configuration space at {:X?} overlaps RAM.", device_frame);
+            return None;
+        };
+
+        // Safety: Vendor ID is read-only, so reading it has no side effects. Configuration space which the platform
+        //         left unmapped faults, and is treated as absent.
+        let vendor_id = u16::from_le(unsafe { crate::mem::io::probe::read_u16(device_ptr.cast())? });
+        (vendor_id > u16::MIN && vendor_id < u16::MAX).then_some(device_ptr)
+    }
+
+    fn take_bus(&mut self) -> Option<u8> {
+        let bus = u8::try_from(self.next_bus).ok().filter(|bus| self.buses.contains(bus))?;
+        self.next_bus += 1;
+
+        Some(bus)
+    }
+
+    fn scan_bus(&mut self, bus_index: u8) {
+        if core::mem::replace(&mut self.visited[usize::from(bus_index)], true) {
+            return;
+        }
+
+        for device_index in 0u8..32u8 {
+            for function_index in 0u8..8u8 {
+                let Some(ptr) = self.probe(bus_index, device_index, function_index) else {
+                    if function_index == 0 {
+                        break;
+                    }
+
+                    continue;
+                };
+
+                // Safety: The header type lies within the function's header.
+                let is_multi_function = unsafe { ptr.as_ptr().add(14).read_volatile() }.get_bit(7);
+                self.scan_function(
+                    ptr,
+                    Bdf { segment: self.segment, bus: bus_index, device: device_index, function: function_index },
+                );
+
+                if function_index == 0 && !is_multi_function {
+                    break;
+                }
+            }
+        }
+    }
+
+    fn scan_function(&mut self, ptr: NonNull<u8>, bdf: Bdf) {
+        debug!("Configuring PCIe device: [{}@{:X?}]", bdf, ptr);
+
+        // Safety: Base pointer, at this point, has been verified as known-good.
+        match unsafe { new(ptr, bdf) } {
+            Ok(Devices::Standard(device)) => {
+                trace!("{:#?}", device);
+                self.devices.push(device);
+            }
+
+            Ok(Devices::PCI2PCI(bridge)) => self.scan_bridge(bridge),
+
+            Err(err) => warn!("Failed to configure PCIe device {}: {:?}", bdf, err),
+        }
+    }
+
+    fn scan_bridge(&mut self, mut bridge: Device<PCI2PCI>) {
+        let bdf = bridge.bdf();
+        let secondary = bridge.secondary_bus();
+
+        if secondary > bdf.bus && self.buses.contains(&secondary) && !self.visited[usize::from(secondary)] {
+            // Numbered by the firmware, so the buses it numbered behind the bridge aren't given out again.
+            self.next_bus = self.next_bus.max(u16::from(bridge.subordinate_bus()) + 1);
+            trace!("{:#?}", bridge);
+
+            self.scan_bus(secondary);
+        } else {
+            let Some(secondary) = self.take_bus() else {
+                warn!("No bus numbers are left for the bridge at {}; the devices behind it are unreachable.", bdf);
+                return;
+            };
+
+            bridge.set_bus_numbers(bdf.bus, secondary, *self.buses.end());
+            self.scan_bus(secondary);
+
+            // Narrowed to the buses numbered behind it.
+            let subordinate = u8::try_from(self.next_bus - 1).unwrap();
+            bridge.set_bus_numbers(bdf.bus, secondary, subordinate);
+            trace!("{:#?}", bridge);
+        }
+    }
+}
+
+/// Tags the frames of the configuration space `range` which lie within the frame table as device memory, so they're
+/// never handed out (and pass the checks of [`crate::mem::Hhdm::mmio`]).
+fn track_config_space(range: Range<usize>) {
+    for address in range.step_by(page_size()) {
+        let frame = Address::new(address).unwrap();
+
+        // Frames already in use are left alone, and will fail the check instead.
+        match pmm::get().owner(frame) {
+            Ok(pmm::Owner::Free) => pmm::get().lock_frame(frame, pmm::Owner::Mmio).ok(),
+            Ok(pmm::Owner::Reserved) => pmm::get().retag_frame(frame, pmm::Owner::Mmio).ok(),
+            _ => None,
+        };
+    }
 }
 
 pub fn init_devices() -> Result<()> {
@@ -34,39 +162,237 @@ pub fn init_devices() -> Result<()> {
     let acpi_tables = crate::acpi::TABLES.get().ok_or(Error::NoninitTables)?.lock();
     let pci_regions = acpi::PciConfigRegions::new(&acpi_tables, pmm::get()).map_err(|err| Error::AcpiError { err })?;
 
-    pci_regions
+    let mut config_ranges = Vec::new();
+    for entry in pci_regions.iter() {
+        let config_range = (entry.physical_address + (usize::from(*entry.bus_range.start()) << 20))
+            ..(entry.physical_address + ((usize::from(*entry.bus_range.end()) + 1) << 20));
+        track_config_space(config_range.clone());
+        config_ranges.push(config_range);
+
+        let root_bus = *entry.bus_range.start();
+        let mut enumeration = Enumeration {
+            base: entry.physical_address,
+            segment: entry.segment_group,
+            buses: entry.bus_range.clone(),
+            next_bus: u16::from(root_bus) + 1,
+            visited: [false; 256],
+            devices: &mut devices,
+        };
+
+        enumeration.scan_bus(root_bus);
+
+        // Host bridges may decode further root buses, which no bridge leads to.
+        for bus_index in entry.bus_range.clone() {
+            enumeration.scan_bus(bus_index);
+        }
+    }
+
+    // Assigned BARs are claimed first, so unassigned BARs are placed around them.
+    for device in devices.iter_mut() {
+        claim_bars(device);
+    }
+
+    match crate::init::boot::get_memory_map() {
+        Ok(memory_map) => {
+            let mut reserved: Vec<Range<usize>> = memory_map
+                .iter()
+                .map(|entry| {
+                    let range = entry.range();
+                    usize::try_from(range.start).unwrap()..usize::try_from(range.end).unwrap()
+                })
+                .collect();
+            reserved.extend(config_ranges);
+
+            assign_bars(&mut devices, &assignment_windows(reserved));
+        }
+
+        Err(err) => warn!("Unassigned BARs can't be placed without the memory map: {:?}", err),
+    }
+
+    for device in devices.iter_mut() {
+        publish_attributes(device);
+    }
+
+    Ok(())
+}
+
+/// Offers each unbound device to `bind`, which takes ownership of those it binds to a driver, and hands back the rest.
+pub fn bind_devices(mut bind: impl FnMut(Device<Standard>) -> Option<Device<Standard>>) {
+    let mut devices = PCI_DEVICES.lock();
+    *devices = core::mem::take(&mut *devices).into_iter().filter_map(&mut bind).collect();
+}
+
+/// Start of the range holding the I/O APIC, local APIC, and firmware, which BARs are never placed at or above (below
+/// 4 GiB).
+const LOW_WINDOW_END: usize = 0xFEC0_0000;
+/// Length of the window above both 4 GiB and the memory map, in which 64-bit BARs are placed.
+const HIGH_WINDOW_LEN: usize = 1 << 36;
+
+/// Windows of the physical address space in which unassigned BARs are placed.
+struct Windows {
+    /// Gaps between the `reserved` ranges below [`LOW_WINDOW_END`], which any memory BAR can decode.
+    low: Vec<Range<usize>>,
+    /// Range above 4 GiB, which only 64-bit BARs can decode.
+    high: Range<usize>,
+}
+
+/// Finds the windows unassigned BARs can be placed in, around the `reserved` ranges (i.e. the memory map).
+///
+/// #### Remark
+///
+/// The ranges a host bridge really forwards are described by its `_CRS`, which can't be read without evaluating AML.
+/// Instead, BARs are placed where firmware conventionally leaves room for them: between the memory map's entries
+/// below the APICs, or past the top of the memory map.
+fn assignment_windows(mut reserved: Vec<Range<usize>>) -> Windows {
+    const LOW_WINDOW_START: usize = 0x10_0000;
+    const FOUR_GIB: usize = 1 << 32;
+
+    reserved.sort_unstable_by_key(|range| range.start);
+
+    let mut low = Vec::new();
+    let mut start = LOW_WINDOW_START;
+    for range in reserved.iter().chain(core::iter::once(&(LOW_WINDOW_END..usize::MAX))) {
+        let end = range.start.min(LOW_WINDOW_END);
+        if end > start {
+            low.push(start..end);
+        }
+
+        start = start.max(range.end);
+        if start >= LOW_WINDOW_END {
+            break;
+        }
+    }
+
+    let high_start = reserved.iter().map(|range| range.end).max().unwrap_or(0).max(FOUR_GIB).next_multiple_of(FOUR_GIB);
+
+    Windows { low, high: high_start..(high_start + HIGH_WINDOW_LEN) }
+}
+
+/// Gives each memory BAR the firmware left unassigned an address within `windows`, claiming it as the device's.
+///
+/// #### Remark
+///
+/// BARs are placed largest first, as each is aligned to its own size. I/O space BARs aren't assigned.
+fn assign_bars(devices: &mut [Device<Standard>], windows: &Windows) {
+    use crate::mem::io::resource::{self, Kind, Space};
+
+    let mut unassigned: Vec<(usize, usize, Bar)> = devices
         .iter()
-        .map(|entry| (entry.physical_address, entry.segment_group, entry.bus_range))
-        .flat_map(|(base_address, segment_index, bus_range)| {
-            bus_range.map(move |bus_index| (base_address, segment_index, bus_index))
-        })
-        .flat_map(|(base_address, segment_index, bus_index)| {
-            (0u8..32u8).map(move |device_index| (base_address, segment_index, bus_index, device_index))
+        .enumerate()
+        .flat_map(|(device_index, device)| {
+            device
+                .bars()
+                .into_iter()
+                .filter(|(_, bar)| !matches!(bar, Bar::IOSpace { .. }) && bar.is_unused() && bar.is_implemented())
+                .map(move |(index, bar)| (device_index, index, bar))
         })
-        .try_for_each(|(base_address, segment_index, bus_index, device_index)| {
-            let device_frame = get_device_base_address(base_address, bus_index, device_index);
-            let device_page = HHDM.offset(device_frame).unwrap();
-
-            // Safety: We should be reading known-good memory here, according to the PCI spec. The following `if` test will verify that.
-            let vendor_id = unsafe { device_page.as_ptr().cast::<LittleEndianU16>().read_volatile() };
-            if vendor_id.get() > u16::MIN && vendor_id.get() < u16::MAX {
-                debug!(
-                    "Configuring PCIe device: [{:0>2}:{:0>2}:{:0>2}.00@{:X?}]",
-                    segment_index, bus_index, device_index, device_page
-                );
+        .collect();
+    unassigned.sort_unstable_by_key(|(_, _, bar)| core::cmp::Reverse(bar.get_size()));
 
-                // Safety: Base pointer, at this point, has been verified as known-good.
-                match unsafe { new(NonNull::new(device_page.as_ptr()).unwrap()) } {
-                    Ok(Devices::Standard(device)) => {
-                        trace!("{:#?}", device);
-                        devices.push(device);
-                    }
+    for (device_index, index, bar) in unassigned {
+        let device = &mut devices[device_index];
+        let owner = alloc::format!("pci:{}", device.bdf());
+        let size = bar.get_size();
 
-                    // TODO handle PCI-to-PCI busses
-                    _ => {}
-                }
+        let mut candidates = Vec::new();
+        if matches!(bar, Bar::MemorySpace64 { .. }) {
+            candidates.push(windows.high.clone());
+        }
+        candidates.extend(windows.low.iter().cloned());
+
+        let Some((address, id)) = candidates
+            .into_iter()
+            .find_map(|window| resource::claim_free(Space::Memory, window, size, size, &owner, Kind::Window).ok())
+        else {
+            warn!("No room is left to assign BAR{} of {} ({:#X} bytes).", index, owner, size);
+            continue;
+        };
+
+        match device.set_bar_address(index, Address::new(address).unwrap()) {
+            Ok(()) => {
+                debug!("Assigned BAR{} of {} to {:#X} ({:#X} bytes).", index, owner, address, size);
+                device.set_memory_decode(true);
             }
 
-            Ok(())
-        })
+            Err(err) => {
+                warn!("Failed to assign BAR{} of {}: {:?}", index, owner, err);
+                resource::release(id).ok();
+            }
+        }
+    }
+}
+
+/// Claims the device's assigned BARs as windows, for its driver to claim within.
+fn claim_bars(device: &mut Device<Standard>) {
+    use crate::mem::io::resource::{self, Kind, Space};
+
+    let owner = alloc::format!("pci:{}", device.bdf());
+
+    for (index, bar) in device.bars() {
+        if bar.is_unused() || !bar.is_implemented() {
+            continue;
+        }
+
+        let space = match bar {
+            Bar::MemorySpace32 { .. } | Bar::MemorySpace64 { .. } => Space::Memory,
+            Bar::IOSpace { .. } => Space::Port,
+        };
+
+        if let Err(err) = resource::claim(space, bar.get_address().get(), bar.get_size(), &owner, Kind::Window) {
+            warn!("Failed to claim BAR{} of {}: {:?}", index, owner, err);
+        }
+    }
+}
+
+/// Publishes the device's configuration to the attribute tree, under `bus/pci/<bdf>`.
+fn publish_attributes(device: &mut Device<Standard>) {
+    use alloc::format;
+
+    let bdf = device.bdf();
+    let path = format!("bus/pci/{bdf}");
+
+    crate::sysfs::set(&path, "vendor", format!("{:#06x}", device.get_vendor_id()));
+    crate::sysfs::set(&path, "device", format!("{:#06x}", device.get_device_id()));
+    crate::sysfs::set(&path, "revision", format!("{:#04x}", device.get_revision_id()));
+    crate::sysfs::set(&path, "class", format!("{:?}", device.get_class()));
+    crate::sysfs::set(
+        &path,
+        "subsystem",
+        format!("{:#06x}:{:#06x}", device.subsystem_vendor_id(), device.subsystem_id()),
+    );
+    crate::sysfs::set(&path, "irq_line", format!("{:?}", device.interrupt_line()));
+    crate::sysfs::set(&path, "irq_pin", format!("{:?}", device.interrupt_pin()));
+
+    for (index, bar) in device.bars() {
+        if !bar.is_unused() {
+            crate::sysfs::set(&path, &format!("bar{index}"), format!("{bar:X?}"));
+        }
+    }
+
+    match device.read_expansion_rom() {
+        Ok(rom) => {
+            let images = rom::images(&rom)
+                .into_iter()
+                .map(|image| {
+                    format!(
+                        "{:#x}+{:#x} {:?} {:04x}:{:04x} rev {:#x}\n",
+                        image.offset, image.len, image.code_type, image.vendor_id, image.device_id, image.revision
+                    )
+                })
+                .collect::<alloc::string::String>();
+
+            crate::sysfs::set(&path, "rom", images);
+        }
+
+        Err(device::Error::NoExpansionRom) => {}
+        Err(err) => debug!("Failed to read the expansion ROM of {}: {:?}", bdf, err),
+    }
+
+    crate::sysfs::set(
+        &path,
+        "driver",
+        crate::sysfs::Value::Dynamic(alloc::boxed::Box::new(move || {
+            alloc::string::String::from(crate::driver::bound_to(bdf).unwrap_or("none"))
+        })),
+    );
 }
@@ -0,0 +1,99 @@
+//! PCI option ROMs: the images held by a device's expansion ROM, each with the code which initializes the device on
+//! some platform (i.e. a legacy video BIOS, or an EFI driver).
+
+use alloc::vec::Vec;
+
+/// Every image begins with this signature, and is a multiple of this many bytes long.
+const IMAGE_SIGNATURE: u16 = 0xAA55;
+const IMAGE_UNIT: usize = 512;
+const PCI_DATA_SIGNATURE: &[u8; 4] = b"PCIR";
+/// Bit of the indicator byte marking the last image of the ROM.
+const LAST_IMAGE: u8 = 1 << 7;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodeType {
+    /// x86 real-mode code (i.e. a legacy video BIOS).
+    PcAt,
+    OpenFirmware,
+    HpPaRisc,
+    Efi,
+    Unknown(u8),
+}
+
+impl From<u8> for CodeType {
+    fn from(code_type: u8) -> Self {
+        match code_type {
+            0x00 => Self::PcAt,
+            0x01 => Self::OpenFirmware,
+            0x02 => Self::HpPaRisc,
+            0x03 => Self::Efi,
+            code_type => Self::Unknown(code_type),
+        }
+    }
+}
+
+/// An image of an option ROM, as described by its PCI data structure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Image {
+    /// Offset of the image within the ROM.
+    pub offset: usize,
+    pub len: usize,
+    pub vendor_id: u16,
+    pub device_id: u16,
+    /// Class, subclass, and programming interface codes the image's code supports.
+    pub class_codes: (u8, u8, u8),
+    /// Vendor-defined revision of the image's code (i.e. the firmware version).
+    pub revision: u16,
+    pub code_type: CodeType,
+}
+
+fn read_u16(rom: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(rom.get(offset..(offset + 2))?.try_into().unwrap()))
+}
+
+/// Parses the image at `offset` of `rom`, returning it and whether it's the last.
+fn parse_image(rom: &[u8], offset: usize) -> Option<(Image, bool)> {
+    if read_u16(rom, offset)? != IMAGE_SIGNATURE {
+        return None;
+    }
+
+    let data = offset + usize::from(read_u16(rom, offset + 0x18)?);
+    let pci_data = rom.get(data..(data + 0x18))?;
+    if &pci_data[..4] != PCI_DATA_SIGNATURE {
+        return None;
+    }
+
+    let len = usize::from(read_u16(pci_data, 0x10)?) * IMAGE_UNIT;
+    if len == 0 {
+        return None;
+    }
+
+    let image = Image {
+        offset,
+        len,
+        vendor_id: read_u16(pci_data, 0x04)?,
+        device_id: read_u16(pci_data, 0x06)?,
+        class_codes: (pci_data[0x0F], pci_data[0x0E], pci_data[0x0D]),
+        revision: read_u16(pci_data, 0x12)?,
+        code_type: CodeType::from(pci_data[0x14]),
+    };
+
+    Some((image, (pci_data[0x15] & LAST_IMAGE) > 0))
+}
+
+/// Parses the images of `rom`, stopping at the last (or the first which is malformed).
+pub fn images(rom: &[u8]) -> Vec<Image> {
+    let mut images = Vec::new();
+
+    let mut offset = 0;
+    while let Some((image, last)) = parse_image(rom, offset) {
+        offset += image.len;
+        images.push(image);
+
+        if last {
+            break;
+        }
+    }
+
+    images
+}
@@ -0,0 +1,130 @@
+//! Driver utilities for polling device state with a timeout, and updating MMIO register fields.
+
+use crate::time::ClockSource;
+use core::{
+    ops::{BitAnd, BitOr, Not},
+    sync::atomic::{fence, Ordering},
+};
+use libkernel::{mem::VolatileCell, ReadWrite};
+
+crate::error_impl! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Error {
+        /// The polled condition did not become true before the timeout elapsed.
+        Timeout => None
+    }
+}
+
+/// Number of microseconds [`poll_until_yield`] spins before it begins waiting for interrupts between polls.
+const SPIN_US: u32 = 50;
+
+/// A timeout, measured against a [`ClockSource`] (the system clock, unless otherwise specified).
+pub struct Timeout<'a> {
+    clock: &'a dyn ClockSource,
+    remaining_ticks: u64,
+    last_tick: u64,
+}
+
+impl Timeout<'static> {
+    pub fn from_us(microseconds: u32) -> Self {
+        Self::with_clock(&*crate::time::SYSTEM_CLOCK, microseconds)
+    }
+}
+
+impl<'a> Timeout<'a> {
+    /// Creates a timeout measured against `clock` (i.e. a [`crate::time::VirtualClock`]).
+    pub fn with_clock(clock: &'a dyn ClockSource, microseconds: u32) -> Self {
+        Self {
+            clock,
+            remaining_ticks: (u64::from(microseconds) * clock.frequency()) / 1_000_000,
+            last_tick: clock.get_timestamp(),
+        }
+    }
+
+    /// Accumulates the time elapsed since the last check, and indicates whether the timeout has expired.
+    pub fn is_expired(&mut self) -> bool {
+        let tick = self.clock.get_timestamp();
+        let elapsed = tick.wrapping_sub(self.last_tick) & self.clock.max_timestamp();
+        self.remaining_ticks = self.remaining_ticks.saturating_sub(elapsed);
+        self.last_tick = tick;
+
+        self.remaining_ticks == 0
+    }
+}
+
+/// Spins until `condition` returns `true`, or `timeout_us` microseconds have elapsed.
+pub fn poll_until(timeout_us: u32, mut condition: impl FnMut() -> bool) -> Result<()> {
+    let mut timeout = Timeout::from_us(timeout_us);
+
+    loop {
+        // Order the poll after any preceding device register writes.
+        fence(Ordering::SeqCst);
+
+        if condition() {
+            return Ok(());
+        } else if timeout.is_expired() {
+            return Err(Error::Timeout);
+        }
+
+        core::hint::spin_loop();
+    }
+}
+
+/// Polls until `condition` returns `true`, or `timeout_us` microseconds have elapsed.
+///
+/// Spins briefly, then (if interrupts are enabled) waits for the next interrupt between polls,
+/// which is better suited to long device timeouts (e.g. controller resets).
+pub fn poll_until_yield(timeout_us: u32, mut condition: impl FnMut() -> bool) -> Result<()> {
+    match poll_until(timeout_us.min(SPIN_US), &mut condition) {
+        Err(Error::Timeout) if timeout_us > SPIN_US => {}
+        result => return result,
+    }
+
+    let mut timeout = Timeout::from_us(timeout_us - SPIN_US);
+
+    loop {
+        fence(Ordering::SeqCst);
+
+        if condition() {
+            return Ok(());
+        } else if timeout.is_expired() {
+            return Err(Error::Timeout);
+        }
+
+        if crate::interrupts::are_enabled() {
+            crate::interrupts::wait();
+        } else {
+            core::hint::spin_loop();
+        }
+    }
+}
+
+/// Performs a read-modify-write of the register.
+pub fn update<T: Copy>(register: &VolatileCell<T, ReadWrite>, func: impl FnOnce(T) -> T) {
+    register.write(func(register.read()));
+    fence(Ordering::SeqCst);
+}
+
+/// Sets the bits of `mask` in the register.
+pub fn set_bits<T: Copy + BitOr<Output = T>>(register: &VolatileCell<T, ReadWrite>, mask: T) {
+    update(register, |value| value | mask);
+}
+
+/// Clears the bits of `mask` in the register.
+pub fn clear_bits<T: Copy + BitAnd<Output = T> + Not<Output = T>>(register: &VolatileCell<T, ReadWrite>, mask: T) {
+    update(register, |value| value & !mask);
+}
+
+/// Sets the (self-clearing) reset bits of `mask` in the register, then waits for the device to clear them.
+pub fn reset_and_wait<T>(register: &VolatileCell<T, ReadWrite>, mask: T, timeout_us: u32) -> Result<()>
+where
+    T: Copy + Default + PartialEq + BitAnd<Output = T> + BitOr<Output = T>,
+{
+    set_bits(register, mask);
+    poll_until_yield(timeout_us, || (register.read() & mask) == T::default())
+}
+
+/// Runs `reset` exactly once for the lifetime of `once`, returning its (cached) result to every caller.
+pub fn reset_once(once: &spin::Once<Result<()>>, reset: impl FnOnce() -> Result<()>) -> Result<()> {
+    *once.call_once(reset)
+}
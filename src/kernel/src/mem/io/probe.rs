@@ -0,0 +1,118 @@
+//! Reads of MMIO which may not be backed by anything, such as configuration space a firmware table describes but
+//! the platform never mapped. A read which faults is caught by its exception table entry (see
+//! [`crate::interrupts::exceptions::fixup`]), rather than oopsing the kernel.
+
+use crate::interrupts::exceptions::fixup::fixup_entry;
+use core::ptr::NonNull;
+
+/// Reads the `u16` at `ptr`, or `None` if the read faulted.
+///
+/// ### Safety
+///
+/// Reading `ptr` must have no side effects beyond those the caller expects of the device.
+#[cfg(target_arch = "x86_64")]
+pub unsafe fn read_u16(ptr: NonNull<u16>) -> Option<u16> {
+    let value: u16;
+    let read: u32;
+
+    core::arch::asm!(
+        "
+        xor {read:e}, {read:e}
+        2:
+        mov {value:x}, word ptr [{ptr}]
+        mov {read:e}, 1
+        3:
+        ",
+        fixup_entry!("2b", "3b"),
+        ptr = in(reg) ptr.as_ptr(),
+        value = out(reg) value,
+        read = out(reg) read,
+        options(nostack)
+    );
+
+    (read > 0).then_some(value)
+}
+
+/// Reads the `u16` at `ptr`, or `None` if the read faulted.
+///
+/// ### Safety
+///
+/// Reading `ptr` must have no side effects beyond those the caller expects of the device.
+#[cfg(target_arch = "riscv64")]
+pub unsafe fn read_u16(ptr: NonNull<u16>) -> Option<u16> {
+    let value: u16;
+    let read: u32;
+
+    core::arch::asm!(
+        "
+        li {read}, 0
+        2:
+        lhu {value}, 0({ptr})
+        li {read}, 1
+        3:
+        ",
+        fixup_entry!("2b", "3b"),
+        ptr = in(reg) ptr.as_ptr(),
+        value = out(reg) value,
+        read = out(reg) read,
+        options(nostack)
+    );
+
+    (read > 0).then_some(value)
+}
+
+/// Reads the `u32` at `ptr`, or `None` if the read faulted.
+///
+/// ### Safety
+///
+/// Reading `ptr` must have no side effects beyond those the caller expects of the device.
+#[cfg(target_arch = "x86_64")]
+pub unsafe fn read_u32(ptr: NonNull<u32>) -> Option<u32> {
+    let value: u32;
+    let read: u32;
+
+    core::arch::asm!(
+        "
+        xor {read:e}, {read:e}
+        2:
+        mov {value:e}, dword ptr [{ptr}]
+        mov {read:e}, 1
+        3:
+        ",
+        fixup_entry!("2b", "3b"),
+        ptr = in(reg) ptr.as_ptr(),
+        value = out(reg) value,
+        read = out(reg) read,
+        options(nostack)
+    );
+
+    (read > 0).then_some(value)
+}
+
+/// Reads the `u32` at `ptr`, or `None` if the read faulted.
+///
+/// ### Safety
+///
+/// Reading `ptr` must have no side effects beyond those the caller expects of the device.
+#[cfg(target_arch = "riscv64")]
+pub unsafe fn read_u32(ptr: NonNull<u32>) -> Option<u32> {
+    let value: u32;
+    let read: u32;
+
+    core::arch::asm!(
+        "
+        li {read}, 0
+        2:
+        lwu {value}, 0({ptr})
+        li {read}, 1
+        3:
+        ",
+        fixup_entry!("2b", "3b"),
+        ptr = in(reg) ptr.as_ptr(),
+        value = out(reg) value,
+        read = out(reg) read,
+        options(nostack)
+    );
+
+    (read > 0).then_some(value)
+}
@@ -0,0 +1,213 @@
+//! Tree of the physical I/O ranges (memory-mapped and port) claimed by drivers, so that no two drivers program the
+//! same registers.
+//!
+//! Claims nest: a [`Kind::Window`] (i.e. a PCI BAR) may be subdivided by claims which lie within it, while any other
+//! overlap is a conflict. The claims of each [`Space`] are published as `io/resources/<space>`.
+
+use crate::interrupts::InterruptCell;
+use alloc::{string::String, vec::Vec};
+use core::{
+    fmt::Write,
+    ops::Range,
+    sync::atomic::{AtomicU64, Ordering},
+};
+use spin::Mutex;
+
+crate::error_impl! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Error {
+        /// The range is empty, or overflows the address space.
+        InvalidRange => None,
+        /// The range overlaps the claim at `start..end`, without either fitting within the other as a window.
+        Conflict { start: usize, end: usize } => None,
+        /// No claim with the given ID exists.
+        NotClaimed => None,
+        /// No free range of the requested length lies within the bounds given.
+        Exhausted => None
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Space {
+    Memory,
+    Port,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// The claimant drives the range itself.
+    Exclusive,
+    /// The claimant decodes the range on behalf of the devices behind it, which claim within it.
+    Window,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ResourceId(u64);
+
+struct Resource {
+    id: ResourceId,
+    range: Range<usize>,
+    owner: String,
+    kind: Kind,
+    /// Claims within this one, ordered by their start.
+    children: Vec<Resource>,
+}
+
+static MEMORY: InterruptCell<Mutex<Vec<Resource>>> = InterruptCell::new(Mutex::new(Vec::new()));
+static PORTS: InterruptCell<Mutex<Vec<Resource>>> = InterruptCell::new(Mutex::new(Vec::new()));
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+const fn tree(space: Space) -> &'static InterruptCell<Mutex<Vec<Resource>>> {
+    match space {
+        Space::Memory => &MEMORY,
+        Space::Port => &PORTS,
+    }
+}
+
+const fn overlaps(a: &Range<usize>, b: &Range<usize>) -> bool {
+    a.start < b.end && b.start < a.end
+}
+
+const fn contains(outer: &Range<usize>, inner: &Range<usize>) -> bool {
+    outer.start <= inner.start && inner.end <= outer.end
+}
+
+fn insert(siblings: &mut Vec<Resource>, mut resource: Resource) -> Result<()> {
+    if let Some(window) =
+        siblings.iter_mut().find(|sibling| sibling.kind == Kind::Window && contains(&sibling.range, &resource.range))
+    {
+        return insert(&mut window.children, resource);
+    }
+
+    // A new window adopts the claims which lie within it (i.e. a device claimed before its bus was enumerated).
+    let is_adoptable = |sibling: &Resource| resource.kind == Kind::Window && contains(&resource.range, &sibling.range);
+    if let Some(conflict) =
+        siblings.iter().find(|sibling| overlaps(&sibling.range, &resource.range) && !is_adoptable(sibling))
+    {
+        warn!("I/O range {:#X?} conflicts with {:#X?} of {}", resource.range, conflict.range, conflict.owner);
+        return Err(Error::Conflict { start: conflict.range.start, end: conflict.range.end });
+    }
+
+    let (adopted, rest): (Vec<_>, Vec<_>) =
+        core::mem::take(siblings).into_iter().partition(|sibling| overlaps(&sibling.range, &resource.range));
+    resource.children = adopted;
+    *siblings = rest;
+
+    let index = siblings.partition_point(|sibling| sibling.range.start < resource.range.start);
+    siblings.insert(index, resource);
+
+    Ok(())
+}
+
+/// Removes the claim `id`, handing any claims within it to its parent.
+fn remove(siblings: &mut Vec<Resource>, id: ResourceId) -> bool {
+    if let Some(index) = siblings.iter().position(|sibling| sibling.id == id) {
+        let removed = siblings.remove(index);
+        for child in removed.children.into_iter().rev() {
+            siblings.insert(index, child);
+        }
+
+        true
+    } else {
+        siblings.iter_mut().any(|sibling| remove(&mut sibling.children, id))
+    }
+}
+
+/// Claims `start..(start + len)` of `space` for `owner`, returning its ID (for [`release`]).
+pub fn claim(space: Space, start: usize, len: usize, owner: &str, kind: Kind) -> Result<ResourceId> {
+    let end = start.checked_add(len).filter(|_| len > 0).ok_or(Error::InvalidRange)?;
+    let id = ResourceId(NEXT_ID.fetch_add(1, Ordering::Relaxed));
+
+    tree(space).with(|tree| {
+        insert(
+            &mut tree.lock(),
+            Resource { id, range: start..end, owner: String::from(owner), kind, children: Vec::new() },
+        )
+    })?;
+
+    Ok(id)
+}
+
+/// Claims the lowest `align`-aligned range of `len` bytes within `within` which overlaps no other claim (windows
+/// included), returning its start and ID.
+pub fn claim_free(
+    space: Space,
+    within: Range<usize>,
+    len: usize,
+    align: usize,
+    owner: &str,
+    kind: Kind,
+) -> Result<(usize, ResourceId)> {
+    if len == 0 || !align.is_power_of_two() {
+        return Err(Error::InvalidRange);
+    }
+
+    tree(space).with(|tree| {
+        let mut tree = tree.lock();
+
+        let mut start = within.start.checked_next_multiple_of(align).ok_or(Error::Exhausted)?;
+        loop {
+            let range = start..start.checked_add(len).filter(|end| *end <= within.end).ok_or(Error::Exhausted)?;
+
+            // Claims nest within their parents, so only the outermost can overlap.
+            match tree.iter().find(|claim| overlaps(&claim.range, &range)) {
+                Some(claim) => start = claim.range.end.checked_next_multiple_of(align).ok_or(Error::Exhausted)?,
+
+                None => {
+                    let id = ResourceId(NEXT_ID.fetch_add(1, Ordering::Relaxed));
+                    insert(&mut tree, Resource { id, range, owner: String::from(owner), kind, children: Vec::new() })?;
+
+                    return Ok((start, id));
+                }
+            }
+        }
+    })
+}
+
+/// Releases the claim `id`.
+pub fn release(id: ResourceId) -> Result<()> {
+    [Space::Memory, Space::Port]
+        .into_iter()
+        .any(|space| tree(space).with(|tree| remove(&mut tree.lock(), id)))
+        .then_some(())
+        .ok_or(Error::NotClaimed)
+}
+
+fn dump_into(out: &mut String, resources: &[Resource], depth: usize) {
+    for resource in resources {
+        writeln!(
+            out,
+            "{:indent$}{:#010x}-{:#010x} : {}",
+            "",
+            resource.range.start,
+            resource.range.end - 1,
+            resource.owner,
+            indent = depth * 2
+        )
+        .unwrap();
+
+        dump_into(out, &resource.children, depth + 1);
+    }
+}
+
+/// Renders the claims of `space`, one per line, with claims indented beneath the windows they lie within.
+pub fn dump(space: Space) -> String {
+    let mut out = String::new();
+    tree(space).with(|tree| dump_into(&mut out, &tree.lock(), 0));
+
+    out
+}
+
+/// Publishes the claims of each space to the attribute tree.
+pub fn init() {
+    crate::sysfs::set(
+        "io/resources",
+        "memory",
+        crate::sysfs::Value::Dynamic(alloc::boxed::Box::new(|| dump(Space::Memory))),
+    );
+    crate::sysfs::set(
+        "io/resources",
+        "port",
+        crate::sysfs::Value::Dynamic(alloc::boxed::Box::new(|| dump(Space::Port))),
+    );
+}
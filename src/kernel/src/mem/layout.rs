@@ -0,0 +1,124 @@
+//! Layout of the virtual address space, by slot of the root page table (the PML4, or PML5 with 5-level paging).
+//!
+//! | Slots      | Region                                      |
+//! |------------|---------------------------------------------|
+//! | `0..256`   | Userspace (the lower half)                  |
+//! | `256..508` | Higher-half direct map of physical memory   |
+//! | `508..510` | EFI runtime services window                 |
+//! | `510`      | Kernel mapping window (see [`super::vmap`]) |
+//! | `511`      | Kernel image                                |
+//!
+//! Regions are assigned whole slots, so no two share an intermediate table. A slot spans 512GiB with 4-level paging
+//! and 256TiB with 5-level, so a region's addresses depend on the paging depth, while its slots don't. Core-local
+//! state is allocated from the kernel heap (within the direct map), so it has no region of its own.
+
+use crate::mem::paging::TableDepth;
+use core::ops::{Range, RangeInclusive};
+use libsys::table_index_size;
+
+crate::error_impl! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Error {
+        /// The range `start..end` doesn't lie within the slots of `region`.
+        OutOfRegion { region: Region, start: usize, end: usize } => None
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Region {
+    Userspace,
+    Hhdm,
+    EfiRuntime,
+    Vmap,
+    Kernel,
+}
+
+pub const REGIONS: [Region; 5] = [Region::Userspace, Region::Hhdm, Region::EfiRuntime, Region::Vmap, Region::Kernel];
+
+impl Region {
+    /// Slots of the root page table assigned to the region.
+    pub const fn slots(self) -> Range<usize> {
+        let half = table_index_size() / 2;
+
+        match self {
+            Self::Userspace => 0..half,
+            Self::Hhdm => half..508,
+            Self::EfiRuntime => 508..510,
+            Self::Vmap => 510..511,
+            Self::Kernel => 511..table_index_size(),
+        }
+    }
+
+    /// Virtual addresses spanned by the region's slots, under the active paging depth.
+    pub fn addresses(self) -> RangeInclusive<usize> {
+        let slots = self.slots();
+
+        slot_base(slots.start)..=(slot_base(slots.end - 1) + (slot_size() - 1))
+    }
+
+    #[inline]
+    pub fn start(self) -> usize {
+        *self.addresses().start()
+    }
+
+    /// Fails with [`Error::OutOfRegion`] unless `range` lies within the region.
+    pub fn check(self, range: Range<usize>) -> Result<()> {
+        let addresses = self.addresses();
+
+        if range.is_empty() || (addresses.contains(&range.start) && addresses.contains(&(range.end - 1))) {
+            Ok(())
+        } else {
+            Err(Error::OutOfRegion { region: self, start: range.start, end: range.end })
+        }
+    }
+}
+
+// Regions must be non-empty, and never share a slot.
+const _: () = {
+    let mut index = 0;
+    while index < REGIONS.len() {
+        let slots = REGIONS[index].slots();
+        assert!(slots.start < slots.end && slots.end <= table_index_size());
+
+        let mut other_index = index + 1;
+        while other_index < REGIONS.len() {
+            let other = REGIONS[other_index].slots();
+            assert!(slots.end <= other.start || other.end <= slots.start);
+
+            other_index += 1;
+        }
+
+        index += 1;
+    }
+};
+
+/// Bytes of address space spanned by a slot of the root page table.
+pub fn slot_size() -> usize {
+    TableDepth::max_align() / table_index_size()
+}
+
+/// Base address of the root page table slot `slot`, sign-extended for the slots of the higher half.
+pub fn slot_base(slot: usize) -> usize {
+    let address = slot * slot_size();
+
+    if slot < (table_index_size() / 2) {
+        address
+    } else {
+        address | !(TableDepth::max_align() - 1)
+    }
+}
+
+/// Checks that the boot-time contents of the address space fit their regions: the direct map of physical memory up
+/// to `max_physical`, the kernel image at `kernel_image`, and the userspace address space.
+pub fn validate(max_physical: usize, kernel_image: Range<usize>) -> Result<()> {
+    let hhdm = super::HHDM.address().get();
+    Region::Hhdm.check(hhdm..hhdm.saturating_add(max_physical))?;
+    Region::Kernel.check(kernel_image)?;
+    Region::Userspace.check(0..crate::task::DEFAULT_USERSPACE_SIZE.get())?;
+
+    for region in REGIONS {
+        debug!("{:?}: slots {:?}, {:#X?}", region, region.slots(), region.addresses());
+    }
+
+    Ok(())
+}
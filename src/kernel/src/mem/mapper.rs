@@ -1,5 +1,5 @@
 use crate::mem::{
-    alloc::pmm,
+    alloc::{pmm, zero},
     paging,
     paging::{Error, Result, TableDepth},
     HHDM,
@@ -19,7 +19,7 @@ unsafe impl Send for Mapper {}
 impl Mapper {
     /// Attempts to construct a new page manager. Returns `None` if the `pmm::get()` could not provide a root frame.
     pub fn new(depth: TableDepth) -> Option<Self> {
-        let root_frame = pmm::get().next_frame().ok()?;
+        let root_frame = pmm::get().next_frame(pmm::Owner::PageTable).ok()?;
         trace!("New mapper root frame: {:X}", root_frame);
 
         // Safety: pmm::get() promises rented frames to be within the HHDM.
@@ -64,9 +64,12 @@ impl Mapper {
         lock_frame: bool,
         attributes: paging::TableEntryFlags,
     ) -> Result<()> {
+        // Only memory taken over from the bootloader is mapped with its frames locked (each of them, for a huge page).
         if lock_frame {
-            // If the acquisition of the frame fails, return an error.
-            pmm::get().lock_frame(frame).map_err(|err| match err {
+            let count = core::num::NonZeroUsize::new(depth.align() / libsys::page_size()).unwrap();
+
+            // If the acquisition of the frames fails, return an error.
+            pmm::get().lock_frames(frame, count, pmm::Owner::Reserved).map_err(|err| match err {
                 super::alloc::pmm::Error::OutOfBounds => Error::FrameBounds,
                 _ => Error::AllocError,
             })?;
@@ -108,7 +111,7 @@ impl Mapper {
             unsafe { entry.set_frame(Address::new_truncate(0)) };
 
             if free_frame {
-                pmm::get().free_frame(frame).unwrap();
+                zero::free_frame(frame).unwrap();
             }
 
             // Invalidate the page in the TLB.
@@ -117,8 +120,17 @@ impl Mapper {
         })
     }
 
+    /// Creates the table beneath the root table entry spanning `page`, if it doesn't exist.
+    pub fn create_root_table(&mut self, page: Address<Page>) -> Result<()> {
+        let depth = TableDepth::new(self.depth.get() - 2).unwrap();
+
+        // Descending past the root table entry creates its table, while the entry within it is left empty.
+        self.root_table_mut().with_entry_create(page, depth, |_| ())
+    }
+
+    /// Maps `page` to a newly allocated, zeroed frame.
     pub fn auto_map(&mut self, page: Address<Page>, flags: paging::TableEntryFlags) -> Result<()> {
-        match pmm::get().next_frame() {
+        match zero::next_frame() {
             Ok(frame) => self.map(page, TableDepth::min(), frame, false, flags),
             Err(err) => {
                 trace!("Auto alloc pmm::get() error: {:?}", err);
@@ -173,12 +185,70 @@ impl Mapper {
             self.root_frame,
             crate::arch::x86_64::registers::control::CR3Flags::empty(),
         );
+
+        crate::mem::tlb::set_loaded_root(self.root_frame);
     }
 
     pub const fn root_frame(&self) -> Address<Frame> {
         self.root_frame
     }
 
+    /// Frees the page tables of the userspace slots of the root table (see [`crate::mem::layout`]), and the root
+    /// table itself.
+    ///
+    /// ### Safety
+    ///
+    /// - The page table tree must not be active on any core, and mustn't be used after.
+    /// - No pages may remain mapped in the userspace slots, as their frames aren't freed.
+    /// - The remaining slots are shared with the kernel's page tables, so are left as they are.
+    pub unsafe fn free_user_tables(&mut self) {
+        /// Frees the table `frame` at `depth`, and the tables beneath it.
+        unsafe fn free_table(frame: Address<Frame>, depth: TableDepth) {
+            // Tables at the lowest depth hold the entries of pages, rather than tables.
+            if depth.get() > 1 {
+                // Safety: Frame holds a page table, which is valid within the HHDM.
+                let table = unsafe {
+                    core::slice::from_raw_parts(
+                        HHDM.offset(frame).unwrap().as_ptr().cast::<paging::PageTableEntry>(),
+                        libsys::table_index_size(),
+                    )
+                };
+
+                for entry in table {
+                    let attributes = entry.get_attributes();
+                    if attributes.contains(paging::TableEntryFlags::PRESENT)
+                        && !attributes.contains(paging::TableEntryFlags::HUGE)
+                    {
+                        // Safety: Caller ensures the tree is no longer used.
+                        unsafe { free_table(entry.get_frame(), TableDepth::new(depth.get() - 1).unwrap()) };
+                    }
+                }
+            }
+
+            if let Err(err) = pmm::get().free_frame(frame) {
+                warn!("Failed to free page table frame {:X?}: {:?}", frame, err);
+            }
+        }
+
+        let root = self.view_page_table();
+        let user_tables = crate::mem::layout::Region::Userspace
+            .slots()
+            .map(|slot| root[slot])
+            .filter(|entry| entry.get_attributes().contains(paging::TableEntryFlags::PRESENT))
+            .map(paging::PageTableEntry::get_frame)
+            .collect::<alloc::vec::Vec<_>>();
+
+        let child_depth = TableDepth::new(self.depth.get() - 1).unwrap();
+        for frame in user_tables {
+            // Safety: Caller ensures the tree is no longer used.
+            unsafe { free_table(frame, child_depth) };
+        }
+
+        if let Err(err) = pmm::get().free_frame(self.root_frame) {
+            warn!("Failed to free root page table frame {:X?}: {:?}", self.root_frame, err);
+        }
+    }
+
     pub fn view_page_table(&self) -> &[paging::PageTableEntry; libsys::table_index_size()] {
         // Safety: Root frame is guaranteed to be valid within the HHDM.
         let table_ptr = HHDM.offset(self.root_frame).unwrap().as_ptr().cast();
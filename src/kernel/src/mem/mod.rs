@@ -3,8 +3,12 @@ pub use hhdm::*;
 
 pub mod alloc;
 pub mod io;
+pub mod layout;
 pub mod mapper;
 pub mod paging;
+pub mod tlb;
+pub mod user;
+pub mod vmap;
 
 use self::mapper::Mapper;
 use crate::interrupts::InterruptCell;
@@ -36,6 +40,12 @@ impl<const SIZE: usize> core::ops::Deref for Stack<SIZE> {
     }
 }
 
+impl<const SIZE: usize> core::ops::DerefMut for Stack<SIZE> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
 pub fn with_kmapper<T>(func: impl FnOnce(&mut Mapper) -> T) -> T {
     static KERNEL_MAPPER: Lazy<InterruptCell<Mutex<Mapper>>> = Lazy::new(|| {
         debug!("Creating kernel-space address mapper.");
@@ -50,14 +60,12 @@ pub fn with_kmapper<T>(func: impl FnOnce(&mut Mapper) -> T) -> T {
 }
 
 pub fn copy_kernel_page_table() -> alloc::pmm::Result<Address<Frame>> {
-    let table_frame = alloc::pmm::get().next_frame()?;
+    let table_frame = alloc::pmm::get().next_frame(alloc::pmm::Owner::PageTable)?;
 
-    // Safety: Frame is provided by allocator, and so guaranteed to be within the HHDM, and is frame-sized.
+    let table_ptr = HHDM.frames(table_frame, core::num::NonZeroUsize::MIN).ok_or(alloc::pmm::Error::OutOfBounds)?;
+    // Safety: Frame was just allocated (so is owned by this function), and a table fits exactly within it.
     let new_table = unsafe {
-        core::slice::from_raw_parts_mut(
-            HHDM.offset(table_frame).unwrap().as_ptr().cast::<paging::PageTableEntry>(),
-            table_index_size(),
-        )
+        core::slice::from_raw_parts_mut(table_ptr.cast::<paging::PageTableEntry>().as_ptr(), table_index_size())
     };
     new_table.fill(paging::PageTableEntry::empty());
     with_kmapper(|kmapper| new_table.copy_from_slice(kmapper.view_page_table()));
@@ -106,47 +114,3 @@ impl PagingRegister {
 pub unsafe fn out_of_memory() -> ! {
     panic!("Kernel ran out of memory during initialization.")
 }
-
-// pub unsafe fn catch_read(ptr: NonNull<[u8]>) -> Result<Box<[u8]>, Exception> {
-//     let mem_range = ptr.as_uninit_slice().as_ptr_range();
-//     let aligned_start = libsys::align_down(mem_range.start.addr(), libsys::page_shift());
-//     let mem_end = mem_range.end.addr();
-
-//     let mut copied_mem = Box::new_uninit_slice(ptr.len());
-//     for (offset, page_addr) in (aligned_start..mem_end).enumerate().step_by(page_size()) {
-//         let ptr_addr = core::cmp::max(mem_range.start.addr(), page_addr);
-//         let ptr_len = core::cmp::min(mem_end.saturating_sub(ptr_addr), page_size());
-
-//         // Safety: Box slice and this iterator are bound by the ptr len.
-//         let to_ptr = unsafe { copied_mem.as_mut_ptr().add(offset) };
-//         // Safety: Copy is only invalid if the caller provided an invalid pointer.
-//         crate::local::do_catch(|| unsafe {
-//             core::ptr::copy_nonoverlapping(ptr_addr as *mut u8, to_ptr, ptr_len);
-//         })?;
-//     }
-
-//     Ok(copied_mem)
-// }
-
-// TODO TryString
-// pub unsafe fn catch_read_str(mut read_ptr: NonNull<u8>) -> Result<String, Exception> {
-//     let mut strlen = 0;
-//     'y: loop {
-//         let read_len = read_ptr.as_ptr().align_offset(page_size());
-//         read_ptr = NonNull::new(
-//             // Safety: This pointer isn't used without first being validated.
-//             unsafe { read_ptr.as_ptr().add(page_size() - read_len) },
-//         )
-//         .unwrap();
-
-//         for byte in catch_read(NonNull::slice_from_raw_parts(read_ptr, read_len))?.iter() {
-//             if byte.ne(&b'\0') {
-//                 strlen += 1;
-//             } else {
-//                 break 'y;
-//             }
-//         }
-//     }
-
-//     Ok(String::from_utf8_lossy(core::slice::from_raw_parts(read_ptr.as_ptr(), strlen)).into_owned())
-// }
@@ -136,6 +136,8 @@ bitflags::bitflags! {
         const HUGE = 1 << 7;
         const GLOBAL = 1 << 8;
         const DEMAND = 1 << 9;
+        /// The frame is shared with another address space, and is copied on the first write to it.
+        const COPY_ON_WRITE = 1 << 10;
         const NO_EXECUTE = 1 << 63;
 
         const RO = Self::PRESENT.bits() | Self::NO_EXECUTE.bits();
@@ -144,6 +146,8 @@ bitflags::bitflags! {
         const PTE = Self::PRESENT.bits() | Self::WRITABLE.bits() | Self::USER.bits();
 
         const MMIO = Self::RW.bits() | Self::UNCACHEABLE.bits();
+        /// Write-combining memory, as PAT entry 1 (selected by `WRITE_THROUGH`) is reprogrammed during CPU setup.
+        const WC = Self::RW.bits() | Self::WRITE_THROUGH.bits();
     }
 }
 
@@ -159,6 +163,8 @@ bitflags::bitflags! {
         const GLOBAL = 1 << 5;
         const ACCESSED = 1 << 6;
         const DIRTY = 1 << 7;
+        /// The frame is shared with another address space, and is copied on the first write to it.
+        const COPY_ON_WRITE = 1 << 8;
 
         const RO = Self::VALID.bits() | Self::READ.bits();
         const RW = Self::VALID.bits() | Self::READ.bits() | Self::WRITE.bits();
@@ -166,6 +172,7 @@ bitflags::bitflags! {
         const PTE = Self::VALID.bits() | Self::READ.bits() | Self::WRITE.bits();
 
         const MMIO = Self::RW.bits();
+        const WC = Self::RW.bits();
     }
 }
 
@@ -418,7 +425,9 @@ impl<'a> PageTable<'a, Mut> {
 
                 // Set the entry frame and set attributes to make a valid PTE.
                 *self.entry = PageTableEntry::new(
-                    crate::mem::alloc::pmm::get().next_frame().map_err(|_| Error::AllocError)?,
+                    crate::mem::alloc::pmm::get()
+                        .next_frame(crate::mem::alloc::pmm::Owner::PageTable)
+                        .map_err(|_| Error::AllocError)?,
                     flags,
                 );
 
@@ -0,0 +1,125 @@
+//! TLB shootdowns, which invalidate changed mappings from the TLBs of the other cores that may have cached them.
+//!
+//! Each core records the root table it has loaded, so a change to a userspace address space is only shot down on the
+//! cores which have it loaded. That includes idle cores, as the idle task runs in whichever address space was last
+//! loaded. Changes to the kernel's mappings are shot down on every core.
+//!
+//! One shootdown is in flight at a time. Its initiator publishes the pages, flags and interrupts each target, then
+//! spins until every target has invalidated them. A core waiting its turn to initiate services the shootdowns sent to
+//! it meanwhile, so two initiators never wait on each other with interrupts disabled.
+
+use crate::interrupts::{
+    ipi::{self, MAX_CORES},
+    Vector,
+};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use libsys::{Address, Frame, Page};
+use spin::Mutex;
+
+/// Pages past which the whole TLB is flushed, rather than each page being invalidated.
+const FULL_FLUSH_THRESHOLD: usize = 32;
+
+/// Value of [`ROOTS`] for cores which haven't recorded a root table.
+const NO_ROOT: usize = 0;
+
+/// Root table loaded by each core, by its index in the IPI registry.
+static ROOTS: [AtomicUsize; MAX_CORES] = [const { AtomicUsize::new(NO_ROOT) }; MAX_CORES];
+/// Whether each core has yet to service the shootdown in flight.
+static FLAGGED: [AtomicBool; MAX_CORES] = [const { AtomicBool::new(false) }; MAX_CORES];
+
+static INITIATOR: Mutex<()> = Mutex::new(());
+/// Index of the first page of the shootdown in flight.
+static START_INDEX: AtomicUsize = AtomicUsize::new(0);
+static PAGE_COUNT: AtomicUsize = AtomicUsize::new(0);
+/// Targets of the shootdown in flight which have yet to service it.
+static PENDING: AtomicUsize = AtomicUsize::new(0);
+
+/// Records that the local core has loaded the root table `root`.
+pub fn set_loaded_root(root: Address<Frame>) {
+    // Cores record their root once their local state is initialized, which is before any task is run.
+    if let Ok(index) = crate::cpu::state::get_core_index() {
+        ROOTS[index].store(root.get().get(), Ordering::Release);
+    }
+}
+
+/// Invalidates `page_count` pages from `start` in the local TLB.
+fn invalidate(start: Address<Page>, page_count: usize) {
+    if page_count > FULL_FLUSH_THRESHOLD {
+        // Safety: Reloading the loaded root table only flushes the TLB. The kernel's mappings aren't global, so are
+        //         flushed along with the rest.
+        unsafe { crate::mem::PagingRegister::write(&crate::mem::PagingRegister::read()) };
+    } else {
+        for page in (start.index()..(start.index() + page_count)).filter_map(Address::<Page>::from_index) {
+            #[cfg(target_arch = "x86_64")]
+            crate::arch::x86_64::instructions::tlb::invlpg(page);
+        }
+    }
+}
+
+/// Services the shootdown in flight, if the local core is one of its targets.
+pub fn service() {
+    let Ok(index) = crate::cpu::state::get_core_index() else { return };
+    if !FLAGGED[index].swap(false, Ordering::AcqRel) {
+        return;
+    }
+
+    let start = Address::<Page>::from_index(START_INDEX.load(Ordering::Acquire)).unwrap();
+    invalidate(start, PAGE_COUNT.load(Ordering::Acquire));
+
+    PENDING.fetch_sub(1, Ordering::AcqRel);
+}
+
+/// Invalidates `page_count` pages from `start` in the TLBs of the other cores which have the root table `root`
+/// loaded, or of every other core if `root` is `None` (i.e. for the kernel's mappings).
+///
+/// #### Remark
+///
+/// The local TLB isn't invalidated, as the mapper invalidates each page as it changes it. As targets acknowledge from
+/// their interrupt handler, this mustn't be called while holding a lock which another core may spin on with
+/// interrupts disabled, unless that core services shootdowns as it spins (as it does for an address space's lock,
+/// see [`crate::task::Process::address_space`]).
+pub fn shootdown(root: Option<Address<Frame>>, start: Address<Page>, page_count: usize) {
+    // Until the local state is initialized, no other core is running.
+    let Ok(local_index) = crate::cpu::state::get_core_index() else { return };
+
+    // The changed entries must be visible before the cores which may hold them are found.
+    core::sync::atomic::fence(Ordering::SeqCst);
+
+    let is_target = |index: usize| {
+        index != local_index && root.map_or(true, |root| ROOTS[index].load(Ordering::Acquire) == root.get().get())
+    };
+    if page_count == 0 || !ipi::cores().any(is_target) {
+        return;
+    }
+
+    let _guard = loop {
+        if let Some(guard) = INITIATOR.try_lock() {
+            break guard;
+        }
+
+        service();
+        core::hint::spin_loop();
+    };
+
+    START_INDEX.store(start.index(), Ordering::Release);
+    PAGE_COUNT.store(page_count, Ordering::Release);
+
+    for index in ipi::cores().filter(|&index| is_target(index)) {
+        PENDING.fetch_add(1, Ordering::AcqRel);
+        FLAGGED[index].store(true, Ordering::Release);
+
+        // Safety: The shootdown vector is handled by every core, by servicing the shootdown.
+        if let Err(err) = unsafe { ipi::send(index, Vector::TlbShootdown) } {
+            warn!("Failed to send TLB shootdown to core {}: {:?}", index, err);
+
+            // The target may have serviced the shootdown regardless (i.e. from its own wait to initiate).
+            if FLAGGED[index].swap(false, Ordering::AcqRel) {
+                PENDING.fetch_sub(1, Ordering::AcqRel);
+            }
+        }
+    }
+
+    while PENDING.load(Ordering::Acquire) > 0 {
+        core::hint::spin_loop();
+    }
+}
@@ -0,0 +1,176 @@
+//! Copies between the kernel and the active task's memory.
+//!
+//! Each copy is a single `rep movsb` with an exception table entry (see
+//! [`crate::interrupts::exceptions::fixup`]). Memory the task hasn't touched is demand-mapped by the page fault
+//! handler as the copy reaches it, and memory it can't map ends the copy early, rather than oopsing the kernel.
+//!
+//! Supervisor accesses to user pages fault while they're prevented (by `CR4.SMAP` on x86_64, or a clear `sstatus.SUM`
+//! on RISC-V), so each copy permits them for only as long as it runs.
+//!
+//! #### Remark
+//!
+//! On RISC-V, traps can't nest (see [`crate::arch::rv64::trap`]), so a copy made from a system call mustn't fault.
+//! The task's memory is demand-mapped up front instead, and the copy ends at the first page which couldn't be.
+
+use crate::{interrupts::exceptions::fixup::fixup_entry, mem::layout::Region};
+use alloc::vec::Vec;
+
+crate::error_impl! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Error {
+        /// The range extends beyond userspace.
+        OutOfRange { address: usize, len: usize } => None,
+        /// The task's memory faulted after `copied` bytes.
+        Fault { copied: usize } => None,
+        /// No room could be allocated to copy into.
+        OutOfMemory => None
+    }
+}
+
+impl From<Error> for libsys::syscall::Error {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::OutOfRange { .. } => Self::InvalidPtr,
+            Error::Fault { .. } => Self::UnmappedMemory,
+            Error::OutOfMemory => Self::OutOfMemory,
+        }
+    }
+}
+
+/// Checks that `address..(address + len)` lies within userspace.
+pub fn check(address: usize, len: usize) -> Result<()> {
+    address
+        .checked_add(len)
+        .and_then(|end| Region::Userspace.check(address..end).ok())
+        .ok_or(Error::OutOfRange { address, len })
+}
+
+/// Copies `len` bytes from `src` to `dst`, returning how many were left uncopied when an access faulted.
+///
+/// ### Safety
+///
+/// Both ranges must be valid to access, save for memory in userspace, which may fault.
+#[cfg(target_arch = "x86_64")]
+unsafe fn copy(dst: *mut u8, src: *const u8, len: usize) -> usize {
+    use crate::arch::x86_64::registers::control::{CR4Flags, CR4};
+
+    let remaining: usize;
+    let smap = CR4::read().contains(CR4Flags::SMAP);
+
+    // `RFLAGS.AC` permits the accesses to user pages. It's set without `nomem`, so the copy isn't moved outside it.
+    if smap {
+        core::arch::asm!("stac", options(nostack));
+    }
+
+    // A fault leaves `rcx` as the bytes yet to be copied.
+    core::arch::asm!(
+        "
+        2:
+        rep movsb
+        3:
+        ",
+        fixup_entry!("2b", "3b"),
+        inout("rcx") len => remaining,
+        inout("rdi") dst => _,
+        inout("rsi") src => _,
+        options(nostack, preserves_flags)
+    );
+
+    if smap {
+        core::arch::asm!("clac", options(nostack));
+    }
+
+    remaining
+}
+
+/// Copies `len` bytes from `src` to `dst`, returning how many were left uncopied as the task's memory wasn't mapped.
+///
+/// ### Safety
+///
+/// Both ranges must be valid to access, save for memory in userspace, which must be the active task's.
+#[cfg(target_arch = "riscv64")]
+unsafe fn copy(dst: *mut u8, src: *const u8, len: usize) -> usize {
+    use crate::arch::rv64::registers::SSTATUS;
+
+    let user = if Region::Userspace.addresses().contains(&dst.addr()) { dst.addr() } else { src.addr() };
+    let mapped = mapped_len(user, len);
+
+    // `sstatus.SUM` permits the accesses to user pages. It's set without `nomem`, so the copy isn't moved outside it.
+    core::arch::asm!("csrs sstatus, {}", in(reg) SSTATUS::SUM.bits(), options(nostack, preserves_flags));
+    core::ptr::copy_nonoverlapping(src, dst, mapped);
+    core::arch::asm!("csrc sstatus, {}", in(reg) SSTATUS::SUM.bits(), options(nostack, preserves_flags));
+
+    len - mapped
+}
+
+/// Demand-maps the active task's memory at `address..(address + len)`, returning how much of it, from `address`, is
+/// mapped.
+#[cfg(target_arch = "riscv64")]
+fn mapped_len(address: usize, len: usize) -> usize {
+    use crate::task::Error as TaskError;
+    use libsys::{page_mask, page_size, Address};
+
+    let end = address + len;
+
+    crate::cpu::state::with_scheduler(|scheduler| {
+        let Some(task) = scheduler.task_mut() else { return 0 };
+
+        let mut mapped_end = address;
+        for page in ((address & !page_mask())..end).step_by(page_size()) {
+            match task.process().demand_map(Address::new_truncate(page)) {
+                Ok(()) | Err(TaskError::AlreadyMapped) => mapped_end = (page + page_size()).min(end),
+                Err(_) => break,
+            }
+        }
+
+        mapped_end - address
+    })
+}
+
+/// Fills `dst` from the task's memory at `src`.
+pub fn copy_from(dst: &mut [u8], src: usize) -> Result<()> {
+    check(src, dst.len())?;
+
+    // Safety: Source lies within userspace, and the destination is a kernel slice.
+    match unsafe { copy(dst.as_mut_ptr(), src as *const u8, dst.len()) } {
+        0 => Ok(()),
+        remaining => Err(Error::Fault { copied: dst.len() - remaining }),
+    }
+}
+
+/// Copies `values` to the task's memory at `dst`.
+pub fn write<T: Copy>(dst: usize, values: &[T]) -> Result<()> {
+    let len = core::mem::size_of_val(values);
+    check(dst, len)?;
+
+    // Safety: Destination lies within userspace, and the source is a kernel slice (copied as raw bytes, padding and
+    //         all).
+    match unsafe { copy(dst as *mut u8, values.as_ptr().cast::<u8>(), len) } {
+        0 => Ok(()),
+        remaining => Err(Error::Fault { copied: len - remaining }),
+    }
+}
+
+/// Copies `count` values from the task's memory at `src`.
+///
+/// ### Safety
+///
+/// Every bit pattern must be a valid `T`, as the task's memory may hold any.
+pub unsafe fn read<T: Copy>(src: usize, count: usize) -> Result<Vec<T>> {
+    let len =
+        count.checked_mul(core::mem::size_of::<T>()).ok_or(Error::OutOfRange { address: src, len: usize::MAX })?;
+    check(src, len)?;
+
+    // Count is the task's to choose, so failing to allocate for it is an error rather than an abort.
+    let mut values = Vec::<T>::new();
+    values.try_reserve_exact(count).map_err(|_| Error::OutOfMemory)?;
+    match copy(values.as_mut_ptr().cast::<u8>(), src as *const u8, len) {
+        0 => {
+            // Safety: Every value was copied, and the caller guarantees any bytes are a valid `T`.
+            values.set_len(count);
+            Ok(values)
+        }
+
+        remaining => Err(Error::Fault { copied: len - remaining }),
+    }
+}
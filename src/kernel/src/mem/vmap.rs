@@ -0,0 +1,165 @@
+//! Kernel virtual address window ([`Region::Vmap`]) for mapping physical ranges which may lie outside of the
+//! higher-half direct map, such as the BARs of devices decoding above the top of RAM.
+//!
+//! The window's tables beneath the root table are created by [`init`], before any address space copies the kernel's
+//! root table, so mappings made later are visible from every address space.
+
+use crate::{
+    interrupts::InterruptCell,
+    mem::{
+        layout::{slot_base, slot_size, Region},
+        paging::{self, TableDepth, TableEntryFlags},
+    },
+};
+use alloc::collections::BTreeMap;
+use core::ptr::NonNull;
+use libsys::{page_size, Address};
+use spin::Mutex;
+
+crate::error_impl! {
+    #[derive(Debug)]
+    pub enum Error {
+        /// The range is empty, or overflows the physical address space.
+        InvalidRange => None,
+        /// No free range of the window is large enough for the mapping.
+        Exhausted => None,
+        Paging { err: paging::Error } => Some(err)
+    }
+}
+
+/// Free ranges of the window, keyed on their start address and holding their length.
+static FREE: InterruptCell<Mutex<BTreeMap<usize, usize>>> = InterruptCell::new(Mutex::new(BTreeMap::new()));
+
+/// A physical range mapped into the window, unmapped on drop.
+#[derive(Debug)]
+pub struct Mapping {
+    /// First page of the mapping within the window.
+    base: usize,
+    /// Bytes of the window taken by the mapping (a whole number of pages).
+    span: usize,
+    ptr: NonNull<u8>,
+    len: usize,
+}
+
+// Safety: Type refers only to kernel memory, which is mapped identically on every core.
+unsafe impl Send for Mapping {}
+// Safety: See above.
+unsafe impl Sync for Mapping {}
+
+impl Mapping {
+    /// Pointer to the start of the physical range (which needn't be page-aligned).
+    #[inline]
+    pub const fn as_ptr(&self) -> NonNull<u8> {
+        self.ptr
+    }
+
+    #[inline]
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        crate::mem::with_kmapper(|kmapper| {
+            for page in (self.base..(self.base + self.span)).step_by(page_size()) {
+                // Safety: The page belongs to this mapping alone, and its frame is device memory (so isn't freed).
+                if let Err(err) = unsafe { kmapper.unmap(Address::new(page).unwrap(), None, false) } {
+                    warn!("Failed to unmap window page {:#X}: {:?}", page, err);
+                }
+            }
+        });
+        // Shot down once the kernel's mapper is unlocked, as other cores may be spinning on it.
+        crate::mem::tlb::shootdown(None, Address::new(self.base).unwrap(), self.span / page_size());
+
+        release(self.base, self.span);
+    }
+}
+
+/// Creates the tables beneath the window's root table slots, and marks the whole window as free.
+pub fn init() -> Result<()> {
+    crate::mem::with_kmapper(|kmapper| {
+        Region::Vmap
+            .slots()
+            .try_for_each(|slot| kmapper.create_root_table(Address::new(slot_base(slot)).unwrap()))
+            .map_err(|err| Error::Paging { err })
+    })?;
+
+    FREE.with(|free| free.lock().insert(Region::Vmap.start(), Region::Vmap.slots().len() * slot_size()));
+
+    Ok(())
+}
+
+/// Takes the first free range of at least `span` bytes.
+fn reserve(span: usize) -> Result<usize> {
+    FREE.with(|free| {
+        let mut free = free.lock();
+
+        let (&base, &len) = free.iter().find(|(_, len)| **len >= span).ok_or(Error::Exhausted)?;
+        free.remove(&base);
+        if len > span {
+            free.insert(base + span, len - span);
+        }
+
+        Ok(base)
+    })
+}
+
+/// Returns `base..(base + span)` to the free ranges, merging it with its neighbours.
+fn release(mut base: usize, mut span: usize) {
+    FREE.with(|free| {
+        let mut free = free.lock();
+
+        let prev = free.range(..base).next_back().map(|(&prev_base, &prev_len)| (prev_base, prev_len));
+        if let Some((prev_base, prev_len)) = prev.filter(|(prev_base, prev_len)| (prev_base + prev_len) == base) {
+            free.remove(&prev_base);
+            base = prev_base;
+            span += prev_len;
+        }
+
+        if let Some(next_len) = free.remove(&(base + span)) {
+            span += next_len;
+        }
+
+        free.insert(base, span);
+    });
+}
+
+/// Maps the physical range `address..(address + len)` into the window with `attributes`.
+pub fn map(address: usize, len: usize, attributes: TableEntryFlags) -> Result<Mapping> {
+    let end = address.checked_add(len).filter(|_| len > 0).ok_or(Error::InvalidRange)?;
+    let frame_start = libsys::align_down(address, libsys::page_shift());
+    let frame_end = libsys::align_up(end, libsys::page_shift());
+    if Address::<libsys::Frame>::new(frame_end - page_size()).is_none() {
+        return Err(Error::InvalidRange);
+    }
+
+    let span = frame_end - frame_start;
+    let base = reserve(span)?;
+
+    let result = crate::mem::with_kmapper(|kmapper| {
+        (0..span).step_by(page_size()).try_for_each(|offset| {
+            kmapper.map(
+                Address::new(base + offset).unwrap(),
+                TableDepth::min(),
+                Address::new(frame_start + offset).unwrap(),
+                false,
+                attributes,
+            )
+        })
+    });
+
+    // Dropping the partial mapping unmaps what was mapped, and releases the range.
+    let mapping = Mapping {
+        base,
+        span,
+        ptr: NonNull::new(Address::<libsys::Page>::new(base).unwrap().as_ptr().wrapping_add(address - frame_start))
+            .unwrap(),
+        len,
+    };
+    result.map_err(|err| Error::Paging { err })?;
+
+    trace!("Mapped {:#X}..{:#X} into the window at {:#X}", address, end, base);
+
+    Ok(mapping)
+}
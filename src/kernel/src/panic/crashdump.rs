@@ -0,0 +1,269 @@
+//! Structured crash image, streamed over the serial port on kernel panic.
+//!
+//! The image is plain ASCII, framed by `BEGIN`/`END` markers, so that it can be
+//! picked out of a serial capture and parsed after the fact. Every section is
+//! written on a best-effort basis: any lock that cannot be acquired immediately is
+//! reported as such, rather than risking a deadlock in the panic path.
+//!
+//! Before the image is written, the other cores are halted by an IPI, each saving
+//! the state it was interrupted in. A core which has interrupts disabled (i.e. one
+//! spinning on a lock) can't be halted, and is reported as unresponsive.
+
+use crate::{
+    interrupts::{ipi, Vector},
+    task::{Registers, State},
+};
+use core::fmt::Write;
+
+/// Version of the crash image format. Bump whenever section layout changes.
+pub const FORMAT_VERSION: u32 = 4;
+
+/// Most bytes of the panicking core's stack to include in the image.
+const STACK_DUMP_LEN: usize = 0x200;
+/// Number of bytes output per line of a memory dump.
+const BYTES_PER_LINE: usize = 16;
+/// Polls of the other cores' saved state before those yet to save it are given up on, as there's no timer to rely on.
+const HALT_POLLS: usize = 10_000_000;
+
+/// State each other core was interrupted in as it halted, by core index.
+static HALTED: [spin::Once<(State, Registers)>; ipi::MAX_CORES] = [const { spin::Once::new() }; ipi::MAX_CORES];
+
+/// Indicates whether a crash image should be written on panic.
+pub fn is_enabled() -> bool {
+    crate::init::try_get().is_some_and(|params| params.crashdump)
+}
+
+/// Streams a crash image for the current core over the serial port, dumping the stack from `stack_ptr` (the
+/// panicking stack, rather than the emergency stack the panic path runs on).
+///
+/// #### Remark
+///
+/// This function should *never* panic.
+pub fn write(info: &core::panic::PanicInfo, stack_ptr: usize) {
+    let Some(mut uart) = super::serial_writer() else { return };
+
+    halt_other_cores();
+
+    // Errors are ignored, as there's no one left to report them to.
+    let _ = write_image(&mut uart, info, stack_ptr);
+}
+
+/// Saves the state the local core was interrupted in, as it halts for another core's panic.
+pub fn save_halted(state: &State, regs: &Registers) {
+    if let Ok(index) = crate::cpu::state::get_core_index() {
+        HALTED[index].call_once(|| (*state, *regs));
+    }
+}
+
+/// Halts every other core, waiting (for a bounded number of polls) for each to save its state.
+fn halt_other_cores() {
+    let Ok(local) = crate::cpu::state::get_core_index() else { return };
+
+    for index in ipi::cores().filter(|&index| index != local) {
+        // Safety: The vector is handled by `handle_trap`, which halts the core.
+        let _ = unsafe { ipi::send(index, Vector::PanicHalt) };
+    }
+
+    for _ in 0..HALT_POLLS {
+        if ipi::cores().filter(|&index| index != local).all(|index| HALTED[index].is_completed()) {
+            break;
+        }
+
+        core::hint::spin_loop();
+    }
+}
+
+fn write_image(w: &mut impl Write, info: &core::panic::PanicInfo, stack_ptr: usize) -> core::fmt::Result {
+    writeln!(w, "==== BEGIN CRASH DUMP v{FORMAT_VERSION} ====")?;
+
+    write_summary(w, info)?;
+    write_registers(w, stack_ptr)?;
+    write_cores(w)?;
+    write_memory(w)?;
+    write_tasks(w)?;
+    write_stack(w, stack_ptr)?;
+    write_log(w)?;
+
+    writeln!(w, "==== END CRASH DUMP ====")
+}
+
+fn write_summary(w: &mut impl Write, info: &core::panic::PanicInfo) -> core::fmt::Result {
+    writeln!(w, "[summary]")?;
+
+    match crate::cpu::state::get_core_id() {
+        Ok(core_id) => writeln!(w, "core={core_id}")?,
+        Err(_) => writeln!(w, "core=unknown")?,
+    }
+
+    match info.location() {
+        Some(location) => writeln!(w, "location={location}")?,
+        None => writeln!(w, "location=unknown")?,
+    }
+
+    writeln!(w, "message={}", info.message().unwrap_or(&format_args!("no panic message")))
+}
+
+fn write_registers(w: &mut impl Write, stack_ptr: usize) -> core::fmt::Result {
+    writeln!(w, "[registers]")?;
+
+    #[cfg(target_arch = "x86_64")]
+    {
+        use crate::arch::x86_64::registers::{control, stack, RFlags};
+
+        writeln!(w, "rsp={stack_ptr:#018X}")?;
+        writeln!(w, "rbp={:#018X}", stack::RBP::read())?;
+        writeln!(w, "rflags={:#018X}", RFlags::read().bits())?;
+        writeln!(w, "cr0={:#018X}", control::CR0::read().bits())?;
+        writeln!(w, "cr2={:#018X}", control::CR2::read().get())?;
+        let (cr3_frame, cr3_flags) = control::CR3::read();
+        writeln!(w, "cr3={:#018X}", cr3_frame.get().get() | cr3_flags.bits())?;
+        writeln!(w, "cr4={:#018X}", control::CR4::read().bits())?;
+    }
+
+    Ok(())
+}
+
+/// Writes the state each other core was halted in.
+fn write_cores(w: &mut impl Write) -> core::fmt::Result {
+    writeln!(w, "[cores]")?;
+
+    let local = crate::cpu::state::get_core_index().ok();
+    for index in ipi::cores().filter(|&index| Some(index) != local) {
+        let Some((state, regs)) = HALTED[index].get() else {
+            writeln!(w, "core index={index} state=unresponsive")?;
+            continue;
+        };
+
+        #[cfg(target_arch = "x86_64")]
+        let frame_ptr = regs.rbp;
+        #[cfg(target_arch = "riscv64")]
+        let frame_ptr = regs.s0;
+
+        writeln!(w, "core index={index} ip={:#018X} sp={:#018X} fp={frame_ptr:#018X}", state.ip.get(), state.sp.get())?;
+    }
+
+    Ok(())
+}
+
+fn write_memory(w: &mut impl Write) -> core::fmt::Result {
+    writeln!(w, "[memory]")?;
+
+    writeln!(w, "hhdm={:#018X}", crate::mem::HHDM.address().get())?;
+    for region in crate::mem::layout::REGIONS {
+        let addresses = region.addresses();
+        writeln!(w, "region={region:?} start={:#018X} end={:#018X}", addresses.start(), addresses.end())?;
+    }
+
+    match crate::mem::alloc::pmm::try_get().map(|pmm| pmm.try_usage()) {
+        Some(Some(usage)) => writeln!(w, "frames {usage}")?,
+        Some(None) => writeln!(w, "frames=locked")?,
+        None => writeln!(w, "frames=uninitialized")?,
+    }
+
+    let mut result = Ok(());
+    let listed = crate::mem::alloc::mempool::try_for_each_stats(|stats| {
+        result = result.and_then(|()| {
+            writeln!(
+                w,
+                "pool name={} capacity={} available={} exhausted={}",
+                stats.name, stats.capacity, stats.available, stats.exhausted
+            )
+        });
+    });
+    result?;
+    if !listed {
+        writeln!(w, "pools=locked")?;
+    }
+
+    Ok(())
+}
+
+fn write_tasks(w: &mut impl Write) -> core::fmt::Result {
+    writeln!(w, "[tasks]")?;
+
+    let Some(processes) = crate::task::PROCESSES.try_lock() else {
+        return writeln!(w, "queue=locked");
+    };
+
+    writeln!(w, "queue={}", processes.len())?;
+    for task in processes.iter() {
+        writeln!(
+            w,
+            "task id={} priority={:?} load_offset={:#X} ip={:#018X} sp={:#018X}",
+            task.id(),
+            task.priority(),
+            task.process().load_offset(),
+            task.context().0.ip.get(),
+            task.context().0.sp.get()
+        )?;
+        match task.process().try_address_space() {
+            Some(address_space) => write!(w, "{}", address_space.maps())?,
+            None => writeln!(w, "maps=locked")?,
+        }
+    }
+
+    Ok(())
+}
+
+fn write_stack(w: &mut impl Write, stack_ptr: usize) -> core::fmt::Result {
+    writeln!(w, "[stack]")?;
+
+    #[cfg(target_arch = "x86_64")]
+    {
+        // Where the stack's bounds aren't known (i.e. the boot stack), the dump ends with the page the stack pointer
+        // lies in, which is mapped, as it was in use.
+        let top = match crate::interrupts::stacks::bounds(stack_ptr) {
+            Some(bounds) => {
+                writeln!(w, "bounds={:#018X}..{:#018X}", bounds.start, bounds.end)?;
+                bounds.end
+            }
+
+            None => {
+                writeln!(w, "bounds=unknown")?;
+                (stack_ptr & !(libsys::page_size() - 1)) + libsys::page_size()
+            }
+        };
+
+        // Safety: The stack pointer points into the panicking stack, and the bytes above it (up to the stack's top)
+        //         are the frames of the panic handler and its callers.
+        let stack = unsafe {
+            core::slice::from_raw_parts(stack_ptr as *const u8, top.saturating_sub(stack_ptr).min(STACK_DUMP_LEN))
+        };
+        write_hex_dump(w, stack_ptr, stack)?;
+    }
+
+    Ok(())
+}
+
+/// Drains the log rings (whatever they still hold of the records before the panic).
+fn write_log(w: &mut impl Write) -> core::fmt::Result {
+    writeln!(w, "[log]")?;
+
+    let mut reader = crate::logging::ring::Reader::new();
+    while let Some(record) = reader.read() {
+        writeln!(
+            w,
+            "{}.{:06} core={} level={} module={} {}",
+            record.uptime.as_secs(),
+            record.uptime.subsec_micros(),
+            record.core,
+            record.level,
+            record.module(),
+            record.message()
+        )?;
+    }
+
+    writeln!(w, "dropped={}", reader.dropped)
+}
+
+fn write_hex_dump(w: &mut impl Write, base: usize, bytes: &[u8]) -> core::fmt::Result {
+    for (index, line) in bytes.chunks(BYTES_PER_LINE).enumerate() {
+        write!(w, "{:#018X}:", base + (index * BYTES_PER_LINE))?;
+        for byte in line {
+            write!(w, " {byte:02X}")?;
+        }
+        writeln!(w)?;
+    }
+
+    Ok(())
+}
@@ -1,7 +1,12 @@
+//! The panic path, which runs on the core's emergency stack and never allocates, so it can report a panic from
+//! within the allocator or on an overflowed stack.
+
+mod crashdump;
 pub mod symbols;
 
-use core::ptr::NonNull;
+use core::{fmt::Write, ptr::NonNull};
 use libsys::{Address, Virtual};
+use uart::{Data, Uart, UartWriter};
 
 #[repr(C)]
 #[derive(Debug)]
@@ -35,29 +40,91 @@ impl Iterator for StackTracer {
     }
 }
 
+/// Writes directly to the serial port, as the logger's lock may be held by whatever caused the panic.
+pub(crate) fn serial_writer() -> Option<UartWriter> {
+    UartWriter::new(
+        #[cfg(target_arch = "x86_64")]
+        // Safety: The core is about to halt, so at worst, concurrent use of the port interleaves output.
+        unsafe {
+            Uart::<Data>::new(uart::COM1)
+        },
+    )
+}
+
 /// #### Remark
 ///
 /// This function should *never* panic or abort.
 #[panic_handler]
 fn panic(info: &core::panic::PanicInfo) -> ! {
-    error!(
-        "KERNEL PANIC (at {}): {}",
-        info.location().unwrap_or(core::panic::Location::caller()),
-        info.message().unwrap_or(&format_args!("no panic message"))
-    );
+    let Some(stack_top) = crate::cpu::state::take_emergency_stack() else {
+        // The panic path itself panicked, so report as little as possible to avoid doing so again.
+        if let Some(mut w) = serial_writer() {
+            let _ = w.write_str("KERNEL PANIC: panicked while panicking\n");
+        }
+
+        // Safety: It's dead, Jim.
+        unsafe { crate::interrupts::halt_and_catch_fire() }
+    };
+
+    #[cfg(target_arch = "x86_64")]
+    // Safety: The emergency stack was just taken, so nothing else uses it, and it's never switched away from, as
+    //         the panic path doesn't return. The frame pointer is left as-is, so stack traces continue through the
+    //         panicking stack.
+    unsafe {
+        core::arch::asm!(
+            "mov rsi, rsp",
+            "mov rsp, rdx",
+            "call {entry}",
+            entry = sym panic_on_emergency_stack,
+            in("rdi") info,
+            in("rdx") stack_top.as_ptr(),
+            options(noreturn)
+        )
+    }
 
-    stack_trace();
+    #[cfg(not(target_arch = "x86_64"))]
+    panic_on_emergency_stack(info, 0)
+}
+
+/// Reports the panic, and halts the core.
+///
+/// `stack_ptr` is the stack pointer as the panic handler switched away from it.
+///
+/// #### Remark
+///
+/// This is the C ABI (rather than `sysv64`) so it's declarable on every architecture; on the kernel's x86_64 target,
+/// it's the System V ABI the handler's call relies on.
+extern "C" fn panic_on_emergency_stack(info: &core::panic::PanicInfo, stack_ptr: usize) -> ! {
+    if let Some(mut w) = serial_writer() {
+        // Errors are ignored, as there's no one left to report them to.
+        let _ = writeln!(
+            w,
+            "KERNEL PANIC (at {}): {}",
+            info.location().unwrap_or(core::panic::Location::caller()),
+            info.message().unwrap_or(&format_args!("no panic message"))
+        );
+
+        let _ = stack_trace(&mut w);
+    }
+
+    if crashdump::is_enabled() {
+        crashdump::write(info, stack_ptr);
+    }
 
     // Safety: It's dead, Jim.
     unsafe { crate::interrupts::halt_and_catch_fire() }
 }
 
-fn stack_trace() {
-    fn print_stack_trace_entry<D: core::fmt::Display>(entry_num: usize, fn_address: Address<Virtual>, symbol_name: D) {
-        error!("{entry_num:.<4}0x{:X} {symbol_name:#}", fn_address.get());
-    }
+/// Halts the core for another core's panic, saving the state it was interrupted in for the crash image.
+pub fn halt_for_panic(state: &crate::task::State, regs: &crate::task::Registers) -> ! {
+    crashdump::save_halted(state, regs);
+
+    // Safety: It's dead, Jim.
+    unsafe { crate::interrupts::halt_and_catch_fire() }
+}
 
-    error!("----------STACK-TRACE---------");
+fn stack_trace(w: &mut impl Write) -> core::fmt::Result {
+    writeln!(w, "----------STACK-TRACE---------")?;
 
     let frame_ptr = {
         #[cfg(target_arch = "x86_64")]
@@ -69,18 +136,18 @@ fn stack_trace() {
     // Safety: Frame pointer is pulled directly from the frame pointer register.
     let stack_tracer = unsafe { StackTracer::new(NonNull::new(frame_ptr.cast_mut()).unwrap()) };
     for (depth, trace_address) in stack_tracer.enumerate() {
-        const SYMBOL_TYPE_FUNCTION: u8 = 2;
+        let fn_address = trace_address.get();
 
         if let Some((_, Some(symbol_name))) = symbols::get(trace_address) {
             if let Ok(demangled) = rustc_demangle::try_demangle(symbol_name) {
-                print_stack_trace_entry(depth, trace_address, demangled);
+                writeln!(w, "{depth:.<4}0x{fn_address:X} {demangled:#}")?;
             } else {
-                print_stack_trace_entry(depth, trace_address, symbol_name);
+                writeln!(w, "{depth:.<4}0x{fn_address:X} {symbol_name}")?;
             }
         } else {
-            print_stack_trace_entry(depth, trace_address, "!!! no function found !!!");
+            writeln!(w, "{depth:.<4}0x{fn_address:X} !!! no function found !!!")?;
         }
     }
 
-    error!("----------STACK-TRACE----------");
+    writeln!(w, "----------STACK-TRACE----------")
 }
@@ -0,0 +1,140 @@
+//! Description of the hardware the firmware reports, common to each architecture: it's read from the ACPI tables on
+//! x86_64, and from the devicetree on RISC-V (see [`crate::fdt`]).
+//!
+//! Devices which aren't on an enumerable bus (i.e. a RISC-V board's UART and interrupt controllers) are found here by
+//! their `compatible` strings, whichever the source.
+
+use alloc::{string::String, vec::Vec};
+use core::ops::Range;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cpu {
+    /// Local APIC ID (x86_64), or hart ID (RISC-V).
+    pub id: u32,
+    /// Whether the system booted on the CPU.
+    pub is_boot: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Device {
+    /// Name of the device, including its unit address (i.e. `serial@10000000`).
+    pub name: String,
+    /// Models the device is compatible with, from the most specific.
+    pub compatible: Vec<String>,
+    /// Physical memory ranges of the device's registers.
+    pub regs: Vec<Range<usize>>,
+    /// Interrupt specifier cells, as understood by the device's interrupt controller.
+    pub interrupts: Vec<u32>,
+    /// Properties of the device's devicetree node, undecoded (so empty for devices described by ACPI).
+    pub properties: Vec<crate::fdt::Property>,
+}
+
+impl Device {
+    pub fn is_compatible(&self, compatible: &str) -> bool {
+        self.compatible.iter().any(|model| model == compatible)
+    }
+
+    /// Reads a single-cell property of the device's devicetree node, i.e. `reg-shift`.
+    pub fn u32(&self, name: &str) -> Option<u32> {
+        let property = self.properties.iter().find(|property| property.name == name)?;
+
+        Some(u32::from_be_bytes(property.value.get(..4)?.try_into().unwrap()))
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Platform {
+    pub cpus: Vec<Cpu>,
+    /// Physical memory ranges of RAM.
+    pub memory: Vec<Range<usize>>,
+    pub devices: Vec<Device>,
+}
+
+impl Platform {
+    pub fn compatible<'a>(&'a self, compatible: &'a str) -> impl Iterator<Item = &'a Device> {
+        self.devices.iter().filter(move |device| device.is_compatible(compatible))
+    }
+}
+
+static PLATFORM: spin::Once<Platform> = spin::Once::new();
+
+/// Returns the platform description, if the firmware's tables could be read.
+pub fn get() -> Option<&'static Platform> {
+    PLATFORM.get()
+}
+
+/// Describes the platform from the firmware's tables.
+///
+/// #### Remark
+///
+/// This function must be called before bootloader memory is reclaimed.
+pub fn init() {
+    #[cfg(target_arch = "x86_64")]
+    let platform = from_acpi();
+    #[cfg(target_arch = "riscv64")]
+    let platform = crate::fdt::load().map(|tree| crate::fdt::platform(&tree));
+
+    match platform {
+        Ok(platform) => {
+            let platform = PLATFORM.call_once(|| platform);
+            info!(
+                "Platform            {} CPUs, {} memory ranges, {} devices",
+                platform.cpus.len(),
+                platform.memory.len(),
+                platform.devices.len()
+            );
+        }
+
+        Err(err) => warn!("Failed to describe the platform: {:?}", err),
+    }
+}
+
+/// Length of an I/O APIC's register window: its index and data registers.
+#[cfg(target_arch = "x86_64")]
+const IOAPIC_REGS_LEN: usize = 0x20;
+
+#[cfg(target_arch = "x86_64")]
+fn from_acpi() -> crate::init::boot::Result<Platform> {
+    use acpi::platform::{interrupt::InterruptModel, ProcessorState};
+
+    let mut platform = Platform::default();
+
+    if let Some(platform_info) = crate::acpi::PLATFORM_INFO.as_ref() {
+        let platform_info = platform_info.lock();
+
+        if let Some(processor_info) = platform_info.processor_info.as_ref() {
+            platform.cpus = core::iter::once(&processor_info.boot_processor)
+                .chain(processor_info.application_processors.iter())
+                .filter(|processor| processor.state != ProcessorState::Disabled)
+                .map(|processor| Cpu { id: processor.local_apic_id, is_boot: !processor.is_ap })
+                .collect();
+        }
+
+        // Other devices are described in AML, which the kernel doesn't interpret (PCI devices are enumerated instead).
+        if let InterruptModel::Apic(apic) = &platform_info.interrupt_model {
+            platform.devices.extend(apic.io_apics.iter().map(|io_apic| {
+                let address = usize::try_from(io_apic.address).unwrap();
+
+                Device {
+                    name: alloc::format!("ioapic@{address:x}"),
+                    compatible: alloc::vec![String::from("intel,82093aa")],
+                    regs: alloc::vec![address..(address + IOAPIC_REGS_LEN)],
+                    interrupts: Vec::new(),
+                    properties: Vec::new(),
+                }
+            }));
+        }
+    }
+
+    // ACPI leaves RAM to the boot memory map.
+    platform.memory = crate::init::boot::get_memory_map()?
+        .iter()
+        .filter(|entry| entry.ty() == limine::MemoryMapEntryType::Usable)
+        .map(|entry| {
+            let range = entry.range();
+            usize::try_from(range.start).unwrap()..usize::try_from(range.end).unwrap()
+        })
+        .collect();
+
+    Ok(platform)
+}
@@ -0,0 +1,126 @@
+//! System sleep, and the driver hooks which quiesce devices before it (and restore them after).
+//!
+//! Sleep is requested by writing a [`Mode`] (`test` or `mem`) to `/dev/power`, which only a process holding
+//! [`libsys::syscall::cred::Capabilities::POWER`] may open.
+//!
+//! #### Remark
+//!
+//! Entering S3 requires evaluating `_PTS`/`_WAK` and the `\_S3` package, for which there's no AML interpreter yet,
+//! and a real-mode trampoline at the firmware waking vector to resume through. Until then, [`Mode::SuspendToRam`]
+//! quiesces and resumes devices, then fails with [`Error::Unsupported`], and [`Mode::Test`] exercises the hooks alone.
+
+use crate::interrupts::InterruptCell;
+use alloc::{string::String, sync::Arc, vec::Vec};
+use spin::Mutex;
+
+crate::error_impl! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Error {
+        /// A device failed to quiesce, so the system stayed awake.
+        Device => None,
+        /// The platform can't enter the requested sleep state.
+        Unsupported => None
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Suspends and immediately resumes every device, without the system sleeping.
+    Test,
+    /// ACPI S3: everything but memory is powered down.
+    SuspendToRam,
+}
+
+/// Lifecycle hooks of a device across system sleep.
+pub trait Hooks: Send + Sync {
+    /// Quiesces the device, completing (or flushing) outstanding requests, such that it's safe to lose power.
+    fn suspend(&self) -> core::result::Result<(), ()>;
+
+    /// Restores the device after the system wakes (or after a failed suspend).
+    fn resume(&self);
+}
+
+static HOOKS: InterruptCell<Mutex<Vec<(String, Arc<dyn Hooks>)>>> = InterruptCell::new(Mutex::new(Vec::new()));
+
+/// Registers the sleep hooks of the device `name`.
+///
+/// #### Remark
+///
+/// Devices are suspended in the reverse of the order they're registered (so a device is suspended before any device
+/// it was registered after, i.e. its bus), and resumed in order.
+pub fn register(name: &str, hooks: Arc<dyn Hooks>) {
+    HOOKS.with(|registry| registry.lock().push((String::from(name), hooks)));
+}
+
+fn resume_all(devices: &[(String, Arc<dyn Hooks>)]) {
+    for (name, hooks) in devices {
+        trace!("Resuming device: {}", name);
+        hooks.resume();
+    }
+}
+
+/// Suspends every device, sleeps according to `mode`, then resumes every device.
+pub fn suspend(mode: Mode) -> Result<()> {
+    // Hooks run outside of the registry lock, as quiescing a device may wait on it for some time.
+    let devices = HOOKS.with(|registry| registry.lock().clone());
+
+    for (index, (name, hooks)) in devices.iter().enumerate().rev() {
+        trace!("Suspending device: {}", name);
+
+        if hooks.suspend().is_err() {
+            warn!("Device {} failed to suspend; resuming devices.", name);
+            resume_all(&devices[(index + 1)..]);
+
+            return Err(Error::Device);
+        }
+    }
+
+    let result = match mode {
+        Mode::Test => Ok(()),
+        Mode::SuspendToRam => Err(Error::Unsupported),
+    };
+
+    resume_all(&devices);
+
+    result
+}
+
+/// Registers the power device node, `/dev/power`.
+pub fn init() -> crate::vfs::Result<()> {
+    crate::vfs::devfs::register("power", Arc::new(Device))
+}
+
+struct Device;
+
+impl crate::vfs::Node for Device {
+    fn metadata(&self) -> crate::vfs::Metadata {
+        crate::vfs::Metadata {
+            kind: crate::vfs::NodeKind::Device,
+            size: 0,
+            mode: libsys::syscall::fs::Mode::from_bits_retain(0o200),
+            owner: crate::vfs::Owner::ROOT,
+            accessed: 0,
+            modified: 0,
+            created: 0,
+        }
+    }
+
+    fn required_capabilities(&self) -> libsys::syscall::cred::Capabilities {
+        libsys::syscall::cred::Capabilities::POWER
+    }
+
+    /// Sleeps in the mode named by `buf` (`test` or `mem`, ignoring a trailing newline).
+    fn write(&self, _offset: usize, buf: &[u8]) -> crate::vfs::Result<usize> {
+        let mode = match buf.strip_suffix(b"\n").unwrap_or(buf) {
+            b"test" => Mode::Test,
+            b"mem" => Mode::SuspendToRam,
+            _ => return Err(crate::vfs::Error::InvalidArgument),
+        };
+
+        match suspend(mode) {
+            Ok(()) => Ok(buf.len()),
+            Err(Error::Device) => Err(crate::vfs::Error::Busy),
+            Err(Error::Unsupported) => Err(crate::vfs::Error::Unsupported),
+        }
+    }
+}
@@ -0,0 +1,309 @@
+//! SMBIOS (DMI) table parsing, for identifying the hardware the kernel is running on.
+
+use crate::mem::HHDM;
+use alloc::{format, string::String, vec::Vec};
+
+crate::error_impl! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Error {
+        Boot { err: crate::init::boot::Error } => Some(err),
+        UnknownEntryPoint => None,
+        InvalidChecksum => None
+    }
+}
+
+const ENTRY_POINT_32_ANCHOR: &[u8] = b"_SM_";
+const ENTRY_POINT_64_ANCHOR: &[u8] = b"_SM3_";
+
+const TYPE_BIOS: u8 = 0;
+const TYPE_SYSTEM: u8 = 1;
+const TYPE_MEMORY_DEVICE: u8 = 17;
+const TYPE_END_OF_TABLE: u8 = 127;
+
+#[derive(Debug, Clone, Default)]
+pub struct Bios {
+    pub vendor: Option<String>,
+    pub version: Option<String>,
+    pub release_date: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct System {
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+    pub version: Option<String>,
+    pub serial_number: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MemoryDevice {
+    /// Size of the device in KiB, or `None` if it is unknown or the slot is empty.
+    pub size_kib: Option<u64>,
+    /// Configured speed in MT/s, if known.
+    pub speed: Option<u16>,
+    pub memory_type: u8,
+    pub device_locator: Option<String>,
+    pub bank_locator: Option<String>,
+    pub manufacturer: Option<String>,
+    pub part_number: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Info {
+    pub version: (u8, u8),
+    pub bios: Option<Bios>,
+    pub system: Option<System>,
+    pub memory_devices: Vec<MemoryDevice>,
+}
+
+static INFO: spin::Once<Info> = spin::Once::new();
+
+/// Returns the parsed SMBIOS information, if the platform provided any.
+pub fn get() -> Option<&'static Info> {
+    INFO.get()
+}
+
+/// Locates and parses the SMBIOS tables.
+///
+/// #### Remark
+///
+/// This function must be called before bootloader memory is reclaimed.
+pub fn init() -> Result<()> {
+    let info = INFO.try_call_once(|| {
+        let entry_address = crate::init::boot::get_smbios_address().map_err(|err| Error::Boot { err })?;
+        // Safety: Bootloader guarantees the provided SMBIOS entry point address is valid.
+        let entry_point = unsafe { physical_slice(entry_address.get(), 0x20) };
+
+        let (version, table_address, table_len) = if entry_point.starts_with(ENTRY_POINT_64_ANCHOR) {
+            let len = usize::from(entry_point[0x06]);
+            validate_checksum(&entry_point[..len.min(entry_point.len())])?;
+
+            (
+                (entry_point[0x07], entry_point[0x08]),
+                usize::try_from(read_u64(entry_point, 0x10)).unwrap(),
+                usize::try_from(read_u32(entry_point, 0x0C)).unwrap(),
+            )
+        } else if entry_point.starts_with(ENTRY_POINT_32_ANCHOR) {
+            let len = usize::from(entry_point[0x05]);
+            validate_checksum(&entry_point[..len.min(entry_point.len())])?;
+
+            (
+                (entry_point[0x06], entry_point[0x07]),
+                usize::try_from(read_u32(entry_point, 0x18)).unwrap(),
+                usize::from(read_u16(entry_point, 0x16)),
+            )
+        } else {
+            return Err(Error::UnknownEntryPoint);
+        };
+
+        // Safety: Entry point checksum was validated, so the table address & length are as reliable as firmware makes
+        //         them.
+        let table = unsafe { physical_slice(table_address, table_len) };
+
+        Ok(parse_table(version, table))
+    })?;
+
+    info!("SMBIOS              v{}.{}", info.version.0, info.version.1);
+    if let Some(bios) = &info.bios {
+        info!(
+            "BIOS                {} {} ({})",
+            bios.vendor.as_deref().unwrap_or("Unknown"),
+            bios.version.as_deref().unwrap_or("Unknown"),
+            bios.release_date.as_deref().unwrap_or("Unknown")
+        );
+    }
+    if let Some(system) = &info.system {
+        info!(
+            "System              {} {} {}",
+            system.manufacturer.as_deref().unwrap_or("Unknown"),
+            system.product.as_deref().unwrap_or("Unknown"),
+            system.version.as_deref().unwrap_or("")
+        );
+    }
+    for device in info.memory_devices.iter().filter(|device| device.size_kib.is_some()) {
+        info!(
+            "Memory Device       {} {} KiB @ {} MT/s ({} {})",
+            device.device_locator.as_deref().unwrap_or("Unknown"),
+            device.size_kib.unwrap_or(0),
+            device.speed.unwrap_or(0),
+            device.manufacturer.as_deref().unwrap_or("Unknown"),
+            device.part_number.as_deref().unwrap_or("Unknown")
+        );
+    }
+
+    publish(info);
+
+    Ok(())
+}
+
+/// Publishes the parsed tables to sysfs (`firmware/smbios`), with a node for the BIOS, the system, and each populated
+/// memory device (by its index among them). Strings the firmware left out are left unset.
+fn publish(info: &Info) {
+    fn set(path: &str, name: &str, value: Option<&String>) {
+        if let Some(value) = value {
+            crate::sysfs::set(path, name, value.clone());
+        }
+    }
+
+    crate::sysfs::set("firmware/smbios", "version", format!("{}.{}", info.version.0, info.version.1));
+
+    if let Some(bios) = &info.bios {
+        set("firmware/smbios/bios", "vendor", bios.vendor.as_ref());
+        set("firmware/smbios/bios", "version", bios.version.as_ref());
+        set("firmware/smbios/bios", "release_date", bios.release_date.as_ref());
+    }
+
+    if let Some(system) = &info.system {
+        set("firmware/smbios/system", "manufacturer", system.manufacturer.as_ref());
+        set("firmware/smbios/system", "product", system.product.as_ref());
+        set("firmware/smbios/system", "version", system.version.as_ref());
+        set("firmware/smbios/system", "serial_number", system.serial_number.as_ref());
+    }
+
+    for (index, device) in info.memory_devices.iter().filter(|device| device.size_kib.is_some()).enumerate() {
+        let path = format!("firmware/smbios/memory/{index}");
+
+        set(&path, "size_kib", device.size_kib.map(|size| format!("{size}")).as_ref());
+        set(&path, "speed", device.speed.map(|speed| format!("{speed}")).as_ref());
+        crate::sysfs::set(&path, "type", format!("{:#04X}", device.memory_type));
+        set(&path, "device_locator", device.device_locator.as_ref());
+        set(&path, "bank_locator", device.bank_locator.as_ref());
+        set(&path, "manufacturer", device.manufacturer.as_ref());
+        set(&path, "part_number", device.part_number.as_ref());
+    }
+}
+
+/// ### Safety
+///
+/// `address..(address + len)` must be a valid physical memory range.
+unsafe fn physical_slice(address: usize, len: usize) -> &'static [u8] {
+    core::slice::from_raw_parts(HHDM.ptr().add(address), len)
+}
+
+fn validate_checksum(bytes: &[u8]) -> Result<()> {
+    if bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) == 0 {
+        Ok(())
+    } else {
+        Err(Error::InvalidChecksum)
+    }
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    bytes.get(offset..(offset + 2)).map_or(0, |bytes| u16::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    bytes.get(offset..(offset + 4)).map_or(0, |bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    bytes.get(offset..(offset + 8)).map_or(0, |bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+/// A single SMBIOS structure: its formatted area, followed by its string set.
+struct Structure<'a> {
+    ty: u8,
+    formatted: &'a [u8],
+    strings: &'a [u8],
+}
+
+impl Structure<'_> {
+    fn byte(&self, offset: usize) -> u8 {
+        self.formatted.get(offset).copied().unwrap_or(0)
+    }
+
+    /// Resolves the string referenced by the (1-based) string index at `offset` in the formatted area.
+    fn string(&self, offset: usize) -> Option<String> {
+        let index = usize::from(self.byte(offset)).checked_sub(1)?;
+
+        self.strings
+            .split(|byte| *byte == 0)
+            .nth(index)
+            .and_then(|bytes| core::str::from_utf8(bytes).ok())
+            .map(str::trim)
+            .filter(|str| !str.is_empty())
+            .map(String::from)
+    }
+}
+
+struct StructureIter<'a> {
+    table: &'a [u8],
+}
+
+impl<'a> Iterator for StructureIter<'a> {
+    type Item = Structure<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let ty = *self.table.first()?;
+        let len = usize::from(*self.table.get(1)?);
+        if len < 4 || len > self.table.len() {
+            return None;
+        }
+
+        let (formatted, remaining) = self.table.split_at(len);
+        // The string set is terminated by a double null byte.
+        let strings_len = remaining.windows(2).position(|window| window == [0, 0])?;
+        let strings = &remaining[..strings_len];
+        self.table = &remaining[(strings_len + 2)..];
+
+        if ty == TYPE_END_OF_TABLE {
+            self.table = &[];
+        }
+
+        Some(Structure { ty, formatted, strings })
+    }
+}
+
+fn parse_table(version: (u8, u8), table: &[u8]) -> Info {
+    let mut info = Info { version, ..Info::default() };
+
+    for structure in (StructureIter { table }) {
+        match structure.ty {
+            TYPE_BIOS => {
+                info.bios = Some(Bios {
+                    vendor: structure.string(0x04),
+                    version: structure.string(0x05),
+                    release_date: structure.string(0x08),
+                });
+            }
+
+            TYPE_SYSTEM => {
+                info.system = Some(System {
+                    manufacturer: structure.string(0x04),
+                    product: structure.string(0x05),
+                    version: structure.string(0x06),
+                    serial_number: structure.string(0x07),
+                });
+            }
+
+            TYPE_MEMORY_DEVICE => {
+                let size_kib = match read_u16(structure.formatted, 0x0C) {
+                    0 | 0xFFFF => None,
+                    // Size is provided in the extended size field, in MiB.
+                    0x7FFF => Some(u64::from(read_u32(structure.formatted, 0x1C) & 0x7FFF_FFFF) * 1024),
+                    // Bit 15 indicates the granularity is KiB, rather than MiB.
+                    size if (size & 0x8000) > 0 => Some(u64::from(size & 0x7FFF)),
+                    size => Some(u64::from(size) * 1024),
+                };
+                let speed = match read_u16(structure.formatted, 0x15) {
+                    0 => None,
+                    speed => Some(speed),
+                };
+
+                info.memory_devices.push(MemoryDevice {
+                    size_kib,
+                    speed,
+                    memory_type: structure.byte(0x12),
+                    device_locator: structure.string(0x10),
+                    bank_locator: structure.string(0x11),
+                    manufacturer: structure.string(0x17),
+                    part_number: structure.string(0x1A),
+                });
+            }
+
+            _ => {}
+        }
+    }
+
+    info
+}
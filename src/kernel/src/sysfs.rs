@@ -0,0 +1,106 @@
+//! Read-only attribute tree describing kernel objects (devices, drivers, etc.).
+//!
+//! Nodes are addressed by `/`-separated paths (e.g. `bus/pci/0000:00:01.0`), and each
+//! node holds named attributes whose values are rendered as strings on read.
+
+use alloc::{
+    boxed::Box,
+    collections::BTreeMap,
+    string::{String, ToString},
+    vec::Vec,
+};
+use spin::RwLock;
+
+pub enum Value {
+    /// A value fixed at the time of insertion.
+    Static(String),
+    /// A value computed on every read.
+    Dynamic(Box<dyn Fn() -> String + Send + Sync>),
+}
+
+impl Value {
+    pub fn render(&self) -> String {
+        match self {
+            Value::Static(value) => value.clone(),
+            Value::Dynamic(func) => func(),
+        }
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Self::Static(value)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Self::Static(value.to_string())
+    }
+}
+
+#[derive(Default)]
+struct Node {
+    children: BTreeMap<String, Node>,
+    attributes: BTreeMap<String, Value>,
+}
+
+impl Node {
+    fn get(&self, path: &str) -> Option<&Node> {
+        components(path).try_fold(self, |node, component| node.children.get(component))
+    }
+
+    fn get_or_create(&mut self, path: &str) -> &mut Node {
+        components(path).fold(self, |node, component| node.children.entry(component.to_string()).or_default())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Entry {
+    Node(String),
+    Attribute(String),
+}
+
+static ROOT: RwLock<Node> = RwLock::new(Node { children: BTreeMap::new(), attributes: BTreeMap::new() });
+
+fn components(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|component| !component.is_empty())
+}
+
+/// Sets the attribute `name` of the node at `path`, creating any nodes along the path.
+pub fn set(path: &str, name: &str, value: impl Into<Value>) {
+    ROOT.write().get_or_create(path).attributes.insert(name.to_string(), value.into());
+}
+
+/// Reads the attribute `name` of the node at `path`.
+pub fn read(path: &str, name: &str) -> Option<String> {
+    ROOT.read().get(path)?.attributes.get(name).map(Value::render)
+}
+
+/// Lists the child nodes and attributes of the node at `path`.
+pub fn list(path: &str) -> Option<Vec<Entry>> {
+    let root = ROOT.read();
+    let node = root.get(path)?;
+
+    Some(
+        node.children
+            .keys()
+            .cloned()
+            .map(Entry::Node)
+            .chain(node.attributes.keys().cloned().map(Entry::Attribute))
+            .collect(),
+    )
+}
+
+/// Removes the node at `path`, and all of its descendants.
+pub fn remove(path: &str) {
+    let mut components = components(path).collect::<Vec<_>>();
+    let Some(name) = components.pop() else { return };
+
+    let mut root = ROOT.write();
+    if let Some(parent) =
+        components.into_iter().try_fold(&mut *root, |node, component| node.children.get_mut(component))
+    {
+        parent.children.remove(name);
+    }
+}
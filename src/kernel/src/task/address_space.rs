@@ -1,10 +1,14 @@
 use crate::mem::{
+    alloc::{pmm, zero},
     mapper::Mapper,
     paging,
     paging::{TableDepth, TableEntryFlags},
+    HHDM,
 };
+use alloc::{borrow::Cow, collections::BTreeMap, vec::Vec};
 use core::{num::NonZeroUsize, ptr::NonNull};
-use libsys::{page_size, Address, Page, Virtual};
+use elf::segment::ProgramHeader;
+use libsys::{page_size, Address, Frame, Page, Physical, Virtual};
 
 crate::error_impl! {
     #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -27,11 +31,25 @@ crate::error_impl! {
 
         NotMapped { addr: Address<Virtual> } => None,
 
+        /// The change would let a region be writable at one time, and executable at another.
+        WxViolation => None,
+
         /// Provides the error that occured within the internal `Mapper`.
         Paging { err: paging::Error } => Some(err)
     }
 }
 
+impl From<Error> for libsys::syscall::Error {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::AllocError => Self::OutOfMemory,
+            Error::NotMapped { .. } => Self::UnmappedMemory,
+            Error::WxViolation => Self::WxViolation,
+            _ => Self::InvalidArgument,
+        }
+    }
+}
+
 impl From<paging::Error> for Error {
     fn from(value: paging::Error) -> Self {
         match value {
@@ -61,13 +79,132 @@ impl From<MmapPermissions> for TableEntryFlags {
 }
 
 pub const DEFAULT_USERSPACE_SIZE: NonZeroUsize = NonZeroUsize::new(1 << 47).unwrap();
+/// Base of the region in which anonymous memory is mapped, above any ELF image or stack.
+pub const ANONYMOUS_MAPPINGS_BASE: usize = DEFAULT_USERSPACE_SIZE.get() / 4;
+/// Base of the region in which device memory is mapped, well above any ELF image or stack.
+pub const DEVICE_MAPPINGS_BASE: usize = DEFAULT_USERSPACE_SIZE.get() / 2;
+
+/// Whether `frame` is mapped more than once (i.e. shared by [`AddressSpace::fork`]).
+fn is_shared(frame: Address<Frame>) -> bool {
+    pmm::get().references(frame).is_ok_and(|references| references > 1)
+}
+
+/// Drops a mapping of `frame`, freeing it if it was the last.
+fn release_frame(frame: Address<Frame>) -> Result<()> {
+    zero::release_frame(frame).map_err(|_| Error::AllocError)
+}
+
+/// Source of a region's contents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backing {
+    /// Zero-filled memory, owned by the address space.
+    Anonymous,
+    /// A `PT_LOAD` segment of the task's executable, copied in as its pages are demand mapped.
+    Elf(ProgramHeader),
+    /// Frames which belong to a device (or the kernel), and so outlive the mapping.
+    Device,
+    /// A segment of an object mapped by the dynamic loader, which the kernel relocates on its behalf.
+    Loaded,
+    /// Pages which are never mapped, so that accessing them faults (i.e. below a stack, to catch its overflow).
+    Guard,
+}
+
+/// A contiguous range of pages of an address space, which may be only partially mapped (i.e. an ELF segment which is
+/// demand mapped).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Region {
+    pub start: Address<Page>,
+    pub page_count: NonZeroUsize,
+    pub permissions: MmapPermissions,
+    pub backing: Backing,
+    /// Describes the region's contents (i.e. `stack`, `elf:.text`, or `mmio:fd3`).
+    pub name: Option<Cow<'static, str>>,
+
+    /// Whether the region has ever been writable.
+    was_writable: bool,
+    /// Whether the region has ever been executable.
+    was_executable: bool,
+    /// Whether the region may alternate between writable and executable.
+    allow_wx_transition: bool,
+    /// Whether the region is fixed read-only (i.e. its frames are shared with the kernel).
+    sealed: bool,
+    /// Permissions the region may take on besides read-only, if limited (i.e. to those its device was opened with).
+    max_permissions: Option<MmapPermissions>,
+}
+
+impl Region {
+    fn new(
+        start: Address<Page>,
+        page_count: NonZeroUsize,
+        permissions: MmapPermissions,
+        backing: Backing,
+        name: Option<Cow<'static, str>>,
+    ) -> Self {
+        Self {
+            start,
+            page_count,
+            permissions,
+            backing,
+            name,
+            was_writable: permissions == MmapPermissions::ReadWrite,
+            was_executable: permissions == MmapPermissions::ReadExecute,
+            allow_wx_transition: false,
+            sealed: false,
+            max_permissions: None,
+        }
+    }
+
+    /// Indicates whether the region may take on `permissions` without violating W^X.
+    ///
+    /// #### Remark
+    ///
+    /// Writable and executable history is tracked (rather than just the current permissions), so that a region
+    /// can't be written, made read-only, then made executable.
+    pub const fn permits(&self, permissions: MmapPermissions) -> bool {
+        if self.sealed {
+            return matches!(permissions, MmapPermissions::ReadOnly);
+        }
+
+        match (self.max_permissions, permissions) {
+            (None, _)
+            | (Some(_), MmapPermissions::ReadOnly)
+            | (Some(MmapPermissions::ReadWrite), MmapPermissions::ReadWrite)
+            | (Some(MmapPermissions::ReadExecute), MmapPermissions::ReadExecute) => {}
+            (Some(_), _) => return false,
+        }
+
+        self.allow_wx_transition
+            || match permissions {
+                MmapPermissions::ReadExecute => !self.was_writable,
+                MmapPermissions::ReadWrite => !self.was_executable,
+                MmapPermissions::ReadOnly => true,
+            }
+    }
+
+    fn end_index(&self) -> usize {
+        self.start.index() + self.page_count.get()
+    }
+
+    #[inline]
+    pub fn contains(&self, page: Address<Page>) -> bool {
+        (self.start.index()..self.end_index()).contains(&page.index())
+    }
+}
 
-pub struct AddressSpace(Mapper);
+pub struct AddressSpace {
+    mapper: Mapper,
+    /// Regions of the address space, keyed by the index of their first page.
+    regions: BTreeMap<usize, Region>,
+    /// Pages mapped through this address space (excluding the kernel's).
+    resident_pages: usize,
+    /// Pages covered by regions, whether or not they're mapped yet.
+    virtual_pages: usize,
+}
 
 impl AddressSpace {
     #[inline]
     pub const fn new(mapper: Mapper) -> Self {
-        Self(mapper)
+        Self { mapper, regions: BTreeMap::new(), resident_pages: 0, virtual_pages: 0 }
     }
 
     pub fn new_userspace() -> Self {
@@ -75,7 +212,7 @@ impl AddressSpace {
     }
 
     pub fn is_current(&self) -> bool {
-        let root_frame = self.0.root_frame();
+        let root_frame = self.mapper.root_frame();
         let cr3_frame = crate::mem::PagingRegister::read().frame();
 
         root_frame == cr3_frame
@@ -89,49 +226,518 @@ impl AddressSpace {
         // lazy: bool,
         permissions: MmapPermissions,
     ) -> Result<NonNull<[u8]>> {
-        if let Some(address) = address {
-            self.map_exact(address, page_count, permissions)
-        } else {
-            self.map_any(page_count, permissions)
+        let address = match address {
+            Some(address) => address,
+            None => self.find_free(ANONYMOUS_MAPPINGS_BASE, page_count).ok_or(Error::AllocError)?,
+        };
+        let end_index = address.index().checked_add(page_count.get()).ok_or(Error::InvalidAddress)?;
+
+        // Mappings within an existing region (i.e. demand mapping) don't form regions of their own, but mustn't
+        // extend past it.
+        let new_region = match self.region(address) {
+            Some(region) if end_index <= region.end_index() => false,
+            Some(_) => return Err(Error::OverlappingAddress),
+            None if self.overlaps(address.index(), end_index) => return Err(Error::OverlappingAddress),
+            None => true,
+        };
+
+        let mapping = self.map_exact(address, page_count, permissions)?;
+        if new_region {
+            self.insert_region(Region::new(address, page_count, permissions, Backing::Anonymous, None));
         }
+
+        Ok(mapping)
     }
 
-    #[cfg_attr(debug_assertions, inline(never))]
-    fn map_any(&mut self, page_count: NonZeroUsize, permissions: MmapPermissions) -> Result<NonNull<[u8]>> {
-        let walker = unsafe {
-            paging::walker::Walker::new(self.0.view_page_table(), TableDepth::max(), TableDepth::min()).unwrap()
-        };
+    /// Maps a stack of `page_count` pages from `address`, reserving `guard_count` guard pages below it.
+    pub fn mmap_stack(
+        &mut self,
+        address: Address<Page>,
+        page_count: NonZeroUsize,
+        guard_count: NonZeroUsize,
+    ) -> Result<NonNull<[u8]>> {
+        let guard_index = address.index().checked_sub(guard_count.get()).ok_or(Error::InvalidAddress)?;
+        let guard = Address::from_index(guard_index).ok_or(Error::AddressIndexOverrun { index: guard_index })?;
+        self.reserve(guard, guard_count, MmapPermissions::ReadOnly, Backing::Guard, "stack-guard")?;
 
-        let mut index = 0;
-        let mut run = 0;
-        walker.walk(|entry| {
-            use core::ops::ControlFlow;
+        let stack = self.mmap(Some(address), page_count, MmapPermissions::ReadWrite)?;
+        self.name_region(address, "stack")?;
 
-            if entry.is_none() {
-                run += 1;
+        Ok(stack)
+    }
+
+    /// Indicates whether any region covers a page of `start_index..end_index`.
+    fn overlaps(&self, start_index: usize, end_index: usize) -> bool {
+        self.regions.range(..end_index).next_back().is_some_and(|(_, region)| region.end_index() > start_index)
+    }
+
+    fn insert_region(&mut self, region: Region) {
+        self.virtual_pages += region.page_count.get();
+        self.regions.insert(region.start.index(), region);
+    }
+
+    /// Declares a region which will be mapped lazily (i.e. by demand mapping).
+    pub fn reserve(
+        &mut self,
+        start: Address<Page>,
+        page_count: NonZeroUsize,
+        permissions: MmapPermissions,
+        backing: Backing,
+        name: impl Into<Cow<'static, str>>,
+    ) -> Result<()> {
+        if self.overlaps(start.index(), start.index() + page_count.get()) {
+            return Err(Error::OverlappingAddress);
+        }
+
+        self.insert_region(Region::new(start, page_count, permissions, backing, Some(name.into())));
+
+        Ok(())
+    }
+
+    /// Returns the first run of `page_count` pages, at or above `base`, which no region covers.
+    fn find_free(&self, base: usize, page_count: NonZeroUsize) -> Option<Address<Page>> {
+        let mut start_index = Address::<Page>::new_truncate(base).index();
+
+        // A region beginning below `base` may extend past it.
+        if let Some((_, region)) = self.regions.range(..start_index).next_back() {
+            start_index = start_index.max(region.end_index());
+        }
+
+        for region in self.regions.range(start_index..).map(|(_, region)| region) {
+            if (region.start.index() - start_index) >= page_count.get() {
+                break;
+            }
+
+            start_index = region.end_index();
+        }
+
+        let end_index = start_index.checked_add(page_count.get())?;
+        (end_index <= (DEFAULT_USERSPACE_SIZE.get() / page_size())).then(|| Address::from_index(start_index)).flatten()
+    }
+
+    /// Returns the region containing `page`, if any.
+    pub fn region(&self, page: Address<Page>) -> Option<&Region> {
+        self.regions.range(..=page.index()).next_back().map(|(_, region)| region).filter(|region| region.contains(page))
+    }
+
+    fn region_mut(&mut self, page: Address<Page>) -> Result<&mut Region> {
+        self.regions
+            .range_mut(..=page.index())
+            .next_back()
+            .map(|(_, region)| region)
+            .filter(|region| region.contains(page))
+            .ok_or(Error::NotMapped { addr: page.get() })
+    }
+
+    /// Names the region containing `page`.
+    pub fn name_region(&mut self, page: Address<Page>, name: impl Into<Cow<'static, str>>) -> Result<()> {
+        self.region_mut(page)?.name = Some(name.into());
+
+        Ok(())
+    }
+
+    /// Permits the region containing `page` to alternate between writable and executable (i.e. for a JIT compiler).
+    pub fn allow_wx_transition(&mut self, page: Address<Page>) -> Result<()> {
+        self.region_mut(page)?.allow_wx_transition = true;
+
+        Ok(())
+    }
+
+    /// Fixes the region containing `page` read-only, such that it may never be made writable or executable.
+    pub fn seal(&mut self, page: Address<Page>) -> Result<()> {
+        self.region_mut(page)?.sealed = true;
+
+        Ok(())
+    }
+
+    /// Splits the region containing `index` (if any), such that a region begins at `index`.
+    fn split_region(&mut self, index: usize) {
+        let Some((&start_index, region)) = self.regions.range_mut(..index).next_back() else { return };
+        let Some(tail_count) = region.end_index().checked_sub(index).and_then(NonZeroUsize::new) else { return };
+
+        region.page_count = NonZeroUsize::new(index - start_index).unwrap();
+        let tail = Region { start: Address::from_index(index).unwrap(), page_count: tail_count, ..region.clone() };
+        self.regions.insert(index, tail);
+    }
 
-                if run == page_count.get() {
-                    return ControlFlow::Break(());
+    /// Changes the permissions of `page_count` pages from `address`, which must lie within a single region.
+    ///
+    /// Fails with [`Error::WxViolation`] if the region doesn't [`Region::permits`] the new permissions.
+    pub fn protect(
+        &mut self,
+        address: Address<Page>,
+        page_count: NonZeroUsize,
+        permissions: MmapPermissions,
+    ) -> Result<()> {
+        let end_index = address.index().checked_add(page_count.get()).ok_or(Error::InvalidAddress)?;
+        let region = self.region(address).ok_or(Error::NotMapped { addr: address.get() })?;
+        if end_index > region.end_index() {
+            return Err(Error::InvalidAddress);
+        } else if !region.permits(permissions) {
+            return Err(Error::WxViolation);
+        }
+
+        self.split_region(address.index());
+        self.split_region(end_index);
+
+        let flags = TableEntryFlags::PRESENT | TableEntryFlags::USER | TableEntryFlags::from(permissions);
+        for page in (address.index()..end_index).filter_map(Address::<Page>::from_index) {
+            // Pages which haven't been demand mapped yet take on the region's permissions when they are.
+            if let Some(attributes) = self.mapper.get_page_attributes(page) {
+                // Copy-on-write pages stay read-only until they're copied.
+                let flags = if attributes.contains(TableEntryFlags::COPY_ON_WRITE) {
+                    (flags - TableEntryFlags::WRITABLE) | TableEntryFlags::COPY_ON_WRITE
+                } else {
+                    flags
+                };
+
+                // Safety: The page belongs to a userspace region, and the new flags never make it both writable and
+                //         executable. Each page is invalidated in the TLB as its flags are set.
+                unsafe { self.mapper.set_page_attributes(page, None, flags, paging::FlagsModify::Set) }?;
+            }
+        }
+
+        self.shootdown(address, page_count.get());
+
+        let region = self.regions.get_mut(&address.index()).unwrap();
+        region.permissions = permissions;
+        region.was_writable |= permissions == MmapPermissions::ReadWrite;
+        region.was_executable |= permissions == MmapPermissions::ReadExecute;
+
+        Ok(())
+    }
+
+    /// Unmaps `page_count` pages from `address`, releasing the frames backing them (unless they belong to a device).
+    ///
+    /// Fails with [`Error::NotMapped`] if any page of the range lies outside of every region, in which case nothing
+    /// is unmapped.
+    ///
+    /// #### Remark
+    ///
+    /// Frames are only released once the range is shot down from the TLBs of every core with the address space
+    /// loaded, so no core can reach a frame through a stale entry after it's reused.
+    pub fn munmap(&mut self, address: Address<Page>, page_count: NonZeroUsize) -> Result<()> {
+        let end_index = address.index().checked_add(page_count.get()).ok_or(Error::InvalidAddress)?;
+
+        // Validate the whole range up-front, so a partially unmapped range is never left behind.
+        let mut index = address.index();
+        while index < end_index {
+            let page = Address::<Page>::from_index(index).ok_or(Error::AddressIndexOverrun { index })?;
+            index = self.region(page).ok_or(Error::NotMapped { addr: page.get() })?.end_index();
+        }
+
+        self.split_region(address.index());
+        self.split_region(end_index);
+
+        let starts = self.regions.range(address.index()..end_index).map(|(&start, _)| start).collect::<Vec<_>>();
+        let mut released = Vec::new();
+        for start in starts {
+            let region = self.regions.remove(&start).unwrap();
+            self.virtual_pages -= region.page_count.get();
+
+            for page in (start..region.end_index()).filter_map(Address::<Page>::from_index) {
+                let Some(frame) = self.mapper.get_mapped_to(page) else { continue };
+
+                // Safety: The page belongs to a userspace region which is being removed, so nothing refers to it
+                //         once it's unmapped.
+                unsafe { self.mapper.unmap(page, None, false) }?;
+                self.resident_pages -= 1;
+
+                // Device frames outlive the mapping, so aren't freed.
+                if region.backing != Backing::Device {
+                    released.push(frame);
                 }
-            } else {
-                run = 0;
             }
+        }
+
+        self.shootdown(address, page_count.get());
+        released.into_iter().try_for_each(release_frame)
+    }
+
+    /// Duplicates the address space, sharing its frames with the copy until either writes to them.
+    ///
+    /// #### Remark
+    ///
+    /// Shared pages are made read-only in both address spaces and marked [`TableEntryFlags::COPY_ON_WRITE`], so the
+    /// first write to one faults, and is resolved by [`AddressSpace::copy_on_write`]. Device frames are mapped into
+    /// the copy as they are, as they belong to the device.
+    pub fn fork(&mut self) -> Result<Self> {
+        let mut child = Self::new_userspace();
+
+        for region in self.regions.values() {
+            for page in (region.start.index()..region.end_index()).filter_map(Address::<Page>::from_index) {
+                let Some(frame) = self.mapper.get_mapped_to(page) else { continue };
+                let mut flags = self.mapper.get_page_attributes(page).unwrap();
+
+                if region.backing != Backing::Device {
+                    flags.remove(TableEntryFlags::WRITABLE);
+                    flags.insert(TableEntryFlags::COPY_ON_WRITE);
+
+                    // Safety: Only write access is removed, and the page is invalidated in the TLB as it is.
+                    unsafe { self.mapper.set_page_attributes(page, None, flags, paging::FlagsModify::Set) }?;
+                    pmm::retain(frame).map_err(|_| Error::AllocError)?;
+                }
+
+                child.mapper.map(page, TableDepth::min(), frame, false, flags)?;
+                child.resident_pages += 1;
+            }
+
+            self.shootdown(region.start, region.page_count.get());
+
+            child.insert_region(region.clone());
+        }
+
+        Ok(child)
+    }
+
+    /// Resolves a write fault on the copy-on-write `page`, giving the address space its own copy of the frame (or
+    /// taking sole ownership of it, if every other mapping is gone).
+    ///
+    /// Returns `false` if the fault isn't a copy-on-write fault, i.e. the page isn't mapped copy-on-write, or its
+    /// region isn't writable.
+    pub fn copy_on_write(&mut self, page: Address<Page>) -> Result<bool> {
+        let Some(attributes) = self.mapper.get_page_attributes(page) else { return Ok(false) };
+        if !attributes.contains(TableEntryFlags::COPY_ON_WRITE) {
+            return Ok(false);
+        }
+
+        let region = self.region(page).ok_or(Error::NotMapped { addr: page.get() })?;
+        if region.permissions != MmapPermissions::ReadWrite {
+            return Ok(false);
+        }
+
+        let flags = TableEntryFlags::PRESENT | TableEntryFlags::USER | TableEntryFlags::from(region.permissions);
+        self.unshare(page, flags)?;
+
+        Ok(true)
+    }
+
+    /// Gives the address space its own copy of the frame mapped to the copy-on-write `page`, mapping it with `flags`.
+    fn unshare(&mut self, page: Address<Page>, flags: TableEntryFlags) -> Result<()> {
+        let frame = self.mapper.get_mapped_to(page).ok_or(Error::NotMapped { addr: page.get() })?;
+
+        if is_shared(frame) {
+            let original = HHDM.frames(frame, NonZeroUsize::MIN).ok_or(Error::NotMapped { addr: page.get() })?;
+            let copy = pmm::get().next_frame(pmm::Owner::UserAnon).map_err(|_| Error::AllocError)?;
+
+            // Safety: The copy was just allocated, so is owned by the address space alone.
+            let copy_bytes = unsafe { HHDM.slice_mut(copy, NonZeroUsize::MIN) }.unwrap();
+            // Safety: The original frame is mapped (so allocated), and can't overlap the newly allocated copy.
+            copy_bytes.copy_from_slice(unsafe { original.as_ref() });
+
+            self.mapper.map(page, TableDepth::min(), copy, false, flags)?;
+            self.shootdown(page, 1);
+            // Released only once copied, so the frame can't be written by its last owner mid-copy.
+            release_frame(frame)?;
+        } else {
+            // Safety: The frame is no longer shared, so the address space may write to it.
+            unsafe { self.mapper.set_page_attributes(page, None, flags, paging::FlagsModify::Set) }?;
+            self.shootdown(page, 1);
+        }
+
+        Ok(())
+    }
+
+    /// Maps `page_count` zeroed pages from `address` for a segment of an object loaded by the dynamic loader, which
+    /// [`AddressSpace::write_loaded`] may later write whatever its permissions.
+    pub fn map_loaded(
+        &mut self,
+        address: Address<Page>,
+        page_count: NonZeroUsize,
+        permissions: MmapPermissions,
+        name: impl Into<Cow<'static, str>>,
+    ) -> Result<()> {
+        let end_index = address.index().checked_add(page_count.get()).ok_or(Error::InvalidAddress)?;
+        if end_index > (DEFAULT_USERSPACE_SIZE.get() / page_size()) {
+            return Err(Error::InvalidAddress);
+        } else if self.overlaps(address.index(), end_index) {
+            return Err(Error::OverlappingAddress);
+        }
+
+        self.map_exact(address, page_count, permissions)?;
+        self.insert_region(Region::new(address, page_count, permissions, Backing::Loaded, Some(name.into())));
+
+        Ok(())
+    }
+
+    /// Writes `bytes` from `address`, which must lie within segments mapped by [`AddressSpace::map_loaded`],
+    /// regardless of their permissions (i.e. to relocate read-only data).
+    pub fn write_loaded(&mut self, address: Address<Virtual>, bytes: &[u8]) -> Result<()> {
+        self.write_through(address, bytes, |region| region.backing == Backing::Loaded)
+    }
+
+    /// Writes `bytes` from `address` as [`AddressSpace::write_loaded`] does, for a relocation requested by the task.
+    ///
+    /// Fails with [`Error::WxViolation`] if any segment written has been executable, unless it's allowed to alternate
+    /// (see [`AddressSpace::allow_wx_transition`]). Segments written count as having been writable, so a read-only
+    /// segment which is relocated can't later be made executable.
+    pub fn relocate_loaded(&mut self, address: Address<Virtual>, bytes: &[u8]) -> Result<()> {
+        let end = address.get().checked_add(bytes.len()).ok_or(Error::InvalidAddress)?;
+        let pages = (address.get() / page_size())..end.div_ceil(page_size());
+
+        for page in pages.clone().filter_map(Address::<Page>::from_index) {
+            let region = self.region(page).ok_or(Error::NotMapped { addr: page.get() })?;
+            if region.backing != Backing::Loaded {
+                return Err(Error::InvalidAddress);
+            } else if region.was_executable && !region.allow_wx_transition {
+                return Err(Error::WxViolation);
+            }
+        }
+
+        for page in pages.filter_map(Address::<Page>::from_index) {
+            self.region_mut(page)?.was_writable = true;
+        }
+
+        self.write_loaded(address, bytes)
+    }
+
+    /// Writes `bytes` from `address` through the page tables, so the address space needn't be active (i.e. to set up
+    /// a task's stack before it first runs). The pages must already be mapped, and writable.
+    pub fn write_mapped(&mut self, address: Address<Virtual>, bytes: &[u8]) -> Result<()> {
+        self.write_through(address, bytes, |region| region.permissions == MmapPermissions::ReadWrite)
+    }
+
+    /// Writes `bytes` from `address` via the higher-half direct map, into regions which `writable` allows.
+    fn write_through(
+        &mut self,
+        address: Address<Virtual>,
+        mut bytes: &[u8],
+        writable: impl Fn(&Region) -> bool,
+    ) -> Result<()> {
+        let mut address = address.get();
+
+        while !bytes.is_empty() {
+            let page = Address::<Page>::new_truncate(address);
+            let region = self.region(page).ok_or(Error::NotMapped { addr: page.get() })?;
+            if !writable(region) {
+                return Err(Error::InvalidAddress);
+            }
+
+            let flags = TableEntryFlags::PRESENT | TableEntryFlags::USER | TableEntryFlags::from(region.permissions);
+            let attributes = self.get_flags(page)?;
+            if attributes.contains(TableEntryFlags::COPY_ON_WRITE) {
+                self.unshare(page, flags)?;
+            }
+
+            let frame = self.mapper.get_mapped_to(page).ok_or(Error::NotMapped { addr: page.get() })?;
+            let offset = address & libsys::page_mask();
+            let (chunk, remaining) = bytes.split_at(bytes.len().min(page_size() - offset));
+
+            // Safety: The frame is owned by the address space alone, having just been unshared.
+            let frame_bytes =
+                unsafe { HHDM.slice_mut(frame, NonZeroUsize::MIN) }.ok_or(Error::NotMapped { addr: page.get() })?;
+            frame_bytes[offset..(offset + chunk.len())].copy_from_slice(chunk);
+
+            address += chunk.len();
+            bytes = remaining;
+        }
+
+        Ok(())
+    }
+
+    /// Reads `buf.len()` bytes from `address` through the page tables, rather than by dereferencing it, so an unmapped
+    /// (or kernel) address fails instead of faulting. Pages which aren't yet mapped aren't demand mapped.
+    pub fn read_mapped(&self, address: Address<Virtual>, mut buf: &mut [u8]) -> Result<()> {
+        let mut address = address.get();
+
+        while !buf.is_empty() {
+            let page = Address::<Page>::new_truncate(address);
+            let attributes = self.get_flags(page)?;
+            if !attributes.contains(TableEntryFlags::PRESENT | TableEntryFlags::USER) {
+                return Err(Error::NotMapped { addr: page.get() });
+            }
+
+            let frame = self.mapper.get_mapped_to(page).ok_or(Error::NotMapped { addr: page.get() })?;
+            let offset = address & libsys::page_mask();
+            let len = buf.len().min(page_size() - offset);
+            let (chunk, remaining) = core::mem::take(&mut buf).split_at_mut(len);
+
+            let frame_bytes = HHDM.frames(frame, NonZeroUsize::MIN).ok_or(Error::NotMapped { addr: page.get() })?;
+            // Safety: The frame is mapped by the address space (so allocated), and can't overlap the kernel's buffer.
+            chunk.copy_from_slice(unsafe { &frame_bytes.as_ref()[offset..(offset + chunk.len())] });
+
+            address += chunk.len();
+            buf = remaining;
+        }
+
+        Ok(())
+    }
 
-            index += 1;
+    /// Physical address `address` is mapped to, if its page is present.
+    pub fn translate(&self, address: Address<Virtual>) -> Option<Address<Physical>> {
+        let frame = self.mapper.get_mapped_to(Address::new_truncate(address.get()))?;
+        Address::new(frame.get().get() + (address.get() & libsys::page_mask()))
+    }
+
+    /// Frame holding the root page table, which identifies the address space.
+    #[inline]
+    pub const fn root_frame(&self) -> Address<Frame> {
+        self.mapper.root_frame()
+    }
 
-            ControlFlow::Continue(())
-        });
+    /// Regions of the address space, in ascending order of address.
+    pub fn regions(&self) -> impl Iterator<Item = &Region> {
+        self.regions.values()
+    }
+
+    /// Lists the regions of the address space, one per line (in the style of `/proc/<pid>/maps`).
+    pub fn maps(&self) -> Maps<'_> {
+        Maps(self)
+    }
 
-        match run.cmp(&page_count.get()) {
-            core::cmp::Ordering::Equal => {
-                let address = Address::<Page>::new(index << libsys::page_shift().get()).unwrap();
-                let flags = TableEntryFlags::PRESENT | TableEntryFlags::USER | TableEntryFlags::from(permissions);
+    /// Maps `frames` to consecutive pages in the device mapping region, without taking ownership of them. The mapping
+    /// may never be made more permissive than `permissions` (see [`Region::permits`]).
+    ///
+    /// #### Remark
+    ///
+    /// The frames must outlive the mapping, i.e. by belonging to a device.
+    pub fn map_frames(
+        &mut self,
+        frames: &[Address<Frame>],
+        permissions: MmapPermissions,
+        name: impl Into<Cow<'static, str>>,
+    ) -> Result<NonNull<[u8]>> {
+        let page_count = frames.len();
+        if page_count == 0 {
+            return Err(Error::InvalidAddress);
+        }
+
+        // Device mappings are few, so a linear probe for the first free run is sufficient.
+        let mut start_index = Address::<Page>::new_truncate(DEVICE_MAPPINGS_BASE).index();
+        let mut run = 0;
+        while run < page_count {
+            let page = Address::from_index(start_index + run).ok_or(Error::AllocError)?;
 
-                unsafe { self.invoke_mapper(address, page_count, flags) }
+            // Regions which aren't mapped yet (i.e. guard pages, or reservations) are as taken as those which are.
+            if self.mapper.is_mapped(page, None) || self.region(page).is_some() {
+                start_index += run + 1;
+                run = 0;
+            } else {
+                run += 1;
             }
-            core::cmp::Ordering::Less => Err(Error::AllocError),
-            core::cmp::Ordering::Greater => unreachable!(),
         }
+
+        let flags = TableEntryFlags::PRESENT | TableEntryFlags::USER | TableEntryFlags::from(permissions);
+        for (offset, frame) in frames.iter().enumerate() {
+            let page = Address::from_index(start_index + offset).unwrap();
+            self.mapper.map(page, TableDepth::min(), *frame, false, flags)?;
+            self.resident_pages += 1;
+        }
+
+        let address = Address::<Page>::from_index(start_index).unwrap();
+        let mut region = Region::new(
+            address,
+            NonZeroUsize::new(page_count).unwrap(),
+            permissions,
+            Backing::Device,
+            Some(name.into()),
+        );
+        // Device memory is shared with the kernel (or the device itself), so it's never made executable.
+        region.was_writable = true;
+        region.max_permissions = Some(permissions);
+        self.insert_region(region);
+
+        Ok(NonNull::slice_from_raw_parts(NonNull::new(address.as_ptr()).unwrap(), page_count * page_size()))
     }
 
     #[cfg_attr(debug_assertions, inline(never))]
@@ -163,7 +769,7 @@ impl AddressSpace {
         (0..mapping_size)
             .step_by(page_size())
             .map(|offset| Address::new_truncate(address.get().get() + offset))
-            .try_for_each(|offset_page| self.0.auto_map(offset_page, flags))
+            .try_for_each(|offset_page| self.mapper.auto_map(offset_page, flags).map(|()| self.resident_pages += 1))
             .map_err(Error::from)?;
 
         Ok(NonNull::slice_from_raw_parts(NonNull::new(address.as_ptr()).unwrap(), mapping_size))
@@ -180,32 +786,95 @@ impl AddressSpace {
             let offset_address =
                 Address::from_index(offset_index).ok_or(Error::AddressIndexOverrun { index: offset_index })?;
 
-            self.0
+            self.mapper
                 .set_page_attributes(offset_address, None, flags, paging::FlagsModify::Set)
                 .map_err(|err| Error::Paging { err })?;
         }
 
+        self.shootdown(address, page_count.get());
+
         Ok(())
     }
 
     pub fn get_flags(&self, address: Address<Page>) -> Result<TableEntryFlags> {
-        self.0.get_page_attributes(address).ok_or(Error::NotMapped { addr: address.get() })
+        self.mapper.get_page_attributes(address).ok_or(Error::NotMapped { addr: address.get() })
+    }
+
+    /// Number of pages mapped into the address space.
+    #[inline]
+    pub const fn resident_pages(&self) -> usize {
+        self.resident_pages
+    }
+
+    /// Number of pages covered by regions, including those not yet mapped (i.e. by demand mapping).
+    #[inline]
+    pub const fn virtual_pages(&self) -> usize {
+        self.virtual_pages
+    }
+
+    /// Invalidates `page_count` changed pages from `start` in the TLBs of the other cores with the address space
+    /// loaded (see [`crate::mem::tlb`]).
+    fn shootdown(&self, start: Address<Page>, page_count: usize) {
+        crate::mem::tlb::shootdown(Some(self.mapper.root_frame()), start, page_count);
     }
 
     pub fn is_mmapped(&self, address: Address<Page>) -> bool {
-        self.0.is_mapped(address, None)
+        self.mapper.is_mapped(address, None)
     }
 
     /// ### Safety
     ///
     /// Caller must ensure that switching the currently active address space will not cause undefined behaviour.
     pub unsafe fn swap_into(&self) {
-        self.0.swap_into();
+        self.mapper.swap_into();
+    }
+}
+
+pub struct Maps<'a>(&'a AddressSpace);
+
+impl core::fmt::Display for Maps<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        for region in self.0.regions() {
+            let permissions = match region.permissions {
+                MmapPermissions::ReadExecute => "r-x",
+                MmapPermissions::ReadWrite => "rw-",
+                MmapPermissions::ReadOnly => "r--",
+            };
+            let end = region.end_index() * page_size();
+
+            writeln!(
+                f,
+                "{:016x}-{:016x} {} {}",
+                region.start.get().get(),
+                end,
+                permissions,
+                region.name.as_deref().unwrap_or("[anon]")
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for AddressSpace {
+    /// Releases the frames of every region, then the page tables of the address space.
+    fn drop(&mut self) {
+        debug_assert!(!self.is_current(), "cannot drop the active address space");
+
+        let regions = self.regions.values().map(|region| (region.start, region.page_count)).collect::<Vec<_>>();
+        for (start, page_count) in regions {
+            if let Err(err) = self.munmap(start, page_count) {
+                warn!("Failed to release region at {:X?}: {:?}", start, err);
+            }
+        }
+
+        // Safety: The address space isn't active, and its regions (so every userspace page) have been unmapped.
+        unsafe { self.mapper.free_user_tables() };
     }
 }
 
 impl core::fmt::Debug for AddressSpace {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        f.debug_tuple("AddressSpace").field(&self.0.view_page_table().as_ptr()).finish()
+        f.debug_tuple("AddressSpace").field(&self.mapper.view_page_table().as_ptr()).finish()
     }
 }
@@ -0,0 +1,109 @@
+//! Backtraces of crashed userspace tasks, walked from the kernel along their frame pointer chains.
+//!
+//! Frames are read through the task's page tables (see [`super::AddressSpace::read_mapped`]), so a corrupt chain
+//! ends the walk rather than faulting, and only frames within the stack region the crash occurred on are followed.
+//! Return addresses are reported relative to the task's load offset as well, so they can be symbolized offline
+//! against its ELF.
+
+use super::{Registers, State, Task};
+use alloc::vec::Vec;
+use libsys::{Address, Virtual};
+
+/// Frames after which the walk stops, in case a chain loops back on itself without descending.
+const MAX_FRAMES: usize = 64;
+
+/// Offsets (from a frame pointer) of the caller's frame pointer and the return address.
+#[cfg(target_arch = "x86_64")]
+const FRAME_LAYOUT: (isize, isize) = (0, 8);
+#[cfg(target_arch = "riscv64")]
+const FRAME_LAYOUT: (isize, isize) = (-16, -8);
+
+fn frame_ptr(regs: &Registers) -> usize {
+    #[cfg(target_arch = "x86_64")]
+    {
+        regs.rbp
+    }
+
+    #[cfg(target_arch = "riscv64")]
+    {
+        regs.s0
+    }
+}
+
+fn read_usize(task: &Task, address: usize) -> Option<usize> {
+    let mut bytes = [0u8; core::mem::size_of::<usize>()];
+    task.process().address_space().read_mapped(Address::new(address)?, &mut bytes).ok()?;
+
+    Some(usize::from_ne_bytes(bytes))
+}
+
+/// Walks the stack of `task`, which crashed in the context `state` and `regs`, returning the faulting instruction
+/// pointer followed by the return address of each frame.
+pub fn walk(task: &Task, state: &State, regs: &Registers) -> Vec<Address<Virtual>> {
+    let mut addresses = alloc::vec![state.ip];
+
+    // The stack pointer may lie in the guard below the stack (i.e. on overflow), so the stack is found from the frame
+    // pointer. Kernel tasks' stacks aren't regions of their address space, so only userspace stacks are walked.
+    let mut frame = frame_ptr(regs);
+    let Some(stack_range) = task
+        .process()
+        .address_space()
+        .region(Address::new_truncate(frame))
+        .filter(|region| region.backing != super::Backing::Guard)
+        .map(|stack| {
+            stack.start.get().get()..(stack.start.get().get() + (stack.page_count.get() * libsys::page_size()))
+        })
+    else {
+        return addresses;
+    };
+
+    let (prev_offset, return_offset) = FRAME_LAYOUT;
+    while addresses.len() < MAX_FRAMES {
+        let (Some(prev_address), Some(return_address)) =
+            (frame.checked_add_signed(prev_offset), frame.checked_add_signed(return_offset))
+        else {
+            break;
+        };
+
+        let is_in_stack = |address: usize| {
+            stack_range.contains(&address) && stack_range.contains(&(address + (core::mem::size_of::<usize>() - 1)))
+        };
+        if !is_in_stack(prev_address) || !is_in_stack(return_address) || (frame % core::mem::size_of::<usize>()) != 0 {
+            break;
+        }
+
+        let (Some(prev_frame), Some(return_ip)) = (read_usize(task, prev_address), read_usize(task, return_address))
+        else {
+            break;
+        };
+
+        // The outermost frame has a null return address (or frame pointer).
+        let Some(return_ip) = Address::new(return_ip).filter(|ip| ip.get() != 0) else { break };
+        addresses.push(return_ip);
+
+        // Callers' frames lie above their callees', so a chain which doesn't ascend is corrupt.
+        if prev_frame <= frame {
+            break;
+        }
+        frame = prev_frame;
+    }
+
+    addresses
+}
+
+/// Logs the backtrace of `task`, which crashed in the context `state` and `regs`.
+pub fn report(task: &Task, state: &State, regs: &Registers) {
+    let load_offset = task.process().load_offset();
+
+    error!("Backtrace of task {:?} ({}):", task.id(), task.alias());
+    for (depth, address) in walk(task, state, regs).into_iter().enumerate() {
+        let address = address.get();
+
+        match address.checked_sub(load_offset) {
+            Some(elf_address) if load_offset > 0 => {
+                error!("  #{:<3} {:#018X} (elf {:#X})", depth, address, elf_address);
+            }
+            _ => error!("  #{:<3} {:#018X}", depth, address),
+        }
+    }
+}
@@ -57,4 +57,66 @@ mod context_impl {
     }
 }
 
+#[cfg(target_arch = "riscv64")]
+mod context_impl {
+    use libsys::{Address, Virtual};
+
+    use crate::arch::rv64::registers::SSTATUS;
+
+    /// General-purpose registers, other than `zero` and `sp` (which is held in [`State`]), in register order.
+    #[repr(C)]
+    #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+    pub struct Registers {
+        pub ra: usize,
+        pub gp: usize,
+        pub tp: usize,
+        pub t0: usize,
+        pub t1: usize,
+        pub t2: usize,
+        pub s0: usize,
+        pub s1: usize,
+        pub a0: usize,
+        pub a1: usize,
+        pub a2: usize,
+        pub a3: usize,
+        pub a4: usize,
+        pub a5: usize,
+        pub a6: usize,
+        pub a7: usize,
+        pub s2: usize,
+        pub s3: usize,
+        pub s4: usize,
+        pub s5: usize,
+        pub s6: usize,
+        pub s7: usize,
+        pub s8: usize,
+        pub s9: usize,
+        pub s10: usize,
+        pub s11: usize,
+        pub t3: usize,
+        pub t4: usize,
+        pub t5: usize,
+        pub t6: usize,
+    }
+
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct State {
+        /// Address `sret` returns to (`sepc`).
+        pub ip: Address<Virtual>,
+        pub sp: Address<Virtual>,
+        pub sstatus: SSTATUS,
+    }
+
+    impl State {
+        pub fn kernel(ip: Address<Virtual>, sp: Address<Virtual>) -> Self {
+            Self { ip, sp, sstatus: SSTATUS::SPP | SSTATUS::SPIE }
+        }
+
+        pub fn user(ip: Address<Virtual>, sp: Address<Virtual>) -> Self {
+            Self { ip, sp, sstatus: SSTATUS::SPIE }
+        }
+    }
+}
+
 pub use context_impl::*;
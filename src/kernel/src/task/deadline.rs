@@ -0,0 +1,114 @@
+//! Earliest-deadline-first scheduling class for periodic real-time tasks.
+//!
+//! Deadline tasks declare a `(period, budget)` pair in scheduler ticks, and are
+//! guaranteed `budget` ticks of execution within every `period`. They always take
+//! precedence over tasks in the fair class. Reservations are made per-core, so a
+//! deadline task is pinned to the core it was admitted on.
+
+use core::num::NonZeroU16;
+
+crate::error_impl! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Error {
+        /// The budget exceeds the period.
+        InvalidParameters => None,
+        /// Admitting the task would reserve more than [`MAX_UTILIZATION`] of its core.
+        OverSubscribed => None
+    }
+}
+
+/// Utilization is expressed in parts-per-`UTILIZATION_SCALE`, to avoid floating point arithmetic.
+pub const UTILIZATION_SCALE: u32 = 1_000_000;
+
+/// Maximum share of a core that can be reserved by deadline tasks. The remainder is
+/// left for the fair class, so it can't be starved outright.
+pub const MAX_UTILIZATION: u32 = (UTILIZATION_SCALE / 20) * 19;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Parameters {
+    period: NonZeroU16,
+    budget: NonZeroU16,
+}
+
+impl Parameters {
+    pub fn new(period: NonZeroU16, budget: NonZeroU16) -> Result<Self> {
+        if budget <= period {
+            Ok(Self { period, budget })
+        } else {
+            Err(Error::InvalidParameters)
+        }
+    }
+
+    #[inline]
+    pub const fn period(self) -> NonZeroU16 {
+        self.period
+    }
+
+    #[inline]
+    pub const fn budget(self) -> NonZeroU16 {
+        self.budget
+    }
+
+    /// Share of a core this task requires, in parts-per-[`UTILIZATION_SCALE`].
+    pub fn utilization(self) -> u32 {
+        (u32::from(self.budget.get()) * UTILIZATION_SCALE) / u32::from(self.period.get())
+    }
+}
+
+/// Runtime accounting for an admitted deadline task.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reservation {
+    params: Parameters,
+    deadline: u64,
+    remaining: u16,
+}
+
+impl Reservation {
+    pub fn new(params: Parameters, now: u64) -> Self {
+        Self { params, deadline: now + u64::from(params.period().get()), remaining: params.budget().get() }
+    }
+
+    #[inline]
+    pub const fn params(&self) -> Parameters {
+        self.params
+    }
+
+    /// Absolute deadline of the current period, in scheduler ticks.
+    #[inline]
+    pub const fn deadline(&self) -> u64 {
+        self.deadline
+    }
+
+    /// Budget remaining in the current period, in scheduler ticks.
+    #[inline]
+    pub const fn remaining(&self) -> u16 {
+        self.remaining
+    }
+
+    #[inline]
+    pub const fn is_runnable(&self) -> bool {
+        self.remaining > 0
+    }
+
+    /// Number of ticks until the next period begins.
+    #[inline]
+    pub const fn until_replenish(&self, now: u64) -> u64 {
+        self.deadline.saturating_sub(now)
+    }
+
+    /// Consumes `ticks` from the remaining budget.
+    pub fn charge(&mut self, ticks: u16) {
+        self.remaining = self.remaining.saturating_sub(ticks);
+    }
+
+    /// Moves the reservation into the current period, refilling its budget, if its deadline has passed.
+    pub fn replenish(&mut self, now: u64) {
+        if now >= self.deadline {
+            let period = u64::from(self.params.period().get());
+            let elapsed_periods = ((now - self.deadline) / period) + 1;
+
+            self.deadline += elapsed_periods * period;
+            self.remaining = self.params.budget().get();
+        }
+    }
+}
@@ -0,0 +1,81 @@
+//! Tasks blocked on futexes (see [`libsys::syscall::futex`]).
+//!
+//! Waiters are queued by [`Key`], which names a futex in one of two ways:
+//! - A word of shared memory (i.e. a mapped file or device) is named by its physical address, so tasks which map its
+//!   frame wait on the same futex, whichever address space (or address) they map it at.
+//! - A word of private memory is named by its virtual address within the address space it was waited on in, as its
+//!   frame can change under it: a word left copy-on-write after a fork is moved to a new frame by the first write to
+//!   it, which is likely the store that precedes a wake. The futexes of separate address spaces never collide, even
+//!   where they share a frame.
+
+use super::{quota::Resource, AddressSpace, Backing, Task};
+use crate::interrupts::InterruptCell;
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    vec::Vec,
+};
+use libsys::{syscall::Success, Address, Virtual};
+use spin::Mutex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Key {
+    /// Word of shared memory, by its physical address.
+    Shared { address: usize },
+    /// Word of private memory, by the index of its address space's root page table frame, and its virtual address.
+    Private { root: usize, address: usize },
+}
+
+impl Key {
+    /// Names the futex whose word is at `address` in `address_space`, or returns `None` if its shared memory isn't
+    /// mapped.
+    pub fn new(address_space: &AddressSpace, address: Address<Virtual>) -> Option<Self> {
+        let region = address_space.region(Address::new_truncate(address.get()));
+        if region.is_some_and(|region| region.backing == Backing::Device) {
+            address_space.translate(address).map(|address| Self::Shared { address: address.get() })
+        } else {
+            Some(Self::Private { root: address_space.root_frame().index(), address: address.get() })
+        }
+    }
+}
+
+/// Tasks blocked on each futex, in the order they began waiting.
+pub struct Queues(BTreeMap<Key, VecDeque<Task>>);
+
+impl Queues {
+    /// Queues `waiter` on the futex `key`, charging it against its process's quota of futex waiters until it's woken.
+    pub fn push(&mut self, key: Key, waiter: Task) {
+        waiter.process().usage().charge(Resource::FutexWaiters);
+        self.0.entry(key).or_default().push_back(waiter);
+    }
+}
+
+static QUEUES: InterruptCell<Mutex<Queues>> = InterruptCell::new(Mutex::new(Queues(BTreeMap::new())));
+
+/// Locks the futex queues for `func`.
+///
+/// #### Remark
+///
+/// A waiter must compare its futex word within `func`, so that it can't miss a [`wake`] issued after the word changes.
+pub fn with_queues<T>(func: impl FnOnce(&mut Queues) -> T) -> T {
+    QUEUES.with(|queues| func(&mut queues.lock()))
+}
+
+/// Takes at most `count` of the tasks waiting on the futex `key`, in the order they began waiting, with success written
+/// as the result of their waits.
+pub fn wake(key: Key, count: usize) -> Vec<Task> {
+    with_queues(|queues| {
+        let Some(waiters) = queues.0.get_mut(&key) else { return Vec::new() };
+
+        let mut woken: Vec<Task> = waiters.drain(..count.min(waiters.len())).collect();
+        if waiters.is_empty() {
+            queues.0.remove(&key);
+        }
+
+        for waiter in &mut woken {
+            waiter.process().usage().release(Resource::FutexWaiters);
+            crate::interrupts::traps::write_result(&mut waiter.context.1, Ok(Success::Ok));
+        }
+
+        woken
+    })
+}
@@ -0,0 +1,237 @@
+//! Resource groups, between which the fair class shares each core in proportion to their shares (see
+//! [`libsys::syscall::group`]).
+//!
+//! Groups are flat: every task is in exactly one, starting in [`ROOT`]. Each group's virtual runtime is the ticks its
+//! tasks have run, scaled down by its shares, and of the fair tasks ready at the highest priority, a task of the group
+//! furthest behind runs next. A group which was idle resumes no further behind than the groups already running, so it
+//! can't bank time while idle and then monopolize the core.
+
+use super::Task;
+use crate::interrupts::InterruptCell;
+use alloc::{collections::BTreeMap, string::String, vec::Vec};
+use core::fmt::Write;
+use libsys::syscall::group::{Info, DEFAULT_SHARES, MAX_SHARES, MIN_SHARES};
+use spin::Mutex;
+
+pub use libsys::syscall::group::ROOT;
+
+crate::error_impl! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Error {
+        /// The shares are outside of `MIN_SHARES..=MAX_SHARES`.
+        InvalidShares { shares: u32 } => None,
+        NoSuchGroup { group: u32 } => None,
+        /// The group still has tasks in it.
+        Busy => None,
+        /// The root group can't be destroyed.
+        Root => None
+    }
+}
+
+impl From<Error> for libsys::syscall::Error {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::InvalidShares { .. } => Self::InvalidArgument,
+            Error::NoSuchGroup { .. } => Self::NotFound,
+            Error::Busy => Self::Busy,
+            Error::Root => Self::PermissionDenied,
+        }
+    }
+}
+
+/// Ticks of virtual runtime are scaled up by this, so a group with [`MAX_SHARES`] still advances.
+const VRUNTIME_SCALE: u64 = 1 << 20;
+
+struct Group {
+    shares: u32,
+    /// Ticks run, each scaled by `VRUNTIME_SCALE / shares`.
+    vruntime: u64,
+    /// Scheduler ticks the group's tasks have run for.
+    cpu_ticks: u64,
+    tasks: usize,
+}
+
+impl Group {
+    const fn new(shares: u32) -> Self {
+        Self { shares, vruntime: 0, cpu_ticks: 0, tasks: 0 }
+    }
+}
+
+struct Groups {
+    root: Group,
+    groups: BTreeMap<u32, Group>,
+    next_id: u32,
+    /// Virtual runtime of the group last selected, below which no group falls once it runs again.
+    floor: u64,
+}
+
+impl Groups {
+    fn get(&self, id: u32) -> Result<&Group> {
+        if id == ROOT {
+            Ok(&self.root)
+        } else {
+            self.groups.get(&id).ok_or(Error::NoSuchGroup { group: id })
+        }
+    }
+
+    fn get_mut(&mut self, id: u32) -> Result<&mut Group> {
+        if id == ROOT {
+            Ok(&mut self.root)
+        } else {
+            self.groups.get_mut(&id).ok_or(Error::NoSuchGroup { group: id })
+        }
+    }
+
+    fn vruntime(&self, id: u32) -> u64 {
+        self.get(id).map_or(self.floor, |group| group.vruntime)
+    }
+}
+
+static GROUPS: InterruptCell<Mutex<Groups>> = InterruptCell::new(Mutex::new(Groups {
+    root: Group::new(DEFAULT_SHARES),
+    groups: BTreeMap::new(),
+    next_id: ROOT + 1,
+    floor: 0,
+}));
+
+fn with_groups<T>(func: impl FnOnce(&mut Groups) -> T) -> T {
+    GROUPS.with(|groups| func(&mut groups.lock()))
+}
+
+fn check_shares(shares: u32) -> Result<()> {
+    if (MIN_SHARES..=MAX_SHARES).contains(&shares) {
+        Ok(())
+    } else {
+        Err(Error::InvalidShares { shares })
+    }
+}
+
+/// Creates an empty group with `shares`, returning its ID.
+pub fn create(shares: u32) -> Result<u32> {
+    check_shares(shares)?;
+
+    with_groups(|groups| {
+        let id = groups.next_id;
+        groups.next_id += 1;
+
+        // Starts level with the groups already running, rather than owed all the time they've run for.
+        let mut group = Group::new(shares);
+        group.vruntime = groups.floor;
+        groups.groups.insert(id, group);
+
+        Ok(id)
+    })
+}
+
+/// Destroys the empty group `id`.
+pub fn destroy(id: u32) -> Result<()> {
+    with_groups(|groups| {
+        if id == ROOT {
+            return Err(Error::Root);
+        } else if groups.get(id)?.tasks > 0 {
+            return Err(Error::Busy);
+        }
+
+        groups.groups.remove(&id);
+
+        Ok(())
+    })
+}
+
+pub fn set_shares(id: u32, shares: u32) -> Result<()> {
+    check_shares(shares)?;
+
+    with_groups(|groups| {
+        groups.get_mut(id)?.shares = shares;
+
+        Ok(())
+    })
+}
+
+/// Moves the task with `info` from its group into the group `id`.
+pub fn attach(info: &super::registry::Info, id: u32) -> Result<()> {
+    with_groups(|groups| {
+        groups.get(id)?;
+
+        if let Ok(group) = groups.get_mut(info.group()) {
+            group.tasks -= 1;
+        }
+        groups.get_mut(id)?.tasks += 1;
+        info.set_group(id);
+
+        Ok(())
+    })
+}
+
+/// Counts a newly registered task in the root group.
+pub(super) fn join_root() {
+    with_groups(|groups| groups.root.tasks += 1);
+}
+
+/// Removes the task with `info` from its group's count, as the task is dropped.
+pub(super) fn leave(info: &super::registry::Info) {
+    with_groups(|groups| {
+        if let Ok(group) = groups.get_mut(info.group()) {
+            group.tasks -= 1;
+        }
+    });
+}
+
+/// Charges `ticks` of CPU time to the group `id`.
+pub(super) fn charge(id: u32, ticks: u16) {
+    with_groups(|groups| {
+        let floor = groups.floor;
+        let Ok(group) = groups.get_mut(id) else { return };
+
+        group.cpu_ticks += u64::from(ticks);
+        group.vruntime = group.vruntime.max(floor) + ((u64::from(ticks) * VRUNTIME_SCALE) / u64::from(group.shares));
+    });
+}
+
+/// Returns the index of the first task in `tasks` of the group furthest behind its share of the core.
+pub(super) fn select<'a>(tasks: impl Iterator<Item = &'a Task>) -> Option<usize> {
+    with_groups(|groups| {
+        let (index, vruntime) =
+            tasks.map(|task| groups.vruntime(task.info().group())).enumerate().min_by_key(|(_, vruntime)| *vruntime)?;
+        groups.floor = groups.floor.max(vruntime);
+
+        Some(index)
+    })
+}
+
+/// Snapshot of the group `id`, as reported to userspace.
+pub fn snapshot(id: u32) -> Result<Info> {
+    with_groups(|groups| {
+        let group = groups.get(id)?;
+
+        Ok(Info {
+            group: id,
+            shares: group.shares,
+            tasks: group.tasks as u64,
+            // The scheduler ticks at 1000Hz, so ticks are milliseconds.
+            cpu_time_ms: group.cpu_ticks,
+        })
+    })
+}
+
+/// Publishes the groups' shares and usage to sysfs (`sched/groups`), listed as they're read.
+pub fn publish() {
+    crate::sysfs::set(
+        "sched",
+        "groups",
+        crate::sysfs::Value::Dynamic(alloc::boxed::Box::new(|| {
+            let ids =
+                with_groups(|groups| core::iter::once(ROOT).chain(groups.groups.keys().copied()).collect::<Vec<_>>());
+
+            ids.into_iter().filter_map(|id| snapshot(id).ok()).fold(String::new(), |mut string, info| {
+                writeln!(
+                    string,
+                    "group {:<4} shares={:<6} tasks={:<4} cpu_ms={}",
+                    info.group, info.shares, info.tasks, info.cpu_time_ms
+                )
+                .unwrap();
+                string
+            })
+        })),
+    );
+}
@@ -0,0 +1,292 @@
+//! Loading of ELF executables into new tasks.
+//!
+//! A static executable is loaded itself, with its relocations applied by the kernel as its pages are demand mapped.
+//! An executable which names an interpreter (`PT_INTERP`) is instead handed to it: the interpreter is loaded in its
+//! place, and entered with the executable open as a descriptor in its first argument register. The interpreter then
+//! maps the executable and the libraries it needs (`DT_NEEDED`) through the [`libsys::syscall::dl`] calls.
+//!
+//! Either way, the task starts as the System V ABI describes: its stack pointer addresses `argc`, followed by the
+//! `argv` and `envp` pointer arrays (each terminated by null) and the auxiliary vector, with the strings above them.
+
+use super::{AddressSpace, ElfData, ElfRela, Priority, Task, MIN_LOAD_OFFSET, STACK_SIZE};
+use alloc::{boxed::Box, format, string::String, vec::Vec};
+use elf::{endian::AnyEndian, segment::ProgramHeader, ElfBytes};
+use libsys::{page_size, syscall::fs::OpenFlags, Address};
+
+crate::error_impl! {
+    #[derive(Debug)]
+    pub enum Error {
+        Parse { err: elf::ParseError } => None,
+        NoSegments => None,
+        /// A segment is both writable and executable.
+        WxViolation => None,
+        /// The executable names an interpreter, but wasn't loaded from a file which the interpreter could open.
+        NotAFile => None,
+        /// The interpreter's path isn't valid UTF-8.
+        InvalidInterpreter => None,
+        /// The interpreter itself names an interpreter.
+        NestedInterpreter => None,
+        /// The executable needs a library, but names no interpreter to load it.
+        NoInterpreter { library: String } => None,
+        UnsupportedRelocation { r_type: u32 } => None,
+        UndefinedSymbol { name: String } => None,
+        InvalidAddress { address: u64 } => None,
+        /// The arguments, environment, and auxiliary vector don't fit on the task's stack.
+        ArgumentsTooLong => None,
+        AddressSpace { err: super::AddressSpaceError } => Some(err),
+        Vfs { err: crate::vfs::Error } => Some(err)
+    }
+}
+
+// Auxiliary vector entry types.
+const AT_NULL: usize = 0;
+const AT_EXECFD: usize = 2;
+const AT_PHDR: usize = 3;
+const AT_PHENT: usize = 4;
+const AT_PHNUM: usize = 5;
+const AT_PAGESZ: usize = 6;
+const AT_BASE: usize = 7;
+const AT_ENTRY: usize = 9;
+const AT_RANDOM: usize = 25;
+
+/// Loads the executable at `path`, or its interpreter if it names one, to start with `argv` and `envp`.
+pub fn load_file(path: &str, argv: &[&str], envp: &[&str]) -> Result<Task> {
+    let bytes = read_file(path)?;
+    let elf = ElfBytes::<AnyEndian>::minimal_parse(&bytes).map_err(|err| Error::Parse { err })?;
+
+    let Some(interpreter_path) = interpreter(&elf)? else {
+        let mut task = load(&elf, ElfData::File(String::from(path)))?;
+        let auxv = image_auxv(&task);
+        push_startup(&mut task, argv, envp, &auxv)?;
+
+        return Ok(task);
+    };
+
+    debug!("Loading {} through its interpreter: {}", path, interpreter_path);
+
+    let executable = crate::vfs::open(crate::vfs::ROOT, path, OpenFlags::READ, crate::vfs::Owner::ROOT)
+        .map_err(|err| Error::Vfs { err })?;
+    let interpreter_bytes = read_file(interpreter_path)?;
+    let interpreter_elf =
+        ElfBytes::<AnyEndian>::minimal_parse(&interpreter_bytes).map_err(|err| Error::Parse { err })?;
+    if interpreter(&interpreter_elf)?.is_some() {
+        return Err(Error::NestedInterpreter);
+    }
+
+    let mut task = load(&interpreter_elf, ElfData::File(String::from(interpreter_path)))?;
+    let fd = task.process().files().insert(executable);
+
+    #[cfg(target_arch = "x86_64")]
+    {
+        task.context_mut().1.rdi = fd;
+    }
+    #[cfg(target_arch = "riscv64")]
+    {
+        task.context_mut().1.a0 = fd;
+    }
+
+    // The executable isn't mapped yet, so only the interpreter (and where to find the executable) can be described.
+    let auxv = [(AT_EXECFD, fd), (AT_BASE, task.process().load_offset())];
+    push_startup(&mut task, argv, envp, &auxv)?;
+
+    Ok(task)
+}
+
+/// Loads the executable `bytes`, demand mapping its segments from `data` (which holds the same executable), to start
+/// with `argv` and `envp`.
+pub fn load_bytes(bytes: &[u8], data: ElfData, argv: &[&str], envp: &[&str]) -> Result<Task> {
+    let elf = ElfBytes::<AnyEndian>::minimal_parse(bytes).map_err(|err| Error::Parse { err })?;
+    if interpreter(&elf)?.is_some() {
+        return Err(Error::NotAFile);
+    }
+
+    let mut task = load(&elf, data)?;
+    let auxv = image_auxv(&task);
+    push_startup(&mut task, argv, envp, &auxv)?;
+
+    Ok(task)
+}
+
+fn read_file(path: &str) -> Result<Vec<u8>> {
+    crate::vfs::open(crate::vfs::ROOT, path, OpenFlags::READ, crate::vfs::Owner::ROOT)
+        .and_then(|file| file.read_to_end())
+        .map_err(|err| Error::Vfs { err })
+}
+
+/// Path of the interpreter the executable names, if any.
+fn interpreter<'a>(elf: &ElfBytes<'a, AnyEndian>) -> Result<Option<&'a str>> {
+    let Some(segment) =
+        elf.segments().and_then(|segments| segments.iter().find(|phdr| phdr.p_type == elf::abi::PT_INTERP))
+    else {
+        return Ok(None);
+    };
+
+    let path = elf.segment_data(&segment).map_err(|err| Error::Parse { err })?;
+    let len = path.iter().position(|&byte| byte == 0).unwrap_or(path.len());
+    let path = core::str::from_utf8(&path[..len]).map_err(|_| Error::InvalidInterpreter)?;
+
+    Ok(Some(path))
+}
+
+fn load(elf: &ElfBytes<AnyEndian>, data: ElfData) -> Result<Task> {
+    let segments: Box<[ProgramHeader]> = elf.segments().ok_or(Error::NoSegments)?.iter().collect();
+    if segments.iter().any(|phdr| {
+        phdr.p_type == elf::abi::PT_LOAD && (phdr.p_flags & elf::abi::PF_W) > 0 && (phdr.p_flags & elf::abi::PF_X) > 0
+    }) {
+        return Err(Error::WxViolation);
+    }
+
+    if let Some(library) = needed(elf)?.into_iter().next() {
+        return Err(Error::NoInterpreter { library: String::from(library) });
+    }
+
+    let load_offset = MIN_LOAD_OFFSET;
+    let relas = relocations(elf, load_offset)?;
+
+    Ok(Task::new(Priority::Normal, AddressSpace::new_userspace(), load_offset, elf.ehdr, segments, relas, data))
+}
+
+/// Libraries the executable's dynamic section (`PT_DYNAMIC`) says it needs.
+fn needed<'a>(elf: &ElfBytes<'a, AnyEndian>) -> Result<Vec<&'a str>> {
+    let Some(dynamic) = elf.dynamic().map_err(|err| Error::Parse { err })? else { return Ok(Vec::new()) };
+    let Some((_, strings)) = elf.dynamic_symbol_table().map_err(|err| Error::Parse { err })? else {
+        return Ok(Vec::new());
+    };
+
+    dynamic
+        .iter()
+        .filter(|entry| entry.d_tag == elf::abi::DT_NEEDED)
+        .map(|entry| {
+            let offset =
+                usize::try_from(entry.d_val()).map_err(|_| Error::InvalidAddress { address: entry.d_val() })?;
+            strings.get(offset).map_err(|err| Error::Parse { err })
+        })
+        .collect()
+}
+
+/// Collects the executable's relocations, resolved against its own symbols as loaded at `load_offset`.
+fn relocations(elf: &ElfBytes<AnyEndian>, load_offset: usize) -> Result<Vec<ElfRela>> {
+    // Relocations are found through the section headers, so a stripped executable can't be relocated.
+    let Ok((Some(shdrs), _)) = elf.section_headers_with_strtab() else { return Ok(Vec::new()) };
+    let symbols = elf.dynamic_symbol_table().map_err(|err| Error::Parse { err })?;
+
+    let resolve = |index: u32| -> Result<usize> {
+        let Some((symbols, strings)) = symbols.as_ref() else {
+            return Err(Error::UndefinedSymbol { name: format!("#{index}") });
+        };
+        let symbol = symbols.get(usize::try_from(index).unwrap()).map_err(|err| Error::Parse { err })?;
+        let value = usize::try_from(symbol.st_value).map_err(|_| Error::InvalidAddress { address: symbol.st_value })?;
+
+        if !symbol.is_undefined() {
+            Ok(if symbol.st_shndx == elf::abi::SHN_ABS { value } else { load_offset + value })
+        } else if symbol.st_bind() == elf::abi::STB_WEAK {
+            // An undefined weak symbol resolves to null.
+            Ok(0)
+        } else {
+            let name = strings.get(usize::try_from(symbol.st_name).unwrap()).unwrap_or("?");
+            Err(Error::UndefinedSymbol { name: String::from(name) })
+        }
+    };
+
+    let mut relas = Vec::new();
+    for shdr in shdrs.iter().filter(|shdr| shdr.sh_type == elf::abi::SHT_RELA) {
+        for rela in elf.section_data_as_relas(&shdr).map_err(|err| Error::Parse { err })? {
+            let addend = isize::try_from(rela.r_addend).unwrap();
+            let value = match rela.r_type {
+                elf::abi::R_X86_64_NONE => continue,
+                elf::abi::R_X86_64_RELATIVE => load_offset.wrapping_add_signed(addend),
+                elf::abi::R_X86_64_64 => resolve(rela.r_sym)?.wrapping_add_signed(addend),
+                elf::abi::R_X86_64_GLOB_DAT | elf::abi::R_X86_64_JUMP_SLOT => resolve(rela.r_sym)?,
+                r_type => return Err(Error::UnsupportedRelocation { r_type }),
+            };
+
+            let address = usize::try_from(rela.r_offset)
+                .ok()
+                .and_then(Address::new)
+                .ok_or(Error::InvalidAddress { address: rela.r_offset })?;
+            relas.push(ElfRela { address, value });
+        }
+    }
+
+    Ok(relas)
+}
+
+/// Auxiliary vector entries which describe the task's own image: where its program headers were loaded, and its entry.
+fn image_auxv(task: &Task) -> Vec<(usize, usize)> {
+    let process = task.process();
+    let Some(header) = process.elf_header() else { return Vec::new() };
+    let segments = process.elf_segments();
+    let load_offset = process.load_offset();
+
+    let mut auxv = Vec::new();
+
+    // Without a `PT_PHDR` segment, the program headers can still be found if a loaded segment covers them.
+    let phdr = segments.iter().find(|phdr| phdr.p_type == elf::abi::PT_PHDR).map(|phdr| phdr.p_vaddr).or_else(|| {
+        segments
+            .iter()
+            .filter(|phdr| phdr.p_type == elf::abi::PT_LOAD)
+            .find(|phdr| (phdr.p_offset..(phdr.p_offset + phdr.p_filesz)).contains(&header.e_phoff))
+            .map(|phdr| phdr.p_vaddr + (header.e_phoff - phdr.p_offset))
+    });
+    if let Some(phdr) = phdr.and_then(|phdr| usize::try_from(phdr).ok()) {
+        auxv.push((AT_PHDR, load_offset + phdr));
+    }
+
+    auxv.push((AT_PHENT, usize::from(header.e_phentsize)));
+    auxv.push((AT_PHNUM, usize::from(header.e_phnum)));
+    auxv.push((AT_ENTRY, load_offset + usize::try_from(header.e_entry).unwrap()));
+
+    auxv
+}
+
+/// Lays out `argv`, `envp`, and the auxiliary vector (with [`AT_PAGESZ`] and [`AT_RANDOM`] added to `auxv`) on the
+/// task's stack, and points its stack pointer at them.
+fn push_startup(task: &mut Task, argv: &[&str], envp: &[&str], auxv: &[(usize, usize)]) -> Result<()> {
+    const WORD_LEN: usize = core::mem::size_of::<usize>();
+
+    let top = task.context().0.sp.get();
+
+    // Strings sit at the top of the stack, beginning with the bytes `AT_RANDOM` points to.
+    let mut strings = Vec::new();
+    strings.extend(crate::rand::prng::next_u64().to_ne_bytes());
+    strings.extend(crate::rand::prng::next_u64().to_ne_bytes());
+
+    let strings_len = 16 + argv.iter().chain(envp).map(|str| str.len() + 1).sum::<usize>();
+    let strings_start = top.checked_sub(strings_len).ok_or(Error::ArgumentsTooLong)?;
+
+    let mut push_string = |str: &str| {
+        let address = strings_start + strings.len();
+        strings.extend(str.as_bytes());
+        strings.push(0);
+
+        address
+    };
+
+    let mut words = Vec::with_capacity(1 + argv.len() + 1 + envp.len() + 1 + ((auxv.len() + 3) * 2));
+    words.push(argv.len());
+    words.extend(argv.iter().map(|arg| push_string(arg)));
+    words.push(0);
+    words.extend(envp.iter().map(|var| push_string(var)));
+    words.push(0);
+    for &(key, value) in auxv {
+        words.extend([key, value]);
+    }
+    words.extend([AT_PAGESZ, page_size(), AT_RANDOM, strings_start, AT_NULL, 0]);
+
+    // The stack pointer must be 16-byte aligned at entry.
+    let sp = strings_start.checked_sub(words.len() * WORD_LEN).ok_or(Error::ArgumentsTooLong)? & !0xF;
+    if (top - sp) > STACK_SIZE.get() {
+        return Err(Error::ArgumentsTooLong);
+    }
+
+    let mut stack = Vec::with_capacity(top - sp);
+    stack.extend(words.iter().flat_map(|word| word.to_ne_bytes()));
+    stack.resize(strings_start - sp, 0);
+    stack.extend(strings);
+
+    let sp = Address::new(sp).unwrap();
+    task.process().address_space().write_mapped(sp, &stack).map_err(|err| Error::AddressSpace { err })?;
+    task.context_mut().0.sp = sp;
+
+    Ok(())
+}
@@ -5,18 +5,42 @@ mod scheduling;
 pub use scheduling::*;
 
 mod address_space;
+pub use address_space::Error as AddressSpaceError;
 pub use address_space::*;
 
-use alloc::{boxed::Box, string::String, vec::Vec};
+mod process;
+pub use process::Process;
+
+mod tid;
+pub use tid::Tid;
+
+mod wheel;
+
+pub mod backtrace;
+pub mod deadline;
+pub mod futex;
+pub mod group;
+pub mod loader;
+pub mod quota;
+pub mod registry;
+pub mod trace;
+pub mod wait;
+pub mod workqueue;
+
+use alloc::{boxed::Box, string::String, sync::Arc, vec::Vec};
 use bit_field::BitField;
 use core::num::NonZeroUsize;
 use elf::{endian::AnyEndian, file::FileHeader, segment::ProgramHeader};
 use libsys::{page_size, Address, Virtual};
 
 #[allow(clippy::cast_possible_truncation)]
-pub const STACK_SIZE: NonZeroUsize = NonZeroUsize::new((libsys::MIBIBYTE as usize) - page_size()).unwrap();
+/// Unmapped pages below the stack, so that overflowing it faults (see [`Backing::Guard`]).
+pub const STACK_GUARD_PAGES: NonZeroUsize = NonZeroUsize::MIN;
+/// The stack and its guard pages sit above the (unmapped) null page.
+pub const STACK_SIZE: NonZeroUsize =
+    NonZeroUsize::new((libsys::MIBIBYTE as usize) - ((1 + STACK_GUARD_PAGES.get()) * page_size())).unwrap();
 pub const STACK_PAGES: NonZeroUsize = NonZeroUsize::new(STACK_SIZE.get() / page_size()).unwrap();
-pub const STACK_START: NonZeroUsize = NonZeroUsize::new(page_size()).unwrap();
+pub const STACK_START: NonZeroUsize = NonZeroUsize::new((1 + STACK_GUARD_PAGES.get()) * page_size()).unwrap();
 pub const MIN_LOAD_OFFSET: usize = STACK_START.get() + STACK_SIZE.get();
 
 pub const PT_FLAG_EXEC_BIT: usize = 0;
@@ -36,7 +60,9 @@ crate::error_impl! {
     pub enum Error {
         AlreadyMapped => None,
         AddressUnderrun { addr: Address<Virtual> } => None,
-        UnhandledAddress { addr: Address<Virtual> } => None
+        UnhandledAddress { addr: Address<Virtual> } => None,
+        /// The executable backing the address couldn't be read.
+        ElfUnreadable { addr: Address<Virtual> } => None
     }
 }
 
@@ -51,6 +77,20 @@ pub enum Priority {
     Critical = 4,
 }
 
+impl From<libsys::syscall::task::Priority> for Priority {
+    fn from(priority: libsys::syscall::task::Priority) -> Self {
+        use libsys::syscall::task::Priority as Abi;
+
+        match priority {
+            Abi::Idle => Self::Idle,
+            Abi::Low => Self::Low,
+            Abi::Normal => Self::Normal,
+            Abi::High => Self::High,
+            Abi::Critical => Self::Critical,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct ElfRela {
     pub address: Address<Virtual>,
@@ -65,21 +105,22 @@ pub enum ElfData {
     File(String),
 }
 
+/// A thread of a [`Process`], and the unit of scheduling.
 pub struct Task {
-    id: uuid::Uuid,
-    priority: Priority,
+    /// ID, alias, name, scheduling class, state, and usage of the task, shared with the [`registry`].
+    info: Arc<registry::Info>,
 
-    address_space: AddressSpace,
+    process: Arc<Process>,
     context: Context,
-    load_offset: usize,
 
-    elf_header: FileHeader<AnyEndian>,
-    elf_segments: Box<[ProgramHeader]>,
-    elf_relas: Vec<ElfRela>,
-    elf_data: ElfData,
+    /// Stack of a kernel-mode task. Userspace tasks' stacks are mapped into their address space.
+    kernel_stack: Option<Box<[u128]>>,
+
+    reservation: Option<deadline::Reservation>,
 }
 
 impl Task {
+    /// Creates the first thread of a new process, loaded from an executable's segments.
     pub fn new(
         priority: Priority,
         mut address_space: AddressSpace,
@@ -89,215 +130,182 @@ impl Task {
         elf_relas: Vec<ElfRela>,
         elf_data: ElfData,
     ) -> Self {
-        trace!("Generating a random ID for new task.");
-        let id = uuid::Uuid::new_v4();
+        let id = tid::Allocation::new();
+
+        trace!("Allocating userspace stack for task: {:?}.", id.tid());
+        let stack =
+            address_space.mmap_stack(Address::new_truncate(STACK_START.get()), STACK_PAGES, STACK_GUARD_PAGES).unwrap();
+        let entry = Address::new(load_offset + usize::try_from(elf_header.e_entry).unwrap()).unwrap();
 
-        trace!("Allocating userspace stack for task: {:?}.", id);
-        let stack = address_space
-            .mmap(Some(Address::new_truncate(STACK_START.get())), STACK_PAGES, MmapPermissions::ReadWrite)
-            .unwrap();
+        let process = Arc::new(Process::new(address_space, load_offset, elf_header, elf_segments, elf_relas, elf_data));
 
         Self {
-            id,
-            priority,
-            address_space,
+            info: registry::register(id, priority, &process),
+            process,
             context: (
                 State::user(
-                    Address::new(load_offset + usize::try_from(elf_header.e_entry).unwrap()).unwrap(),
+                    entry,
                     // Safety: Addition keeps the pointer within the bounds of the allocation, and the unit size is 1.
                     unsafe { Address::from_ptr(stack.as_non_null_ptr().as_ptr().add(stack.len())) },
                 ),
                 Registers::default(),
             ),
-            load_offset,
-            elf_header,
-            elf_segments,
-            elf_relas,
-            elf_data,
+            kernel_stack: None,
+            reservation: None,
         }
     }
 
-    #[inline]
-    pub const fn id(&self) -> uuid::Uuid {
-        self.id
+    /// Creates a kernel-mode task which begins execution at `entry`, on a fresh stack of (at least) `stack_size` bytes.
+    pub fn kernel(entry: extern "C" fn() -> !, stack_size: NonZeroUsize, priority: Priority) -> Self {
+        let process = Arc::new(Process::empty(AddressSpace::new_userspace(), 0));
+        let info = registry::register(tid::Allocation::new(), priority, &process);
+
+        // `u128` elements keep the stack 16-byte aligned.
+        let stack_len = stack_size.get().div_ceil(core::mem::size_of::<u128>());
+        let stack = alloc::vec![0u128; stack_len].into_boxed_slice();
+        let stack_top = stack.as_ptr_range().end.addr();
+
+        Self {
+            info,
+            process,
+            context: (
+                State::kernel(
+                    Address::new(entry as usize).unwrap(),
+                    // Entry expects the stack to be misaligned by a return address, as if it were `call`ed.
+                    Address::new(stack_top - core::mem::size_of::<usize>()).unwrap(),
+                ),
+                Registers::default(),
+            ),
+            kernel_stack: Some(stack),
+            reservation: None,
+        }
     }
 
-    #[inline]
-    pub const fn priority(&self) -> Priority {
-        self.priority
+    /// Creates another thread of this task's (userspace) process, which begins execution at `entry` on the stack
+    /// `stack` (i.e. its top), with `arg` in its first argument register. The thread is a child of this task.
+    pub fn spawn_thread(&self, entry: Address<Virtual>, stack: Address<Virtual>, arg: usize) -> Self {
+        debug_assert!(!self.is_kernel());
+
+        let info = registry::register(tid::Allocation::new(), self.priority(), &self.process);
+        info.set_parent(Arc::clone(&self.info));
+
+        let mut registers = Registers::default();
+        #[cfg(target_arch = "x86_64")]
+        {
+            registers.rdi = arg;
+        }
+        #[cfg(target_arch = "riscv64")]
+        {
+            registers.a0 = arg;
+        }
+
+        Self {
+            info,
+            process: Arc::clone(&self.process),
+            context: (State::user(entry, stack), registers),
+            kernel_stack: None,
+            reservation: None,
+        }
     }
 
     #[inline]
-    pub const fn address_space(&self) -> &AddressSpace {
-        &self.address_space
+    pub fn id(&self) -> Tid {
+        self.info.tid()
     }
 
+    /// UUID alias of the task, for identifying it outside the kernel (where its [`Tid`] may be recycled).
     #[inline]
-    pub fn address_space_mut(&mut self) -> &mut AddressSpace {
-        &mut self.address_space
+    pub fn alias(&self) -> uuid::Uuid {
+        self.info.alias()
     }
 
     #[inline]
-    pub const fn load_offset(&self) -> usize {
-        self.load_offset
+    pub fn priority(&self) -> Priority {
+        self.info.priority()
     }
 
     #[inline]
-    pub const fn elf_header(&self) -> &FileHeader<AnyEndian> {
-        &self.elf_header
+    pub fn policy(&self) -> libsys::syscall::task::Policy {
+        self.info.policy()
     }
 
     #[inline]
-    pub const fn elf_segments(&self) -> &[ProgramHeader] {
-        &self.elf_segments
+    pub fn info(&self) -> &registry::Info {
+        &self.info
     }
 
+    /// Sets the name of the task, as listed by `ps`-like tools.
     #[inline]
-    pub const fn elf_data(&self) -> &ElfData {
-        &self.elf_data
+    pub fn set_name(&self, name: &str) {
+        self.info.set_name(name);
     }
 
+    /// Publishes the task's memory usage to the [`registry`].
+    pub fn update_info(&self) {
+        let address_space = self.process.address_space();
+        self.info.set_memory(address_space.resident_pages(), address_space.virtual_pages());
+    }
+
+    /// Process the task is a thread of.
     #[inline]
-    pub fn elf_relas(&mut self) -> &mut Vec<ElfRela> {
-        &mut self.elf_relas
+    pub fn process(&self) -> &Process {
+        &self.process
     }
 
-    pub fn demand_map(&mut self, address: Address<Virtual>) -> Result<()> {
-        use crate::mem::paging::TableEntryFlags;
-        use core::mem::MaybeUninit;
-        use libsys::Page;
+    /// Number of live threads of the task's process, including itself.
+    #[inline]
+    pub fn thread_count(&self) -> usize {
+        Arc::strong_count(&self.process)
+    }
 
-        let fault_page = Address::new_truncate(address.get());
+    #[inline]
+    pub const fn context(&self) -> &Context {
+        &self.context
+    }
 
-        if self.address_space().is_mmapped(fault_page) {
-            return Err(Error::AlreadyMapped);
-        }
+    /// Context the task resumes with, i.e. to pass arguments in registers before it first runs.
+    #[inline]
+    pub fn context_mut(&mut self) -> &mut Context {
+        &mut self.context
+    }
 
-        let fault_unoffset =
-            address.get().checked_sub(self.load_offset()).ok_or(Error::AddressUnderrun { addr: address })?;
-
-        let segment = self
-            .elf_segments()
-            .iter()
-            .filter(|phdr| phdr.p_type == elf::abi::PT_LOAD)
-            .find(|phdr| {
-                (phdr.p_vaddr..(phdr.p_vaddr + phdr.p_memsz)).contains(&u64::try_from(fault_unoffset).unwrap())
-            })
-            .copied()
-            .ok_or(Error::UnhandledAddress { addr: address })?;
-
-        // Small check to help ensure the segment alignments are page-fit.
-        debug_assert_eq!(segment.p_align & (libsys::page_mask() as u64), 0);
-
-        debug!("Demand mapping {:X?} from segment: {:X?}", Address::<Page>::new_truncate(address.get()), segment);
-
-        let fault_unoffset_page: Address<Page> = Address::new_truncate(fault_unoffset);
-        let fault_unoffset_page_addr = fault_unoffset_page.get().get();
-
-        let fault_unoffset_end_page: Address<Page> = Address::new_truncate(fault_unoffset_page_addr + page_size());
-        let fault_unoffset_end_page_addr = fault_unoffset_end_page.get().get();
-
-        let segment_addr = usize::try_from(segment.p_vaddr).unwrap();
-        let segment_size = usize::try_from(segment.p_filesz).unwrap();
-        let segment_end_addr = segment_addr + segment_size;
-
-        let fault_offset = fault_unoffset_page_addr.saturating_sub(segment_addr);
-        let fault_end_pad = fault_unoffset_end_page_addr.saturating_sub(segment_end_addr);
-        let fault_front_pad = segment_addr.saturating_sub(fault_unoffset_page_addr);
-        let fault_size = ((fault_unoffset_end_page_addr - fault_unoffset_page_addr) - fault_front_pad) - fault_end_pad;
-
-        trace!("Mapping the demand page RW so data can be copied.");
-        let mapped_memory = self
-            .address_space_mut()
-            .mmap(Some(fault_page), core::num::NonZeroUsize::MIN, crate::task::MmapPermissions::ReadWrite)
-            .unwrap();
-        // Safety: Address space allocator fulfills all required invariants.
-        let mapped_memory = unsafe { mapped_memory.as_uninit_slice_mut() };
-
-        let (front_pad, remaining) = mapped_memory.split_at_mut(fault_front_pad);
-        let (file_memory, end_pad) = remaining.split_at_mut(fault_size);
-
-        debug_assert_eq!(fault_front_pad, front_pad.len(), "front padding");
-        debug_assert_eq!(fault_end_pad, end_pad.len(), "end padding");
-        debug_assert_eq!(fault_size, file_memory.len(), "file memory");
-
-        trace!(
-            "Copying memory into demand mapping: {:#X}..{:#X}..{:#X}.",
-            front_pad.len(),
-            file_memory.len(),
-            end_pad.len()
-        );
-        front_pad.fill(MaybeUninit::uninit());
-        end_pad.fill(MaybeUninit::uninit());
-
-        if !file_memory.is_empty() {
-            match self.elf_data() {
-                ElfData::Memory(data) => {
-                    let segment_data_offset = usize::try_from(segment.p_offset).unwrap();
-
-                    let offset_segment_range =
-                        (segment_data_offset + fault_offset)..(segment_data_offset + fault_offset + fault_size);
-
-                    // Safety: Same-sized reinterpret for copying.
-                    let (_, copy_data, _) = unsafe { data[offset_segment_range].align_to() };
-
-                    file_memory.copy_from_slice(copy_data);
-                }
-                ElfData::File(_) => unimplemented!(),
-            }
-        }
+    /// Deadline reservation of the task, if it has been admitted to the deadline class.
+    #[inline]
+    pub const fn reservation(&self) -> Option<&deadline::Reservation> {
+        self.reservation.as_ref()
+    }
 
-        // Safety: Slice has been initialized with values.
-        let _mapped_memory = unsafe { MaybeUninit::slice_assume_init_mut(mapped_memory) };
-
-        trace!("Processing demand mapping relocations.");
-        let load_offset = self.load_offset();
-        let fault_page_as_range = fault_unoffset_page_addr..fault_unoffset_end_page_addr;
-
-        self.elf_relas().retain(|rela| {
-            if fault_page_as_range.contains(&rela.address.get()) {
-                trace!("Processing relocation: {:X?}", rela);
-                // Safety: Fault page is checked to contain the relocation's address, and the pointer is guaranteed after
-                // offset to lie within the memory mapped region above.
-                unsafe {
-                    rela.address.as_ptr().add(load_offset).cast::<usize>().write(rela.value);
-                }
-
-                false
-            } else {
-                true
-            }
-        });
-
-        trace!("Finalizing page's access attributes.");
-        // Safety: Page is already mapped, permissions are being modified according to the segment access type.
-        unsafe {
-            self.address_space_mut()
-                .set_flags(
-                    fault_page,
-                    core::num::NonZeroUsize::new(1).unwrap(),
-                    TableEntryFlags::PRESENT
-                        | TableEntryFlags::USER
-                        | TableEntryFlags::from(crate::task::segment_to_mmap_permissions(segment.p_type)),
-                )
-                .unwrap();
-        }
+    /// Addresses of the task's own stack, if it runs in kernel mode.
+    pub fn kernel_stack(&self) -> Option<core::ops::Range<usize>> {
+        self.kernel_stack.as_ref().map(|stack| {
+            let range = stack.as_ptr_range();
+            range.start.addr()..range.end.addr()
+        })
+    }
 
-        trace!("Demand mapping complete.");
+    /// Indicates whether the task runs in kernel mode.
+    #[inline]
+    pub const fn is_kernel(&self) -> bool {
+        self.kernel_stack.is_some()
+    }
+}
 
-        Ok(())
+impl Drop for Task {
+    fn drop(&mut self) {
+        // The ID allocation is held by the task's info, so it's released (at the earliest) once it's unregistered, and
+        // a recycled ID is never registered twice.
+        registry::unregister(self.id());
     }
 }
 
 impl core::fmt::Debug for Task {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         f.debug_struct("Task")
-            .field("ID", &self.id)
-            .field("Priority", &self.priority)
-            .field("Address Space", &self.address_space)
+            .field("ID", &self.id())
+            .field("Priority", &self.priority())
+            .field("Policy", &self.policy())
+            .field("Process", &self.process)
             .field("Context", &self.context)
-            .field("ELF Load Offset", &self.load_offset)
-            .field("ELF Header", &self.elf_header)
             .finish_non_exhaustive()
     }
 }
@@ -0,0 +1,338 @@
+use super::{segment_to_mmap_permissions, AddressSpace, Backing, ElfData, ElfRela, Error, MmapPermissions, Result};
+use alloc::{boxed::Box, string::String, vec::Vec};
+use core::num::NonZeroUsize;
+use elf::{endian::AnyEndian, file::FileHeader, segment::ProgramHeader};
+use libsys::{page_size, syscall::cred::Credentials, Address, Virtual};
+use spin::{Mutex, MutexGuard};
+
+/// ELF metadata of a process loaded from an executable.
+struct ElfImage {
+    header: FileHeader<AnyEndian>,
+    segments: Box<[ProgramHeader]>,
+    /// Relocations not yet applied, as their pages haven't been demand mapped.
+    relas: Mutex<Vec<ElfRela>>,
+    data: ElfData,
+}
+
+/// Resources shared by the threads (tasks) of a process.
+///
+/// #### Remark
+///
+/// Each resource is locked separately, and only ever within a system call or fault (with interrupts disabled), so a
+/// lock is never held across a switch to a sibling thread. The address space lock may be taken while holding any
+/// other (i.e. by a fault on a buffer being read into), so only the ELF relocations may be locked while holding it.
+pub struct Process {
+    address_space: Mutex<AddressSpace>,
+    load_offset: usize,
+
+    /// ELF metadata, if the process was loaded from an executable.
+    elf: Option<ElfImage>,
+
+    /// Canonical path of the working directory, against which relative paths are resolved.
+    cwd: Mutex<String>,
+    /// Identity the process accesses files as, and the capabilities it holds for privileged system calls.
+    credentials: Mutex<Credentials>,
+    files: Mutex<crate::vfs::Descriptors>,
+    /// Quota usage of the resources the process's threads hold outside of it.
+    usage: super::quota::Usage,
+}
+
+impl Process {
+    /// Creates a process from an executable's segments, reserving a region to demand map each loaded segment from.
+    pub fn new(
+        mut address_space: AddressSpace,
+        load_offset: usize,
+        elf_header: FileHeader<AnyEndian>,
+        elf_segments: Box<[ProgramHeader]>,
+        elf_relas: Vec<ElfRela>,
+        elf_data: ElfData,
+    ) -> Self {
+        for segment in elf_segments.iter().filter(|phdr| phdr.p_type == elf::abi::PT_LOAD) {
+            let start = load_offset + usize::try_from(segment.p_vaddr).unwrap();
+            let end = start + usize::try_from(segment.p_memsz).unwrap();
+
+            // A page shared with the previous segment is covered by (and demand mapped from) its region.
+            let mut start_index = start / page_size();
+            if let Some(region) = address_space.region(Address::from_index(start_index).unwrap()) {
+                start_index = region.start.index() + region.page_count.get();
+            }
+            let Some(page_count) = end.div_ceil(page_size()).checked_sub(start_index).and_then(NonZeroUsize::new)
+            else {
+                continue;
+            };
+
+            let permissions = segment_to_mmap_permissions(segment.p_flags);
+            let name = match permissions {
+                MmapPermissions::ReadExecute => "elf:.text",
+                MmapPermissions::ReadWrite => "elf:.data",
+                MmapPermissions::ReadOnly => "elf:.rodata",
+            };
+
+            let start = Address::from_index(start_index).unwrap();
+            if let Err(err) = address_space.reserve(start, page_count, permissions, Backing::Elf(*segment), name) {
+                trace!("Not reserving region for segment {:X?}: {:?}", segment, err);
+            }
+        }
+
+        Self {
+            elf: Some(ElfImage {
+                header: elf_header,
+                segments: elf_segments,
+                relas: Mutex::new(elf_relas),
+                data: elf_data,
+            }),
+            ..Self::empty(address_space, load_offset)
+        }
+    }
+
+    /// Creates a process with no executable image, i.e. for kernel-mode tasks.
+    pub fn empty(address_space: AddressSpace, load_offset: usize) -> Self {
+        Self {
+            address_space: Mutex::new(address_space),
+            load_offset,
+            elf: None,
+            cwd: Mutex::new(String::from(crate::vfs::ROOT)),
+            credentials: Mutex::new(Credentials::ROOT),
+            files: Mutex::new(crate::vfs::Descriptors::default()),
+            usage: super::quota::Usage::default(),
+        }
+    }
+
+    /// Locks the address space.
+    ///
+    /// #### Remark
+    ///
+    /// Its holder may be shooting down a change to it, waiting on this core to acknowledge (see [`crate::mem::tlb`]),
+    /// which it can't from the interrupt handler while spinning here with interrupts disabled. So shootdowns are
+    /// serviced while waiting for the lock.
+    pub fn address_space(&self) -> MutexGuard<'_, AddressSpace> {
+        loop {
+            if let Some(guard) = self.address_space.try_lock() {
+                break guard;
+            }
+
+            crate::mem::tlb::service();
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Locks the address space only if it isn't already, i.e. where its holder may be what failed.
+    #[inline]
+    pub fn try_address_space(&self) -> Option<MutexGuard<'_, AddressSpace>> {
+        self.address_space.try_lock()
+    }
+
+    #[inline]
+    pub const fn load_offset(&self) -> usize {
+        self.load_offset
+    }
+
+    #[inline]
+    pub fn elf_header(&self) -> Option<&FileHeader<AnyEndian>> {
+        self.elf.as_ref().map(|elf| &elf.header)
+    }
+
+    /// ELF segments of the process, or an empty slice if it wasn't loaded from an executable.
+    #[inline]
+    pub fn elf_segments(&self) -> &[ProgramHeader] {
+        self.elf.as_ref().map_or(&[], |elf| &elf.segments)
+    }
+
+    #[inline]
+    pub fn elf_data(&self) -> Option<&ElfData> {
+        self.elf.as_ref().map(|elf| &elf.data)
+    }
+
+    #[inline]
+    pub fn cwd(&self) -> MutexGuard<'_, String> {
+        self.cwd.lock()
+    }
+
+    /// Sets the working directory of the process.
+    ///
+    /// #### Remark
+    ///
+    /// `cwd` is expected to be the canonical path of an existing directory.
+    #[inline]
+    pub fn set_cwd(&self, cwd: String) {
+        *self.cwd.lock() = cwd;
+    }
+
+    #[inline]
+    pub fn credentials(&self) -> Credentials {
+        *self.credentials.lock()
+    }
+
+    /// Replaces the credentials of the process, if its current credentials permit (see [`Credentials::permits`]).
+    pub fn set_credentials(&self, new: Credentials) -> core::result::Result<(), ()> {
+        let mut credentials = self.credentials.lock();
+        if !credentials.permits(&new) {
+            return Err(());
+        }
+
+        *credentials = new;
+
+        Ok(())
+    }
+
+    /// User & group on whose behalf the process accesses files.
+    #[inline]
+    pub fn owner(&self) -> crate::vfs::Owner {
+        let Credentials { uid, gid, .. } = self.credentials();
+        crate::vfs::Owner { uid, gid }
+    }
+
+    #[inline]
+    pub fn files(&self) -> MutexGuard<'_, crate::vfs::Descriptors> {
+        self.files.lock()
+    }
+
+    #[inline]
+    pub const fn usage(&self) -> &super::quota::Usage {
+        &self.usage
+    }
+
+    pub fn demand_map(&self, address: Address<Virtual>) -> Result<()> {
+        use crate::mem::paging::TableEntryFlags;
+        use core::mem::MaybeUninit;
+        use libsys::Page;
+
+        let fault_page = Address::new_truncate(address.get());
+
+        // Held throughout, so a sibling thread faulting on the same page waits for it to be mapped.
+        let mut address_space = self.address_space();
+        if address_space.is_mmapped(fault_page) {
+            return Err(Error::AlreadyMapped);
+        }
+
+        let fault_unoffset =
+            address.get().checked_sub(self.load_offset()).ok_or(Error::AddressUnderrun { addr: address })?;
+
+        let region = address_space.region(fault_page).ok_or(Error::UnhandledAddress { addr: address })?;
+        let Backing::Elf(segment) = region.backing else { return Err(Error::UnhandledAddress { addr: address }) };
+        // Taken from the region, so any change in protection since it was reserved is honoured.
+        let permissions = region.permissions;
+
+        // Small check to help ensure the segment alignments are page-fit.
+        debug_assert_eq!(segment.p_align & (libsys::page_mask() as u64), 0);
+
+        debug!("Demand mapping {:X?} from segment: {:X?}", Address::<Page>::new_truncate(address.get()), segment);
+
+        let fault_unoffset_page: Address<Page> = Address::new_truncate(fault_unoffset);
+        let fault_unoffset_page_addr = fault_unoffset_page.get().get();
+
+        let fault_unoffset_end_page: Address<Page> = Address::new_truncate(fault_unoffset_page_addr + page_size());
+        let fault_unoffset_end_page_addr = fault_unoffset_end_page.get().get();
+
+        let segment_addr = usize::try_from(segment.p_vaddr).unwrap();
+        let segment_size = usize::try_from(segment.p_filesz).unwrap();
+        let segment_end_addr = segment_addr + segment_size;
+
+        let fault_offset = fault_unoffset_page_addr.saturating_sub(segment_addr);
+        let fault_end_pad = fault_unoffset_end_page_addr.saturating_sub(segment_end_addr);
+        let fault_front_pad = segment_addr.saturating_sub(fault_unoffset_page_addr);
+        let fault_size = ((fault_unoffset_end_page_addr - fault_unoffset_page_addr) - fault_front_pad) - fault_end_pad;
+
+        trace!("Mapping the demand page RW so data can be copied.");
+        let mapped_memory = address_space
+            .mmap(Some(fault_page), core::num::NonZeroUsize::MIN, crate::task::MmapPermissions::ReadWrite)
+            .unwrap();
+        // Safety: Address space allocator fulfills all required invariants.
+        let mapped_memory = unsafe { mapped_memory.as_uninit_slice_mut() };
+
+        let (front_pad, remaining) = mapped_memory.split_at_mut(fault_front_pad);
+        let (file_memory, end_pad) = remaining.split_at_mut(fault_size);
+
+        debug_assert_eq!(fault_front_pad, front_pad.len(), "front padding");
+        debug_assert_eq!(fault_end_pad, end_pad.len(), "end padding");
+        debug_assert_eq!(fault_size, file_memory.len(), "file memory");
+
+        trace!(
+            "Copying memory into demand mapping: {:#X}..{:#X}..{:#X}.",
+            front_pad.len(),
+            file_memory.len(),
+            end_pad.len()
+        );
+        front_pad.fill(MaybeUninit::uninit());
+        end_pad.fill(MaybeUninit::uninit());
+
+        if !file_memory.is_empty() {
+            match self.elf_data() {
+                Some(ElfData::Memory(data)) => {
+                    let segment_data_offset = usize::try_from(segment.p_offset).unwrap();
+
+                    let offset_segment_range =
+                        (segment_data_offset + fault_offset)..(segment_data_offset + fault_offset + fault_size);
+
+                    // Safety: Same-sized reinterpret for copying.
+                    let (_, copy_data, _) = unsafe { data[offset_segment_range].align_to() };
+
+                    file_memory.copy_from_slice(copy_data);
+                }
+                Some(ElfData::File(path)) => {
+                    file_memory.fill(MaybeUninit::new(0));
+                    // Safety: Every byte was just initialized.
+                    let file_memory = unsafe { MaybeUninit::slice_assume_init_mut(file_memory) };
+                    let offset = usize::try_from(segment.p_offset).unwrap() + fault_offset;
+
+                    match crate::vfs::lookup(crate::vfs::ROOT, path).and_then(|node| node.read(offset, file_memory)) {
+                        Ok(read) if read == file_memory.len() => {}
+                        _ => return Err(Error::ElfUnreadable { addr: address }),
+                    }
+                }
+                // A segment was found, so the process must have ELF metadata.
+                None => unreachable!(),
+            }
+        }
+
+        // Safety: Slice has been initialized with values.
+        let _mapped_memory = unsafe { MaybeUninit::slice_assume_init_mut(mapped_memory) };
+
+        trace!("Processing demand mapping relocations.");
+        let load_offset = self.load_offset();
+        let fault_page_as_range = fault_unoffset_page_addr..fault_unoffset_end_page_addr;
+
+        if let Some(elf) = self.elf.as_ref() {
+            elf.relas.lock().retain(|rela| {
+                if fault_page_as_range.contains(&rela.address.get()) {
+                    trace!("Processing relocation: {:X?}", rela);
+                    // Safety: Fault page is checked to contain the relocation's address, and the pointer is guaranteed after
+                    // offset to lie within the memory mapped region above.
+                    unsafe {
+                        rela.address.as_ptr().add(load_offset).cast::<usize>().write(rela.value);
+                    }
+
+                    false
+                } else {
+                    true
+                }
+            });
+        }
+
+        trace!("Finalizing page's access attributes.");
+        // Safety: Page is already mapped, permissions are being modified according to the segment access type.
+        unsafe {
+            address_space
+                .set_flags(
+                    fault_page,
+                    core::num::NonZeroUsize::new(1).unwrap(),
+                    TableEntryFlags::PRESENT | TableEntryFlags::USER | TableEntryFlags::from(permissions),
+                )
+                .unwrap();
+        }
+
+        trace!("Demand mapping complete.");
+
+        Ok(())
+    }
+}
+
+impl core::fmt::Debug for Process {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Process")
+            .field("Address Space", &self.address_space)
+            .field("ELF Load Offset", &self.load_offset)
+            .field("ELF Header", &self.elf_header())
+            .finish_non_exhaustive()
+    }
+}
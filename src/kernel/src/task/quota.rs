@@ -0,0 +1,65 @@
+//! Per-task quotas on the kernel objects userspace can have the kernel allocate on its behalf, so that no task can
+//! exhaust kernel memory (i.e. by opening descriptors in a loop).
+//!
+//! Limits are set from the kernel parameters (`--maxfds=`, `--maxregions=`, `--maxthreads=`, `--maxfutexwaiters=`, and
+//! `--maxtimers=`).
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resource {
+    /// Open file descriptors.
+    Descriptors,
+    /// Regions of the task's address space (each mapping, and each piece of one split by a protection change).
+    Regions,
+    /// Threads of the task's process, including itself.
+    Threads,
+    /// Threads of the task's process blocked on futexes.
+    FutexWaiters,
+    /// Threads of the task's process asleep on a timer.
+    SleepTimers,
+}
+
+const RESOURCE_COUNT: usize = Resource::SleepTimers as usize + 1;
+
+pub fn limit(resource: Resource) -> usize {
+    let params = crate::init::get();
+
+    match resource {
+        Resource::Descriptors => params.max_descriptors,
+        Resource::Regions => params.max_regions,
+        Resource::Threads => params.max_threads,
+        Resource::FutexWaiters => params.max_futex_waiters,
+        Resource::SleepTimers => params.max_sleep_timers,
+    }
+}
+
+/// Indicates whether a task holding `used` of `resource` may allocate another.
+pub fn permits(resource: Resource, used: usize) -> bool {
+    let permits = used < limit(resource);
+    if !permits {
+        debug!("Task quota of {:?} exhausted ({} held).", resource, used);
+    }
+
+    permits
+}
+
+/// Counts of the resources a process holds which live outside of it (i.e. its blocked threads, which are held by the
+/// queues they're blocked in), charged as they're allocated and released as they're freed.
+#[derive(Debug, Default)]
+pub struct Usage([AtomicUsize; RESOURCE_COUNT]);
+
+impl Usage {
+    /// Number of `resource` held.
+    pub fn held(&self, resource: Resource) -> usize {
+        self.0[resource as usize].load(Ordering::Relaxed)
+    }
+
+    pub fn charge(&self, resource: Resource) {
+        self.0[resource as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn release(&self, resource: Resource) {
+        self.0[resource as usize].fetch_sub(1, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,334 @@
+//! Registry of live tasks, through which their names, states, and usage are reported to userspace (see
+//! [`libsys::syscall::task`]).
+//!
+//! Tasks move between the scheduler queues and cores, so each holds an [`Info`] shared with the registry, which the
+//! scheduler keeps up to date as the task runs.
+//!
+//! Tasks are keyed on their [`Tid`]s, which are only converted from (and to) their UUID aliases at the system call
+//! boundary. An [`Info`] holds its task's ID allocation, so the ID (and alias) isn't recycled until the last reference
+//! to it is dropped (i.e. once the task's exit code is collected, see [`super::wait`]).
+
+use super::{tid, Priority, Process, Tid};
+use crate::interrupts::InterruptCell;
+use alloc::{
+    collections::BTreeMap,
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::{
+    fmt::Write,
+    sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering},
+};
+use libsys::syscall::{
+    cred::Credentials,
+    task::{IoClass, IoStats, Policy, State, NAME_LEN},
+};
+use spin::Mutex;
+
+/// Kinds of object a task's I/O is accounted against (see [`Info::account`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoKind {
+    File,
+    Block,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoDirection {
+    Read,
+    Write,
+}
+
+#[derive(Debug, Default)]
+struct IoCounters {
+    read_bytes: AtomicU64,
+    written_bytes: AtomicU64,
+    reads: AtomicU64,
+    writes: AtomicU64,
+}
+
+impl IoCounters {
+    fn snapshot(&self) -> IoStats {
+        IoStats {
+            read_bytes: self.read_bytes.load(Ordering::Relaxed),
+            written_bytes: self.written_bytes.load(Ordering::Relaxed),
+            reads: self.reads.load(Ordering::Relaxed),
+            writes: self.writes.load(Ordering::Relaxed),
+        }
+    }
+}
+
+static REGISTRY: InterruptCell<Mutex<BTreeMap<Tid, Arc<Info>>>> = InterruptCell::new(Mutex::new(BTreeMap::new()));
+/// IDs of the tasks whose aliases have been generated, keyed on their alias.
+static ALIASES: InterruptCell<Mutex<BTreeMap<uuid::Uuid, Tid>>> = InterruptCell::new(Mutex::new(BTreeMap::new()));
+
+#[derive(Debug)]
+pub struct Info {
+    id: tid::Allocation,
+    /// System-unique alias of the task, generated on first use.
+    alias: spin::Once<uuid::Uuid>,
+    /// The task's parent, which collects its exit code (see [`super::wait`]), and whose ID is held until the task is
+    /// dropped.
+    parent: spin::Once<Arc<Info>>,
+    /// Process the task is a thread of, whose credentials it runs with.
+    process: Weak<Process>,
+    /// [`Priority`] of the task.
+    priority: AtomicU32,
+    /// [`Policy`] of the task.
+    policy: AtomicU32,
+    /// Resource group the task is in (see [`super::group`]).
+    group: AtomicU32,
+    /// [`IoClass`] the task's block requests are dispatched by.
+    io_class: AtomicU32,
+    name: InterruptCell<Mutex<String>>,
+    /// [`State`] of the task.
+    state: AtomicU32,
+    /// Scheduler ticks the task has run for.
+    cpu_ticks: AtomicU64,
+    resident_pages: AtomicUsize,
+    virtual_pages: AtomicUsize,
+    file_io: IoCounters,
+    block_io: IoCounters,
+}
+
+impl Info {
+    /// Sets the name of the task, truncated to [`NAME_LEN`] bytes (on a character boundary).
+    pub fn set_name(&self, name: &str) {
+        let len = (0..=name.len().min(NAME_LEN)).rev().find(|&len| name.is_char_boundary(len)).unwrap_or(0);
+
+        self.name.with(|task_name| {
+            let mut task_name = task_name.lock();
+            task_name.clear();
+            task_name.push_str(&name[..len]);
+        });
+    }
+
+    #[inline]
+    pub const fn tid(&self) -> Tid {
+        self.id.tid()
+    }
+
+    /// UUID alias of the task, for identifying it outside the kernel (where its [`Tid`] may be recycled).
+    pub fn alias(&self) -> uuid::Uuid {
+        *self.alias.call_once(|| {
+            let alias = uuid::Uuid::new_v4();
+            ALIASES.with(|aliases| aliases.lock().insert(alias, self.tid()));
+
+            alias
+        })
+    }
+
+    /// ID of the task's parent, if it has one.
+    pub fn parent(&self) -> Option<Tid> {
+        self.parent.get().map(|parent| parent.tid())
+    }
+
+    /// Indicates whether the task's parent has yet to exit.
+    pub fn parent_is_live(&self) -> bool {
+        self.parent.get().is_some_and(|parent| parent.state() != State::Exited)
+    }
+
+    /// Credentials the task runs with, unless its process has been dropped.
+    pub fn credentials(&self) -> Option<Credentials> {
+        self.process.upgrade().map(|process| process.credentials())
+    }
+
+    /// Makes the task a child of the task `parent`. A task's parent is fixed once set.
+    pub fn set_parent(&self, parent: Arc<Info>) {
+        self.parent.call_once(|| parent);
+    }
+
+    pub fn state(&self) -> State {
+        State::try_from(self.state.load(Ordering::Relaxed)).unwrap()
+    }
+
+    pub fn priority(&self) -> Priority {
+        libsys::syscall::task::Priority::try_from(self.priority.load(Ordering::Relaxed)).unwrap().into()
+    }
+
+    pub fn policy(&self) -> Policy {
+        Policy::try_from(self.policy.load(Ordering::Relaxed)).unwrap()
+    }
+
+    /// Moves the task into the scheduling class `policy`, at `priority`.
+    ///
+    /// #### Remark
+    ///
+    /// The scheduler reads the class as it queues the task, so a queued task must be requeued to move (see
+    /// [`super::RunQueue::requeue`]).
+    pub fn set_sched_policy(&self, policy: Policy, priority: Priority) {
+        self.policy.store(policy as u32, Ordering::Relaxed);
+        self.priority.store(priority as u32, Ordering::Relaxed);
+    }
+
+    pub fn group(&self) -> u32 {
+        self.group.load(Ordering::Relaxed)
+    }
+
+    /// Records the task as in the group `group`, which [`super::group::attach`] has counted it in.
+    #[inline]
+    pub(super) fn set_group(&self, group: u32) {
+        self.group.store(group, Ordering::Relaxed);
+    }
+
+    pub fn io_class(&self) -> IoClass {
+        IoClass::try_from(self.io_class.load(Ordering::Relaxed)).unwrap()
+    }
+
+    pub fn set_io_class(&self, class: IoClass) {
+        self.io_class.store(class as u32, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub(super) fn set_state(&self, state: State) {
+        self.state.store(state as u32, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub(super) fn charge(&self, ticks: u16) {
+        self.cpu_ticks.fetch_add(u64::from(ticks), Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub(super) fn set_memory(&self, resident_pages: usize, virtual_pages: usize) {
+        self.resident_pages.store(resident_pages, Ordering::Relaxed);
+        self.virtual_pages.store(virtual_pages, Ordering::Relaxed);
+    }
+
+    /// Accounts an operation of `direction` against `kind`, which transferred `bytes`.
+    pub fn account(&self, kind: IoKind, direction: IoDirection, bytes: usize) {
+        let counters = match kind {
+            IoKind::File => &self.file_io,
+            IoKind::Block => &self.block_io,
+        };
+
+        let (bytes_counter, operations) = match direction {
+            IoDirection::Read => (&counters.read_bytes, &counters.reads),
+            IoDirection::Write => (&counters.written_bytes, &counters.writes),
+        };
+
+        bytes_counter.fetch_add(bytes as u64, Ordering::Relaxed);
+        operations.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Snapshot of the task, as reported to userspace.
+    pub fn snapshot(&self) -> libsys::syscall::task::Info {
+        let mut info = libsys::syscall::task::Info {
+            tid: self.tid().get(),
+            state: self.state.load(Ordering::Relaxed),
+            priority: self.priority.load(Ordering::Relaxed),
+            policy: self.policy.load(Ordering::Relaxed),
+            // The scheduler ticks at 1000Hz, so ticks are milliseconds.
+            cpu_time_ms: self.cpu_ticks.load(Ordering::Relaxed),
+            resident_pages: self.resident_pages.load(Ordering::Relaxed) as u64,
+            virtual_pages: self.virtual_pages.load(Ordering::Relaxed) as u64,
+            alias: self.alias().into_bytes(),
+            parent: self.parent.get().map(|parent| parent.alias()).unwrap_or_default().into_bytes(),
+            group: self.group(),
+            io_class: self.io_class.load(Ordering::Relaxed),
+            file_io: self.file_io.snapshot(),
+            block_io: self.block_io.snapshot(),
+            ..Default::default()
+        };
+
+        self.name.with(|name| {
+            let name = name.lock();
+            info.name[..name.len()].copy_from_slice(name.as_bytes());
+            info.name_len = u32::try_from(name.len()).unwrap();
+        });
+
+        info
+    }
+}
+
+impl Drop for Info {
+    fn drop(&mut self) {
+        // Forgotten before the ID allocation is released, so an alias never resolves to a recycled ID.
+        if let Some(alias) = self.alias.get() {
+            ALIASES.with(|aliases| aliases.lock().remove(alias));
+        }
+    }
+}
+
+/// Registers a newly created task with the ID `id`, as a thread of `process`, returning the [`Info`] it shares with the
+/// registry.
+pub(super) fn register(id: tid::Allocation, priority: Priority, process: &Arc<Process>) -> Arc<Info> {
+    let tid = id.tid();
+    let info = Arc::new(Info {
+        id,
+        alias: spin::Once::new(),
+        parent: spin::Once::new(),
+        process: Arc::downgrade(process),
+        priority: AtomicU32::new(priority as u32),
+        policy: AtomicU32::new(Policy::Fair as u32),
+        group: AtomicU32::new(super::group::ROOT),
+        io_class: AtomicU32::new(IoClass::Normal as u32),
+        name: InterruptCell::new(Mutex::new(String::new())),
+        state: AtomicU32::new(State::Ready as u32),
+        cpu_ticks: AtomicU64::new(0),
+        resident_pages: AtomicUsize::new(0),
+        virtual_pages: AtomicUsize::new(0),
+        file_io: IoCounters::default(),
+        block_io: IoCounters::default(),
+    });
+
+    REGISTRY.with(|registry| registry.lock().insert(tid, info.clone()));
+    super::group::join_root();
+
+    info
+}
+
+/// Removes a task from the registry (and its group), as it's dropped.
+pub(super) fn unregister(tid: Tid) {
+    if let Some(info) = REGISTRY.with(|registry| registry.lock().remove(&tid)) {
+        super::group::leave(&info);
+    }
+}
+
+/// Returns the [`Info`] of the live task with the raw ID `tid`, if any.
+pub fn get(tid: u32) -> Option<Arc<Info>> {
+    find(Tid::from_raw(tid))
+}
+
+/// Returns the [`Info`] of the live task `tid`, if any.
+pub(super) fn find(tid: Tid) -> Option<Arc<Info>> {
+    REGISTRY.with(|registry| registry.lock().get(&tid).cloned())
+}
+
+/// Returns the ID of the task aliased `alias`, which may have exited, if its ID hasn't been recycled.
+pub fn resolve(alias: uuid::Uuid) -> Option<Tid> {
+    ALIASES.with(|aliases| aliases.lock().get(&alias).copied())
+}
+
+/// IDs of all live tasks, in ascending order.
+pub fn tids() -> Vec<Tid> {
+    REGISTRY.with(|registry| registry.lock().keys().copied().collect())
+}
+
+/// Publishes the I/O of every live task to sysfs (`tasks/io`), listed as it's read.
+pub fn publish() {
+    crate::sysfs::set(
+        "tasks",
+        "io",
+        crate::sysfs::Value::Dynamic(alloc::boxed::Box::new(|| {
+            let infos = REGISTRY.with(|registry| registry.lock().values().cloned().collect::<Vec<_>>());
+
+            infos.into_iter().map(|info| info.snapshot()).fold(String::new(), |mut string, info| {
+                writeln!(
+                    string,
+                    "task {:<5} {:<16} file_rb={:<12} file_wb={:<12} file_ops={:<8} blk_rb={:<12} blk_wb={:<12} blk_ops={}",
+                    info.tid,
+                    info.name().unwrap_or("?"),
+                    info.file_io.read_bytes,
+                    info.file_io.written_bytes,
+                    info.file_io.reads + info.file_io.writes,
+                    info.block_io.read_bytes,
+                    info.block_io.written_bytes,
+                    info.block_io.reads + info.block_io.writes,
+                )
+                .unwrap();
+                string
+            })
+        })),
+    );
+}
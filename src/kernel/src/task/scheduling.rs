@@ -1,21 +1,236 @@
 use crate::{
     mem::Stack,
-    task::{Registers, State, Task},
+    task::{deadline, futex, group, trace, wait, wheel::TimerWheel, Priority, Registers, State, Task, Tid},
 };
-use alloc::collections::VecDeque;
-use libsys::Address;
+use alloc::{collections::VecDeque, vec::Vec};
+use core::num::NonZeroU16;
+use libsys::{
+    syscall::{
+        sched_trace::Reason,
+        task::{Policy, State as TaskState},
+    },
+    Address, Virtual,
+};
+
+/// Behaviour of a scheduling class (selected per-task by its [`Policy`]).
+pub trait Class: Sync {
+    /// Whether the class's tasks are queued ahead of every task of the fair class.
+    fn is_realtime(&self) -> bool;
+
+    /// Number of scheduler ticks a task of `priority` runs for before being preempted, or `None` if it runs until
+    /// it yields.
+    fn time_slice(&self, priority: Priority) -> Option<NonZeroU16>;
+
+    /// Whether a preempted task resumes ahead of the other tasks of its priority, rather than after them.
+    fn resumes_first(&self) -> bool;
+
+    /// Highest priority a task may select in the class.
+    fn ceiling(&self) -> Priority;
+}
+
+struct Fair;
+
+impl Class for Fair {
+    fn is_realtime(&self) -> bool {
+        false
+    }
+
+    fn time_slice(&self, priority: Priority) -> Option<NonZeroU16> {
+        Some(time_slice(priority))
+    }
+
+    fn resumes_first(&self) -> bool {
+        false
+    }
+
+    fn ceiling(&self) -> Priority {
+        crate::init::get().fair_ceiling
+    }
+}
+
+struct Fifo;
+
+impl Class for Fifo {
+    fn is_realtime(&self) -> bool {
+        true
+    }
+
+    fn time_slice(&self, _: Priority) -> Option<NonZeroU16> {
+        None
+    }
+
+    // Preemption only ever makes way for a higher priority, so the task keeps its place.
+    fn resumes_first(&self) -> bool {
+        true
+    }
+
+    fn ceiling(&self) -> Priority {
+        crate::init::get().realtime_ceiling
+    }
+}
+
+/// Number of scheduler ticks a round-robin task runs for before making way for the others of its priority.
+const ROUND_ROBIN_SLICE: NonZeroU16 = NonZeroU16::new(10).unwrap();
+
+struct RoundRobin;
+
+impl Class for RoundRobin {
+    fn is_realtime(&self) -> bool {
+        true
+    }
+
+    fn time_slice(&self, _: Priority) -> Option<NonZeroU16> {
+        Some(ROUND_ROBIN_SLICE)
+    }
+
+    fn resumes_first(&self) -> bool {
+        false
+    }
+
+    fn ceiling(&self) -> Priority {
+        crate::init::get().realtime_ceiling
+    }
+}
+
+/// Scheduling class of `policy`.
+pub fn class(policy: Policy) -> &'static dyn Class {
+    match policy {
+        Policy::Fair => &Fair,
+        Policy::Fifo => &Fifo,
+        Policy::RoundRobin => &RoundRobin,
+    }
+}
+
+/// Number of [`Priority`] levels.
+const PRIORITY_COUNT: usize = Priority::Critical as usize + 1;
+
+/// Queue of each [`Priority`] of a tier of the [`RunQueue`].
+type Tier = [VecDeque<Task>; PRIORITY_COUNT];
+
+/// Ready tasks, with a queue per [`Priority`] of the real-time classes, and of the fair class beneath them.
+///
+/// Tasks are always taken from the highest-priority queue which isn't empty, so a ready task preempts any
+/// lower-priority task at the end of its slice.
+pub struct RunQueue {
+    realtime: Tier,
+    fair: Tier,
+}
+
+impl RunQueue {
+    const fn new() -> Self {
+        Self {
+            realtime: [const { VecDeque::new() }; PRIORITY_COUNT],
+            fair: [const { VecDeque::new() }; PRIORITY_COUNT],
+        }
+    }
+
+    fn queue_of(&mut self, task: &Task) -> &mut VecDeque<Task> {
+        let tier = if class(task.policy()).is_realtime() { &mut self.realtime } else { &mut self.fair };
+
+        &mut tier[task.priority() as usize]
+    }
 
-pub static PROCESSES: spin::Mutex<VecDeque<Task>> = spin::Mutex::new(VecDeque::new());
+    pub fn push_back(&mut self, task: Task) {
+        self.queue_of(&task).push_back(task);
+    }
+
+    pub fn push_front(&mut self, task: Task) {
+        self.queue_of(&task).push_front(task);
+    }
+
+    /// Removes the longest-waiting task of the highest priority. Between the fair tasks of a priority, the
+    /// longest-waiting task of the group furthest behind its share runs first (see [`group`]).
+    pub fn pop_front(&mut self) -> Option<Task> {
+        if let Some(task) = self.realtime.iter_mut().rev().find_map(VecDeque::pop_front) {
+            return Some(task);
+        }
+
+        let queue = self.fair.iter_mut().rev().find(|queue| !queue.is_empty())?;
+        let index = group::select(queue.iter())?;
+        queue.remove(index)
+    }
+
+    /// Moves the queued task `tid` into the queue of its current class and priority, returning whether it's queued.
+    pub fn requeue(&mut self, tid: Tid) -> bool {
+        let task = self.realtime.iter_mut().chain(&mut self.fair).find_map(|queue| {
+            let index = queue.iter().position(|task| task.id() == tid)?;
+            queue.remove(index)
+        });
+
+        task.map(|task| self.push_back(task)).is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.realtime.iter().chain(&self.fair).map(VecDeque::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.realtime.iter().chain(&self.fair).all(VecDeque::is_empty)
+    }
+
+    /// Iterates the queued tasks, in the order they'd be scheduled.
+    pub fn iter(&self) -> impl Iterator<Item = &Task> {
+        self.realtime.iter().rev().chain(self.fair.iter().rev()).flatten()
+    }
+}
+
+/// Tasks ready to run on any core.
+///
+/// #### Remark
+///
+/// A task's context is saved before it's queued, and only restored after it's taken from the queue, both under the
+/// queue's lock. Releasing the lock on one core, and acquiring it on the core that resumes the task, is what makes the
+/// saved context (and everything the task wrote before it) visible there.
+pub static PROCESSES: spin::Mutex<RunQueue> = spin::Mutex::new(RunQueue::new());
+
+/// Queues a newly created task to be scheduled.
+pub fn spawn(task: Task) {
+    trace::record_wakeup(task.id(), Reason::Spawn);
+    PROCESSES.lock().push_back(task);
+}
+
+/// Number of scheduler ticks a fair-class task of the lowest priority runs for before being preempted.
+const BASE_TIME_SLICE: u16 = 2;
+
+/// Number of scheduler ticks a fair-class task runs for before being preempted.
+///
+/// Slices grow with priority, so a task waits at most a short slice for a lower-priority task to be preempted (there
+/// being no way to interrupt another core's task early).
+const fn time_slice(priority: Priority) -> NonZeroU16 {
+    NonZeroU16::new(BASE_TIME_SLICE * (priority as u16 + 1)).unwrap()
+}
 
 pub struct Scheduler {
     enabled: bool,
     idle_stack: Stack<0x1000>,
     task: Option<Task>,
+
+    /// Scheduler ticks elapsed on this core.
+    ticks: u64,
+    /// Length of the currently programmed preemption wait, in scheduler ticks.
+    slice: NonZeroU16,
+    /// Share of this core reserved by deadline tasks, in parts-per-[`deadline::UTILIZATION_SCALE`].
+    utilization: u32,
+    deadline_tasks: Vec<Task>,
+    /// Sleeping tasks, keyed on the tick at which each wakes.
+    sleeping: TimerWheel,
+    /// Tasks which have exited, and are released at the next scheduling point (once this core is off their stacks).
+    exited: Vec<Task>,
 }
 
 impl Scheduler {
     pub const fn new(enabled: bool) -> Self {
-        Self { enabled, idle_stack: Stack::new(), task: None }
+        Self {
+            enabled,
+            idle_stack: Stack::new(),
+            task: None,
+            ticks: 0,
+            slice: NonZeroU16::MIN,
+            utilization: 0,
+            deadline_tasks: Vec::new(),
+            sleeping: TimerWheel::new(),
+            exited: Vec::new(),
+        }
     }
 
     /// Enables the scheduler to pop tasks.
@@ -46,11 +261,47 @@ impl Scheduler {
         self.task.as_mut()
     }
 
+    /// Scheduler ticks elapsed on this core.
+    #[inline]
+    pub const fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    /// Admits the current task to this core's deadline class (replacing any reservation it holds), if doing so
+    /// wouldn't over-subscribe the core.
+    ///
+    /// The task keeps running, and moves into the deadline class as it's next queued.
+    pub fn admit_deadline(&mut self, params: deadline::Parameters) -> deadline::Result<()> {
+        let task = self.task.as_mut().expect("cannot admit without process");
+
+        let held = task.reservation.map_or(0, |reservation| reservation.params().utilization());
+        let utilization = (self.utilization - held) + params.utilization();
+        if utilization > deadline::MAX_UTILIZATION {
+            warn!("Rejecting deadline task {:?}: core would be over-subscribed.", task.id());
+            return Err(deadline::Error::OverSubscribed);
+        }
+
+        trace!("Admitting deadline task {:?} with {:?}", task.id(), params);
+        trace::record_wakeup(task.id(), Reason::Admit);
+
+        self.utilization = utilization;
+        task.reservation = Some(deadline::Reservation::new(params, self.ticks));
+
+        Ok(())
+    }
+
     pub fn interrupt_task(&mut self, state: &mut State, regs: &mut Registers) {
         debug_assert!(!crate::interrupts::are_enabled());
 
+        self.reap();
+
         let mut processes = PROCESSES.lock();
 
+        // The preemption wait expired, so the whole slice has elapsed.
+        self.advance(self.slice.get());
+
+        let prev = self.task.as_ref().map(Task::id);
+
         // Move the current task, if any, back into the scheduler queue.
         if let Some(mut process) = self.task.take() {
             trace!("Interrupting task: {:?}", process.id());
@@ -58,52 +309,382 @@ impl Scheduler {
             process.context.0 = *state;
             process.context.1 = *regs;
 
-            processes.push_back(process);
+            let resumes_first = class(process.policy()).resumes_first();
+            self.requeue(&mut processes, process, resumes_first);
         }
 
-        self.next_task(&mut processes, state, regs);
+        self.next_task(&mut processes, state, regs, prev, Reason::Preempt);
     }
 
     /// Attempts to schedule the next task in the local task queue.
     pub fn yield_task(&mut self, state: &mut State, regs: &mut Registers) {
         debug_assert!(!crate::interrupts::are_enabled());
 
+        self.reap();
+
         let mut processes = PROCESSES.lock();
 
+        // Time spent in a partial slice isn't measured, so round it up to a single tick.
+        self.advance(1);
+
         let mut process = self.task.take().expect("cannot yield without process");
         trace!("Yielding task: {:?}", process.id());
 
         process.context.0 = *state;
         process.context.1 = *regs;
 
-        processes.push_back(process);
+        let prev = process.id();
+        self.requeue(&mut processes, process, false);
+
+        self.next_task(&mut processes, state, regs, Some(prev), Reason::Yield);
+    }
+
+    /// Suspends the current task for at least `ticks` scheduler ticks, and schedules the next task in its place.
+    pub fn sleep_task(&mut self, state: &mut State, regs: &mut Registers, ticks: u64) {
+        debug_assert!(!crate::interrupts::are_enabled());
+
+        self.reap();
+
+        let mut processes = PROCESSES.lock();
+
+        self.advance(1);
+
+        let mut process = self.task.take().expect("cannot sleep without process");
+        trace!("Sleeping task: {:?}", process.id());
+
+        process.context.0 = *state;
+        process.context.1 = *regs;
+        process.info.set_state(TaskState::Sleeping);
+        process.update_info();
+
+        let prev = process.id();
+        self.sleeping.insert(self.ticks.saturating_add(ticks), process);
 
-        self.next_task(&mut processes, state, regs);
+        self.next_task(&mut processes, state, regs, Some(prev), Reason::Sleep);
     }
 
+    /// Terminates the current task, as it faulted, reporting its backtrace.
     pub fn kill_task(&mut self, state: &mut State, regs: &mut Registers) {
+        if let Some(task) = self.task.as_ref() {
+            super::backtrace::report(task, state, regs);
+        }
+
+        self.exit_task(state, regs, libsys::syscall::task::EXIT_FAULTED);
+    }
+
+    /// Terminates the current task with the exit code `code`, and schedules the next task in its place.
+    ///
+    /// #### Remark
+    ///
+    /// The task is released (its address space torn down, and its ELF image and stack freed) at the next scheduling
+    /// point, as this core may still be running on its kernel stack.
+    pub fn exit_task(&mut self, state: &mut State, regs: &mut Registers, code: u32) {
         debug_assert!(!crate::interrupts::are_enabled());
 
-        // TODO add process to reap queue to reclaim address space memory
+        self.reap();
+        self.advance(1);
+
         let process = self.task.take().expect("cannot exit without process");
-        trace!("Exiting process: {:?}", process.id());
+        trace!("Exiting process {:?} with code {:#X}", process.id(), code);
+
+        if let Some(reservation) = process.reservation() {
+            self.utilization -= reservation.params().utilization();
+        }
+
+        let waiters = wait::notify_exit(&process, code);
+        let prev = process.id();
+        self.exited.push(process);
+
+        let mut processes = PROCESSES.lock();
+        for waiter in waiters {
+            trace::record_wakeup(waiter.id(), Reason::Join);
+            self.requeue(&mut processes, waiter, false);
+        }
+
+        self.next_task(&mut processes, state, regs, Some(prev), Reason::Exit);
+    }
+
+    /// Blocks the current task until the task `target` exits, and schedules the next task in its place.
+    ///
+    /// Returns the result of the wait if it doesn't block; otherwise, the result is written into the task's registers
+    /// as it's woken (see [`wait::notify_exit`]).
+    pub fn wait_task(
+        &mut self,
+        state: &mut State,
+        regs: &mut Registers,
+        target: Tid,
+    ) -> Option<libsys::syscall::Result> {
+        use libsys::syscall::{Error, Success};
+
+        debug_assert!(!crate::interrupts::are_enabled());
+
+        self.reap();
+
+        let Some(process) = self.task.as_ref() else { return Some(Err(Error::NoActiveTask)) };
+        let prev = process.id();
+        if prev == target {
+            return Some(Err(Error::InvalidArgument));
+        }
+        // A reservation's utilization is counted against its core, which a waiter woken elsewhere wouldn't return to.
+        if process.reservation().is_some() {
+            return Some(Err(Error::Unsupported));
+        }
+
+        let status = wait::block(target, prev, || {
+            self.advance(1);
+
+            let mut process = self.task.take().unwrap();
+            trace!("Task {:?} waiting on {}", process.id(), target);
+
+            process.context.0 = *state;
+            process.context.1 = *regs;
+            process.info.set_state(TaskState::Waiting);
+            process.update_info();
+
+            process
+        });
+
+        match status {
+            wait::Status::Blocked => {
+                let mut processes = PROCESSES.lock();
+                self.next_task(&mut processes, state, regs, Some(prev), Reason::Wait);
+
+                None
+            }
+
+            wait::Status::Exited(code) => Some(Ok(Success::Value(usize::try_from(code).unwrap()))),
+            wait::Status::NotFound => Some(Err(Error::NoSuchTask)),
+        }
+    }
+
+    /// Blocks the current task on the futex word at `address` if it holds `expected`, and schedules the next task in
+    /// its place.
+    ///
+    /// Returns the result of the wait if it doesn't block; otherwise, the result is written into the task's registers
+    /// as it's woken (see [`futex::wake`]).
+    pub fn futex_wait_task(
+        &mut self,
+        state: &mut State,
+        regs: &mut Registers,
+        address: Address<Virtual>,
+        expected: u32,
+    ) -> Option<libsys::syscall::Result> {
+        use libsys::syscall::Error;
+
+        debug_assert!(!crate::interrupts::are_enabled());
+
+        self.reap();
+
+        let Some(process) = self.task.as_ref() else { return Some(Err(Error::NoActiveTask)) };
+        let prev = process.id();
+        // Refused for the same reason as in `wait_task`.
+        if process.reservation().is_some() {
+            return Some(Err(Error::Unsupported));
+        }
+
+        let blocked = futex::with_queues(|queues| -> Result<(), Error> {
+            let (key, value) = {
+                let address_space = self.task.as_ref().unwrap().process().address_space();
+                let key = futex::Key::new(&address_space, address).ok_or(Error::UnmappedMemory)?;
+
+                let mut value = [0u8; core::mem::size_of::<u32>()];
+                address_space.read_mapped(address, &mut value).map_err(|_| Error::UnmappedMemory)?;
+
+                (key, u32::from_ne_bytes(value))
+            };
+
+            if value != expected {
+                return Err(Error::WouldBlock);
+            }
+
+            self.advance(1);
+
+            let mut process = self.task.take().unwrap();
+            trace!("Task {:?} waiting on futex {:X?}", process.id(), key);
+
+            process.context.0 = *state;
+            process.context.1 = *regs;
+            process.info.set_state(TaskState::Waiting);
+            process.update_info();
+
+            queues.push(key, process);
+
+            Ok(())
+        });
+
+        match blocked {
+            Ok(()) => {
+                let mut processes = PROCESSES.lock();
+                self.next_task(&mut processes, state, regs, Some(prev), Reason::FutexWait);
+
+                None
+            }
+
+            Err(err) => Some(Err(err)),
+        }
+    }
+
+    /// Wakes at most `count` of the tasks blocked on the futex word at `address` in the current task's address space,
+    /// returning how many were woken.
+    pub fn futex_wake(&mut self, address: Address<Virtual>, count: usize) -> libsys::syscall::Result<usize> {
+        let process = self.task.as_ref().ok_or(libsys::syscall::Error::NoActiveTask)?;
+        let key = futex::Key::new(&process.process().address_space(), address)
+            .ok_or(libsys::syscall::Error::UnmappedMemory)?;
+
+        let woken = futex::wake(key, count);
+        let count = woken.len();
 
         let mut processes = PROCESSES.lock();
-        self.next_task(&mut processes, state, regs);
+        for waiter in woken {
+            trace::record_wakeup(waiter.id(), Reason::FutexWake);
+            self.requeue(&mut processes, waiter, false);
+        }
+
+        Ok(count)
+    }
+
+    /// Releases the tasks which have exited on this core.
+    fn reap(&mut self) {
+        for task in self.exited.drain(..) {
+            // An exited task's address space stays active until another task's is switched into.
+            if task.process().address_space().is_current() {
+                // Safety: The kernel's page tables map the whole of the kernel, and nothing refers to the task's
+                //         userspace memory once it has exited.
+                crate::mem::with_kmapper(|kmapper| unsafe { kmapper.swap_into() });
+            }
+
+            trace!("Reaping task: {:?}", task.id());
+        }
+    }
+
+    /// Advances the core's tick count, charging the elapsed ticks to the current task (and its reservation).
+    fn advance(&mut self, ticks: u16) {
+        self.ticks += u64::from(ticks);
+        crate::time::sample();
+
+        if let Some(task) = self.task.as_mut() {
+            task.info.charge(ticks);
+            group::charge(task.info.group(), ticks);
+
+            if let Some(reservation) = task.reservation.as_mut() {
+                reservation.charge(ticks);
+            }
+        }
+    }
+
+    /// Queues `task` in its class, ahead of the other tasks of its priority if `front`.
+    fn requeue(&mut self, processes: &mut RunQueue, task: Task, front: bool) {
+        task.info.set_state(TaskState::Ready);
+        task.update_info();
+
+        if task.reservation.is_some() {
+            self.deadline_tasks.push(task);
+        } else if front {
+            processes.push_front(task);
+        } else {
+            processes.push_back(task);
+        }
     }
 
-    fn next_task(&mut self, processes: &mut VecDeque<Task>, state: &mut State, regs: &mut Registers) {
+    /// Requeues every sleeping task whose sleep has elapsed.
+    fn wake_sleeping(&mut self, processes: &mut RunQueue) {
+        let mut woken = Vec::new();
+        self.sleeping.expire(self.ticks, |task| woken.push(task));
+
+        for task in woken {
+            trace::record_wakeup(task.id(), Reason::Wake);
+            self.requeue(processes, task, false);
+        }
+    }
+
+    /// Removes the runnable deadline task with the earliest deadline, if any.
+    fn pop_deadline_task(&mut self) -> Option<Task> {
+        let now = self.ticks;
+
+        for task in &mut self.deadline_tasks {
+            let id = task.id();
+            let Some(reservation) = task.reservation.as_mut() else { continue };
+
+            let was_runnable = reservation.is_runnable();
+            reservation.replenish(now);
+            if !was_runnable && reservation.is_runnable() {
+                trace::record_wakeup(id, Reason::Replenish);
+            }
+        }
+
+        let index = self
+            .deadline_tasks
+            .iter()
+            .enumerate()
+            .filter_map(|(index, task)| task.reservation.map(|reservation| (index, reservation)))
+            .filter(|(_, reservation)| reservation.is_runnable())
+            .min_by_key(|(_, reservation)| reservation.deadline())
+            .map(|(index, _)| index)?;
+
+        Some(self.deadline_tasks.swap_remove(index))
+    }
+
+    /// Computes the preemption wait for `next_task`, such that budgets and time slices are enforced,
+    /// no deadline task misses the start of its next period, and no sleeping task oversleeps.
+    fn next_slice(&self, next_task: Option<&Task>) -> NonZeroU16 {
+        let now = self.ticks;
+
+        let slice = match next_task {
+            Some(task) => task.reservation().map_or_else(
+                || class(task.policy()).time_slice(task.priority()).map_or(u16::MAX, NonZeroU16::get),
+                deadline::Reservation::remaining,
+            ),
+            None => time_slice(Priority::Idle).get(),
+        };
+
+        let until_replenish = self
+            .deadline_tasks
+            .iter()
+            .filter_map(Task::reservation)
+            .filter(|reservation| !reservation.is_runnable())
+            .map(|reservation| reservation.until_replenish(now))
+            .min()
+            .unwrap_or(u64::MAX);
+
+        let until_wake = self.sleeping.until_next(now).unwrap_or(u64::MAX);
+
+        let slice = u64::from(slice).min(until_replenish).min(until_wake);
+        NonZeroU16::new(u16::try_from(slice).unwrap_or(u16::MAX)).unwrap_or(NonZeroU16::MIN)
+    }
+
+    /// Switches to the next task, where `prev` is the task switched away from (for tracing, as it has already been
+    /// requeued), and `reason` why it was switched away from.
+    fn next_task(
+        &mut self,
+        processes: &mut RunQueue,
+        state: &mut State,
+        regs: &mut Registers,
+        prev: Option<Tid>,
+        reason: Reason,
+    ) {
+        self.wake_sleeping(processes);
+
+        // Deadline tasks always take precedence over the fair class.
+        let next_process = self.pop_deadline_task().or_else(|| processes.pop_front());
+        let slice = self.next_slice(next_process.as_ref());
+        trace::record_switch(prev, next_process.as_ref().map(Task::id), reason);
+
         // Pop a new task from the task queue, or simply switch in the idle task.
-        if let Some(next_process) = processes.pop_front() {
+        if let Some(next_process) = next_process {
+            next_process.info.set_state(TaskState::Running);
             *state = next_process.context.0;
             *regs = next_process.context.1;
 
-            if !next_process.address_space.is_current() {
+            let address_space = next_process.process().address_space();
+            if !address_space.is_current() {
                 // Safety: New task requires its own address space.
                 unsafe {
-                    next_process.address_space.swap_into();
+                    address_space.swap_into();
                 }
             }
+            drop(address_space);
+
+            crate::interrupts::stacks::set_running(next_process.kernel_stack());
 
             trace!("Switched task: {:?}", next_process.id());
             let old_value = self.task.replace(next_process);
@@ -115,16 +696,18 @@ impl Scheduler {
             );
             *regs = Registers::default();
 
+            let idle_stack = self.idle_stack.as_ptr_range();
+            crate::interrupts::stacks::set_running(Some(idle_stack.start.addr()..idle_stack.end.addr()));
+
             trace!("Switched idle task.");
         };
 
-        // TODO have some kind of queue of preemption waits, to ensure we select the shortest one.
         // Safety: Just having switched tasks, no preemption wait should supercede this one.
         unsafe {
-            const TIME_SLICE: core::num::NonZeroU16 = core::num::NonZeroU16::new(5).unwrap();
-
-            crate::cpu::state::set_preemption_wait(TIME_SLICE).unwrap();
+            crate::cpu::state::set_preemption_wait(slice).unwrap();
         }
+
+        self.slice = slice;
     }
 }
 
@@ -0,0 +1,59 @@
+//! Compact, recycled task identifiers.
+
+use crate::interrupts::InterruptCell;
+use libkernel::IdAllocator;
+use spin::Mutex;
+
+/// Identifier of a live task.
+///
+/// #### Remark
+///
+/// IDs are recycled once their task is dropped, so they're only unique among live tasks. For an identifier which is
+/// unique across the lifetime of the system, see [`super::Task::alias`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Tid(u32);
+
+impl Tid {
+    /// Reinterprets a raw ID (i.e. from userspace), which may not belong to a live task.
+    #[inline]
+    pub(super) const fn from_raw(id: u32) -> Self {
+        Self(id)
+    }
+
+    #[inline]
+    pub const fn get(self) -> u32 {
+        self.0
+    }
+}
+
+impl core::fmt::Display for Tid {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Allocator of IDs, which reuses those released by dropped tasks before allocating new ones.
+static IDS: InterruptCell<Mutex<IdAllocator>> = InterruptCell::new(Mutex::new(IdAllocator::new()));
+
+/// Ownership of an allocated [`Tid`], which is released back to the allocator on drop.
+#[derive(Debug)]
+pub(super) struct Allocation(Tid);
+
+impl Allocation {
+    pub fn new() -> Self {
+        let id = IDS.with(|ids| ids.lock().allocate()).expect("task IDs exhausted");
+
+        Self(Tid(id))
+    }
+
+    #[inline]
+    pub const fn tid(&self) -> Tid {
+        self.0
+    }
+}
+
+impl Drop for Allocation {
+    fn drop(&mut self) {
+        IDS.with(|ids| ids.lock().release(self.0 .0));
+    }
+}
@@ -0,0 +1,105 @@
+//! Scheduler tracing: context switches and wakeups, recorded into a bounded buffer which userspace drains through
+//! `/dev/sched_trace` (see [`libsys::syscall::sched_trace`]).
+
+use super::Tid;
+use crate::interrupts::InterruptCell;
+use alloc::collections::VecDeque;
+use core::sync::atomic::{AtomicBool, Ordering};
+use libsys::syscall::sched_trace::{Reason, Record, RecordKind, IDLE};
+use spin::Mutex;
+
+/// Number of records held before the oldest are discarded.
+const CAPACITY: usize = 8192;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static RECORDS: InterruptCell<Mutex<VecDeque<Record>>> = InterruptCell::new(Mutex::new(VecDeque::new()));
+
+fn push(kind: RecordKind, reason: Reason, prev: u32, next: u32) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+
+    let record = Record {
+        timestamp_ms: crate::time::coarse_ms(),
+        core: crate::cpu::state::get_core_id().unwrap_or(u32::MAX),
+        kind: kind as u16,
+        reason: reason as u16,
+        prev,
+        next,
+    };
+
+    RECORDS.with(|records| {
+        let mut records = records.lock();
+        // The buffer is reserved up-front, so the scheduler never allocates here.
+        if records.len() == CAPACITY {
+            records.pop_front();
+        }
+
+        records.push_back(record);
+    });
+}
+
+/// Records a context switch on the local core, where `None` is the idle task.
+pub fn record_switch(prev: Option<Tid>, next: Option<Tid>, reason: Reason) {
+    push(RecordKind::Switch, reason, prev.map_or(IDLE, Tid::get), next.map_or(IDLE, Tid::get));
+}
+
+/// Records that `task` became runnable.
+pub fn record_wakeup(task: Tid, reason: Reason) {
+    push(RecordKind::Wakeup, reason, IDLE, task.get());
+}
+
+/// Reserves the trace buffer, and registers the trace device node, `/dev/sched_trace`.
+pub fn init() -> crate::vfs::Result<()> {
+    RECORDS.with(|records| records.lock().reserve_exact(CAPACITY));
+
+    crate::vfs::devfs::register("sched_trace", alloc::sync::Arc::new(Device))
+}
+
+struct Device;
+
+impl crate::vfs::Node for Device {
+    fn metadata(&self) -> crate::vfs::Metadata {
+        crate::vfs::Metadata {
+            kind: crate::vfs::NodeKind::Device,
+            size: 0,
+            mode: libsys::syscall::fs::Mode::from_bits_retain(0o600),
+            owner: crate::vfs::Owner::ROOT,
+            accessed: 0,
+            modified: 0,
+            created: 0,
+        }
+    }
+
+    /// Dequeues as many whole records as fit in `buf`.
+    fn read(&self, _offset: usize, buf: &mut [u8]) -> crate::vfs::Result<usize> {
+        const RECORD_LEN: usize = core::mem::size_of::<Record>();
+
+        if buf.len() < RECORD_LEN {
+            return Err(crate::vfs::Error::BufferTooSmall);
+        }
+
+        RECORDS.with(|records| {
+            let mut records = records.lock();
+
+            let mut written = 0;
+            for chunk in buf.chunks_exact_mut(RECORD_LEN) {
+                let Some(record) = records.pop_front() else { break };
+
+                // Safety: `chunk` is exactly the size of `Record`, and unaligned writes are used.
+                unsafe { chunk.as_mut_ptr().cast::<Record>().write_unaligned(record) };
+                written += RECORD_LEN;
+            }
+
+            Ok(written)
+        })
+    }
+
+    /// Enables tracing if the last byte of `buf` is non-zero, or disables it otherwise.
+    fn write(&self, _offset: usize, buf: &[u8]) -> crate::vfs::Result<usize> {
+        let &enable = buf.last().ok_or(crate::vfs::Error::InvalidArgument)?;
+        ENABLED.store(enable != 0, Ordering::Relaxed);
+
+        Ok(buf.len())
+    }
+}
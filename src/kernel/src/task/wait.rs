@@ -0,0 +1,94 @@
+//! Tasks blocked until another task exits (see [`libsys::syscall::task::wait`]), and the exit codes which exited
+//! tasks leave for their parents.
+//!
+//! A task's exit code is handed to each task waiting on it as it exits. If its parent wasn't among them, the code is
+//! kept until the parent collects it with a wait of its own (or exits itself).
+
+use super::{registry, Task, Tid};
+use crate::interrupts::InterruptCell;
+use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
+use libsys::syscall::{task::State, Success};
+use spin::Mutex;
+
+struct Exited {
+    parent: Tid,
+    code: u32,
+    /// Held so that the task's ID (and alias) isn't recycled until its exit code is collected.
+    _info: Arc<registry::Info>,
+}
+
+struct Queues {
+    /// Tasks blocked on the exit of each task, keyed on its ID.
+    waiters: BTreeMap<Tid, Vec<Task>>,
+    /// Exit codes of exited tasks yet to be collected by their parents, keyed on the task's ID.
+    exited: BTreeMap<Tid, Exited>,
+}
+
+static QUEUES: InterruptCell<Mutex<Queues>> =
+    InterruptCell::new(Mutex::new(Queues { waiters: BTreeMap::new(), exited: BTreeMap::new() }));
+
+/// Outcome of [`block`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// The waiter was queued, and is handed back (through [`notify_exit`]) once the task exits.
+    Blocked,
+    /// The task had exited, leaving this exit code for the waiter (its parent).
+    Exited(u32),
+    /// The task isn't live, and left no exit code for the waiter.
+    NotFound,
+}
+
+fn is_live(tid: Tid) -> bool {
+    registry::find(tid).is_some_and(|info| info.state() != State::Exited)
+}
+
+/// Blocks the task `waiter` until the task `target` exits, taking the waiter with `take_waiter`.
+///
+/// #### Remark
+///
+/// The waiter is only taken if it blocks, so that it's never queued after the target's exit is notified.
+pub fn block(target: Tid, waiter: Tid, take_waiter: impl FnOnce() -> Task) -> Status {
+    QUEUES.with(|queues| {
+        let mut queues = queues.lock();
+
+        if queues.exited.get(&target).is_some_and(|exited| exited.parent == waiter) {
+            let exited = queues.exited.remove(&target).unwrap();
+            Status::Exited(exited.code)
+        } else if is_live(target) {
+            queues.waiters.entry(target).or_default().push(take_waiter());
+            Status::Blocked
+        } else {
+            Status::NotFound
+        }
+    })
+}
+
+/// Marks `task` as exited with `code`, returning the tasks which were waiting on it, with the exit code written as the
+/// result of their waits.
+pub fn notify_exit(task: &Task, code: u32) -> Vec<Task> {
+    let tid = task.id();
+
+    QUEUES.with(|queues| {
+        let mut queues = queues.lock();
+
+        // Marked under the lock, so that no waiter blocks after its waiters are taken.
+        task.info.set_state(State::Exited);
+
+        // Exit codes left for the task can no longer be collected.
+        queues.exited.retain(|_, exited| exited.parent != tid);
+
+        let mut waiters = queues.waiters.remove(&tid).unwrap_or_default();
+        for waiter in &mut waiters {
+            let result = Ok(Success::Value(usize::try_from(code).unwrap()));
+            crate::interrupts::traps::write_result(&mut waiter.context.1, result);
+        }
+
+        // The parent's ID is held by the task, so it can't have been recycled, only exited.
+        let parent = task.info.parent().filter(|&parent| !waiters.iter().any(|waiter| waiter.id() == parent));
+        if let Some(parent) = parent.filter(|_| task.info.parent_is_live()) {
+            queues.exited.insert(tid, Exited { parent, code, _info: Arc::clone(&task.info) });
+        }
+
+        waiters
+    })
+}
@@ -0,0 +1,39 @@
+//! Timer wheel of sleeping tasks, keyed on the scheduler tick at which each wakes.
+//!
+//! Sleeps are filed in constant time, and expiring the sleeps of a tick only visits its slot (see
+//! [`libkernel::TimerWheel`]). Each sleep is charged against its process's quota of sleep timers until it wakes.
+
+use super::{quota::Resource, Task};
+
+pub struct TimerWheel(libkernel::TimerWheel<Task>);
+
+impl TimerWheel {
+    pub const fn new() -> Self {
+        Self(libkernel::TimerWheel::new())
+    }
+
+    #[inline]
+    pub const fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Files `task` to wake at the tick `wake`, charging it against its process's quota of sleep timers until it does.
+    pub fn insert(&mut self, wake: u64, task: Task) {
+        task.process().usage().charge(Resource::SleepTimers);
+        self.0.insert(wake, task);
+    }
+
+    /// Removes every task whose wake tick is at or before `now`, passing each to `wake`.
+    pub fn expire(&mut self, now: u64, mut wake: impl FnMut(Task)) {
+        self.0.expire(now, |task| {
+            task.process().usage().release(Resource::SleepTimers);
+            wake(task);
+        });
+    }
+
+    /// Number of ticks from `now` until the earliest wake, if any task is sleeping.
+    #[inline]
+    pub fn until_next(&self, now: u64) -> Option<u64> {
+        self.0.until_next(now)
+    }
+}
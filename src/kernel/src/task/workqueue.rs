@@ -0,0 +1,212 @@
+//! Workqueues: functions queued to run later in task context, by a shared pool of kernel tasks.
+//!
+//! Each queue limits how many of its items run at once, so a subsystem can queue work freely without occupying the
+//! whole pool (and with a limit of one, its items run one at a time, in the order they became pending). The pool's
+//! size is set from the kernel parameters (`--workers=`).
+//!
+//! Queueing an item allocates it, so contexts which mustn't allocate (i.e. interrupt handlers) instead kick a
+//! [`StaticWork`], allocated up front.
+
+use alloc::{boxed::Box, collections::VecDeque, format, string::String, sync::Arc, vec::Vec};
+use core::{
+    num::NonZeroUsize,
+    sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    time::Duration,
+};
+use spin::{Mutex, Once, RwLock};
+
+type Work = Box<dyn FnOnce() + Send>;
+
+struct State {
+    pending: VecDeque<Work>,
+    /// Delayed items, with the coarse millisecond (see [`crate::time::coarse_ms`]) at which each becomes pending.
+    delayed: Vec<(u64, Work)>,
+    /// Items being run by workers.
+    active: usize,
+}
+
+/// A work item allocated up front, which is queued by kicking it. Kicks made before it runs are merged into one run.
+pub struct StaticWork {
+    func: fn(),
+    kicked: AtomicBool,
+}
+
+impl StaticWork {
+    pub const fn new(func: fn()) -> Self {
+        Self { func, kicked: AtomicBool::new(false) }
+    }
+
+    /// Makes the item pending on the queue it's registered with (see [`Workqueue::register`]), without allocating.
+    #[inline]
+    pub fn kick(&self) {
+        self.kicked.store(true, Ordering::Release);
+    }
+}
+
+pub struct Workqueue {
+    name: String,
+    max_active: NonZeroUsize,
+    state: Mutex<State>,
+    /// Items which are pending while kicked.
+    statics: RwLock<Vec<&'static StaticWork>>,
+    completed: AtomicU64,
+}
+
+impl Workqueue {
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Queues `work` to be run by the next free worker.
+    pub fn queue(&self, work: impl FnOnce() + Send + 'static) {
+        self.state.lock().pending.push_back(Box::new(work));
+    }
+
+    /// Queues `work` to become pending once `delay` has elapsed.
+    ///
+    /// #### Remark
+    ///
+    /// Delays are measured against the coarse clock, so work may run up to a scheduler tick late.
+    pub fn queue_delayed(&self, work: impl FnOnce() + Send + 'static, delay: Duration) {
+        let delay_ms = u64::try_from(delay.as_millis()).unwrap_or(u64::MAX);
+        let due = crate::time::coarse_ms().saturating_add(delay_ms);
+
+        self.state.lock().delayed.push((due, Box::new(work)));
+    }
+
+    /// Registers `work`, so that kicking it makes it pending on this queue.
+    pub fn register(&self, work: &'static StaticWork) {
+        self.statics.write().push(work);
+    }
+
+    /// Makes pending the delayed items due by `now`, then takes the first pending item, unless the queue's limit of
+    /// active items is reached.
+    fn take(&self, now: u64) -> Option<Work> {
+        let mut state = self.state.lock();
+
+        let mut index = 0;
+        while index < state.delayed.len() {
+            if state.delayed[index].0 <= now {
+                let (_, work) = state.delayed.remove(index);
+                state.pending.push_back(work);
+            } else {
+                index += 1;
+            }
+        }
+
+        if state.active >= self.max_active.get() {
+            return None;
+        }
+
+        // Kicked items are taken first, as they're kicked where nothing else could be queued.
+        let kicked = self.statics.read().iter().find(|work| work.kicked.swap(false, Ordering::AcqRel)).copied();
+        let work: Work = match kicked {
+            Some(work) => Box::new(work.func),
+            None => state.pending.pop_front()?,
+        };
+        state.active += 1;
+
+        Some(work)
+    }
+
+    fn finish(&self) {
+        self.state.lock().active -= 1;
+        self.completed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn render(&self) -> String {
+        let state = self.state.lock();
+
+        format!(
+            "pending={} delayed={} active={} max_active={} completed={}",
+            state.pending.len(),
+            state.delayed.len(),
+            state.active,
+            self.max_active,
+            self.completed.load(Ordering::Relaxed)
+        )
+    }
+}
+
+static QUEUES: RwLock<Vec<Arc<Workqueue>>> = RwLock::new(Vec::new());
+/// Queue which workers start searching for work from, rotated so no queue is always searched last.
+static NEXT_QUEUE: AtomicUsize = AtomicUsize::new(0);
+
+/// Creates a workqueue which runs at most `max_active` of its items at once, and publishes its statistics under
+/// `workqueue/<name>`.
+pub fn create(name: &str, max_active: NonZeroUsize) -> Arc<Workqueue> {
+    let queue = Arc::new(Workqueue {
+        name: String::from(name),
+        max_active,
+        state: Mutex::new(State { pending: VecDeque::new(), delayed: Vec::new(), active: 0 }),
+        statics: RwLock::new(Vec::new()),
+        completed: AtomicU64::new(0),
+    });
+    QUEUES.write().push(queue.clone());
+
+    crate::sysfs::set(&format!("workqueue/{name}"), "stat", {
+        let queue = Arc::downgrade(&queue);
+
+        crate::sysfs::Value::Dynamic(Box::new(move || queue.upgrade().map(|queue| queue.render()).unwrap_or_default()))
+    });
+
+    queue
+}
+
+/// Shared queue, for work which needs no limit of its own.
+pub fn system() -> &'static Arc<Workqueue> {
+    static SYSTEM: Once<Arc<Workqueue>> = Once::new();
+
+    SYSTEM.call_once(|| create("system", NonZeroUsize::new(4).unwrap()))
+}
+
+/// Queues `work` on the [`system`] queue.
+#[inline]
+pub fn queue_work(work: impl FnOnce() + Send + 'static) {
+    system().queue(work);
+}
+
+/// Queues `work` on the [`system`] queue, to become pending once `delay` has elapsed.
+#[inline]
+pub fn queue_delayed_work(work: impl FnOnce() + Send + 'static, delay: Duration) {
+    system().queue_delayed(work, delay);
+}
+
+/// Takes the next item able to run, along with its queue.
+fn next() -> Option<(Arc<Workqueue>, Work)> {
+    let now = crate::time::coarse_ms();
+    let queues = QUEUES.read();
+    let start = NEXT_QUEUE.fetch_add(1, Ordering::Relaxed);
+
+    queues
+        .iter()
+        .cycle()
+        .skip(start % queues.len().max(1))
+        .take(queues.len())
+        .find_map(|queue| queue.take(now).map(|work| (queue.clone(), work)))
+}
+
+/// Spawns the pool of workers.
+pub fn init() {
+    use crate::task::{Priority, Task};
+
+    extern "C" fn worker() -> ! {
+        loop {
+            match next() {
+                Some((queue, work)) => {
+                    work();
+                    queue.finish();
+                }
+
+                None => crate::interrupts::wait(),
+            }
+        }
+    }
+
+    for index in 0..crate::init::get().workers {
+        let task = Task::kernel(worker, NonZeroUsize::new(0x4000).unwrap(), Priority::Normal);
+        task.set_name(&format!("kworker/{index}"));
+        crate::task::spawn(task);
+    }
+}
@@ -48,43 +48,326 @@ mod clock {
                 Type::Acpi(_) => {}
             }
         }
+    }
 
+    impl super::ClockSource for Clock<'_> {
         #[inline]
-        pub const fn frequency(&self) -> u64 {
+        fn frequency(&self) -> u64 {
             self.frequency
         }
 
         #[inline]
-        pub const fn max_timestamp(&self) -> u64 {
+        fn max_timestamp(&self) -> u64 {
             self.max_timestamp
         }
 
         #[inline]
-        pub fn get_timestamp(&self) -> u64 {
+        fn get_timestamp(&self) -> u64 {
             match &self.ty {
                 Type::Acpi(register) => u64::from(register.read()),
             }
         }
-
-        /// Spin-waits for the given number of microseconds.
-        pub fn spin_wait_us(&self, microseconds: u32) {
-            let ticks_per_us = self.frequency() / 1000000;
-            let mut total_ticks = u64::from(microseconds) * ticks_per_us;
-            let mut current_tick = self.get_timestamp();
-
-            while total_ticks > 0 {
-                let new_tick = self.get_timestamp();
-                total_ticks -= (new_tick.wrapping_sub(current_tick) & self.max_timestamp()).min(total_ticks);
-                current_tick = new_tick;
-
-                core::hint::spin_loop();
-            }
-        }
     }
 }
 
+use crate::interrupts::InterruptCell;
+use alloc::{boxed::Box, format, string::String};
+use core::{
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    time::Duration,
+};
+use spin::Mutex;
+
 pub(self) const US_PER_SEC: u32 = 1000000;
 pub(self) const US_WAIT: u32 = 10000;
 pub(self) const US_FREQ_FACTOR: u32 = US_PER_SEC / US_WAIT;
 
+/// Frequency of the scheduler timer, in hertz.
+pub const TIMER_FREQUENCY: u16 = 1000;
+
 pub use clock::*;
+
+/// A free-running counter, against which elapsed time is measured.
+pub trait ClockSource: Sync {
+    /// Ticks of the counter per second.
+    fn frequency(&self) -> u64;
+
+    /// Greatest timestamp before the counter wraps (which is always a mask of its valid bits).
+    fn max_timestamp(&self) -> u64;
+
+    fn get_timestamp(&self) -> u64;
+
+    /// Ticks elapsed from `since` until now, accounting for (at most one) wrap of the counter.
+    #[inline]
+    fn elapsed(&self, since: u64) -> u64 {
+        self.get_timestamp().wrapping_sub(since) & self.max_timestamp()
+    }
+
+    /// Spin-waits for the given number of microseconds.
+    fn spin_wait_us(&self, microseconds: u32) {
+        let ticks_per_us = self.frequency() / 1000000;
+        let mut total_ticks = u64::from(microseconds) * ticks_per_us;
+        let mut current_tick = self.get_timestamp();
+
+        while total_ticks > 0 {
+            let new_tick = self.get_timestamp();
+            total_ticks -= (new_tick.wrapping_sub(current_tick) & self.max_timestamp()).min(total_ticks);
+            current_tick = new_tick;
+
+            core::hint::spin_loop();
+        }
+    }
+}
+
+/// A clock which only advances when told to, so code measuring time against it runs deterministically (i.e. when
+/// exercising timeouts, or the ordering of expiries).
+pub struct VirtualClock {
+    frequency: u64,
+    timestamp: core::sync::atomic::AtomicU64,
+}
+
+impl VirtualClock {
+    pub const fn new(frequency: u64) -> Self {
+        Self { frequency, timestamp: core::sync::atomic::AtomicU64::new(0) }
+    }
+
+    /// Advances the clock by `ticks`.
+    #[inline]
+    pub fn advance(&self, ticks: u64) {
+        self.timestamp.fetch_add(ticks, core::sync::atomic::Ordering::Relaxed);
+    }
+
+    /// Advances the clock by `microseconds`.
+    #[inline]
+    pub fn advance_us(&self, microseconds: u64) {
+        self.advance((microseconds * self.frequency) / u64::from(US_PER_SEC));
+    }
+}
+
+impl ClockSource for VirtualClock {
+    #[inline]
+    fn frequency(&self) -> u64 {
+        self.frequency
+    }
+
+    #[inline]
+    fn max_timestamp(&self) -> u64 {
+        u64::MAX
+    }
+
+    #[inline]
+    fn get_timestamp(&self) -> u64 {
+        self.timestamp.load(core::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// Advances the clock by `microseconds`, rather than waiting for it to.
+    fn spin_wait_us(&self, microseconds: u32) {
+        self.advance_us(u64::from(microseconds));
+    }
+}
+
+/// Ticks of the system clock elapsed since boot, counted across its wraps.
+struct Monotonic {
+    last_timestamp: u64,
+    ticks: u64,
+}
+
+static MONOTONIC: InterruptCell<Mutex<Option<Monotonic>>> = InterruptCell::new(Mutex::new(None));
+/// Uptime as of the latest sample of the system clock, in nanoseconds.
+static LAST_UPTIME_NS: AtomicU64 = AtomicU64::new(0);
+static IS_INITIALIZED: AtomicBool = AtomicBool::new(false);
+/// Wall-clock time at boot, since the Unix epoch.
+static BOOT_TIME: spin::Once<Duration> = spin::Once::new();
+
+/// Anchors uptime to the system clock, and the wall clock (if the firmware provides one) to uptime.
+pub fn init() {
+    let uptime = uptime();
+    IS_INITIALIZED.store(true, Ordering::Release);
+
+    match crate::efi::get_time().map(|time| unix_time(&time)) {
+        Ok(Some(now)) => set_wall_clock(now, uptime),
+        Ok(None) => warn!("Firmware wall clock time is malformed."),
+        Err(err) => debug!("No firmware wall clock is available: {:?}", err),
+    }
+
+    crate::sysfs::set("time", "uptime", crate::sysfs::Value::Dynamic(Box::new(|| format!("{}", Seconds(uptime())))));
+    crate::sysfs::set(
+        "time",
+        "boot_time",
+        crate::sysfs::Value::Dynamic(Box::new(|| {
+            boot_time().map_or_else(|| String::from("unknown"), |boot_time| format!("{}", DateTime(boot_time)))
+        })),
+    );
+    crate::sysfs::set(
+        "time",
+        "now",
+        crate::sysfs::Value::Dynamic(Box::new(|| {
+            now().map_or_else(|| String::from("unknown"), |now| format!("{}", DateTime(now)))
+        })),
+    );
+}
+
+/// Anchors the wall clock to uptime, from `now` as read at `uptime`, unless a wall clock was already read.
+///
+/// #### Remark
+///
+/// The firmware's wall clock is read by [`init`]. Platforms without one read a real-time clock device instead (i.e. a
+/// RISC-V board's Goldfish RTC).
+pub fn set_wall_clock(now: Duration, uptime: Duration) {
+    let mut is_set = false;
+    let boot_time = BOOT_TIME.call_once(|| {
+        is_set = true;
+        now.saturating_sub(uptime)
+    });
+
+    if is_set {
+        debug!("Booted at {}.", DateTime(*boot_time));
+    }
+}
+
+/// Time elapsed since boot (i.e. since the system clock was first sampled).
+///
+/// #### Remark
+///
+/// The system clock wraps (every few seconds, for a 24-bit ACPI PM timer), so it must be sampled at least once per
+/// wrap to be counted across them. Each scheduler tick samples it.
+pub fn uptime() -> Duration {
+    MONOTONIC.with(|monotonic| advance(&mut monotonic.lock()))
+}
+
+/// Counts the ticks of the system clock since `monotonic` was last advanced, returning the uptime.
+fn advance(monotonic: &mut Option<Monotonic>) -> Duration {
+    let timestamp = SYSTEM_CLOCK.get_timestamp();
+    let monotonic = monotonic.get_or_insert(Monotonic { last_timestamp: timestamp, ticks: 0 });
+    monotonic.ticks += timestamp.wrapping_sub(monotonic.last_timestamp) & SYSTEM_CLOCK.max_timestamp();
+    monotonic.last_timestamp = timestamp;
+
+    let nanos = (u128::from(monotonic.ticks) * 1_000_000_000) / u128::from(SYSTEM_CLOCK.frequency());
+    let uptime_ns = u64::try_from(nanos).unwrap_or(u64::MAX);
+    LAST_UPTIME_NS.fetch_max(uptime_ns, Ordering::Relaxed);
+
+    Duration::from_nanos(uptime_ns)
+}
+
+/// Uptime, unless sampling the system clock would wait (i.e. on another core sampling it), in which case the latest
+/// sample is returned. Before [`init`], this is zero.
+///
+/// #### Remark
+///
+/// This is for contexts which can't wait on the clock, such as logging (which may happen while it's being sampled).
+pub fn uptime_nowait() -> Duration {
+    IS_INITIALIZED
+        .load(Ordering::Acquire)
+        .then(|| MONOTONIC.with(|monotonic| monotonic.try_lock().map(|mut monotonic| advance(&mut monotonic))))
+        .flatten()
+        .unwrap_or_else(|| Duration::from_nanos(LAST_UPTIME_NS.load(Ordering::Relaxed)))
+}
+
+/// Samples the system clock, so that uptime is counted across its wraps.
+#[inline]
+pub fn sample() {
+    if IS_INITIALIZED.load(Ordering::Acquire) {
+        uptime();
+    }
+}
+
+/// Coarse milliseconds elapsed since boot, as of the latest sample of the system clock.
+#[inline]
+pub fn coarse_ms() -> u64 {
+    LAST_UPTIME_NS.load(Ordering::Relaxed) / 1_000_000
+}
+
+/// Wall-clock time at boot, since the Unix epoch, or `None` if no wall clock is available.
+#[inline]
+pub fn boot_time() -> Option<Duration> {
+    BOOT_TIME.get().copied()
+}
+
+/// Current wall-clock time, since the Unix epoch, or `None` if no wall clock is available.
+pub fn now() -> Option<Duration> {
+    boot_time().map(|boot_time| boot_time + uptime())
+}
+
+/// Days from the Unix epoch to the (proleptic Gregorian) date `year`-`month`-`day`.
+const fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let day_of_year = (((153 * (if month > 2 { month - 3 } else { month + 9 })) + 2) / 5) + day - 1;
+    let day_of_era = (year_of_era * 365) + (year_of_era / 4) - (year_of_era / 100) + day_of_year;
+
+    (era * 146_097) + day_of_era - 719_468
+}
+
+/// Date (`year`, `month`, `day`) of the day `days` after the Unix epoch.
+const fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - (day_of_era / 1460) + (day_of_era / 36524) - (day_of_era / 146_096)) / 365;
+    let day_of_year = day_of_era - ((365 * year_of_era) + (year_of_era / 4) - (year_of_era / 100));
+    let month_index = ((5 * day_of_year) + 2) / 153;
+    let day = day_of_year - (((153 * month_index) + 2) / 5) + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = (era * 400) + year_of_era + (if month <= 2 { 1 } else { 0 });
+
+    (year, month, day)
+}
+
+/// Converts the firmware's time to time since the Unix epoch (in UTC), or `None` if it's malformed or predates the
+/// epoch.
+fn unix_time(time: &crate::efi::Time) -> Option<Duration> {
+    /// Time zone of a time which is local, with an unspecified offset (taken to be UTC).
+    const UNSPECIFIED_TIME_ZONE: i16 = 0x7FF;
+
+    let is_valid = (1..=12).contains(&time.month)
+        && (1..=31).contains(&time.day)
+        && time.hour < 24
+        && time.minute < 60
+        && time.second < 60
+        && time.nanosecond < 1_000_000_000;
+    if !is_valid {
+        return None;
+    }
+
+    let days = days_from_civil(i64::from(time.year), i64::from(time.month), i64::from(time.day));
+    let seconds =
+        (days * 86_400) + (i64::from(time.hour) * 3600) + (i64::from(time.minute) * 60) + i64::from(time.second);
+    // The time zone is the local time's offset from UTC, in minutes.
+    let offset = if time.time_zone == UNSPECIFIED_TIME_ZONE { 0 } else { i64::from(time.time_zone) * 60 };
+
+    u64::try_from(seconds - offset).ok().map(|seconds| Duration::new(seconds, time.nanosecond))
+}
+
+/// Formats a duration as seconds, to the millisecond (i.e. `  12.345`), as log records are timestamped.
+#[derive(Debug, Clone, Copy)]
+pub struct Seconds(pub Duration);
+
+impl core::fmt::Display for Seconds {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{:4}.{:03}", self.0.as_secs(), self.0.subsec_millis())
+    }
+}
+
+/// Formats a duration since the Unix epoch as a UTC date and time (i.e. `2024-01-31T12:00:00Z`).
+#[derive(Debug, Clone, Copy)]
+pub struct DateTime(pub Duration);
+
+impl core::fmt::Display for DateTime {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let seconds = self.0.as_secs();
+        let (year, month, day) = civil_from_days(i64::try_from(seconds / 86_400).unwrap());
+        let seconds_of_day = seconds % 86_400;
+
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            year,
+            month,
+            day,
+            seconds_of_day / 3600,
+            (seconds_of_day / 60) % 60,
+            seconds_of_day % 60
+        )
+    }
+}
@@ -0,0 +1,150 @@
+//! PC Screen Fonts (PSF versions 1 and 2), the bitmap fonts of the Linux console.
+
+use alloc::collections::BTreeMap;
+
+crate::error_impl! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Error {
+        /// The data begins with neither PSF magic.
+        UnknownFormat => None,
+        /// The header describes more glyphs (or larger ones) than the data holds.
+        Truncated => None
+    }
+}
+
+const PSF1_MAGIC: [u8; 2] = [0x36, 0x04];
+const PSF1_MODE_512: u8 = 1 << 0;
+const PSF1_MODE_HAS_TABLE: u8 = 1 << 1;
+const PSF1_SEPARATOR: u16 = 0xFFFF;
+const PSF1_SEQUENCE: u16 = 0xFFFE;
+
+const PSF2_MAGIC: [u8; 4] = [0x72, 0xB5, 0x4A, 0x86];
+const PSF2_HAS_TABLE: u32 = 1 << 0;
+const PSF2_SEPARATOR: u8 = 0xFF;
+const PSF2_SEQUENCE: u8 = 0xFE;
+
+pub struct Font {
+    width: usize,
+    height: usize,
+    glyphs: &'static [u8],
+    glyph_count: usize,
+    /// Glyph drawn for each character, if the font has a Unicode table; otherwise characters index glyphs directly.
+    unicode: BTreeMap<char, usize>,
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32> {
+    Ok(u32::from_le_bytes(data.get(offset..(offset + 4)).ok_or(Error::Truncated)?.try_into().unwrap()))
+}
+
+impl Font {
+    pub fn parse(data: &'static [u8]) -> Result<Self> {
+        let font = if data.starts_with(&PSF2_MAGIC) {
+            Self::parse_psf2(data)?
+        } else if data.starts_with(&PSF1_MAGIC) {
+            Self::parse_psf1(data)?
+        } else {
+            return Err(Error::UnknownFormat);
+        };
+
+        if font.width == 0 || font.height == 0 {
+            return Err(Error::Truncated);
+        }
+
+        Ok(font)
+    }
+
+    fn parse_psf1(data: &'static [u8]) -> Result<Self> {
+        const HEADER_LEN: usize = 4;
+
+        let (mode, height) = match data.get(2..HEADER_LEN) {
+            Some(&[mode, height]) => (mode, usize::from(height)),
+            _ => return Err(Error::Truncated),
+        };
+        let glyph_count = if (mode & PSF1_MODE_512) > 0 { 512 } else { 256 };
+        let glyphs = data.get(HEADER_LEN..(HEADER_LEN + (glyph_count * height))).ok_or(Error::Truncated)?;
+
+        let mut unicode = BTreeMap::new();
+        if (mode & PSF1_MODE_HAS_TABLE) > 0 {
+            let table = &data[(HEADER_LEN + glyphs.len())..];
+            let mut entries = table.chunks_exact(2).map(|entry| u16::from_le_bytes([entry[0], entry[1]]));
+
+            for glyph in 0..glyph_count {
+                let mut in_sequence = false;
+                for entry in entries.by_ref() {
+                    match entry {
+                        PSF1_SEPARATOR => break,
+                        PSF1_SEQUENCE => in_sequence = true,
+                        // Sequences (a base character with combining marks) can't be drawn as a single cell.
+                        _ if in_sequence => {}
+                        entry => {
+                            if let Some(char) = char::from_u32(u32::from(entry)) {
+                                unicode.entry(char).or_insert(glyph);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(Self { width: 8, height, glyphs, glyph_count, unicode })
+    }
+
+    fn parse_psf2(data: &'static [u8]) -> Result<Self> {
+        let header_len = usize::try_from(read_u32(data, 8)?).unwrap();
+        let flags = read_u32(data, 12)?;
+        let glyph_count = usize::try_from(read_u32(data, 16)?).unwrap();
+        let glyph_len = usize::try_from(read_u32(data, 20)?).unwrap();
+        let height = usize::try_from(read_u32(data, 24)?).unwrap();
+        let width = usize::try_from(read_u32(data, 28)?).unwrap();
+
+        if glyph_count == 0 || glyph_len < (width.div_ceil(8) * height) {
+            return Err(Error::Truncated);
+        }
+
+        let glyphs_len = glyph_count.checked_mul(glyph_len).ok_or(Error::Truncated)?;
+        let glyphs_end = header_len.checked_add(glyphs_len).ok_or(Error::Truncated)?;
+        let glyphs = data.get(header_len..glyphs_end).ok_or(Error::Truncated)?;
+
+        let mut unicode = BTreeMap::new();
+        if (flags & PSF2_HAS_TABLE) > 0 {
+            let mut table = data[glyphs_end..].split(|&byte| byte == PSF2_SEPARATOR);
+
+            for (glyph, entry) in (0..glyph_count).zip(table.by_ref()) {
+                // Entries are UTF-8, with any sequences (which can't be drawn as a single cell) following the chars.
+                let chars = entry.split(|&byte| byte == PSF2_SEQUENCE).next().unwrap_or_default();
+                for char in core::str::from_utf8(chars).unwrap_or_default().chars() {
+                    unicode.entry(char).or_insert(glyph);
+                }
+            }
+        }
+
+        Ok(Self { width, height, glyphs, glyph_count, unicode })
+    }
+
+    #[inline]
+    pub const fn width(&self) -> usize {
+        self.width
+    }
+
+    #[inline]
+    pub const fn height(&self) -> usize {
+        self.height
+    }
+
+    fn glyph_index(&self, char: char) -> Option<usize> {
+        if self.unicode.is_empty() {
+            usize::try_from(u32::from(char)).ok().filter(|&index| index < self.glyph_count)
+        } else {
+            self.unicode.get(&char).copied()
+        }
+    }
+
+    /// Bitmap of the glyph for `char` (or for `?` if the font has none), as rows of `width.div_ceil(8)` bytes with
+    /// the leftmost pixel in the most significant bit.
+    pub fn glyph(&self, char: char) -> &'static [u8] {
+        let index = self.glyph_index(char).or_else(|| self.glyph_index('?')).unwrap_or(0);
+        let glyph_len = self.glyphs.len() / self.glyph_count;
+
+        &self.glyphs[(index * glyph_len)..][..glyph_len]
+    }
+}
@@ -0,0 +1,118 @@
+//! Line discipline: how a terminal turns typed characters into the input its reader sees.
+
+use alloc::{collections::VecDeque, string::String};
+use libsys::syscall::tty::Mode;
+
+/// Bytes of input held before further characters are dropped.
+const CAPACITY: usize = 4096;
+
+const BACKSPACE: char = '\x08';
+const DELETE: char = '\x7F';
+/// Ctrl+U, which erases the line being edited.
+const KILL: char = '\x15';
+
+pub struct LineDiscipline {
+    mode: Mode,
+    /// Line being edited, in canonical mode.
+    line: String,
+    /// Input which is ready to be read.
+    input: VecDeque<u8>,
+}
+
+impl LineDiscipline {
+    pub fn new() -> Self {
+        Self { mode: Mode::CANONICAL | Mode::ECHO, line: String::new(), input: VecDeque::new() }
+    }
+
+    #[inline]
+    pub const fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Changes the mode, making any line being edited readable if canonical mode is left.
+    pub fn set_mode(&mut self, mode: Mode) {
+        if !mode.contains(Mode::CANONICAL) {
+            let line = core::mem::take(&mut self.line);
+            self.push_input(&line);
+        }
+
+        self.mode = mode;
+    }
+
+    fn push_input(&mut self, str: &str) {
+        let len = str.len().min(CAPACITY - self.input.len());
+        self.input.extend(&str.as_bytes()[..len]);
+    }
+
+    /// Receives a typed character, calling `echo` with anything to be written back to the terminal.
+    pub fn receive(&mut self, char: char, mut echo: impl FnMut(&str)) {
+        let echo_enabled = self.mode.contains(Mode::ECHO);
+
+        if !self.mode.contains(Mode::CANONICAL) {
+            let mut encoded = [0; 4];
+            let encoded = char.encode_utf8(&mut encoded);
+            self.push_input(encoded);
+
+            if echo_enabled {
+                echo(encoded);
+            }
+
+            return;
+        }
+
+        match char {
+            '\n' | '\r' => {
+                self.line.push('\n');
+                let line = core::mem::take(&mut self.line);
+                self.push_input(&line);
+
+                if echo_enabled {
+                    echo("\n");
+                }
+            }
+
+            BACKSPACE | DELETE => {
+                if self.line.pop().is_some() && echo_enabled {
+                    echo("\x08 \x08");
+                }
+            }
+
+            KILL => {
+                while self.line.pop().is_some() {
+                    if echo_enabled {
+                        echo("\x08 \x08");
+                    }
+                }
+            }
+
+            char if char.is_control() && char != '\t' => {}
+
+            char => {
+                if (self.input.len() + self.line.len() + char.len_utf8()) >= CAPACITY {
+                    return;
+                }
+
+                self.line.push(char);
+
+                if echo_enabled {
+                    let mut encoded = [0; 4];
+                    echo(char.encode_utf8(&mut encoded));
+                }
+            }
+        }
+    }
+
+    /// Takes as much readable input as fits in `buf`; in canonical mode, at most one line.
+    pub fn read(&mut self, buf: &mut [u8]) -> usize {
+        let mut len = buf.len().min(self.input.len());
+        if self.mode.contains(Mode::CANONICAL) {
+            len = self.input.iter().take(len).position(|&byte| byte == b'\n').map_or(len, |end| end + 1);
+        }
+
+        for (dest, byte) in buf.iter_mut().zip(self.input.drain(..len)) {
+            *dest = byte;
+        }
+
+        len
+    }
+}
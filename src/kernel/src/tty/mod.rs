@@ -0,0 +1,366 @@
+//! Virtual terminals: several TTYs (`/dev/tty1` onwards) sharing the framebuffer, one of which is shown and receives
+//! keyboard input at a time. Alt+F<n> (with or without Ctrl) switches to the n-th terminal.
+//!
+//! Each terminal keeps its own scrollback (browsed with Shift+PageUp/PageDown), keyboard translation state, and line
+//! discipline, so a terminal keeps working while another is shown.
+//!
+//! Text is drawn with the PSF font provided as the `font` boot module. Without one, terminals still buffer their
+//! output and accept input, but nothing is drawn.
+
+mod font;
+mod ldisc;
+
+use crate::{
+    fb::{Framebuffer, Rect},
+    input::{
+        keymap::{self, Modifiers, Output, Translator},
+        KeyCode, KeyEvent,
+    },
+    interrupts::InterruptCell,
+};
+use alloc::{collections::VecDeque, string::String, vec::Vec};
+use font::Font;
+use ldisc::LineDiscipline;
+use libsys::syscall::tty::{self, Mode};
+use spin::{Mutex, Once};
+
+/// Number of terminals.
+const COUNT: usize = 6;
+/// Lines each terminal keeps, including those on screen.
+const SCROLLBACK: usize = 1000;
+const TAB_WIDTH: usize = 8;
+/// Size of a terminal which isn't drawn, so its output still wraps.
+const UNDRAWN_COLUMNS: usize = 80;
+const UNDRAWN_ROWS: usize = 25;
+
+/// Name of the boot module holding the font.
+pub const FONT_MODULE: &str = "font";
+
+const FOREGROUND: (u8, u8, u8) = (0xCC, 0xCC, 0xCC);
+const BACKGROUND: (u8, u8, u8) = (0x00, 0x00, 0x00);
+
+struct Terminal {
+    /// Lines of output, oldest first; the last holds the cursor.
+    lines: VecDeque<Vec<char>>,
+    column: usize,
+    /// Lines the view is scrolled back from the bottom.
+    scroll: usize,
+    translator: Translator,
+    ldisc: LineDiscipline,
+}
+
+impl Terminal {
+    fn new(translator: Translator) -> Self {
+        Self { lines: VecDeque::from([Vec::new()]), column: 0, scroll: 0, translator, ldisc: LineDiscipline::new() }
+    }
+
+    fn new_line(&mut self) {
+        if self.lines.len() == SCROLLBACK {
+            self.lines.pop_front();
+        }
+
+        self.lines.push_back(Vec::new());
+        self.column = 0;
+    }
+
+    fn put(&mut self, char: char, columns: usize) {
+        match char {
+            '\n' => self.new_line(),
+            '\r' => self.column = 0,
+            '\x08' => self.column = self.column.saturating_sub(1),
+            '\t' => {
+                let stop = ((self.column / TAB_WIDTH) + 1) * TAB_WIDTH;
+                while self.column < stop.min(columns) {
+                    self.put(' ', columns);
+                }
+            }
+
+            char if char.is_control() => {}
+
+            char => {
+                if self.column >= columns {
+                    self.new_line();
+                }
+
+                let line = self.lines.back_mut().unwrap();
+                if line.len() <= self.column {
+                    line.resize(self.column + 1, ' ');
+                }
+                line[self.column] = char;
+                self.column += 1;
+            }
+        }
+    }
+
+    /// Writes `str` at the cursor, returning the view to the bottom.
+    fn write(&mut self, str: &str, columns: usize) {
+        self.scroll = 0;
+
+        for char in str.chars() {
+            self.put(char, columns);
+        }
+    }
+}
+
+/// Grid of character cells the framebuffer is divided into.
+struct Screen {
+    font: Font,
+    columns: usize,
+    rows: usize,
+}
+
+impl Screen {
+    fn draw(&self, terminal: &Terminal, framebuffer: &mut Framebuffer) {
+        let foreground = framebuffer.encode(FOREGROUND.0, FOREGROUND.1, FOREGROUND.2);
+        let background = framebuffer.encode(BACKGROUND.0, BACKGROUND.1, BACKGROUND.2);
+        let (width, height) = (self.font.width(), self.font.height());
+        let bytes_per_row = width.div_ceil(8);
+
+        let bottom = terminal.lines.len() - terminal.scroll;
+        let top = bottom.saturating_sub(self.rows);
+        for row in 0..self.rows {
+            let index = top + row;
+            let line = terminal.lines.get(index).filter(|_| index < bottom);
+
+            for column in 0..self.columns {
+                let char = line.and_then(|line| line.get(column)).copied().unwrap_or(' ');
+                // The cursor is drawn by inverting its cell.
+                let cursor = terminal.scroll == 0 && (index + 1) == terminal.lines.len() && column == terminal.column;
+                let glyph = self.font.glyph(char);
+
+                for y in 0..height {
+                    let bits = &glyph[(y * bytes_per_row)..][..bytes_per_row];
+                    let pixels = &mut framebuffer.row_mut((row * height) + y)[(column * width)..][..width];
+
+                    for (x, pixel) in pixels.iter_mut().enumerate() {
+                        let set = (bits[x / 8] & (0x80 >> (x % 8))) > 0;
+                        *pixel = if set == cursor { background } else { foreground };
+                    }
+                }
+            }
+        }
+
+        framebuffer.damage(Rect::new(0, 0, self.columns * width, self.rows * height));
+        framebuffer.flush();
+    }
+}
+
+struct Terminals {
+    terminals: Vec<Terminal>,
+    active: usize,
+    screen: Option<Screen>,
+}
+
+impl Terminals {
+    fn size(&self) -> (usize, usize) {
+        self.screen.as_ref().map_or((UNDRAWN_COLUMNS, UNDRAWN_ROWS), |screen| (screen.columns, screen.rows))
+    }
+
+    fn redraw(&self, index: usize) {
+        if index != self.active {
+            return;
+        }
+
+        if let Some(screen) = self.screen.as_ref() {
+            crate::fb::with(|framebuffer| screen.draw(&self.terminals[index], framebuffer));
+        }
+    }
+
+    fn write(&mut self, index: usize, str: &str) {
+        let (columns, _) = self.size();
+        self.terminals[index].write(str, columns);
+        self.redraw(index);
+    }
+
+    fn activate(&mut self, index: usize) {
+        self.active = index;
+        self.redraw(index);
+    }
+
+    /// Scrolls the active terminal's view back (or forward, if `back` is false) by a screen.
+    fn scroll(&mut self, back: bool) {
+        let (_, rows) = self.size();
+        let terminal = &mut self.terminals[self.active];
+        let limit = terminal.lines.len().saturating_sub(rows);
+
+        terminal.scroll = if back { (terminal.scroll + rows).min(limit) } else { terminal.scroll.saturating_sub(rows) };
+        self.redraw(self.active);
+    }
+}
+
+static TERMINALS: Once<InterruptCell<Mutex<Terminals>>> = Once::new();
+
+fn with<T>(func: impl FnOnce(&mut Terminals) -> T) -> Option<T> {
+    TERMINALS.get().map(|terminals| terminals.with(|terminals| func(&mut terminals.lock())))
+}
+
+/// Index of the terminal a function key switches to.
+fn function_key(code: KeyCode) -> Option<usize> {
+    match code {
+        KeyCode(code) if (KeyCode::F1.0..(KeyCode::F1.0 + 10)).contains(&code) => {
+            Some(usize::from(code - KeyCode::F1.0))
+        }
+        KeyCode::F11 => Some(10),
+        KeyCode::F12 => Some(11),
+        _ => None,
+    }
+}
+
+/// Passes a key event to the active terminal, unless it's a chord which switches terminals or scrolls the view.
+pub fn key(event: KeyEvent) {
+    with(|terminals| {
+        let active = terminals.active;
+
+        // Modifiers are tracked by every terminal, so they're correct for whichever becomes active while they're held.
+        if matches!(
+            event.code,
+            KeyCode::LEFT_SHIFT
+                | KeyCode::RIGHT_SHIFT
+                | KeyCode::LEFT_CTRL
+                | KeyCode::RIGHT_CTRL
+                | KeyCode::LEFT_ALT
+                | KeyCode::RIGHT_ALT
+                | KeyCode::CAPS_LOCK
+        ) {
+            for (index, terminal) in terminals.terminals.iter_mut().enumerate() {
+                if index != active {
+                    terminal.translator.feed(event);
+                }
+            }
+        }
+
+        let translator = &mut terminals.terminals[active].translator;
+        let output = translator.feed(event);
+        let modifiers = translator.modifiers();
+
+        if event.pressed && modifiers.contains(Modifiers::ALT) {
+            if let Some(index) = function_key(event.code).filter(|&index| index < COUNT) {
+                terminals.activate(index);
+                return;
+            }
+        }
+
+        if event.pressed && modifiers.contains(Modifiers::SHIFT) {
+            match event.code {
+                KeyCode::PAGE_UP => return terminals.scroll(true),
+                KeyCode::PAGE_DOWN => return terminals.scroll(false),
+                _ => {}
+            }
+        }
+
+        let ldisc = &mut terminals.terminals[active].ldisc;
+        let mut echo = String::new();
+        match output {
+            Output::Char(char) => ldisc.receive(char, |str| echo.push_str(str)),
+            Output::Chars(first, second) => {
+                ldisc.receive(first, |str| echo.push_str(str));
+                ldisc.receive(second, |str| echo.push_str(str));
+            }
+            Output::Nothing | Output::Key(_) => {}
+        }
+
+        if !echo.is_empty() {
+            terminals.write(active, &echo);
+        }
+    });
+}
+
+/// Creates the terminals, and registers their device nodes.
+pub fn init() -> crate::vfs::Result<()> {
+    let font = match crate::init::modules::get(FONT_MODULE).map(|module| Font::parse(module.data())) {
+        Some(Ok(font)) => Some(font),
+        Some(Err(err)) => {
+            warn!("Failed to parse the font module: {:?}", err);
+            None
+        }
+        None => {
+            debug!("No font module was provided, so terminals won't be drawn.");
+            None
+        }
+    };
+
+    let screen = font.and_then(|font| {
+        let (columns, rows) =
+            crate::fb::with(|framebuffer| (framebuffer.width() / font.width(), framebuffer.height() / font.height()))?;
+
+        (columns > 0 && rows > 0).then_some(Screen { font, columns, rows })
+    });
+
+    let keymap = keymap::get(keymap::DEFAULT_KEYMAP).unwrap();
+    TERMINALS.call_once(|| {
+        let terminals =
+            (0..COUNT).map(|_| Terminal::new(Translator::new(keymap.clone(), keymap::Mode::Translated))).collect();
+
+        InterruptCell::new(Mutex::new(Terminals { terminals, active: 0, screen }))
+    });
+
+    for index in 0..COUNT {
+        crate::vfs::devfs::register(&alloc::format!("tty{}", index + 1), alloc::sync::Arc::new(Device { index }))?;
+    }
+
+    with(|terminals| terminals.redraw(terminals.active));
+
+    Ok(())
+}
+
+/// Device node of a terminal: reads take its input (as its line discipline allows), and writes are its output.
+struct Device {
+    index: usize,
+}
+
+impl crate::vfs::Node for Device {
+    fn metadata(&self) -> crate::vfs::Metadata {
+        crate::vfs::Metadata {
+            kind: crate::vfs::NodeKind::Device,
+            size: 0,
+            mode: libsys::syscall::fs::Mode::from_bits_retain(0o620),
+            owner: crate::vfs::Owner::ROOT,
+            accessed: 0,
+            modified: 0,
+            created: 0,
+        }
+    }
+
+    /// Takes as much input as is ready, without blocking.
+    fn read(&self, _offset: usize, buf: &mut [u8]) -> crate::vfs::Result<usize> {
+        with(|terminals| terminals.terminals[self.index].ldisc.read(buf)).ok_or(crate::vfs::Error::NotFound)
+    }
+
+    fn write(&self, _offset: usize, buf: &[u8]) -> crate::vfs::Result<usize> {
+        let str = String::from_utf8_lossy(buf);
+        with(|terminals| terminals.write(self.index, &str)).ok_or(crate::vfs::Error::NotFound)?;
+
+        Ok(buf.len())
+    }
+
+    fn control(&self, request: u32, arg: &mut [u8]) -> crate::vfs::Result<usize> {
+        const MODE_LEN: usize = core::mem::size_of::<u32>();
+
+        with(|terminals| match request {
+            tty::GET_MODE => {
+                let arg = arg.get_mut(..MODE_LEN).ok_or(crate::vfs::Error::BufferTooSmall)?;
+                arg.copy_from_slice(&terminals.terminals[self.index].ldisc.mode().bits().to_ne_bytes());
+
+                Ok(MODE_LEN)
+            }
+
+            tty::SET_MODE => {
+                let arg = arg.get(..MODE_LEN).ok_or(crate::vfs::Error::BufferTooSmall)?;
+                let mode = Mode::from_bits(u32::from_ne_bytes(arg.try_into().unwrap()))
+                    .ok_or(crate::vfs::Error::InvalidArgument)?;
+                terminals.terminals[self.index].ldisc.set_mode(mode);
+
+                Ok(0)
+            }
+
+            tty::ACTIVATE => {
+                terminals.activate(self.index);
+
+                Ok(0)
+            }
+
+            _ => Err(crate::vfs::Error::InvalidArgument),
+        })
+        .ok_or(crate::vfs::Error::NotFound)?
+    }
+}
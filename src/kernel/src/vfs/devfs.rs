@@ -0,0 +1,71 @@
+//! Device filesystem: a flat directory of the device nodes registered by drivers.
+
+use super::{Capabilities, DirEntry, Error, Filesystem, Metadata, Node, NodeKind, NodeRef, Owner, Result};
+use alloc::{collections::BTreeMap, string::String, sync::Arc};
+use libsys::syscall::fs::Mode;
+use spin::RwLock;
+
+pub const MOUNT_PATH: &str = "/dev";
+
+static DEVICES: RwLock<BTreeMap<String, NodeRef>> = RwLock::new(BTreeMap::new());
+
+/// Registers `node` as the device `name`, i.e. `/dev/<name>`.
+///
+/// #### Remark
+///
+/// Devices may be registered before the filesystem is mounted.
+pub fn register(name: &str, node: NodeRef) -> Result<()> {
+    if name.is_empty() || name.contains(super::SEPARATOR) {
+        return Err(Error::InvalidPath);
+    }
+
+    DEVICES.write().try_insert(String::from(name), node).map(|_| ()).map_err(|_| Error::AlreadyExists)
+}
+
+pub struct Devfs;
+
+impl Filesystem for Devfs {
+    fn name(&self) -> &'static str {
+        "devfs"
+    }
+
+    fn root(&self) -> NodeRef {
+        Arc::new(Root)
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::empty()
+    }
+}
+
+struct Root;
+
+impl Node for Root {
+    fn metadata(&self) -> Metadata {
+        Metadata {
+            kind: NodeKind::Directory,
+            size: DEVICES.read().len(),
+            mode: Mode::from_bits_retain(0o755),
+            owner: Owner::ROOT,
+            accessed: 0,
+            modified: 0,
+            created: 0,
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Result<NodeRef> {
+        DEVICES.read().get(name).cloned().ok_or(Error::NotFound)
+    }
+
+    fn read_dir(&self, cursor: Option<&str>) -> Result<Option<DirEntry>> {
+        use core::ops::Bound;
+
+        let lower_bound = cursor.map_or(Bound::Unbounded, Bound::Excluded);
+
+        Ok(DEVICES
+            .read()
+            .range::<str, _>((lower_bound, Bound::Unbounded))
+            .next()
+            .map(|(name, node)| DirEntry { name: name.clone(), kind: node.kind() }))
+    }
+}
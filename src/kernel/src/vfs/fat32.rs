@@ -0,0 +1,703 @@
+//! FAT32 filesystem, on a partition of a block device (i.e. the EFI system partition).
+//!
+//! Files can be read, overwritten, and extended, but entries can't be created, removed, or renamed, so the
+//! filesystem reports no [`Capabilities`]. Long file names are read, and lookups ignore ASCII case.
+
+use super::{Capabilities, DirEntry, Error, Filesystem, Metadata, Node, NodeKind, NodeRef, Owner, Result};
+use crate::block::{
+    gpt::{self, Partition},
+    Disk,
+};
+use alloc::{
+    collections::BTreeMap,
+    string::String,
+    sync::{Arc, Weak},
+    vec,
+    vec::Vec,
+};
+use libsys::syscall::fs::Mode;
+use spin::{Mutex, RwLock};
+
+/// Mount point of the EFI system partition.
+pub const ESP_MOUNT_PATH: &str = "/efi";
+
+/// FAT entries are 28 bits; the top 4 are reserved, and preserved when an entry is written.
+const ENTRY_MASK: u32 = 0x0FFF_FFFF;
+/// Entries at or above this end a cluster chain.
+const END_OF_CHAIN: u32 = 0x0FFF_FFF8;
+/// Volumes with fewer clusters are FAT12 or FAT16.
+const MIN_CLUSTERS: u32 = 65525;
+/// Cluster numbers must lie below the entry values reserved for bad clusters and chain ends.
+const MAX_CLUSTERS: u32 = 0x0FFF_FFF5;
+
+const DIR_ENTRY_SIZE: usize = 32;
+/// Characters of a long file name held by each of its entries.
+const LONG_NAME_CHARS: usize = 13;
+
+const ATTR_READ_ONLY: u8 = 0x01;
+const ATTR_VOLUME_ID: u8 = 0x08;
+const ATTR_DIRECTORY: u8 = 0x10;
+const ATTR_LONG_NAME: u8 = 0x0F;
+
+const FSINFO_LEAD_SIGNATURE: u32 = 0x4161_5252;
+const FSINFO_STRUCT_SIGNATURE: u32 = 0x6141_7272;
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(bytes[offset..(offset + 2)].try_into().unwrap())
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..(offset + 4)].try_into().unwrap())
+}
+
+fn write_u16(bytes: &mut [u8], offset: usize, value: u16) {
+    bytes[offset..(offset + 2)].copy_from_slice(&value.to_le_bytes());
+}
+
+fn write_u32(bytes: &mut [u8], offset: usize, value: u32) {
+    bytes[offset..(offset + 4)].copy_from_slice(&value.to_le_bytes());
+}
+
+/// Mounts the first EFI system partition found on the registered disks at [`ESP_MOUNT_PATH`].
+pub fn mount_esp() {
+    let found = crate::block::disks().into_iter().find_map(|disk| match gpt::find(&disk, gpt::EFI_SYSTEM) {
+        Ok(partition) => partition.map(|partition| (disk, partition)),
+        Err(err) => {
+            warn!("Failed to read the partition table of {}: {:?}", disk.name(), err);
+            None
+        }
+    });
+
+    let Some((disk, partition)) = found else {
+        info!("No EFI system partition found");
+        return;
+    };
+
+    let result = Fat32::new(disk.clone(), partition).and_then(|fat32| {
+        // A read-only root (i.e. the initramfs) already holds the mount point.
+        if super::lookup(super::ROOT, ESP_MOUNT_PATH).is_err() {
+            super::create(
+                super::ROOT,
+                ESP_MOUNT_PATH,
+                NodeKind::Directory,
+                Mode::from_bits_retain(0o755),
+                Owner::ROOT,
+            )?;
+        }
+        super::mount(ESP_MOUNT_PATH, Arc::new(fat32))
+    });
+
+    if let Err(err) = result {
+        warn!("Failed to mount the EFI system partition of {}: {:?}", disk.name(), err);
+    }
+}
+
+pub struct Fat32 {
+    volume: Arc<Volume>,
+}
+
+impl Fat32 {
+    /// Reads the boot sector of `partition` on `disk`, failing with [`Error::InvalidArgument`] if it doesn't
+    /// describe a FAT32 volume.
+    pub fn new(disk: Arc<Disk>, partition: Partition) -> Result<Self> {
+        Volume::new(disk, partition).map(|volume| Self { volume: Arc::new(volume) })
+    }
+}
+
+impl Filesystem for Fat32 {
+    fn name(&self) -> &'static str {
+        "fat32"
+    }
+
+    fn root(&self) -> NodeRef {
+        Arc::new(Directory { volume: self.volume.clone(), first_cluster: self.volume.root_cluster })
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::empty()
+    }
+}
+
+/// Where a directory entry lies, as a sector of the volume and a byte offset within it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Location {
+    sector: u64,
+    offset: usize,
+}
+
+/// Cluster allocation state, locked for the duration of every write.
+struct Allocator {
+    /// Cluster to resume searching for free clusters from.
+    next_free: u32,
+    /// Whether FSInfo's free cluster count has been marked unknown, as allocating makes it stale.
+    fsinfo_invalidated: bool,
+}
+
+struct Volume {
+    disk: Arc<Disk>,
+    first_lba: u64,
+    /// Disk blocks per sector of the volume.
+    blocks_per_sector: u64,
+    bytes_per_sector: usize,
+    sectors_per_cluster: u64,
+    reserved_sectors: u64,
+    fat_count: u64,
+    fat_sectors: u64,
+    data_start: u64,
+    cluster_count: u32,
+    root_cluster: u32,
+    fsinfo_sector: Option<u64>,
+
+    allocator: Mutex<Allocator>,
+    /// Open files, so every node for an entry shares its size and clusters.
+    files: Mutex<BTreeMap<Location, Weak<File>>>,
+}
+
+impl Volume {
+    fn new(disk: Arc<Disk>, partition: Partition) -> Result<Self> {
+        let block_size = disk.device().block_size();
+        if block_size < 512 {
+            return Err(Error::InvalidArgument);
+        }
+
+        let mut boot = vec![0u8; block_size];
+        disk.read(partition.first_lba, &mut boot).map_err(|_| Error::Io)?;
+
+        let bytes_per_sector = usize::from(read_u16(&boot, 11));
+        let sectors_per_cluster = boot[13];
+        let reserved_sectors = u64::from(read_u16(&boot, 14));
+        let fat_count = u64::from(boot[16]);
+        let total_sectors = u64::from(read_u32(&boot, 32));
+        let fat_sectors = u64::from(read_u32(&boot, 36));
+        let root_cluster = read_u32(&boot, 44);
+        let fsinfo_sector = u64::from(read_u16(&boot, 48));
+
+        // FAT32 has no fixed root directory, nor a 16-bit FAT size.
+        if read_u16(&boot, 510) != 0xAA55
+            || !bytes_per_sector.is_power_of_two()
+            || bytes_per_sector < block_size
+            || !sectors_per_cluster.is_power_of_two()
+            || reserved_sectors == 0
+            || fat_count == 0
+            || read_u16(&boot, 17) != 0
+            || read_u16(&boot, 22) != 0
+        {
+            return Err(Error::InvalidArgument);
+        }
+
+        let blocks_per_sector = u64::try_from(bytes_per_sector / block_size).unwrap();
+        let partition_blocks = partition.last_lba.saturating_add(1).saturating_sub(partition.first_lba);
+        let data_start = reserved_sectors + (fat_count * fat_sectors);
+        if total_sectors <= data_start || (total_sectors * blocks_per_sector) > partition_blocks {
+            return Err(Error::InvalidArgument);
+        }
+
+        let sectors_per_cluster = u64::from(sectors_per_cluster);
+        let cluster_count = u32::try_from((total_sectors - data_start) / sectors_per_cluster).unwrap();
+        let fat_entries = (fat_sectors * u64::try_from(bytes_per_sector).unwrap()) / 4;
+        if !(MIN_CLUSTERS..=MAX_CLUSTERS).contains(&cluster_count)
+            || fat_entries < u64::from(cluster_count + 2)
+            || !(2..(cluster_count + 2)).contains(&root_cluster)
+        {
+            return Err(Error::InvalidArgument);
+        }
+
+        Ok(Self {
+            disk,
+            first_lba: partition.first_lba,
+            blocks_per_sector,
+            bytes_per_sector,
+            sectors_per_cluster,
+            reserved_sectors,
+            fat_count,
+            fat_sectors,
+            data_start,
+            cluster_count,
+            root_cluster,
+            fsinfo_sector: (1..reserved_sectors).contains(&fsinfo_sector).then_some(fsinfo_sector),
+            allocator: Mutex::new(Allocator { next_free: 2, fsinfo_invalidated: false }),
+            files: Mutex::new(BTreeMap::new()),
+        })
+    }
+
+    #[inline]
+    fn cluster_size(&self) -> usize {
+        self.bytes_per_sector * usize::try_from(self.sectors_per_cluster).unwrap()
+    }
+
+    #[inline]
+    fn is_data_cluster(&self, cluster: u32) -> bool {
+        (2..(self.cluster_count + 2)).contains(&cluster)
+    }
+
+    /// Reads `buf.len()` bytes, starting at `sector`.
+    fn read_sectors(&self, sector: u64, buf: &mut [u8]) -> Result<()> {
+        self.disk.read(self.first_lba + (sector * self.blocks_per_sector), buf).map_err(|_| Error::Io)
+    }
+
+    /// Writes `buf`, starting at `sector`.
+    fn write_sectors(&self, sector: u64, buf: &[u8]) -> Result<()> {
+        self.disk.write(self.first_lba + (sector * self.blocks_per_sector), buf).map_err(|_| Error::Io)
+    }
+
+    fn cluster_sector(&self, cluster: u32) -> u64 {
+        self.data_start + (u64::from(cluster - 2) * self.sectors_per_cluster)
+    }
+
+    /// Returns the sector of the (first) FAT holding `cluster`'s entry, and the entry's offset within it.
+    fn fat_position(&self, cluster: u32) -> (u64, usize) {
+        let offset = usize::try_from(cluster).unwrap() * 4;
+
+        (self.reserved_sectors + u64::try_from(offset / self.bytes_per_sector).unwrap(), offset % self.bytes_per_sector)
+    }
+
+    /// Sets `cluster`'s entry in every copy of the FAT.
+    fn set_fat_entry(&self, cluster: u32, value: u32) -> Result<()> {
+        let (sector, offset) = self.fat_position(cluster);
+        let mut buf = vec![0u8; self.bytes_per_sector];
+        for fat in 0..self.fat_count {
+            let sector = sector + (fat * self.fat_sectors);
+            self.read_sectors(sector, &mut buf)?;
+            write_u32(&mut buf, offset, (read_u32(&buf, offset) & !ENTRY_MASK) | (value & ENTRY_MASK));
+            self.write_sectors(sector, &buf)?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns up to `limit` clusters of the chain starting at `first` (which is 0 for an empty file).
+    fn chain(&self, first: u32, limit: usize) -> Result<Vec<u32>> {
+        let mut fat = FatReader::new(self);
+        let mut clusters = Vec::new();
+        if first == 0 {
+            return Ok(clusters);
+        }
+
+        let mut cluster = first;
+        while clusters.len() < limit {
+            // A chain longer than the volume must loop back on itself.
+            if !self.is_data_cluster(cluster) || clusters.len() >= usize::try_from(self.cluster_count).unwrap() {
+                return Err(Error::Io);
+            }
+
+            clusters.push(cluster);
+            cluster = match fat.entry(cluster)? {
+                entry if entry >= END_OF_CHAIN => break,
+                entry => entry,
+            };
+        }
+
+        Ok(clusters)
+    }
+
+    /// Allocates a free cluster as the end of a chain, appending it to the chain ending at `last` (if any).
+    fn allocate(&self, allocator: &mut Allocator, last: Option<u32>) -> Result<u32> {
+        let mut fat = FatReader::new(self);
+        let start = allocator.next_free;
+        let mut cluster = start;
+        while fat.entry(cluster)? != 0 {
+            cluster = if self.is_data_cluster(cluster + 1) { cluster + 1 } else { 2 };
+            if cluster == start {
+                return Err(Error::NoSpace);
+            }
+        }
+
+        self.set_fat_entry(cluster, ENTRY_MASK)?;
+        if let Some(last) = last {
+            self.set_fat_entry(last, cluster)?;
+        }
+        allocator.next_free = if self.is_data_cluster(cluster + 1) { cluster + 1 } else { 2 };
+
+        if !allocator.fsinfo_invalidated {
+            self.invalidate_free_count()?;
+            allocator.fsinfo_invalidated = true;
+        }
+
+        Ok(cluster)
+    }
+
+    /// Marks FSInfo's count of free clusters as unknown, so it's recomputed rather than trusted by the next reader.
+    fn invalidate_free_count(&self) -> Result<()> {
+        let Some(sector) = self.fsinfo_sector else { return Ok(()) };
+
+        let mut buf = vec![0u8; self.bytes_per_sector];
+        self.read_sectors(sector, &mut buf)?;
+        if read_u32(&buf, 0) == FSINFO_LEAD_SIGNATURE && read_u32(&buf, 484) == FSINFO_STRUCT_SIGNATURE {
+            write_u32(&mut buf, 488, u32::MAX);
+            self.write_sectors(sector, &buf)?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads every entry of the directory starting at cluster `first`, besides `.` and `..`.
+    fn read_directory(&self, first: u32) -> Result<Vec<Entry>> {
+        let entries_per_sector = self.bytes_per_sector / DIR_ENTRY_SIZE;
+
+        let mut entries = Vec::new();
+        let mut long_name = LongName::default();
+        let mut buf = vec![0u8; self.cluster_size()];
+        for cluster in self.chain(first, usize::MAX)? {
+            let first_sector = self.cluster_sector(cluster);
+            self.read_sectors(first_sector, &mut buf)?;
+
+            for (index, raw) in buf.chunks_exact(DIR_ENTRY_SIZE).enumerate() {
+                match raw[0] {
+                    // No entries follow.
+                    0x00 => return Ok(entries),
+                    // Deleted.
+                    0xE5 => {
+                        long_name.clear();
+                        continue;
+                    }
+                    _ => {}
+                }
+
+                let attributes = raw[11];
+                if (attributes & 0x3F) == ATTR_LONG_NAME {
+                    long_name.push(raw);
+                    continue;
+                }
+
+                let name = long_name.take(&raw[..11]).unwrap_or_else(|| short_name(raw));
+                if (attributes & ATTR_VOLUME_ID) > 0 || name == "." || name == ".." {
+                    continue;
+                }
+
+                entries.push(Entry {
+                    name,
+                    attributes,
+                    first_cluster: (u32::from(read_u16(raw, 20)) << 16) | u32::from(read_u16(raw, 26)),
+                    size: read_u32(raw, 28),
+                    location: Location {
+                        sector: first_sector + u64::try_from(index / entries_per_sector).unwrap(),
+                        offset: (index % entries_per_sector) * DIR_ENTRY_SIZE,
+                    },
+                });
+            }
+        }
+
+        Ok(entries)
+    }
+
+    /// Records `extent` in the directory entry at `location`.
+    fn write_extent(&self, location: Location, extent: Extent) -> Result<()> {
+        let mut buf = vec![0u8; self.bytes_per_sector];
+        self.read_sectors(location.sector, &mut buf)?;
+
+        let raw = &mut buf[location.offset..(location.offset + DIR_ENTRY_SIZE)];
+        write_u16(raw, 20, u16::try_from(extent.first_cluster >> 16).unwrap());
+        write_u16(raw, 26, u16::try_from(extent.first_cluster & 0xFFFF).unwrap());
+        write_u32(raw, 28, extent.size);
+
+        self.write_sectors(location.sector, &buf)
+    }
+
+    fn node(self: &Arc<Self>, entry: &Entry) -> NodeRef {
+        if (entry.attributes & ATTR_DIRECTORY) > 0 {
+            return Arc::new(Directory { volume: self.clone(), first_cluster: entry.first_cluster });
+        }
+
+        let mut files = self.files.lock();
+        if let Some(file) = files.get(&entry.location).and_then(Weak::upgrade) {
+            return file;
+        }
+
+        let file = Arc::new(File {
+            volume: self.clone(),
+            location: entry.location,
+            read_only: (entry.attributes & ATTR_READ_ONLY) > 0,
+            extent: RwLock::new(Extent { first_cluster: entry.first_cluster, size: entry.size }),
+        });
+        files.retain(|_, file| file.strong_count() > 0);
+        files.insert(entry.location, Arc::downgrade(&file));
+
+        file
+    }
+}
+
+/// Reads FAT entries, holding onto the last sector read so neighbouring entries cost no further I/O.
+struct FatReader<'a> {
+    volume: &'a Volume,
+    sector: Option<u64>,
+    buf: Vec<u8>,
+}
+
+impl<'a> FatReader<'a> {
+    fn new(volume: &'a Volume) -> Self {
+        Self { volume, sector: None, buf: vec![0u8; volume.bytes_per_sector] }
+    }
+
+    fn entry(&mut self, cluster: u32) -> Result<u32> {
+        let (sector, offset) = self.volume.fat_position(cluster);
+        if self.sector != Some(sector) {
+            self.sector = None;
+            self.volume.read_sectors(sector, &mut self.buf)?;
+            self.sector = Some(sector);
+        }
+
+        Ok(read_u32(&self.buf, offset) & ENTRY_MASK)
+    }
+}
+
+/// A long file name, assembled from the entries which precede its short entry (last part first).
+#[derive(Default)]
+struct LongName {
+    chars: Vec<u16>,
+    checksum: Option<u8>,
+}
+
+impl LongName {
+    fn clear(&mut self) {
+        self.checksum = None;
+    }
+
+    fn push(&mut self, raw: &[u8]) {
+        let ordinal = usize::from(raw[0] & 0x1F);
+        if (raw[0] & 0x40) > 0 {
+            self.chars = vec![0xFFFF; ordinal * LONG_NAME_CHARS];
+            self.checksum = Some(raw[13]);
+        }
+
+        if ordinal == 0 || (ordinal * LONG_NAME_CHARS) > self.chars.len() || self.checksum != Some(raw[13]) {
+            self.clear();
+            return;
+        }
+
+        let offsets = (1..11).step_by(2).chain((14..26).step_by(2)).chain((28..32).step_by(2));
+        for (index, offset) in offsets.enumerate() {
+            self.chars[((ordinal - 1) * LONG_NAME_CHARS) + index] = read_u16(raw, offset);
+        }
+    }
+
+    /// Takes the assembled name, if it belongs to the short entry named `short`.
+    fn take(&mut self, short: &[u8]) -> Option<String> {
+        let checksum = short.iter().fold(0u8, |sum, &byte| sum.rotate_right(1).wrapping_add(byte));
+        if self.checksum.take() != Some(checksum) {
+            return None;
+        }
+
+        let len = self.chars.iter().position(|&c| c == 0x0000 || c == 0xFFFF).unwrap_or(self.chars.len());
+        Some(String::from_utf16_lossy(&self.chars[..len]))
+    }
+}
+
+/// Formats the 8.3 name of `raw`, lowercasing its base or extension as its case flags indicate.
+fn short_name(raw: &[u8]) -> String {
+    fn part(bytes: &[u8], lowercase: bool) -> impl Iterator<Item = char> + '_ {
+        bytes
+            .iter()
+            .take_while(|&&byte| byte != b' ')
+            .map(move |&byte| char::from(if lowercase { byte.to_ascii_lowercase() } else { byte }))
+    }
+
+    let mut base = [0u8; 8];
+    base.copy_from_slice(&raw[..8]);
+    // A leading 0xE5 is stored as 0x05, as 0xE5 marks a deleted entry.
+    if base[0] == 0x05 {
+        base[0] = 0xE5;
+    }
+
+    let mut name: String = part(&base, (raw[12] & 0x08) > 0).collect();
+    let extension: String = part(&raw[8..11], (raw[12] & 0x10) > 0).collect();
+    if !extension.is_empty() {
+        name.push('.');
+        name.push_str(&extension);
+    }
+
+    name
+}
+
+struct Entry {
+    name: String,
+    attributes: u8,
+    first_cluster: u32,
+    size: u32,
+    location: Location,
+}
+
+impl Entry {
+    fn kind(&self) -> NodeKind {
+        if (self.attributes & ATTR_DIRECTORY) > 0 {
+            NodeKind::Directory
+        } else {
+            NodeKind::File
+        }
+    }
+}
+
+fn metadata(kind: NodeKind, size: usize, mode: u32) -> Metadata {
+    Metadata {
+        kind,
+        size,
+        mode: Mode::from_bits_retain(mode),
+        owner: Owner::ROOT,
+        accessed: 0,
+        modified: 0,
+        created: 0,
+    }
+}
+
+struct Directory {
+    volume: Arc<Volume>,
+    first_cluster: u32,
+}
+
+impl Node for Directory {
+    fn metadata(&self) -> Metadata {
+        let size = self.volume.read_directory(self.first_cluster).map_or(0, |entries| entries.len());
+
+        metadata(NodeKind::Directory, size, 0o755)
+    }
+
+    fn lookup(&self, name: &str) -> Result<NodeRef> {
+        let entries = self.volume.read_directory(self.first_cluster)?;
+        let entry = entries.iter().find(|entry| entry.name.eq_ignore_ascii_case(name)).ok_or(Error::NotFound)?;
+
+        Ok(self.volume.node(entry))
+    }
+
+    fn read_dir(&self, cursor: Option<&str>) -> Result<Option<DirEntry>> {
+        let entries = self.volume.read_directory(self.first_cluster)?;
+        let index = match cursor {
+            Some(cursor) => {
+                entries.iter().position(|entry| entry.name == cursor).map_or(entries.len(), |index| index + 1)
+            }
+            None => 0,
+        };
+
+        Ok(entries.get(index).map(|entry| DirEntry { name: entry.name.clone(), kind: entry.kind() }))
+    }
+}
+
+/// A file's first cluster and size, as recorded in its directory entry.
+#[derive(Debug, Clone, Copy)]
+struct Extent {
+    first_cluster: u32,
+    size: u32,
+}
+
+struct File {
+    volume: Arc<Volume>,
+    location: Location,
+    read_only: bool,
+    extent: RwLock<Extent>,
+}
+
+impl File {
+    /// Appends clusters to `clusters` (the file's chain) until it holds `count`.
+    fn grow(&self, allocator: &mut Allocator, clusters: &mut Vec<u32>, count: usize) -> Result<()> {
+        while clusters.len() < count {
+            let cluster = self.volume.allocate(allocator, clusters.last().copied())?;
+            clusters.push(cluster);
+        }
+
+        Ok(())
+    }
+}
+
+impl Node for File {
+    fn metadata(&self) -> Metadata {
+        metadata(
+            NodeKind::File,
+            usize::try_from(self.extent.read().size).unwrap(),
+            if self.read_only { 0o444 } else { 0o644 },
+        )
+    }
+
+    fn read(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        let extent = *self.extent.read();
+        let size = usize::try_from(extent.size).unwrap();
+        if offset >= size {
+            return Ok(0);
+        }
+
+        let len = buf.len().min(size - offset);
+        let cluster_size = self.volume.cluster_size();
+        let clusters = self.volume.chain(extent.first_cluster, (offset + len).div_ceil(cluster_size))?;
+
+        let mut cluster_buf = vec![0u8; cluster_size];
+        let mut read = 0;
+        while read < len {
+            let position = offset + read;
+            let cluster = *clusters.get(position / cluster_size).ok_or(Error::Io)?;
+            let within = position % cluster_size;
+            let count = (cluster_size - within).min(len - read);
+
+            self.volume.read_sectors(self.volume.cluster_sector(cluster), &mut cluster_buf)?;
+            buf[read..(read + count)].copy_from_slice(&cluster_buf[within..(within + count)]);
+            read += count;
+        }
+
+        Ok(len)
+    }
+
+    fn write(&self, offset: usize, buf: &[u8]) -> Result<usize> {
+        if self.read_only {
+            return Err(Error::PermissionDenied);
+        }
+
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        // File sizes are 32-bit.
+        let end = offset
+            .checked_add(buf.len())
+            .and_then(|end| u32::try_from(end).ok())
+            .map(|end| usize::try_from(end).unwrap())
+            .ok_or(Error::InvalidArgument)?;
+
+        let volume = &self.volume;
+        let mut allocator = volume.allocator.lock();
+        let mut extent = self.extent.write();
+        let size = usize::try_from(extent.size).unwrap();
+        let cluster_size = volume.cluster_size();
+        let cluster_count = end.div_ceil(cluster_size);
+
+        let mut clusters = volume.chain(extent.first_cluster, cluster_count)?;
+        let grown = self.grow(&mut allocator, &mut clusters, cluster_count);
+        // Record a newly allocated first cluster even if growing failed part way, so its chain isn't lost.
+        if let Some(&first_cluster) = clusters.first() {
+            if first_cluster != extent.first_cluster {
+                extent.first_cluster = first_cluster;
+                volume.write_extent(self.location, *extent)?;
+            }
+        }
+        grown?;
+
+        let mut cluster_buf = vec![0u8; cluster_size];
+        for (index, &cluster) in clusters.iter().enumerate().skip(offset.min(size) / cluster_size) {
+            let cluster_start = index * cluster_size;
+            let cluster_end = cluster_start + cluster_size;
+            let sector = volume.cluster_sector(cluster);
+
+            if cluster_start < size && (offset > cluster_start || end < cluster_end) {
+                volume.read_sectors(sector, &mut cluster_buf)?;
+            } else {
+                cluster_buf.fill(0);
+            }
+
+            // Whatever lies between the old end of the file and the write must read back as zeroes.
+            let gap_start = size.clamp(cluster_start, cluster_end);
+            let gap_end = offset.clamp(cluster_start, cluster_end);
+            if gap_start < gap_end {
+                cluster_buf[(gap_start - cluster_start)..(gap_end - cluster_start)].fill(0);
+            }
+
+            let write_start = offset.clamp(cluster_start, cluster_end);
+            let write_end = end.clamp(cluster_start, cluster_end);
+            cluster_buf[(write_start - cluster_start)..(write_end - cluster_start)]
+                .copy_from_slice(&buf[(write_start - offset)..(write_end - offset)]);
+
+            volume.write_sectors(sector, &cluster_buf)?;
+        }
+
+        if end > size {
+            extent.size = u32::try_from(end).unwrap();
+            volume.write_extent(self.location, *extent)?;
+        }
+
+        Ok(buf.len())
+    }
+}
@@ -0,0 +1,139 @@
+use super::{Error, NodeRef, Result};
+use alloc::{string::String, vec::Vec};
+use libsys::{
+    syscall::fs::{pack_dir_entry, OpenFlags},
+    Address, Frame,
+};
+
+/// A node opened by a task, with its own file offset.
+pub struct OpenFile {
+    node: NodeRef,
+    flags: OpenFlags,
+    offset: usize,
+    /// Name of the last directory entry returned by [`OpenFile::read_dir`].
+    dir_cursor: Option<String>,
+}
+
+impl OpenFile {
+    pub(super) fn new(node: NodeRef, flags: OpenFlags) -> Self {
+        Self { node, flags, offset: 0, dir_cursor: None }
+    }
+
+    #[inline]
+    pub fn node(&self) -> &NodeRef {
+        &self.node
+    }
+
+    #[inline]
+    pub const fn flags(&self) -> OpenFlags {
+        self.flags
+    }
+
+    /// Reads from the current offset into `buf`, advancing the offset by the number of bytes read.
+    pub fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if !self.flags.contains(OpenFlags::READ) {
+            return Err(Error::BadDescriptor);
+        }
+
+        let len = self.node.read(self.offset, buf)?;
+        self.offset += len;
+
+        Ok(len)
+    }
+
+    /// Reads the whole file, without moving the current offset.
+    pub fn read_to_end(&self) -> Result<Vec<u8>> {
+        if !self.flags.contains(OpenFlags::READ) {
+            return Err(Error::BadDescriptor);
+        }
+
+        let mut data = alloc::vec![0; self.node.metadata().size];
+        let mut len = 0;
+        while len < data.len() {
+            match self.node.read(len, &mut data[len..])? {
+                0 => break,
+                read => len += read,
+            }
+        }
+        data.truncate(len);
+
+        Ok(data)
+    }
+
+    /// Writes `buf` at the current offset, advancing the offset by the number of bytes written.
+    pub fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        if !self.flags.contains(OpenFlags::WRITE) {
+            return Err(Error::BadDescriptor);
+        }
+
+        let len = self.node.write(self.offset, buf)?;
+        self.offset += len;
+
+        Ok(len)
+    }
+
+    /// Packs as many of the directory's remaining entries into `buf` as fit, returning the number of bytes written.
+    pub fn read_dir(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if !self.flags.contains(OpenFlags::READ) {
+            return Err(Error::BadDescriptor);
+        }
+
+        let mut written = 0;
+        while let Some(entry) = self.node.read_dir(self.dir_cursor.as_deref())? {
+            match pack_dir_entry(&mut buf[written..], entry.kind.into(), &entry.name) {
+                Some(len) => {
+                    written += len;
+                    self.dir_cursor = Some(entry.name);
+                }
+
+                None if written == 0 => return Err(Error::BufferTooSmall),
+                None => break,
+            }
+        }
+
+        Ok(written)
+    }
+
+    pub fn control(&mut self, request: u32, arg: &mut [u8]) -> Result<usize> {
+        self.node.control(request, arg)
+    }
+
+    /// Collects the frames backing the first `page_count` pages of the device.
+    pub fn map_frames(&self, page_count: usize) -> Result<Vec<Address<Frame>>> {
+        if !self.flags.contains(OpenFlags::READ) {
+            return Err(Error::BadDescriptor);
+        }
+
+        (0..page_count).map(|index| self.node.map_frame(index)).collect()
+    }
+}
+
+/// A task's table of open files, indexed by descriptor.
+#[derive(Default)]
+pub struct Descriptors(Vec<Option<OpenFile>>);
+
+impl Descriptors {
+    /// Inserts `file` at the lowest free descriptor, and returns that descriptor.
+    pub fn insert(&mut self, file: OpenFile) -> usize {
+        if let Some(fd) = self.0.iter().position(Option::is_none) {
+            self.0[fd] = Some(file);
+            fd
+        } else {
+            self.0.push(Some(file));
+            self.0.len() - 1
+        }
+    }
+
+    /// Number of open descriptors.
+    pub fn count(&self) -> usize {
+        self.0.iter().flatten().count()
+    }
+
+    pub fn get_mut(&mut self, fd: usize) -> Result<&mut OpenFile> {
+        self.0.get_mut(fd).and_then(Option::as_mut).ok_or(Error::BadDescriptor)
+    }
+
+    pub fn remove(&mut self, fd: usize) -> Result<OpenFile> {
+        self.0.get_mut(fd).and_then(Option::take).ok_or(Error::BadDescriptor)
+    }
+}
@@ -0,0 +1,292 @@
+//! Initial RAM filesystem: a read-only filesystem unpacked from an archive the bootloader loads as a module.
+//!
+//! Archives are either ustar (`tar --format=ustar`) or new ASCII cpio (`cpio -H newc`), and must be uncompressed.
+//! Only regular files and directories are unpacked; links and special files are skipped. File data is never copied,
+//! as module memory is never reclaimed.
+
+use super::{Capabilities, DirEntry, Error, Filesystem, Metadata, Node, NodeKind, NodeRef, Owner, Result};
+use alloc::{collections::BTreeMap, string::String, sync::Arc, vec::Vec};
+use libsys::syscall::fs::Mode;
+
+/// Name of the boot module holding the archive.
+pub const MODULE_NAME: &str = "initramfs";
+
+const TAR_BLOCK_SIZE: usize = 512;
+const CPIO_HEADER_SIZE: usize = 110;
+const CPIO_TRAILER: &str = "TRAILER!!!";
+
+const MODE_TYPE_MASK: u32 = 0o170_000;
+const MODE_DIRECTORY: u32 = 0o040_000;
+const MODE_REGULAR: u32 = 0o100_000;
+
+pub struct Initramfs {
+    root: NodeRef,
+}
+
+impl Initramfs {
+    /// Unpacks the archive `data`, adding any of `mount_points` which it lacks as empty directories (so other
+    /// filesystems can be mounted over them).
+    pub fn parse(data: &'static [u8], mount_points: &[&str]) -> Result<Self> {
+        let mut root = Entry::directory(Mode::from_bits_retain(0o755), Owner::ROOT);
+
+        if data.starts_with(b"07070") {
+            parse_cpio(data, &mut root)?;
+        } else if data.get(257..262) == Some(&b"ustar"[..]) {
+            parse_tar(data, &mut root)?;
+        } else {
+            return Err(Error::InvalidArgument);
+        }
+
+        for path in mount_points {
+            root.insert(path, Entry::directory(Mode::from_bits_retain(0o755), Owner::ROOT), false);
+        }
+
+        Ok(Self { root: root.into_node() })
+    }
+}
+
+impl Filesystem for Initramfs {
+    fn name(&self) -> &'static str {
+        "initramfs"
+    }
+
+    fn root(&self) -> NodeRef {
+        self.root.clone()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::empty()
+    }
+}
+
+/// A node of the archive, as it's unpacked.
+enum Entry {
+    File { data: &'static [u8], mode: Mode, owner: Owner },
+    Directory { entries: BTreeMap<String, Entry>, mode: Mode, owner: Owner },
+}
+
+impl Entry {
+    fn directory(mode: Mode, owner: Owner) -> Self {
+        Self::Directory { entries: BTreeMap::new(), mode, owner }
+    }
+
+    /// Inserts `entry` at `path` (relative to this directory), creating any missing parent directories. An existing
+    /// entry is replaced if `replace`, though a directory only has its attributes replaced, keeping its entries.
+    fn insert(&mut self, path: &str, entry: Entry, replace: bool) {
+        let mut components: Vec<&str> = super::components(path).collect();
+        let Some(name) = components.pop() else { return };
+        if name == ".." || components.contains(&"..") {
+            warn!("Skipping initramfs entry outside of the archive: {:?}", path);
+            return;
+        }
+
+        let mut directory = self;
+        for component in components {
+            let Self::Directory { entries, .. } = directory else { unreachable!() };
+            directory = entries
+                .entry(String::from(component))
+                .or_insert_with(|| Self::directory(Mode::from_bits_retain(0o755), Owner::ROOT));
+
+            if !matches!(directory, Self::Directory { .. }) {
+                warn!("Skipping initramfs entry beneath a file: {:?}", path);
+                return;
+            }
+        }
+
+        let Self::Directory { entries, .. } = directory else { unreachable!() };
+        match (entries.get_mut(name), entry) {
+            (None, entry) => {
+                entries.insert(String::from(name), entry);
+            }
+
+            (Some(Self::Directory { mode, owner, .. }), Self::Directory { mode: new_mode, owner: new_owner, .. })
+                if replace =>
+            {
+                *mode = new_mode;
+                *owner = new_owner;
+            }
+
+            (Some(existing), entry) if replace => *existing = entry,
+
+            (Some(_), _) => {}
+        }
+    }
+
+    fn into_node(self) -> NodeRef {
+        match self {
+            Self::File { data, mode, owner } => Arc::new(File { data, mode, owner }),
+            Self::Directory { entries, mode, owner } => Arc::new(Directory {
+                entries: entries.into_iter().map(|(name, entry)| (name, entry.into_node())).collect(),
+                mode,
+                owner,
+            }),
+        }
+    }
+}
+
+fn parse_number(field: &[u8], radix: u32) -> Result<usize> {
+    let field = core::str::from_utf8(field).map_err(|_| Error::InvalidArgument)?;
+    let field = field.trim_matches(|c: char| c == '\0' || c == ' ');
+    if field.is_empty() {
+        return Ok(0);
+    }
+
+    usize::from_str_radix(field, radix).map_err(|_| Error::InvalidArgument)
+}
+
+/// Adds the entry `path` of type `kind` (as the type bits of a mode), skipping types which aren't unpacked.
+fn add(root: &mut Entry, path: &str, kind: u32, data: &'static [u8], mode: u32, owner: Owner) {
+    let mode = Mode::from_bits_truncate(mode);
+
+    match kind {
+        MODE_REGULAR => root.insert(path, Entry::File { data, mode, owner }, true),
+        MODE_DIRECTORY => root.insert(path, Entry::directory(mode, owner), true),
+        _ => debug!("Skipping initramfs entry of unsupported type: {:?}", path),
+    }
+}
+
+fn parse_tar(data: &'static [u8], root: &mut Entry) -> Result<()> {
+    fn c_str(field: &[u8]) -> Result<&str> {
+        let len = field.iter().position(|&byte| byte == 0).unwrap_or(field.len());
+        core::str::from_utf8(&field[..len]).map_err(|_| Error::InvalidArgument)
+    }
+
+    let mut offset = 0;
+    while let Some(header) = data.get(offset..(offset + TAR_BLOCK_SIZE)) {
+        // The archive ends with (at least one) zeroed block.
+        if header.iter().all(|&byte| byte == 0) {
+            break;
+        }
+
+        let size = parse_number(&header[124..136], 8)?;
+        let start = offset + TAR_BLOCK_SIZE;
+        let contents = data.get(start..(start + size)).ok_or(Error::InvalidArgument)?;
+
+        let name = c_str(&header[..100])?;
+        let prefix = c_str(&header[345..500])?;
+        let path = if prefix.is_empty() { String::from(name) } else { alloc::format!("{prefix}/{name}") };
+
+        let mode = u32::try_from(parse_number(&header[100..108], 8)?).map_err(|_| Error::InvalidArgument)?;
+        let owner = Owner {
+            uid: u32::try_from(parse_number(&header[108..116], 8)?).map_err(|_| Error::InvalidArgument)?,
+            gid: u32::try_from(parse_number(&header[116..124], 8)?).map_err(|_| Error::InvalidArgument)?,
+        };
+        let kind = match header[156] {
+            b'0' | b'\0' => MODE_REGULAR,
+            b'5' => MODE_DIRECTORY,
+            _ => 0,
+        };
+        add(root, &path, kind, contents, mode, owner);
+
+        offset = start + size.next_multiple_of(TAR_BLOCK_SIZE);
+    }
+
+    Ok(())
+}
+
+fn parse_cpio(data: &'static [u8], root: &mut Entry) -> Result<()> {
+    let mut offset = 0;
+    loop {
+        let header = data.get(offset..(offset + CPIO_HEADER_SIZE)).ok_or(Error::InvalidArgument)?;
+        if &header[..6] != b"070701" && &header[..6] != b"070702" {
+            return Err(Error::InvalidArgument);
+        }
+
+        // Fields are 8 hex digits each, following the magic.
+        let field = |index: usize| parse_number(&header[(6 + (index * 8))..][..8], 16);
+        let mode = field(1)?;
+        let owner = Owner {
+            uid: u32::try_from(field(2)?).map_err(|_| Error::InvalidArgument)?,
+            gid: u32::try_from(field(3)?).map_err(|_| Error::InvalidArgument)?,
+        };
+        let size = field(6)?;
+        let name_size = field(11)?;
+
+        // Names are NUL-terminated, and both they and the file data are padded to 4 bytes.
+        let name_start = offset + CPIO_HEADER_SIZE;
+        let name = data.get(name_start..(name_start + name_size)).ok_or(Error::InvalidArgument)?;
+        let name =
+            core::str::from_utf8(name.strip_suffix(b"\0").unwrap_or(name)).map_err(|_| Error::InvalidArgument)?;
+        let start = (name_start + name_size).next_multiple_of(4);
+        let contents = data.get(start..(start + size)).ok_or(Error::InvalidArgument)?;
+
+        if name == CPIO_TRAILER {
+            break;
+        }
+
+        let mode = u32::try_from(mode).map_err(|_| Error::InvalidArgument)?;
+        add(root, name, mode & MODE_TYPE_MASK, contents, mode & !MODE_TYPE_MASK, owner);
+
+        offset = (start + size).next_multiple_of(4);
+    }
+
+    Ok(())
+}
+
+struct Directory {
+    entries: BTreeMap<String, NodeRef>,
+    mode: Mode,
+    owner: Owner,
+}
+
+impl Node for Directory {
+    fn metadata(&self) -> Metadata {
+        Metadata {
+            kind: NodeKind::Directory,
+            size: self.entries.len(),
+            mode: self.mode,
+            owner: self.owner,
+            accessed: 0,
+            modified: 0,
+            created: 0,
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Result<NodeRef> {
+        self.entries.get(name).cloned().ok_or(Error::NotFound)
+    }
+
+    fn read_dir(&self, cursor: Option<&str>) -> Result<Option<DirEntry>> {
+        use core::ops::Bound;
+
+        let lower_bound = cursor.map_or(Bound::Unbounded, Bound::Excluded);
+
+        Ok(self
+            .entries
+            .range::<str, _>((lower_bound, Bound::Unbounded))
+            .next()
+            .map(|(name, node)| DirEntry { name: name.clone(), kind: node.kind() }))
+    }
+}
+
+struct File {
+    data: &'static [u8],
+    mode: Mode,
+    owner: Owner,
+}
+
+impl Node for File {
+    fn metadata(&self) -> Metadata {
+        Metadata {
+            kind: NodeKind::File,
+            size: self.data.len(),
+            mode: self.mode,
+            owner: self.owner,
+            accessed: 0,
+            modified: 0,
+            created: 0,
+        }
+    }
+
+    fn read(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        let Some(data) = self.data.get(offset..) else { return Ok(0) };
+        let len = buf.len().min(data.len());
+        buf[..len].copy_from_slice(&data[..len]);
+
+        Ok(len)
+    }
+
+    fn write(&self, _offset: usize, _buf: &[u8]) -> Result<usize> {
+        Err(Error::PermissionDenied)
+    }
+}
@@ -0,0 +1,449 @@
+//! Virtual filesystem: a single namespace of mounted filesystems, addressed by path.
+
+mod path;
+pub use path::*;
+
+mod file;
+pub use file::*;
+
+pub mod devfs;
+#[cfg(feature = "fat32")]
+pub mod fat32;
+pub mod initramfs;
+pub mod tmpfs;
+
+use alloc::{collections::BTreeMap, string::String, sync::Arc};
+use libsys::{
+    syscall::fs::{FileKind, Mode, OpenFlags, Stat},
+    Address, Frame,
+};
+use spin::RwLock;
+
+crate::error_impl! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Error {
+        NotFound => None,
+        NotDirectory => None,
+        IsDirectory => None,
+        InvalidPath => None,
+        AlreadyMounted => None,
+        PermissionDenied => None,
+        BadDescriptor => None,
+        BufferTooSmall => None,
+        Unsupported => None,
+        CrossDevice => None,
+        NotEmpty => None,
+        AlreadyExists => None,
+        Busy => None,
+        InvalidArgument => None,
+        /// The underlying device failed a request.
+        Io => None,
+        NoSpace => None
+    }
+}
+
+impl From<Error> for libsys::syscall::Error {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::NotFound => Self::NotFound,
+            Error::NotDirectory => Self::NotDirectory,
+            Error::IsDirectory => Self::IsDirectory,
+            Error::InvalidPath | Error::AlreadyMounted => Self::InvalidPath,
+            Error::PermissionDenied => Self::PermissionDenied,
+            Error::BadDescriptor => Self::BadDescriptor,
+            Error::BufferTooSmall => Self::BufferTooSmall,
+            Error::Unsupported => Self::Unsupported,
+            Error::CrossDevice => Self::CrossDevice,
+            Error::NotEmpty => Self::NotEmpty,
+            Error::AlreadyExists => Self::AlreadyExists,
+            Error::Busy => Self::Busy,
+            Error::InvalidArgument => Self::InvalidArgument,
+            Error::Io => Self::Io,
+            Error::NoSpace => Self::NoSpace,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeKind {
+    File,
+    Directory,
+    Device,
+}
+
+/// User & group which own a file, or on whose behalf a task accesses files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Owner {
+    pub uid: u32,
+    pub gid: u32,
+}
+
+impl Owner {
+    pub const ROOT: Self = Self { uid: 0, gid: 0 };
+
+    #[inline]
+    pub const fn is_root(self) -> bool {
+        self.uid == 0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Metadata {
+    pub kind: NodeKind,
+    pub size: usize,
+    pub mode: Mode,
+    pub owner: Owner,
+    /// Timestamps, in coarse milliseconds since boot (see [`crate::time::coarse_ms`]).
+    pub accessed: u64,
+    pub modified: u64,
+    pub created: u64,
+}
+
+impl Metadata {
+    /// Indicates whether `accessor` may open the node with `flags`.
+    pub fn permits(&self, accessor: Owner, flags: OpenFlags) -> bool {
+        if accessor.is_root() {
+            return true;
+        }
+
+        let (read, write) = if accessor.uid == self.owner.uid {
+            (Mode::OWNER_READ, Mode::OWNER_WRITE)
+        } else if accessor.gid == self.owner.gid {
+            (Mode::GROUP_READ, Mode::GROUP_WRITE)
+        } else {
+            (Mode::OTHER_READ, Mode::OTHER_WRITE)
+        };
+
+        (!flags.contains(OpenFlags::READ) || self.mode.contains(read))
+            && (!flags.contains(OpenFlags::WRITE) || self.mode.contains(write))
+    }
+}
+
+impl From<Metadata> for Stat {
+    fn from(metadata: Metadata) -> Self {
+        Self {
+            kind: FileKind::from(metadata.kind) as u32,
+            mode: metadata.mode.bits(),
+            uid: metadata.owner.uid,
+            gid: metadata.owner.gid,
+            size: metadata.size as u64,
+            accessed: metadata.accessed,
+            modified: metadata.modified,
+            created: metadata.created,
+        }
+    }
+}
+
+impl From<NodeKind> for FileKind {
+    fn from(kind: NodeKind) -> Self {
+        match kind {
+            NodeKind::File => FileKind::File,
+            NodeKind::Directory => FileKind::Directory,
+            NodeKind::Device => FileKind::Device,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirEntry {
+    pub name: String,
+    pub kind: NodeKind,
+}
+
+pub type NodeRef = Arc<dyn Node>;
+
+/// A file or directory within a mounted filesystem.
+pub trait Node: Send + Sync {
+    fn metadata(&self) -> Metadata;
+
+    #[inline]
+    fn kind(&self) -> NodeKind {
+        self.metadata().kind
+    }
+
+    /// Looks up the child `name` of this directory.
+    fn lookup(&self, _name: &str) -> Result<NodeRef> {
+        Err(Error::NotDirectory)
+    }
+
+    /// Returns the entry of this directory which follows the entry named `cursor` (or the first entry, if `None`).
+    ///
+    /// #### Remark
+    ///
+    /// Enumeration order is up to the filesystem, but must be stable while the directory is unmodified. Filesystems
+    /// should also continue correctly from a `cursor` whose entry has since been removed (i.e. by ordering by name).
+    fn read_dir(&self, _cursor: Option<&str>) -> Result<Option<DirEntry>> {
+        Err(Error::NotDirectory)
+    }
+
+    /// Reads from this file at `offset` into `buf`, returning the number of bytes read.
+    ///
+    /// #### Remark
+    ///
+    /// Reads update the access time, and writes the modification time.
+    fn read(&self, _offset: usize, _buf: &mut [u8]) -> Result<usize> {
+        Err(Error::IsDirectory)
+    }
+
+    /// Writes `buf` into this file at `offset`, returning the number of bytes written.
+    fn write(&self, _offset: usize, _buf: &[u8]) -> Result<usize> {
+        Err(Error::IsDirectory)
+    }
+
+    /// Creates a new, empty child `name` of this directory.
+    fn create(&self, _name: &str, _kind: NodeKind, _mode: Mode, _owner: Owner) -> Result<NodeRef> {
+        Err(Error::Unsupported)
+    }
+
+    /// Adds `node` (which belongs to the same filesystem) to this directory as `name`, replacing any existing entry.
+    fn attach(&self, _name: &str, _node: NodeRef) -> Result<()> {
+        Err(Error::Unsupported)
+    }
+
+    /// Removes the entry `name` from this directory, returning its node.
+    fn detach(&self, _name: &str) -> Result<NodeRef> {
+        Err(Error::Unsupported)
+    }
+
+    /// Handles the device-specific `request`, whose argument is read from and written back to `arg`, returning the
+    /// number of bytes of `arg` written.
+    fn control(&self, _request: u32, _arg: &mut [u8]) -> Result<usize> {
+        Err(Error::Unsupported)
+    }
+
+    /// Returns the frame backing page `index` of this device, so it can be mapped into an address space.
+    ///
+    /// #### Remark
+    ///
+    /// Returned frames are owned by the device, and must remain valid for as long as the device exists.
+    fn map_frame(&self, _index: usize) -> Result<Address<Frame>> {
+        Err(Error::Unsupported)
+    }
+
+    /// Capabilities a process must hold to open this node from userspace, regardless of its mode.
+    #[inline]
+    fn required_capabilities(&self) -> libsys::syscall::cred::Capabilities {
+        libsys::syscall::cred::Capabilities::empty()
+    }
+}
+
+bitflags::bitflags! {
+    /// Optional operations supported by a filesystem.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Capabilities: u32 {
+        /// Nodes can be created, removed, and renamed.
+        const WRITE = 1 << 0;
+        /// Files can be linked under more than one name.
+        const HARD_LINKS = 1 << 1;
+    }
+}
+
+pub trait Filesystem: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn root(&self) -> NodeRef;
+    fn capabilities(&self) -> Capabilities;
+}
+
+/// Mounted filesystems, keyed by the canonical path of their mount point.
+static MOUNTS: RwLock<BTreeMap<String, Arc<dyn Filesystem>>> = RwLock::new(BTreeMap::new());
+
+/// Mounts the root filesystem, and the device filesystem at `/dev`.
+///
+/// #### Remark
+///
+/// The root is the initramfs if the bootloader provided one (see [`initramfs`]), or an empty tmpfs otherwise.
+pub fn init() -> Result<()> {
+    let initramfs = crate::init::modules::get(initramfs::MODULE_NAME).and_then(|module| {
+        let mount_points = [
+            devfs::MOUNT_PATH,
+            #[cfg(feature = "fat32")]
+            fat32::ESP_MOUNT_PATH,
+        ];
+
+        initramfs::Initramfs::parse(module.data(), &mount_points)
+            .inspect_err(|err| warn!("Failed to unpack the initramfs: {:?}", err))
+            .ok()
+    });
+
+    match initramfs {
+        Some(initramfs) => mount(ROOT, Arc::new(initramfs))?,
+        None => {
+            mount(ROOT, Arc::new(tmpfs::Tmpfs::new()))?;
+            create(ROOT, devfs::MOUNT_PATH, NodeKind::Directory, Mode::from_bits_retain(0o755), Owner::ROOT)?;
+        }
+    }
+
+    mount(devfs::MOUNT_PATH, Arc::new(devfs::Devfs))
+}
+
+/// Mounts `filesystem` at the canonical path `path`, which must be an existing directory (or the root).
+pub fn mount(path: &str, filesystem: Arc<dyn Filesystem>) -> Result<()> {
+    let path = resolve(ROOT, path)?;
+    if path != ROOT && lookup(ROOT, &path)?.kind() != NodeKind::Directory {
+        return Err(Error::NotDirectory);
+    }
+
+    info!("Mounting {} at {}", filesystem.name(), path);
+
+    MOUNTS.write().try_insert(path, filesystem).map(|_| ()).map_err(|_| Error::AlreadyMounted)
+}
+
+/// Returns the mount point & filesystem which contain the canonical path `path`.
+fn find_mount(path: &str) -> Result<(String, Arc<dyn Filesystem>)> {
+    let mounts = MOUNTS.read();
+    let (mount_path, filesystem) = mounts
+        .iter()
+        .filter(|(mount_path, _)| starts_with(path, mount_path))
+        .max_by_key(|(mount_path, _)| mount_path.len())
+        .ok_or(Error::NotFound)?;
+
+    Ok((mount_path.clone(), filesystem.clone()))
+}
+
+/// Looks up the node at `path`, which is resolved relative to `cwd` if it isn't absolute.
+pub fn lookup(cwd: &str, path: &str) -> Result<NodeRef> {
+    let path = resolve(cwd, path)?;
+    let (mount_path, filesystem) = find_mount(&path)?;
+
+    components(&path[mount_path.len()..]).try_fold(filesystem.root(), |node, name| node.lookup(name))
+}
+
+/// The parent directory of a path which is to be modified.
+struct Parent {
+    /// Canonical path of the entry within the parent.
+    path: String,
+    mount_path: String,
+    capabilities: Capabilities,
+    node: NodeRef,
+}
+
+impl Parent {
+    /// Looks up the parent directory of `path`, checking that `accessor` may modify it.
+    fn lookup(cwd: &str, path: &str, accessor: Owner) -> Result<Self> {
+        let path = resolve(cwd, path)?;
+        let (parent_path, _) = split_last(&path).ok_or(Error::InvalidPath)?;
+
+        // Mount points can't be modified through the filesystem they're mounted on.
+        if MOUNTS.read().contains_key(&path) {
+            return Err(Error::Busy);
+        }
+
+        let node = lookup(ROOT, parent_path)?;
+        let metadata = node.metadata();
+        if metadata.kind != NodeKind::Directory {
+            return Err(Error::NotDirectory);
+        } else if !metadata.permits(accessor, OpenFlags::WRITE) {
+            return Err(Error::PermissionDenied);
+        }
+
+        let (mount_path, filesystem) = find_mount(parent_path)?;
+        let capabilities = filesystem.capabilities();
+        if !capabilities.contains(Capabilities::WRITE) {
+            return Err(Error::Unsupported);
+        }
+
+        Ok(Self { path, mount_path, capabilities, node })
+    }
+
+    fn name(&self) -> &str {
+        split_last(&self.path).map(|(_, name)| name).unwrap()
+    }
+
+    /// Looks up the entry within the parent, if it exists.
+    fn entry(&self) -> Result<Option<NodeRef>> {
+        match self.node.lookup(self.name()) {
+            Ok(node) => Ok(Some(node)),
+            Err(Error::NotFound) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+fn is_empty_directory(node: &NodeRef) -> Result<bool> {
+    Ok(node.kind() == NodeKind::Directory && node.read_dir(None)?.is_none())
+}
+
+/// Creates an empty file or directory at `path`, owned by `accessor`.
+pub fn create(cwd: &str, path: &str, kind: NodeKind, mode: Mode, accessor: Owner) -> Result<NodeRef> {
+    let parent = Parent::lookup(cwd, path, accessor)?;
+    if parent.entry()?.is_some() {
+        return Err(Error::AlreadyExists);
+    }
+
+    parent.node.create(parent.name(), kind, mode, accessor)
+}
+
+/// Removes the file or (empty) directory at `path`.
+pub fn unlink(cwd: &str, path: &str, accessor: Owner) -> Result<()> {
+    let parent = Parent::lookup(cwd, path, accessor)?;
+    let node = parent.entry()?.ok_or(Error::NotFound)?;
+    if node.kind() == NodeKind::Directory && !is_empty_directory(&node)? {
+        return Err(Error::NotEmpty);
+    }
+
+    parent.node.detach(parent.name()).map(|_| ())
+}
+
+/// Links the existing file at `existing` under the new path `new`.
+pub fn link(cwd: &str, existing: &str, new: &str, accessor: Owner) -> Result<()> {
+    let node = lookup(cwd, existing)?;
+    if node.kind() == NodeKind::Directory {
+        return Err(Error::IsDirectory);
+    }
+
+    let parent = Parent::lookup(cwd, new, accessor)?;
+    if find_mount(&resolve(cwd, existing)?)?.0 != parent.mount_path {
+        return Err(Error::CrossDevice);
+    } else if !parent.capabilities.contains(Capabilities::HARD_LINKS) {
+        return Err(Error::Unsupported);
+    } else if parent.entry()?.is_some() {
+        return Err(Error::AlreadyExists);
+    }
+
+    parent.node.attach(parent.name(), node)
+}
+
+/// Moves the node at `from` to `to`, replacing the node at `to` if it's a file, or an empty directory.
+pub fn rename(cwd: &str, from: &str, to: &str, accessor: Owner) -> Result<()> {
+    let from = Parent::lookup(cwd, from, accessor)?;
+    let to = Parent::lookup(cwd, to, accessor)?;
+    if from.mount_path != to.mount_path {
+        return Err(Error::CrossDevice);
+    }
+
+    let node = from.entry()?.ok_or(Error::NotFound)?;
+    if from.path == to.path {
+        return Ok(());
+    } else if node.kind() == NodeKind::Directory && starts_with(&to.path, &from.path) {
+        // A directory can't be moved beneath itself.
+        return Err(Error::InvalidPath);
+    }
+
+    if let Some(existing) = to.entry()? {
+        match (node.kind(), existing.kind()) {
+            (NodeKind::Directory, NodeKind::File) => return Err(Error::NotDirectory),
+            (NodeKind::File, NodeKind::Directory) => return Err(Error::IsDirectory),
+            (NodeKind::Directory, NodeKind::Directory) if !is_empty_directory(&existing)? => {
+                return Err(Error::NotEmpty)
+            }
+            _ => {}
+        }
+    }
+
+    to.node.attach(to.name(), node)?;
+    from.node.detach(from.name()).map(|_| ())
+}
+
+/// Opens the node at `path` on behalf of `accessor`, checking its permissions.
+pub fn open(cwd: &str, path: &str, flags: OpenFlags, accessor: Owner) -> Result<OpenFile> {
+    let node = lookup(cwd, path)?;
+    let metadata = node.metadata();
+
+    if metadata.kind == NodeKind::Directory && flags.contains(OpenFlags::WRITE) {
+        Err(Error::IsDirectory)
+    } else if !metadata.permits(accessor, flags) {
+        Err(Error::PermissionDenied)
+    } else {
+        Ok(OpenFile::new(node, flags))
+    }
+}
@@ -0,0 +1,60 @@
+use super::{Error, Result};
+use alloc::{string::String, vec::Vec};
+
+pub const SEPARATOR: char = '/';
+pub const ROOT: &str = "/";
+
+/// Iterates the components of `path`, skipping empty and `.` components.
+pub fn components(path: &str) -> impl Iterator<Item = &str> {
+    path.split(SEPARATOR).filter(|component| !component.is_empty() && *component != ".")
+}
+
+/// Resolves `path` against the absolute directory path `cwd`, producing a canonical absolute path.
+///
+/// #### Remark
+///
+/// `..` components are resolved lexically, and `..` of the root is the root itself.
+pub fn resolve(cwd: &str, path: &str) -> Result<String> {
+    if path.is_empty() {
+        return Err(Error::InvalidPath);
+    }
+
+    let base = if path.starts_with(SEPARATOR) { ROOT } else { cwd };
+
+    let mut resolved = Vec::new();
+    for component in components(base).chain(components(path)) {
+        if component == ".." {
+            resolved.pop();
+        } else {
+            resolved.push(component);
+        }
+    }
+
+    Ok(join(resolved))
+}
+
+fn join<'a>(components: impl IntoIterator<Item = &'a str>) -> String {
+    let path = components.into_iter().fold(String::new(), |mut path, component| {
+        path.push(SEPARATOR);
+        path.push_str(component);
+        path
+    });
+
+    if path.is_empty() {
+        String::from(ROOT)
+    } else {
+        path
+    }
+}
+
+/// Splits the canonical path `path` into its parent path and final component, or `None` if it is the root.
+pub fn split_last(path: &str) -> Option<(&str, &str)> {
+    let (parent, name) = path.rsplit_once(SEPARATOR)?;
+
+    (!name.is_empty()).then_some((if parent.is_empty() { ROOT } else { parent }, name))
+}
+
+/// Indicates whether the canonical path `path` is `prefix`, or lies beneath it.
+pub fn starts_with(path: &str, prefix: &str) -> bool {
+    prefix == ROOT || path.strip_prefix(prefix).is_some_and(|rest| rest.is_empty() || rest.starts_with(SEPARATOR))
+}
@@ -0,0 +1,173 @@
+//! In-memory filesystem, whose contents are lost when it is dropped.
+
+use super::{Capabilities, DirEntry, Error, Filesystem, Metadata, Node, NodeKind, NodeRef, Owner, Result};
+use alloc::{collections::BTreeMap, string::String, sync::Arc, vec::Vec};
+use libsys::syscall::fs::Mode;
+use spin::RwLock;
+
+pub struct Tmpfs {
+    root: Arc<Directory>,
+}
+
+impl Tmpfs {
+    pub fn new() -> Self {
+        Self { root: Arc::new(Directory::new(Mode::from_bits_retain(0o755), Owner::ROOT)) }
+    }
+}
+
+impl Default for Tmpfs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Filesystem for Tmpfs {
+    fn name(&self) -> &'static str {
+        "tmpfs"
+    }
+
+    fn root(&self) -> NodeRef {
+        self.root.clone()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::WRITE | Capabilities::HARD_LINKS
+    }
+}
+
+/// Attributes common to every tmpfs node.
+struct Attributes {
+    mode: Mode,
+    owner: Owner,
+    accessed: u64,
+    modified: u64,
+    created: u64,
+}
+
+impl Attributes {
+    fn new(mode: Mode, owner: Owner) -> Self {
+        let now = crate::time::coarse_ms();
+
+        Self { mode, owner, accessed: now, modified: now, created: now }
+    }
+
+    fn metadata(&self, kind: NodeKind, size: usize) -> Metadata {
+        Metadata {
+            kind,
+            size,
+            mode: self.mode,
+            owner: self.owner,
+            accessed: self.accessed,
+            modified: self.modified,
+            created: self.created,
+        }
+    }
+}
+
+struct Directory {
+    attributes: RwLock<Attributes>,
+    entries: RwLock<BTreeMap<String, NodeRef>>,
+}
+
+impl Directory {
+    fn new(mode: Mode, owner: Owner) -> Self {
+        Self { attributes: RwLock::new(Attributes::new(mode, owner)), entries: RwLock::new(BTreeMap::new()) }
+    }
+}
+
+impl Node for Directory {
+    fn metadata(&self) -> Metadata {
+        // Entries are locked (and unlocked) first, as they are while changing the attributes.
+        let len = self.entries.read().len();
+        self.attributes.read().metadata(NodeKind::Directory, len)
+    }
+
+    fn lookup(&self, name: &str) -> Result<NodeRef> {
+        self.entries.read().get(name).cloned().ok_or(Error::NotFound)
+    }
+
+    fn read_dir(&self, cursor: Option<&str>) -> Result<Option<DirEntry>> {
+        use core::ops::Bound;
+
+        self.attributes.write().accessed = crate::time::coarse_ms();
+
+        let entries = self.entries.read();
+        let lower_bound = cursor.map_or(Bound::Unbounded, Bound::Excluded);
+
+        Ok(entries
+            .range::<str, _>((lower_bound, Bound::Unbounded))
+            .next()
+            .map(|(name, node)| DirEntry { name: name.clone(), kind: node.kind() }))
+    }
+
+    fn create(&self, name: &str, kind: NodeKind, mode: Mode, owner: Owner) -> Result<NodeRef> {
+        let node: NodeRef = match kind {
+            NodeKind::File => Arc::new(File::new(mode, owner)),
+            NodeKind::Directory => Arc::new(Directory::new(mode, owner)),
+            NodeKind::Device => return Err(Error::Unsupported),
+        };
+
+        self.attach(name, node.clone())?;
+
+        Ok(node)
+    }
+
+    fn attach(&self, name: &str, node: NodeRef) -> Result<()> {
+        self.entries.write().insert(String::from(name), node);
+        self.attributes.write().modified = crate::time::coarse_ms();
+
+        Ok(())
+    }
+
+    fn detach(&self, name: &str) -> Result<NodeRef> {
+        let node = self.entries.write().remove(name).ok_or(Error::NotFound)?;
+        self.attributes.write().modified = crate::time::coarse_ms();
+
+        Ok(node)
+    }
+}
+
+struct File {
+    attributes: RwLock<Attributes>,
+    data: RwLock<Vec<u8>>,
+}
+
+impl File {
+    fn new(mode: Mode, owner: Owner) -> Self {
+        Self { attributes: RwLock::new(Attributes::new(mode, owner)), data: RwLock::new(Vec::new()) }
+    }
+}
+
+impl Node for File {
+    fn metadata(&self) -> Metadata {
+        // Data is locked (and unlocked) first, as it is while changing the attributes.
+        let len = self.data.read().len();
+        self.attributes.read().metadata(NodeKind::File, len)
+    }
+
+    fn read(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        let data = self.data.read();
+        self.attributes.write().accessed = crate::time::coarse_ms();
+
+        let Some(remaining) = data.get(offset..) else { return Ok(0) };
+
+        let len = remaining.len().min(buf.len());
+        buf[..len].copy_from_slice(&remaining[..len]);
+
+        Ok(len)
+    }
+
+    fn write(&self, offset: usize, buf: &[u8]) -> Result<usize> {
+        let mut data = self.data.write();
+        self.attributes.write().modified = crate::time::coarse_ms();
+
+        let end = offset + buf.len();
+        if data.len() < end {
+            // Writing past the end of the file zero-fills the gap.
+            data.resize(end, 0);
+        }
+        data[offset..end].copy_from_slice(buf);
+
+        Ok(buf.len())
+    }
+}
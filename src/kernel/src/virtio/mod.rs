@@ -0,0 +1,337 @@
+//! Virtio devices, over the modern PCI transport.
+//!
+//! The transport's registers are found through vendor-specific capabilities of the PCI function, each locating a region
+//! of one of its memory BARs: the common configuration (feature negotiation, device status, and queue setup), the
+//! notification doorbells of the queues, and the configuration specific to the device type. Legacy (pre-1.0) devices
+//! lack these capabilities, and aren't supported.
+//!
+//! A device driver [`Transport::new`]s the transport, negotiates its features, sets up its [`Virtqueue`]s, and then
+//! marks itself ready with [`Transport::finish`].
+
+mod queue;
+
+pub use queue::*;
+
+use crate::mem::{
+    alloc::pmm,
+    io::{
+        pci::{self, Device, Standard},
+        poll,
+    },
+    vmap::Mapping,
+};
+use alloc::vec::Vec;
+use bit_field::BitField;
+use core::ptr::NonNull;
+
+crate::error_impl! {
+    #[derive(Debug)]
+    pub enum Error {
+        Pci { err: pci::device::Error } => Some(err),
+        Allocation { err: pmm::Error } => None,
+        /// The device has no capability of the configuration type `cfg_type` (i.e. it's a legacy device).
+        MissingCapability { cfg_type: u8 } => None,
+        /// The region of the capability of configuration type `cfg_type` lies beyond its BAR.
+        InvalidCapability { cfg_type: u8 } => None,
+        /// The device doesn't offer the features `missing`, which the driver requires.
+        MissingFeatures { missing: u64 } => None,
+        /// The device didn't accept the features the driver negotiated.
+        FeaturesRejected => None,
+        /// The device has no queue `index`.
+        NoSuchQueue { index: u16 } => None,
+        /// The device didn't reset, or a request didn't complete, in time.
+        Timeout => None
+    }
+}
+
+/// PCI vendor ID of every virtio device.
+pub const VENDOR_ID: u16 = 0x1AF4;
+
+/// The device conforms to the virtio 1.0 (or later) specification, rather than the legacy interface.
+pub const F_VERSION_1: u64 = 1 << 32;
+
+/// Capability ID of vendor-specific capabilities.
+const CAPABILITY_VENDOR: u8 = 0x09;
+
+/// Configuration types of the transport's capabilities.
+const CFG_COMMON: u8 = 1;
+const CFG_NOTIFY: u8 = 2;
+const CFG_DEVICE: u8 = 4;
+
+/// Offsets of the fields of the common configuration.
+mod common {
+    pub const DEVICE_FEATURE_SELECT: usize = 0x00;
+    pub const DEVICE_FEATURE: usize = 0x04;
+    pub const DRIVER_FEATURE_SELECT: usize = 0x08;
+    pub const DRIVER_FEATURE: usize = 0x0C;
+    pub const NUM_QUEUES: usize = 0x12;
+    pub const DEVICE_STATUS: usize = 0x14;
+    pub const CONFIG_GENERATION: usize = 0x15;
+    pub const QUEUE_SELECT: usize = 0x16;
+    pub const QUEUE_SIZE: usize = 0x18;
+    pub const QUEUE_MSIX_VECTOR: usize = 0x1A;
+    pub const QUEUE_ENABLE: usize = 0x1C;
+    pub const QUEUE_NOTIFY_OFF: usize = 0x1E;
+    pub const QUEUE_DESC: usize = 0x20;
+    pub const QUEUE_DRIVER: usize = 0x28;
+    pub const QUEUE_DEVICE: usize = 0x30;
+}
+
+/// Bits of the device status.
+mod status {
+    pub const ACKNOWLEDGE: u8 = 1 << 0;
+    pub const DRIVER: u8 = 1 << 1;
+    pub const DRIVER_OK: u8 = 1 << 2;
+    pub const FEATURES_OK: u8 = 1 << 3;
+    pub const FAILED: u8 = 1 << 7;
+}
+
+/// Queue MSI-X vector which leaves the queue without an interrupt.
+const NO_VECTOR: u16 = 0xFFFF;
+
+/// Time the device may take to reset.
+const RESET_TIMEOUT_US: u32 = 1_000_000;
+
+/// A region of a mapped BAR, which bounds each access to it.
+struct Region {
+    ptr: NonNull<u8>,
+    len: usize,
+}
+
+// Safety: Region lies within a mapping of the kernel's window, which is owned by its transport.
+unsafe impl Send for Region {}
+// Safety: See above.
+unsafe impl Sync for Region {}
+
+impl Region {
+    fn read<T: Copy>(&self, offset: usize) -> T {
+        assert!(offset + core::mem::size_of::<T>() <= self.len);
+
+        // Safety: Offset lies within the region, and device registers are naturally aligned.
+        unsafe { self.ptr.as_ptr().add(offset).cast::<T>().read_volatile() }
+    }
+
+    fn write<T: Copy>(&self, offset: usize, value: T) {
+        assert!(offset + core::mem::size_of::<T>() <= self.len);
+
+        // Safety: Offset lies within the region, and device registers are naturally aligned.
+        unsafe { self.ptr.as_ptr().add(offset).cast::<T>().write_volatile(value) };
+    }
+}
+
+/// A capability of the transport, locating a region of one of the function's BARs.
+#[derive(Debug, Clone, Copy)]
+struct Capability {
+    cfg_type: u8,
+    bar: usize,
+    offset: usize,
+    len: usize,
+    /// Dword following the capability's common fields (i.e. the notify offset multiplier).
+    extra: u32,
+}
+
+/// Returns the first capability of each configuration type the device has.
+fn capabilities(device: &Device<Standard>) -> Vec<Capability> {
+    let mut found: Vec<Capability> = Vec::new();
+
+    for (_, offset) in device.capabilities().filter(|(id, _)| *id == CAPABILITY_VENDOR) {
+        let header = device.read_capability_dword(offset, 0);
+        let capability = Capability {
+            cfg_type: u8::try_from(header.get_bits(24..32)).unwrap(),
+            bar: usize::try_from(device.read_capability_dword(offset, 1).get_bits(0..8)).unwrap(),
+            offset: usize::try_from(device.read_capability_dword(offset, 2)).unwrap(),
+            len: usize::try_from(device.read_capability_dword(offset, 3)).unwrap(),
+            extra: if header.get_bits(16..24) >= 20 { device.read_capability_dword(offset, 4) } else { 0 },
+        };
+
+        // Reserved BAR indices mark capabilities the driver should ignore.
+        if capability.bar < 6 && !found.iter().any(|other| other.cfg_type == capability.cfg_type) {
+            found.push(capability);
+        }
+    }
+
+    found
+}
+
+/// The modern PCI transport of a virtio device.
+///
+/// #### Remark
+///
+/// Dropping it resets the device, so it stops using its queues. A driver should hold its transport in a field declared
+/// before its queues, so it's dropped before them.
+pub struct Transport {
+    common: Region,
+    notify: Region,
+    /// Bytes between the doorbells of consecutive queue notify offsets.
+    notify_multiplier: usize,
+    device_config: Region,
+    /// Mappings of the BARs the regions lie within.
+    _mappings: Vec<(usize, Mapping)>,
+}
+
+impl Transport {
+    /// Maps the transport's regions of `device`, and resets it, acknowledging it as a driver for it.
+    pub fn new(device: &mut Device<Standard>) -> Result<Self> {
+        let capabilities = capabilities(device);
+        let find = |cfg_type| {
+            capabilities
+                .iter()
+                .copied()
+                .find(|capability| capability.cfg_type == cfg_type)
+                .ok_or(Error::MissingCapability { cfg_type })
+        };
+        let (common, notify, device_config) = (find(CFG_COMMON)?, find(CFG_NOTIFY)?, find(CFG_DEVICE)?);
+
+        device.set_memory_decode(true);
+        device.set_bus_master(true);
+
+        let mut mappings: Vec<(usize, Mapping)> = Vec::new();
+        let mut region = |capability: Capability| {
+            let index = match mappings.iter().position(|(bar, _)| *bar == capability.bar) {
+                Some(index) => index,
+
+                None => {
+                    let mapping = device.map_bar(capability.bar).map_err(|err| Error::Pci { err })?;
+                    mappings.push((capability.bar, mapping));
+                    mappings.len() - 1
+                }
+            };
+            let mapping = &mappings[index].1;
+
+            if capability.offset.checked_add(capability.len).is_none_or(|end| end > mapping.len()) {
+                return Err(Error::InvalidCapability { cfg_type: capability.cfg_type });
+            }
+
+            Ok(Region {
+                // Safety: Offset was just checked to lie within the mapping.
+                ptr: unsafe { mapping.as_ptr().add(capability.offset) },
+                len: capability.len,
+            })
+        };
+
+        let transport = Self {
+            common: region(common)?,
+            notify: region(notify)?,
+            notify_multiplier: usize::try_from(notify.extra).unwrap(),
+            device_config: region(device_config)?,
+            _mappings: mappings,
+        };
+
+        transport.reset()?;
+        transport.add_status(status::ACKNOWLEDGE | status::DRIVER);
+
+        Ok(transport)
+    }
+
+    /// Resets the device, waiting for it to finish.
+    fn reset(&self) -> Result<()> {
+        self.common.write::<u8>(common::DEVICE_STATUS, 0);
+
+        poll::poll_until_yield(RESET_TIMEOUT_US, || self.common.read::<u8>(common::DEVICE_STATUS) == 0)
+            .map_err(|_| Error::Timeout)
+    }
+
+    fn add_status(&self, bits: u8) {
+        let status = self.common.read::<u8>(common::DEVICE_STATUS);
+        self.common.write::<u8>(common::DEVICE_STATUS, status | bits);
+    }
+
+    /// Negotiates the `required` features (along with [`F_VERSION_1`]) and whichever of the `optional` features the
+    /// device offers, returning the features negotiated.
+    pub fn negotiate(&self, required: u64, optional: u64) -> Result<u64> {
+        let required = required | F_VERSION_1;
+
+        let mut offered = 0;
+        for half in 0..2u32 {
+            self.common.write::<u32>(common::DEVICE_FEATURE_SELECT, half);
+            offered |= u64::from(self.common.read::<u32>(common::DEVICE_FEATURE)) << (32 * half);
+        }
+
+        let missing = required & !offered;
+        if missing > 0 {
+            self.add_status(status::FAILED);
+            return Err(Error::MissingFeatures { missing });
+        }
+
+        let features = offered & (required | optional);
+        let halves = [features.get_bits(0..32), features.get_bits(32..64)];
+        for (select, half) in (0u32..).zip(halves) {
+            self.common.write::<u32>(common::DRIVER_FEATURE_SELECT, select);
+            self.common.write::<u32>(common::DRIVER_FEATURE, u32::try_from(half).unwrap());
+        }
+
+        self.add_status(status::FEATURES_OK);
+        if (self.common.read::<u8>(common::DEVICE_STATUS) & status::FEATURES_OK) == 0 {
+            self.add_status(status::FAILED);
+            return Err(Error::FeaturesRejected);
+        }
+
+        Ok(features)
+    }
+
+    /// Number of queues the device has.
+    pub fn queue_count(&self) -> u16 {
+        self.common.read::<u16>(common::NUM_QUEUES)
+    }
+
+    /// Sets up queue `index` with at most `max_size` entries (or as many as the device allows, if fewer), without an
+    /// interrupt, and enables it.
+    ///
+    /// #### Remark
+    ///
+    /// Split queue sizes are a power of two, so the size is rounded down to one.
+    pub fn queue(&self, index: u16, max_size: u16) -> Result<Virtqueue> {
+        if index >= self.queue_count() {
+            return Err(Error::NoSuchQueue { index });
+        }
+
+        self.common.write::<u16>(common::QUEUE_SELECT, index);
+        let size = match self.common.read::<u16>(common::QUEUE_SIZE).min(max_size) {
+            0 => return Err(Error::NoSuchQueue { index }),
+            size => 1 << size.ilog2(),
+        };
+
+        let notify_offset = usize::from(self.common.read::<u16>(common::QUEUE_NOTIFY_OFF)) * self.notify_multiplier;
+        if notify_offset + core::mem::size_of::<u16>() > self.notify.len {
+            return Err(Error::NoSuchQueue { index });
+        }
+        // Safety: Doorbell was just checked to lie within the notify region.
+        let doorbell = unsafe { self.notify.ptr.add(notify_offset) }.cast::<u16>();
+        let queue = Virtqueue::new(index, size, doorbell)?;
+
+        self.common.write::<u16>(common::QUEUE_SIZE, size);
+        self.common.write::<u16>(common::QUEUE_MSIX_VECTOR, NO_VECTOR);
+        self.common.write::<u64>(common::QUEUE_DESC, u64::try_from(queue.descriptors_address()).unwrap());
+        self.common.write::<u64>(common::QUEUE_DRIVER, u64::try_from(queue.available_address()).unwrap());
+        self.common.write::<u64>(common::QUEUE_DEVICE, u64::try_from(queue.used_address()).unwrap());
+        self.common.write::<u16>(common::QUEUE_ENABLE, 1);
+
+        Ok(queue)
+    }
+
+    /// Marks the driver as ready, after which the device may process its queues.
+    pub fn finish(&self) {
+        self.add_status(status::DRIVER_OK);
+    }
+
+    /// Reads the field of type `T` at `offset` of the device-specific configuration, retrying until it's read
+    /// consistently with the rest of the configuration.
+    pub fn read_config<T: Copy>(&self, offset: usize) -> T {
+        loop {
+            let generation = self.common.read::<u8>(common::CONFIG_GENERATION);
+            let value = self.device_config.read::<T>(offset);
+
+            if self.common.read::<u8>(common::CONFIG_GENERATION) == generation {
+                break value;
+            }
+        }
+    }
+}
+
+impl Drop for Transport {
+    fn drop(&mut self) {
+        if let Err(err) = self.reset() {
+            error!("Failed to reset virtio device: {:?}", err);
+        }
+    }
+}
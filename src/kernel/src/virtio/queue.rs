@@ -0,0 +1,281 @@
+//! Split virtqueues: a descriptor table, the available ring the driver offers descriptor chains on, and the used ring
+//! the device returns them on.
+//!
+//! Each chain in flight is tracked by its head descriptor. Chains are reaped from the used ring by whichever waiter
+//! polls it, as the queue is set up without an interrupt.
+
+use super::{Error, Result};
+use crate::{
+    interrupts::InterruptCell,
+    mem::io::{dma::DmaBuffer, poll},
+};
+use alloc::{boxed::Box, vec::Vec};
+use core::{
+    ptr::NonNull,
+    sync::atomic::{fence, AtomicU32, Ordering},
+};
+use spin::Mutex;
+
+/// Head holds no chain.
+const HEAD_FREE: u32 = 0;
+/// Head's chain has been offered to the device, and is yet to be used.
+const HEAD_PENDING: u32 = 1;
+/// Head's chain has been used by the device.
+const HEAD_DONE: u32 = 2;
+
+/// Descriptor continues onto the one in its `next` field.
+const DESC_F_NEXT: u16 = 1 << 0;
+/// Descriptor's buffer is written by the device (rather than read).
+const DESC_F_WRITE: u16 = 1 << 1;
+
+/// Asks the device not to interrupt as it uses chains.
+const AVAIL_F_NO_INTERRUPT: u16 = 1 << 0;
+
+/// Time a chain may take to be used before it's given up on.
+pub const REQUEST_TIMEOUT_US: u32 = 5_000_000;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct Descriptor {
+    address: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+/// A buffer of a descriptor chain, by its physical address.
+#[derive(Debug, Clone, Copy)]
+pub struct Segment {
+    pub address: usize,
+    pub len: u32,
+    /// Whether the device writes the buffer, rather than reading it.
+    pub writable: bool,
+}
+
+struct State {
+    /// Descriptors not in any chain.
+    free: Vec<u16>,
+    /// Descriptors of the chain each head descriptor begins (kept by the driver, rather than trusting the table).
+    chains: Box<[Vec<u16>]>,
+    /// Index of the next available ring entry.
+    available_index: u16,
+    /// Index of the next used ring entry to reap.
+    used_index: u16,
+}
+
+pub struct Virtqueue {
+    index: u16,
+    size: u16,
+    descriptors: DmaBuffer,
+    available: DmaBuffer,
+    used: DmaBuffer,
+    /// Doorbell within the transport's notify region.
+    doorbell: NonNull<u16>,
+    state: InterruptCell<Mutex<State>>,
+    /// State of the chain of each head descriptor (see [`HEAD_FREE`]).
+    heads: Box<[AtomicU32]>,
+    /// Bytes the device wrote to the chain of each head descriptor, as it used it.
+    used_lens: Box<[AtomicU32]>,
+}
+
+// Safety: Doorbell lies within the notify region of the transport, which outlives the queue.
+unsafe impl Send for Virtqueue {}
+// Safety: See above.
+unsafe impl Sync for Virtqueue {}
+
+impl Virtqueue {
+    /// Allocates the rings of queue `index`, of `size` entries each, notifying the device through `doorbell`.
+    pub(super) fn new(index: u16, size: u16, doorbell: NonNull<u16>) -> Result<Self> {
+        let allocate = |len| DmaBuffer::new(len).map_err(|err| Error::Allocation { err });
+        let entries = usize::from(size);
+
+        let queue = Self {
+            index,
+            size,
+            descriptors: allocate(entries * core::mem::size_of::<Descriptor>())?,
+            // Flags, index, ring, and used event.
+            available: allocate(6 + (entries * 2))?,
+            // Flags, index, ring of (ID, length), and available event.
+            used: allocate(6 + (entries * 8))?,
+            doorbell,
+            state: InterruptCell::new(Mutex::new(State {
+                free: (0..size).rev().collect(),
+                chains: (0..size).map(|_| Vec::new()).collect(),
+                available_index: 0,
+                used_index: 0,
+            })),
+            heads: (0..size).map(|_| AtomicU32::new(HEAD_FREE)).collect(),
+            used_lens: (0..size).map(|_| AtomicU32::new(0)).collect(),
+        };
+
+        // Safety: Flags are the first field of the available ring.
+        unsafe { queue.available.as_ptr().cast::<u16>().write_volatile(AVAIL_F_NO_INTERRUPT) };
+
+        Ok(queue)
+    }
+
+    #[inline]
+    pub const fn index(&self) -> u16 {
+        self.index
+    }
+
+    /// Number of entries of the queue, which may be fewer than were asked for (see [`super::Transport::queue`]).
+    #[inline]
+    pub const fn size(&self) -> u16 {
+        self.size
+    }
+
+    #[inline]
+    pub fn descriptors_address(&self) -> usize {
+        self.descriptors.physical()
+    }
+
+    #[inline]
+    pub fn available_address(&self) -> usize {
+        self.available.physical()
+    }
+
+    #[inline]
+    pub fn used_address(&self) -> usize {
+        self.used.physical()
+    }
+
+    /// Offers a chain of `segments` to the device, returning its head descriptor once there are enough free.
+    pub fn submit(&self, segments: &[Segment]) -> Result<u16> {
+        assert!(!segments.is_empty() && segments.len() <= usize::from(self.size));
+
+        let mut head = None;
+        poll::poll_until_yield(REQUEST_TIMEOUT_US, || {
+            self.reap();
+            head = self.try_submit(segments);
+            head.is_some()
+        })
+        .map_err(|_| Error::Timeout)?;
+
+        Ok(head.unwrap())
+    }
+
+    fn try_submit(&self, segments: &[Segment]) -> Option<u16> {
+        self.state.with(|state| {
+            let mut state = state.lock();
+            if state.free.len() < segments.len() {
+                return None;
+            }
+
+            let split = state.free.len() - segments.len();
+            let chain = state.free.split_off(split);
+            let head = chain[0];
+
+            for (index, (segment, &descriptor)) in segments.iter().zip(&chain).enumerate() {
+                let next = chain.get(index + 1).copied();
+
+                let mut flags = 0;
+                if next.is_some() {
+                    flags |= DESC_F_NEXT;
+                }
+                if segment.writable {
+                    flags |= DESC_F_WRITE;
+                }
+
+                let entry =
+                    self.descriptors.as_ptr().cast::<Descriptor>().as_ptr().wrapping_add(usize::from(descriptor));
+                // Safety: Descriptor lies within the table, and (as it was free) the device is done with it.
+                unsafe {
+                    entry.write_volatile(Descriptor {
+                        address: u64::try_from(segment.address).unwrap(),
+                        len: segment.len,
+                        flags,
+                        next: next.unwrap_or(0),
+                    });
+                }
+            }
+
+            state.chains[usize::from(head)] = chain;
+            self.heads[usize::from(head)].store(HEAD_PENDING, Ordering::Release);
+
+            let ring = self.available.as_ptr().cast::<u16>().as_ptr();
+            // Safety: Entry lies within the ring, which follows the flags and index.
+            unsafe { ring.add(2 + usize::from(state.available_index % self.size)).write_volatile(head) };
+            state.available_index = state.available_index.wrapping_add(1);
+
+            // The entry must be visible to the device before the index is, and the index before the doorbell rings.
+            fence(Ordering::SeqCst);
+            // Safety: Index is the second field of the available ring.
+            unsafe { ring.add(1).write_volatile(state.available_index) };
+            fence(Ordering::SeqCst);
+            // Safety: Doorbell lies within the transport's notify region.
+            unsafe { self.doorbell.as_ptr().write_volatile(self.index) };
+
+            Some(head)
+        })
+    }
+
+    /// Reaps any chains the device has used, returning whether there were any.
+    pub fn reap(&self) -> bool {
+        self.state.with(|state| {
+            let mut state = state.lock();
+            let ring = self.used.as_ptr().cast::<u16>().as_ptr();
+
+            // Safety: Index is the second field of the used ring.
+            let device_index = unsafe { ring.add(1).read_volatile() };
+            fence(Ordering::Acquire);
+
+            let mut reaped = false;
+            while state.used_index != device_index {
+                let entry =
+                    self.used.as_ptr().as_ptr().wrapping_add(4 + (usize::from(state.used_index % self.size) * 8));
+                // Safety: Entry lies within the ring of (ID, length) pairs following the flags and index.
+                let (id, len) =
+                    unsafe { (entry.cast::<u32>().read_volatile(), entry.add(4).cast::<u32>().read_volatile()) };
+
+                match usize::try_from(id).ok().filter(|id| *id < self.heads.len()) {
+                    Some(id) if self.heads[id].load(Ordering::Acquire) == HEAD_PENDING => {
+                        self.used_lens[id].store(len, Ordering::Relaxed);
+                        self.heads[id].store(HEAD_DONE, Ordering::Release);
+                    }
+
+                    _ => warn!("Virtqueue {} used unknown chain {}.", self.index, id),
+                }
+
+                state.used_index = state.used_index.wrapping_add(1);
+                reaped = true;
+            }
+
+            reaped
+        })
+    }
+
+    /// Takes the chain of `head` (freeing its descriptors) if the device has used it, returning the bytes it wrote.
+    pub fn take(&self, head: u16) -> Option<u32> {
+        if self.heads[usize::from(head)].load(Ordering::Acquire) != HEAD_DONE {
+            return None;
+        }
+
+        let len = self.used_lens[usize::from(head)].load(Ordering::Relaxed);
+        self.state.with(|state| {
+            let mut state = state.lock();
+            let chain = core::mem::take(&mut state.chains[usize::from(head)]);
+            state.free.extend(chain);
+        });
+        self.heads[usize::from(head)].store(HEAD_FREE, Ordering::Release);
+
+        Some(len)
+    }
+
+    /// Waits for the device to use the chain of `head`, returning the bytes it wrote.
+    ///
+    /// #### Remark
+    ///
+    /// A chain which times out keeps its descriptors, as the device may yet use it.
+    pub fn wait(&self, head: u16) -> Result<u32> {
+        let mut len = None;
+        poll::poll_until_yield(REQUEST_TIMEOUT_US, || {
+            self.reap();
+            len = self.take(head);
+            len.is_some()
+        })
+        .map_err(|_| Error::Timeout)?;
+
+        Ok(len.unwrap())
+    }
+}
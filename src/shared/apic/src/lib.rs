@@ -253,7 +253,11 @@ impl Apic {
     }
 
     pub fn get_id(&self) -> u32 {
-        self.read_register(Register::ID).get_bits(24..32)
+        match self.0 {
+            Type::xAPIC(_) => self.read_register(Register::ID).get_bits(24..32),
+            // The x2APIC ID register holds the full 32-bit ID.
+            Type::x2APIC => self.read_register(Register::ID),
+        }
     }
 
     #[inline]
@@ -277,8 +281,24 @@ impl Apic {
     /// An invalid or unexpcted interrupt command could potentially put the core in an unusable state.
     #[inline]
     pub unsafe fn send_int_cmd(&self, interrupt_command: InterruptCommand) {
-        self.write_register(Register::ICRL, interrupt_command.get_id());
-        self.write_register(Register::ICRH, interrupt_command.get_cmd());
+        match self.0 {
+            Type::xAPIC(_) => {
+                // The command is sent by the write to the low register, so the destination is written first.
+                self.write_register(Register::ICRH, interrupt_command.get_id() << 24);
+                self.write_register(Register::ICRL, interrupt_command.get_cmd());
+
+                // Wait for the command to be accepted, so the next isn't written over it.
+                while self.read_register(Register::ICRL).get_bit(12) {
+                    core::hint::spin_loop();
+                }
+            }
+
+            // The x2APIC ICR is a single 64-bit register, with the destination in its high half.
+            Type::x2APIC => msr::wrmsr(
+                Register::ICRL.x2apic_msr(),
+                (u64::from(interrupt_command.get_id()) << 32) | u64::from(interrupt_command.get_cmd()),
+            ),
+        }
     }
 
     /// ### Safety
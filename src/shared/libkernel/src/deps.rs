@@ -0,0 +1,173 @@
+//! Deterministic topological sort of dependents, each of which declares what it requires.
+//!
+//! A requirement names either a dependent, or a facility some dependent provides (so a requirement like `clock` is met
+//! by whichever dependents provide it, without naming them). Dependents which are ready at the same time are ordered as
+//! they were declared.
+
+/// Most dependents which can be sorted, as sorting can't allocate (i.e. before the allocator is available).
+pub const MAX_DEPENDENTS: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    TooManyDependents {
+        count: usize,
+    },
+    /// `dependent` requires `requirement`, which no dependent is named or provides.
+    MissingProvider {
+        dependent: &'static str,
+        requirement: &'static str,
+    },
+    /// `dependent` waits on `requirement`, whose providers (directly, or through what they require) wait on it.
+    Cycle {
+        dependent: &'static str,
+        requirement: &'static str,
+    },
+}
+
+pub trait Dependent {
+    fn name(&self) -> &'static str;
+    /// Facilities the dependent provides, besides its name.
+    fn provides(&self) -> &'static [&'static str];
+    /// Dependents (or facilities) which must be ordered first.
+    fn requires(&self) -> &'static [&'static str];
+
+    fn is_provider_of(&self, requirement: &str) -> bool {
+        self.name() == requirement || self.provides().contains(&requirement)
+    }
+}
+
+/// Requirement of `dependents[index]` which isn't yet met, being one with a provider that isn't `done`.
+fn unmet<T: Dependent>(dependents: &[T], done: &[bool], index: usize) -> Option<&'static str> {
+    dependents[index].requires().iter().copied().find(|&requirement| {
+        dependents
+            .iter()
+            .enumerate()
+            .any(|(provider, dependent)| provider != index && !done[provider] && dependent.is_provider_of(requirement))
+    })
+}
+
+/// Sorts `dependents` so each follows everything it requires, returning their indices in sorted order (the first
+/// `dependents.len()` of them).
+pub fn sort<T: Dependent>(dependents: &[T]) -> Result<[usize; MAX_DEPENDENTS], Error> {
+    if dependents.len() > MAX_DEPENDENTS {
+        return Err(Error::TooManyDependents { count: dependents.len() });
+    }
+
+    for (index, dependent) in dependents.iter().enumerate() {
+        for &requirement in dependent.requires() {
+            if !dependents
+                .iter()
+                .enumerate()
+                .any(|(provider, dependent)| provider != index && dependent.is_provider_of(requirement))
+            {
+                return Err(Error::MissingProvider { dependent: dependent.name(), requirement });
+            }
+        }
+    }
+
+    let mut done = [false; MAX_DEPENDENTS];
+    let mut order = [0; MAX_DEPENDENTS];
+    for position in &mut order[..dependents.len()] {
+        // Taking the first ready dependent (rather than any) is what keeps the order deterministic.
+        let Some(next) = (0..dependents.len()).find(|&index| !done[index] && unmet(dependents, &done, index).is_none())
+        else {
+            let stuck = (0..dependents.len()).find(|&index| !done[index]).unwrap();
+
+            return Err(Error::Cycle {
+                dependent: dependents[stuck].name(),
+                requirement: unmet(dependents, &done, stuck).unwrap(),
+            });
+        };
+
+        done[next] = true;
+        *position = next;
+    }
+
+    Ok(order)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sort, Dependent, Error, MAX_DEPENDENTS};
+    use alloc::vec::Vec;
+
+    struct Node(&'static str, &'static [&'static str], &'static [&'static str]);
+
+    impl Dependent for Node {
+        fn name(&self) -> &'static str {
+            self.0
+        }
+
+        fn provides(&self) -> &'static [&'static str] {
+            self.1
+        }
+
+        fn requires(&self) -> &'static [&'static str] {
+            self.2
+        }
+    }
+
+    fn sorted_names(nodes: &[Node]) -> Result<Vec<&'static str>, Error> {
+        sort(nodes).map(|order| order[..nodes.len()].iter().map(|&index| nodes[index].name()).collect())
+    }
+
+    #[test]
+    fn follows_requirements_then_declaration_order() {
+        let nodes = [
+            Node("sched", &[], &["clock", "memory"]),
+            Node("memory", &[], &[]),
+            Node("hpet", &["clock"], &["memory"]),
+            Node("log", &[], &[]),
+            Node("tsc", &["clock"], &[]),
+        ];
+
+        // Every provider of `clock` precedes `sched`, and ready nodes keep their declared order.
+        assert_eq!(sorted_names(&nodes), Ok(["memory", "hpet", "log", "tsc", "sched"].into()));
+    }
+
+    #[test]
+    fn empty() {
+        assert_eq!(sorted_names(&[]), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn missing_provider() {
+        let nodes = [Node("memory", &[], &[]), Node("sched", &[], &["memory", "clock"])];
+
+        assert_eq!(sort(&nodes), Err(Error::MissingProvider { dependent: "sched", requirement: "clock" }));
+    }
+
+    #[test]
+    fn self_provision_is_missing() {
+        // A node can't satisfy its own requirement.
+        let nodes = [Node("tsc", &["clock"], &["clock"])];
+
+        assert_eq!(sort(&nodes), Err(Error::MissingProvider { dependent: "tsc", requirement: "clock" }));
+    }
+
+    #[test]
+    fn cycle() {
+        let nodes = [
+            Node("memory", &[], &[]),
+            Node("a", &[], &["memory", "c"]),
+            Node("b", &[], &["a"]),
+            Node("c", &["clock"], &["b"]),
+        ];
+
+        assert_eq!(sort(&nodes), Err(Error::Cycle { dependent: "a", requirement: "c" }));
+    }
+
+    #[test]
+    fn cycle_through_facility() {
+        let nodes = [Node("hpet", &["clock"], &["timer"]), Node("apic", &["timer"], &["clock"])];
+
+        assert_eq!(sort(&nodes), Err(Error::Cycle { dependent: "hpet", requirement: "timer" }));
+    }
+
+    #[test]
+    fn too_many() {
+        let nodes: Vec<Node> = (0..=MAX_DEPENDENTS).map(|_| Node("node", &[], &[])).collect();
+
+        assert_eq!(sort(&nodes), Err(Error::TooManyDependents { count: MAX_DEPENDENTS + 1 }));
+    }
+}
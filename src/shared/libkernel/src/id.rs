@@ -0,0 +1,104 @@
+use alloc::vec::Vec;
+
+/// Allocator of compact `u32` IDs, which reuses released IDs (most recently released first) before allocating new ones.
+#[derive(Debug, Default)]
+pub struct IdAllocator {
+    next: u32,
+    free: Vec<u32>,
+}
+
+impl IdAllocator {
+    pub const fn new() -> Self {
+        Self { next: 0, free: Vec::new() }
+    }
+
+    /// Allocates an ID, or returns `None` if every ID is in use.
+    pub fn allocate(&mut self) -> Option<u32> {
+        self.free.pop().or_else(|| {
+            let id = self.next;
+            self.next = self.next.checked_add(1)?;
+
+            Some(id)
+        })
+    }
+
+    /// Releases `id` to be reused.
+    ///
+    /// #### Remark
+    ///
+    /// `id` must be live (allocated, and not yet released), otherwise it may be handed out twice.
+    pub fn release(&mut self, id: u32) {
+        debug_assert!(id < self.next && !self.free.contains(&id));
+
+        self.free.push(id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IdAllocator;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn allocates_sequentially() {
+        let mut ids = IdAllocator::new();
+
+        for expected in 0..64 {
+            assert_eq!(ids.allocate(), Some(expected));
+        }
+    }
+
+    #[test]
+    fn reuses_released_ids_before_new_ones() {
+        let mut ids = IdAllocator::new();
+        let allocated = [(); 4].map(|()| ids.allocate().unwrap());
+        assert_eq!(allocated, [0, 1, 2, 3]);
+
+        ids.release(1);
+        ids.release(3);
+
+        // Most recently released first, then fresh IDs once none are free.
+        assert_eq!(ids.allocate(), Some(3));
+        assert_eq!(ids.allocate(), Some(1));
+        assert_eq!(ids.allocate(), Some(4));
+    }
+
+    #[test]
+    fn live_ids_are_unique() {
+        let mut ids = IdAllocator::new();
+        let mut live = Vec::new();
+
+        for round in 0..16u32 {
+            for _ in 0..8 {
+                let id = ids.allocate().unwrap();
+                assert!(!live.contains(&id), "ID {id} allocated twice");
+                live.push(id);
+            }
+
+            // Release an uneven, shifting subset, so both reused and fresh IDs are handed out next round.
+            let mut index = 0;
+            let (kept, released): (Vec<u32>, Vec<u32>) = live.iter().partition(|_| {
+                index += 1;
+                (index + round) % 3 != 0
+            });
+            live = kept;
+            for id in released {
+                ids.release(id);
+            }
+        }
+
+        // Every ID allocated is either live or free.
+        assert_eq!(live.len() + ids.free.len(), ids.next as usize);
+    }
+
+    #[test]
+    fn exhausts_at_max() {
+        let mut ids = IdAllocator { next: u32::MAX - 1, free: Vec::new() };
+
+        assert_eq!(ids.allocate(), Some(u32::MAX - 1));
+        assert_eq!(ids.allocate(), None);
+
+        ids.release(u32::MAX - 1);
+        assert_eq!(ids.allocate(), Some(u32::MAX - 1));
+    }
+}
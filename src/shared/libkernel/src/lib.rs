@@ -1,14 +1,24 @@
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 #![feature(
     extern_types,                   // #43467 <https://github.com/rust-lang/rust/issues/43467>
     exclusive_range_pattern,        // #37854 <https://github.com/rust-lang/rust/issues/37854>
 )]
 
+extern crate alloc;
+
+pub mod deps;
+
+mod id;
+pub use id::*;
+
 pub mod mem;
 
 mod num;
 pub use num::*;
 
+mod wheel;
+pub use wheel::*;
+
 pub struct ReadOnly;
 pub struct WriteOnly;
 pub struct ReadWrite;
@@ -0,0 +1,221 @@
+use core::ops::Range;
+
+const WORD_BITS: usize = usize::BITS as usize;
+
+/// Bitmap with one summary bit per word (set if every bit of the word is set), so clear bits are found by scanning the
+/// summary words, rather than every word.
+///
+/// #### Remark
+///
+/// Bits past `len` (up to the end of their word) are kept set, so they're never found clear.
+pub struct SummaryBitmap<'a> {
+    words: &'a mut [usize],
+    summary: &'a mut [usize],
+    len: usize,
+}
+
+impl<'a> SummaryBitmap<'a> {
+    /// Number of words required to hold `len` bits, including the summary words.
+    pub const fn words_for(len: usize) -> usize {
+        let words = len.div_ceil(WORD_BITS);
+        words + words.div_ceil(WORD_BITS)
+    }
+
+    /// Creates a bitmap of `len` clear bits, from memory of (at least) [`Self::words_for`] words.
+    pub fn new(memory: &'a mut [usize], len: usize) -> Self {
+        let word_count = len.div_ceil(WORD_BITS);
+        let (words, summary) = memory.split_at_mut(word_count);
+        let summary = &mut summary[..word_count.div_ceil(WORD_BITS)];
+
+        words.fill(0);
+        summary.fill(0);
+
+        let mut bitmap = Self { words, summary, len };
+
+        // Set the extant bits, as the length may not be exactly divisible by `usize::BITS`.
+        bitmap.set_range(len..(word_count * WORD_BITS), true);
+        // Likewise, mark the summary bits which don't correspond to a word as full.
+        for word_index in word_count..(bitmap.summary.len() * WORD_BITS) {
+            bitmap.summary[word_index / WORD_BITS] |= 1 << (word_index % WORD_BITS);
+        }
+
+        bitmap
+    }
+
+    #[inline]
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn update_summary(&mut self, word_index: usize) {
+        let summary_bit = 1 << (word_index % WORD_BITS);
+        let summary_word = &mut self.summary[word_index / WORD_BITS];
+
+        if self.words[word_index] == usize::MAX {
+            *summary_word |= summary_bit;
+        } else {
+            *summary_word &= !summary_bit;
+        }
+    }
+
+    /// Splits `range` into its constituent (word index, bit mask) pairs.
+    fn word_masks(range: Range<usize>) -> impl Iterator<Item = (usize, usize)> {
+        let mut index = range.start;
+
+        core::iter::from_fn(move || {
+            (index < range.end).then(|| {
+                let shift = index % WORD_BITS;
+                let bit_count = (WORD_BITS - shift).min(range.end - index);
+                let mask = if bit_count == WORD_BITS { usize::MAX } else { ((1 << bit_count) - 1) << shift };
+                let word_index = index / WORD_BITS;
+                index += bit_count;
+
+                (word_index, mask)
+            })
+        })
+    }
+
+    /// Sets (or clears) the bits of `range`.
+    pub fn set_range(&mut self, range: Range<usize>, set: bool) {
+        for (word_index, mask) in Self::word_masks(range) {
+            if set {
+                self.words[word_index] |= mask;
+            } else {
+                self.words[word_index] &= !mask;
+            }
+
+            self.update_summary(word_index);
+        }
+    }
+
+    /// Returns the index of the first set bit in `range`, if any.
+    pub fn first_set(&self, range: Range<usize>) -> Option<usize> {
+        Self::word_masks(range).find_map(|(word_index, mask)| {
+            let set = self.words[word_index] & mask;
+            (set > 0).then(|| (word_index * WORD_BITS) + (set.trailing_zeros() as usize))
+        })
+    }
+
+    /// Returns the index of the first clear bit at or after `start`, if any.
+    pub fn first_clear(&self, start: usize) -> Option<usize> {
+        if start >= self.len {
+            return None;
+        }
+
+        // Check the remainder of the starting word, ignoring the bits prior to `start`.
+        let word_index = start / WORD_BITS;
+        let word = self.words[word_index] | ((1 << (start % WORD_BITS)) - 1);
+        if word != usize::MAX {
+            return Some((word_index * WORD_BITS) + (word.trailing_ones() as usize));
+        }
+
+        // Otherwise, use the summary to skip to the next word with a clear bit.
+        let next_word_index = word_index + 1;
+        let mut summary_index = next_word_index / WORD_BITS;
+        let mut summary_word = self.summary.get(summary_index)? | ((1 << (next_word_index % WORD_BITS)) - 1);
+        while summary_word == usize::MAX {
+            summary_index += 1;
+            summary_word = *self.summary.get(summary_index)?;
+        }
+
+        let word_index = (summary_index * WORD_BITS) + (summary_word.trailing_ones() as usize);
+        Some((word_index * WORD_BITS) + (self.words[word_index].trailing_ones() as usize))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SummaryBitmap, WORD_BITS};
+
+    /// Bits covered by a single summary word.
+    const SUMMARY_BITS: usize = WORD_BITS * WORD_BITS;
+
+    fn with_bitmap(len: usize, func: impl FnOnce(&mut SummaryBitmap)) {
+        let mut memory = vec![0usize; SummaryBitmap::words_for(len)];
+        func(&mut SummaryBitmap::new(&mut memory, len));
+    }
+
+    /// Takes the first clear bit (as the frame allocator does), returning its index.
+    fn take(bitmap: &mut SummaryBitmap) -> Option<usize> {
+        let index = bitmap.first_clear(0)?;
+        bitmap.set_range(index..(index + 1), true);
+
+        Some(index)
+    }
+
+    #[test]
+    fn takes_every_bit_in_order_then_exhausts() {
+        let len = (2 * SUMMARY_BITS) + 3;
+
+        with_bitmap(len, |bitmap| {
+            for expected in 0..len {
+                assert_eq!(take(bitmap), Some(expected));
+            }
+
+            assert_eq!(take(bitmap), None);
+        });
+    }
+
+    #[test]
+    fn finds_clear_bit_past_full_summary_words() {
+        let len = 3 * SUMMARY_BITS;
+
+        with_bitmap(len, |bitmap| {
+            bitmap.set_range(0..len, true);
+            assert_eq!(bitmap.first_clear(0), None);
+
+            // Just past the first summary word, then at the very end of the last.
+            bitmap.set_range(SUMMARY_BITS..(SUMMARY_BITS + 1), false);
+            assert_eq!(bitmap.first_clear(0), Some(SUMMARY_BITS));
+            assert_eq!(bitmap.first_clear(SUMMARY_BITS + 1), None);
+
+            bitmap.set_range((len - 1)..len, false);
+            assert_eq!(bitmap.first_clear(SUMMARY_BITS + 1), Some(len - 1));
+        });
+    }
+
+    #[test]
+    fn freed_bit_clears_its_summary_bit() {
+        let len = 2 * SUMMARY_BITS;
+
+        with_bitmap(len, |bitmap| {
+            bitmap.set_range(0..len, true);
+            bitmap.set_range((SUMMARY_BITS - 1)..SUMMARY_BITS, false);
+
+            assert_eq!(bitmap.first_clear(0), Some(SUMMARY_BITS - 1));
+            assert_eq!(take(bitmap), Some(SUMMARY_BITS - 1));
+            assert_eq!(take(bitmap), None);
+        });
+    }
+
+    #[test]
+    fn never_finds_bits_past_len() {
+        // A length which ends partway through both a word, and the summary word covering it.
+        let len = SUMMARY_BITS + WORD_BITS + 1;
+
+        with_bitmap(len, |bitmap| {
+            bitmap.set_range(0..(len - 1), true);
+
+            assert_eq!(bitmap.first_clear(0), Some(len - 1));
+            assert_eq!(take(bitmap), Some(len - 1));
+            assert_eq!(bitmap.first_clear(0), None);
+            assert_eq!(bitmap.first_clear(len), None);
+        });
+    }
+
+    #[test]
+    fn finds_first_set_bit_across_words() {
+        with_bitmap(SUMMARY_BITS, |bitmap| {
+            assert_eq!(bitmap.first_set(0..SUMMARY_BITS), None);
+
+            bitmap.set_range((WORD_BITS + 5)..(WORD_BITS + 6), true);
+            assert_eq!(bitmap.first_set(3..(3 * WORD_BITS)), Some(WORD_BITS + 5));
+            assert_eq!(bitmap.first_set(3..(WORD_BITS + 5)), None);
+        });
+    }
+}
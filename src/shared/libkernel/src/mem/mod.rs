@@ -1,3 +1,6 @@
+mod bitmap;
+pub use bitmap::*;
+
 mod volatile;
 pub use volatile::*;
 
@@ -0,0 +1,192 @@
+use alloc::vec::Vec;
+
+const SLOTS: usize = 256;
+
+/// Timer wheel of entries, keyed on the tick at which each expires.
+///
+/// Entries are hashed into [`Self::SLOTS`] slots by their expiry tick, so an entry is filed in constant time, and
+/// expiring the entries of a tick only visits its slot. Entries further out than a rotation of the wheel share slots
+/// with nearer ones, and are skipped over until their rotation comes around.
+pub struct TimerWheel<T> {
+    slots: [Vec<(u64, T)>; SLOTS],
+    /// Latest tick whose entries have been expired.
+    now: u64,
+    /// Number of pending entries.
+    len: usize,
+}
+
+impl<T> TimerWheel<T> {
+    /// Number of slots (and so ticks) in a rotation of the wheel.
+    pub const SLOTS: usize = SLOTS;
+
+    pub const fn new() -> Self {
+        Self { slots: [const { Vec::new() }; SLOTS], now: 0, len: 0 }
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    const fn slot(tick: u64) -> usize {
+        (tick % (Self::SLOTS as u64)) as usize
+    }
+
+    #[inline]
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Files `value` to expire at the tick `expiry`.
+    pub fn insert(&mut self, expiry: u64, value: T) {
+        // An expiry tick which has already been expired is filed under the next tick, so it's never missed.
+        let expiry = expiry.max(self.now + 1);
+        self.slots[Self::slot(expiry)].push((expiry, value));
+        self.len += 1;
+    }
+
+    /// Removes every entry whose expiry tick is at or before `now`, passing each to `expire`.
+    ///
+    /// #### Remark
+    ///
+    /// Entries are passed in order of their expiry tick, though entries sharing a tick are in no particular order.
+    pub fn expire(&mut self, now: u64, mut expire: impl FnMut(T)) {
+        if now <= self.now {
+            return;
+        }
+
+        // Visiting more than a rotation's worth of ticks would only revisit slots.
+        let first = self.now + 1;
+        let ticks = now.saturating_sub(first).saturating_add(1).min(Self::SLOTS as u64);
+
+        for tick in first..(first + ticks) {
+            let slot = &mut self.slots[Self::slot(tick)];
+
+            let mut index = 0;
+            while index < slot.len() {
+                if slot[index].0 <= now {
+                    let (_, value) = slot.swap_remove(index);
+                    self.len -= 1;
+                    expire(value);
+                } else {
+                    index += 1;
+                }
+            }
+        }
+
+        self.now = now;
+    }
+
+    /// Number of ticks from `now` until the earliest expiry, if any entry is pending.
+    pub fn until_next(&self, now: u64) -> Option<u64> {
+        if self.is_empty() {
+            return None;
+        }
+
+        // Within a rotation, the first slot holding an expiry for its own tick is the earliest.
+        let nearest = (1..=(Self::SLOTS as u64))
+            .map(|offset| now + offset)
+            .find(|&tick| self.slots[Self::slot(tick)].iter().any(|&(expiry, _)| expiry == tick));
+
+        nearest
+            .or_else(|| self.slots.iter().flatten().map(|&(expiry, _)| expiry).min())
+            .map(|expiry| expiry.saturating_sub(now))
+    }
+}
+
+impl<T> Default for TimerWheel<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TimerWheel;
+    use alloc::vec::Vec;
+
+    const SLOTS: u64 = TimerWheel::<()>::SLOTS as u64;
+
+    /// Advances virtual time to `now`, returning what expired.
+    fn expire(wheel: &mut TimerWheel<u64>, now: u64) -> Vec<u64> {
+        let mut expired = Vec::new();
+        wheel.expire(now, |value| expired.push(value));
+
+        expired
+    }
+
+    #[test]
+    fn expires_in_tick_order() {
+        let mut wheel = TimerWheel::new();
+        // Each entry's value is its expiry tick.
+        for expiry in [7, 3, 12, 1, 5] {
+            wheel.insert(expiry, expiry);
+        }
+
+        assert_eq!(wheel.until_next(0), Some(1));
+        assert_eq!(expire(&mut wheel, 6), [1, 3, 5]);
+        assert_eq!(wheel.until_next(6), Some(1));
+        assert_eq!(expire(&mut wheel, 12), [7, 12]);
+        assert!(wheel.is_empty());
+        assert_eq!(wheel.until_next(12), None);
+    }
+
+    #[test]
+    fn tick_by_tick_expires_each_entry_on_time() {
+        let mut wheel = TimerWheel::new();
+        for expiry in (1..=(3 * SLOTS)).step_by(7) {
+            wheel.insert(expiry, expiry);
+        }
+
+        for now in 1..=(3 * SLOTS) {
+            for expiry in expire(&mut wheel, now) {
+                assert_eq!(expiry, now);
+            }
+        }
+
+        assert!(wheel.is_empty());
+    }
+
+    #[test]
+    fn far_entries_cascade_until_their_rotation() {
+        let mut wheel = TimerWheel::new();
+        // Both share a slot, a rotation (and then two) further out than the near entry.
+        wheel.insert(10, 10);
+        wheel.insert(10 + SLOTS, 10 + SLOTS);
+        wheel.insert(10 + (2 * SLOTS), 10 + (2 * SLOTS));
+
+        assert_eq!(expire(&mut wheel, 10), [10]);
+        assert_eq!(wheel.until_next(10), Some(SLOTS));
+        assert_eq!(expire(&mut wheel, 9 + SLOTS), []);
+        assert_eq!(expire(&mut wheel, 10 + SLOTS), [10 + SLOTS]);
+
+        // Past a rotation with nothing nearer, the earliest is found by scanning every slot.
+        assert_eq!(wheel.until_next(10 + SLOTS), Some(SLOTS));
+        assert_eq!(wheel.len(), 1);
+    }
+
+    #[test]
+    fn jump_past_a_rotation_expires_everything_due() {
+        let mut wheel = TimerWheel::new();
+        for expiry in [2, 100, 300, 700, 5000] {
+            wheel.insert(expiry, expiry);
+        }
+
+        let mut expired = expire(&mut wheel, 1000);
+        expired.sort_unstable();
+        assert_eq!(expired, [2, 100, 300, 700]);
+        assert_eq!(wheel.until_next(1000), Some(4000));
+    }
+
+    #[test]
+    fn past_expiry_is_filed_under_the_next_tick() {
+        let mut wheel = TimerWheel::new();
+        assert_eq!(expire(&mut wheel, 50), []);
+
+        wheel.insert(20, 20);
+        assert_eq!(wheel.until_next(50), Some(1));
+        assert_eq!(expire(&mut wheel, 50), []);
+        assert_eq!(expire(&mut wheel, 51), [20]);
+    }
+}
@@ -17,6 +17,9 @@ pub use constants::*;
 // pub mod sync;
 pub mod syscall;
 
+#[cfg(all(test, loom))]
+mod protocols;
+
 #[macro_use]
 extern crate static_assertions;
 extern crate alloc;
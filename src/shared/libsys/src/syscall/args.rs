@@ -0,0 +1,61 @@
+//! Typed system call arguments, shared by userspace and the kernel so both sides agree on register layout.
+
+use super::raw::MAX_ARGS;
+
+/// Arguments for the `Klog*` system calls: a UTF-8 string in the caller's memory.
+pub type KlogArgs = BufferArgs;
+
+/// Arguments of a system call, convertible to and from argument registers.
+pub trait Arguments: Sized {
+    fn into_args(self) -> [usize; MAX_ARGS];
+    fn from_args(args: [usize; MAX_ARGS]) -> Self;
+}
+
+/// Arguments for system calls which take none.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NoArgs;
+
+impl Arguments for NoArgs {
+    fn into_args(self) -> [usize; MAX_ARGS] {
+        [0; MAX_ARGS]
+    }
+
+    fn from_args(_: [usize; MAX_ARGS]) -> Self {
+        Self
+    }
+}
+
+/// Arguments for system calls which take a single buffer (or string) in the caller's memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BufferArgs {
+    pub ptr: usize,
+    pub len: usize,
+}
+
+impl From<&str> for BufferArgs {
+    fn from(str: &str) -> Self {
+        Self { ptr: str.as_ptr().addr(), len: str.len() }
+    }
+}
+
+impl From<&[u8]> for BufferArgs {
+    fn from(buf: &[u8]) -> Self {
+        Self { ptr: buf.as_ptr().addr(), len: buf.len() }
+    }
+}
+
+impl From<&mut [u8]> for BufferArgs {
+    fn from(buf: &mut [u8]) -> Self {
+        Self { ptr: buf.as_mut_ptr().addr(), len: buf.len() }
+    }
+}
+
+impl Arguments for BufferArgs {
+    fn into_args(self) -> [usize; MAX_ARGS] {
+        super::raw::pad_args(&[self.ptr, self.len])
+    }
+
+    fn from_args(args: [usize; MAX_ARGS]) -> Self {
+        Self { ptr: args[0], len: args[1] }
+    }
+}
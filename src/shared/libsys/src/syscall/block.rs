@@ -0,0 +1,53 @@
+use super::{Arguments, BufferArgs, Error, Result, Vector};
+use crate::syscall::raw::{pad_args, MAX_ARGS};
+
+/// Request counters of a block device, as returned by [`stats`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Stats {
+    pub reads: u64,
+    pub writes: u64,
+    pub flushes: u64,
+    /// Requests which failed.
+    pub errors: u64,
+    /// Mean latency of completed requests, in microseconds.
+    pub average_latency_us: u64,
+}
+
+/// Arguments for [`Vector::BlockStats`]: a device name, and a [`Stats`] to write into, both in the caller's memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StatsArgs {
+    pub name: BufferArgs,
+    pub stats_ptr: usize,
+}
+
+impl Arguments for StatsArgs {
+    fn into_args(self) -> [usize; MAX_ARGS] {
+        pad_args(&[self.name.ptr, self.name.len, self.stats_ptr])
+    }
+
+    fn from_args(args: [usize; MAX_ARGS]) -> Self {
+        Self { name: BufferArgs { ptr: args[0], len: args[1] }, stats_ptr: args[2] }
+    }
+}
+
+/// Returns the request counters of the block device `name`.
+pub fn stats(name: &str) -> core::result::Result<Stats, Error> {
+    let mut stats = Stats::default();
+
+    // Safety: String is valid for reads of its length, and `stats` is valid for a write of `Stats`.
+    unsafe {
+        super::invoke(
+            Vector::BlockStats,
+            StatsArgs { name: BufferArgs::from(name), stats_ptr: core::ptr::addr_of_mut!(stats).addr() },
+        )?;
+    }
+
+    Ok(stats)
+}
+
+/// Writes back any data cached by the block device `name`.
+pub fn flush(name: &str) -> Result {
+    // Safety: String is valid for reads of its length.
+    unsafe { super::invoke(Vector::BlockFlush, BufferArgs::from(name)) }
+}
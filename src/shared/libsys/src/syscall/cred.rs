@@ -0,0 +1,103 @@
+//! Credentials of a process: the identity it accesses files as, and the capabilities it holds for privileged calls.
+//!
+//! The first process holds every capability, as root. Capabilities (and identity) are only ever given up, i.e. for
+//! `init` to drop them before starting ordinary programs; a process can't regain what it has dropped.
+
+use super::{Arguments, Error, Result, Vector};
+use crate::syscall::raw::{pad_args, MAX_ARGS};
+
+bitflags::bitflags! {
+    /// Privileged operations a process may perform.
+    #[repr(transparent)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Capabilities: u32 {
+        /// Mapping device memory (see [`super::fs::map`]) and boot modules (see [`super::mem::map_module`]).
+        const RAW_DEVICE = 1 << 0;
+        /// Mounting and unmounting filesystems.
+        const MOUNT = 1 << 1;
+        /// Opening power devices (i.e. `/dev/power`), to sleep or restart the system.
+        const POWER = 1 << 2;
+        /// Changing the user and group the process accesses files as.
+        const SET_IDENTITY = 1 << 3;
+        /// Changing the scheduling policy, I/O class, and resource group of tasks run by other users.
+        const TASK_CONTROL = 1 << 4;
+    }
+}
+
+/// Credentials of a process, as written by [`Vector::CredGet`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Credentials {
+    pub uid: u32,
+    pub gid: u32,
+    pub capabilities: Capabilities,
+}
+
+impl Credentials {
+    /// Credentials of the first process.
+    pub const ROOT: Self = Self { uid: 0, gid: 0, capabilities: Capabilities::all() };
+
+    /// Indicates whether the credentials may be changed to `new`: capabilities can only be dropped, and the identity
+    /// only changed while holding [`Capabilities::SET_IDENTITY`].
+    pub fn permits(&self, new: &Self) -> bool {
+        self.capabilities.contains(new.capabilities)
+            && (self.capabilities.contains(Capabilities::SET_IDENTITY) || (new.uid == self.uid && new.gid == self.gid))
+    }
+}
+
+/// Arguments for [`Vector::CredGet`]: [`Credentials`] to write into, in the caller's memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GetArgs {
+    pub credentials_ptr: usize,
+}
+
+impl Arguments for GetArgs {
+    fn into_args(self) -> [usize; MAX_ARGS] {
+        pad_args(&[self.credentials_ptr])
+    }
+
+    fn from_args(args: [usize; MAX_ARGS]) -> Self {
+        Self { credentials_ptr: args[0] }
+    }
+}
+
+/// Arguments for [`Vector::CredSet`]: the credentials to take on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SetArgs {
+    pub uid: u32,
+    pub gid: u32,
+    pub capabilities: u32,
+}
+
+impl Arguments for SetArgs {
+    fn into_args(self) -> [usize; MAX_ARGS] {
+        pad_args(&[self.uid as usize, self.gid as usize, self.capabilities as usize])
+    }
+
+    fn from_args(args: [usize; MAX_ARGS]) -> Self {
+        #[allow(clippy::cast_possible_truncation)]
+        Self { uid: args[0] as u32, gid: args[1] as u32, capabilities: args[2] as u32 }
+    }
+}
+
+/// Gets the credentials of the calling process.
+pub fn get() -> core::result::Result<Credentials, Error> {
+    let mut credentials = core::mem::MaybeUninit::<Credentials>::uninit();
+
+    // Safety: `credentials` is valid for a write of `Credentials`.
+    unsafe {
+        super::invoke(Vector::CredGet, GetArgs { credentials_ptr: credentials.as_mut_ptr().addr() })?;
+    }
+
+    // Safety: Kernel has written the `Credentials` on success.
+    Ok(unsafe { credentials.assume_init() })
+}
+
+/// Sets the credentials of the calling process (and so of each of its threads), failing with
+/// [`Error::PermissionDenied`] unless the current credentials [permit](Credentials::permits) it.
+pub fn set(credentials: Credentials) -> Result {
+    let args = SetArgs { uid: credentials.uid, gid: credentials.gid, capabilities: credentials.capabilities.bits() };
+
+    // Safety: System call takes no pointers.
+    unsafe { super::invoke(Vector::CredSet, args) }
+}
@@ -0,0 +1,130 @@
+//! Object loading for a userspace dynamic loader (`ld.so`), which needs no privileges of its own.
+//!
+//! The loader reads an object's [`ProgramHeader`]s, maps each `PT_LOAD` segment at its chosen base with
+//! [`map_segment`], then applies relocations with [`relocate`]. Segments take the protection of their flags from the
+//! start, so relocations (even of read-only data) are written by the kernel, which only writes within mapped
+//! segments.
+//!
+//! When the kernel runs an executable which names the loader as its interpreter (`PT_INTERP`), the loader is entered
+//! in its place, with the executable open as a descriptor in its first argument register.
+
+use super::{Arguments, Error, Result, Success, Vector};
+use crate::syscall::raw::{pad_args, MAX_ARGS};
+
+/// A program header of an object, as written by [`Vector::DlProgramHeaders`] (laid out as an `Elf64_Phdr`).
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ProgramHeader {
+    pub p_type: u32,
+    pub p_flags: u32,
+    pub p_offset: u64,
+    pub p_vaddr: u64,
+    pub p_paddr: u64,
+    pub p_filesz: u64,
+    pub p_memsz: u64,
+    pub p_align: u64,
+}
+
+/// A relocation for [`Vector::DlRelocate`]: `value` is written to `address`, which must lie within a segment mapped
+/// by [`map_segment`]. Executable segments aren't relocated (failing with [`Error::WxViolation`]), as text relocations
+/// would let the caller rewrite its code.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Relocation {
+    pub address: u64,
+    pub value: u64,
+}
+
+/// Arguments for [`Vector::DlProgramHeaders`]: a descriptor of an object, and a buffer of `count`
+/// [`ProgramHeader`]s to write into, in the caller's memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeadersArgs {
+    pub fd: usize,
+    pub headers_ptr: usize,
+    pub count: usize,
+}
+
+impl Arguments for HeadersArgs {
+    fn into_args(self) -> [usize; MAX_ARGS] {
+        pad_args(&[self.fd, self.headers_ptr, self.count])
+    }
+
+    fn from_args(args: [usize; MAX_ARGS]) -> Self {
+        Self { fd: args[0], headers_ptr: args[1], count: args[2] }
+    }
+}
+
+/// Arguments for [`Vector::DlMapSegment`]: a descriptor of an object, the index of one of its `PT_LOAD` program
+/// headers, and the base the segment's address is relative to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SegmentArgs {
+    pub fd: usize,
+    pub index: usize,
+    pub base: usize,
+}
+
+impl Arguments for SegmentArgs {
+    fn into_args(self) -> [usize; MAX_ARGS] {
+        pad_args(&[self.fd, self.index, self.base])
+    }
+
+    fn from_args(args: [usize; MAX_ARGS]) -> Self {
+        Self { fd: args[0], index: args[1], base: args[2] }
+    }
+}
+
+/// Arguments for [`Vector::DlRelocate`]: `count` [`Relocation`]s, in the caller's memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RelocateArgs {
+    pub relocations_ptr: usize,
+    pub count: usize,
+}
+
+impl Arguments for RelocateArgs {
+    fn into_args(self) -> [usize; MAX_ARGS] {
+        pad_args(&[self.relocations_ptr, self.count])
+    }
+
+    fn from_args(args: [usize; MAX_ARGS]) -> Self {
+        Self { relocations_ptr: args[0], count: args[1] }
+    }
+}
+
+/// Writes as many of the program headers of the object open as `fd` as fit into `headers`, returning the total
+/// number of program headers (which may exceed the length of `headers`).
+pub fn program_headers(fd: usize, headers: &mut [ProgramHeader]) -> core::result::Result<usize, Error> {
+    let args = HeadersArgs { fd, headers_ptr: headers.as_mut_ptr().addr(), count: headers.len() };
+
+    // Safety: `headers` is valid for writes of its length.
+    match unsafe { super::invoke(Vector::DlProgramHeaders, args) }? {
+        Success::Value(count) => Ok(count),
+        _ => Err(Error::InvalidResult),
+    }
+}
+
+/// Maps the `PT_LOAD` segment `index` of the object open as `fd` at `base + p_vaddr`, with the protection of its
+/// flags, returning the segment's address.
+///
+/// #### Remark
+///
+/// The segment's pages must be free, except for a first page shared with a segment mapped before it.
+pub fn map_segment(fd: usize, index: usize, base: usize) -> core::result::Result<core::ptr::NonNull<u8>, Error> {
+    // Safety: System call takes no pointers.
+    match unsafe { super::invoke(Vector::DlMapSegment, SegmentArgs { fd, index, base }) }? {
+        Success::NonNullPtr(ptr) => Ok(ptr.cast()),
+        _ => Err(Error::InvalidResult),
+    }
+}
+
+/// Applies `relocations` to mapped segments, in order.
+///
+/// ### Safety
+///
+/// No reference may exist to the relocated memory, as the kernel writes it.
+pub unsafe fn relocate(relocations: &[Relocation]) -> Result {
+    let args = RelocateArgs { relocations_ptr: relocations.as_ptr().addr(), count: relocations.len() };
+
+    // Safety: `relocations` is valid for reads of its length, and the caller ensures the relocated memory may be
+    //         written.
+    unsafe { super::invoke(Vector::DlRelocate, args) }
+}
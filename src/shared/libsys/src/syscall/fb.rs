@@ -0,0 +1,62 @@
+//! Requests understood by framebuffer devices (i.e. `/dev/fb0`), issued via [`super::fs::control`].
+//!
+//! Drawing is done into the device's shadow buffer, mapped with [`super::fs::map`], and made visible by
+//! flushing the damaged region.
+
+use super::{fs, Error};
+
+/// Writes the device's [`ModeInfo`].
+pub const GET_MODE_INFO: u32 = 0x4600;
+/// Copies the [`Damage`] region of the shadow buffer to the display.
+pub const FLUSH: u32 = 0x4601;
+
+/// Layout of the framebuffer's pixels.
+///
+/// The shadow buffer is `pitch * height` bytes, and each pixel is `bpp` bits, whose color channels are
+/// 8 bits at the given shifts.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ModeInfo {
+    pub width: u32,
+    pub height: u32,
+    /// Length of a scanline, in bytes.
+    pub pitch: u32,
+    pub bpp: u16,
+    pub red_shift: u8,
+    pub green_shift: u8,
+    pub blue_shift: u8,
+    pub reserved: [u8; 3],
+}
+
+/// A region of the framebuffer, in pixels.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Damage {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Returns the mode information of the framebuffer open as `fd`.
+pub fn mode_info(fd: usize) -> Result<ModeInfo, Error> {
+    let mut info = ModeInfo::default();
+
+    // Safety: `ModeInfo` is plain-old-data, so any bytes the kernel writes are a valid value.
+    let buf = unsafe {
+        core::slice::from_raw_parts_mut(core::ptr::addr_of_mut!(info).cast::<u8>(), core::mem::size_of::<ModeInfo>())
+    };
+    fs::control(fd, GET_MODE_INFO, buf)?;
+
+    Ok(info)
+}
+
+/// Copies `damage` from the shadow buffer of the framebuffer open as `fd` to the display.
+pub fn flush(fd: usize, mut damage: Damage) -> Result<(), Error> {
+    // Safety: `Damage` is plain-old-data.
+    let buf = unsafe {
+        core::slice::from_raw_parts_mut(core::ptr::addr_of_mut!(damage).cast::<u8>(), core::mem::size_of::<Damage>())
+    };
+
+    fs::control(fd, FLUSH, buf).map(|_| ())
+}
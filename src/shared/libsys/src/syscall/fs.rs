@@ -0,0 +1,364 @@
+use super::{Arguments, BufferArgs, Error, Result, Success, Vector};
+use crate::syscall::raw::{pad_args, MAX_ARGS};
+use num_enum::TryFromPrimitive;
+
+bitflags::bitflags! {
+    /// Permission bits of a file, in the conventional owner/group/other layout.
+    #[repr(transparent)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Mode: u32 {
+        const OWNER_READ = 0o400;
+        const OWNER_WRITE = 0o200;
+        const OWNER_EXEC = 0o100;
+        const GROUP_READ = 0o040;
+        const GROUP_WRITE = 0o020;
+        const GROUP_EXEC = 0o010;
+        const OTHER_READ = 0o004;
+        const OTHER_WRITE = 0o002;
+        const OTHER_EXEC = 0o001;
+    }
+}
+
+bitflags::bitflags! {
+    #[repr(transparent)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct OpenFlags: u32 {
+        const READ = 1 << 0;
+        const WRITE = 1 << 1;
+    }
+}
+
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromPrimitive)]
+pub enum FileKind {
+    File = 0,
+    Directory = 1,
+    /// A device node, which may support [`control`] requests and [`map`].
+    Device = 2,
+}
+
+/// Metadata of a file, as returned by [`stat`].
+///
+/// Timestamps are in milliseconds since boot.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stat {
+    /// [`FileKind`] of the file.
+    pub kind: u32,
+    /// [`Mode`] bits of the file.
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub size: u64,
+    pub accessed: u64,
+    pub modified: u64,
+    pub created: u64,
+}
+
+/// Arguments for [`Vector::FsOpen`]: a path in the caller's memory, and [`OpenFlags`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpenArgs {
+    pub path: BufferArgs,
+    pub flags: u32,
+}
+
+impl Arguments for OpenArgs {
+    fn into_args(self) -> [usize; MAX_ARGS] {
+        pad_args(&[self.path.ptr, self.path.len, self.flags as usize])
+    }
+
+    fn from_args(args: [usize; MAX_ARGS]) -> Self {
+        #[allow(clippy::cast_possible_truncation)]
+        Self { path: BufferArgs { ptr: args[0], len: args[1] }, flags: args[2] as u32 }
+    }
+}
+
+/// Arguments for [`Vector::FsRead`] and [`Vector::FsWrite`]: a descriptor, and a buffer in the caller's memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IoArgs {
+    pub fd: usize,
+    pub buf: BufferArgs,
+}
+
+impl Arguments for IoArgs {
+    fn into_args(self) -> [usize; MAX_ARGS] {
+        pad_args(&[self.fd, self.buf.ptr, self.buf.len])
+    }
+
+    fn from_args(args: [usize; MAX_ARGS]) -> Self {
+        Self { fd: args[0], buf: BufferArgs { ptr: args[1], len: args[2] } }
+    }
+}
+
+/// Arguments for [`Vector::FsClose`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FdArgs {
+    pub fd: usize,
+}
+
+impl Arguments for FdArgs {
+    fn into_args(self) -> [usize; MAX_ARGS] {
+        pad_args(&[self.fd])
+    }
+
+    fn from_args(args: [usize; MAX_ARGS]) -> Self {
+        Self { fd: args[0] }
+    }
+}
+
+/// Arguments for [`Vector::FsStat`]: a path, and a [`Stat`] to write into, both in the caller's memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StatArgs {
+    pub path: BufferArgs,
+    pub stat_ptr: usize,
+}
+
+impl Arguments for StatArgs {
+    fn into_args(self) -> [usize; MAX_ARGS] {
+        pad_args(&[self.path.ptr, self.path.len, self.stat_ptr])
+    }
+
+    fn from_args(args: [usize; MAX_ARGS]) -> Self {
+        Self { path: BufferArgs { ptr: args[0], len: args[1] }, stat_ptr: args[2] }
+    }
+}
+
+/// Length of the header preceding each packed directory entry.
+///
+/// The header is little-endian: the record length (`u16`, including the header and padding), the name
+/// length (`u16`), and the [`FileKind`] (`u32`). The name's UTF-8 bytes follow, padded to [`DIR_ENTRY_ALIGN`].
+pub const DIR_ENTRY_HEADER_LEN: usize = 8;
+pub const DIR_ENTRY_ALIGN: usize = 8;
+
+/// Packs a directory entry into the start of `buf`, returning the length of the record, or `None` if it doesn't fit.
+pub fn pack_dir_entry(buf: &mut [u8], kind: FileKind, name: &str) -> Option<usize> {
+    let name_len = u16::try_from(name.len()).ok()?;
+    let record_len = (DIR_ENTRY_HEADER_LEN + name.len()).next_multiple_of(DIR_ENTRY_ALIGN);
+    let record = buf.get_mut(..record_len)?;
+
+    record[0..2].copy_from_slice(&u16::try_from(record_len).ok()?.to_le_bytes());
+    record[2..4].copy_from_slice(&name_len.to_le_bytes());
+    record[4..8].copy_from_slice(&(kind as u32).to_le_bytes());
+    record[DIR_ENTRY_HEADER_LEN..(DIR_ENTRY_HEADER_LEN + name.len())].copy_from_slice(name.as_bytes());
+    record[(DIR_ENTRY_HEADER_LEN + name.len())..].fill(0);
+
+    Some(record_len)
+}
+
+/// Iterator over the packed directory entries written by [`read_dir`].
+pub struct DirEntries<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> DirEntries<'a> {
+    pub const fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+}
+
+impl<'a> Iterator for DirEntries<'a> {
+    type Item = (FileKind, &'a str);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let header = self.buf.get(..DIR_ENTRY_HEADER_LEN)?;
+        let record_len = usize::from(u16::from_le_bytes([header[0], header[1]]));
+        let name_len = usize::from(u16::from_le_bytes([header[2], header[3]]));
+        let kind = FileKind::try_from(u32::from_le_bytes([header[4], header[5], header[6], header[7]])).ok()?;
+
+        let name = self.buf.get(DIR_ENTRY_HEADER_LEN..(DIR_ENTRY_HEADER_LEN + name_len))?;
+        let name = core::str::from_utf8(name).ok()?;
+        self.buf = self.buf.get(record_len.max(DIR_ENTRY_HEADER_LEN)..).unwrap_or(&[]);
+
+        Some((kind, name))
+    }
+}
+
+/// Arguments for [`Vector::FsCreate`]: a path in the caller's memory, a [`FileKind`], and [`Mode`] bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CreateArgs {
+    pub path: BufferArgs,
+    pub kind: u32,
+    pub mode: u32,
+}
+
+impl Arguments for CreateArgs {
+    fn into_args(self) -> [usize; MAX_ARGS] {
+        pad_args(&[self.path.ptr, self.path.len, self.kind as usize, self.mode as usize])
+    }
+
+    fn from_args(args: [usize; MAX_ARGS]) -> Self {
+        #[allow(clippy::cast_possible_truncation)]
+        Self { path: BufferArgs { ptr: args[0], len: args[1] }, kind: args[2] as u32, mode: args[3] as u32 }
+    }
+}
+
+/// Arguments for [`Vector::FsRename`] and [`Vector::FsLink`]: two paths in the caller's memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PathPairArgs {
+    pub from: BufferArgs,
+    pub to: BufferArgs,
+}
+
+impl Arguments for PathPairArgs {
+    fn into_args(self) -> [usize; MAX_ARGS] {
+        pad_args(&[self.from.ptr, self.from.len, self.to.ptr, self.to.len])
+    }
+
+    fn from_args(args: [usize; MAX_ARGS]) -> Self {
+        Self { from: BufferArgs { ptr: args[0], len: args[1] }, to: BufferArgs { ptr: args[2], len: args[3] } }
+    }
+}
+
+/// Arguments for [`Vector::FsControl`]: a descriptor, a device-specific request, and its argument buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ControlArgs {
+    pub fd: usize,
+    pub request: u32,
+    pub arg: BufferArgs,
+}
+
+impl Arguments for ControlArgs {
+    fn into_args(self) -> [usize; MAX_ARGS] {
+        pad_args(&[self.fd, self.request as usize, self.arg.ptr, self.arg.len])
+    }
+
+    fn from_args(args: [usize; MAX_ARGS]) -> Self {
+        #[allow(clippy::cast_possible_truncation)]
+        Self { fd: args[0], request: args[1] as u32, arg: BufferArgs { ptr: args[2], len: args[3] } }
+    }
+}
+
+/// Arguments for [`Vector::FsMap`]: a descriptor, and the length of the file to map from its start.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MapArgs {
+    pub fd: usize,
+    pub len: usize,
+}
+
+impl Arguments for MapArgs {
+    fn into_args(self) -> [usize; MAX_ARGS] {
+        pad_args(&[self.fd, self.len])
+    }
+
+    fn from_args(args: [usize; MAX_ARGS]) -> Self {
+        Self { fd: args[0], len: args[1] }
+    }
+}
+
+fn into_value(result: Result) -> core::result::Result<usize, Error> {
+    match result? {
+        Success::Value(value) => Ok(value),
+        _ => Err(Error::InvalidResult),
+    }
+}
+
+/// Changes the working directory of the current task to `path`.
+pub fn chdir(path: &str) -> Result {
+    // Safety: String is valid for reads of its length.
+    unsafe { super::invoke(Vector::FsChdir, BufferArgs::from(path)) }
+}
+
+/// Writes the working directory of the current task into `buf`, returning it as a string.
+pub fn getcwd(buf: &mut [u8]) -> core::result::Result<&str, Error> {
+    // Safety: Buffer is valid for writes of its length.
+    let len = into_value(unsafe { super::invoke(Vector::FsGetcwd, BufferArgs::from(&mut *buf)) })?;
+
+    core::str::from_utf8(buf.get(..len).ok_or(Error::InvalidResult)?).map_err(Error::from)
+}
+
+/// Opens the file at `path`, returning its descriptor.
+pub fn open(path: &str, flags: OpenFlags) -> core::result::Result<usize, Error> {
+    // Safety: String is valid for reads of its length.
+    into_value(unsafe { super::invoke(Vector::FsOpen, OpenArgs { path: BufferArgs::from(path), flags: flags.bits() }) })
+}
+
+pub fn close(fd: usize) -> Result {
+    // Safety: System call takes no pointers.
+    unsafe { super::invoke(Vector::FsClose, FdArgs { fd }) }
+}
+
+/// Reads from the descriptor `fd` into `buf`, returning the number of bytes read.
+pub fn read(fd: usize, buf: &mut [u8]) -> core::result::Result<usize, Error> {
+    // Safety: Buffer is valid for writes of its length.
+    into_value(unsafe { super::invoke(Vector::FsRead, IoArgs { fd, buf: BufferArgs::from(buf) }) })
+}
+
+/// Writes `buf` to the descriptor `fd`, returning the number of bytes written.
+pub fn write(fd: usize, buf: &[u8]) -> core::result::Result<usize, Error> {
+    // Safety: Buffer is valid for reads of its length.
+    into_value(unsafe { super::invoke(Vector::FsWrite, IoArgs { fd, buf: BufferArgs::from(buf) }) })
+}
+
+/// Returns the metadata of the file at `path`.
+pub fn stat(path: &str) -> core::result::Result<Stat, Error> {
+    let mut stat = core::mem::MaybeUninit::<Stat>::uninit();
+
+    // Safety: String is valid for reads of its length, and `stat` is valid for a write of `Stat`.
+    unsafe {
+        super::invoke(Vector::FsStat, StatArgs { path: BufferArgs::from(path), stat_ptr: stat.as_mut_ptr().addr() })?;
+    }
+
+    // Safety: Kernel has written the `Stat` on success.
+    Ok(unsafe { stat.assume_init() })
+}
+
+/// Reads the next entries of the directory open as `fd` into `buf`, returning the number of bytes written
+/// (which can be enumerated with [`DirEntries`]), or `0` once the directory has been fully read.
+///
+/// #### Remark
+///
+/// The descriptor keeps a cursor, so each call continues from the last entry returned.
+pub fn read_dir(fd: usize, buf: &mut [u8]) -> core::result::Result<usize, Error> {
+    // Safety: Buffer is valid for writes of its length.
+    into_value(unsafe { super::invoke(Vector::FsReadDir, IoArgs { fd, buf: BufferArgs::from(buf) }) })
+}
+
+/// Creates an empty file or directory at `path`.
+pub fn create(path: &str, kind: FileKind, mode: Mode) -> Result {
+    // Safety: String is valid for reads of its length.
+    unsafe {
+        super::invoke(
+            Vector::FsCreate,
+            CreateArgs { path: BufferArgs::from(path), kind: kind as u32, mode: mode.bits() },
+        )
+    }
+}
+
+/// Removes the file or empty directory at `path`.
+pub fn unlink(path: &str) -> Result {
+    // Safety: String is valid for reads of its length.
+    unsafe { super::invoke(Vector::FsUnlink, BufferArgs::from(path)) }
+}
+
+/// Moves the file or directory at `from` to `to`, replacing a file or empty directory at `to`.
+pub fn rename(from: &str, to: &str) -> Result {
+    // Safety: Strings are valid for reads of their lengths.
+    unsafe { super::invoke(Vector::FsRename, PathPairArgs { from: BufferArgs::from(from), to: BufferArgs::from(to) }) }
+}
+
+/// Links the existing file at `existing` under the new path `new`.
+pub fn link(existing: &str, new: &str) -> Result {
+    // Safety: Strings are valid for reads of their lengths.
+    unsafe {
+        super::invoke(Vector::FsLink, PathPairArgs { from: BufferArgs::from(existing), to: BufferArgs::from(new) })
+    }
+}
+
+/// Issues the device-specific `request` to the device open as `fd`, returning the number of bytes of `arg` written.
+pub fn control(fd: usize, request: u32, arg: &mut [u8]) -> core::result::Result<usize, Error> {
+    // Safety: Buffer is valid for reads and writes of its length.
+    into_value(unsafe { super::invoke(Vector::FsControl, ControlArgs { fd, request, arg: BufferArgs::from(arg) }) })
+}
+
+/// Maps the first `len` bytes of the device open as `fd` into the current task, returning the mapping's address.
+///
+/// #### Remark
+///
+/// The mapping is writable only if the descriptor was opened with [`OpenFlags::WRITE`].
+pub fn map(fd: usize, len: usize) -> core::result::Result<core::ptr::NonNull<u8>, Error> {
+    // Safety: System call takes no pointers.
+    match unsafe { super::invoke(Vector::FsMap, MapArgs { fd, len }) }? {
+        Success::NonNullPtr(ptr) => Ok(ptr.cast()),
+        _ => Err(Error::InvalidResult),
+    }
+}
@@ -0,0 +1,67 @@
+//! Futexes: words of memory a task may block on until another task wakes it, i.e. to build mutexes and condition
+//! variables which sleep rather than spin while contended.
+//!
+//! A futex in private memory is identified by the address of the word within the caller's address space, so the threads
+//! of a process share it whichever of them waits or wakes. A futex in shared memory (i.e. a mapped file or device) is
+//! identified by the word's physical address, so processes which map it share it, wherever each maps it.
+
+use super::{Arguments, Error, Result, Success, Vector};
+use crate::syscall::raw::{pad_args, MAX_ARGS};
+use core::sync::atomic::AtomicU32;
+
+/// Arguments for [`Vector::FutexWait`]: the address of the futex word, and the value it's expected to hold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WaitArgs {
+    pub addr: usize,
+    pub expected: u32,
+}
+
+impl Arguments for WaitArgs {
+    fn into_args(self) -> [usize; MAX_ARGS] {
+        pad_args(&[self.addr, self.expected as usize])
+    }
+
+    fn from_args(args: [usize; MAX_ARGS]) -> Self {
+        #[allow(clippy::cast_possible_truncation)]
+        Self { addr: args[0], expected: args[1] as u32 }
+    }
+}
+
+/// Arguments for [`Vector::FutexWake`]: the address of the futex word, and the most waiters to wake.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WakeArgs {
+    pub addr: usize,
+    pub count: usize,
+}
+
+impl Arguments for WakeArgs {
+    fn into_args(self) -> [usize; MAX_ARGS] {
+        pad_args(&[self.addr, self.count])
+    }
+
+    fn from_args(args: [usize; MAX_ARGS]) -> Self {
+        Self { addr: args[0], count: args[1] }
+    }
+}
+
+/// Blocks the calling task on `word` until it's woken by [`wake`], if the word still holds `expected`; otherwise,
+/// fails with [`Error::WouldBlock`].
+///
+/// #### Remark
+///
+/// The word is compared as the task is queued, so a wake issued after the word changes is never missed. Having been
+/// woken, the caller should check the word again, as another task may have taken whatever it was waiting for.
+pub fn wait(word: &AtomicU32, expected: u32) -> Result {
+    // Safety: The word is valid for reads for the duration of the call.
+    unsafe { super::invoke(Vector::FutexWait, WaitArgs { addr: word.as_ptr().addr(), expected }) }
+}
+
+/// Wakes at most `count` of the tasks blocked on `word`, in the order they began waiting, returning how many were
+/// woken.
+pub fn wake(word: &AtomicU32, count: usize) -> core::result::Result<usize, Error> {
+    // Safety: The word is only used to identify the futex.
+    match unsafe { super::invoke(Vector::FutexWake, WakeArgs { addr: word.as_ptr().addr(), count }) }? {
+        Success::Value(woken) => Ok(woken),
+        _ => Err(Error::InvalidResult),
+    }
+}
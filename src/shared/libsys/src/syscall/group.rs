@@ -0,0 +1,159 @@
+//! Resource groups: flat sets of tasks which share the CPU in proportion to their groups' shares, and whose CPU time is
+//! accounted together (i.e. to keep background services from starving interactive tasks).
+
+use super::{Arguments, Error, Result, Success, Vector};
+use crate::syscall::raw::{pad_args, MAX_ARGS};
+
+/// Group every task starts in, which can't be destroyed.
+pub const ROOT: u32 = 0;
+
+/// Shares of the root group, and of any group created without a reason to weigh it otherwise.
+pub const DEFAULT_SHARES: u32 = 1024;
+pub const MIN_SHARES: u32 = 2;
+pub const MAX_SHARES: u32 = 262_144;
+
+/// A snapshot of a group, as written by [`Vector::GroupInfo`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Info {
+    pub group: u32,
+    pub shares: u32,
+    /// Live tasks in the group.
+    pub tasks: u64,
+    /// CPU time the group's tasks have consumed, in milliseconds.
+    pub cpu_time_ms: u64,
+}
+
+/// Arguments for [`Vector::GroupCreate`]: the shares of the new group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CreateArgs {
+    pub shares: u32,
+}
+
+impl Arguments for CreateArgs {
+    fn into_args(self) -> [usize; MAX_ARGS] {
+        pad_args(&[self.shares as usize])
+    }
+
+    fn from_args(args: [usize; MAX_ARGS]) -> Self {
+        #[allow(clippy::cast_possible_truncation)]
+        Self { shares: args[0] as u32 }
+    }
+}
+
+/// Arguments for [`Vector::GroupDestroy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GroupArgs {
+    pub group: u32,
+}
+
+impl Arguments for GroupArgs {
+    fn into_args(self) -> [usize; MAX_ARGS] {
+        pad_args(&[self.group as usize])
+    }
+
+    fn from_args(args: [usize; MAX_ARGS]) -> Self {
+        #[allow(clippy::cast_possible_truncation)]
+        Self { group: args[0] as u32 }
+    }
+}
+
+/// Arguments for [`Vector::GroupSetShares`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SharesArgs {
+    pub group: u32,
+    pub shares: u32,
+}
+
+impl Arguments for SharesArgs {
+    fn into_args(self) -> [usize; MAX_ARGS] {
+        pad_args(&[self.group as usize, self.shares as usize])
+    }
+
+    fn from_args(args: [usize; MAX_ARGS]) -> Self {
+        #[allow(clippy::cast_possible_truncation)]
+        Self { group: args[0] as u32, shares: args[1] as u32 }
+    }
+}
+
+/// Arguments for [`Vector::GroupAttach`]: a task ID (or [`super::task::CURRENT`]), and the group to move it into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AttachArgs {
+    pub tid: u32,
+    pub group: u32,
+}
+
+impl Arguments for AttachArgs {
+    fn into_args(self) -> [usize; MAX_ARGS] {
+        pad_args(&[self.tid as usize, self.group as usize])
+    }
+
+    fn from_args(args: [usize; MAX_ARGS]) -> Self {
+        #[allow(clippy::cast_possible_truncation)]
+        Self { tid: args[0] as u32, group: args[1] as u32 }
+    }
+}
+
+/// Arguments for [`Vector::GroupInfo`]: a group, and an [`Info`] to write into, in the caller's memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InfoArgs {
+    pub group: u32,
+    pub info_ptr: usize,
+}
+
+impl Arguments for InfoArgs {
+    fn into_args(self) -> [usize; MAX_ARGS] {
+        pad_args(&[self.group as usize, self.info_ptr])
+    }
+
+    fn from_args(args: [usize; MAX_ARGS]) -> Self {
+        #[allow(clippy::cast_possible_truncation)]
+        Self { group: args[0] as u32, info_ptr: args[1] }
+    }
+}
+
+/// Creates an empty group with `shares` (between [`MIN_SHARES`] and [`MAX_SHARES`]), returning its ID.
+pub fn create(shares: u32) -> core::result::Result<u32, Error> {
+    // Safety: System call takes no pointers.
+    match unsafe { super::invoke(Vector::GroupCreate, CreateArgs { shares }) }? {
+        Success::Value(group) => u32::try_from(group).map_err(|_| Error::InvalidResult),
+        _ => Err(Error::InvalidResult),
+    }
+}
+
+/// Destroys the group `group`, which fails with [`Error::Busy`] while any task remains in it.
+pub fn destroy(group: u32) -> Result {
+    // Safety: System call takes no pointers.
+    unsafe { super::invoke(Vector::GroupDestroy, GroupArgs { group }) }
+}
+
+/// Sets the shares of the group `group`: while groups compete for a core, each receives CPU time in proportion to
+/// its shares.
+pub fn set_shares(group: u32, shares: u32) -> Result {
+    // Safety: System call takes no pointers.
+    unsafe { super::invoke(Vector::GroupSetShares, SharesArgs { group, shares }) }
+}
+
+/// Moves the task `tid` (or the calling task, with [`super::task::CURRENT`]) into the group `group`.
+///
+/// #### Remark
+///
+/// The task's CPU time so far stays accounted to its previous group. Moving a task run by another user fails with
+/// [`super::Error::PermissionDenied`], unless the caller holds [`super::cred::Capabilities::TASK_CONTROL`].
+pub fn attach(tid: u32, group: u32) -> Result {
+    // Safety: System call takes no pointers.
+    unsafe { super::invoke(Vector::GroupAttach, AttachArgs { tid, group }) }
+}
+
+/// Gets a snapshot of the group `group`.
+pub fn get_info(group: u32) -> core::result::Result<Info, Error> {
+    let mut info = core::mem::MaybeUninit::<Info>::uninit();
+
+    // Safety: `info` is valid for a write of `Info`.
+    unsafe {
+        super::invoke(Vector::GroupInfo, InfoArgs { group, info_ptr: info.as_mut_ptr().addr() })?;
+    }
+
+    // Safety: Kernel has written the `Info` on success.
+    Ok(unsafe { info.assume_init() })
+}
@@ -0,0 +1,38 @@
+//! Events read from the input device (`/dev/input`), which yields whole [`Event`] records.
+//!
+//! Reads never block; a read returns `0` bytes when no events are pending.
+
+use num_enum::TryFromPrimitive;
+
+#[repr(u16)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromPrimitive)]
+pub enum EventKind {
+    /// `code` is the key code, and `value` is `1` when pressed, or `0` when released.
+    Key = 1,
+    /// `code` is the axis ([`AXIS_X`] or [`AXIS_Y`]), and `value` the relative motion along it.
+    Motion = 2,
+    /// `code` is the button (i.e. [`BUTTON_LEFT`]), and `value` is `1` when pressed, or `0` when released.
+    Button = 3,
+    /// `value` is the number of detents scrolled, positive away from the user.
+    Scroll = 4,
+}
+
+/// Positive motion along the X axis is rightwards.
+pub const AXIS_X: u16 = 0;
+/// Positive motion along the Y axis is downwards, as in screen coordinates.
+pub const AXIS_Y: u16 = 1;
+
+pub const BUTTON_LEFT: u16 = 0;
+pub const BUTTON_RIGHT: u16 = 1;
+pub const BUTTON_MIDDLE: u16 = 2;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Event {
+    /// Milliseconds since boot at which the event was received.
+    pub timestamp_ms: u64,
+    /// [`EventKind`] of the event.
+    pub kind: u16,
+    pub code: u16,
+    pub value: i32,
+}
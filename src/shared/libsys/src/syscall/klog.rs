@@ -1,46 +1,22 @@
-use super::{Result, Vector};
-
-enum KlogVectorOffset {
-    Info = 0,
-    Error = 1,
-    Debug = 2,
-    Trace = 3,
-}
+use super::{KlogArgs, Result, Vector};
 
 pub fn info(str: &str) -> Result {
-    klog(KlogVectorOffset::Info, str)
+    klog(Vector::KlogInfo, str)
 }
 
 pub fn error(str: &str) -> Result {
-    klog(KlogVectorOffset::Error, str)
+    klog(Vector::KlogError, str)
 }
 
 pub fn debug(str: &str) -> Result {
-    klog(KlogVectorOffset::Debug, str)
+    klog(Vector::KlogDebug, str)
 }
 
 pub fn trace(str: &str) -> Result {
-    klog(KlogVectorOffset::Trace, str)
+    klog(Vector::KlogTrace, str)
 }
 
-fn klog(offset: KlogVectorOffset, str: &str) -> Result {
-    let vector = (Vector::KlogInfo as usize) + (offset as usize);
-    let str_ptr = str.as_ptr();
-    let str_len = str.len();
-
-    // Safety: It isn't.
-    unsafe {
-        let discriminant: usize;
-        let value: usize;
-
-        core::arch::asm!(
-            "int 0x80",
-            in("rax") vector,
-            inout("rdi") str_ptr => discriminant,
-            inout("rsi") str_len => value,
-            options(nostack, nomem, preserves_flags)
-        );
-
-        <Result as super::ResultConverter>::from_registers((discriminant, value))
-    }
+fn klog(vector: Vector, str: &str) -> Result {
+    // Safety: String is valid for reads of its length.
+    unsafe { super::invoke(vector, KlogArgs::from(str)) }
 }
@@ -0,0 +1,156 @@
+//! Anonymous memory mappings, changes to their protection, and their release.
+//!
+//! ### W^X
+//!
+//! No mapping is ever both writable and executable. Further, a region which has been writable can't later be made
+//! executable (nor the reverse), unless it was mapped with [`MapFlags::ALLOW_WX_TRANSITION`] (i.e. by a JIT
+//! compiler); such requests fail with [`Error::WxViolation`].
+
+use super::{Arguments, BufferArgs, Error, Result, Success, Vector};
+use crate::syscall::raw::{pad_args, MAX_ARGS};
+use num_enum::TryFromPrimitive;
+
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromPrimitive)]
+pub enum Protection {
+    ReadOnly = 0,
+    ReadWrite = 1,
+    ReadExecute = 2,
+}
+
+bitflags::bitflags! {
+    #[repr(transparent)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct MapFlags: u32 {
+        /// Permits the region to alternate between writable and executable (though never both at once).
+        const ALLOW_WX_TRANSITION = 1 << 0;
+    }
+}
+
+/// Arguments for [`Vector::MemMap`]: the number of pages to map, and their [`Protection`] and [`MapFlags`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemMapArgs {
+    pub page_count: usize,
+    pub protection: u32,
+    pub flags: u32,
+}
+
+impl Arguments for MemMapArgs {
+    fn into_args(self) -> [usize; MAX_ARGS] {
+        pad_args(&[self.page_count, self.protection as usize, self.flags as usize])
+    }
+
+    fn from_args(args: [usize; MAX_ARGS]) -> Self {
+        #[allow(clippy::cast_possible_truncation)]
+        Self { page_count: args[0], protection: args[1] as u32, flags: args[2] as u32 }
+    }
+}
+
+/// Arguments for [`Vector::MemProtect`]: a page-aligned address, the number of pages from it, and their new
+/// [`Protection`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProtectArgs {
+    pub address: usize,
+    pub page_count: usize,
+    pub protection: u32,
+}
+
+impl Arguments for ProtectArgs {
+    fn into_args(self) -> [usize; MAX_ARGS] {
+        pad_args(&[self.address, self.page_count, self.protection as usize])
+    }
+
+    fn from_args(args: [usize; MAX_ARGS]) -> Self {
+        #[allow(clippy::cast_possible_truncation)]
+        Self { address: args[0], page_count: args[1], protection: args[2] as u32 }
+    }
+}
+
+/// Arguments for [`Vector::MemUnmap`]: a page-aligned address, and the number of pages from it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnmapArgs {
+    pub address: usize,
+    pub page_count: usize,
+}
+
+impl Arguments for UnmapArgs {
+    fn into_args(self) -> [usize; MAX_ARGS] {
+        pad_args(&[self.address, self.page_count])
+    }
+
+    fn from_args(args: [usize; MAX_ARGS]) -> Self {
+        Self { address: args[0], page_count: args[1] }
+    }
+}
+
+/// Arguments for [`Vector::MemMapModule`]: the name of a boot module, and a `usize` to write its length into, in the
+/// caller's memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModuleArgs {
+    pub name: BufferArgs,
+    pub len_ptr: usize,
+}
+
+impl Arguments for ModuleArgs {
+    fn into_args(self) -> [usize; MAX_ARGS] {
+        pad_args(&[self.name.ptr, self.name.len, self.len_ptr])
+    }
+
+    fn from_args(args: [usize; MAX_ARGS]) -> Self {
+        Self { name: BufferArgs { ptr: args[0], len: args[1] }, len_ptr: args[2] }
+    }
+}
+
+/// Maps `page_count` zeroed pages into the current task, returning the mapping's address.
+pub fn map(
+    page_count: usize,
+    protection: Protection,
+    flags: MapFlags,
+) -> core::result::Result<core::ptr::NonNull<u8>, Error> {
+    let args = MemMapArgs { page_count, protection: protection as u32, flags: flags.bits() };
+
+    // Safety: System call takes no pointers.
+    match unsafe { super::invoke(Vector::MemMap, args) }? {
+        Success::NonNullPtr(ptr) => Ok(ptr.cast()),
+        _ => Err(Error::InvalidResult),
+    }
+}
+
+/// Changes the protection of `page_count` pages from `address`, which must all lie within a single mapping.
+pub fn protect(address: core::ptr::NonNull<u8>, page_count: usize, protection: Protection) -> Result {
+    let args = ProtectArgs { address: address.addr().get(), page_count, protection: protection as u32 };
+
+    // Safety: The kernel only changes the mapping's protection; it never accesses the memory.
+    unsafe { super::invoke(Vector::MemProtect, args) }
+}
+
+/// Unmaps `page_count` pages from `address`, releasing their memory. Every page must lie within a mapping, though
+/// the range may span several.
+///
+/// ### Safety
+///
+/// The pages must no longer be referenced, as any further access faults.
+pub unsafe fn unmap(address: core::ptr::NonNull<u8>, page_count: usize) -> Result {
+    let args = UnmapArgs { address: address.addr().get(), page_count };
+
+    // Safety: The kernel never accesses the memory; the caller ensures it isn't referenced after.
+    unsafe { super::invoke(Vector::MemUnmap, args) }
+}
+
+/// Maps the boot module `name` (i.e. `initrd`) read-only into the current task, without copying it, returning its
+/// data.
+///
+/// #### Remark
+///
+/// Only tasks owned by root may map boot modules; others fail with [`Error::PermissionDenied`]. The mapping is
+/// released with [`unmap`], like any other.
+pub fn map_module(name: &str) -> core::result::Result<core::ptr::NonNull<[u8]>, Error> {
+    let mut len = 0usize;
+    let args = ModuleArgs { name: BufferArgs::from(name), len_ptr: core::ptr::addr_of_mut!(len).addr() };
+
+    // Safety: String is valid for reads of its length, and `len` is valid for a write of `usize`.
+    match unsafe { super::invoke(Vector::MemMapModule, args) }? {
+        Success::NonNullPtr(ptr) => Ok(core::ptr::NonNull::slice_from_raw_parts(ptr.cast(), len)),
+        _ => Err(Error::InvalidResult),
+    }
+}
@@ -1,9 +1,42 @@
+//! Userspace side of the system call ABI.
+//!
+//! ### ABI (version [`ABI_VERSION`])
+//!
+//! |                              | x86_64                         | riscv64    |
+//! |------------------------------|--------------------------------|------------|
+//! | Instruction                  | `int 0x80`                     | `ecall`    |
+//! | Vector                       | `rax`                          | `a7`       |
+//! | Arguments                    | `rdi, rsi, rdx, rcx, r8, r9`   | `a0..=a5`  |
+//! | Result (discriminant, value) | `rdi, rsi`                     | `a0, a1`   |
+//!
+//! All other registers are preserved across the call. The result registers are decoded
+//! with [`ResultConverter`].
+
+pub mod block;
+pub mod cred;
+pub mod dl;
+pub mod fb;
+pub mod fs;
+pub mod futex;
+pub mod group;
+pub mod input;
 pub mod klog;
+pub mod mem;
+pub mod raw;
+pub mod sched_trace;
 pub mod task;
+pub mod time;
+pub mod tty;
+
+mod args;
+pub use args::*;
 
 use core::ffi::c_void;
 use num_enum::TryFromPrimitive;
 
+/// Version of the system call ABI. Bumped whenever vectors, argument layouts, or result encodings change.
+pub const ABI_VERSION: u32 = 26;
+
 #[repr(usize)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromPrimitive, Hash)]
 pub enum Vector {
@@ -14,6 +47,57 @@ pub enum Vector {
 
     TaskExit = 0x200,
     TaskYield = 0x201,
+    TaskSetName = 0x202,
+    TaskInfo = 0x203,
+    TaskList = 0x204,
+    TaskId = 0x205,
+    TaskSleep = 0x206,
+    TaskSetSchedPolicy = 0x207,
+    TaskWait = 0x208,
+    TaskSetIoClass = 0x209,
+    TaskSpawnThread = 0x20A,
+    TaskSetDeadline = 0x20B,
+
+    FsChdir = 0x300,
+    FsGetcwd = 0x301,
+    FsOpen = 0x302,
+    FsClose = 0x303,
+    FsRead = 0x304,
+    FsWrite = 0x305,
+    FsStat = 0x306,
+    FsReadDir = 0x307,
+    FsCreate = 0x308,
+    FsUnlink = 0x309,
+    FsRename = 0x30A,
+    FsLink = 0x30B,
+    FsControl = 0x30C,
+    FsMap = 0x30D,
+
+    BlockStats = 0x400,
+    BlockFlush = 0x401,
+
+    MemMap = 0x500,
+    MemProtect = 0x501,
+    MemUnmap = 0x502,
+    MemMapModule = 0x503,
+
+    DlProgramHeaders = 0x600,
+    DlMapSegment = 0x601,
+    DlRelocate = 0x602,
+
+    TimeGet = 0x700,
+
+    GroupCreate = 0x800,
+    GroupDestroy = 0x801,
+    GroupSetShares = 0x802,
+    GroupAttach = 0x803,
+    GroupInfo = 0x804,
+
+    CredGet = 0x900,
+    CredSet = 0x901,
+
+    FutexWait = 0xA00,
+    FutexWake = 0xA01,
 }
 
 const_assert!({
@@ -21,7 +105,7 @@ const_assert!({
     size_of::<Result>() <= size_of::<(u64, u64)>()
 });
 
-pub type Result = core::result::Result<Success, Error>;
+pub type Result<T = Success> = core::result::Result<T, Error>;
 
 pub trait ResultConverter {
     type Registers;
@@ -34,15 +118,19 @@ impl ResultConverter for Result {
     type Registers = (usize, usize);
 
     fn from_registers((discriminant, value): Self::Registers) -> Self {
-        let discriminant = u32::try_from(discriminant).unwrap();
+        let Ok(discriminant) = u32::try_from(discriminant) else { return Err(Error::InvalidResult) };
+
         match Error::try_from_primitive(discriminant).map_err(|err| err.number) {
             Ok(err) => Err(err),
 
             Err(0x0) => Ok(Success::Ok),
             Err(0x1) => Ok(Success::Ptr(value as *mut c_void)),
-            Err(0x2) => Ok(Success::NonNullPtr(core::ptr::NonNull::new(value as *mut c_void).unwrap())),
+            Err(0x2) => {
+                core::ptr::NonNull::new(value as *mut c_void).map(Success::NonNullPtr).ok_or(Error::InvalidResult)
+            }
+            Err(0x3) => Ok(Success::Value(value)),
 
-            Err(_) => unimplemented!(),
+            Err(_) => Err(Error::InvalidResult),
         }
     }
 
@@ -51,6 +139,7 @@ impl ResultConverter for Result {
             Ok(success @ Success::Ok) => (success.discriminant() as usize, usize::default()),
             Ok(success @ Success::Ptr(ptr)) => (success.discriminant() as usize, ptr.addr()),
             Ok(success @ Success::NonNullPtr(ptr)) => (success.discriminant() as usize, ptr.addr().get()),
+            Ok(success @ Success::Value(value)) => (success.discriminant() as usize, value),
 
             Err(err) => (err as usize, Default::default()),
         }
@@ -63,6 +152,7 @@ pub enum Success {
     Ok = 0x0,
     Ptr(*mut c_void) = 0x1,
     NonNullPtr(core::ptr::NonNull<c_void>) = 0x2,
+    Value(usize) = 0x3,
 }
 
 impl Success {
@@ -83,6 +173,90 @@ pub enum Error {
     UnmappedMemory = 0x40000,
 
     NoActiveTask = 0x50000,
+    /// No task has the given ID.
+    NoSuchTask = 0x50001,
+    /// The task holds its quota of the kernel object the call would allocate (i.e. open descriptors).
+    QuotaExceeded = 0x50002,
+    /// The futex word didn't hold the expected value, so the task didn't block (see [`futex::wait`]).
+    WouldBlock = 0x50003,
+    /// The core can't reserve the share of its time a deadline task asks for (see [`task::set_deadline`]).
+    OverSubscribed = 0x50004,
+
+    NotFound = 0x60000,
+    NotDirectory = 0x60001,
+    IsDirectory = 0x60002,
+    InvalidPath = 0x60003,
+    PermissionDenied = 0x60004,
+    BadDescriptor = 0x60005,
+    /// The filesystem doesn't support the operation.
+    Unsupported = 0x60006,
+    /// The operation would cross between filesystems.
+    CrossDevice = 0x60007,
+    NotEmpty = 0x60008,
+    AlreadyExists = 0x60009,
+    /// The path is in use, i.e. as a mount point.
+    Busy = 0x6000A,
+    /// The device reported an error.
+    Io = 0x6000B,
+    /// The device has no space left to hold the data.
+    NoSpace = 0x6000C,
+
+    /// The provided buffer is too small to hold the result.
+    BufferTooSmall = 0x70000,
+    /// A non-pointer argument held an invalid value.
+    InvalidArgument = 0x70001,
+
+    /// The mapping would violate W^X (see [`mem`]).
+    WxViolation = 0x80000,
+    /// There isn't enough free memory (or address space) to satisfy the request.
+    OutOfMemory = 0x80001,
+
+    /// The result registers did not hold a valid encoding. This is never returned by the kernel.
+    InvalidResult = 0xFFFF0000,
+}
+
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            Self::InvalidVector => "invalid system call vector",
+            Self::InvalidPtr => "invalid pointer argument",
+            Self::InvalidUtf8 => "string argument is not valid UTF-8",
+            Self::UnmappedMemory => "argument references unmapped memory",
+            Self::NoActiveTask => "no task is active",
+            Self::NoSuchTask => "no such task",
+            Self::QuotaExceeded => "task quota exceeded",
+            Self::WouldBlock => "futex value changed",
+            Self::OverSubscribed => "core over-subscribed",
+            Self::NotFound => "no such file or directory",
+            Self::NotDirectory => "not a directory",
+            Self::IsDirectory => "is a directory",
+            Self::InvalidPath => "invalid path",
+            Self::PermissionDenied => "permission denied",
+            Self::BadDescriptor => "bad file descriptor",
+            Self::Unsupported => "operation not supported by filesystem",
+            Self::CrossDevice => "cross-device link",
+            Self::NotEmpty => "directory not empty",
+            Self::AlreadyExists => "file exists",
+            Self::Busy => "resource busy",
+            Self::Io => "input/output error",
+            Self::NoSpace => "no space left on device",
+            Self::BufferTooSmall => "buffer is too small",
+            Self::InvalidArgument => "invalid argument",
+            Self::WxViolation => "mapping would be both writable and executable",
+            Self::OutOfMemory => "out of memory",
+            Self::InvalidResult => "invalid system call result encoding",
+        })
+    }
+}
+
+/// Invokes the system call `vector` with typed arguments.
+///
+/// ### Safety
+///
+/// Any pointers held by `args` must be valid for whatever access the system call performs on them.
+#[cfg(any(target_arch = "x86_64", target_arch = "riscv64"))]
+pub unsafe fn invoke(vector: Vector, args: impl Arguments) -> Result {
+    raw::syscall(vector, args.into_args())
 }
 
 impl From<core::str::Utf8Error> for Error {
@@ -90,3 +264,209 @@ impl From<core::str::Utf8Error> for Error {
         Self::InvalidUtf8
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn result_round_trip() {
+        let ptr = core::ptr::NonNull::<c_void>::dangling();
+
+        for result in [
+            Ok(Success::Ok),
+            Ok(Success::Ptr(core::ptr::null_mut())),
+            Ok(Success::NonNullPtr(ptr)),
+            Ok(Success::Value(usize::MAX)),
+            Err(Error::InvalidVector),
+            Err(Error::UnmappedMemory),
+        ] {
+            assert_eq!(<Result as ResultConverter>::from_registers(result.into_registers()), result);
+        }
+    }
+
+    #[test]
+    fn invalid_result_encoding() {
+        assert_eq!(<Result as ResultConverter>::from_registers((0x4, 0)), Err(Error::InvalidResult));
+        assert_eq!(<Result as ResultConverter>::from_registers((0x2, 0)), Err(Error::InvalidResult));
+        assert_eq!(<Result as ResultConverter>::from_registers((usize::MAX, 0)), Err(Error::InvalidResult));
+    }
+
+    #[test]
+    fn dir_entries_round_trip() {
+        use fs::{pack_dir_entry, DirEntries, FileKind, DIR_ENTRY_ALIGN};
+
+        let mut buf = [0u8; 64];
+        let first_len = pack_dir_entry(&mut buf, FileKind::Directory, "bin").unwrap();
+        let second_len = pack_dir_entry(&mut buf[first_len..], FileKind::File, "readme.txt").unwrap();
+        assert_eq!(first_len % DIR_ENTRY_ALIGN, 0);
+        let rest = &mut buf[(first_len + second_len)..];
+        assert!(pack_dir_entry(rest, FileKind::File, "too-long-for-the-rest").is_none());
+
+        let entries = DirEntries::new(&buf[..(first_len + second_len)]).collect::<alloc::vec::Vec<_>>();
+        assert_eq!(entries, [(FileKind::Directory, "bin"), (FileKind::File, "readme.txt")]);
+    }
+
+    #[test]
+    fn mem_args_round_trip() {
+        use mem::{MapFlags, MemMapArgs, ModuleArgs, ProtectArgs, Protection, UnmapArgs};
+
+        let map_args = MemMapArgs {
+            page_count: 4,
+            protection: Protection::ReadWrite as u32,
+            flags: MapFlags::ALLOW_WX_TRANSITION.bits(),
+        };
+        assert_eq!(MemMapArgs::from_args(map_args.into_args()), map_args);
+
+        let protect_args = ProtectArgs { address: 0x1000, page_count: 4, protection: Protection::ReadExecute as u32 };
+        assert_eq!(ProtectArgs::from_args(protect_args.into_args()), protect_args);
+
+        let unmap_args = UnmapArgs { address: 0x1000, page_count: 4 };
+        assert_eq!(UnmapArgs::from_args(unmap_args.into_args()), unmap_args);
+
+        let module_args = ModuleArgs { name: BufferArgs { ptr: 0x2000, len: 6 }, len_ptr: 0x3000 };
+        assert_eq!(ModuleArgs::from_args(module_args.into_args()), module_args);
+    }
+
+    #[test]
+    fn dl_args_round_trip() {
+        use dl::{HeadersArgs, RelocateArgs, SegmentArgs};
+
+        let headers_args = HeadersArgs { fd: 3, headers_ptr: 0x1000, count: 8 };
+        assert_eq!(HeadersArgs::from_args(headers_args.into_args()), headers_args);
+
+        let segment_args = SegmentArgs { fd: 3, index: 1, base: 0x4000_0000 };
+        assert_eq!(SegmentArgs::from_args(segment_args.into_args()), segment_args);
+
+        let relocate_args = RelocateArgs { relocations_ptr: 0x2000, count: 16 };
+        assert_eq!(RelocateArgs::from_args(relocate_args.into_args()), relocate_args);
+    }
+
+    #[test]
+    fn task_info_args_round_trip() {
+        let args = task::InfoArgs { tid: task::CURRENT, info_ptr: 0x1000 };
+        assert_eq!(task::InfoArgs::from_args(args.into_args()), args);
+    }
+
+    #[test]
+    fn task_exit_args_round_trip() {
+        let args = task::ExitArgs { code: task::EXIT_FAULTED };
+        assert_eq!(task::ExitArgs::from_args(args.into_args()), args);
+    }
+
+    #[test]
+    fn task_wait_args_round_trip() {
+        let args = task::WaitArgs { alias: 0x0123_4567_89AB_CDEF_FEDC_BA98_7654_3210 };
+        assert_eq!(task::WaitArgs::from_args(args.into_args()), args);
+    }
+
+    #[test]
+    fn task_sleep_args_round_trip() {
+        let args = task::SleepArgs { ns: 250_000_000 };
+        assert_eq!(task::SleepArgs::from_args(args.into_args()), args);
+    }
+
+    #[test]
+    fn task_sched_policy_args_round_trip() {
+        let args = task::SchedPolicyArgs {
+            tid: task::CURRENT,
+            policy: task::Policy::RoundRobin as u32,
+            priority: task::Priority::High as u32,
+        };
+        assert_eq!(task::SchedPolicyArgs::from_args(args.into_args()), args);
+    }
+
+    #[test]
+    fn task_io_class_args_round_trip() {
+        let args = task::IoClassArgs { tid: task::CURRENT, class: task::IoClass::Idle as u32 };
+        assert_eq!(task::IoClassArgs::from_args(args.into_args()), args);
+        assert_eq!(task::IoClass::try_from(args.class), Ok(task::IoClass::Idle));
+    }
+
+    #[test]
+    fn task_spawn_thread_args_round_trip() {
+        let args = task::SpawnThreadArgs { entry: 0x40_1000, stack: 0x7FFF_F000, arg: usize::MAX };
+        assert_eq!(task::SpawnThreadArgs::from_args(args.into_args()), args);
+    }
+
+    #[test]
+    fn task_info_name() {
+        let mut info = task::Info::default();
+        info.name[..4].copy_from_slice(b"init");
+        info.name_len = 4;
+        assert_eq!(info.name(), Some("init"));
+
+        info.name_len = u32::MAX;
+        assert_eq!(info.name(), None);
+    }
+
+    #[test]
+    fn cred_args_round_trip() {
+        use cred::{Capabilities, GetArgs, SetArgs};
+
+        let get_args = GetArgs { credentials_ptr: 0x1000 };
+        assert_eq!(GetArgs::from_args(get_args.into_args()), get_args);
+
+        let set_args = SetArgs { uid: 1000, gid: 100, capabilities: Capabilities::POWER.bits() };
+        assert_eq!(SetArgs::from_args(set_args.into_args()), set_args);
+    }
+
+    #[test]
+    fn futex_args_round_trip() {
+        use futex::{WaitArgs, WakeArgs};
+
+        let wait_args = WaitArgs { addr: 0x1004, expected: u32::MAX };
+        assert_eq!(WaitArgs::from_args(wait_args.into_args()), wait_args);
+
+        let wake_args = WakeArgs { addr: 0x1004, count: usize::MAX };
+        assert_eq!(WakeArgs::from_args(wake_args.into_args()), wake_args);
+    }
+
+    #[test]
+    fn cred_changes_only_drop_privileges() {
+        use cred::{Capabilities, Credentials};
+
+        let user = Credentials { uid: 1000, gid: 100, capabilities: Capabilities::empty() };
+        assert!(Credentials::ROOT.permits(&user));
+        assert!(!user.permits(&Credentials::ROOT));
+        assert!(!user.permits(&Credentials { uid: 0, ..user }));
+        assert!(!user.permits(&Credentials { capabilities: Capabilities::MOUNT, ..user }));
+
+        let capable = Credentials { capabilities: Capabilities::MOUNT, ..Credentials::ROOT };
+        assert!(capable.permits(&Credentials { capabilities: Capabilities::empty(), ..capable }));
+        assert!(!capable.permits(&user));
+    }
+
+    #[test]
+    fn time_clock_args_round_trip() {
+        let args = time::ClockArgs { clock: time::Clock::Realtime as u32 };
+        assert_eq!(time::ClockArgs::from_args(args.into_args()), args);
+    }
+
+    #[test]
+    fn group_args_round_trip() {
+        use group::{AttachArgs, CreateArgs, GroupArgs, InfoArgs, SharesArgs, DEFAULT_SHARES, MAX_SHARES};
+
+        let create_args = CreateArgs { shares: DEFAULT_SHARES };
+        assert_eq!(CreateArgs::from_args(create_args.into_args()), create_args);
+
+        let group_args = GroupArgs { group: 3 };
+        assert_eq!(GroupArgs::from_args(group_args.into_args()), group_args);
+
+        let shares_args = SharesArgs { group: 3, shares: MAX_SHARES };
+        assert_eq!(SharesArgs::from_args(shares_args.into_args()), shares_args);
+
+        let attach_args = AttachArgs { tid: task::CURRENT, group: 3 };
+        assert_eq!(AttachArgs::from_args(attach_args.into_args()), attach_args);
+
+        let info_args = InfoArgs { group: group::ROOT, info_ptr: 0x1000 };
+        assert_eq!(InfoArgs::from_args(info_args.into_args()), info_args);
+    }
+
+    #[test]
+    fn klog_args_round_trip() {
+        let args = KlogArgs::from("hello");
+        assert_eq!(KlogArgs::from_args(args.into_args()), args);
+        assert_eq!(args.into_args()[2..], [0; 4]);
+    }
+}
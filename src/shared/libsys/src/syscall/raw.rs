@@ -0,0 +1,86 @@
+//! Raw, per-architecture system call stubs.
+//!
+//! Prefer the typed wrappers in the parent module, or the [`crate::syscall!`] macro, over calling these directly.
+
+use super::{Result, ResultConverter, Vector};
+
+/// Maximum number of arguments a system call can take.
+pub const MAX_ARGS: usize = 6;
+
+/// Pads `args` out to [`MAX_ARGS`] registers.
+///
+/// #### Panics
+///
+/// If more than [`MAX_ARGS`] arguments are provided.
+pub const fn pad_args(args: &[usize]) -> [usize; MAX_ARGS] {
+    assert!(args.len() <= MAX_ARGS, "too many system call arguments");
+
+    let mut padded = [0usize; MAX_ARGS];
+    let mut index = 0;
+    while index < args.len() {
+        padded[index] = args[index];
+        index += 1;
+    }
+
+    padded
+}
+
+/// Invokes the system call `vector` with the provided argument registers.
+///
+/// ### Safety
+///
+/// Any pointers passed in `args` must be valid for whatever access the system call performs on them.
+#[cfg(target_arch = "x86_64")]
+pub unsafe fn syscall(vector: Vector, args: [usize; MAX_ARGS]) -> Result {
+    let discriminant: usize;
+    let value: usize;
+
+    core::arch::asm!(
+        "int 0x80",
+        in("rax") vector as usize,
+        inout("rdi") args[0] => discriminant,
+        inout("rsi") args[1] => value,
+        in("rdx") args[2],
+        in("rcx") args[3],
+        in("r8") args[4],
+        in("r9") args[5],
+        options(nostack, preserves_flags)
+    );
+
+    <Result as ResultConverter>::from_registers((discriminant, value))
+}
+
+/// Invokes the system call `vector` with the provided argument registers.
+///
+/// ### Safety
+///
+/// Any pointers passed in `args` must be valid for whatever access the system call performs on them.
+#[cfg(target_arch = "riscv64")]
+pub unsafe fn syscall(vector: Vector, args: [usize; MAX_ARGS]) -> Result {
+    let discriminant: usize;
+    let value: usize;
+
+    core::arch::asm!(
+        "ecall",
+        in("a7") vector as usize,
+        inout("a0") args[0] => discriminant,
+        inout("a1") args[1] => value,
+        in("a2") args[2],
+        in("a3") args[3],
+        in("a4") args[4],
+        in("a5") args[5],
+        options(nostack)
+    );
+
+    <Result as ResultConverter>::from_registers((discriminant, value))
+}
+
+/// Invokes a system call, e.g. `syscall!(Vector::TaskYield)` or `syscall!(Vector::KlogInfo, ptr, len)`.
+///
+/// Evaluates to a [`crate::syscall::Result`]. Must be used within an `unsafe` block; see [`syscall`].
+#[macro_export]
+macro_rules! syscall {
+    ($vector:expr $(, $arg:expr)* $(,)?) => {
+        $crate::syscall::raw::syscall($vector, $crate::syscall::raw::pad_args(&[$($arg),*]))
+    };
+}
@@ -0,0 +1,65 @@
+//! Records read from the scheduler trace device (`/dev/sched_trace`), which yields whole [`Record`]s.
+//!
+//! Tracing is disabled until a non-zero byte is written to the device (and a zero byte disables it again). Reads
+//! never block, and the device holds a bounded number of records, discarding the oldest once full.
+//!
+//! Records are little-endian (on every supported target) and laid out as in [`Record`]. `cargo xtask sched_trace`
+//! converts a captured stream into a Chrome trace, as loaded by Perfetto or `chrome://tracing`.
+
+use num_enum::TryFromPrimitive;
+
+/// Task ID standing in for the idle task (i.e. when a core has nothing to run).
+pub const IDLE: u32 = u32::MAX;
+
+#[repr(u16)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromPrimitive)]
+pub enum RecordKind {
+    /// The core switched from task `prev` to task `next`.
+    Switch = 1,
+    /// Task `next` became runnable; `prev` is unused.
+    Wakeup = 2,
+}
+
+#[repr(u16)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromPrimitive)]
+pub enum Reason {
+    /// The previous task's time slice expired.
+    Preempt = 1,
+    /// The previous task yielded.
+    Yield = 2,
+    /// The previous task exited.
+    Exit = 3,
+    /// The task was newly created.
+    Spawn = 4,
+    /// The task's deadline reservation began a new period.
+    Replenish = 5,
+    /// The task was admitted to the deadline class.
+    Admit = 6,
+    /// The previous task went to sleep.
+    Sleep = 7,
+    /// The task's sleep elapsed.
+    Wake = 8,
+    /// The previous task began waiting for another task to exit.
+    Wait = 9,
+    /// A task the task was waiting on exited.
+    Join = 10,
+    /// The previous task blocked on a futex.
+    FutexWait = 11,
+    /// The task was woken from a futex.
+    FutexWake = 12,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Record {
+    /// Milliseconds since boot at which the event occurred.
+    pub timestamp_ms: u64,
+    /// ID of the core on which the event occurred.
+    pub core: u32,
+    /// [`RecordKind`] of the record.
+    pub kind: u16,
+    /// [`Reason`] for the event.
+    pub reason: u16,
+    pub prev: u32,
+    pub next: u32,
+}
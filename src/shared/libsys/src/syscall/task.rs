@@ -1,37 +1,430 @@
-use super::{Result, Vector};
+//! Task lifecycle, naming, and enumeration (i.e. for `ps`/`top`-like tools).
+
+use super::{Arguments, BufferArgs, Error, Result, Success, Vector};
+use crate::syscall::raw::{pad_args, MAX_ARGS};
+use num_enum::TryFromPrimitive;
+use uuid::Uuid;
+
+/// Task ID standing in for the calling task.
+pub const CURRENT: u32 = u32::MAX;
+
+/// Exit code of a task killed for a fault it didn't handle.
+pub const EXIT_FAULTED: u32 = u32::MAX;
+
+/// Maximum length of a task name, in bytes. Longer names are truncated (on a character boundary).
+pub const NAME_LEN: usize = 32;
+
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromPrimitive)]
+pub enum State {
+    /// The task is waiting to be scheduled.
+    Ready = 0,
+    /// The task is running on a core.
+    Running = 1,
+    /// The task is waiting for a sleep to elapse.
+    Sleeping = 2,
+    /// The task is waiting for another task to exit (see [`wait`]), or on a futex (see [`super::futex::wait`]).
+    Waiting = 3,
+    /// The task has exited, and is yet to be released.
+    Exited = 4,
+}
+
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromPrimitive)]
+pub enum Priority {
+    Idle = 0,
+    Low = 1,
+    Normal = 2,
+    High = 3,
+    Critical = 4,
+}
+
+/// Scheduling class of a task.
+///
+/// Tasks of the real-time classes ([`Policy::Fifo`] and [`Policy::RoundRobin`]) always run ahead of the fair class,
+/// and in strict order of priority.
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromPrimitive)]
+pub enum Policy {
+    /// The task shares its core with the tasks of its priority, in time slices which grow with its priority.
+    Fair = 0,
+    /// The task runs until it yields or sleeps, unless a real-time task of a higher priority becomes ready.
+    Fifo = 1,
+    /// As [`Policy::Fifo`], but the task shares its core with the real-time tasks of its priority in fixed slices.
+    RoundRobin = 2,
+}
+
+/// I/O priority class of a task, which its block requests are dispatched by.
+///
+/// Requests of a higher class are issued ahead of those of the classes below it, though an [`IoClass::Idle`] request
+/// which has waited long enough is issued as if it were [`IoClass::Normal`], so it can't starve.
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, TryFromPrimitive)]
+pub enum IoClass {
+    /// Only tasks of a real-time scheduling class (see [`Policy`]) may select the class.
+    Realtime = 0,
+    Normal = 1,
+    /// Requests are only issued while the device has no others waiting (i.e. for background write-back).
+    Idle = 2,
+}
+
+/// I/O a task has issued against one kind of object (see [`Info`]).
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct IoStats {
+    pub read_bytes: u64,
+    pub written_bytes: u64,
+    /// Read operations which succeeded, whether or not they transferred anything.
+    pub reads: u64,
+    pub writes: u64,
+}
+
+/// A snapshot of a task, as written by [`Vector::TaskInfo`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Info {
+    pub tid: u32,
+    /// [`State`] of the task.
+    pub state: u32,
+    /// [`Priority`] of the task.
+    pub priority: u32,
+    /// [`Policy`] of the task.
+    pub policy: u32,
+    /// Length of the task's name, in bytes.
+    pub name_len: u32,
+    /// UTF-8 name of the task (only the first `name_len` bytes are valid).
+    pub name: [u8; NAME_LEN],
+    /// CPU time the task has consumed, in milliseconds.
+    pub cpu_time_ms: u64,
+    /// Pages mapped into the task's address space.
+    pub resident_pages: u64,
+    /// Pages reserved in the task's address space, whether or not they're mapped yet.
+    pub virtual_pages: u64,
+    /// UUID of the task, which (unlike its ID) is never reused.
+    pub alias: [u8; 16],
+    /// UUID of the task's parent, or nil if it has none.
+    pub parent: [u8; 16],
+    /// Resource group the task is in (see [`super::group`]).
+    pub group: u32,
+    /// [`IoClass`] of the task.
+    pub io_class: u32,
+    /// I/O through files (of any kind) the task has open, as counted by the read and write system calls.
+    pub file_io: IoStats,
+    /// I/O the task has issued to block devices, including that of filesystems reading or writing on its behalf.
+    pub block_io: IoStats,
+}
+
+impl Info {
+    pub fn name(&self) -> Option<&str> {
+        let name = self.name.get(..(self.name_len as usize))?;
+        core::str::from_utf8(name).ok()
+    }
+
+    pub fn state(&self) -> Option<State> {
+        State::try_from(self.state).ok()
+    }
+
+    pub fn priority(&self) -> Option<Priority> {
+        Priority::try_from(self.priority).ok()
+    }
+
+    pub fn policy(&self) -> Option<Policy> {
+        Policy::try_from(self.policy).ok()
+    }
+
+    pub fn io_class(&self) -> Option<IoClass> {
+        IoClass::try_from(self.io_class).ok()
+    }
+
+    pub const fn alias(&self) -> Uuid {
+        Uuid::from_bytes(self.alias)
+    }
+
+    pub fn parent(&self) -> Option<Uuid> {
+        Some(Uuid::from_bytes(self.parent)).filter(|parent| !parent.is_nil())
+    }
+}
+
+/// Arguments for [`Vector::TaskInfo`]: a task ID (or [`CURRENT`]), and an [`Info`] to write into, in the caller's
+/// memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InfoArgs {
+    pub tid: u32,
+    pub info_ptr: usize,
+}
+
+impl Arguments for InfoArgs {
+    fn into_args(self) -> [usize; MAX_ARGS] {
+        pad_args(&[self.tid as usize, self.info_ptr])
+    }
+
+    fn from_args(args: [usize; MAX_ARGS]) -> Self {
+        #[allow(clippy::cast_possible_truncation)]
+        Self { tid: args[0] as u32, info_ptr: args[1] }
+    }
+}
+
+/// Arguments for [`Vector::TaskExit`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExitArgs {
+    pub code: u32,
+}
+
+impl Arguments for ExitArgs {
+    fn into_args(self) -> [usize; MAX_ARGS] {
+        pad_args(&[self.code as usize])
+    }
+
+    fn from_args(args: [usize; MAX_ARGS]) -> Self {
+        #[allow(clippy::cast_possible_truncation)]
+        Self { code: args[0] as u32 }
+    }
+}
+
+/// Arguments for [`Vector::TaskWait`]: the UUID of the task to wait on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WaitArgs {
+    pub alias: u128,
+}
+
+impl Arguments for WaitArgs {
+    fn into_args(self) -> [usize; MAX_ARGS] {
+        #[allow(clippy::cast_possible_truncation)]
+        pad_args(&[self.alias as usize, (self.alias >> 64) as usize])
+    }
+
+    fn from_args(args: [usize; MAX_ARGS]) -> Self {
+        Self { alias: (args[0] as u128) | ((args[1] as u128) << 64) }
+    }
+}
+
+/// Arguments for [`Vector::TaskSleep`]: the duration to sleep for, in nanoseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SleepArgs {
+    pub ns: u64,
+}
+
+impl Arguments for SleepArgs {
+    fn into_args(self) -> [usize; MAX_ARGS] {
+        #[allow(clippy::cast_possible_truncation)]
+        pad_args(&[self.ns as usize])
+    }
+
+    fn from_args(args: [usize; MAX_ARGS]) -> Self {
+        Self { ns: args[0] as u64 }
+    }
+}
+
+/// Arguments for [`Vector::TaskSetSchedPolicy`]: a task ID (or [`CURRENT`]), and the [`Policy`] and [`Priority`] to
+/// schedule it with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SchedPolicyArgs {
+    pub tid: u32,
+    pub policy: u32,
+    pub priority: u32,
+}
+
+impl Arguments for SchedPolicyArgs {
+    fn into_args(self) -> [usize; MAX_ARGS] {
+        pad_args(&[self.tid as usize, self.policy as usize, self.priority as usize])
+    }
+
+    fn from_args(args: [usize; MAX_ARGS]) -> Self {
+        #[allow(clippy::cast_possible_truncation)]
+        Self { tid: args[0] as u32, policy: args[1] as u32, priority: args[2] as u32 }
+    }
+}
+
+/// Arguments for [`Vector::TaskSetIoClass`]: a task ID (or [`CURRENT`]), and the [`IoClass`] to dispatch its block
+/// requests by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IoClassArgs {
+    pub tid: u32,
+    pub class: u32,
+}
+
+impl Arguments for IoClassArgs {
+    fn into_args(self) -> [usize; MAX_ARGS] {
+        pad_args(&[self.tid as usize, self.class as usize])
+    }
+
+    fn from_args(args: [usize; MAX_ARGS]) -> Self {
+        #[allow(clippy::cast_possible_truncation)]
+        Self { tid: args[0] as u32, class: args[1] as u32 }
+    }
+}
+
+/// Arguments for [`Vector::TaskSetDeadline`]: the period of the calling task, and its budget of execution within
+/// each, in scheduler ticks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeadlineArgs {
+    pub period: u16,
+    pub budget: u16,
+}
+
+impl Arguments for DeadlineArgs {
+    fn into_args(self) -> [usize; MAX_ARGS] {
+        pad_args(&[usize::from(self.period), usize::from(self.budget)])
+    }
+
+    fn from_args(args: [usize; MAX_ARGS]) -> Self {
+        #[allow(clippy::cast_possible_truncation)]
+        Self { period: args[0] as u16, budget: args[1] as u16 }
+    }
+}
+
+/// Arguments for [`Vector::TaskSpawnThread`]: where the new thread begins execution, the top of its stack, and the
+/// argument it's passed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpawnThreadArgs {
+    pub entry: usize,
+    pub stack: usize,
+    pub arg: usize,
+}
+
+impl Arguments for SpawnThreadArgs {
+    fn into_args(self) -> [usize; MAX_ARGS] {
+        pad_args(&[self.entry, self.stack, self.arg])
+    }
+
+    fn from_args(args: [usize; MAX_ARGS]) -> Self {
+        Self { entry: args[0], stack: args[1], arg: args[2] }
+    }
+}
 
 pub fn yield_task() -> Result {
-    // Safety: We're very careful.
-    unsafe {
-        let discriminant: usize;
-        let value: usize;
+    // Safety: System call takes no arguments.
+    unsafe { crate::syscall!(Vector::TaskYield) }
+}
+
+/// Terminates the calling task with the exit code `code`. Its process's memory and descriptors are released as its
+/// last thread exits.
+pub fn exit_task(code: u32) -> Result {
+    // Safety: System call takes no pointers.
+    unsafe { super::invoke(Vector::TaskExit, ExitArgs { code }) }
+}
+
+/// Gets the ID of the calling task.
+pub fn id() -> core::result::Result<u32, Error> {
+    // Safety: System call takes no arguments.
+    match unsafe { crate::syscall!(Vector::TaskId) }? {
+        Success::Value(tid) => u32::try_from(tid).map_err(|_| Error::InvalidResult),
+        _ => Err(Error::InvalidResult),
+    }
+}
+
+/// Suspends the calling task for at least `ns` nanoseconds.
+///
+/// #### Remark
+///
+/// Sleeps are counted in (millisecond) scheduler ticks, so they're rounded up to a whole tick.
+pub fn sleep(ns: u64) -> Result {
+    // Safety: System call takes no pointers.
+    unsafe { super::invoke(Vector::TaskSleep, SleepArgs { ns }) }
+}
 
-        core::arch::asm!(
-            "int 0x80",
-            in("rax") Vector::TaskYield as usize,
-            out("rdi") discriminant,
-            out("rsi") value,
-            options(nostack, nomem, preserves_flags)
-        );
+/// Moves the calling task into the deadline scheduling class, guaranteeing it `budget` ticks of execution within
+/// every `period` ticks (replacing any reservation it already holds).
+///
+/// #### Remark
+///
+/// Reservations are made on the core the task is running on, to which it's pinned from then on. Fails with
+/// [`Error::InvalidArgument`] if either is zero or the budget exceeds the period, or with [`Error::OverSubscribed`] if
+/// the core's remaining share is too small.
+pub fn set_deadline(period: u16, budget: u16) -> Result {
+    // Safety: System call takes no pointers.
+    unsafe { super::invoke(Vector::TaskSetDeadline, DeadlineArgs { period, budget }) }
+}
 
-        <Result as super::ResultConverter>::from_registers((discriminant, value))
+/// Blocks until the task `task` exits, returning its exit code.
+///
+/// #### Remark
+///
+/// The exit code of a task which has already exited is kept only for its parent, which collects it with its first
+/// wait. Waiting on any other task which is no longer live fails with [`Error::NoSuchTask`].
+pub fn wait(task: Uuid) -> core::result::Result<u32, Error> {
+    // Safety: System call takes no pointers.
+    match unsafe { super::invoke(Vector::TaskWait, WaitArgs { alias: task.as_u128() }) }? {
+        Success::Value(code) => u32::try_from(code).map_err(|_| Error::InvalidResult),
+        _ => Err(Error::InvalidResult),
     }
 }
 
-pub fn exit_task() -> Result {
-    // Safety: We're very careful.
+/// Names the calling task, truncating the name to [`NAME_LEN`] bytes.
+pub fn set_name(name: &str) -> Result {
+    // Safety: String is valid for reads of its length.
+    unsafe { super::invoke(Vector::TaskSetName, BufferArgs::from(name)) }
+}
+
+/// Moves the task `tid` (or the calling task, with [`CURRENT`]) into the scheduling class `policy`, at `priority`.
+///
+/// #### Remark
+///
+/// Each class has a priority ceiling, set by the kernel parameters, above which this fails with
+/// [`Error::PermissionDenied`], as does moving a task run by another user without
+/// [`super::cred::Capabilities::TASK_CONTROL`]. A task which is running or sleeping moves as it's next queued.
+pub fn set_sched_policy(tid: u32, policy: Policy, priority: Priority) -> Result {
+    let args = SchedPolicyArgs { tid, policy: policy as u32, priority: priority as u32 };
+
+    // Safety: System call takes no pointers.
+    unsafe { super::invoke(Vector::TaskSetSchedPolicy, args) }
+}
+
+/// Sets the I/O priority class of the task `tid` (or of the calling task, with [`CURRENT`]).
+///
+/// #### Remark
+///
+/// Selecting [`IoClass::Realtime`] for a task outside of the real-time scheduling classes fails with
+/// [`Error::PermissionDenied`], as does setting the class of a task run by another user without
+/// [`super::cred::Capabilities::TASK_CONTROL`]. Requests the task already has waiting keep the class they were
+/// submitted with.
+pub fn set_io_class(tid: u32, class: IoClass) -> Result {
+    // Safety: System call takes no pointers.
+    unsafe { super::invoke(Vector::TaskSetIoClass, IoClassArgs { tid, class: class as u32 }) }
+}
+
+/// Gets a snapshot of the task `tid` (or of the calling task, with [`CURRENT`]).
+pub fn get_info(tid: u32) -> core::result::Result<Info, Error> {
+    let mut info = core::mem::MaybeUninit::<Info>::uninit();
+
+    // Safety: `info` is valid for a write of `Info`.
     unsafe {
-        let discriminant: usize;
-        let value: usize;
-
-        core::arch::asm!(
-            "int 0x80",
-            in("rax") Vector::TaskExit as usize,
-            out("rdi") discriminant,
-            out("rsi") value,
-            options(nostack, nomem, preserves_flags)
-        );
-
-        <Result as super::ResultConverter>::from_registers((discriminant, value))
+        super::invoke(Vector::TaskInfo, InfoArgs { tid, info_ptr: info.as_mut_ptr().addr() })?;
+    }
+
+    // Safety: Kernel has written the `Info` on success.
+    Ok(unsafe { info.assume_init() })
+}
+
+/// Starts another thread of the calling task's process, sharing its address space and descriptors, which calls
+/// `entry` with `arg` on the stack whose top is `stack`. Returns the ID of the new thread, which is a child of the
+/// calling task, so it may be joined by [`wait`]ing on its alias (see [`get_info`]).
+///
+/// ### Safety
+///
+/// `stack` must be the (16-byte aligned) top of writable memory which nothing else uses while the thread runs.
+pub unsafe fn spawn_thread(
+    entry: extern "C" fn(usize) -> !,
+    stack: core::ptr::NonNull<u8>,
+    arg: usize,
+) -> core::result::Result<u32, Error> {
+    let args = SpawnThreadArgs { entry: entry as usize, stack: stack.as_ptr().addr(), arg };
+
+    // Safety: Caller is required to provide a stack for the thread.
+    match unsafe { super::invoke(Vector::TaskSpawnThread, args) }? {
+        Success::Value(tid) => u32::try_from(tid).map_err(|_| Error::InvalidResult),
+        _ => Err(Error::InvalidResult),
+    }
+}
+
+/// Writes the IDs of as many live tasks as fit into `tids`, returning the total number of live tasks (which may
+/// exceed the length of `tids`).
+pub fn list_tasks(tids: &mut [u32]) -> core::result::Result<usize, Error> {
+    let args = BufferArgs { ptr: tids.as_mut_ptr().addr(), len: tids.len() };
+
+    // Safety: `tids` is valid for writes of its length.
+    match unsafe { super::invoke(Vector::TaskList, args) }? {
+        Success::Value(count) => Ok(count),
+        _ => Err(Error::InvalidResult),
     }
 }
@@ -0,0 +1,45 @@
+//! Clocks, on the same time base as the kernel's log timestamps and scheduler statistics.
+
+use super::{Arguments, Error, Success, Vector};
+use crate::syscall::raw::{pad_args, MAX_ARGS};
+use core::time::Duration;
+use num_enum::TryFromPrimitive;
+
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromPrimitive)]
+pub enum Clock {
+    /// Time elapsed since boot, which never goes backwards.
+    Monotonic = 0,
+    /// Wall-clock time, since the Unix epoch (in UTC).
+    Realtime = 1,
+}
+
+/// Arguments for [`Vector::TimeGet`]: the [`Clock`] to read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClockArgs {
+    pub clock: u32,
+}
+
+impl Arguments for ClockArgs {
+    fn into_args(self) -> [usize; MAX_ARGS] {
+        pad_args(&[self.clock as usize])
+    }
+
+    fn from_args(args: [usize; MAX_ARGS]) -> Self {
+        #[allow(clippy::cast_possible_truncation)]
+        Self { clock: args[0] as u32 }
+    }
+}
+
+/// Reads the current time of `clock`, to the nanosecond.
+///
+/// #### Remark
+///
+/// [`Clock::Realtime`] fails with [`Error::Unsupported`] if the platform provides no wall clock.
+pub fn now(clock: Clock) -> core::result::Result<Duration, Error> {
+    // Safety: System call takes no pointers.
+    match unsafe { super::invoke(Vector::TimeGet, ClockArgs { clock: clock as u32 }) }? {
+        Success::Value(ns) => Ok(Duration::from_nanos(ns as u64)),
+        _ => Err(Error::InvalidResult),
+    }
+}
@@ -0,0 +1,40 @@
+//! Requests understood by terminal devices (i.e. `/dev/tty1`), issued via [`super::fs::control`].
+
+use super::{fs, Error};
+
+/// Writes the terminal's [`Mode`].
+pub const GET_MODE: u32 = 0x5400;
+/// Sets the terminal's [`Mode`] from the argument.
+pub const SET_MODE: u32 = 0x5401;
+/// Shows the terminal, and directs keyboard input to it.
+pub const ACTIVATE: u32 = 0x5402;
+
+bitflags::bitflags! {
+    /// Line discipline of a terminal.
+    #[repr(transparent)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Mode: u32 {
+        /// Input is edited a line at a time, and only becomes readable once the line is ended.
+        const CANONICAL = 1 << 0;
+        /// Typed characters are written back to the terminal.
+        const ECHO = 1 << 1;
+    }
+}
+
+/// Returns the mode of the terminal open as `fd`.
+pub fn mode(fd: usize) -> Result<Mode, Error> {
+    let mut buf = [0; core::mem::size_of::<u32>()];
+    fs::control(fd, GET_MODE, &mut buf)?;
+
+    Ok(Mode::from_bits_truncate(u32::from_ne_bytes(buf)))
+}
+
+/// Sets the mode of the terminal open as `fd`.
+pub fn set_mode(fd: usize, mode: Mode) -> Result<(), Error> {
+    fs::control(fd, SET_MODE, &mut mode.bits().to_ne_bytes()).map(|_| ())
+}
+
+/// Switches the display (and keyboard) to the terminal open as `fd`.
+pub fn activate(fd: usize) -> Result<(), Error> {
+    fs::control(fd, ACTIVATE, &mut []).map(|_| ())
+}
@@ -70,6 +70,7 @@ macro_rules! generic_msr {
 generic_msr!(IA32_FS_BASE, 0xC0000100);
 generic_msr!(IA32_GS_BASE, 0xC0000101);
 generic_msr!(IA32_KERNEL_GS_BASE, 0xC0000102);
+generic_msr!(IA32_PAT, 0x277);
 
 pub struct IA32_APIC_BASE;
 impl IA32_APIC_BASE {
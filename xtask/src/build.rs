@@ -20,6 +20,14 @@ pub struct Options {
 
     #[arg(long, default_value = "test_driver")]
     drivers: Vec<String>,
+
+    /// Build the kernel without its default driver & subsystem features.
+    #[arg(long)]
+    no_default_features: bool,
+
+    /// Driver & subsystem features to compile into the kernel (e.g. `nvme`, `fat32`).
+    #[arg(long, value_delimiter = ',')]
+    features: Vec<String>,
 }
 
 pub fn build(sh: &Shell, options: Options) -> Result<()> {
@@ -63,7 +71,15 @@ pub fn build(sh: &Shell, options: Options) -> Result<()> {
         let _dir = sh.push_dir("src/kernel/");
 
         cmd!(sh, "cargo fmt").run()?;
-        let local_args = &cargo_args;
+        let features = options.features.join(",");
+        let mut local_args = cargo_args.clone();
+        if options.no_default_features {
+            local_args.push("--no-default-features");
+        }
+        if !features.is_empty() {
+            local_args.extend(["--features", &features]);
+        }
+
         cmd!(sh, "cargo build -Z unstable-options {local_args...}").run()?;
 
         // Copy the output kernel binary to the virtual HDD.
@@ -1,5 +1,6 @@
 mod build;
 mod run;
+mod sched_trace;
 mod target;
 
 use anyhow::{Context, Result};
@@ -53,6 +54,7 @@ enum Arguments {
 
     Build(build::Options),
     Run(run::Options),
+    SchedTrace(sched_trace::Options),
 }
 
 fn main() -> Result<()> {
@@ -144,6 +146,10 @@ fn main() -> Result<()> {
         Arguments::Run(run_options) => {
             run::run(&sh, run_options)?;
         }
+
+        Arguments::SchedTrace(sched_trace_options) => {
+            sched_trace::convert(sched_trace_options)?;
+        }
     }
 
     Ok(())
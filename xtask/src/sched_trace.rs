@@ -0,0 +1,130 @@
+use anyhow::{bail, Result};
+use std::{collections::BTreeMap, fmt::Write, path::PathBuf};
+
+/// Size of a record read from `/dev/sched_trace` (see `libsys::syscall::sched_trace::Record`).
+const RECORD_LEN: usize = 24;
+const IDLE: u32 = u32::MAX;
+
+const KIND_SWITCH: u16 = 1;
+const KIND_WAKEUP: u16 = 2;
+
+#[derive(clap::Parser)]
+pub struct Options {
+    /// Scheduler trace captured from `/dev/sched_trace`.
+    input: PathBuf,
+
+    /// Path to write the Chrome trace (JSON) to, for loading into Perfetto or `chrome://tracing`.
+    output: PathBuf,
+}
+
+struct Record {
+    timestamp_ms: u64,
+    core: u32,
+    kind: u16,
+    reason: u16,
+    prev: u32,
+    next: u32,
+}
+
+impl Record {
+    fn parse(bytes: &[u8]) -> Self {
+        let u16_at = |offset: usize| u16::from_le_bytes(bytes[offset..(offset + 2)].try_into().unwrap());
+        let u32_at = |offset: usize| u32::from_le_bytes(bytes[offset..(offset + 4)].try_into().unwrap());
+
+        Self {
+            timestamp_ms: u64::from_le_bytes(bytes[0..8].try_into().unwrap()),
+            core: u32_at(8),
+            kind: u16_at(12),
+            reason: u16_at(14),
+            prev: u32_at(16),
+            next: u32_at(20),
+        }
+    }
+}
+
+fn reason_name(reason: u16) -> &'static str {
+    match reason {
+        1 => "preempt",
+        2 => "yield",
+        3 => "exit",
+        4 => "spawn",
+        5 => "replenish",
+        6 => "admit",
+        7 => "sleep",
+        8 => "wake",
+        _ => "unknown",
+    }
+}
+
+fn task_name(task: u32) -> String {
+    if task == IDLE {
+        String::from("idle")
+    } else {
+        format!("task {task}")
+    }
+}
+
+/// Converts a captured scheduler trace into a Chrome trace, with one track per core showing which task ran when,
+/// and instant events for wakeups.
+pub fn convert(options: Options) -> Result<()> {
+    let bytes = std::fs::read(&options.input)?;
+    if bytes.len() % RECORD_LEN != 0 {
+        bail!("trace length ({}) isn't a multiple of the record length ({RECORD_LEN})", bytes.len());
+    }
+
+    let mut events = Vec::new();
+    // Task running on each core, and when it was switched in.
+    let mut running = BTreeMap::<u32, (u32, u64)>::new();
+    let mut last_timestamp_us = 0;
+
+    for record in bytes.chunks_exact(RECORD_LEN).map(Record::parse) {
+        let timestamp_us = record.timestamp_ms * 1000;
+        last_timestamp_us = last_timestamp_us.max(timestamp_us);
+
+        match record.kind {
+            KIND_SWITCH => {
+                if let Some((task, start_us)) = running.insert(record.core, (record.next, timestamp_us)) {
+                    events.push(slice_event(record.core, task, start_us, timestamp_us, Some(record.reason)));
+                }
+            }
+
+            KIND_WAKEUP => events.push(format!(
+                r#"{{"name":"wakeup {} ({})","ph":"i","s":"t","pid":0,"tid":{},"ts":{timestamp_us}}}"#,
+                task_name(record.next),
+                reason_name(record.reason),
+                record.core
+            )),
+
+            kind => bail!("unknown record kind: {kind}"),
+        }
+    }
+
+    // Close the slices still running at the end of the capture.
+    for (core, (task, start_us)) in running {
+        events.push(slice_event(core, task, start_us, last_timestamp_us, None));
+    }
+
+    let mut json = String::from(r#"{"displayTimeUnit":"ms","traceEvents":["#);
+    for (index, event) in events.iter().enumerate() {
+        if index > 0 {
+            json.push(',');
+        }
+
+        write!(json, "\n{event}")?;
+    }
+    json.push_str("\n]}\n");
+
+    std::fs::write(&options.output, json)?;
+    println!("Wrote {} events to {}.", events.len(), options.output.display());
+
+    Ok(())
+}
+
+fn slice_event(core: u32, task: u32, start_us: u64, end_us: u64, end_reason: Option<u16>) -> String {
+    format!(
+        r#"{{"name":"{}","ph":"X","pid":0,"tid":{core},"ts":{start_us},"dur":{},"args":{{"switched_out":"{}"}}}}"#,
+        task_name(task),
+        end_us - start_us,
+        end_reason.map_or("end of trace", reason_name)
+    )
+}
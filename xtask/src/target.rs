@@ -60,7 +60,14 @@ impl ConfigOptions {
     }
 
     fn userspace_default(target: Target) -> Self {
-        Self { unstable: Default::default(), build: BuildOptions { target: target.to_string(), rustflags: None } }
+        Self {
+            unstable: Default::default(),
+            build: BuildOptions {
+                target: target.to_string(),
+                // Frame pointers are kept so the kernel can walk a crashed task's stack.
+                rustflags: Some(vec!["-C".into(), "force-frame-pointers=yes".into()]),
+            },
+        }
     }
 }
 